@@ -0,0 +1,33 @@
+#![no_main]
+
+use consumer::ilp::{to_ilp_line, NormTrade};
+use libfuzzer_sys::fuzz_target;
+
+/// Count line breaks not preceded by an (unescaped) backslash.
+fn bare_newlines(s: &str) -> usize {
+    let (mut n, mut esc) = (0, false);
+    for c in s.chars() {
+        match c {
+            _ if esc => esc = false,
+            '\\' => esc = true,
+            '\n' | '\r' => n += 1,
+            _ => {}
+        }
+    }
+    n
+}
+
+// Adversarial symbol/msg_id and raw f64 bit patterns: the line must stay one line
+// with exactly one unescaped separator between the tag, field and timestamp sections.
+fuzz_target!(|input: (&str, &str, u64, u64, i64, i64)| {
+    let (symbol, msg_id, price, qty, trade_id, ts_ms) = input;
+    let (price, qty) = (f64::from_bits(price), f64::from_bits(qty));
+    if !price.is_finite() || !qty.is_finite() {
+        return;
+    }
+    let t = NormTrade { ts_ms, symbol: symbol.to_string(), price, qty, trade_id, is_bm: false, notional: None, first_trade_id: None, contract_size: None };
+    let line = to_ilp_line(&t, msg_id);
+    assert_eq!(bare_newlines(&line), 0, "{:?}", line);
+    assert!(line.starts_with("trades,symbol="));
+    assert!(line.ends_with(&format!(" {}", ts_ms as i128 * 1_000_000)));
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Arbitrary bytes through the producer's normalization and back out as `ticks.norm` JSON.
+fuzz_target!(|data: &[u8]| {
+    let Ok(s) = std::str::from_utf8(data) else { return };
+    let Ok(n) = producer::normalize::normalize(s) else { return };
+    assert!(n.price.is_finite() && n.qty.is_finite());
+
+    let wire = serde_json::to_string(&n).unwrap();
+    let back: consumer::ilp::NormTrade = serde_json::from_str(&wire).unwrap();
+    assert_eq!(back.symbol, n.symbol);
+    assert_eq!(back.price.to_bits(), n.price.to_bits());
+    assert_eq!(back.qty.to_bits(), n.qty.to_bits());
+});
@@ -0,0 +1,68 @@
+//! The exchange's view of a window, from `GET /api/v3/aggTrades`.
+//!
+//! Each aggregate covers trade ids `f..=l`, all at one price, side and time,
+//! so the aggregates of a window enumerate every trade id in it.
+
+use anyhow::Result;
+use serde::Deserialize;
+
+const LIMIT: usize = 1000;
+/// Request weight of one aggTrades page.
+const WEIGHT: u32 = 4;
+/// Binance rejects startTime/endTime windows of an hour or more.
+const MAX_WINDOW_MS: i64 = 3_600_000 - 1;
+
+#[derive(Debug, Deserialize)]
+struct AggTrade {
+    #[serde(rename = "a")] id: i64,
+    #[serde(rename = "p")] price: String,
+    #[serde(rename = "f")] first: i64,
+    #[serde(rename = "l")] last: i64,
+    #[serde(rename = "T")] ts_ms: i64,
+    #[serde(rename = "m")] is_bm: bool,
+}
+
+/// One exchange trade as far as aggTrades can tell us.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expected {
+    pub trade_id: i64,
+    pub price: f64,
+    pub ts_ms: i64,
+    pub is_bm: bool,
+}
+
+async fn page(client: &reqwest::Client, base: &str, symbol: &str, query: &[(&str, i64)]) -> Result<Vec<AggTrade>> {
+    let mut req = client.get(format!("{}/api/v3/aggTrades", base))
+        .query(&[("symbol", symbol)])
+        .query(&[("limit", LIMIT)]);
+    for (k, v) in query {
+        req = req.query(&[(k, v)]);
+    }
+    Ok(ratelimit::shared("binance")?.send(req, WEIGHT).await?.error_for_status()?.json().await?)
+}
+
+/// Every trade in `[from_ms, to_ms)`, ordered by trade id.
+pub async fn trades(client: &reqwest::Client, base: &str, symbol: &str, from_ms: i64, to_ms: i64) -> Result<Vec<Expected>> {
+    let mut out = Vec::new();
+    let mut start = from_ms;
+    while start < to_ms {
+        let end = (start + MAX_WINDOW_MS).min(to_ms - 1);
+        let mut batch = page(client, base, symbol, &[("startTime", start), ("endTime", end)]).await?;
+        loop {
+            let full = batch.len() == LIMIT;
+            let next = batch.last().map(|a| a.id + 1);
+            for a in batch {
+                let price = a.price.parse()?;
+                out.extend((a.first..=a.last).map(|trade_id| Expected { trade_id, price, ts_ms: a.ts_ms, is_bm: a.is_bm }));
+            }
+            let Some(from_id) = next.filter(|_| full) else { break };
+            batch = page(client, base, symbol, &[("fromId", from_id)]).await?;
+            batch.retain(|a| a.ts_ms <= end);
+            if batch.is_empty() {
+                break;
+            }
+        }
+        start = end + 1;
+    }
+    Ok(out)
+}
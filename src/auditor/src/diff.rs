@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use crate::binance::Expected;
+use crate::questdb::Stored;
+
+/// Relative price tolerance; both sides are the same decimal string parsed to f64.
+const PRICE_EPS: f64 = 1e-12;
+
+#[derive(Debug, Default, Clone)]
+pub struct Diff {
+    pub expected: usize,
+    pub found: usize,
+    /// Exchange trade ids with no row.
+    pub missing: Vec<i64>,
+    /// Trade ids stored more than once.
+    pub duplicates: Vec<i64>,
+    /// Stored with a different price, side or time than the exchange reports.
+    pub mismatched: Vec<i64>,
+    /// Stored ids the exchange doesn't list for the window.
+    pub unexpected: Vec<i64>,
+}
+
+impl Diff {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.duplicates.is_empty() && self.mismatched.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+pub fn diff(expected: &[Expected], stored: &[Stored]) -> Diff {
+    let mut by_id: HashMap<i64, Vec<&Stored>> = HashMap::new();
+    for s in stored {
+        by_id.entry(s.trade_id).or_default().push(s);
+    }
+
+    let mut d = Diff { expected: expected.len(), found: stored.len(), ..Default::default() };
+    for e in expected {
+        let Some(rows) = by_id.remove(&e.trade_id) else {
+            d.missing.push(e.trade_id);
+            continue;
+        };
+        if rows.len() > 1 {
+            d.duplicates.push(e.trade_id);
+        }
+        let same = |s: &&Stored| {
+            (s.price - e.price).abs() <= PRICE_EPS * e.price.abs() && s.is_bm == e.is_bm && s.ts_ms == e.ts_ms
+        };
+        if !rows.iter().all(same) {
+            d.mismatched.push(e.trade_id);
+        }
+    }
+    d.unexpected = by_id.into_keys().collect();
+    d.unexpected.sort_unstable();
+    d
+}
@@ -0,0 +1,176 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use bus::BusConfig;
+use calendar::Calendar;
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use consumer::ilp::{escape_tag, ilp_connect};
+use consumer::schema;
+use metrics::{counter, gauge};
+use obsv::load::Load;
+use obsv::{init_metrics, init_tracing, namespace};
+use tokio::io::AsyncWriteExt;
+
+mod binance;
+mod diff;
+mod questdb;
+
+use diff::Diff;
+
+/// Diff a window of trades in QuestDB against the exchange's REST history.
+#[derive(Debug, Parser)]
+struct Args {
+    /// Comma-separated exchange symbols, e.g. BTCUSDT,ETHUSDT.
+    #[arg(long, env = "SYMBOLS", default_value = "BTCUSDT", value_delimiter = ',')]
+    symbols: Vec<String>,
+    /// Window start (RFC 3339). Default: now - lag - window.
+    #[arg(long)]
+    from: Option<DateTime<Utc>>,
+    /// Window end, exclusive (RFC 3339). Default: now - lag.
+    #[arg(long)]
+    to: Option<DateTime<Utc>>,
+    /// Window length when --from/--to aren't given (seconds).
+    #[arg(long, default_value_t = 3600)]
+    window: i64,
+    /// How far behind now the default window ends, so in-flight trades have landed (seconds).
+    #[arg(long, default_value_t = 300)]
+    lag: i64,
+    /// Re-run on the trailing window every N seconds instead of once.
+    #[arg(long)]
+    every: Option<u64>,
+    /// Exit non-zero when a one-shot audit finds any discrepancy.
+    #[arg(long)]
+    fail_on_gaps: bool,
+    #[arg(long, env = "BINANCE_REST_URL", default_value = "https://api.binance.com")]
+    rest_url: String,
+    #[arg(long, env = "QDB_HTTP_URL", default_value = "http://localhost:9000")]
+    qdb_http: String,
+    #[arg(long, env = "QDB_HOST", default_value = "localhost")]
+    qdb_host: String,
+    #[arg(long, env = "QDB_ILP_PORT", default_value_t = 9009)]
+    qdb_ilp_port: u16,
+    #[arg(long, default_value = "trades")]
+    table: String,
+    #[arg(long, default_value = "audit_reports")]
+    report_table: String,
+    #[arg(long, default_value_t = 9467)]
+    metrics_port: u16,
+    /// Maintenance windows, as in the calendar crate; windows touching one aren't audited.
+    #[arg(long, env = "MAINTENANCE_WINDOWS", default_value = "")]
+    maintenance: String,
+}
+
+impl Args {
+    fn window(&self) -> (i64, i64) {
+        let to = self.to.map(|t| t.timestamp_millis())
+            .unwrap_or_else(|| Utc::now().timestamp_millis() - self.lag * 1000);
+        let from = self.from.map(|t| t.timestamp_millis()).unwrap_or(to - self.window * 1000);
+        (from, to)
+    }
+}
+
+fn report_line(table: &str, symbol: &str, from_ms: i64, to_ms: i64, d: &Diff) -> String {
+    format!(
+        "{},symbol={} window_start={}i,window_end={}i,expected={}i,found={}i,missing={}i,duplicates={}i,mismatched={}i,unexpected={}i {}\n",
+        table, escape_tag(symbol), from_ms, to_ms,
+        d.expected, d.found, d.missing.len(), d.duplicates.len(), d.mismatched.len(), d.unexpected.len(),
+        Utc::now().timestamp_nanos_opt().unwrap(),
+    )
+}
+
+async fn audit_symbol(args: &Args, client: &reqwest::Client, qdb: &schema::Client, symbol: &str, from_ms: i64, to_ms: i64) -> Result<Diff> {
+    let expected = binance::trades(client, &args.rest_url, symbol, from_ms, to_ms).await?;
+    let stored = questdb::trades(qdb, &args.table, symbol, from_ms, to_ms).await?;
+    let d = diff::diff(&expected, &stored);
+
+    for (name, n) in [
+        ("audit_expected", d.expected),
+        ("audit_missing", d.missing.len()),
+        ("audit_duplicates", d.duplicates.len()),
+        ("audit_mismatched", d.mismatched.len()),
+        ("audit_unexpected", d.unexpected.len()),
+    ] {
+        gauge!(name, "symbol" => symbol.to_string()).set(n as f64);
+    }
+    let sample = |v: &[i64]| v.iter().take(20).copied().collect::<Vec<_>>();
+    tracing::info!(target: "auditor", symbol, from_ms, to_ms,
+        expected = d.expected, found = d.found,
+        missing = d.missing.len(), duplicates = d.duplicates.len(),
+        mismatched = d.mismatched.len(), unexpected = d.unexpected.len(),
+        missing_sample = ?sample(&d.missing), duplicate_sample = ?sample(&d.duplicates),
+        mismatched_sample = ?sample(&d.mismatched), "audit result");
+    Ok(d)
+}
+
+/// Audit every symbol; returns whether all were clean.
+async fn audit_once(args: &Args, client: &reqwest::Client, qdb: &schema::Client) -> Result<bool> {
+    let (from_ms, to_ms) = args.window();
+    anyhow::ensure!(from_ms < to_ms, "empty audit window");
+    if Calendar::parse("", &args.maintenance)?.overlaps_maintenance(from_ms, to_ms) {
+        // The exchange's history and ours both have holes there; a diff would only be noise.
+        tracing::info!(target: "auditor", from_ms, to_ms, "window overlaps maintenance; skipped");
+        counter!("audit_skipped_total").increment(1);
+        return Ok(true);
+    }
+
+    let mut lines = String::new();
+    let mut clean = true;
+    for symbol in &args.symbols {
+        let symbol = symbol.trim().to_uppercase();
+        match audit_symbol(args, client, qdb, &symbol, from_ms, to_ms).await {
+            Ok(d) => {
+                clean &= d.is_clean();
+                lines.push_str(&report_line(&args.report_table, &symbol, from_ms, to_ms, &d));
+            }
+            Err(e) => {
+                clean = false;
+                counter!("audit_errors_total").increment(1);
+                tracing::error!(target: "auditor", symbol = %symbol, error = ?e, "audit failed");
+            }
+        }
+    }
+    counter!("audit_runs_total").increment(1);
+
+    if !lines.is_empty() {
+        let mut ilp = ilp_connect(&args.qdb_host, args.qdb_ilp_port).await?;
+        ilp.write_all(lines.as_bytes()).await?;
+        ilp.flush().await?;
+    }
+    Ok(clean)
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!(), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    let mut args = Args::parse();
+    // A one-shot run serves them too, for as long as it runs.
+    init_metrics(args.metrics_port)?;
+    // Both tables are named as in the namespace.
+    (args.table, args.report_table) = (namespace::table(&args.table), namespace::table(&args.report_table));
+    let client = reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?;
+    let qdb = schema::Client::authed(&args.qdb_http).await?.with_timeout(Duration::from_secs(30))?;
+    // Only for the lifecycle events; the audit itself reads QuestDB and the exchange.
+    bus::audit::start(&BusConfig::from_env()?).await;
+
+    let Some(every) = args.every else {
+        let clean = audit_once(&args, &client, &qdb).await?;
+        anyhow::ensure!(clean || !args.fail_on_gaps, "audit found discrepancies");
+        return Ok(());
+    };
+
+    let mut tick = tokio::time::interval(Duration::from_secs(every.max(1)));
+    // An audit run is the auditor's message.
+    let load = Load::new("auditor")?;
+    loop {
+        tick.tick().await;
+        let _busy = load.busy();
+        load.processed(1);
+        if let Err(e) = audit_once(&args, &client, &qdb).await {
+            tracing::error!(target: "auditor", error = ?e, "audit run failed");
+        }
+    }
+}
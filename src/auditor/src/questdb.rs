@@ -0,0 +1,40 @@
+//! The pipeline's view of a window, via QuestDB's HTTP `/exec` endpoint.
+
+use anyhow::{Context, Result};
+use consumer::schema::Client;
+
+/// One persisted row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stored {
+    pub trade_id: i64,
+    pub price: f64,
+    pub ts_ms: i64,
+    pub is_bm: bool,
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// Every row of `symbol` in `[from_ms, to_ms)`, duplicates included. An
+/// aggregated trade comes back as one row per exchange trade id it covers.
+pub async fn trades(client: &Client, table: &str, symbol: &str, from_ms: i64, to_ms: i64) -> Result<Vec<Stored>> {
+    // Filter on the designated timestamp (µs) so QuestDB can prune partitions.
+    let sql = format!(
+        "SELECT first_trade_id, trade_id, price, ts_ms, is_bm FROM {} WHERE symbol = {} \
+         AND timestamp >= cast({} AS timestamp) AND timestamp < cast({} AS timestamp)",
+        table, quote(symbol), from_ms * 1000, to_ms * 1000,
+    );
+    let resp = client.rows(&sql).await?;
+    let col = |name: &str| resp.column(name);
+    let (first, id, price, ts, bm) = (col("first_trade_id")?, col("trade_id")?, col("price")?, col("ts_ms")?, col("is_bm")?);
+    let mut out = Vec::with_capacity(resp.dataset.len());
+    for row in &resp.dataset {
+        let last = row[id].as_i64().context("trade_id")?;
+        let (price, ts_ms, is_bm) = (row[price].as_f64().context("price")?, row[ts].as_i64().context("ts_ms")?, row[bm].as_bool().context("is_bm")?);
+        // Null unless the fetcher ran on the aggTrade stream.
+        let first = row[first].as_i64().unwrap_or(last);
+        out.extend((first..=last).map(|trade_id| Stored { trade_id, price, ts_ms, is_bm }));
+    }
+    Ok(out)
+}
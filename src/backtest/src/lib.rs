@@ -0,0 +1,4 @@
+//! Backtests: history from QuestDB or Parquet through the live strategy engine.
+
+pub mod report;
+pub mod source;
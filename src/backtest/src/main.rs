@@ -0,0 +1,121 @@
+//! Backtest: replay history through the strategies the `strategy` stage runs
+//! live, with the same engine and simulated fills, and report how they did.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use backtest::report::Report;
+use backtest::source::{Event, Query, Source};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use consumer::schema;
+use obsv::init_tracing;
+use strategy::engine::Engine;
+use strategy::signal;
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// Start (RFC 3339).
+    #[arg(long)]
+    from: DateTime<Utc>,
+    /// End, exclusive (RFC 3339). Default: now.
+    #[arg(long)]
+    to: Option<DateTime<Utc>>,
+    /// As for the strategy stage, e.g. sma_cross:10:30:0.01,momentum:20:0.01.
+    #[arg(long, env = "STRATEGIES", default_value = "sma_cross:10:30:1")]
+    strategies: String,
+    #[arg(long, env = "STRATEGY_FEE_BPS", default_value_t = 10.0)]
+    fee_bps: f64,
+    /// Only these symbols; default all.
+    #[arg(long, env = "SYMBOLS", value_delimiter = ',')]
+    symbols: Vec<String>,
+    /// Read trades instead of bars, rebuilding bars and feeding strategies every trade.
+    #[arg(long)]
+    trades: bool,
+    /// Bars table, or the trades table with --trades. Default: trades_1m, or trades.
+    #[arg(long)]
+    table: Option<String>,
+    /// Read these Parquet files, in order, instead of QuestDB.
+    #[arg(long, num_args = 1..)]
+    parquet: Vec<PathBuf>,
+    /// Bar length when rebuilding bars from trades (seconds).
+    #[arg(long, default_value_t = 60)]
+    bar_secs: i64,
+    /// Rows per QuestDB query.
+    #[arg(long, default_value_t = 50_000)]
+    page: usize,
+    /// Pace the replay at this many times real time; 0 runs flat out.
+    #[arg(long, default_value_t = 0.0)]
+    speed: f64,
+    /// Also write the report as JSON here.
+    #[arg(long)]
+    report: Option<PathBuf>,
+    #[arg(long, env = "QDB_HTTP_URL", default_value = "http://localhost:9000")]
+    qdb_http: String,
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!(), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    let args = Args::parse();
+    let specs = signal::parse(&args.strategies)?;
+    anyhow::ensure!(!specs.is_empty(), "--strategies is empty");
+    let bar_ms = args.bar_secs.max(1) * 1000;
+    let from_ms = args.from.timestamp_millis();
+    let to_ms = args.to.unwrap_or_else(Utc::now).timestamp_millis();
+    anyhow::ensure!(from_ms < to_ms, "--from must be before --to");
+
+    let mut source = match args.parquet.is_empty() {
+        true => {
+            let table = obsv::namespace::table(args.table.as_deref().unwrap_or(if args.trades { "trades" } else { "trades_1m" }));
+            let query = Query { table, trades: args.trades, from_ms, to_ms, symbols: args.symbols.clone() };
+            Source::questdb(schema::Client::authed(&args.qdb_http).await?, query, args.page, bar_ms)
+        }
+        false => Source::parquet(args.parquet.clone(), bar_ms, args.symbols.clone())?,
+    };
+    tracing::info!(target="backtest", strategies=?specs.iter().map(|s| &s.name).collect::<Vec<_>>(), trades=source.is_trades(), "backtest");
+
+    let mut engine = Engine::new(specs, args.fee_bps);
+    let mut report = Report::default();
+    let started = Instant::now();
+    let (mut first_ts, mut events) = (None, 0u64);
+    while let Some(event) = source.next().await? {
+        let ts = event.ts_ms();
+        // Parquet files aren't filtered by the query.
+        if ts < from_ms || ts >= to_ms {
+            continue;
+        }
+        if args.speed > 0.0 {
+            let since = (ts - *first_ts.get_or_insert(ts)) as f64 / args.speed;
+            let due = Duration::from_secs_f64(since.max(0.0) / 1000.0);
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                tokio::time::sleep(wait).await;
+            }
+        }
+        match &event {
+            Event::Bar(bar) => {
+                let (fills, pnl) = engine.on_bar(bar);
+                report.fills(&fills);
+                report.pnl(&pnl);
+            }
+            Event::Trade(t) => report.fills(&engine.on_trade(t)),
+        }
+        events += 1;
+    }
+
+    let summaries = report.summaries();
+    tracing::info!(target="backtest", events, secs=started.elapsed().as_secs_f64(), "done");
+    println!("{:<32} {:>7} {:>14} {:>10} {:>14} {:>12} {:>7} {:>5}", "strategy", "fills", "turnover", "fees", "pnl", "max_dd", "sharpe", "days");
+    for s in &summaries {
+        let sharpe = s.sharpe.map_or("-".to_string(), |v| format!("{:.2}", v));
+        println!("{:<32} {:>7} {:>14.2} {:>10.2} {:>14.2} {:>12.2} {:>7} {:>5}", s.strategy, s.fills, s.turnover, s.fees, s.pnl, s.max_drawdown, sharpe, s.days);
+    }
+    if let Some(path) = &args.report {
+        std::fs::write(path, serde_json::to_vec_pretty(&summaries)?)?;
+    }
+    Ok(())
+}
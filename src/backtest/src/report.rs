@@ -0,0 +1,93 @@
+//! Performance figures per strategy, across all of its symbols.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+use strategy::ledger::{Fill, Pnl};
+
+const DAY_MS: i64 = 86_400_000;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Summary {
+    pub strategy: String,
+    pub fills: u64,
+    /// Traded notional, in quote units.
+    pub turnover: f64,
+    pub fees: f64,
+    /// Net of fees: realized plus what's still open, marked to the last price.
+    pub pnl: f64,
+    /// Largest fall in PnL from a previous high.
+    pub max_drawdown: f64,
+    /// Mean over standard deviation of daily PnL changes, times sqrt(365).
+    pub sharpe: Option<f64>,
+    pub days: usize,
+}
+
+#[derive(Default)]
+struct Tally {
+    summary: Summary,
+    /// Latest PnL per symbol; their sum is the strategy's PnL.
+    by_symbol: HashMap<String, f64>,
+    peak: f64,
+    /// PnL at the end of each UTC day seen.
+    daily: BTreeMap<i64, f64>,
+}
+
+impl Tally {
+    fn total(&self) -> f64 {
+        self.by_symbol.values().sum()
+    }
+}
+
+#[derive(Default)]
+pub struct Report {
+    tallies: BTreeMap<String, Tally>,
+}
+
+impl Report {
+    pub fn fills(&mut self, fills: &[Fill]) {
+        for f in fills {
+            let s = &mut self.tally(&f.strategy).summary;
+            s.fills += 1;
+            s.turnover += f.qty.abs() * f.price;
+            s.fees += f.fee;
+        }
+    }
+
+    pub fn pnl(&mut self, rows: &[Pnl]) {
+        for p in rows {
+            let t = self.tally(&p.strategy);
+            t.by_symbol.insert(p.symbol.clone(), p.position.realized + p.position.unrealized());
+            let total = t.total();
+            t.peak = t.peak.max(total);
+            t.summary.max_drawdown = t.summary.max_drawdown.max(t.peak - total);
+            t.daily.insert(p.ts_ms.div_euclid(DAY_MS), total);
+        }
+    }
+
+    pub fn summaries(&self) -> Vec<Summary> {
+        self.tallies.values()
+            .map(|t| {
+                let closes: Vec<f64> = t.daily.values().copied().collect();
+                // Day one's change is from flat.
+                let changes: Vec<f64> = closes.iter().scan(0.0, |prev, &c| Some(c - std::mem::replace(prev, c))).collect();
+                Summary { pnl: t.total(), sharpe: sharpe(&changes), days: closes.len(), ..t.summary.clone() }
+            })
+            .collect()
+    }
+
+    fn tally(&mut self, strategy: &str) -> &mut Tally {
+        self.tallies.entry(strategy.to_string())
+            .or_insert_with(|| Tally { summary: Summary { strategy: strategy.to_string(), ..Default::default() }, ..Default::default() })
+    }
+}
+
+fn sharpe(changes: &[f64]) -> Option<f64> {
+    if changes.len() < 2 {
+        return None;
+    }
+    let n = changes.len() as f64;
+    let mean = changes.iter().sum::<f64>() / n;
+    let sd = (changes.iter().map(|c| (c - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+    (sd > 0.0).then(|| mean / sd * 365f64.sqrt())
+}
@@ -0,0 +1,267 @@
+//! History as a stream of events, oldest first.
+//!
+//! Bars can be read as they were stored by the consumer, or rebuilt from
+//! trades with the consumer's own [`Rollup`]; with trades, strategies also
+//! see every trade, as they do live with TRADES_TOPIC set.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use consumer::ilp::NormTrade;
+use consumer::rollup::{Bar, Rollup};
+use consumer::schema;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::{Field, Row};
+use serde_json::Value;
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    Bar(Bar),
+    Trade(NormTrade),
+}
+
+impl Event {
+    pub fn ts_ms(&self) -> i64 {
+        match self {
+            Event::Bar(b) => b.ts_ms,
+            Event::Trade(t) => t.ts_ms,
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        match self {
+            Event::Bar(b) => &b.symbol,
+            Event::Trade(t) => &t.symbol,
+        }
+    }
+}
+
+/// Bars from trades. A bar is emitted as soon as a later window's trade
+/// arrives, ahead of that trade; live, the consumer waits out a grace period
+/// first, so a few trades reach strategies before the bar does.
+pub struct Replay {
+    rollup: Rollup,
+}
+
+impl Replay {
+    pub fn new(bar_ms: i64) -> Self {
+        Self { rollup: Rollup::new(bar_ms, 0, i64::MAX / 2) }
+    }
+
+    pub fn trade(&mut self, t: NormTrade) -> Vec<Event> {
+        self.rollup.add(&t);
+        let mut out: Vec<Event> = self.rollup.close(i64::MIN).into_iter().map(Event::Bar).collect();
+        out.push(Event::Trade(t));
+        out
+    }
+
+    /// Bars still open at the end of the history.
+    pub fn finish(&mut self) -> Vec<Event> {
+        self.rollup.close(i64::MAX).into_iter().map(Event::Bar).collect()
+    }
+}
+
+/// Which table to read from QuestDB, over `[from_ms, to_ms)`.
+pub struct Query {
+    pub table: String,
+    pub trades: bool,
+    pub from_ms: i64,
+    pub to_ms: i64,
+    pub symbols: Vec<String>,
+}
+
+impl Query {
+    fn sql(&self, offset: usize, page: usize) -> String {
+        let cols = match self.trades {
+            true => "symbol, price, qty, trade_id, is_bm, ts_ms",
+            false => "symbol, open, high, low, close, volume, trades, timestamp",
+        };
+        let symbols = match self.symbols.is_empty() {
+            true => String::new(),
+            false => format!(" AND symbol IN ({})", self.symbols.iter().map(|s| format!("'{}'", s.replace('\'', "''"))).collect::<Vec<_>>().join(",")),
+        };
+        format!(
+            "SELECT {} FROM {} WHERE timestamp >= cast({} AS timestamp) AND timestamp < cast({} AS timestamp){} ORDER BY timestamp LIMIT {},{}",
+            cols, self.table, self.from_ms * 1000, self.to_ms * 1000, symbols, offset, offset + page,
+        )
+    }
+}
+
+enum Input {
+    Qdb { client: schema::Client, query: Query, offset: usize, page: usize, done: bool },
+    Parquet { files: VecDeque<PathBuf>, rows: Option<parquet::record::reader::RowIter<'static>> },
+}
+
+pub struct Source {
+    input: Input,
+    /// Set when the input is trades.
+    replay: Option<Replay>,
+    symbols: Vec<String>,
+    queued: VecDeque<Event>,
+}
+
+impl Source {
+    /// Pages of `page` rows through QuestDB's HTTP API.
+    pub fn questdb(client: schema::Client, query: Query, page: usize, bar_ms: i64) -> Self {
+        let replay = query.trades.then(|| Replay::new(bar_ms));
+        let symbols = query.symbols.clone();
+        Self { input: Input::Qdb { client, query, offset: 0, page: page.max(1), done: false }, replay, symbols, queued: VecDeque::new() }
+    }
+
+    /// Parquet files read in the order given, each sorted by time. Files with
+    /// a `close` column hold bars; any other holds trades.
+    pub fn parquet(files: Vec<PathBuf>, bar_ms: i64, symbols: Vec<String>) -> Result<Self> {
+        let trades = match files.first() {
+            Some(f) => !open(f)?.metadata().file_metadata().schema_descr().columns().iter().any(|c| c.name() == "close"),
+            None => false,
+        };
+        let replay = trades.then(|| Replay::new(bar_ms));
+        Ok(Self { input: Input::Parquet { files: files.into(), rows: None }, replay, symbols, queued: VecDeque::new() })
+    }
+
+    pub fn is_trades(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    pub async fn next(&mut self) -> Result<Option<Event>> {
+        loop {
+            if let Some(e) = self.queued.pop_front() {
+                return Ok(Some(e));
+            }
+            let Some(rows) = self.fill().await? else {
+                if let Some(r) = &mut self.replay {
+                    self.queued.extend(r.finish());
+                    self.replay = None;
+                    continue;
+                }
+                return Ok(None);
+            };
+            for e in rows {
+                match (&mut self.replay, e) {
+                    (Some(r), Event::Trade(t)) => self.queued.extend(r.trade(t)),
+                    (_, e) => self.queued.push_back(e),
+                }
+            }
+        }
+    }
+
+    /// The next batch read, or `None` at the end of the input.
+    async fn fill(&mut self) -> Result<Option<Vec<Event>>> {
+        let trades = self.replay.is_some();
+        match &mut self.input {
+            Input::Qdb { client, query, offset, page, done } => {
+                if *done {
+                    return Ok(None);
+                }
+                let rows = client.query(&query.sql(*offset, *page)).await?;
+                *offset += rows.len();
+                *done = rows.len() < *page;
+                Ok(Some(rows.iter().filter_map(|r| from_json(r, query.trades)).collect()))
+            }
+            Input::Parquet { files, rows } => loop {
+                if let Some(it) = rows {
+                    let mut batch = Vec::new();
+                    for row in it.by_ref().take(10_000) {
+                        if let Some(e) = from_row(&row?, trades) {
+                            if self.symbols.is_empty() || self.symbols.iter().any(|s| s == e.symbol()) {
+                                batch.push(e);
+                            }
+                        }
+                    }
+                    if !batch.is_empty() {
+                        return Ok(Some(batch));
+                    }
+                }
+                let Some(path) = files.pop_front() else { return Ok(None) };
+                tracing::info!(target="backtest", file=%path.display(), "reading");
+                *rows = Some(open(&path)?.into_iter());
+            },
+        }
+    }
+}
+
+fn open(path: &PathBuf) -> Result<SerializedFileReader<File>> {
+    let f = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    SerializedFileReader::new(f).with_context(|| format!("reading {}", path.display()))
+}
+
+fn from_json(r: &[Value], trades: bool) -> Option<Event> {
+    let f = |i: usize| r.get(i).and_then(Value::as_f64);
+    let symbol = r.first()?.as_str()?.to_string();
+    Some(match trades {
+        true => Event::Trade(NormTrade {
+            symbol,
+            price: f(1)?,
+            qty: f(2)?,
+            trade_id: r.get(3)?.as_i64()?,
+            is_bm: r.get(4)?.as_bool()?,
+            ts_ms: r.get(5)?.as_i64()?,
+            session: None,
+            notional: None,
+            first_trade_id: None,
+            contract_size: None,
+            ts_ns: None,
+            ingest_ts_ns: None,
+        }),
+        false => Event::Bar(Bar {
+            symbol,
+            open: f(1)?,
+            high: f(2)?,
+            low: f(3)?,
+            close: f(4)?,
+            volume: f(5)?,
+            trades: r.get(6)?.as_u64()?,
+            ts_ms: r.get(7)?.as_str()?.parse::<chrono::DateTime<chrono::Utc>>().ok()?.timestamp_millis(),
+        }),
+    })
+}
+
+/// By column name, so files exported with extra or reordered columns still read.
+fn from_row(row: &Row, trades: bool) -> Option<Event> {
+    let get = |name: &str| row.get_column_iter().find(|(n, _)| n.as_str() == name).map(|(_, v)| v);
+    let num = |name: &str| match get(name)? {
+        Field::Double(v) => Some(*v),
+        Field::Float(v) => Some(*v as f64),
+        Field::Long(v) => Some(*v as f64),
+        Field::Int(v) => Some(*v as f64),
+        _ => None,
+    };
+    // `ts_ms`, else the designated `timestamp`.
+    let ts_ms = match get("ts_ms").or_else(|| get("timestamp"))? {
+        Field::Long(v) | Field::TimestampMillis(v) => *v,
+        Field::TimestampMicros(v) => v.div_euclid(1000),
+        _ => return None,
+    };
+    let symbol = match get("symbol")? {
+        Field::Str(s) => s.clone(),
+        _ => return None,
+    };
+    Some(match trades {
+        true => Event::Trade(NormTrade {
+            symbol,
+            ts_ms,
+            price: num("price")?,
+            qty: num("qty")?,
+            trade_id: num("trade_id").unwrap_or(0.0) as i64,
+            is_bm: matches!(get("is_bm"), Some(Field::Bool(true))),
+            session: None,
+            notional: None,
+            first_trade_id: None,
+            contract_size: None,
+            ts_ns: None,
+            ingest_ts_ns: None,
+        }),
+        false => Event::Bar(Bar {
+            symbol,
+            ts_ms,
+            open: num("open")?,
+            high: num("high")?,
+            low: num("low")?,
+            close: num("close")?,
+            volume: num("volume").unwrap_or(0.0),
+            trades: num("trades").unwrap_or(0.0) as u64,
+        }),
+    })
+}
@@ -0,0 +1,43 @@
+use backtest::report::Report;
+use backtest::source::{Event, Replay};
+use consumer::ilp::NormTrade;
+use consumer::rollup::Bar;
+use strategy::engine::Engine;
+use strategy::signal::parse;
+
+fn trade(ts_ms: i64, price: f64) -> NormTrade {
+    NormTrade { ts_ms, symbol: "BTC".into(), price, qty: 1.0, trade_id: ts_ms, is_bm: false, session: None, notional: None, first_trade_id: None, contract_size: None, ts_ns: None, ingest_ts_ns: None }
+}
+
+#[test]
+fn trades_replay_as_bars_ahead_of_the_next_windows_trade() {
+    let mut r = Replay::new(60_000);
+    assert_eq!(r.trade(trade(1_000, 10.0)).len(), 1);
+    r.trade(trade(59_000, 12.0));
+    let out = r.trade(trade(61_000, 11.0));
+    let Event::Bar(bar) = &out[0] else { panic!("bar first") };
+    assert_eq!((bar.ts_ms, bar.open, bar.close, bar.trades), (0, 10.0, 12.0, 2));
+    assert!(matches!(&out[1], Event::Trade(t) if t.ts_ms == 61_000));
+    assert!(matches!(&r.finish()[..], [Event::Bar(b)] if b.ts_ms == 60_000));
+}
+
+#[test]
+fn report_tracks_pnl_drawdown_and_fees_across_days() {
+    let day = 86_400_000;
+    let mut e = Engine::new(parse("momentum:1:1").unwrap(), 10.0);
+    let mut report = Report::default();
+    for (ts, close) in [(0, 100.0), (1, 110.0), (day, 120.0), (day + 1, 100.0), (2 * day, 90.0)] {
+        let bar = Bar { symbol: "BTC".into(), ts_ms: ts, open: close, high: close, low: close, close, volume: 1.0, trades: 1 };
+        let (fills, pnl) = e.on_bar(&bar);
+        report.fills(&fills);
+        report.pnl(&pnl);
+    }
+    let s = &report.summaries()[0];
+    // Long at 110, up 10 at 120, flipped short at 100 (-10), then 90 (+10).
+    assert_eq!((s.fills, s.days), (2, 3));
+    assert!((s.turnover - 310.0).abs() < 1e-9);
+    assert!((s.fees - 0.31).abs() < 1e-9);
+    assert!((s.pnl - (0.0 - 0.31)).abs() < 1e-9, "{}", s.pnl);
+    assert!((s.max_drawdown - 20.2).abs() < 1e-9, "{}", s.max_drawdown);
+    assert!(s.sharpe.is_some());
+}
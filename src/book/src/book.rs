@@ -0,0 +1,179 @@
+//! One symbol's L2 book, as Binance's REST `depth` snapshot plus the
+//! `depthUpdate` diffs after it, and the records published from it.
+//!
+//! Diffs carry absolute quantities per price level (0 removes the level), so
+//! applying one twice changes nothing; only a missed one corrupts the book.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// A price level's key. Parsed levels are finite, so `total_cmp` is the usual order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Price(f64);
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// `GET /api/v3/depth`.
+#[derive(Debug, Deserialize)]
+pub struct RestDepth {
+    #[serde(rename = "lastUpdateId")]
+    pub last_update_id: u64,
+    pub bids: Vec<[String; 2]>,
+    pub asks: Vec<[String; 2]>,
+}
+
+/// A `<symbol>@depth` event: every level that changed in updates `U..=u`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DepthUpdate {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    /// Event time, ms.
+    #[serde(rename = "E")]
+    pub ts_ms: i64,
+    #[serde(rename = "U")]
+    pub first_update_id: u64,
+    #[serde(rename = "u")]
+    pub last_update_id: u64,
+    #[serde(rename = "b")]
+    pub bids: Vec<[String; 2]>,
+    #[serde(rename = "a")]
+    pub asks: Vec<[String; 2]>,
+}
+
+pub fn parse_depth_update(payload: &str) -> Result<DepthUpdate> {
+    let u: DepthUpdate = serde_json::from_str(payload)?;
+    anyhow::ensure!(u.first_update_id <= u.last_update_id, "update ids {}..={} run backwards", u.first_update_id, u.last_update_id);
+    Ok(u)
+}
+
+fn levels(side: &[[String; 2]]) -> Result<Vec<(f64, f64)>> {
+    side.iter()
+        .map(|[p, q]| {
+            let (price, qty): (f64, f64) = (p.parse().context("price")?, q.parse().context("qty")?);
+            anyhow::ensure!(price.is_finite() && price > 0.0 && qty.is_finite() && qty >= 0.0, "bad level {}@{}", q, p);
+            Ok((price, qty))
+        })
+        .collect()
+}
+
+/// Best bid and ask, published to the compacted top-of-book topic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Top {
+    pub symbol: String,
+    /// Event time of the last update applied, ms.
+    pub ts_ms: i64,
+    pub update_id: u64,
+    pub bid: f64,
+    pub bid_qty: f64,
+    pub ask: f64,
+    pub ask_qty: f64,
+}
+
+impl Top {
+    /// Same prices and sizes, whatever the time.
+    pub fn same_quote(&self, other: &Top) -> bool {
+        (self.bid, self.bid_qty, self.ask, self.ask_qty) == (other.bid, other.bid_qty, other.ask, other.ask_qty)
+    }
+}
+
+/// The book's levels, best first, as `[price, qty]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub symbol: String,
+    pub ts_ms: i64,
+    pub update_id: u64,
+    pub bids: Vec<(f64, f64)>,
+    pub asks: Vec<(f64, f64)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Book {
+    pub symbol: String,
+    /// The last update id reflected in the book.
+    pub update_id: u64,
+    /// Event time of the last update applied; the fetch time for a fresh snapshot.
+    pub ts_ms: i64,
+    bids: BTreeMap<Price, f64>,
+    asks: BTreeMap<Price, f64>,
+}
+
+impl Book {
+    pub fn from_rest(symbol: &str, depth: &RestDepth, ts_ms: i64) -> Result<Self> {
+        let mut book = Self {
+            symbol: symbol.to_uppercase(),
+            update_id: depth.last_update_id,
+            ts_ms,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        };
+        book.set(&levels(&depth.bids)?, &levels(&depth.asks)?);
+        Ok(book)
+    }
+
+    fn set(&mut self, bids: &[(f64, f64)], asks: &[(f64, f64)]) {
+        for (side, levels) in [(&mut self.bids, bids), (&mut self.asks, asks)] {
+            for &(price, qty) in levels {
+                if qty == 0.0 {
+                    side.remove(&Price(price));
+                } else {
+                    side.insert(Price(price), qty);
+                }
+            }
+        }
+    }
+
+    /// Apply `u`'s levels and move to its last update id. Sequencing is the caller's.
+    pub fn apply(&mut self, u: &DepthUpdate) -> Result<()> {
+        let (bids, asks) = (levels(&u.bids)?, levels(&u.asks)?);
+        self.set(&bids, &asks);
+        self.update_id = u.last_update_id;
+        self.ts_ms = u.ts_ms;
+        Ok(())
+    }
+
+    pub fn best_bid(&self) -> Option<(f64, f64)> {
+        self.bids.iter().next_back().map(|(p, q)| (p.0, *q))
+    }
+
+    pub fn best_ask(&self) -> Option<(f64, f64)> {
+        self.asks.iter().next().map(|(p, q)| (p.0, *q))
+    }
+
+    /// `None` while either side is empty.
+    pub fn top(&self) -> Option<Top> {
+        let ((bid, bid_qty), (ask, ask_qty)) = (self.best_bid()?, self.best_ask()?);
+        Some(Top { symbol: self.symbol.clone(), ts_ms: self.ts_ms, update_id: self.update_id, bid, bid_qty, ask, ask_qty })
+    }
+
+    /// Up to `depth` levels a side; 0 for all of them.
+    pub fn snapshot(&self, depth: usize) -> Snapshot {
+        let depth = if depth == 0 { usize::MAX } else { depth };
+        Snapshot {
+            symbol: self.symbol.clone(),
+            ts_ms: self.ts_ms,
+            update_id: self.update_id,
+            bids: self.bids.iter().rev().take(depth).map(|(p, q)| (p.0, *q)).collect(),
+            asks: self.asks.iter().take(depth).map(|(p, q)| (p.0, *q)).collect(),
+        }
+    }
+
+    /// Levels on the (bid, ask) side.
+    pub fn depth(&self) -> (usize, usize) {
+        (self.bids.len(), self.asks.len())
+    }
+}
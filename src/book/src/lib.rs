@@ -0,0 +1,6 @@
+//! Rebuild Binance L2 books from `depth` diffs and publish them for readers
+//! that join late: the top of book on every change and full-depth snapshots
+//! on an interval, both to compacted topics keyed by symbol.
+
+pub mod book;
+pub mod sync;
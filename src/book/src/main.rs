@@ -0,0 +1,184 @@
+//! Book builder: Binance `depth` diffs from `depth.raw` -> per-symbol L2
+//! books -> `book.top` (every top-of-book change) and `book.snapshots` (full
+//! depth every `BOOK_SNAPSHOT_SECS`).
+//!
+//! Both outputs are compacted and keyed by symbol, so a reader that starts
+//! late reads one record per symbol instead of every diff. Books are only
+//! state: diffs are committed on an interval, and after a restart each book
+//! is rebuilt from a fresh REST snapshot.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use book::book::parse_depth_update;
+use book::sync::{self, Rejected, Step, Syncer};
+use bus::commit::{CommitStrategy, Committer};
+use bus::{BusConfig, Headers, Publisher};
+use metrics::counter;
+use obsv::load::Load;
+use obsv::{init_metrics, init_tracing, startup};
+use retry::{Backoff, Policy, Retry};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+fn env<T: AsRef<str>>(k: T, default: &str) -> String {
+    std::env::var(k.as_ref()).unwrap_or_else(|_| default.to_string())
+}
+
+/// Publish on `policy`. A compacted topic is only useful to late joiners if
+/// its latest record is the book's, so a record that can't be published
+/// stops the stage rather than being skipped.
+async fn publish<T: Serialize>(publisher: &dyn Publisher, policy: &Policy, topic: &str, symbol: &str, record: &T) -> Result<()> {
+    let (body, headers) = (serde_json::to_vec(record)?, Headers::new());
+    policy.run("kafka_publish", || publisher.publish(topic, symbol, &body, &headers)).await
+        .with_context(|| format!("publishing the {} book to {}", symbol, topic))
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!("nats", "pulsar"), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    init_metrics(9476)?;
+
+    let topic_in = env("TOPIC_IN", "depth.raw");
+    let top_topic = env("BOOK_TOP_TOPIC", "book.top");
+    let snapshot_topic = env("BOOK_SNAPSHOT_TOPIC", "book.snapshots");
+    let group_id = env("GROUP_ID", "book");
+    let every = Duration::from_secs(env("BOOK_SNAPSHOT_SECS", "10").parse().unwrap_or(10).max(1));
+    // Levels a side in each published snapshot; 0 publishes the whole book.
+    let depth: usize = env("BOOK_SNAPSHOT_DEPTH", "0").parse().unwrap_or(0);
+    let rest_limit: u32 = env("BOOK_REST_LIMIT", "1000").parse().unwrap_or(1000);
+    let max_buffer: usize = env("BOOK_MAX_BUFFERED", "10000").parse().unwrap_or(10_000);
+    let rest_base = env("BINANCE_API_URL", "https://api.binance.com");
+    let publishes = Policy::times(
+        env("PUBLISH_RETRIES", "5").parse().unwrap_or(5),
+        Backoff::from_env("PUBLISH", Duration::from_millis(100), Duration::from_secs(5)),
+    );
+    let rest = Policy::times(
+        env("BOOK_REST_RETRIES", "3").parse().unwrap_or(3),
+        Backoff::from_env("BOOK_REST", Duration::from_millis(500), Duration::from_secs(10)),
+    );
+
+    let bus = BusConfig::from_env()?;
+    let topics = [topic_in.as_str(), top_topic.as_str(), snapshot_topic.as_str()];
+    startup::wait_for("kafka", || bus.check_topics(&topics)).await?;
+    bus::audit::start(&bus).await;
+    let mut subscriber = bus.subscriber(&topic_in, &group_id).await?;
+    let publisher = bus.publisher().await?;
+    bus.ensure_compacted(&top_topic).await?;
+    bus.ensure_compacted(&snapshot_topic).await?;
+    let mut commits = Committer::new(CommitStrategy::Interval(Duration::from_secs(1)));
+    let http = reqwest::Client::new();
+
+    let mut books: HashMap<String, Syncer> = HashMap::new();
+    // Symbols with a snapshot fetch in flight, and the backoff between refetches.
+    let mut fetching: HashMap<String, Retry> = HashMap::new();
+    let (fetched_tx, mut fetched) = mpsc::unbounded_channel();
+    let fetch = |symbol: String, after: Duration| {
+        let (http, base, tx, rest) = (http.clone(), rest_base.clone(), fetched_tx.clone(), rest.clone());
+        tokio::spawn(async move {
+            tokio::time::sleep(after).await;
+            let res = rest.run("book_snapshot", || sync::fetch(&http, &base, &symbol, rest_limit)).await;
+            let _ = tx.send((symbol, res));
+        });
+    };
+    let mut tick = tokio::time::interval(every);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    tracing::info!(target="book", input=%topic_in, top=%top_topic, snapshots=%snapshot_topic, "building books");
+
+    let load = Load::new("book")?;
+    loop {
+        tokio::select! {
+            next = subscriber.next() => match next {
+                None => break,
+                Some(Err(e)) => tracing::error!(target="book", error=?e, "poll error"),
+                Some(Ok(d)) => {
+                    let _busy = load.busy();
+                    load.processed(1);
+                    match d.payload_str().map(parse_depth_update) {
+                        Some(Ok(u)) => {
+                            let symbol = u.symbol.to_uppercase();
+                            let syncer = books.entry(symbol.clone()).or_insert_with(|| Syncer::new(max_buffer));
+                            match syncer.update(u) {
+                                Ok(step) => {
+                                    let name = match step { Step::Applied => "applied", Step::Buffered => "buffered", Step::Stale => "stale", Step::Gap => "gap" };
+                                    counter!("book_diffs_total", "step" => name).increment(1);
+                                    if step == Step::Applied {
+                                        if let Some(top) = syncer.changed_top() {
+                                            publish(publisher.as_ref(), &publishes, &top_topic, &symbol, &top).await?;
+                                            counter!("book_top_published_total").increment(1);
+                                        }
+                                    }
+                                }
+                                Err(e) => {
+                                    tracing::warn!(target="book", symbol=%symbol, error=?e, "bad depth diff");
+                                    counter!("book_bad_diffs_total").increment(1);
+                                }
+                            }
+                            if syncer.needs_snapshot() && !fetching.contains_key(&symbol) {
+                                fetching.insert(symbol.clone(), rest.start("book_refetch"));
+                                fetch(symbol, Duration::ZERO);
+                            }
+                        }
+                        _ => counter!("book_bad_diffs_total").increment(1),
+                    }
+                    if let Err(e) = commits.done(subscriber.as_ref(), d).await {
+                        tracing::warn!(target="book", error=?e, "commit failed");
+                    }
+                }
+            },
+            Some((symbol, res)) = fetched.recv() => {
+                let _busy = load.busy();
+                let Some(syncer) = books.get_mut(&symbol) else { continue };
+                let outcome = match res {
+                    Ok(b) => syncer.snapshot(b),
+                    Err(e) => Err(e),
+                };
+                match outcome {
+                    Ok(Ok(())) => {
+                        counter!("book_snapshot_fetches_total", "result" => "ok").increment(1);
+                        fetching.remove(&symbol);
+                        if let Some(b) = syncer.book() {
+                            tracing::info!(target="book", symbol=%symbol, update_id=b.update_id, levels=?b.depth(), "book live");
+                            publish(publisher.as_ref(), &publishes, &snapshot_topic, &symbol, &b.snapshot(depth)).await?;
+                            counter!("book_snapshots_published_total").increment(1);
+                        }
+                        if let Some(top) = syncer.changed_top() {
+                            publish(publisher.as_ref(), &publishes, &top_topic, &symbol, &top).await?;
+                            counter!("book_top_published_total").increment(1);
+                        }
+                    }
+                    Ok(Err(Rejected::NotNeeded)) => {
+                        fetching.remove(&symbol);
+                    }
+                    // The diffs have moved past it already; the next one will be newer.
+                    Ok(Err(Rejected::TooOld)) => {
+                        counter!("book_snapshot_fetches_total", "result" => "too_old").increment(1);
+                        let wait = fetching.get_mut(&symbol).map_or(Duration::ZERO, Retry::next_wait);
+                        fetch(symbol, wait);
+                    }
+                    Err(e) => {
+                        tracing::warn!(target="book", symbol=%symbol, error=?e, "depth snapshot failed; will retry");
+                        counter!("book_snapshot_fetches_total", "result" => "error").increment(1);
+                        let wait = fetching.get_mut(&symbol).map_or(Duration::ZERO, Retry::next_wait);
+                        fetch(symbol, wait);
+                    }
+                }
+            },
+            _ = tick.tick() => {
+                let _busy = load.busy();
+                for (symbol, syncer) in &books {
+                    if let Some(b) = syncer.book() {
+                        publish(publisher.as_ref(), &publishes, &snapshot_topic, symbol, &b.snapshot(depth)).await?;
+                        counter!("book_snapshots_published_total").increment(1);
+                    }
+                }
+            }
+        }
+    }
+    let _ = commits.commit(subscriber.as_ref()).await;
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! Keeping a [`Book`] in step with the diff stream, Binance's way: buffer
+//! diffs, fetch a REST snapshot, drop the diffs it already reflects, and
+//! check every diff after that picks up where the book left off.
+//!
+//! A diff covering updates `U..=u` follows a book at `id` when
+//! `U <= id + 1 <= u`. One with `u <= id` is already in the book. One with
+//! `U > id + 1` means updates were missed: the book is thrown away and
+//! rebuilt from a new snapshot.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+
+use crate::book::{Book, DepthUpdate, RestDepth, Top};
+
+/// What a diff did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Applied,
+    /// No book yet; kept for when the snapshot arrives.
+    Buffered,
+    /// Already reflected in the book.
+    Stale,
+    /// Updates were missed; the book is gone until a new snapshot.
+    Gap,
+}
+
+/// Why a snapshot wasn't used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejected {
+    /// Older than the first buffered diff after it: fetch another.
+    TooOld,
+    /// A book is already live.
+    NotNeeded,
+}
+
+/// One symbol's book and the diffs waiting for it.
+pub struct Syncer {
+    book: Option<Book>,
+    buffer: VecDeque<DepthUpdate>,
+    max_buffer: usize,
+    /// The top last handed out by [`changed_top`](Syncer::changed_top).
+    last_top: Option<Top>,
+}
+
+impl Syncer {
+    /// Keep at most `max_buffer` diffs (the newest) while waiting for a snapshot.
+    pub fn new(max_buffer: usize) -> Self {
+        Self { book: None, buffer: VecDeque::new(), max_buffer: max_buffer.max(1), last_top: None }
+    }
+
+    pub fn book(&self) -> Option<&Book> {
+        self.book.as_ref()
+    }
+
+    pub fn needs_snapshot(&self) -> bool {
+        self.book.is_none()
+    }
+
+    pub fn update(&mut self, u: DepthUpdate) -> Result<Step> {
+        let Some(book) = &mut self.book else {
+            if self.buffer.len() == self.max_buffer {
+                self.buffer.pop_front();
+            }
+            self.buffer.push_back(u);
+            return Ok(Step::Buffered);
+        };
+        if u.last_update_id <= book.update_id {
+            return Ok(Step::Stale);
+        }
+        if u.first_update_id > book.update_id + 1 {
+            tracing::warn!(target="book", symbol=%book.symbol, at=book.update_id, next=u.first_update_id, "depth gap; resyncing");
+            self.book = None;
+            self.buffer.clear();
+            self.buffer.push_back(u);
+            return Ok(Step::Gap);
+        }
+        book.apply(&u)?;
+        Ok(Step::Applied)
+    }
+
+    /// Start from `book` and replay the buffered diffs onto it.
+    pub fn snapshot(&mut self, mut book: Book) -> Result<std::result::Result<(), Rejected>> {
+        if self.book.is_some() {
+            return Ok(Err(Rejected::NotNeeded));
+        }
+        while self.buffer.front().is_some_and(|u| u.last_update_id <= book.update_id) {
+            self.buffer.pop_front();
+        }
+        if self.buffer.front().is_some_and(|u| u.first_update_id > book.update_id + 1) {
+            return Ok(Err(Rejected::TooOld));
+        }
+        for u in self.buffer.drain(..) {
+            anyhow::ensure!(u.first_update_id <= book.update_id + 1, "buffered depth diffs skip {}..{}", book.update_id + 1, u.first_update_id);
+            book.apply(&u)?;
+        }
+        self.book = Some(book);
+        Ok(Ok(()))
+    }
+
+    /// The top of book, if it moved (price or size) since last asked.
+    pub fn changed_top(&mut self) -> Option<Top> {
+        let top = self.book.as_ref()?.top()?;
+        if self.last_top.as_ref().is_some_and(|last| last.same_quote(&top)) {
+            return None;
+        }
+        self.last_top = Some(top.clone());
+        Some(top)
+    }
+}
+
+/// REST request weight of a `depth` snapshot with `limit` levels.
+pub fn depth_weight(limit: u32) -> u32 {
+    match limit {
+        0..=100 => 5,
+        101..=500 => 25,
+        501..=1000 => 50,
+        _ => 250,
+    }
+}
+
+/// `GET <base>/api/v3/depth` through the `binance` REST limiter.
+pub async fn fetch(http: &reqwest::Client, base: &str, symbol: &str, limit: u32) -> Result<Book> {
+    let req = http.get(format!("{}/api/v3/depth", base.trim_end_matches('/')))
+        .query(&[("symbol", symbol.to_uppercase()), ("limit", limit.to_string())])
+        .timeout(Duration::from_secs(10));
+    let depth: RestDepth = ratelimit::shared("binance")?.send(req, depth_weight(limit)).await?
+        .error_for_status()?.json().await
+        .with_context(|| format!("{} depth snapshot", symbol))?;
+    Book::from_rest(symbol, &depth, Utc::now().timestamp_millis())
+}
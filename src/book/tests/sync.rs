@@ -0,0 +1,80 @@
+use book::book::{parse_depth_update, Book, DepthUpdate, RestDepth};
+use book::sync::{depth_weight, Rejected, Step, Syncer};
+
+fn rest(last_update_id: u64, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> Book {
+    let side = |s: &[(&str, &str)]| s.iter().map(|(p, q)| [p.to_string(), q.to_string()]).collect();
+    Book::from_rest("btcusdt", &RestDepth { last_update_id, bids: side(bids), asks: side(asks) }, 1_000).unwrap()
+}
+
+fn diff(first: u64, last: u64, bids: &str, asks: &str) -> DepthUpdate {
+    parse_depth_update(&format!(r#"{{"e":"depthUpdate","E":{},"s":"BTCUSDT","U":{},"u":{},"b":{},"a":{}}}"#,
+        1_000 + last, first, last, bids, asks)).unwrap()
+}
+
+#[test]
+fn buffered_diffs_are_replayed_onto_the_snapshot() {
+    let mut s = Syncer::new(100);
+    assert_eq!(s.update(diff(95, 99, r#"[["99","9"]]"#, "[]")).unwrap(), Step::Buffered);
+    assert_eq!(s.update(diff(100, 102, r#"[["100","0"]]"#, r#"[["101","2"]]"#)).unwrap(), Step::Buffered);
+    assert_eq!(s.update(diff(103, 103, "[]", r#"[["102","1"]]"#)).unwrap(), Step::Buffered);
+    assert!(s.needs_snapshot());
+
+    s.snapshot(rest(100, &[("100", "1"), ("98", "5")], &[("101", "3")])).unwrap().unwrap();
+    let book = s.book().unwrap();
+    assert_eq!(book.update_id, 103);
+    // 95..=99 was already in the snapshot; 100..=102 straddles it and applies.
+    assert_eq!((book.best_bid(), book.best_ask()), (Some((98.0, 5.0)), Some((101.0, 2.0))));
+    let snap = book.snapshot(0);
+    assert_eq!((snap.bids, snap.asks), (vec![(98.0, 5.0)], vec![(101.0, 2.0), (102.0, 1.0)]));
+    assert_eq!(book.snapshot(1).asks, [(101.0, 2.0)]);
+}
+
+#[test]
+fn a_snapshot_older_than_the_diffs_is_refused() {
+    let mut s = Syncer::new(100);
+    s.update(diff(110, 112, "[]", "[]")).unwrap();
+    assert_eq!(s.snapshot(rest(100, &[("1", "1")], &[("2", "1")])).unwrap(), Err(Rejected::TooOld));
+    assert!(s.needs_snapshot());
+    s.snapshot(rest(111, &[("1", "1")], &[("2", "1")])).unwrap().unwrap();
+    assert_eq!(s.book().unwrap().update_id, 112);
+    assert_eq!(s.snapshot(rest(200, &[], &[])).unwrap(), Err(Rejected::NotNeeded));
+}
+
+#[test]
+fn live_books_drop_stale_diffs_and_resync_on_gaps() {
+    let mut s = Syncer::new(100);
+    s.snapshot(rest(10, &[("100", "1")], &[("101", "1")])).unwrap().unwrap();
+    assert_eq!(s.update(diff(5, 10, r#"[["100","7"]]"#, "[]")).unwrap(), Step::Stale);
+    assert_eq!(s.update(diff(11, 12, r#"[["100","2"]]"#, "[]")).unwrap(), Step::Applied);
+    assert_eq!(s.book().unwrap().best_bid(), Some((100.0, 2.0)));
+    assert_eq!(s.update(diff(14, 15, "[]", "[]")).unwrap(), Step::Gap);
+    assert!(s.needs_snapshot());
+    // The diff after the gap waits for the next snapshot.
+    s.snapshot(rest(13, &[("100", "1")], &[("101", "1")])).unwrap().unwrap();
+    assert_eq!(s.book().unwrap().update_id, 15);
+}
+
+#[test]
+fn the_top_is_handed_out_only_when_it_moves() {
+    let mut s = Syncer::new(100);
+    assert_eq!(s.changed_top(), None);
+    s.snapshot(rest(1, &[("100", "1")], &[("101", "1")])).unwrap().unwrap();
+    let top = s.changed_top().unwrap();
+    assert_eq!((top.symbol.as_str(), top.bid, top.ask, top.update_id), ("BTCUSDT", 100.0, 101.0, 1));
+    // A level behind the top changes nothing at the top.
+    s.update(diff(2, 2, r#"[["99","4"]]"#, "[]")).unwrap();
+    assert_eq!(s.changed_top(), None);
+    s.update(diff(3, 3, "[]", r#"[["101","0.5"]]"#)).unwrap();
+    assert_eq!(s.changed_top().map(|t| (t.ask_qty, t.update_id, t.ts_ms)), Some((0.5, 3, 1_003)));
+}
+
+#[test]
+fn diffs_with_bad_levels_or_ids_are_errors() {
+    assert!(parse_depth_update(r#"{"E":1,"s":"BTCUSDT","U":5,"u":4,"b":[],"a":[]}"#).is_err());
+    let mut s = Syncer::new(100);
+    s.snapshot(rest(1, &[], &[])).unwrap().unwrap();
+    assert!(s.update(diff(2, 2, r#"[["NaN","1"]]"#, "[]")).is_err());
+    assert!(s.update(diff(2, 2, r#"[["100","-1"]]"#, "[]")).is_err());
+    assert_eq!(s.book().unwrap().update_id, 1);
+    assert_eq!((depth_weight(100), depth_weight(1000), depth_weight(5000)), (5, 50, 250));
+}
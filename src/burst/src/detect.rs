@@ -0,0 +1,160 @@
+//! Trade counts per symbol in short event-time windows, against a rolling
+//! baseline.
+//!
+//! The baseline is an EWMA of the per-window count over about `baseline`
+//! worth of windows; quiet windows count as zeros. A window is a burst when
+//! it has at least `min_trades` trades and `factor` times the baseline, once
+//! the baseline has seen enough windows to mean something. Each burst also
+//! says how far the price moved: a burst of trades at one price looks like a
+//! venue glitch or stuffing, one with a real move looks like volatility.
+
+use std::collections::HashMap;
+
+use consumer::ilp::NormTrade;
+use metrics::gauge;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    pub window_ms: i64,
+    pub baseline_ms: i64,
+    pub factor: f64,
+    pub min_trades: u64,
+    /// A burst whose high-low range reaches this is `moving`, otherwise `flat`.
+    pub move_bps: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Anomaly {
+    /// Always `burst`; other detectors can share the topic.
+    #[serde(rename = "type")]
+    pub anomaly: &'static str,
+    pub symbol: String,
+    /// Window start, ms since the epoch.
+    pub ts_ms: i64,
+    pub window_ms: i64,
+    pub trades: u64,
+    pub rate_per_s: f64,
+    pub baseline_per_s: f64,
+    pub ratio: f64,
+    pub volume: f64,
+    /// (high - low) / low over the window.
+    pub range_bps: f64,
+    /// (last - first) / first over the window.
+    pub net_bps: f64,
+    /// `flat` or `moving`.
+    pub kind: &'static str,
+}
+
+#[derive(Debug, Default)]
+struct Track {
+    /// Start of the window being counted; `None` between windows.
+    open: Option<i64>,
+    /// End of the last window folded into the baseline.
+    next: Option<i64>,
+    trades: u64,
+    volume: f64,
+    first: f64,
+    last: f64,
+    high: f64,
+    low: f64,
+    baseline: f64,
+    /// Windows folded into the baseline so far.
+    seen: u64,
+}
+
+pub struct Detector {
+    cfg: Config,
+    alpha: f64,
+    warmup: u64,
+    symbols: HashMap<String, Track>,
+}
+
+impl Detector {
+    pub fn new(cfg: Config) -> Self {
+        let windows = (cfg.baseline_ms / cfg.window_ms.max(1)).max(1);
+        Self {
+            cfg,
+            alpha: 1.0 / windows as f64,
+            // Enough history that one noisy minute doesn't set the bar.
+            warmup: windows.min(60) as u64,
+            symbols: HashMap::new(),
+        }
+    }
+
+    /// Count a trade; returns a burst if it closed a window that was one.
+    pub fn add(&mut self, t: &NormTrade) -> Option<Anomaly> {
+        let start = t.ts_ms - t.ts_ms.rem_euclid(self.cfg.window_ms);
+        let track = self.symbols.entry(t.symbol.clone()).or_default();
+        let mut out = None;
+        match track.open {
+            // Out-of-order trades count towards the open window; the rate is what matters.
+            Some(open) if start <= open => {}
+            Some(_) => {
+                out = close(&self.cfg, self.alpha, self.warmup, &t.symbol, track);
+                open_at(self.alpha, self.cfg.window_ms, track, start);
+            }
+            None => open_at(self.alpha, self.cfg.window_ms, track, start),
+        }
+        track.trades += 1;
+        track.volume += t.qty;
+        if track.trades == 1 {
+            (track.first, track.high, track.low) = (t.price, t.price, t.price);
+        }
+        track.last = t.price;
+        track.high = track.high.max(t.price);
+        track.low = track.low.min(t.price);
+        out
+    }
+
+    /// Close windows that ended at least a window ago by wall clock, so a
+    /// burst followed by silence still gets reported.
+    pub fn close_idle(&mut self, now_ms: i64) -> Vec<Anomaly> {
+        let w = self.cfg.window_ms;
+        self.symbols.iter_mut()
+            .filter(|(_, t)| t.open.is_some_and(|s| now_ms >= s + 2 * w))
+            .filter_map(|(symbol, t)| close(&self.cfg, self.alpha, self.warmup, symbol, t))
+            .collect()
+    }
+}
+
+/// Start counting the window at `start`, first folding any empty windows since the last one.
+fn open_at(alpha: f64, window_ms: i64, track: &mut Track, start: i64) {
+    if let Some(next) = track.next {
+        let empty = ((start - next) / window_ms).max(0);
+        track.baseline *= (1.0 - alpha).powi(empty.min(i32::MAX as i64) as i32);
+        track.seen += empty as u64;
+    }
+    track.open = Some(start);
+    (track.trades, track.volume) = (0, 0.0);
+}
+
+fn close(cfg: &Config, alpha: f64, warmup: u64, symbol: &str, track: &mut Track) -> Option<Anomaly> {
+    let start = track.open.take()?;
+    let n = track.trades as f64;
+    let per_s = 1000.0 / cfg.window_ms as f64;
+    let burst = track.seen >= warmup && track.trades >= cfg.min_trades && n >= cfg.factor * track.baseline;
+    let out = burst.then(|| {
+        let range_bps = if track.low > 0.0 { (track.high - track.low) / track.low * 1e4 } else { 0.0 };
+        Anomaly {
+            anomaly: "burst",
+            symbol: symbol.to_string(),
+            ts_ms: start,
+            window_ms: cfg.window_ms,
+            trades: track.trades,
+            rate_per_s: n * per_s,
+            baseline_per_s: track.baseline * per_s,
+            ratio: if track.baseline > 0.0 { n / track.baseline } else { f64::INFINITY },
+            volume: track.volume,
+            range_bps,
+            net_bps: if track.first > 0.0 { (track.last - track.first) / track.first * 1e4 } else { 0.0 },
+            kind: if range_bps >= cfg.move_bps { "moving" } else { "flat" },
+        }
+    });
+    track.baseline = if track.seen == 0 { n } else { track.baseline + alpha * (n - track.baseline) };
+    track.seen += 1;
+    track.next = Some(start + cfg.window_ms);
+    gauge!("burst_rate", "symbol" => symbol.to_string()).set(n * per_s);
+    gauge!("burst_baseline", "symbol" => symbol.to_string()).set(track.baseline * per_s);
+    out
+}
@@ -0,0 +1,3 @@
+//! Per-symbol trade-rate burst detection.
+
+pub mod detect;
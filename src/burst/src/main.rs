@@ -0,0 +1,116 @@
+//! Burst detector: `ticks.norm` -> `anomalies`.
+//!
+//! Baselines are only in memory, so after a restart nothing is flagged until
+//! the warm-up has passed again. Offsets are committed on an interval once
+//! the anomalies up to them are published.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use burst::detect::{Anomaly, Config, Detector};
+use bus::commit::{CommitStrategy, Committer};
+use bus::envelope::{self, NORM_SCHEMA};
+use bus::{BusConfig, Headers, Publisher};
+use calendar::Calendar;
+use chrono::Utc;
+use consumer::decode::decode;
+use metrics::counter;
+use obsv::load::Load;
+use obsv::{init_metrics, init_tracing, startup};
+use retry::Policy;
+
+fn env<T: AsRef<str>>(k: T, default: &str) -> String {
+    std::env::var(k.as_ref()).unwrap_or_else(|_| default.to_string())
+}
+
+/// Publish bursts on `policy`; ones inside a maintenance window are dropped.
+/// Once the policy gives up, the error ends the stage before the trades
+/// behind the burst are committed.
+async fn report(publisher: &dyn Publisher, policy: &Policy, topic: &str, calendar: &Calendar, found: Vec<Anomaly>) -> Result<()> {
+    let headers = Headers::new();
+    for a in found {
+        if calendar.in_maintenance(a.ts_ms) {
+            counter!("anomalies_suppressed_total").increment(1);
+            continue;
+        }
+        tracing::warn!(target="burst", symbol=%a.symbol, ts_ms=a.ts_ms, trades=a.trades, ratio=a.ratio,
+            range_bps=a.range_bps, kind=a.kind, "trade burst");
+        let body = serde_json::to_vec(&a)?;
+        policy.run("kafka_publish", || publisher.publish(topic, &a.symbol, &body, &headers)).await
+            .with_context(|| format!("publishing the {} burst to {}", a.symbol, topic))?;
+        counter!("anomalies_total", "symbol" => a.symbol.clone(), "kind" => a.kind).increment(1);
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!("nats", "pulsar"), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    init_metrics(9471)?;
+
+    let topic_in = env("TOPIC_IN", "ticks.norm");
+    let topic_out = env("ANOMALIES_TOPIC", "anomalies");
+    let group_id = env("GROUP_ID", "burst");
+    let cfg = Config {
+        window_ms: env("BURST_WINDOW_MS", "1000").parse().unwrap_or(1000),
+        baseline_ms: env("BURST_BASELINE_MS", "300000").parse().unwrap_or(300_000),
+        factor: env("BURST_FACTOR", "10").parse().unwrap_or(10.0),
+        min_trades: env("BURST_MIN_TRADES", "50").parse().unwrap_or(50),
+        move_bps: env("BURST_MOVE_BPS", "10").parse().unwrap_or(10.0),
+    };
+    anyhow::ensure!(cfg.window_ms > 0 && cfg.baseline_ms >= cfg.window_ms, "BURST_BASELINE_MS must be at least BURST_WINDOW_MS");
+    let calendar = Calendar::from_env()?;
+
+    let bus = BusConfig::from_env()?;
+    let topics = [topic_in.as_str(), topic_out.as_str()];
+    startup::wait_for("kafka", || bus.check_topics(&topics)).await?;
+    bus::audit::start(&bus).await;
+    let mut subscriber = bus.subscriber(&topic_in, &group_id).await?;
+    let publisher = bus.publisher().await?;
+    let policy = Policy::from_env("PUBLISH", 5, Duration::from_millis(100), Duration::from_secs(5));
+    let mut committer = Committer::new(CommitStrategy::Interval(Duration::from_secs(1)));
+    let mut detector = Detector::new(cfg);
+    let mut tick = tokio::time::interval(Duration::from_millis(cfg.window_ms as u64));
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    tracing::info!(target="burst", input=%topic_in, output=%topic_out, ?cfg, "watching for bursts");
+
+    let load = Load::new("burst")?;
+    let mut stop = std::pin::pin!(obsv::runtime::stopping());
+    loop {
+        let res = tokio::select! {
+            _ = &mut stop => break,
+            next = subscriber.next() => match next {
+                None => break,
+                Some(Err(e)) => { tracing::error!(target="burst", error=?e, "poll error"); continue; }
+                Some(Ok(d)) => {
+                    let _busy = load.busy();
+                    load.processed(1);
+                    let compat = envelope::compat(&d.headers, NORM_SCHEMA);
+                    match d.payload_str().map(|p| decode(p, &compat)) {
+                        Some(Ok(t)) => report(publisher.as_ref(), &policy, &topic_out, &calendar, detector.add(&t).into_iter().collect()).await?,
+                        _ => {
+                            counter!("burst_dropped_total").increment(1);
+                            obsv::errors::record("burst", obsv::errors::ErrorKind::Data);
+                        }
+                    }
+                    committer.done(subscriber.as_ref(), d).await
+                }
+            },
+            _ = tick.tick() => {
+                let _busy = load.busy();
+                report(publisher.as_ref(), &policy, &topic_out, &calendar, detector.close_idle(Utc::now().timestamp_millis())).await?;
+                committer.flushed(subscriber.as_ref()).await
+            }
+        };
+        if let Err(e) = res {
+            tracing::warn!(target="burst", error=?e, "commit failed");
+        }
+    }
+    if let Err(e) = committer.commit(subscriber.as_ref()).await {
+        tracing::warn!(target="burst", error=?e, "final commit failed");
+    }
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use burst::detect::{Config, Detector};
+use consumer::ilp::NormTrade;
+
+fn trade(ts_ms: i64, price: f64) -> NormTrade {
+    NormTrade { ts_ms, symbol: "BTCUSDT".into(), price, qty: 0.1, trade_id: ts_ms, is_bm: false, session: None, notional: None, first_trade_id: None, contract_size: None, ts_ns: None, ingest_ts_ns: None }
+}
+
+fn detector() -> Detector {
+    Detector::new(Config { window_ms: 1000, baseline_ms: 10_000, factor: 5.0, min_trades: 20, move_bps: 10.0 })
+}
+
+#[test]
+fn a_window_far_above_the_baseline_is_a_burst() {
+    let mut d = detector();
+    // Ten warm-up windows of 4 trades each.
+    for s in 0..10 {
+        for i in 0..4 {
+            assert!(d.add(&trade(s * 1000 + i, 100.0)).is_none());
+        }
+    }
+    // 40 trades at one price, closed by the next window's first trade.
+    for i in 0..40 {
+        assert!(d.add(&trade(10_000 + i, 100.0)).is_none());
+    }
+    let a = d.add(&trade(11_000, 100.0)).expect("burst");
+    assert_eq!((a.ts_ms, a.trades, a.kind), (10_000, 40, "flat"));
+    assert_eq!((a.rate_per_s, a.baseline_per_s), (40.0, 4.0));
+
+    // The same burst after a quiet spell, with the price moving, while idle.
+    for i in 0..40 {
+        d.add(&trade(20_000 + i, 100.0 + i as f64 * 0.01));
+    }
+    assert!(d.close_idle(21_999).is_empty(), "not idle long enough");
+    let a = d.close_idle(22_000).pop().expect("burst");
+    assert_eq!((a.ts_ms, a.kind), (20_000, "moving"));
+    assert!(a.ratio > 5.0 && a.range_bps > 10.0);
+}
+
+#[test]
+fn nothing_is_flagged_before_the_warm_up_or_below_the_floor() {
+    let mut d = detector();
+    for i in 0..100 {
+        d.add(&trade(i, 100.0));
+    }
+    assert!(d.add(&trade(1000, 100.0)).is_none(), "first window has no baseline");
+
+    let mut d = detector();
+    for s in 0..10 {
+        d.add(&trade(s * 1000, 100.0));
+    }
+    for i in 0..19 {
+        d.add(&trade(10_000 + i, 100.0));
+    }
+    assert!(d.add(&trade(11_000, 100.0)).is_none(), "19 trades is under min_trades");
+}
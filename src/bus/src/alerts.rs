@@ -0,0 +1,81 @@
+//! SLO burn-rate alerts on a topic, so whatever pages on them needn't
+//! scrape every service.
+//!
+//! [`start`] publishes each [`obsv::slo::Alert`] as it fires and clears,
+//! keyed by the SLO's name: a JSON object with `slo`, `severity`, `state`
+//! (`firing` or `resolved`), `threshold`, `target`, `burn_rate` (over the
+//! rule's long window) and `ts_ms`, plus `service` and `instance`
+//! (`HOSTNAME`). `ALERTS_TOPIC` names the topic (default `alerts`); empty
+//! turns them off. A failed publish is logged and counted in
+//! `alert_publish_failures_total`; the alert's gauges are set regardless.
+
+use std::sync::{Arc, OnceLock};
+
+use metrics::counter;
+use obsv::slo::Alert;
+use serde_json::Value;
+
+use crate::{env, BusConfig, Headers, Publisher};
+
+pub struct Alerts {
+    publisher: Arc<dyn Publisher>,
+    topic: String,
+    service: String,
+    instance: String,
+}
+
+impl Alerts {
+    pub fn new(publisher: Arc<dyn Publisher>, topic: &str) -> Self {
+        Self {
+            publisher,
+            topic: topic.to_string(),
+            service: obsv::version::current().service.clone(),
+            instance: env("HOSTNAME", "unknown"),
+        }
+    }
+
+    pub async fn publish(&self, alert: &Alert) {
+        let mut body = serde_json::to_value(alert).unwrap_or_default();
+        if let Value::Object(fields) = &mut body {
+            fields.insert("service".into(), self.service.clone().into());
+            fields.insert("instance".into(), self.instance.clone().into());
+        }
+        let payload = body.to_string();
+        match self.publisher.publish(&self.topic, &alert.slo, payload.as_bytes(), &Headers::new()).await {
+            Ok(()) => counter!("alerts_published_total", "severity" => alert.severity, "state" => alert.state).increment(1),
+            Err(e) => {
+                counter!("alert_publish_failures_total", "severity" => alert.severity).increment(1);
+                tracing::warn!(target: "bus", topic = %self.topic, slo = %alert.slo, state = alert.state, error = %format!("{:#}", e), "SLO alert not published");
+            }
+        }
+    }
+}
+
+static STARTED: OnceLock<()> = OnceLock::new();
+
+/// Publish this process's SLO alerts from now on. Only the first call counts.
+pub async fn start(bus: &BusConfig) {
+    let topic = env("ALERTS_TOPIC", "alerts");
+    if topic.is_empty() || STARTED.set(()).is_err() {
+        return;
+    }
+    let publisher = match bus.publisher().await {
+        Ok(p) => p,
+        Err(e) => {
+            counter!("alert_publish_failures_total", "severity" => "none").increment(1);
+            tracing::warn!(target: "bus", error = %format!("{:#}", e), "no alerts publisher; SLO alerts stay in the metrics");
+            return;
+        }
+    };
+    let alerts = Alerts::new(publisher, &topic);
+    // The evaluator's a plain thread; a channel keeps each SLO's alerts in order.
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    obsv::slo::on_alert(move |alert| {
+        let _ = tx.send(alert.clone());
+    });
+    tokio::spawn(async move {
+        while let Some(alert) = rx.recv().await {
+            alerts.publish(&alert).await;
+        }
+    });
+}
@@ -0,0 +1,155 @@
+//! Lifecycle events on an ops topic, so what ran where, with which config,
+//! reading what, and why it stopped outlives the logs.
+//!
+//! A stage calls [`start`] once it has its [`BusConfig`]. Every event is a
+//! JSON object keyed by the service, with `event`, `service`, `instance`
+//! (`HOSTNAME`), `pid` and `ts_ms`:
+//!
+//! - `start`: also the build (`version`, `git_sha`, `rustc`, `features`),
+//!   `namespace`, `transport` and `config_hash`, a hash of the environment
+//!   that ignores the variables the platform sets and leaves secrets out
+//!   ([`config_hash`]).
+//! - `subscribed`: for each [`BusConfig::subscriber`], its `topic` and `group`,
+//!   and `subscriptions`, every one so far.
+//! - `shutdown`: `reason`, as [`obsv::runtime::on_shutdown`] gives it.
+//!
+//! `OPS_AUDIT_TOPIC` names the topic (default `ops.audit`); empty turns the
+//! events off. It's created with `OPS_AUDIT_RETENTION_MS` retention (default
+//! -1, forever). A failed publish is logged and counted in
+//! `audit_failures_total`, and never stops the stage.
+
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use metrics::counter;
+use serde_json::{json, Map, Value};
+
+use crate::{env, BusConfig, Headers, Publisher};
+
+/// Variables the platform sets per pod or shell; they'd change the hash
+/// without the config changing.
+const AMBIENT: &[&str] = &["HOSTNAME", "HOME", "PATH", "PWD", "OLDPWD", "SHLVL", "TERM", "USER", "LANG", "_"];
+
+/// A short SHA-256 of the `KEY=value` pairs, sorted, leaving out [`AMBIENT`]
+/// ones and `KUBERNETES_*`. Values go in as the admin API's `config` shows
+/// them ([`obsv::admin::redact`]): a secret's name counts but its value
+/// doesn't, as the topic is kept forever and an unsalted hash of a weak
+/// password can be brute-forced.
+pub fn config_hash(vars: impl IntoIterator<Item = (String, String)>) -> String {
+    let mut vars: Vec<(String, String)> = vars.into_iter()
+        .filter(|(k, _)| !AMBIENT.contains(&k.as_str()) && !k.starts_with("KUBERNETES_"))
+        .collect();
+    vars.sort();
+    let mut ctx = ring::digest::Context::new(&ring::digest::SHA256);
+    for (k, v) in &vars {
+        ctx.update(k.as_bytes());
+        ctx.update(b"=");
+        ctx.update(obsv::admin::redact(k, v).as_bytes());
+        ctx.update(b"\n");
+    }
+    crate::hex(&ctx.finish().as_ref()[..8])
+}
+
+pub struct Audit {
+    publisher: Arc<dyn Publisher>,
+    topic: String,
+    service: String,
+    instance: String,
+    subscriptions: Mutex<Vec<Value>>,
+}
+
+impl Audit {
+    pub fn new(publisher: Arc<dyn Publisher>, topic: &str) -> Self {
+        Self {
+            publisher,
+            topic: topic.to_string(),
+            service: obsv::version::current().service.clone(),
+            instance: env("HOSTNAME", "unknown"),
+            subscriptions: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Publish `event` with `fields` added to the common ones.
+    pub async fn emit(&self, event: &str, fields: Value) {
+        let ts_ms = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        let mut body = Map::new();
+        body.insert("event".into(), event.into());
+        body.insert("service".into(), self.service.clone().into());
+        body.insert("instance".into(), self.instance.clone().into());
+        body.insert("pid".into(), std::process::id().into());
+        body.insert("ts_ms".into(), ts_ms.into());
+        if let Value::Object(fields) = fields {
+            body.extend(fields);
+        }
+        let payload = Value::Object(body).to_string();
+        match self.publisher.publish(&self.topic, &self.service, payload.as_bytes(), &Headers::new()).await {
+            Ok(()) => counter!("audit_events_total", "event" => event.to_string()).increment(1),
+            Err(e) => {
+                counter!("audit_failures_total", "event" => event.to_string()).increment(1);
+                tracing::warn!(target: "bus", topic = %self.topic, event, error = %format!("{:#}", e), "audit event not published");
+            }
+        }
+    }
+
+    pub async fn started(&self, bus: &BusConfig) {
+        let build = obsv::version::current();
+        self.emit("start", json!({
+            "version": build.version,
+            "git_sha": build.git_sha,
+            "rustc": build.rustc,
+            "features": build.features,
+            "namespace": bus.namespace.name(),
+            "transport": format!("{:?}", bus.transport).to_lowercase(),
+            "config_hash": config_hash(std::env::vars()),
+        })).await;
+    }
+
+    pub async fn subscribed(&self, topic: &str, group: &str) {
+        let all = {
+            let mut subs = self.subscriptions.lock().unwrap_or_else(PoisonError::into_inner);
+            subs.push(json!({ "topic": topic, "group": group }));
+            subs.clone()
+        };
+        self.emit("subscribed", json!({ "topic": topic, "group": group, "subscriptions": all })).await;
+    }
+
+    pub async fn shutdown(&self, reason: &str) {
+        self.emit("shutdown", json!({ "reason": reason })).await;
+    }
+}
+
+static AUDIT: OnceLock<Arc<Audit>> = OnceLock::new();
+
+/// Publish this process's `start` event, and its `subscribed` and
+/// `shutdown` events from then on, and start its SLO [`alerts`](crate::alerts).
+/// Only the first call counts.
+pub async fn start(bus: &BusConfig) {
+    crate::alerts::start(bus).await;
+    let topic = env("OPS_AUDIT_TOPIC", "ops.audit");
+    if topic.is_empty() || AUDIT.get().is_some() {
+        return;
+    }
+    // Kept until deleted by hand unless OPS_AUDIT_RETENTION_MS says otherwise.
+    let retention_ms = env("OPS_AUDIT_RETENTION_MS", "-1");
+    if let Err(e) = bus.ensure_topic(&topic, &[("retention.ms", &retention_ms), ("cleanup.policy", "delete")]).await {
+        tracing::warn!(target: "bus", topic = %topic, error = %format!("{:#}", e), "couldn't ensure the audit topic");
+    }
+    let publisher = match bus.publisher().await {
+        Ok(p) => p,
+        Err(e) => {
+            counter!("audit_failures_total", "event" => "start").increment(1);
+            tracing::warn!(target: "bus", error = %format!("{:#}", e), "no audit publisher; lifecycle events are off");
+            return;
+        }
+    };
+    let audit = AUDIT.get_or_init(|| Arc::new(Audit::new(publisher, &topic))).clone();
+    audit.started(bus).await;
+    obsv::runtime::on_shutdown(move |reason| async move { audit.shutdown(&reason).await });
+}
+
+/// For [`BusConfig::subscriber`]: a no-op until [`start`].
+pub(crate) async fn subscribed(topic: &str, group: &str) {
+    if let Some(audit) = AUDIT.get() {
+        audit.subscribed(topic, group).await;
+    }
+}
@@ -0,0 +1,111 @@
+//! When a stage commits the messages it has finished with.
+//!
+//! Committing every message is the safest choice and the slowest one: each
+//! commit is a broker round trip. Stages hand finished deliveries to a
+//! [`Committer`] instead, which holds them until its [`CommitStrategy`] says
+//! to commit. Anything held when a stage dies is redelivered, so a looser
+//! strategy only costs duplicates, never lost messages.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::{env, Delivery, Subscriber};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitStrategy {
+    /// Commit each message as soon as it's done.
+    Message,
+    /// Commit once this many messages are done.
+    EveryN(usize),
+    /// Commit at most this often (checked as messages finish).
+    Interval(Duration),
+    /// Commit whenever the stage flushes its output.
+    Flush,
+}
+
+impl CommitStrategy {
+    /// `message|every_n|interval|flush`; `n` and `interval` only apply to their own strategy.
+    pub fn parse(name: &str, n: usize, interval: Duration) -> Result<Self> {
+        match name {
+            "message" => Ok(Self::Message),
+            "every_n" => Ok(Self::EveryN(n.max(1))),
+            "interval" => Ok(Self::Interval(interval)),
+            "flush" => Ok(Self::Flush),
+            other => anyhow::bail!("unknown COMMIT_STRATEGY {:?} (expected message|every_n|interval|flush)", other),
+        }
+    }
+
+    /// Read `COMMIT_STRATEGY`, `COMMIT_EVERY_N` (default 100) and `COMMIT_INTERVAL_MS` (default 1000).
+    pub fn from_env(default: &str) -> Result<Self> {
+        Self::parse(
+            &env("COMMIT_STRATEGY", default),
+            env("COMMIT_EVERY_N", "100").parse().unwrap_or(100),
+            Duration::from_millis(env("COMMIT_INTERVAL_MS", "1000").parse().unwrap_or(1000)),
+        )
+    }
+}
+
+/// Finished deliveries waiting to be committed.
+pub struct Committer {
+    strategy: CommitStrategy,
+    pending: Vec<Delivery>,
+    last: Instant,
+}
+
+impl Committer {
+    pub fn new(strategy: CommitStrategy) -> Self {
+        Self { strategy, pending: Vec::new(), last: Instant::now() }
+    }
+
+    pub fn strategy(&self) -> CommitStrategy {
+        self.strategy
+    }
+
+    /// Deliveries done but not yet committed.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Hand over a finished delivery. Returns true if this committed.
+    pub async fn done(&mut self, sub: &dyn Subscriber, d: Delivery) -> Result<bool> {
+        self.pending.push(d);
+        let due = match self.strategy {
+            CommitStrategy::Message => true,
+            CommitStrategy::EveryN(n) => self.pending.len() >= n,
+            CommitStrategy::Interval(every) => self.last.elapsed() >= every,
+            CommitStrategy::Flush => false,
+        };
+        if !due {
+            return Ok(false);
+        }
+        self.commit(sub).await
+    }
+
+    /// The stage has flushed its output. Returns true if this committed.
+    pub async fn flushed(&mut self, sub: &dyn Subscriber) -> Result<bool> {
+        let due = match self.strategy {
+            CommitStrategy::Flush => true,
+            CommitStrategy::Interval(every) => self.last.elapsed() >= every,
+            CommitStrategy::Message | CommitStrategy::EveryN(_) => false,
+        };
+        if !due {
+            return Ok(false);
+        }
+        self.commit(sub).await
+    }
+
+    /// Commit everything pending, whatever the strategy (e.g. on shutdown).
+    ///
+    /// Pending deliveries are dropped even if the commit fails: the next
+    /// commit covers them on Kafka, and elsewhere they're redelivered.
+    pub async fn commit(&mut self, sub: &dyn Subscriber) -> Result<bool> {
+        self.last = Instant::now();
+        if self.pending.is_empty() {
+            return Ok(false);
+        }
+        let res = sub.commit_all(&self.pending).await;
+        self.pending.clear();
+        res.map(|_| true)
+    }
+}
@@ -0,0 +1,118 @@
+//! Producer-side batch compression (Kafka only).
+//!
+//! librdkafka compresses whole batches, so it pays most on the JSON stages:
+//! raw exchange payloads repeat the same keys in every message. Consumers
+//! decompress transparently; the broker stores batches as produced.
+
+use std::fmt;
+
+use anyhow::{Context, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    None,
+    Gzip,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+impl Codec {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "none" => Ok(Self::None),
+            "gzip" => Ok(Self::Gzip),
+            "snappy" => Ok(Self::Snappy),
+            "lz4" => Ok(Self::Lz4),
+            "zstd" => Ok(Self::Zstd),
+            other => anyhow::bail!("unknown KAFKA_COMPRESSION {:?} (expected none|gzip|snappy|lz4|zstd)", other),
+        }
+    }
+
+    /// Levels librdkafka accepts for this codec; `None` if it has no levels.
+    fn levels(self) -> Option<(i32, i32)> {
+        match self {
+            Self::Gzip => Some((0, 9)),
+            Self::Lz4 => Some((0, 12)),
+            // zstd itself goes to 22, but librdkafka caps `compression.level` at 12.
+            Self::Zstd => Some((1, 12)),
+            Self::None | Self::Snappy => None,
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Gzip => "gzip",
+            Self::Snappy => "snappy",
+            Self::Lz4 => "lz4",
+            Self::Zstd => "zstd",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    pub codec: Codec,
+    /// Codec default when `None`.
+    pub level: Option<i32>,
+}
+
+impl Compression {
+    pub const NONE: Self = Self { codec: Codec::None, level: None };
+    pub const ZSTD: Self = Self { codec: Codec::Zstd, level: None };
+
+    /// A codec name and an optional level (empty for the codec's default).
+    pub fn parse(codec: &str, level: &str) -> Result<Self> {
+        let codec = Codec::parse(codec)?;
+        let level = match level.trim() {
+            "" => None,
+            l => Some(l.parse::<i32>().with_context(|| format!("bad KAFKA_COMPRESSION_LEVEL {:?}", l))?),
+        };
+        if let Some(l) = level {
+            let Some((lo, hi)) = codec.levels() else {
+                anyhow::bail!("{} has no compression levels", codec);
+            };
+            anyhow::ensure!((lo..=hi).contains(&l), "{} level {} out of range {}..={}", codec, l, lo, hi);
+        }
+        Ok(Self { codec, level })
+    }
+
+    /// `KAFKA_COMPRESSION` and `KAFKA_COMPRESSION_LEVEL`, falling back to `default`.
+    pub fn from_env(default: Self) -> Result<Self> {
+        let codec = std::env::var("KAFKA_COMPRESSION").unwrap_or_else(|_| default.codec.to_string());
+        Self::parse(&codec, &std::env::var("KAFKA_COMPRESSION_LEVEL").unwrap_or_default())
+    }
+
+    /// What the broker can take: zstd needs Kafka 2.1 or later, so older
+    /// brokers get lz4 at its default level. An unknown version is trusted.
+    pub fn negotiate(self, broker_version: Option<(u32, u32)>) -> Self {
+        match (self.codec, broker_version) {
+            (Codec::Zstd, Some(v)) if v < (2, 1) => {
+                tracing::warn!(target: "bus", version = ?v, "broker predates zstd; compressing with lz4 instead");
+                Self { codec: Codec::Lz4, level: None }
+            }
+            _ => self,
+        }
+    }
+
+    /// librdkafka producer settings.
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        let mut out = vec![("compression.type", self.codec.to_string())];
+        if let Some(l) = self.level {
+            out.push(("compression.level", l.to_string()));
+        }
+        out
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.level {
+            Some(l) => write!(f, "{}:{}", self.codec, l),
+            None => write!(f, "{}", self.codec),
+        }
+    }
+}
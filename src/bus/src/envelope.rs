@@ -0,0 +1,117 @@
+//! Versioned message envelope, carried in headers next to `msg_id`/`ts_produce_ns`.
+//!
+//! Every stage stamps what it publishes with the payload's schema version,
+//! its own identity (`<service>/<version>`) and the trade's exchange/market.
+//! Readers accept any minor version of a major they know and reject other
+//! majors, so a minor bump (additive change) never needs a lock-step deploy.
+
+use std::fmt;
+
+use crate::Headers;
+
+pub const SCHEMA_VERSION: &str = "schema_version";
+pub const PRODUCER: &str = "producer";
+pub const EXCHANGE: &str = "exchange";
+pub const MARKET: &str = "market";
+/// Instrument metadata on raw futures trades: quote currency per contract,
+/// for contracts (COIN-M) whose `qty` counts contracts rather than coins.
+pub const CONTRACT_SIZE: &str = "contract_size";
+/// On a raw message that isn't a trade but the fetcher's notice that the
+/// symbol was listed (`listed`) or delisted (`delisted`).
+pub const LISTING: &str = "listing";
+/// On a raw message: the fetcher's estimate of the exchange's clock minus its
+/// own, in milliseconds, for consumers that correct the venue's timestamps.
+pub const CLOCK_OFFSET_MS: &str = "clock_offset_ms";
+/// How a `ticks.norm` payload is encoded: `json`, `msgpack` or `proto`.
+/// Absent means JSON, which is what every stage writes.
+pub const CODEC: &str = "codec";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SchemaVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl SchemaVersion {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+
+    /// `"2"` or `"2.1"`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (major, minor) = s.trim().split_once('.').unwrap_or((s.trim(), "0"));
+        Some(Self { major: major.parse().ok()?, minor: minor.parse().ok()? })
+    }
+}
+
+impl fmt::Display for SchemaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// `ticks.raw`: the venue's payload, untouched. 1.1 added listing markers (`listing`).
+pub const RAW_SCHEMA: SchemaVersion = SchemaVersion::new(1, 1);
+/// `ticks.norm` / `ticks.latest`: `NormTrade` JSON. 1.1 added `session`, 1.2 `notional_usd`,
+/// 1.3 `first_trade_id` (aggregated trades), 1.4 `contract_size` (COIN-M futures),
+/// 1.5 `exchange`, `seq` and `ingest_ts_ns`, 1.6 `listing`, 1.7 `ts_ns`, and 1.8 renamed
+/// `notional_usd` to `notional`, as it's in `NOTIONAL_CURRENCY`.
+pub const NORM_SCHEMA: SchemaVersion = SchemaVersion::new(1, 8);
+
+/// What a reader should do with a message, judged by its `schema_version`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Compat {
+    Supported(SchemaVersion),
+    /// No header: written before envelopes existed; treated as major 1.
+    Unversioned,
+    /// A major this reader doesn't know (or a header it can't parse).
+    Unsupported(String),
+}
+
+/// Check `headers` against the major version `reader` was built for.
+pub fn compat(headers: &Headers, reader: SchemaVersion) -> Compat {
+    match headers.get(SCHEMA_VERSION) {
+        None => Compat::Unversioned,
+        Some(raw) => match SchemaVersion::parse(raw) {
+            Some(v) if v.major == reader.major => Compat::Supported(v),
+            _ => Compat::Unsupported(raw.to_string()),
+        },
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    pub schema: SchemaVersion,
+    /// `<service>/<version>` of the stage that published the message.
+    pub producer: String,
+    pub exchange: String,
+    pub market: String,
+}
+
+impl Envelope {
+    pub fn new(schema: SchemaVersion, service: &str, version: &str, exchange: &str, market: &str) -> Self {
+        Self {
+            schema,
+            producer: format!("{}/{}", service, version),
+            exchange: exchange.to_string(),
+            market: market.to_string(),
+        }
+    }
+
+    /// Keep `upstream`'s exchange/market when it has them (we're forwarding its trade).
+    pub fn inherit(&self, upstream: &Headers) -> Self {
+        Self {
+            exchange: upstream.get(EXCHANGE).unwrap_or(&self.exchange).to_string(),
+            market: upstream.get(MARKET).unwrap_or(&self.market).to_string(),
+            ..self.clone()
+        }
+    }
+
+    pub fn apply(&self, headers: Headers) -> Headers {
+        headers
+            .with(SCHEMA_VERSION, &self.schema.to_string())
+            .with(PRODUCER, &self.producer)
+            .with(EXCHANGE, &self.exchange)
+            .with(MARKET, &self.market)
+    }
+}
@@ -0,0 +1,50 @@
+//! Which [`ErrorKind`] a failed publish, poll or commit is.
+//!
+//! Publishers and subscribers return `anyhow` errors wrapping the transport's
+//! own; [`kind`] looks through the chain for one it knows. Anything it
+//! doesn't know is taken as transient: the broker side of a bus fails far
+//! more often than it refuses outright.
+
+use obsv::errors::ErrorKind;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+
+/// The kind of a bus error.
+pub fn kind(e: &anyhow::Error) -> ErrorKind {
+    e.chain()
+        .find_map(|c| c.downcast_ref::<KafkaError>())
+        .map_or(ErrorKind::Transient, kafka_kind)
+}
+
+/// Credentials, authorization and configuration are fatal; a message the
+/// broker won't take is data; the rest (timeouts, leaders moving, queues
+/// full) is transient.
+pub fn kafka_kind(e: &KafkaError) -> ErrorKind {
+    match e {
+        KafkaError::ClientConfig(..) | KafkaError::ClientCreation(_) | KafkaError::MessageConsumptionFatal(_) => return ErrorKind::Fatal,
+        KafkaError::Nul(_) => return ErrorKind::Data,
+        _ => {}
+    }
+    match e.rdkafka_error_code() {
+        Some(
+            RDKafkaErrorCode::Fatal
+            | RDKafkaErrorCode::Authentication
+            | RDKafkaErrorCode::SaslAuthenticationFailed
+            | RDKafkaErrorCode::TopicAuthorizationFailed
+            | RDKafkaErrorCode::GroupAuthorizationFailed
+            | RDKafkaErrorCode::ClusterAuthorizationFailed
+            | RDKafkaErrorCode::InvalidConfig
+            | RDKafkaErrorCode::InvalidRequiredAcks
+            | RDKafkaErrorCode::UnsupportedVersion
+            | RDKafkaErrorCode::ProducerFenced
+            | RDKafkaErrorCode::InvalidTopic,
+        ) => ErrorKind::Fatal,
+        Some(
+            RDKafkaErrorCode::BadMessage
+            | RDKafkaErrorCode::InvalidMessage
+            | RDKafkaErrorCode::InvalidMessageSize
+            | RDKafkaErrorCode::MessageSizeTooLarge
+            | RDKafkaErrorCode::InvalidRecord,
+        ) => ErrorKind::Data,
+        _ => ErrorKind::Transient,
+    }
+}
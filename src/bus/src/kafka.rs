@@ -0,0 +1,336 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, ResourceSpecifier, TopicReplication};
+use metrics::{counter, gauge};
+use rdkafka::client::{ClientContext, DefaultClientContext};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{CommitMode, Consumer, ConsumerContext, StreamConsumer};
+use rdkafka::error::RDKafkaErrorCode;
+use rdkafka::message::{Header, Headers as _, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use rdkafka::{Message, Offset, Statistics, TopicPartitionList};
+use tokio::sync::OnceCell;
+
+use crate::partition::{Layout, PartitionMap};
+use crate::probe::{self, Profile};
+use crate::{env, Ack, BusConfig, Delivery, Headers, Publisher, Subscriber};
+
+/// Probed once per process, shared by every publisher/subscriber it creates.
+static PROFILE: OnceCell<Profile> = OnceCell::const_new();
+
+#[derive(Clone, Copy)]
+enum Role {
+    Producer,
+    Consumer,
+}
+
+impl Role {
+    fn label(self) -> &'static str {
+        match self {
+            Role::Producer => "producer",
+            Role::Consumer => "consumer",
+        }
+    }
+}
+
+/// Turns librdkafka's periodic statistics into byte counters: what went over
+/// the wire (compressed batches plus request overhead) against the message
+/// bytes inside them. Their ratio is how well compression is doing.
+pub(crate) struct StatsContext {
+    role: Role,
+}
+
+impl ClientContext for StatsContext {
+    fn stats(&self, stats: Statistics) {
+        let (wire, payload): (u64, u64) = match self.role {
+            Role::Producer => (
+                stats.brokers.values().map(|b| b.txbytes).sum(),
+                stats.topics.values().flat_map(|t| t.partitions.values()).map(|p| p.txbytes).sum(),
+            ),
+            Role::Consumer => (
+                stats.brokers.values().map(|b| b.rxbytes).sum(),
+                stats.topics.values().flat_map(|t| t.partitions.values()).map(|p| p.rxbytes).sum(),
+            ),
+        };
+        let labels = [("role", self.role.label().to_string()), ("client", stats.name)];
+        counter!("kafka_wire_bytes_total", &labels).absolute(wire);
+        counter!("kafka_payload_bytes_total", &labels).absolute(payload);
+        if wire > 0 {
+            gauge!("kafka_compression_ratio", &labels).set(payload as f64 / wire as f64);
+        }
+    }
+}
+
+impl ConsumerContext for StatsContext {}
+
+impl BusConfig {
+    /// Shared librdkafka defaults plus any `kafka_set` overrides.
+    pub fn kafka_config(&self) -> ClientConfig {
+        let mut cfg = ClientConfig::new();
+        cfg.set("bootstrap.servers", &self.brokers)
+            // soften control-plane timeouts & keepalive to cut req timeouts:
+            .set("socket.keepalive.enable", "true")
+            .set("request.timeout.ms", "20000");
+        for (k, v) in &self.kafka_overrides {
+            cfg.set(k, v);
+        }
+        cfg
+    }
+
+    /// Defaults, then the broker profile for `role`, then `kafka_set` overrides (which win).
+    async fn tuned_config(&self, role: Role) -> ClientConfig {
+        let profile = PROFILE.get_or_init(|| probe::resolve(self)).await;
+        let mut cfg = self.kafka_config();
+        if let Role::Consumer = role {
+            // Stages commit explicitly once work is done; auto-commit would
+            // commit whatever was polled, done or not. `kafka_set` can turn it back on.
+            cfg.set("enable.auto.commit", "false");
+        }
+        let tuned = match role {
+            Role::Producer => &profile.producer,
+            Role::Consumer => &profile.consumer,
+        };
+        for (k, v) in tuned {
+            cfg.set(*k, v);
+        }
+        if let Role::Producer = role {
+            for (k, v) in self.compression.negotiate(profile.broker_version).settings() {
+                cfg.set(k, v);
+            }
+            if self.idempotence == probe::Idempotence::Off {
+                cfg.set("enable.idempotence", "false").set("max.in.flight.requests.per.connection", "1");
+            }
+        }
+        // 0 turns the byte counters off.
+        cfg.set("statistics.interval.ms", env("KAFKA_STATS_INTERVAL_MS", "15000"));
+        for (k, v) in &self.kafka_overrides {
+            cfg.set(k, v);
+        }
+        cfg
+    }
+}
+
+const ADMIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(crate) async fn ensure_topic(cfg: &BusConfig, topic: &str, configs: &[(&str, &str)]) -> Result<()> {
+    let admin: AdminClient<DefaultClientContext> = cfg.kafka_config().create()?;
+    let opts = AdminOptions::new().request_timeout(Some(ADMIN_TIMEOUT));
+    // -1: broker default partition count and replication factor.
+    let new = configs.iter().fold(NewTopic::new(topic, -1, TopicReplication::Fixed(-1)), |t, (k, v)| t.set(k, v));
+    for res in admin.create_topics([&new], &opts).await? {
+        match res {
+            Ok(_) => tracing::info!(target: "bus", topic, ?configs, "created topic"),
+            Err((_, RDKafkaErrorCode::TopicAlreadyExists)) => {
+                let entries: Vec<_> = admin.describe_configs([&ResourceSpecifier::Topic(topic)], &opts).await?
+                    .into_iter()
+                    .filter_map(|r| r.ok())
+                    .flat_map(|r| r.entries)
+                    .collect();
+                for (k, want) in configs {
+                    let have = entries.iter().find(|e| e.name == *k).and_then(|e| e.value.clone());
+                    // List-valued settings (`compact,delete`) match if they include ours.
+                    if !have.as_deref().is_some_and(|h| h.split(',').any(|v| v == *want)) {
+                        tracing::warn!(target: "bus", topic, key = k, want, ?have, "existing topic has a different setting");
+                    }
+                }
+            }
+            Err((_, code)) => anyhow::bail!("creating {}: {}", topic, code),
+        }
+    }
+    Ok(())
+}
+
+/// The broker answers metadata requests and none of `topics` reports an
+/// error. A topic that doesn't exist yet only warns: producing to it
+/// auto-creates it, and a subscriber waits for it.
+///
+/// Topics are looked up in one request for the whole cluster, never asked
+/// for by name: a request naming a missing topic has a broker with
+/// `auto.create.topics.enable` create it on the spot, with the default
+/// cleanup policy, before `ensure_compacted` can create it compacted.
+pub(crate) async fn check_topics(cfg: &BusConfig, topics: &[&str]) -> Result<()> {
+    let config = cfg.kafka_config();
+    let topics: Vec<String> = topics.iter().map(|t| t.to_string()).collect();
+    tokio::task::spawn_blocking(move || {
+        let admin: AdminClient<DefaultClientContext> = config.create()?;
+        let md = admin.inner().fetch_metadata(None, ADMIN_TIMEOUT)?;
+        anyhow::ensure!(!md.brokers().is_empty(), "metadata returned no brokers");
+        for topic in &topics {
+            match md.topics().iter().find(|t| t.name() == topic) {
+                None => tracing::warn!(target: "bus", topic, "topic does not exist yet"),
+                Some(t) => match t.error().map(RDKafkaErrorCode::from) {
+                    None => tracing::info!(target: "bus", topic, partitions = t.partitions().len(), "topic ok"),
+                    Some(RDKafkaErrorCode::UnknownTopicOrPartition) => tracing::warn!(target: "bus", topic, "topic does not exist yet"),
+                    Some(code) => anyhow::bail!("topic {}: {}", topic, code),
+                },
+            }
+        }
+        Ok(())
+    }).await?
+}
+
+pub(crate) struct KafkaPublisher {
+    producer: FutureProducer<StatsContext>,
+    pins: PartitionMap,
+    /// The pin map resolved per topic, on first publish to it.
+    layouts: Mutex<HashMap<String, Arc<Layout>>>,
+}
+
+impl KafkaPublisher {
+    pub(crate) async fn new(cfg: &BusConfig) -> Result<Self> {
+        let settings = cfg.tuned_config(Role::Producer).await;
+        probe::check_producer(cfg.idempotence, &settings)?;
+        Ok(Self {
+            producer: settings.create_with_context(StatsContext { role: Role::Producer })?,
+            pins: cfg.partition_map.clone(),
+            layouts: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn layout(&self, topic: &str) -> Result<Arc<Layout>> {
+        if let Some(l) = self.layouts.lock().unwrap_or_else(PoisonError::into_inner).get(topic) {
+            return Ok(l.clone());
+        }
+        let producer = self.producer.clone();
+        let name = topic.to_string();
+        let count = tokio::task::spawn_blocking(move || {
+            let md = producer.client().fetch_metadata(Some(&name), ADMIN_TIMEOUT)?;
+            let count = md.topics().first().map_or(0, |t| t.partitions().len());
+            anyhow::ensure!(count > 0, "topic {} has no partitions (does it exist?)", name);
+            Ok(count as i32)
+        }).await??;
+        let layout = Arc::new(self.pins.layout(topic, count));
+        tracing::info!(target: "bus", topic, count, ?layout, "partition map resolved");
+        self.layouts.lock().unwrap_or_else(PoisonError::into_inner).insert(topic.to_string(), layout.clone());
+        Ok(layout)
+    }
+}
+
+#[async_trait]
+impl Publisher for KafkaPublisher {
+    async fn publish(&self, topic: &str, key: &str, payload: &[u8], headers: &Headers) -> Result<()> {
+        let hdrs = headers.iter().fold(OwnedHeaders::new(), |h, (k, v)| {
+            h.insert(Header { key: k, value: Some(v.as_bytes()) })
+        });
+        let mut record = FutureRecord::to(topic).payload(payload).key(key).headers(hdrs);
+        if !self.pins.is_empty() {
+            record = record.partition(self.layout(topic).await?.partition(key));
+        }
+        self.producer
+            .send(record, Duration::from_secs(5))
+            .await
+            .map(|_| ())
+            .map_err(|(e, _)| e.into())
+    }
+}
+
+fn position(d: &Delivery) -> Option<(i32, i64)> {
+    match d.ack {
+        Ack::Kafka { partition, offset } => Some((partition, offset)),
+        _ => None,
+    }
+}
+
+pub(crate) struct KafkaSubscriber {
+    consumer: Arc<StreamConsumer<StatsContext>>,
+}
+
+impl KafkaSubscriber {
+    pub(crate) async fn new(cfg: &BusConfig, topic: &str, group: &str) -> Result<Self> {
+        let consumer: StreamConsumer<StatsContext> = cfg.tuned_config(Role::Consumer).await
+            .set("group.id", group)
+            .set("enable.partition.eof", "false")
+            .set("auto.offset.reset", "latest")
+            .create_with_context(StatsContext { role: Role::Consumer })?;
+        consumer.subscribe(&[topic])?;
+        let consumer = Arc::new(consumer);
+        // The admin API's `assignments`, read from librdkafka when asked for.
+        let weak = Arc::downgrade(&consumer);
+        obsv::admin::view("assignments", &format!("{}@{}", topic, group), move || {
+            let Some(tpl) = weak.upgrade().and_then(|c| c.assignment().ok()) else { return serde_json::Value::Null };
+            let mut partitions: Vec<i32> = tpl.elements().iter().map(|e| e.partition()).collect();
+            partitions.sort_unstable();
+            serde_json::json!(partitions)
+        });
+        Ok(Self { consumer })
+    }
+}
+
+#[async_trait]
+impl Subscriber for KafkaSubscriber {
+    async fn next(&mut self) -> Option<Result<Delivery>> {
+        let msg = match self.consumer.recv().await {
+            Ok(m) => m,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let mut headers = Headers::new();
+        if let Some(h) = msg.headers() {
+            for hdr in h.iter() {
+                if let Some(v) = hdr.value.and_then(|v| std::str::from_utf8(v).ok()) {
+                    headers.insert(hdr.key, v);
+                }
+            }
+        }
+        Some(Ok(Delivery {
+            topic: msg.topic().to_string(),
+            key: msg.key().and_then(|k| std::str::from_utf8(k).ok()).map(str::to_string),
+            payload: msg.payload().unwrap_or_default().to_vec(),
+            headers,
+            ack: Ack::Kafka { partition: msg.partition(), offset: msg.offset() },
+        }))
+    }
+
+    async fn commit(&self, d: &Delivery) -> Result<()> {
+        let Some((partition, offset)) = position(d) else {
+            anyhow::bail!("delivery did not come from Kafka");
+        };
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition_offset(&d.topic, partition, Offset::Offset(offset + 1))?;
+        self.consumer.commit(&tpl, CommitMode::Async)?;
+        Ok(())
+    }
+
+    async fn commit_all(&self, ds: &[Delivery]) -> Result<()> {
+        // Offsets are cumulative: one entry per partition, at the highest offset seen.
+        let mut next: HashMap<(&str, i32), i64> = HashMap::new();
+        for d in ds {
+            let Some((partition, offset)) = position(d) else {
+                anyhow::bail!("delivery did not come from Kafka");
+            };
+            let e = next.entry((d.topic.as_str(), partition)).or_insert(offset + 1);
+            *e = (*e).max(offset + 1);
+        }
+        if next.is_empty() {
+            return Ok(());
+        }
+        let mut tpl = TopicPartitionList::new();
+        for ((topic, partition), offset) in next {
+            tpl.add_partition_offset(topic, partition, Offset::Offset(offset))?;
+        }
+        self.consumer.commit(&tpl, CommitMode::Async)?;
+        Ok(())
+    }
+
+    async fn lag(&self, d: &Delivery) -> Option<i64> {
+        let (partition, offset) = position(d)?;
+        let (_, high) = self.consumer
+            .fetch_watermarks(&d.topic, partition, Duration::from_secs(2))
+            .ok()?;
+        Some((high - (offset + 1)).max(0))
+    }
+
+    /// Pauses the current assignment; partitions assigned by a later rebalance start unpaused.
+    fn pause(&self) -> Result<()> {
+        self.consumer.pause(&self.consumer.assignment()?)?;
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<()> {
+        self.consumer.resume(&self.consumer.assignment()?)?;
+        Ok(())
+    }
+}
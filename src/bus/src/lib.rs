@@ -0,0 +1,436 @@
+//! Message-bus abstraction shared by fetcher, producer and consumer.
+//!
+//! Stages talk to a [`Publisher`] / [`Subscriber`] pair instead of rdkafka
+//! directly; `TRANSPORT` picks the backend at startup (Kafka by default,
+//! NATS JetStream with the `nats` feature). Header semantics (`msg_id`,
+//! `ts_produce_ns`) are identical across backends. Apache Pulsar is
+//! available too, with the `pulsar` feature.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+pub mod alerts;
+pub mod audit;
+pub mod commit;
+pub mod compress;
+mod kafka;
+#[cfg(feature = "nats")]
+mod nats;
+#[cfg(feature = "pulsar")]
+mod pulsar;
+pub mod envelope;
+pub mod error;
+pub mod oversize;
+pub mod partition;
+pub mod probe;
+pub mod route;
+pub mod seal;
+pub mod sign;
+
+/// Header carrying the pipeline-wide message id (set once by the fetcher).
+pub const MSG_ID: &str = "msg_id";
+/// Header carrying the fetcher's produce timestamp, used for E2E latency.
+pub const TS_PRODUCE_NS: &str = "ts_produce_ns";
+/// Header set to `"true"` on messages re-published from storage rather than the live feed.
+pub const REPLAYED: &str = "replayed";
+
+pub(crate) fn env<T: AsRef<str>>(k: T, default: &str) -> String {
+    std::env::var(k.as_ref()).unwrap_or_else(|_| default.to_string())
+}
+
+pub(crate) fn hex(b: &[u8]) -> String {
+    b.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+/// Ordered string headers attached to a message.
+#[derive(Debug, Clone, Default)]
+pub struct Headers(Vec<(String, String)>);
+
+impl Headers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, key: &str, value: &str) -> Self {
+        self.insert(key, value);
+        self
+    }
+
+    /// Set `key`, replacing an existing value.
+    pub fn insert(&mut self, key: &str, value: &str) {
+        match self.0.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.0.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let i = self.0.iter().position(|(k, _)| k == key)?;
+        Some(self.0.remove(i).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+/// Backend-specific handle needed to acknowledge a delivery.
+pub(crate) enum Ack {
+    Kafka { partition: i32, offset: i64 },
+    #[cfg(feature = "nats")]
+    Nats(Box<async_nats::jetstream::Message>),
+    #[cfg(feature = "pulsar")]
+    Pulsar(Box<::pulsar::proto::MessageIdData>),
+    /// Not tied to a broker; see [`Delivery::detached`].
+    Detached,
+}
+
+/// A received message, owned so it can outlive the poll call.
+pub struct Delivery {
+    pub topic: String,
+    pub key: Option<String>,
+    pub payload: Vec<u8>,
+    pub headers: Headers,
+    pub(crate) ack: Ack,
+}
+
+impl Delivery {
+    /// A delivery built in-process (tests, benches, file replays); it can't be committed.
+    pub fn detached(topic: &str, key: Option<&str>, payload: Vec<u8>, headers: Headers) -> Self {
+        Self {
+            topic: topic.to_string(),
+            key: key.map(str::to_string),
+            payload,
+            headers,
+            ack: Ack::Detached,
+        }
+    }
+
+    pub fn payload_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.payload).ok()
+    }
+
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.get(key)
+    }
+
+    /// Source partition, on backends that have them (Kafka).
+    pub fn partition(&self) -> Option<i32> {
+        match self.ack {
+            Ack::Kafka { partition, .. } => Some(partition),
+            _ => None,
+        }
+    }
+
+    /// Offset within the partition, on backends that have them (Kafka).
+    pub fn offset(&self) -> Option<i64> {
+        match self.ack {
+            Ack::Kafka { offset, .. } => Some(offset),
+            _ => None,
+        }
+    }
+
+    /// This delivery without its payload and headers, for holding one that
+    /// won't be read until it's committed; it commits as the original would.
+    /// On NATS the ack handle keeps its own copy of the message.
+    pub fn stripped(self) -> Self {
+        Self { payload: Vec::new(), headers: Headers::new(), ..self }
+    }
+}
+
+#[async_trait]
+pub trait Publisher: Send + Sync {
+    /// Publish one message and wait for the broker's acknowledgement.
+    async fn publish(&self, topic: &str, key: &str, payload: &[u8], headers: &Headers) -> Result<()>;
+}
+
+#[async_trait]
+pub trait Subscriber: Send {
+    /// Next message, or `None` once the subscription is closed.
+    /// Cancellation safe: dropping the future (e.g. on a timeout) loses nothing.
+    async fn next(&mut self) -> Option<Result<Delivery>>;
+
+    /// Mark `d` (and everything before it on its partition) as processed.
+    async fn commit(&self, d: &Delivery) -> Result<()>;
+
+    /// Commit a batch in as few broker calls as the backend allows.
+    async fn commit_all(&self, ds: &[Delivery]) -> Result<()>;
+
+    /// Messages still queued behind `d`, if the backend can tell.
+    async fn lag(&self, d: &Delivery) -> Option<i64>;
+
+    /// Stop fetching until [`resume`](Subscriber::resume), so nothing piles up
+    /// locally. A Kafka caller should keep calling `next` meanwhile, or the
+    /// group drops it; little more than what was already fetched comes out.
+    /// Backends that only fetch when `next` is called (NATS, Pulsar) have nothing to do.
+    fn pause(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Kafka,
+    Nats,
+    Pulsar,
+}
+
+/// Connection settings for whichever transport is selected.
+#[derive(Debug, Clone)]
+pub struct BusConfig {
+    pub transport: Transport,
+    pub brokers: String,
+    pub nats_url: String,
+    pub pulsar_url: String,
+    /// Symbols pinned to their own partitions (Kafka only).
+    pub partition_map: partition::PartitionMap,
+    /// Batch compression for publishers (Kafka only).
+    pub compression: compress::Compression,
+    /// Envelope encryption; `None` when `ENCRYPTION_KEYS` is unset.
+    pub sealer: Option<Arc<seal::Sealer>>,
+    /// Whether publishers must be idempotent (Kafka only).
+    pub idempotence: probe::Idempotence,
+    /// Which librdkafka profile to layer in, or whether to probe for one (Kafka only).
+    pub broker_profile: probe::BrokerProfile,
+    /// Prefixed onto every topic and group passed in (see `obsv::namespace`).
+    pub namespace: obsv::namespace::Namespace,
+    kafka_overrides: Vec<(String, String)>,
+}
+
+impl BusConfig {
+    /// Read `TRANSPORT` (kafka|nats|pulsar), `KAFKA_BROKERS`, `NATS_URL`, `PULSAR_URL`,
+    /// `PARTITION_MAP`, `KAFKA_COMPRESSION` (default `none`), `ENCRYPTION_KEYS`,
+    /// `PRODUCER_IDEMPOTENCE` (default `auto`), `BROKER_PROFILE` (default `auto`)
+    /// and `NAMESPACE`.
+    pub fn from_env() -> Result<Self> {
+        let transport = match env("TRANSPORT", "kafka").to_lowercase().as_str() {
+            "kafka" => Transport::Kafka,
+            "nats" => Transport::Nats,
+            "pulsar" => Transport::Pulsar,
+            other => anyhow::bail!("unknown TRANSPORT {:?} (expected kafka|nats|pulsar)", other),
+        };
+        Ok(Self {
+            transport,
+            brokers: env("KAFKA_BROKERS", "localhost:29092"),
+            nats_url: env("NATS_URL", "nats://localhost:4222"),
+            pulsar_url: env("PULSAR_URL", "pulsar://localhost:6650"),
+            partition_map: partition::PartitionMap::parse(&env("PARTITION_MAP", ""))?,
+            compression: compress::Compression::from_env(compress::Compression::NONE)?,
+            sealer: seal::Sealer::from_env()?.map(Arc::new),
+            idempotence: probe::Idempotence::parse(&env("PRODUCER_IDEMPOTENCE", "auto"))?,
+            broker_profile: probe::BrokerProfile::parse(&env("BROKER_PROFILE", "auto"))?,
+            namespace: obsv::namespace::init()?.clone(),
+            kafka_overrides: Vec::new(),
+        })
+    }
+
+    /// Compress with `default` unless `KAFKA_COMPRESSION` says otherwise.
+    pub fn compress_by_default(mut self, default: compress::Compression) -> Result<Self> {
+        self.compression = compress::Compression::from_env(default)?;
+        Ok(self)
+    }
+
+    /// Extra librdkafka setting applied on top of the shared defaults (Kafka only).
+    pub fn kafka_set(mut self, key: &str, value: &str) -> Self {
+        self.kafka_overrides.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// `topic` as it's named on the broker: prefixed with the namespace.
+    /// Everything here does this itself; it's for code that talks to the
+    /// broker directly (`kafka_config`).
+    pub fn topic(&self, topic: &str) -> String {
+        self.namespace.topic(topic)
+    }
+
+    /// `group` as it's named on the broker; see [`topic`](BusConfig::topic).
+    pub fn group(&self, group: &str) -> String {
+        self.namespace.group(group)
+    }
+
+    /// Seals `ENCRYPT_TOPICS` when encryption keys are configured. Topics are
+    /// passed in unprefixed; deliveries from [`subscriber`](BusConfig::subscriber)
+    /// carry the prefixed name.
+    pub async fn publisher(&self) -> Result<Arc<dyn Publisher>> {
+        if !self.partition_map.is_empty() && self.transport != Transport::Kafka {
+            tracing::warn!(target: "bus", transport = ?self.transport, "PARTITION_MAP only applies to Kafka; ignoring it");
+        }
+        let inner: Arc<dyn Publisher> = match self.transport {
+            Transport::Kafka => Arc::new(kafka::KafkaPublisher::new(self).await?),
+            #[cfg(feature = "nats")]
+            Transport::Nats => Arc::new(nats::NatsPublisher::connect(&self.nats_url).await?),
+            #[cfg(not(feature = "nats"))]
+            Transport::Nats => anyhow::bail!("TRANSPORT=nats requires building with the `nats` feature"),
+            #[cfg(feature = "pulsar")]
+            Transport::Pulsar => Arc::new(pulsar::PulsarPublisher::connect(&self.pulsar_url).await?),
+            #[cfg(not(feature = "pulsar"))]
+            Transport::Pulsar => anyhow::bail!("TRANSPORT=pulsar requires building with the `pulsar` feature"),
+        };
+        let inner: Arc<dyn Publisher> = Arc::new(SampledPublisher { inner });
+        let inner: Arc<dyn Publisher> = match self.namespace.name() {
+            Some(_) => Arc::new(NamespacedPublisher { inner, namespace: self.namespace.clone() }),
+            None => inner,
+        };
+        Ok(match &self.sealer {
+            Some(sealer) => Arc::new(seal::SealingPublisher::new(inner, sealer.clone())),
+            None => inner,
+        })
+    }
+
+    /// Make sure `topic` exists and keeps only the latest message per key.
+    ///
+    /// Kafka: created with `cleanup.policy=compact` (an existing topic is left
+    /// as is, with a warning if it isn't compacted). NATS: JetStream can't
+    /// compact on our key header, so the stream is created with plain retention.
+    pub async fn ensure_compacted(&self, topic: &str) -> Result<()> {
+        let topic = &self.topic(topic);
+        match self.transport {
+            Transport::Kafka => kafka::ensure_topic(self, topic, &[("cleanup.policy", "compact")]).await,
+            #[cfg(feature = "nats")]
+            Transport::Nats => nats::ensure_compacted(&self.nats_url, topic).await,
+            #[cfg(not(feature = "nats"))]
+            Transport::Nats => anyhow::bail!("TRANSPORT=nats requires building with the `nats` feature"),
+            #[cfg(feature = "pulsar")]
+            Transport::Pulsar => pulsar::ensure_topic(topic, &[("cleanup.policy", "compact")]).await,
+            #[cfg(not(feature = "pulsar"))]
+            Transport::Pulsar => anyhow::bail!("TRANSPORT=pulsar requires building with the `pulsar` feature"),
+        }
+    }
+
+    /// Make sure `topic` exists, creating it with the given topic-level settings.
+    ///
+    /// Kafka: an existing topic is left alone, with a warning for each setting
+    /// that differs. NATS: the stream is created with defaults; settings are ignored.
+    pub async fn ensure_topic(&self, topic: &str, configs: &[(&str, &str)]) -> Result<()> {
+        let topic = &self.topic(topic);
+        match self.transport {
+            Transport::Kafka => kafka::ensure_topic(self, topic, configs).await,
+            #[cfg(feature = "nats")]
+            Transport::Nats => nats::ensure_topic(&self.nats_url, topic, configs).await,
+            #[cfg(not(feature = "nats"))]
+            Transport::Nats => anyhow::bail!("TRANSPORT=nats requires building with the `nats` feature"),
+            #[cfg(feature = "pulsar")]
+            Transport::Pulsar => pulsar::ensure_topic(topic, configs).await,
+            #[cfg(not(feature = "pulsar"))]
+            Transport::Pulsar => anyhow::bail!("TRANSPORT=pulsar requires building with the `pulsar` feature"),
+        }
+    }
+
+    /// Check, once, that the broker is reachable and `topics` are usable, for a
+    /// stage to retry before its main loop (see `obsv::startup`).
+    ///
+    /// Kafka only: NATS and Pulsar connect, and fail, when the first
+    /// publisher or subscriber is made.
+    pub async fn check_topics(&self, topics: &[&str]) -> Result<()> {
+        match self.transport {
+            Transport::Kafka => {
+                let topics: Vec<String> = topics.iter().map(|t| self.topic(t)).collect();
+                kafka::check_topics(self, &topics.iter().map(String::as_str).collect::<Vec<_>>()).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Opens sealed deliveries when encryption keys are configured.
+    pub async fn subscriber(&self, topic: &str, group: &str) -> Result<Box<dyn Subscriber>> {
+        let (topic, group) = (&self.topic(topic), &self.group(group));
+        let inner: Box<dyn Subscriber + Sync> = match self.transport {
+            Transport::Kafka => Box::new(kafka::KafkaSubscriber::new(self, topic, group).await?),
+            #[cfg(feature = "nats")]
+            Transport::Nats => Box::new(nats::NatsSubscriber::connect(&self.nats_url, topic, group).await?),
+            #[cfg(not(feature = "nats"))]
+            Transport::Nats => anyhow::bail!("TRANSPORT=nats requires building with the `nats` feature"),
+            #[cfg(feature = "pulsar")]
+            Transport::Pulsar => Box::new(pulsar::PulsarSubscriber::connect(&self.pulsar_url, topic, group).await?),
+            #[cfg(not(feature = "pulsar"))]
+            Transport::Pulsar => anyhow::bail!("TRANSPORT=pulsar requires building with the `pulsar` feature"),
+        };
+        let inner: Box<dyn Subscriber + Sync> = Box::new(SampledSubscriber { inner });
+        audit::subscribed(topic, group).await;
+        Ok(match &self.sealer {
+            Some(sealer) => Box::new(seal::UnsealingSubscriber::new(inner, sealer.clone())),
+            None => inner,
+        })
+    }
+}
+
+/// Samples what goes to the broker for the admin API's `samples`, on any
+/// transport. It sits inside the sealing layer, so sealed topics are shown
+/// sealed, as the broker has them.
+struct SampledPublisher {
+    inner: Arc<dyn Publisher>,
+}
+
+#[async_trait]
+impl Publisher for SampledPublisher {
+    async fn publish(&self, topic: &str, key: &str, payload: &[u8], headers: &Headers) -> Result<()> {
+        obsv::admin::sample(topic, Some(key), payload);
+        self.inner.publish(topic, key, payload, headers).await
+    }
+}
+
+/// [`SampledPublisher`]'s counterpart for what comes off the broker.
+struct SampledSubscriber {
+    inner: Box<dyn Subscriber + Sync>,
+}
+
+#[async_trait]
+impl Subscriber for SampledSubscriber {
+    async fn next(&mut self) -> Option<Result<Delivery>> {
+        let next = self.inner.next().await;
+        if let Some(Ok(d)) = &next {
+            obsv::admin::sample(&d.topic, d.key.as_deref(), &d.payload);
+        }
+        next
+    }
+
+    async fn commit(&self, d: &Delivery) -> Result<()> {
+        self.inner.commit(d).await
+    }
+
+    async fn commit_all(&self, ds: &[Delivery]) -> Result<()> {
+        self.inner.commit_all(ds).await
+    }
+
+    async fn lag(&self, d: &Delivery) -> Option<i64> {
+        self.inner.lag(d).await
+    }
+
+    fn pause(&self) -> Result<()> {
+        self.inner.pause()
+    }
+
+    fn resume(&self) -> Result<()> {
+        self.inner.resume()
+    }
+}
+
+/// Publishes `topic` as `{namespace}.{topic}`.
+struct NamespacedPublisher {
+    inner: Arc<dyn Publisher>,
+    namespace: obsv::namespace::Namespace,
+}
+
+#[async_trait]
+impl Publisher for NamespacedPublisher {
+    async fn publish(&self, topic: &str, key: &str, payload: &[u8], headers: &Headers) -> Result<()> {
+        self.inner.publish(&self.namespace.topic(topic), key, payload, headers).await
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+use std::sync::{Mutex, PoisonError};
+
+use anyhow::Result;
+use async_nats::jetstream::{self, consumer::pull, consumer::AckPolicy, consumer::DeliverPolicy, stream};
+use async_nats::HeaderMap;
+use async_trait::async_trait;
+use futures_util::StreamExt;
+
+use crate::{Ack, Delivery, Headers, Publisher, Subscriber};
+
+/// NATS has no message key; carry it as a header so subscribers see the same shape.
+const KEY_HEADER: &str = "key";
+
+/// One JetStream stream per topic; stream names can't contain dots.
+async fn ensure_stream(js: &jetstream::Context, topic: &str) -> Result<stream::Stream> {
+    let name = topic.replace('.', "_").to_uppercase();
+    js.get_or_create_stream(stream::Config {
+        name,
+        subjects: vec![topic.to_string()],
+        ..Default::default()
+    })
+    .await
+    .map_err(Into::into)
+}
+
+pub(crate) async fn ensure_compacted(url: &str, topic: &str) -> Result<()> {
+    let js = jetstream::new(async_nats::connect(url).await?);
+    ensure_stream(&js, topic).await?;
+    tracing::warn!(target: "bus", topic, "JetStream can't compact by key; keeping full history");
+    Ok(())
+}
+
+pub(crate) async fn ensure_topic(url: &str, topic: &str, configs: &[(&str, &str)]) -> Result<()> {
+    let js = jetstream::new(async_nats::connect(url).await?);
+    ensure_stream(&js, topic).await?;
+    if !configs.is_empty() {
+        tracing::warn!(target: "bus", topic, ?configs, "Kafka topic settings don't apply to JetStream; using stream defaults");
+    }
+    Ok(())
+}
+
+pub(crate) struct NatsPublisher {
+    js: jetstream::Context,
+    streams: Mutex<HashSet<String>>,
+}
+
+impl NatsPublisher {
+    pub(crate) async fn connect(url: &str) -> Result<Self> {
+        let client = async_nats::connect(url).await?;
+        Ok(Self { js: jetstream::new(client), streams: Mutex::new(HashSet::new()) })
+    }
+}
+
+#[async_trait]
+impl Publisher for NatsPublisher {
+    async fn publish(&self, topic: &str, key: &str, payload: &[u8], headers: &Headers) -> Result<()> {
+        // Publishing to a subject without a stream fails with "no responders".
+        let known = self.streams.lock().unwrap_or_else(PoisonError::into_inner).contains(topic);
+        if !known {
+            ensure_stream(&self.js, topic).await?;
+            self.streams.lock().unwrap_or_else(PoisonError::into_inner).insert(topic.to_string());
+        }
+
+        let mut hdrs = HeaderMap::new();
+        for (k, v) in headers.iter() {
+            hdrs.insert(k, v);
+        }
+        hdrs.insert(KEY_HEADER, key);
+
+        self.js
+            .publish_with_headers(topic.to_string(), hdrs, payload.to_vec().into())
+            .await?
+            .await?;
+        Ok(())
+    }
+}
+
+pub(crate) struct NatsSubscriber {
+    consumer: jetstream::consumer::PullConsumer,
+    messages: pull::Stream,
+}
+
+impl NatsSubscriber {
+    pub(crate) async fn connect(url: &str, topic: &str, group: &str) -> Result<Self> {
+        let client = async_nats::connect(url).await?;
+        let js = jetstream::new(client);
+        let stream = ensure_stream(&js, topic).await?;
+        // Durable consumer named after the group: same "resume where the group left off" as Kafka,
+        // and DeliverPolicy::New mirrors auto.offset.reset=latest for a fresh group.
+        let consumer: jetstream::consumer::PullConsumer = stream
+            .get_or_create_consumer(group, pull::Config {
+                durable_name: Some(group.to_string()),
+                deliver_policy: DeliverPolicy::New,
+                ack_policy: AckPolicy::Explicit,
+                ..Default::default()
+            })
+            .await?;
+        let messages = consumer.messages().await?;
+        Ok(Self { consumer, messages })
+    }
+}
+
+#[async_trait]
+impl Subscriber for NatsSubscriber {
+    async fn next(&mut self) -> Option<Result<Delivery>> {
+        let msg = match self.messages.next().await? {
+            Ok(m) => m,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let mut headers = Headers::new();
+        let mut key = None;
+        if let Some(h) = &msg.headers {
+            for (name, values) in h.iter() {
+                let (name, Some(v)) = (AsRef::<str>::as_ref(name), values.first()) else { continue };
+                if name == KEY_HEADER {
+                    key = Some(v.as_str().to_string());
+                } else {
+                    headers.insert(name, v.as_str());
+                }
+            }
+        }
+        Some(Ok(Delivery {
+            topic: msg.subject.to_string(),
+            key,
+            payload: msg.payload.to_vec(),
+            headers,
+            ack: Ack::Nats(Box::new(msg)),
+        }))
+    }
+
+    async fn commit(&self, d: &Delivery) -> Result<()> {
+        match &d.ack {
+            Ack::Nats(m) => m.ack().await.map_err(|e| anyhow::anyhow!(e)),
+            _ => anyhow::bail!("delivery did not come from NATS"),
+        }
+    }
+
+    async fn commit_all(&self, ds: &[Delivery]) -> Result<()> {
+        // JetStream acks are per message.
+        for d in ds {
+            self.commit(d).await?;
+        }
+        Ok(())
+    }
+
+    async fn lag(&self, _d: &Delivery) -> Option<i64> {
+        let info = self.consumer.get_info().await.ok()?;
+        Some(info.num_pending as i64)
+    }
+}
@@ -0,0 +1,95 @@
+//! A cap on payload size at each stage, so one malformed or hostile frame
+//! of several MB can't ride the pipeline from the WebSocket to QuestDB.
+//!
+//! `MAX_PAYLOAD_BYTES` (default 1 MiB, 0 for no cap) is the most a stage
+//! takes; `OVERSIZE_ACTION` says what happens to a message over it:
+//!
+//! - `drop` (the default): it's counted and discarded.
+//! - `dlq`: it goes whole to the stage's dead-letter topic.
+//! - `truncate`: only its first `MAX_PAYLOAD_BYTES` go there, enough to see
+//!   what it was without moving all of it.
+//!
+//! Nothing over the cap is passed downstream either way. Every one is counted
+//! in `payload_oversize_total{stage,action}`, and dead-lettered copies carry
+//! the original size in [`OVERSIZE_BYTES`].
+
+use anyhow::Result;
+use metrics::counter;
+
+use crate::env;
+
+/// Header on a dead-lettered oversize message: its size before any truncation.
+pub const OVERSIZE_BYTES: &str = "oversize_bytes";
+
+pub const DEFAULT_MAX_BYTES: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Drop,
+    DeadLetter,
+    Truncate,
+}
+
+impl Action {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "drop" => Ok(Action::Drop),
+            "dlq" => Ok(Action::DeadLetter),
+            "truncate" => Ok(Action::Truncate),
+            other => anyhow::bail!("unknown OVERSIZE_ACTION {:?} (expected drop|dlq|truncate)", other),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Drop => "drop",
+            Action::DeadLetter => "dlq",
+            Action::Truncate => "truncate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+    pub max_bytes: usize,
+    pub action: Action,
+}
+
+impl Limit {
+    pub fn new(max_bytes: usize, action: Action) -> Self {
+        Self { max_bytes, action }
+    }
+
+    /// From `MAX_PAYLOAD_BYTES` and `OVERSIZE_ACTION`; `None` when the cap is 0.
+    pub fn from_env() -> Result<Option<Self>> {
+        let max_bytes: usize = env("MAX_PAYLOAD_BYTES", &DEFAULT_MAX_BYTES.to_string()).parse()
+            .map_err(|_| anyhow::anyhow!("MAX_PAYLOAD_BYTES is not a byte count"))?;
+        let action = Action::parse(&env("OVERSIZE_ACTION", "drop"))?;
+        Ok(Some(Self::new(max_bytes, action)).filter(|l| l.max_bytes > 0))
+    }
+
+    /// What to do with a payload of `len` bytes at `stage`: `None` when it
+    /// fits, otherwise the action, counted.
+    pub fn exceeded(&self, stage: &'static str, len: usize) -> Option<Action> {
+        if len <= self.max_bytes {
+            return None;
+        }
+        counter!("payload_oversize_total", "stage" => stage, "action" => self.action.name()).increment(1);
+        Some(self.action)
+    }
+
+    /// The part of an oversize `payload` to dead-letter: all of it, or for
+    /// `truncate` the first `max_bytes`, cut back to a UTF-8 boundary so a
+    /// text payload stays text.
+    pub fn dead_letter<'a>(&self, payload: &'a [u8]) -> &'a [u8] {
+        if self.action != Action::Truncate || payload.len() <= self.max_bytes {
+            return payload;
+        }
+        let mut end = self.max_bytes;
+        // Continuation bytes are 0b10xxxxxx; back up to the start of that character.
+        while end > 0 && payload[end] & 0xC0 == 0x80 {
+            end -= 1;
+        }
+        &payload[..end]
+    }
+}
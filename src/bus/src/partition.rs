@@ -0,0 +1,73 @@
+//! Pin heavy symbols to partitions of their own.
+//!
+//! `PARTITION_MAP=BTCUSDT=0,ETHUSDT=1` sends every message keyed `BTCUSDT`
+//! (in any case) to partition 0 and `ETHUSDT` to 1. All other keys are hashed
+//! over the partitions nobody is pinned to, so a pinned partition carries
+//! only its symbol. Without a map, the client's default partitioner decides.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PartitionMap(Vec<(String, i32)>);
+
+impl PartitionMap {
+    /// Parse `SYMBOL=partition,...`; an empty string is an empty map.
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut pins: Vec<(String, i32)> = Vec::new();
+        for item in s.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            let (symbol, p) = item.split_once('=').with_context(|| format!("PARTITION_MAP entry {:?} isn't SYMBOL=partition", item))?;
+            let symbol = symbol.trim().to_uppercase();
+            let p: i32 = p.trim().parse().with_context(|| format!("bad partition in PARTITION_MAP entry {:?}", item))?;
+            anyhow::ensure!(p >= 0, "negative partition in PARTITION_MAP entry {:?}", item);
+            anyhow::ensure!(pins.iter().all(|(s, _)| *s != symbol), "{} pinned twice in PARTITION_MAP", symbol);
+            pins.push((symbol, p));
+        }
+        Ok(Self(pins))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Where keys go on a topic with `count` partitions. Pins beyond the
+    /// partition count are left out (and logged), so those symbols hash normally.
+    pub fn layout(&self, topic: &str, count: i32) -> Layout {
+        let mut pinned = HashMap::new();
+        for (symbol, p) in &self.0 {
+            if *p < count {
+                pinned.insert(symbol.clone(), *p);
+            } else {
+                tracing::warn!(target: "bus", topic, symbol, partition = p, count, "pinned partition doesn't exist; hashing the symbol instead");
+            }
+        }
+        let mut free: Vec<i32> = (0..count).filter(|p| !pinned.values().any(|q| q == p)).collect();
+        if free.is_empty() {
+            tracing::warn!(target: "bus", topic, count, "every partition is pinned; unpinned symbols share them");
+            free = (0..count).collect();
+        }
+        Layout { pinned, free }
+    }
+}
+
+/// A [`PartitionMap`] resolved against one topic's partition count.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pinned: HashMap<String, i32>,
+    free: Vec<i32>,
+}
+
+impl Layout {
+    pub fn partition(&self, key: &str) -> i32 {
+        if let Some(p) = self.pinned.get(&key.to_uppercase()) {
+            return *p;
+        }
+        // FNV-1a over the upper-cased key: stable across builds, and the same
+        // partition for `btcusdt` on ticks.raw as for `BTCUSDT` on ticks.norm.
+        let h = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+            (h ^ b.to_ascii_uppercase() as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+        self.free[(h % self.free.len() as u64) as usize]
+    }
+}
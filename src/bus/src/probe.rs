@@ -0,0 +1,234 @@
+//! Startup probe that identifies the broker and picks a matching librdkafka profile.
+//!
+//! `BROKER_PROFILE=auto` (default) asks the cluster what it is; `kafka` /
+//! `redpanda` force a profile without probing; `none` keeps librdkafka defaults.
+//!
+//! Producers are made idempotent wherever the broker allows it, so a retried
+//! send can neither duplicate nor reorder a partition; `PRODUCER_IDEMPOTENCE`
+//! says whether that is optional (`auto`), a startup requirement (`require`),
+//! or unwanted (`off`).
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use rdkafka::config::ClientConfig;
+use rdkafka::admin::{AdminClient, AdminOptions, ResourceSpecifier};
+use rdkafka::client::DefaultClientContext;
+
+use crate::BusConfig;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrokerKind {
+    Kafka,
+    Redpanda,
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct BrokerInfo {
+    pub kind: BrokerKind,
+    /// (major, minor) from `inter.broker.protocol.version`, when the broker reports it.
+    pub version: Option<(u32, u32)>,
+    pub cluster_id: Option<String>,
+    /// `false` only when the broker explicitly reports idempotence disabled.
+    pub idempotence: bool,
+}
+
+/// Settings layered between the shared defaults and per-stage overrides.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub name: &'static str,
+    /// The probed protocol version, for settings that depend on it.
+    pub broker_version: Option<(u32, u32)>,
+    /// Whether `producer` turns on idempotence.
+    pub idempotent: bool,
+    pub producer: Vec<(&'static str, String)>,
+    pub consumer: Vec<(&'static str, String)>,
+}
+
+/// `BROKER_PROFILE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrokerProfile {
+    /// Probe the cluster and pick the profile that matches.
+    Auto,
+    Kafka,
+    Redpanda,
+    /// librdkafka's defaults.
+    None,
+}
+
+impl BrokerProfile {
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s.trim().to_lowercase().as_str() {
+            "" | "auto" => BrokerProfile::Auto,
+            "kafka" => BrokerProfile::Kafka,
+            "redpanda" => BrokerProfile::Redpanda,
+            "none" => BrokerProfile::None,
+            other => anyhow::bail!("unknown BROKER_PROFILE {:?} (expected auto|kafka|redpanda|none)", other),
+        })
+    }
+}
+
+/// `PRODUCER_IDEMPOTENCE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Idempotence {
+    /// Idempotent when the broker supports it, else one request in flight.
+    Auto,
+    /// Refuse to start a producer that wouldn't be idempotent.
+    Require,
+    /// Never idempotent; one request in flight keeps retries in order.
+    Off,
+}
+
+impl Idempotence {
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s.trim().to_lowercase().as_str() {
+            "" | "auto" => Idempotence::Auto,
+            "require" | "required" => Idempotence::Require,
+            "off" | "false" => Idempotence::Off,
+            other => anyhow::bail!("unknown PRODUCER_IDEMPOTENCE {:?} (expected auto|require|off)", other),
+        })
+    }
+}
+
+/// Check the settings a producer is about to be created with: under
+/// `Require` it must be idempotent, and an idempotent one must not have had
+/// `acks`, `retries` or `max.in.flight` overridden into something librdkafka
+/// would reject.
+pub fn check_producer(mode: Idempotence, cfg: &ClientConfig) -> Result<()> {
+    let idempotent = cfg.get("enable.idempotence") == Some("true");
+    if mode == Idempotence::Require && !idempotent {
+        anyhow::bail!("PRODUCER_IDEMPOTENCE=require, but the broker was not confirmed to support idempotent producers (set BROKER_PROFILE, or check enable.idempotence on the broker)");
+    }
+    if !idempotent {
+        return Ok(());
+    }
+    if let Some(acks) = cfg.get("acks").or_else(|| cfg.get("request.required.acks")) {
+        anyhow::ensure!(matches!(acks, "all" | "-1"), "idempotent producer needs acks=all, not {}", acks);
+    }
+    if let Some(n) = cfg.get("max.in.flight.requests.per.connection").or_else(|| cfg.get("max.in.flight")) {
+        anyhow::ensure!(n.parse::<u32>().is_ok_and(|n| (1..=5).contains(&n)), "idempotent producer needs max.in.flight of 1-5, not {}", n);
+    }
+    if let Some(n) = cfg.get("retries").or_else(|| cfg.get("message.send.max.retries")) {
+        anyhow::ensure!(n.parse::<u64>().is_ok_and(|n| n > 0), "idempotent producer needs retries, not {}", n);
+    }
+    Ok(())
+}
+
+fn parse_version(v: &str) -> Option<(u32, u32)> {
+    // e.g. "3.5-IV2", "2.8", "0.10.2-IV0"
+    let mut it = v.split(['.', '-']);
+    Some((it.next()?.parse().ok()?, it.next()?.parse().ok()?))
+}
+
+/// Ask the cluster who it is: cluster id, broker configs, protocol version.
+pub async fn probe(cfg: &BusConfig) -> Result<BrokerInfo> {
+    let admin: Arc<AdminClient<DefaultClientContext>> = Arc::new(cfg.kafka_config().create()?);
+    // Both are blocking broker round trips of up to PROBE_TIMEOUT; off the runtime's workers.
+    let client = admin.clone();
+    let (md, cluster_id) = tokio::task::spawn_blocking(move || {
+        let md = client.inner().fetch_metadata(None, PROBE_TIMEOUT)?;
+        anyhow::Ok((md, client.inner().fetch_cluster_id(PROBE_TIMEOUT)))
+    })
+    .await??;
+    let broker_id = md.brokers().first().map(|b| b.id())
+        .ok_or_else(|| anyhow::anyhow!("metadata returned no brokers"))?;
+
+    let opts = AdminOptions::new().request_timeout(Some(PROBE_TIMEOUT));
+    let entries = admin
+        .describe_configs([&ResourceSpecifier::Broker(broker_id)], &opts)
+        .await?
+        .into_iter()
+        .filter_map(|r| r.ok())
+        .flat_map(|r| r.entries)
+        .collect::<Vec<_>>();
+    let value = |name: &str| entries.iter().find(|e| e.name == name).and_then(|e| e.value.clone());
+
+    // Redpanda mints cluster ids as "redpanda.<uuid>" and exposes its own property names.
+    let redpanda = cluster_id.as_deref().is_some_and(|c| c.starts_with("redpanda."))
+        || entries.iter().any(|e| e.name.starts_with("redpanda"));
+    let version = value("inter.broker.protocol.version").as_deref().and_then(parse_version);
+    let kind = match (redpanda, version) {
+        (true, _) => BrokerKind::Redpanda,
+        (false, Some(_)) => BrokerKind::Kafka,
+        (false, None) => BrokerKind::Unknown,
+    };
+    let idempotence = !matches!(
+        value("enable.idempotence").or_else(|| value("enable_idempotence")).as_deref(),
+        Some("false")
+    );
+
+    Ok(BrokerInfo { kind, version, cluster_id, idempotence })
+}
+
+/// Pick settings for `info`, logging anything the broker can't do.
+pub fn profile_for(info: &BrokerInfo) -> Profile {
+    let mut p = Profile { broker_version: info.version, ..Default::default() };
+    let idempotence_ok = info.idempotence
+        && !matches!(info.version, Some((0, minor)) if minor < 11);
+    if !idempotence_ok {
+        tracing::warn!(target: "bus", ?info, "broker does not support idempotent producers; duplicates possible on retry");
+    }
+
+    match info.kind {
+        BrokerKind::Kafka => {
+            p.name = "kafka";
+            if let Some((0, minor)) = info.version {
+                tracing::warn!(target: "bus", version = ?info.version, "pre-1.0 Kafka broker; falling back to legacy protocol negotiation");
+                p.producer.push(("broker.version.fallback", format!("0.{}.0", minor)));
+                p.consumer.push(("broker.version.fallback", format!("0.{}.0", minor)));
+            }
+            p.producer.push(("linger.ms", "5".into()));
+            p.consumer.push(("fetch.wait.max.ms", "100".into()));
+        }
+        BrokerKind::Redpanda => {
+            p.name = "redpanda";
+            // Redpanda's fsync-per-batch path rewards small lingers and short fetch waits.
+            p.producer.push(("linger.ms", "1".into()));
+            p.consumer.push(("fetch.wait.max.ms", "10".into()));
+        }
+        BrokerKind::Unknown => {
+            p.name = "unknown";
+            tracing::warn!(target: "bus", "could not identify broker type/version; using librdkafka defaults");
+        }
+    }
+    // Retry transient errors quickly and for as long as `message.timeout.ms` allows.
+    p.producer.push(("retries", "2147483647".into()));
+    p.producer.push(("retry.backoff.ms", "100".into()));
+    p.producer.push(("retry.backoff.max.ms", "1000".into()));
+    p.idempotent = idempotence_ok && info.kind != BrokerKind::Unknown;
+    if p.idempotent {
+        p.producer.push(("enable.idempotence", "true".into()));
+        p.producer.push(("acks", "all".into()));
+        // The most librdkafka keeps in order under idempotence.
+        p.producer.push(("max.in.flight.requests.per.connection", "5".into()));
+    } else {
+        // Without sequence numbers, a retry behind a later request reorders the partition.
+        p.producer.push(("max.in.flight.requests.per.connection", "1".into()));
+    }
+    p.consumer.push(("max.partition.fetch.bytes", "1048576".into()));
+    p
+}
+
+/// Resolve `BROKER_PROFILE` into a profile; probe failures degrade to defaults.
+pub(crate) async fn resolve(cfg: &BusConfig) -> Profile {
+    let forced = |kind| BrokerInfo { kind, version: None, cluster_id: None, idempotence: true };
+    let info = match cfg.broker_profile {
+        BrokerProfile::None => return Profile { name: "none", ..Default::default() },
+        BrokerProfile::Kafka => forced(BrokerKind::Kafka),
+        BrokerProfile::Redpanda => forced(BrokerKind::Redpanda),
+        BrokerProfile::Auto => match probe(cfg).await {
+            Ok(info) => info,
+            Err(e) => {
+                tracing::warn!(target: "bus", error = ?e, "broker probe failed; using librdkafka defaults");
+                return Profile { name: "none", ..Default::default() };
+            }
+        },
+    };
+    let profile = profile_for(&info);
+    tracing::info!(target: "bus", kind = ?info.kind, version = ?info.version, cluster_id = ?info.cluster_id, profile = profile.name, idempotent = profile.idempotent, "broker profile");
+    profile
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use ::pulsar::consumer::ConsumerOptions;
+use ::pulsar::proto::MessageIdData;
+use ::pulsar::{producer, Consumer, Producer, Pulsar, SubType, TokioExecutor};
+use tokio::sync::Mutex;
+
+use crate::{Ack, Delivery, Headers, Publisher, Subscriber};
+
+async fn connect(url: &str) -> Result<Pulsar<TokioExecutor>> {
+    Ok(Pulsar::builder(url, TokioExecutor).build().await?)
+}
+
+/// Topics are auto-created by the broker; compaction is a namespace/topic
+/// policy set through the admin API, which we don't drive from here.
+pub(crate) async fn ensure_topic(topic: &str, configs: &[(&str, &str)]) -> Result<()> {
+    if !configs.is_empty() {
+        tracing::warn!(target: "bus", topic, ?configs, "Pulsar topic policies must be set with pulsar-admin; skipping");
+    }
+    Ok(())
+}
+
+pub(crate) struct PulsarPublisher {
+    client: Pulsar<TokioExecutor>,
+    producers: Mutex<HashMap<String, Producer<TokioExecutor>>>,
+}
+
+impl PulsarPublisher {
+    pub(crate) async fn connect(url: &str) -> Result<Self> {
+        Ok(Self { client: connect(url).await?, producers: Mutex::new(HashMap::new()) })
+    }
+}
+
+#[async_trait]
+impl Publisher for PulsarPublisher {
+    async fn publish(&self, topic: &str, key: &str, payload: &[u8], headers: &Headers) -> Result<()> {
+        let msg = producer::Message {
+            payload: payload.to_vec(),
+            properties: headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            // Key-based routing keeps a symbol on one partition, as with Kafka.
+            partition_key: Some(key.to_string()).filter(|k| !k.is_empty()),
+            ..Default::default()
+        };
+        // Hold the map only while queueing, so concurrent publishes still pipeline.
+        let receipt = {
+            let mut producers = self.producers.lock().await;
+            if !producers.contains_key(topic) {
+                let p = self.client.producer().with_topic(topic).build().await?;
+                producers.insert(topic.to_string(), p);
+            }
+            producers.get_mut(topic).unwrap().send_non_blocking(msg).await?
+        };
+        receipt.await?;
+        Ok(())
+    }
+}
+
+pub(crate) struct PulsarSubscriber {
+    consumer: Mutex<Consumer<Vec<u8>, TokioExecutor>>,
+}
+
+impl PulsarSubscriber {
+    /// A failover subscription named after the group: one active consumer per
+    /// partition, so per-key order holds like a Kafka consumer group.
+    pub(crate) async fn connect(url: &str, topic: &str, group: &str) -> Result<Self> {
+        let consumer = connect(url).await?
+            .consumer()
+            .with_topic(topic)
+            .with_subscription(group)
+            .with_subscription_type(SubType::Failover)
+            .with_options(ConsumerOptions::default())
+            .build()
+            .await?;
+        Ok(Self { consumer: Mutex::new(consumer) })
+    }
+}
+
+fn message_id(d: &Delivery) -> Result<&MessageIdData> {
+    match &d.ack {
+        Ack::Pulsar(id) => Ok(id),
+        _ => anyhow::bail!("delivery did not come from Pulsar"),
+    }
+}
+
+#[async_trait]
+impl Subscriber for PulsarSubscriber {
+    async fn next(&mut self) -> Option<Result<Delivery>> {
+        let msg = match self.consumer.get_mut().try_next().await {
+            Ok(Some(m)) => m,
+            Ok(None) => return None,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let mut headers = Headers::new();
+        for kv in &msg.payload.metadata.properties {
+            headers.insert(&kv.key, &kv.value);
+        }
+        Some(Ok(Delivery {
+            key: msg.key(),
+            payload: msg.payload.data.clone(),
+            headers,
+            ack: Ack::Pulsar(Box::new(msg.message_id().clone())),
+            topic: msg.topic,
+        }))
+    }
+
+    async fn commit(&self, d: &Delivery) -> Result<()> {
+        let id = message_id(d)?.clone();
+        self.consumer.lock().await.cumulative_ack_with_id(&d.topic, id).await?;
+        Ok(())
+    }
+
+    async fn commit_all(&self, ds: &[Delivery]) -> Result<()> {
+        // Cumulative: acking the last delivery per topic (partition) covers the rest.
+        let mut last: HashMap<&str, &MessageIdData> = HashMap::new();
+        for d in ds {
+            last.insert(d.topic.as_str(), message_id(d)?);
+        }
+        let mut consumer = self.consumer.lock().await;
+        for (topic, id) in last {
+            consumer.cumulative_ack_with_id(topic, id.clone()).await?;
+        }
+        Ok(())
+    }
+
+    async fn lag(&self, _d: &Delivery) -> Option<i64> {
+        None
+    }
+}
@@ -0,0 +1,103 @@
+//! Per-symbol routing: a heavy symbol on a topic, consumer deployment and
+//! QuestDB table of its own, by config.
+//!
+//! The producer and the consumer read the same manifest, `ROUTES` (or the
+//! file at `ROUTES_FILE`): one route per line or `;`, `#` starting a comment.
+//!
+//! ```text
+//! BTCUSDT topic=ticks.norm.btc table=trades_btc sinks=questdb
+//! ETHUSDT topic=ticks.norm.eth
+//! ```
+//!
+//! The producer publishes a routed symbol's trades to its `topic` instead
+//! of `TOPIC_OUT`. A consumer whose `TOPIC_IN` is a routed topic writes
+//! those symbols' trades to their `table` (default `trades`) and, unless
+//! `SINKS` says otherwise, to the route's `sinks` (`+`-separated). Routes
+//! sharing a topic must agree on the sinks, since one deployment serves them.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    /// Upper-cased, as on the normalized stream.
+    pub symbol: String,
+    pub topic: String,
+    pub table: Option<String>,
+    /// Empty: whatever `SINKS` names.
+    pub sinks: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Routes(Vec<Route>);
+
+impl Routes {
+    /// Parse a manifest; an empty one routes nothing.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut routes: Vec<Route> = Vec::new();
+        for line in spec.split(['\n', ';']).map(|l| l.split('#').next().unwrap_or_default().trim()).filter(|l| !l.is_empty()) {
+            let mut words = line.split_whitespace();
+            let symbol = words.next().unwrap_or_default().to_uppercase();
+            let (mut topic, mut table, mut sinks) = (None, None, Vec::new());
+            for word in words {
+                let (k, v) = word.split_once('=').with_context(|| format!("route {:?}: {:?} isn't key=value", line, word))?;
+                anyhow::ensure!(!v.is_empty(), "route {:?}: empty {}", line, k);
+                match k {
+                    "topic" => topic = Some(v.to_string()),
+                    "table" => table = Some(v.to_string()),
+                    "sinks" => sinks = v.split('+').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect(),
+                    other => anyhow::bail!("route {:?}: unknown key {:?} (expected topic|table|sinks)", line, other),
+                }
+            }
+            let topic = topic.with_context(|| format!("route {:?} has no topic", line))?;
+            anyhow::ensure!(routes.iter().all(|r| r.symbol != symbol), "{} routed twice", symbol);
+            if let Some(other) = routes.iter().find(|r| r.topic == topic && r.sinks != sinks) {
+                anyhow::bail!("{} and {} share {} but not their sinks", other.symbol, symbol, topic);
+            }
+            routes.push(Route { symbol, topic, table, sinks });
+        }
+        Ok(Self(routes))
+    }
+
+    /// `ROUTES`, or the file at `ROUTES_FILE`; empty when neither is set.
+    pub fn from_env() -> Result<Self> {
+        match (std::env::var("ROUTES"), std::env::var("ROUTES_FILE")) {
+            (Ok(s), _) if !s.trim().is_empty() => Self::parse(&s),
+            (_, Ok(path)) if !path.is_empty() => {
+                Self::parse(&std::fs::read_to_string(&path).with_context(|| format!("reading {}", path))?).with_context(|| format!("in {}", path))
+            }
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The topic `symbol` is routed to, if it is (in any case).
+    pub fn topic(&self, symbol: &str) -> Option<&str> {
+        self.0.iter().find(|r| r.symbol.eq_ignore_ascii_case(symbol)).map(|r| r.topic.as_str())
+    }
+
+    /// Every routed topic, once each, in manifest order.
+    pub fn topics(&self) -> Vec<&str> {
+        let mut out: Vec<&str> = Vec::new();
+        for r in &self.0 {
+            if !out.contains(&r.topic.as_str()) {
+                out.push(&r.topic);
+            }
+        }
+        out
+    }
+
+    /// The routes on `topic`; for the consumer deployment reading it.
+    pub fn on(&self, topic: &str) -> Vec<&Route> {
+        self.0.iter().filter(|r| r.topic == topic).collect()
+    }
+
+    /// Symbol to table, for the routes on `topic` that name one.
+    pub fn tables(&self, topic: &str) -> HashMap<String, String> {
+        self.on(topic).into_iter().filter_map(|r| Some((r.symbol.clone(), r.table.clone()?))).collect()
+    }
+}
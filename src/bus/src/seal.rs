@@ -0,0 +1,259 @@
+//! Envelope encryption for sensitive topics (orders, executions).
+//!
+//! Brokers are shared, so payloads on `ENCRYPT_TOPICS` leave the process
+//! sealed with AES-256-GCM. Each publisher generates its own data key, uses
+//! it for at most `DATA_KEY_ROTATE_SECS` (and never for more than 2^24
+//! messages, well inside GCM's random-nonce budget), and ships it with every
+//! message wrapped by a master key from `ENCRYPTION_KEYS`. Subscribers holding
+//! that master key unwrap and decrypt before the stage sees the delivery;
+//! anyone else gets the ciphertext and an `enc` header saying so.
+//!
+//! Wire format: header `enc: aes256gcm`, header `enc_key: <master id>:<hex of
+//! nonce ‖ wrapped data key>`, payload `nonce ‖ ciphertext ‖ tag`. The
+//! message's `msg_id` is authenticated alongside, so sealed payloads can't be
+//! swapped between messages.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use metrics::counter;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::{hex, unhex, Delivery, Headers, Publisher, Subscriber, MSG_ID};
+
+pub const ENC: &str = "enc";
+pub const ENC_KEY: &str = "enc_key";
+const ALGORITHM: &str = "aes256gcm";
+/// Data key uses before a forced rotation, whatever the age.
+const MAX_USES: u64 = 1 << 24;
+/// Unwrapped data keys remembered per subscriber.
+const CACHE: usize = 1024;
+
+fn aead_key(bytes: &[u8]) -> Result<LessSafeKey> {
+    let key = UnboundKey::new(&AES_256_GCM, bytes).map_err(|_| anyhow::anyhow!("AES-256 keys are 32 bytes"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// `nonce ‖ ciphertext ‖ tag` of `plain`.
+fn seal(key: &LessSafeKey, rng: &SystemRandom, aad: &[u8], plain: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut nonce).map_err(|_| anyhow::anyhow!("no randomness for a nonce"))?;
+    let mut out = Vec::with_capacity(NONCE_LEN + plain.len() + AES_256_GCM.tag_len());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(plain);
+    let tag = key.seal_in_place_separate_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad), &mut out[NONCE_LEN..])
+        .map_err(|_| anyhow::anyhow!("encryption failed"))?;
+    out.extend_from_slice(tag.as_ref());
+    Ok(out)
+}
+
+fn open(key: &LessSafeKey, aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < NONCE_LEN + AES_256_GCM.tag_len() {
+        return None;
+    }
+    let nonce = Nonce::try_assume_unique_for_key(&sealed[..NONCE_LEN]).ok()?;
+    let mut buf = sealed[NONCE_LEN..].to_vec();
+    let plain_len = key.open_in_place(nonce, Aad::from(aad), &mut buf).ok()?.len();
+    buf.truncate(plain_len);
+    Some(buf)
+}
+
+struct DataKey {
+    key: LessSafeKey,
+    /// The `enc_key` header value.
+    wrapped: String,
+    since: Instant,
+    uses: u64,
+}
+
+/// Master keys, which of them wraps new data keys, and which topics to seal.
+pub struct Sealer {
+    masters: HashMap<String, LessSafeKey>,
+    wrapping: String,
+    topics: HashSet<String>,
+    rotate: Duration,
+    rng: SystemRandom,
+    current: Mutex<Option<DataKey>>,
+}
+
+impl std::fmt::Debug for Sealer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Key ids only; never the keys.
+        f.debug_struct("Sealer")
+            .field("masters", &self.masters.keys().collect::<Vec<_>>())
+            .field("wrapping", &self.wrapping)
+            .field("topics", &self.topics)
+            .field("rotate", &self.rotate)
+            .finish()
+    }
+}
+
+impl Sealer {
+    /// `id=<64 hex chars>` master keys separated by commas or newlines. The
+    /// first wraps new data keys unless `wrapping` names another.
+    pub fn parse(spec: &str, wrapping: Option<&str>, topics: &str, rotate: Duration) -> Result<Self> {
+        let mut masters = HashMap::new();
+        let mut first = None;
+        for entry in spec.split([',', '\n']).map(str::trim).filter(|e| !e.is_empty() && !e.starts_with('#')) {
+            let (id, key) = entry.split_once('=').context("encryption keys must be id=hex")?;
+            let id = id.trim();
+            anyhow::ensure!(!id.is_empty() && !id.contains(':'), "bad encryption key id {:?}", id);
+            let bytes = unhex(key.trim()).with_context(|| format!("encryption key {:?} is not hex", id))?;
+            masters.insert(id.to_string(), aead_key(&bytes).with_context(|| format!("encryption key {:?}", id))?);
+            first.get_or_insert_with(|| id.to_string());
+        }
+        let wrapping = wrapping.map(str::to_string).or(first).context("no encryption keys")?;
+        anyhow::ensure!(masters.contains_key(&wrapping), "ENCRYPTION_KEY_ID {:?} is not among the encryption keys", wrapping);
+        let topics = topics.split(',').map(str::trim).filter(|t| !t.is_empty()).map(str::to_string).collect();
+        Ok(Self { masters, wrapping, topics, rotate, rng: SystemRandom::new(), current: Mutex::new(None) })
+    }
+
+    /// `ENCRYPTION_KEYS` or the file at `ENCRYPTION_KEYS_FILE`, `ENCRYPTION_KEY_ID`,
+    /// `ENCRYPT_TOPICS` and `DATA_KEY_ROTATE_SECS` (default 3600). `None` when no keys are set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let spec = match (std::env::var("ENCRYPTION_KEYS"), std::env::var("ENCRYPTION_KEYS_FILE")) {
+            (Ok(s), _) if !s.trim().is_empty() => s,
+            (_, Ok(path)) if !path.is_empty() => std::fs::read_to_string(&path).with_context(|| format!("reading {}", path))?,
+            _ => return Ok(None),
+        };
+        let wrapping = std::env::var("ENCRYPTION_KEY_ID").ok().filter(|s| !s.is_empty());
+        let topics = std::env::var("ENCRYPT_TOPICS").unwrap_or_default();
+        let rotate = std::env::var("DATA_KEY_ROTATE_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(3600);
+        Self::parse(&spec, wrapping.as_deref(), &topics, Duration::from_secs(rotate)).map(Some)
+    }
+
+    pub fn encrypts(&self, topic: &str) -> bool {
+        self.topics.contains(topic)
+    }
+
+    /// Sealed payload and the headers to send it with.
+    pub fn seal(&self, payload: &[u8], headers: &Headers) -> Result<(Vec<u8>, Headers)> {
+        let mut current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+        if current.as_ref().is_none_or(|k| k.since.elapsed() >= self.rotate || k.uses >= MAX_USES) {
+            let mut raw = [0u8; 32];
+            self.rng.fill(&mut raw).map_err(|_| anyhow::anyhow!("no randomness for a data key"))?;
+            let wrapped = seal(&self.masters[&self.wrapping], &self.rng, self.wrapping.as_bytes(), &raw)?;
+            *current = Some(DataKey {
+                key: aead_key(&raw)?,
+                wrapped: format!("{}:{}", self.wrapping, hex(&wrapped)),
+                since: Instant::now(),
+                uses: 0,
+            });
+            counter!("data_key_rotations_total").increment(1);
+        }
+        let dk = current.as_mut().expect("data key just made");
+        dk.uses += 1;
+        let sealed = seal(&dk.key, &self.rng, headers.get(MSG_ID).unwrap_or("").as_bytes(), payload)?;
+        Ok((sealed, headers.clone().with(ENC, ALGORITHM).with(ENC_KEY, &dk.wrapped)))
+    }
+
+    /// The data key behind an `enc_key` header value.
+    fn unwrap(&self, wrapped: &str) -> std::result::Result<LessSafeKey, &'static str> {
+        let (id, hex) = wrapped.split_once(':').ok_or("malformed")?;
+        let master = self.masters.get(id).ok_or("unknown_key")?;
+        let raw = open(master, id.as_bytes(), &unhex(hex).ok_or("malformed")?).ok_or("bad_key")?;
+        aead_key(&raw).map_err(|_| "bad_key")
+    }
+}
+
+/// Seals what goes to `ENCRYPT_TOPICS`; everything else passes through.
+pub struct SealingPublisher {
+    inner: Arc<dyn Publisher>,
+    sealer: Arc<Sealer>,
+}
+
+impl SealingPublisher {
+    pub fn new(inner: Arc<dyn Publisher>, sealer: Arc<Sealer>) -> Self {
+        Self { inner, sealer }
+    }
+}
+
+#[async_trait]
+impl Publisher for SealingPublisher {
+    async fn publish(&self, topic: &str, key: &str, payload: &[u8], headers: &Headers) -> Result<()> {
+        if !self.sealer.encrypts(topic) {
+            return self.inner.publish(topic, key, payload, headers).await;
+        }
+        let (sealed, headers) = self.sealer.seal(payload, headers)?;
+        counter!("encrypted_total", "topic" => topic.to_string()).increment(1);
+        self.inner.publish(topic, key, &sealed, &headers).await
+    }
+}
+
+/// Opens sealed deliveries it holds the master key for.
+pub struct UnsealingSubscriber {
+    inner: Box<dyn Subscriber + Sync>,
+    sealer: Arc<Sealer>,
+    keys: HashMap<String, LessSafeKey>,
+}
+
+impl UnsealingSubscriber {
+    pub fn new(inner: Box<dyn Subscriber + Sync>, sealer: Arc<Sealer>) -> Self {
+        Self { inner, sealer, keys: HashMap::new() }
+    }
+
+    fn open(&mut self, d: &mut Delivery) -> std::result::Result<(), &'static str> {
+        if d.header(ENC) != Some(ALGORITHM) {
+            return Err("unsupported");
+        }
+        let wrapped = d.header(ENC_KEY).ok_or("malformed")?.to_string();
+        if !self.keys.contains_key(&wrapped) {
+            if self.keys.len() >= CACHE {
+                self.keys.clear();
+            }
+            let key = self.sealer.unwrap(&wrapped)?;
+            self.keys.insert(wrapped.clone(), key);
+        }
+        let aad = d.header(MSG_ID).unwrap_or("").as_bytes().to_vec();
+        d.payload = open(&self.keys[&wrapped], &aad, &d.payload).ok_or("tampered")?;
+        d.headers.remove(ENC);
+        d.headers.remove(ENC_KEY);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Subscriber for UnsealingSubscriber {
+    /// Sealed deliveries that can't be opened are passed on as they are, still
+    /// marked `enc`, and counted in `decrypt_failures_total{reason}`.
+    async fn next(&mut self) -> Option<Result<Delivery>> {
+        let mut d = match self.inner.next().await? {
+            Ok(d) => d,
+            Err(e) => return Some(Err(e)),
+        };
+        if d.header(ENC).is_some() {
+            match self.open(&mut d) {
+                Ok(()) => counter!("decrypted_total", "topic" => d.topic.clone()).increment(1),
+                Err(reason) => {
+                    counter!("decrypt_failures_total", "topic" => d.topic.clone(), "reason" => reason).increment(1);
+                    tracing::warn!(target: "bus", topic = %d.topic, reason, "could not decrypt delivery");
+                }
+            }
+        }
+        Some(Ok(d))
+    }
+
+    async fn commit(&self, d: &Delivery) -> Result<()> {
+        self.inner.commit(d).await
+    }
+
+    async fn commit_all(&self, ds: &[Delivery]) -> Result<()> {
+        self.inner.commit_all(ds).await
+    }
+
+    async fn lag(&self, d: &Delivery) -> Option<i64> {
+        self.inner.lag(d).await
+    }
+
+    fn pause(&self) -> Result<()> {
+        self.inner.pause()
+    }
+
+    fn resume(&self) -> Result<()> {
+        self.inner.resume()
+    }
+}
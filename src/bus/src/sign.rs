@@ -0,0 +1,132 @@
+//! HMAC-SHA256 signatures between stages.
+//!
+//! A signing stage puts `signature: <key id>:<hex>` on what it publishes,
+//! computed over `msg_id`, `ts_produce_ns` and the payload. The next stage
+//! checks it before doing anything else. Environments get different keys,
+//! so a message that wandered in from staging fails as surely as a tampered
+//! one. Several keys can be configured at once for rotation: the signing key
+//! is one of them, and any of them verifies.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use metrics::counter;
+use ring::hmac;
+
+use crate::{hex, unhex, Headers, MSG_ID, TS_PRODUCE_NS};
+
+pub const SIGNATURE: &str = "signature";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Drop what fails verification.
+    Enforce,
+    /// Count and log failures, but keep the message.
+    Warn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    Missing,
+    Malformed,
+    UnknownKey,
+    Mismatch,
+}
+
+impl Failure {
+    pub fn reason(self) -> &'static str {
+        match self {
+            Self::Missing => "missing",
+            Self::Malformed => "malformed",
+            Self::UnknownKey => "unknown_key",
+            Self::Mismatch => "mismatch",
+        }
+    }
+}
+
+pub struct Keyring {
+    keys: HashMap<String, hmac::Key>,
+    signing: String,
+    mode: Mode,
+}
+
+fn message(payload: &[u8], headers: &Headers) -> Vec<u8> {
+    let mut m = Vec::with_capacity(payload.len() + 64);
+    m.extend_from_slice(headers.get(MSG_ID).unwrap_or("").as_bytes());
+    m.push(b'\n');
+    m.extend_from_slice(headers.get(TS_PRODUCE_NS).unwrap_or("").as_bytes());
+    m.push(b'\n');
+    m.extend_from_slice(payload);
+    m
+}
+
+impl Keyring {
+    /// `id=secret` entries separated by commas or newlines. The first signs
+    /// unless `signing` names another.
+    pub fn parse(spec: &str, signing: Option<&str>, mode: Mode) -> Result<Self> {
+        let mut keys = HashMap::new();
+        let mut first = None;
+        for entry in spec.split([',', '\n']).map(str::trim).filter(|e| !e.is_empty() && !e.starts_with('#')) {
+            let (id, secret) = entry.split_once('=').context("signing keys must be id=secret")?;
+            let (id, secret) = (id.trim(), secret.trim());
+            anyhow::ensure!(!id.is_empty() && !id.contains(':'), "bad signing key id {:?}", id);
+            anyhow::ensure!(secret.len() >= 16, "signing key {:?} is shorter than 16 bytes", id);
+            first.get_or_insert_with(|| id.to_string());
+            keys.insert(id.to_string(), hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()));
+        }
+        let signing = signing.map(str::to_string).or(first).context("no signing keys")?;
+        anyhow::ensure!(keys.contains_key(&signing), "SIGNING_KEY_ID {:?} is not among the signing keys", signing);
+        Ok(Self { keys, signing, mode })
+    }
+
+    /// `SIGNING_KEYS` or the file at `SIGNING_KEYS_FILE`, `SIGNING_KEY_ID` and
+    /// `SIGNATURE_MODE` (`enforce|warn`, default `enforce`). `None` when no keys are set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let spec = match (std::env::var("SIGNING_KEYS"), std::env::var("SIGNING_KEYS_FILE")) {
+            (Ok(s), _) if !s.trim().is_empty() => s,
+            (_, Ok(path)) if !path.is_empty() => std::fs::read_to_string(&path).with_context(|| format!("reading {}", path))?,
+            _ => return Ok(None),
+        };
+        let mode = match std::env::var("SIGNATURE_MODE").unwrap_or_else(|_| "enforce".into()).as_str() {
+            "enforce" => Mode::Enforce,
+            "warn" => Mode::Warn,
+            other => anyhow::bail!("unknown SIGNATURE_MODE {:?} (expected enforce|warn)", other),
+        };
+        let signing = std::env::var("SIGNING_KEY_ID").ok().filter(|s| !s.is_empty());
+        Self::parse(&spec, signing.as_deref(), mode).map(Some)
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// The `signature` header value for a message with these headers.
+    pub fn sign(&self, payload: &[u8], headers: &Headers) -> String {
+        let tag = hmac::sign(&self.keys[&self.signing], &message(payload, headers));
+        format!("{}:{}", self.signing, hex(tag.as_ref()))
+    }
+
+    /// `headers` plus their signature.
+    pub fn signed(&self, payload: &[u8], headers: Headers) -> Headers {
+        let sig = self.sign(payload, &headers);
+        headers.with(SIGNATURE, &sig)
+    }
+
+    pub fn verify(&self, payload: &[u8], headers: &Headers) -> std::result::Result<(), Failure> {
+        let sig = headers.get(SIGNATURE).ok_or(Failure::Missing)?;
+        let (id, hex) = sig.split_once(':').ok_or(Failure::Malformed)?;
+        let tag = unhex(hex).ok_or(Failure::Malformed)?;
+        let key = self.keys.get(id).ok_or(Failure::UnknownKey)?;
+        hmac::verify(key, &message(payload, headers), &tag).map_err(|_| Failure::Mismatch)
+    }
+
+    /// Verify, counting failures in `signature_failures_total{stage,reason}`.
+    /// Returns whether to keep the message.
+    pub fn check(&self, stage: &'static str, payload: &[u8], headers: &Headers) -> bool {
+        let Err(f) = self.verify(payload, headers) else { return true };
+        counter!("signature_failures_total", "stage" => stage, "reason" => f.reason()).increment(1);
+        tracing::warn!(target: "bus", stage, reason = f.reason(), msg_id = headers.get(MSG_ID).unwrap_or(""),
+            "signature verification failed");
+        self.mode == Mode::Warn
+    }
+}
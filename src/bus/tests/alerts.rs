@@ -0,0 +1,35 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bus::alerts::Alerts;
+use bus::{Headers, Publisher};
+use obsv::slo::Alert;
+use serde_json::Value;
+
+#[derive(Default)]
+struct Recorder(Mutex<Vec<(String, String, Value)>>);
+
+#[async_trait]
+impl Publisher for Recorder {
+    async fn publish(&self, topic: &str, key: &str, payload: &[u8], _headers: &Headers) -> Result<()> {
+        self.0.lock().unwrap().push((topic.to_string(), key.to_string(), serde_json::from_slice(payload)?));
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn alerts_are_keyed_by_slo_and_carry_the_service() {
+    let recorder = Arc::new(Recorder::default());
+    let alerts = Alerts::new(recorder.clone(), "alerts");
+    let alert = Alert { slo: "e2e".into(), severity: "page", state: "firing", threshold: 14.4, target: 0.99, burn_rate: Some(20.0), ts_ms: 1_717_200_000_000 };
+    alerts.publish(&alert).await;
+    alerts.publish(&Alert { state: "resolved", burn_rate: None, ..alert }).await;
+
+    let published = recorder.0.lock().unwrap();
+    let (topic, key, first) = &published[0];
+    assert_eq!((topic.as_str(), key.as_str()), ("alerts", "e2e"));
+    assert_eq!((first["severity"].as_str(), first["state"].as_str(), first["burn_rate"].as_f64()), (Some("page"), Some("firing"), Some(20.0)));
+    assert!(first["service"].is_string() && first["instance"].is_string());
+    assert_eq!((published[1].2["state"].as_str(), &published[1].2["burn_rate"]), (Some("resolved"), &Value::Null));
+}
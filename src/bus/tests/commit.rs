@@ -0,0 +1,66 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bus::commit::{CommitStrategy, Committer};
+use bus::{Delivery, Headers, Subscriber};
+
+/// Records the size of every commit_all call.
+#[derive(Default)]
+struct Recorder(Mutex<Vec<usize>>);
+
+#[async_trait]
+impl Subscriber for Recorder {
+    async fn next(&mut self) -> Option<Result<Delivery>> {
+        None
+    }
+
+    async fn commit(&self, _d: &Delivery) -> Result<()> {
+        self.0.lock().unwrap().push(1);
+        Ok(())
+    }
+
+    async fn commit_all(&self, ds: &[Delivery]) -> Result<()> {
+        self.0.lock().unwrap().push(ds.len());
+        Ok(())
+    }
+
+    async fn lag(&self, _d: &Delivery) -> Option<i64> {
+        None
+    }
+}
+
+fn delivery() -> Delivery {
+    Delivery::detached("t", None, b"x".to_vec(), Headers::new())
+}
+
+async fn run(strategy: CommitStrategy, messages: usize, flush_every: usize) -> Vec<usize> {
+    let sub = Recorder::default();
+    let mut c = Committer::new(strategy);
+    for i in 1..=messages {
+        c.done(&sub, delivery()).await.unwrap();
+        if i % flush_every == 0 {
+            c.flushed(&sub).await.unwrap();
+        }
+    }
+    c.commit(&sub).await.unwrap();
+    sub.0.into_inner().unwrap()
+}
+
+#[tokio::test]
+async fn strategies_commit_at_their_own_points() {
+    assert_eq!(run(CommitStrategy::Message, 3, 2).await, vec![1, 1, 1]);
+    assert_eq!(run(CommitStrategy::EveryN(2), 5, 10).await, vec![2, 2, 1]);
+    assert_eq!(run(CommitStrategy::Flush, 5, 2).await, vec![2, 2, 1]);
+    // Nothing is due within the test, so everything waits for the final commit.
+    assert_eq!(run(CommitStrategy::Interval(Duration::from_secs(60)), 5, 2).await, vec![5]);
+}
+
+#[test]
+fn parse_names() {
+    let d = Duration::from_millis(250);
+    assert_eq!(CommitStrategy::parse("every_n", 0, d).unwrap(), CommitStrategy::EveryN(1));
+    assert_eq!(CommitStrategy::parse("interval", 10, d).unwrap(), CommitStrategy::Interval(d));
+    assert!(CommitStrategy::parse("sometimes", 10, d).is_err());
+}
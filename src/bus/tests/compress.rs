@@ -0,0 +1,22 @@
+use bus::compress::{Codec, Compression};
+
+#[test]
+fn codecs_and_levels_are_validated() {
+    assert_eq!(Compression::parse("ZSTD", "6").unwrap(), Compression { codec: Codec::Zstd, level: Some(6) });
+    assert_eq!(Compression::parse("lz4", "").unwrap(), Compression { codec: Codec::Lz4, level: None });
+    assert_eq!(Compression::parse("", "").unwrap(), Compression::NONE);
+    assert!(Compression::parse("brotli", "").is_err());
+    assert!(Compression::parse("zstd", "13").unwrap_err().to_string().contains("out of range"));
+    assert!(Compression::parse("gzip", "10").is_err());
+    assert!(Compression::parse("snappy", "1").unwrap_err().to_string().contains("no compression levels"));
+}
+
+#[test]
+fn zstd_falls_back_to_lz4_on_old_brokers() {
+    let zstd = Compression::parse("zstd", "3").unwrap();
+    assert_eq!(zstd.negotiate(Some((2, 0))), Compression { codec: Codec::Lz4, level: None });
+    assert_eq!(zstd.negotiate(Some((3, 5))), zstd);
+    assert_eq!(zstd.negotiate(None), zstd);
+    assert_eq!(zstd.settings(), [("compression.type", "zstd".to_string()), ("compression.level", "3".to_string())]);
+    assert_eq!(Compression::NONE.settings(), [("compression.type", "none".to_string())]);
+}
@@ -0,0 +1,19 @@
+use bus::error::{kafka_kind, kind};
+use obsv::errors::ErrorKind;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+
+#[test]
+fn kafka_errors_are_classified_by_code() {
+    assert_eq!(kafka_kind(&KafkaError::MessageProduction(RDKafkaErrorCode::MessageTimedOut)), ErrorKind::Transient);
+    assert_eq!(kafka_kind(&KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull)), ErrorKind::Transient);
+    assert_eq!(kafka_kind(&KafkaError::MessageProduction(RDKafkaErrorCode::MessageSizeTooLarge)), ErrorKind::Data);
+    assert_eq!(kafka_kind(&KafkaError::MessageProduction(RDKafkaErrorCode::TopicAuthorizationFailed)), ErrorKind::Fatal);
+    assert_eq!(kafka_kind(&KafkaError::ClientCreation("bad config".into())), ErrorKind::Fatal);
+}
+
+#[test]
+fn anyhow_chains_are_searched_for_a_kafka_error() {
+    let e = anyhow::Error::from(KafkaError::ConsumerCommit(RDKafkaErrorCode::GroupAuthorizationFailed)).context("committing");
+    assert_eq!(kind(&e), ErrorKind::Fatal);
+    assert_eq!(kind(&anyhow::anyhow!("connection reset")), ErrorKind::Transient);
+}
@@ -0,0 +1,32 @@
+use bus::oversize::{Action, Limit};
+
+#[test]
+fn actions_are_validated() {
+    assert_eq!(Action::parse("dlq").unwrap(), Action::DeadLetter);
+    assert_eq!(Action::parse("truncate").unwrap().name(), "truncate");
+    assert!(Action::parse("DROP").is_err());
+}
+
+#[test]
+fn only_payloads_over_the_limit_are_caught() {
+    let limit = Limit::new(4, Action::Drop);
+    assert_eq!(limit.exceeded("test", 4), None);
+    assert_eq!(limit.exceeded("test", 5), Some(Action::Drop));
+}
+
+#[test]
+fn truncation_keeps_the_head_on_a_character_boundary() {
+    let payload = "ab€cd".as_bytes(); // € is 3 bytes, 2..5
+    assert_eq!(Limit::new(3, Action::Truncate).dead_letter(payload), b"ab");
+    assert_eq!(Limit::new(5, Action::Truncate).dead_letter(payload), "ab€".as_bytes());
+    assert_eq!(Limit::new(3, Action::DeadLetter).dead_letter(payload), payload, "dlq sends it whole");
+    assert_eq!(Limit::new(64, Action::Truncate).dead_letter(payload), payload);
+}
+
+#[test]
+fn a_stripped_delivery_keeps_what_a_commit_needs() {
+    let d = bus::Delivery::detached("ticks.norm", Some("BTCUSDT"), vec![b'x'; 1 << 20], bus::Headers::new().with("k", "v"));
+    let d = d.stripped();
+    assert_eq!((d.topic.as_str(), d.key.as_deref()), ("ticks.norm", Some("BTCUSDT")));
+    assert!(d.payload.is_empty() && d.header("k").is_none());
+}
@@ -0,0 +1,24 @@
+use bus::partition::PartitionMap;
+
+#[test]
+fn pinned_symbols_get_their_partition_to_themselves() {
+    let map = PartitionMap::parse("BTCUSDT=0, ethusdt=3").unwrap();
+    let layout = map.layout("ticks.norm", 4);
+    assert_eq!(layout.partition("BTCUSDT"), 0);
+    assert_eq!(layout.partition("btcusdt"), 0);
+    assert_eq!(layout.partition("ETHUSDT"), 3);
+    for key in ["SOLUSDT", "XRPUSDT", "DOGEUSDT", "ADAUSDT", "BNBUSDT"] {
+        let p = layout.partition(key);
+        assert!(p == 1 || p == 2, "{} went to {}", key, p);
+        assert_eq!(p, layout.partition(&key.to_lowercase()), "case changes the partition of {}", key);
+    }
+}
+
+#[test]
+fn pins_past_the_partition_count_fall_back_to_hashing() {
+    let layout = PartitionMap::parse("BTCUSDT=7").unwrap().layout("ticks.raw", 2);
+    assert!((0..2).contains(&layout.partition("BTCUSDT")));
+    assert!(PartitionMap::parse("BTCUSDT").is_err());
+    assert!(PartitionMap::parse("BTCUSDT=0,btcusdt=1").is_err());
+    assert!(PartitionMap::parse("").unwrap().is_empty());
+}
@@ -0,0 +1,57 @@
+use bus::probe::{check_producer, profile_for, BrokerInfo, BrokerKind, BrokerProfile, Idempotence};
+use rdkafka::config::ClientConfig;
+
+fn info(kind: BrokerKind, version: Option<(u32, u32)>, idempotence: bool) -> BrokerInfo {
+    BrokerInfo { kind, version, cluster_id: None, idempotence }
+}
+
+fn config(settings: &[(&str, String)]) -> ClientConfig {
+    let mut cfg = ClientConfig::new();
+    for (k, v) in settings {
+        cfg.set(*k, v);
+    }
+    cfg
+}
+
+#[test]
+fn idempotent_brokers_get_ordered_retries() {
+    let p = profile_for(&info(BrokerKind::Kafka, Some((3, 5)), true));
+    let cfg = config(&p.producer);
+    assert!(p.idempotent);
+    assert_eq!(cfg.get("enable.idempotence"), Some("true"));
+    assert_eq!(cfg.get("acks"), Some("all"));
+    assert_eq!(cfg.get("max.in.flight.requests.per.connection"), Some("5"));
+    assert!(check_producer(Idempotence::Require, &cfg).is_ok());
+}
+
+#[test]
+fn other_brokers_keep_one_request_in_flight() {
+    for i in [info(BrokerKind::Kafka, Some((0, 10)), true), info(BrokerKind::Redpanda, None, false), info(BrokerKind::Unknown, None, true)] {
+        let p = profile_for(&i);
+        let cfg = config(&p.producer);
+        assert!(!p.idempotent);
+        assert_eq!(cfg.get("max.in.flight.requests.per.connection"), Some("1"));
+        assert!(check_producer(Idempotence::Auto, &cfg).is_ok());
+        assert!(check_producer(Idempotence::Require, &cfg).is_err());
+    }
+}
+
+#[test]
+fn overrides_that_break_idempotence_are_refused() {
+    let p = profile_for(&info(BrokerKind::Redpanda, None, true));
+    for (k, v) in [("acks", "1"), ("max.in.flight.requests.per.connection", "10"), ("retries", "0")] {
+        let mut cfg = config(&p.producer);
+        cfg.set(k, v);
+        assert!(check_producer(Idempotence::Auto, &cfg).is_err(), "{}={}", k, v);
+    }
+    assert_eq!(Idempotence::parse("Require").unwrap(), Idempotence::Require);
+    assert!(Idempotence::parse("maybe").is_err());
+}
+
+#[test]
+fn an_unknown_broker_profile_is_refused_with_the_ones_there_are() {
+    assert_eq!(BrokerProfile::parse(" Redpanda ").unwrap(), BrokerProfile::Redpanda);
+    assert_eq!(BrokerProfile::parse("").unwrap(), BrokerProfile::Auto);
+    let e = BrokerProfile::parse("kafak").unwrap_err().to_string();
+    assert!(e.contains("auto|kafka|redpanda|none"), "{}", e);
+}
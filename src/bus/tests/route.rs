@@ -0,0 +1,51 @@
+use bus::route::Routes;
+
+const MANIFEST: &str = "
+# heavy symbols on their own
+btcusdt topic=ticks.norm.btc table=trades_btc sinks=questdb+parquet
+BTCUSDC topic=ticks.norm.btc table=trades_btc sinks=questdb+parquet
+ETHUSDT topic=ticks.norm.eth
+";
+
+#[test]
+fn symbols_route_to_their_topic_in_any_case() {
+    let routes = Routes::parse(MANIFEST).unwrap();
+    assert_eq!(routes.topic("BTCUSDT"), Some("ticks.norm.btc"));
+    assert_eq!(routes.topic("ethusdt"), Some("ticks.norm.eth"));
+    assert_eq!(routes.topic("SOLUSDT"), None);
+    assert_eq!(routes.topics(), ["ticks.norm.btc", "ticks.norm.eth"]);
+}
+
+#[test]
+fn a_routed_topic_knows_its_tables_and_sinks() {
+    let routes = Routes::parse(MANIFEST).unwrap();
+    let on = routes.on("ticks.norm.btc");
+    assert_eq!(on.len(), 2);
+    assert_eq!(on[0].sinks, ["questdb", "parquet"]);
+    let tables = routes.tables("ticks.norm.btc");
+    assert_eq!(tables.get("BTCUSDC").map(String::as_str), Some("trades_btc"));
+    assert!(routes.tables("ticks.norm.eth").is_empty(), "no table means the default one");
+    assert!(routes.on("ticks.norm").is_empty());
+}
+
+#[test]
+fn semicolons_separate_routes_too() {
+    let routes = Routes::parse("BTCUSDT topic=a; ETHUSDT topic=b").unwrap();
+    assert_eq!(routes.topics(), ["a", "b"]);
+    assert!(Routes::parse("").unwrap().is_empty());
+    assert!(Routes::parse("  # nothing\n").unwrap().is_empty());
+}
+
+#[test]
+fn malformed_manifests_are_rejected() {
+    for bad in [
+        "BTCUSDT table=trades_btc",
+        "BTCUSDT topic=a; btcusdt topic=b",
+        "BTCUSDT topic=a sinks=questdb; ETHUSDT topic=a",
+        "BTCUSDT topic=a shard=2",
+        "BTCUSDT topic",
+        "BTCUSDT topic=",
+    ] {
+        assert!(Routes::parse(bad).is_err(), "{:?}", bad);
+    }
+}
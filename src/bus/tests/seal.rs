@@ -0,0 +1,89 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bus::seal::{Sealer, UnsealingSubscriber, ENC, ENC_KEY};
+use bus::{Delivery, Headers, Subscriber, MSG_ID};
+
+const K1: &str = "k1=000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const K2: &str = "k2=1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+struct Queue(VecDeque<Delivery>);
+
+#[async_trait]
+impl Subscriber for Queue {
+    async fn next(&mut self) -> Option<Result<Delivery>> {
+        self.0.pop_front().map(Ok)
+    }
+
+    async fn commit(&self, _d: &Delivery) -> Result<()> {
+        Ok(())
+    }
+
+    async fn commit_all(&self, _ds: &[Delivery]) -> Result<()> {
+        Ok(())
+    }
+
+    async fn lag(&self, _d: &Delivery) -> Option<i64> {
+        None
+    }
+}
+
+fn sealed(sealer: &Sealer, msg_id: &str, payload: &[u8]) -> Delivery {
+    let (body, headers) = sealer.seal(payload, &Headers::new().with(MSG_ID, msg_id)).unwrap();
+    Delivery::detached("executions", Some("BTCUSDT"), body, headers)
+}
+
+async fn opened(sealer: Sealer, ds: Vec<Delivery>) -> Vec<Delivery> {
+    let mut sub = UnsealingSubscriber::new(Box::new(Queue(ds.into())), Arc::new(sealer));
+    let mut out = Vec::new();
+    while let Some(d) = sub.next().await {
+        out.push(d.unwrap());
+    }
+    out
+}
+
+#[tokio::test]
+async fn holders_of_the_master_key_read_sealed_payloads() {
+    let writer = Sealer::parse(K1, None, "executions", Duration::from_secs(3600)).unwrap();
+    assert!(writer.encrypts("executions") && !writer.encrypts("ticks.norm"));
+    let d = sealed(&writer, "m1", b"{\"status\":\"FILLED\"}");
+    assert!(!d.payload.windows(6).any(|w| w == b"FILLED"));
+    assert!(d.header(ENC_KEY).unwrap().starts_with("k1:"));
+
+    // After rotation the reader still holds k1 alongside the new key.
+    let reader = Sealer::parse(&format!("{},{}", K2, K1), None, "", Duration::from_secs(3600)).unwrap();
+    let out = opened(reader, vec![d]).await;
+    assert_eq!(out[0].payload, b"{\"status\":\"FILLED\"}");
+    assert!(out[0].header(ENC).is_none() && out[0].header(ENC_KEY).is_none());
+    assert_eq!(out[0].header(MSG_ID), Some("m1"));
+}
+
+#[tokio::test]
+async fn unknown_keys_and_swapped_payloads_stay_sealed() {
+    let writer = Sealer::parse(K1, None, "executions", Duration::from_secs(3600)).unwrap();
+    let a = sealed(&writer, "a", b"first");
+    let b = sealed(&writer, "b", b"second");
+    let swapped = Delivery::detached("executions", None, a.payload.clone(), b.headers.clone());
+
+    let other = Sealer::parse(K2, None, "", Duration::from_secs(3600)).unwrap();
+    assert_eq!(opened(other, vec![a]).await[0].header(ENC), Some("aes256gcm"));
+    let reader = Sealer::parse(K1, None, "", Duration::from_secs(3600)).unwrap();
+    let out = opened(reader, vec![swapped]).await;
+    assert_eq!(out[0].header(ENC), Some("aes256gcm"));
+    assert!(out[0].payload != b"first" && out[0].payload != b"second");
+}
+
+#[test]
+fn data_keys_rotate_and_master_keys_are_validated() {
+    let writer = Sealer::parse(K1, None, "orders", Duration::ZERO).unwrap();
+    let (_, h1) = writer.seal(b"x", &Headers::new()).unwrap();
+    let (_, h2) = writer.seal(b"x", &Headers::new()).unwrap();
+    assert_ne!(h1.get(ENC_KEY), h2.get(ENC_KEY));
+
+    assert!(Sealer::parse("k1=abcd", None, "", Duration::ZERO).is_err());
+    assert!(Sealer::parse("k1=zz", None, "", Duration::ZERO).is_err());
+    assert!(Sealer::parse(K1, Some("k2"), "", Duration::ZERO).is_err());
+}
@@ -0,0 +1,38 @@
+use bus::sign::{Failure, Keyring, Mode, SIGNATURE};
+use bus::{Headers, MSG_ID, TS_PRODUCE_NS};
+
+fn headers() -> Headers {
+    Headers::new().with(MSG_ID, "m1").with(TS_PRODUCE_NS, "1700000000000000000")
+}
+
+#[test]
+fn signed_messages_verify_under_any_configured_key() {
+    let old = Keyring::parse("old=0123456789abcdef", None, Mode::Enforce).unwrap();
+    let both = Keyring::parse("old=0123456789abcdef\nnew=fedcba9876543210", Some("new"), Mode::Enforce).unwrap();
+    let h = old.signed(b"{\"p\":1}", headers());
+    assert!(h.get(SIGNATURE).unwrap().starts_with("old:"));
+    assert_eq!(both.verify(b"{\"p\":1}", &h), Ok(()));
+    assert!(both.sign(b"x", &headers()).starts_with("new:"));
+    assert_eq!(old.verify(b"x", &both.signed(b"x", headers())), Err(Failure::UnknownKey));
+}
+
+#[test]
+fn tampering_fails_verification() {
+    let k = Keyring::parse("k1=0123456789abcdef", None, Mode::Enforce).unwrap();
+    let h = k.signed(b"payload", headers());
+    assert_eq!(k.verify(b"payload!", &h), Err(Failure::Mismatch));
+    let mut moved = h.clone();
+    moved.insert(MSG_ID, "m2");
+    assert_eq!(k.verify(b"payload", &moved), Err(Failure::Mismatch));
+    assert_eq!(k.verify(b"payload", &headers()), Err(Failure::Missing));
+    assert_eq!(k.verify(b"payload", &headers().with(SIGNATURE, "k1:zz")), Err(Failure::Malformed));
+    assert!(!k.check("test", b"payload!", &h));
+    assert!(Keyring::parse("k1=0123456789abcdef", None, Mode::Warn).unwrap().check("test", b"payload!", &h));
+}
+
+#[test]
+fn short_keys_and_unknown_signing_ids_are_rejected() {
+    assert!(Keyring::parse("k1=short", None, Mode::Enforce).is_err());
+    assert!(Keyring::parse("k1=0123456789abcdef", Some("k2"), Mode::Enforce).is_err());
+    assert!(Keyring::parse("", None, Mode::Enforce).is_err());
+}
@@ -0,0 +1,149 @@
+//! UTC trading sessions and maintenance windows.
+//!
+//! Crypto trades around the clock, but liquidity follows the regional
+//! sessions, so trades are tagged with the sessions open when they happened.
+//! `SESSIONS` lists them as `label=HH:MM-HH:MM` in UTC. A session may wrap
+//! midnight (`22:00-02:00`), and overlapping sessions give a combined label
+//! (`europe+us`).
+//!
+//! `MAINTENANCE_WINDOWS` lists periods when the data isn't trustworthy
+//! (exchange maintenance, planned outages). Aggregations leave those trades
+//! out, and audits skip them. Entries are either a fixed RFC 3339 range
+//! (`2024-06-01T00:00:00Z/2024-06-01T02:00:00Z`) or a weekly slot
+//! (`sun 22:00-23:30`).
+
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Weekday};
+
+/// Asia (Tokyo/Hong Kong/Singapore), Europe (London/Frankfurt), US (New York).
+pub const DEFAULT_SESSIONS: &str = "asia=00:00-09:00,europe=07:00-16:00,us=13:30-21:00";
+
+const DAY_MS: i64 = 86_400_000;
+const MINUTE_MS: i64 = 60_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Span {
+    /// Minutes after midnight UTC.
+    start: i64,
+    /// May be past 1440 for spans that wrap midnight.
+    end: i64,
+}
+
+impl Span {
+    fn parse(s: &str) -> Result<Self> {
+        let (a, b) = s.trim().split_once('-').with_context(|| format!("{:?} isn't HH:MM-HH:MM", s))?;
+        let (start, end) = (minute_of_day(a)?, minute_of_day(b)?);
+        anyhow::ensure!(start != end, "{:?} is empty", s);
+        Ok(Self { start, end: if end < start { end + 1440 } else { end } })
+    }
+
+    /// True if `ms` (since the epoch) falls in this span, on any day.
+    fn contains(&self, ms: i64) -> bool {
+        let m = ms.rem_euclid(DAY_MS) / MINUTE_MS;
+        (self.start <= m && m < self.end) || (self.start <= m + 1440 && m + 1440 < self.end)
+    }
+}
+
+fn minute_of_day(s: &str) -> Result<i64> {
+    let (h, m) = s.trim().split_once(':').with_context(|| format!("{:?} isn't HH:MM", s))?;
+    let (h, m): (i64, i64) = (h.parse()?, m.parse()?);
+    anyhow::ensure!((0..=24).contains(&h) && (0..60).contains(&m) && h * 60 + m <= 1440, "{:?} isn't a time of day", s);
+    Ok(h * 60 + m)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Session {
+    label: String,
+    span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Maintenance {
+    Once { from_ms: i64, to_ms: i64 },
+    Weekly { day: Weekday, span: Span },
+}
+
+impl Maintenance {
+    fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some((from, to)) = s.split_once('/') {
+            let from_ms = DateTime::parse_from_rfc3339(from.trim())?.timestamp_millis();
+            let to_ms = DateTime::parse_from_rfc3339(to.trim())?.timestamp_millis();
+            anyhow::ensure!(from_ms < to_ms, "maintenance window {:?} ends before it starts", s);
+            return Ok(Self::Once { from_ms, to_ms });
+        }
+        let (day, span) = s.split_once(' ').with_context(|| format!("{:?} is neither FROM/TO nor `DAY HH:MM-HH:MM`", s))?;
+        let day = Weekday::from_str(day.trim()).map_err(|_| anyhow::anyhow!("bad weekday in {:?}", s))?;
+        Ok(Self::Weekly { day, span: Span::parse(span)? })
+    }
+
+    /// The occurrences that could touch `[from_ms, to_ms)`, as ms ranges.
+    fn ranges(&self, from_ms: i64, to_ms: i64) -> Vec<(i64, i64)> {
+        match self {
+            Self::Once { from_ms, to_ms } => vec![(*from_ms, *to_ms)],
+            Self::Weekly { day, span } => {
+                // Start a day early so a slot wrapping midnight into `from_ms`'s day is seen.
+                let mut out = Vec::new();
+                let mut midnight = from_ms.div_euclid(DAY_MS) * DAY_MS - DAY_MS;
+                while midnight < to_ms {
+                    let weekday = DateTime::from_timestamp_millis(midnight).map(|d| d.weekday());
+                    if weekday == Some(*day) {
+                        out.push((midnight + span.start * MINUTE_MS, midnight + span.end * MINUTE_MS));
+                    }
+                    midnight += DAY_MS;
+                }
+                out
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Calendar {
+    sessions: Vec<Session>,
+    maintenance: Vec<Maintenance>,
+}
+
+impl Calendar {
+    /// `sessions` as in `SESSIONS`, `maintenance` as in `MAINTENANCE_WINDOWS`; either may be empty.
+    pub fn parse(sessions: &str, maintenance: &str) -> Result<Self> {
+        let sessions = sessions.split(',').map(str::trim).filter(|s| !s.is_empty())
+            .map(|s| {
+                let (label, span) = s.split_once('=').with_context(|| format!("session {:?} isn't label=HH:MM-HH:MM", s))?;
+                Ok(Session { label: label.trim().to_string(), span: Span::parse(span)? })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let maintenance = maintenance.split(',').map(str::trim).filter(|s| !s.is_empty())
+            .map(Maintenance::parse)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { sessions, maintenance })
+    }
+
+    /// `SESSIONS` (default [`DEFAULT_SESSIONS`]; empty disables tagging) and `MAINTENANCE_WINDOWS`.
+    pub fn from_env() -> Result<Self> {
+        let sessions = std::env::var("SESSIONS").unwrap_or_else(|_| DEFAULT_SESSIONS.to_string());
+        let maintenance = std::env::var("MAINTENANCE_WINDOWS").unwrap_or_default();
+        Self::parse(&sessions, &maintenance).context("bad SESSIONS or MAINTENANCE_WINDOWS")
+    }
+
+    pub fn has_sessions(&self) -> bool {
+        !self.sessions.is_empty()
+    }
+
+    /// Sessions open at `ts_ms`, joined with `+` in configured order; `None` if none is.
+    pub fn session(&self, ts_ms: i64) -> Option<String> {
+        let open: Vec<&str> = self.sessions.iter().filter(|s| s.span.contains(ts_ms)).map(|s| s.label.as_str()).collect();
+        (!open.is_empty()).then(|| open.join("+"))
+    }
+
+    pub fn in_maintenance(&self, ts_ms: i64) -> bool {
+        self.overlaps_maintenance(ts_ms, ts_ms + 1)
+    }
+
+    /// True if any maintenance window touches `[from_ms, to_ms)`.
+    pub fn overlaps_maintenance(&self, from_ms: i64, to_ms: i64) -> bool {
+        self.maintenance.iter().any(|m| m.ranges(from_ms, to_ms).into_iter().any(|(a, b)| a < to_ms && from_ms < b))
+    }
+}
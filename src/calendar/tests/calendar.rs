@@ -0,0 +1,37 @@
+use calendar::{Calendar, DEFAULT_SESSIONS};
+use chrono::{DateTime, Utc};
+
+fn ms(s: &str) -> i64 {
+    s.parse::<DateTime<Utc>>().unwrap().timestamp_millis()
+}
+
+#[test]
+fn sessions_overlap_and_wrap_midnight() {
+    let cal = Calendar::parse(DEFAULT_SESSIONS, "").unwrap();
+    assert_eq!(cal.session(ms("2024-06-03T03:00:00Z")).as_deref(), Some("asia"));
+    assert_eq!(cal.session(ms("2024-06-03T08:30:00Z")).as_deref(), Some("asia+europe"));
+    assert_eq!(cal.session(ms("2024-06-03T14:00:00Z")).as_deref(), Some("europe+us"));
+    assert_eq!(cal.session(ms("2024-06-03T22:00:00Z")), None);
+
+    let cal = Calendar::parse("late=22:00-02:00", "").unwrap();
+    assert_eq!(cal.session(ms("2024-06-03T23:59:00Z")).as_deref(), Some("late"));
+    assert_eq!(cal.session(ms("2024-06-04T01:00:00Z")).as_deref(), Some("late"));
+    assert_eq!(cal.session(ms("2024-06-04T02:00:00Z")), None);
+    assert!(!Calendar::parse("", "").unwrap().has_sessions());
+}
+
+#[test]
+fn maintenance_windows_fixed_and_weekly() {
+    // 2024-06-02 is a Sunday.
+    let cal = Calendar::parse("", "2024-06-01T00:00:00Z/2024-06-01T02:00:00Z, sun 23:00-01:00").unwrap();
+    assert!(cal.in_maintenance(ms("2024-06-01T01:59:59Z")));
+    assert!(!cal.in_maintenance(ms("2024-06-01T02:00:00Z")));
+    assert!(cal.in_maintenance(ms("2024-06-02T23:30:00Z")));
+    assert!(cal.in_maintenance(ms("2024-06-03T00:30:00Z")), "Sunday's slot runs into Monday");
+    assert!(!cal.in_maintenance(ms("2024-06-03T23:30:00Z")));
+    assert!(cal.overlaps_maintenance(ms("2024-06-02T22:00:00Z"), ms("2024-06-02T23:00:01Z")));
+    assert!(!cal.overlaps_maintenance(ms("2024-06-02T22:00:00Z"), ms("2024-06-02T23:00:00Z")));
+
+    assert!(Calendar::parse("", "someday 01:00-02:00").is_err());
+    assert!(Calendar::parse("x=25:00-26:00", "").is_err());
+}
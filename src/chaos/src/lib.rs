@@ -0,0 +1,121 @@
+//! Fault injection for resilience testing.
+//!
+//! Built only when a binary enables its `chaos` feature; otherwise each hook
+//! is an inlined no-op. When built in, faults are driven by env:
+//!
+//! - `CHAOS_DROP_PCT`: percent (0-100) of sink writes that fail with [`Fault`].
+//! - `CHAOS_LATENCY_MS`: extra produce latency, `max` or `min-max`, uniform.
+//! - `CHAOS_WS_DISCONNECT_SECS`: force a websocket disconnect this often.
+//!
+//! Every injected fault counts in `chaos_injected_total{fault}`.
+
+use std::fmt;
+
+/// The error an injected write failure surfaces as.
+#[derive(Debug, Clone, Copy)]
+pub struct Fault {
+    pub site: &'static str,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "chaos: injected write failure at {}", self.site)
+    }
+}
+
+impl std::error::Error for Fault {}
+
+#[cfg(feature = "enabled")]
+mod imp {
+    use std::sync::OnceLock;
+    use std::time::Duration;
+
+    use metrics::counter;
+    use rand::Rng;
+
+    use super::Fault;
+
+    #[derive(Debug, Default)]
+    struct Config {
+        drop_pct: f64,
+        latency_ms: Option<(u64, u64)>,
+        ws_disconnect: Option<Duration>,
+    }
+
+    fn parse_range(s: &str) -> Option<(u64, u64)> {
+        let (lo, hi) = match s.split_once('-') {
+            Some((lo, hi)) => (lo.trim().parse().ok()?, hi.trim().parse().ok()?),
+            None => (0, s.trim().parse().ok()?),
+        };
+        (hi > 0 && lo <= hi).then_some((lo, hi))
+    }
+
+    fn config() -> &'static Config {
+        static CONFIG: OnceLock<Config> = OnceLock::new();
+        CONFIG.get_or_init(|| {
+            let var = |k: &str| std::env::var(k).ok().filter(|v| !v.trim().is_empty());
+            let c = Config {
+                drop_pct: var("CHAOS_DROP_PCT").and_then(|v| v.trim().parse().ok()).unwrap_or(0.0f64).clamp(0.0, 100.0),
+                latency_ms: var("CHAOS_LATENCY_MS").and_then(|v| parse_range(&v)),
+                ws_disconnect: var("CHAOS_WS_DISCONNECT_SECS")
+                    .and_then(|v| v.trim().parse::<f64>().ok())
+                    .filter(|s| *s > 0.0)
+                    .map(Duration::from_secs_f64),
+            };
+            tracing::warn!(target: "chaos", drop_pct = c.drop_pct, latency_ms = ?c.latency_ms,
+                ws_disconnect = ?c.ws_disconnect, "chaos injection compiled in");
+            c
+        })
+    }
+
+    pub fn fault(site: &'static str) -> Result<(), Fault> {
+        let pct = config().drop_pct;
+        if pct > 0.0 && rand::rng().random::<f64>() * 100.0 < pct {
+            counter!("chaos_injected_total", "fault" => "drop").increment(1);
+            return Err(Fault { site });
+        }
+        Ok(())
+    }
+
+    pub async fn produce_delay() {
+        if let Some((lo, hi)) = config().latency_ms {
+            let ms = rand::rng().random_range(lo..=hi);
+            counter!("chaos_injected_total", "fault" => "latency").increment(1);
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+        }
+    }
+
+    pub async fn ws_disconnect() {
+        match config().ws_disconnect {
+            Some(every) => {
+                tokio::time::sleep(every).await;
+                counter!("chaos_injected_total", "fault" => "ws_disconnect").increment(1);
+            }
+            None => std::future::pending().await,
+        }
+    }
+}
+
+#[cfg(not(feature = "enabled"))]
+mod imp {
+    use super::Fault;
+
+    #[inline(always)]
+    pub fn fault(_site: &'static str) -> Result<(), Fault> {
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub async fn produce_delay() {}
+
+    pub async fn ws_disconnect() {
+        std::future::pending().await
+    }
+}
+
+/// Fail this sink write with probability `CHAOS_DROP_PCT`.
+pub use imp::fault;
+/// Sleep for a random `CHAOS_LATENCY_MS` before producing.
+pub use imp::produce_delay;
+/// Resolve when the current websocket should be forcibly dropped; never, unless configured.
+pub use imp::ws_disconnect;
@@ -0,0 +1,86 @@
+//! Wire codecs for normalized trades.
+//!
+//! [`Trade`] is the `ticks.norm` v1 shape; its JSON encoding is byte-for-byte
+//! what the producer has always written. MessagePack and Protobuf carry the
+//! same fields, so switching codecs never changes what a trade means.
+
+use anyhow::Result;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, prost::Message)]
+pub struct Trade {
+    #[prost(int64, tag = "1")]
+    pub ts_ms: i64,
+    #[prost(string, tag = "2")]
+    pub symbol: String,
+    #[prost(double, tag = "3")]
+    pub price: f64,
+    #[prost(double, tag = "4")]
+    pub qty: f64,
+    #[prost(int64, tag = "5")]
+    pub trade_id: i64,
+    #[prost(bool, tag = "6")]
+    pub is_bm: bool,
+    /// `ts_ms` in nanoseconds, from venues that send finer than milliseconds.
+    #[prost(int64, optional, tag = "7")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ts_ns: Option<i64>,
+    /// When the fetcher received the trade, in nanoseconds.
+    #[prost(int64, optional, tag = "8")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingest_ts_ns: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Json,
+    MsgPack,
+    Proto,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 3] = [Codec::Json, Codec::MsgPack, Codec::Proto];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Json => "json",
+            Codec::MsgPack => "msgpack",
+            Codec::Proto => "proto",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "json" => Some(Codec::Json),
+            "msgpack" | "messagepack" => Some(Codec::MsgPack),
+            "proto" | "protobuf" => Some(Codec::Proto),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Codec::Json => "application/json",
+            Codec::MsgPack => "application/msgpack",
+            Codec::Proto => "application/x-protobuf",
+        }
+    }
+
+    pub fn encode(self, t: &Trade) -> Result<Vec<u8>> {
+        Ok(match self {
+            Codec::Json => serde_json::to_vec(t)?,
+            // Named fields (a map), so decoders tolerate added fields like JSON does.
+            Codec::MsgPack => rmp_serde::to_vec_named(t)?,
+            Codec::Proto => t.encode_to_vec(),
+        })
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> Result<Trade> {
+        Ok(match self {
+            Codec::Json => serde_json::from_slice(bytes)?,
+            Codec::MsgPack => rmp_serde::from_slice(bytes)?,
+            Codec::Proto => Trade::decode(bytes)?,
+        })
+    }
+}
@@ -0,0 +1,38 @@
+use std::hint::black_box;
+
+use bus::{Delivery, Headers, MSG_ID, TS_PRODUCE_NS};
+use consumer::ilp::{to_ilp_line, NormTrade};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+const NORM: &str = r#"{"ts_ms":1739880000138,"symbol":"BTCUSDT","price":96123.45,"qty":0.00012,"trade_id":4567890123,"is_bm":true}"#;
+const MSG_ID_VALUE: &str = "359039b3-296e-4d48-ba18-9d316bdbdee0";
+
+fn bench_ilp(c: &mut Criterion) {
+    let t: NormTrade = serde_json::from_str(NORM).unwrap();
+    let mut g = c.benchmark_group("consumer");
+    g.throughput(Throughput::Elements(1));
+    g.bench_function("ilp_encode", |b| b.iter(|| to_ilp_line(black_box(&t), black_box(MSG_ID_VALUE))));
+    g.bench_function("parse_and_ilp_encode", |b| {
+        b.iter(|| {
+            let t: NormTrade = serde_json::from_str(black_box(NORM)).unwrap();
+            to_ilp_line(&t, MSG_ID_VALUE)
+        })
+    });
+    g.finish();
+}
+
+fn bench_headers(c: &mut Criterion) {
+    let headers = Headers::new()
+        .with(MSG_ID, MSG_ID_VALUE)
+        .with(TS_PRODUCE_NS, "1739880000148582245");
+    let msg = Delivery::detached("ticks.norm", Some("BTCUSDT"), NORM.as_bytes().to_vec(), headers);
+    c.bench_function("consumer/header_extract", |b| {
+        b.iter(|| {
+            let ts: Option<i64> = black_box(&msg).header(TS_PRODUCE_NS).and_then(|s| s.parse().ok());
+            (ts, black_box(&msg).header(MSG_ID).unwrap_or(""))
+        })
+    });
+}
+
+criterion_group!(benches, bench_ilp, bench_headers);
+criterion_main!(benches);
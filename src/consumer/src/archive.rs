@@ -0,0 +1,236 @@
+//! Parquet archive of written trades, one directory of rolling files.
+//!
+//! Rows are buffered in memory and written out as a row group every
+//! `row_group_rows`; a file is closed and renamed from `.parquet.tmp` to
+//! `.parquet` once it holds `roll_rows` rows or is `roll_every` old. Point
+//! an object-store sync (e.g. `aws s3 sync`) at the directory and only
+//! finished files get picked up.
+//!
+//! Writes run on a blocking thread. A row group that fails to write stays
+//! buffered for the next try.
+//!
+//! Buffered rows are not durable: offsets are committed once a batch is
+//! buffered, so a crash loses up to one row group (plus an unfinished file,
+//! which has no footer and can't be read). The archive is a convenience copy;
+//! QuestDB stays the source of truth.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use parquet::basic::Compression;
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use parquet::schema::types::Type;
+
+use crate::batch::Batch;
+use crate::sink::Sink;
+
+const SCHEMA: &str = "
+message trades {
+    REQUIRED INT64 ts_ms (TIMESTAMP(MILLIS, true));
+    REQUIRED BINARY symbol (STRING);
+    REQUIRED DOUBLE price;
+    REQUIRED DOUBLE qty;
+    REQUIRED INT64 trade_id;
+    REQUIRED BOOLEAN is_bm;
+    REQUIRED BINARY msg_id (STRING);
+}";
+
+#[derive(Debug, Clone)]
+pub struct ArchiveOptions {
+    pub dir: PathBuf,
+    pub row_group_rows: usize,
+    pub roll_rows: usize,
+    pub roll_every: Duration,
+}
+
+#[derive(Default)]
+struct Columns {
+    ts_ms: Vec<i64>,
+    symbol: Vec<ByteArray>,
+    price: Vec<f64>,
+    qty: Vec<f64>,
+    trade_id: Vec<i64>,
+    is_bm: Vec<bool>,
+    msg_id: Vec<ByteArray>,
+}
+
+impl Columns {
+    fn len(&self) -> usize {
+        self.ts_ms.len()
+    }
+
+    fn truncate(&mut self, n: usize) {
+        self.ts_ms.truncate(n);
+        self.symbol.truncate(n);
+        self.price.truncate(n);
+        self.qty.truncate(n);
+        self.trade_id.truncate(n);
+        self.is_bm.truncate(n);
+        self.msg_id.truncate(n);
+    }
+}
+
+struct OpenFile {
+    writer: SerializedFileWriter<File>,
+    tmp: PathBuf,
+    opened: Instant,
+    rows: usize,
+}
+
+pub struct ParquetSink {
+    dir: PathBuf,
+    /// Away on a blocking thread while it writes; lost only if that panics.
+    archive: Option<Archive>,
+}
+
+struct Archive {
+    opts: ArchiveOptions,
+    schema: Arc<Type>,
+    props: Arc<WriterProperties>,
+    buf: Columns,
+    file: Option<OpenFile>,
+    seq: u64,
+}
+
+impl ParquetSink {
+    pub fn new(opts: ArchiveOptions) -> Result<Self> {
+        std::fs::create_dir_all(&opts.dir).with_context(|| format!("creating {}", opts.dir.display()))?;
+        let schema = Arc::new(parse_message_type(SCHEMA)?);
+        let props = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).build());
+        let dir = opts.dir.clone();
+        Ok(Self { dir, archive: Some(Archive { opts, schema, props, buf: Columns::default(), file: None, seq: 0 }) })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn archive(&mut self) -> Result<&mut Archive> {
+        self.archive.as_mut().context("the parquet archive was lost to a panic while writing")
+    }
+
+    /// Run `f` on a blocking thread: parquet writes and renames block.
+    async fn blocking(&mut self, f: impl FnOnce(&mut Archive) -> Result<()> + Send + 'static) -> Result<()> {
+        let mut archive = self.archive.take().context("the parquet archive was lost to a panic while writing")?;
+        let (archive, res) = tokio::task::spawn_blocking(move || {
+            let res = f(&mut archive);
+            (archive, res)
+        }).await?;
+        self.archive = Some(archive);
+        res
+    }
+}
+
+impl Archive {
+    fn open(&mut self) -> Result<&mut OpenFile> {
+        if self.file.is_none() {
+            self.seq += 1;
+            let tmp = self.opts.dir.join(format!("trades-{}-{}.parquet.tmp", Utc::now().timestamp_millis(), self.seq));
+            let writer = SerializedFileWriter::new(File::create(&tmp)?, self.schema.clone(), self.props.clone())?;
+            self.file = Some(OpenFile { writer, tmp, opened: Instant::now(), rows: 0 });
+        }
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    /// Write the buffer as one row group of the current file. The rows stay
+    /// buffered until the group is written.
+    fn write_row_group(&mut self) -> Result<()> {
+        if self.buf.len() == 0 {
+            return Ok(());
+        }
+        self.open()?;
+        let Some(file) = self.file.as_mut() else { anyhow::bail!("no archive file open") };
+        let buf = &self.buf;
+        let mut rg = file.writer.next_row_group()?;
+        let mut i = 0;
+        while let Some(mut col) = rg.next_column()? {
+            match i {
+                0 => col.typed::<Int64Type>().write_batch(&buf.ts_ms, None, None)?,
+                1 => col.typed::<ByteArrayType>().write_batch(&buf.symbol, None, None)?,
+                2 => col.typed::<DoubleType>().write_batch(&buf.price, None, None)?,
+                3 => col.typed::<DoubleType>().write_batch(&buf.qty, None, None)?,
+                4 => col.typed::<Int64Type>().write_batch(&buf.trade_id, None, None)?,
+                5 => col.typed::<BoolType>().write_batch(&buf.is_bm, None, None)?,
+                _ => col.typed::<ByteArrayType>().write_batch(&buf.msg_id, None, None)?,
+            };
+            col.close()?;
+            i += 1;
+        }
+        rg.close()?;
+        file.rows += buf.len();
+        self.buf = Columns::default();
+        Ok(())
+    }
+
+    /// Write out the buffer and finish the current file, if any.
+    fn roll(&mut self) -> Result<()> {
+        self.write_row_group()?;
+        if let Some(f) = self.file.take() {
+            f.writer.close()?;
+            let done = f.tmp.with_extension("");
+            std::fs::rename(&f.tmp, &done)?;
+            tracing::info!(target="consumer", file=%done.display(), rows=f.rows, "archive file closed");
+        }
+        Ok(())
+    }
+
+    fn roll_due(&self) -> bool {
+        self.file.as_ref().is_some_and(|f| f.rows >= self.opts.roll_rows || f.opened.elapsed() >= self.opts.roll_every)
+    }
+
+    fn flush_due(&mut self) -> Result<()> {
+        if self.buf.len() >= self.opts.row_group_rows {
+            self.write_row_group()?;
+        }
+        if self.roll_due() {
+            self.roll()?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for ParquetSink {
+    fn name(&self) -> &str {
+        "parquet"
+    }
+
+    async fn write(&mut self, batch: &Batch) -> Result<()> {
+        let archive = self.archive()?;
+        let before = archive.buf.len();
+        for r in batch.iter_rows() {
+            let t = &r.trade;
+            archive.buf.ts_ms.push(t.ts_ms);
+            archive.buf.symbol.push(ByteArray::from(t.symbol.as_str()));
+            archive.buf.price.push(t.price);
+            archive.buf.qty.push(t.qty);
+            archive.buf.trade_id.push(t.trade_id);
+            archive.buf.is_bm.push(t.is_bm);
+            archive.buf.msg_id.push(ByteArray::from(r.msg_id.as_str()));
+        }
+        self.blocking(move |archive| {
+            if let Err(e) = archive.flush_due() {
+                // The file is unusable now; start a fresh one, keeping earlier rows
+                // still in memory but not this batch, which the caller will retry.
+                if let Some(f) = archive.file.take() {
+                    tracing::error!(target="consumer", file=%f.tmp.display(), "abandoning archive file after write error");
+                }
+                archive.buf.truncate(before);
+                return Err(e);
+            }
+            Ok(())
+        }).await
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        self.blocking(Archive::roll).await
+    }
+}
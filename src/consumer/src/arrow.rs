@@ -0,0 +1,312 @@
+//! Just enough of the Arrow IPC streaming format to send an export as Arrow
+//! record batches: pyarrow's `ipc.open_stream` and pandas read it without
+//! a conversion step.
+//!
+//! A stream is a schema message, one record batch per page, and an
+//! end-of-stream marker. Each message is a `0xFFFFFFFF` continuation, the
+//! metadata's length, the metadata (an `org.apache.arrow.flatbuf.Message`
+//! flatbuffer) padded to 8 bytes, then the batch's buffers. The flatbuffers
+//! are written front to back by [`Table::finish`], which only has to cover
+//! the handful of tables these two messages use.
+//!
+//! Columns are typed as in [`ParquetExport`](crate::export::ParquetExport):
+//! timestamps as µs since the epoch in UTC, and every column nullable.
+
+use std::io::Write;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::export::{micros, Dataset, Kind};
+
+/// Metadata version V5.
+const V5: i16 = 4;
+
+/// Members of the `MessageHeader` union.
+const SCHEMA: u8 = 1;
+const RECORD_BATCH: u8 = 3;
+
+/// Members of the `Type` union.
+const INT: u8 = 2;
+const FLOATING_POINT: u8 = 3;
+const UTF8: u8 = 5;
+const BOOL: u8 = 6;
+const TIMESTAMP: u8 = 10;
+
+const DOUBLE: i16 = 2;
+const MICROSECOND: i16 = 2;
+
+/// One field of a flatbuffer table.
+enum Field {
+    U8(u8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Str(String),
+    Table(Table),
+    Tables(Vec<Table>),
+    /// A vector of structs of two `long`s (`FieldNode`, `Buffer`).
+    Pairs(Vec<(i64, i64)>),
+}
+
+impl Field {
+    /// Its size inline in the table; every offset is 4 bytes.
+    fn width(&self) -> usize {
+        match self {
+            Field::U8(_) => 1,
+            Field::I16(_) => 2,
+            Field::I64(_) => 8,
+            _ => 4,
+        }
+    }
+}
+
+/// A flatbuffer table as its fields, by slot.
+#[derive(Default)]
+struct Table(Vec<(usize, Field)>);
+
+fn pad(buf: &mut Vec<u8>, align: usize, rem: usize) {
+    while buf.len() % align != rem {
+        buf.push(0);
+    }
+}
+
+fn patch_offset(buf: &mut [u8], at: usize, to: usize) {
+    buf[at..at + 4].copy_from_slice(&((to - at) as u32).to_le_bytes());
+}
+
+impl Table {
+    fn with(mut self, slot: usize, field: Field) -> Self {
+        self.0.push((slot, field));
+        self
+    }
+
+    /// The whole flatbuffer, with this table as its root, padded to 8 bytes.
+    fn finish(&self) -> Vec<u8> {
+        let mut buf = vec![0; 4];
+        let root = self.write(&mut buf);
+        patch_offset(&mut buf, 0, root);
+        pad(&mut buf, 8, 0);
+        buf
+    }
+
+    /// Write the vtable, the table, then whatever its offsets point at, and
+    /// return where the table starts. Everything it points to comes after
+    /// it, as offsets are unsigned.
+    fn write(&self, buf: &mut Vec<u8>) -> usize {
+        let slots = self.0.iter().map(|(s, _)| s + 1).max().unwrap_or(0);
+        pad(buf, 4, 0);
+        let vtable = buf.len();
+        buf.resize(vtable + 4 + 2 * slots, 0);
+        // 8-aligned, so a field aligned within the table is aligned in the buffer.
+        pad(buf, 8, 0);
+        let start = buf.len();
+        buf.extend_from_slice(&((start - vtable) as i32).to_le_bytes());
+        let mut offsets = Vec::new();
+        for (slot, field) in &self.0 {
+            pad(buf, field.width(), 0);
+            let at = buf.len();
+            buf[vtable + 4 + 2 * slot..vtable + 6 + 2 * slot].copy_from_slice(&((at - start) as u16).to_le_bytes());
+            match field {
+                Field::U8(v) => buf.push(*v),
+                Field::I16(v) => buf.extend_from_slice(&v.to_le_bytes()),
+                Field::I32(v) => buf.extend_from_slice(&v.to_le_bytes()),
+                Field::I64(v) => buf.extend_from_slice(&v.to_le_bytes()),
+                _ => {
+                    buf.extend_from_slice(&[0; 4]);
+                    offsets.push((at, field));
+                }
+            }
+        }
+        let size = buf.len() - start;
+        buf[vtable..vtable + 2].copy_from_slice(&((4 + 2 * slots) as u16).to_le_bytes());
+        buf[vtable + 2..vtable + 4].copy_from_slice(&(size as u16).to_le_bytes());
+
+        for (at, field) in offsets {
+            let to = match field {
+                Field::Str(s) => {
+                    pad(buf, 4, 0);
+                    let to = buf.len();
+                    buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                    buf.extend_from_slice(s.as_bytes());
+                    buf.push(0);
+                    to
+                }
+                Field::Table(t) => t.write(buf),
+                Field::Tables(ts) => {
+                    pad(buf, 4, 0);
+                    let to = buf.len();
+                    buf.extend_from_slice(&(ts.len() as u32).to_le_bytes());
+                    buf.resize(to + 4 + 4 * ts.len(), 0);
+                    for (i, t) in ts.iter().enumerate() {
+                        let table = t.write(buf);
+                        patch_offset(buf, to + 4 + 4 * i, table);
+                    }
+                    to
+                }
+                Field::Pairs(ps) => {
+                    // The structs after the length must be 8-aligned.
+                    pad(buf, 8, 4);
+                    let to = buf.len();
+                    buf.extend_from_slice(&(ps.len() as u32).to_le_bytes());
+                    for (a, b) in ps {
+                        buf.extend_from_slice(&a.to_le_bytes());
+                        buf.extend_from_slice(&b.to_le_bytes());
+                    }
+                    to
+                }
+                _ => unreachable!("inline field"),
+            };
+            patch_offset(buf, at, to);
+        }
+        start
+    }
+}
+
+/// An encapsulated message: continuation, metadata length, metadata, body.
+fn message(out: &mut impl Write, header_type: u8, header: Table, body: &[u8]) -> Result<()> {
+    let meta = Table::default()
+        .with(0, Field::I16(V5))
+        .with(1, Field::U8(header_type))
+        .with(2, Field::Table(header))
+        .with(3, Field::I64(body.len() as i64))
+        .finish();
+    out.write_all(&u32::MAX.to_le_bytes())?;
+    out.write_all(&(meta.len() as i32).to_le_bytes())?;
+    out.write_all(&meta)?;
+    out.write_all(body)?;
+    Ok(())
+}
+
+fn field_type(kind: Kind) -> (u8, Table) {
+    match kind {
+        Kind::Timestamp => (TIMESTAMP, Table::default().with(0, Field::I16(MICROSECOND)).with(1, Field::Str("UTC".into()))),
+        Kind::Str => (UTF8, Table::default()),
+        Kind::Double => (FLOATING_POINT, Table::default().with(0, Field::I16(DOUBLE))),
+        Kind::Long => (INT, Table::default().with(0, Field::I32(64)).with(1, Field::U8(1))),
+        Kind::Bool => (BOOL, Table::default()),
+    }
+}
+
+/// A record batch's body as it's built: each buffer 8-aligned, and where it sits.
+#[derive(Default)]
+struct Body {
+    bytes: Vec<u8>,
+    nodes: Vec<(i64, i64)>,
+    buffers: Vec<(i64, i64)>,
+}
+
+impl Body {
+    fn buffer(&mut self, b: &[u8]) {
+        self.buffers.push((self.bytes.len() as i64, b.len() as i64));
+        self.bytes.extend_from_slice(b);
+        pad(&mut self.bytes, 8, 0);
+    }
+
+    /// A column's node and its validity bitmap; its values follow.
+    fn column(&mut self, present: &[bool]) {
+        let nulls = present.iter().filter(|p| !**p).count();
+        self.nodes.push((present.len() as i64, nulls as i64));
+        self.buffer(&bitmap(present));
+    }
+}
+
+/// Arrow's bit packing: least significant bit first.
+fn bitmap(flags: &[bool]) -> Vec<u8> {
+    let mut bits = vec![0u8; flags.len().div_ceil(8)];
+    for (i, _) in flags.iter().enumerate().filter(|(_, f)| **f) {
+        bits[i / 8] |= 1 << (i % 8);
+    }
+    bits
+}
+
+/// Column `i` of `rows` with a slot per row, `T::default()` for nulls.
+fn slots<T: Default>(rows: &[Vec<Value>], i: usize, get: impl Fn(&Value) -> Option<T>) -> (Vec<T>, Vec<bool>) {
+    rows.iter()
+        .map(|r| match r.get(i).and_then(&get) {
+            Some(v) => (v, true),
+            None => (T::default(), false),
+        })
+        .unzip()
+}
+
+/// An Arrow IPC stream written one page (record batch) at a time into `W`.
+pub struct ArrowExport<W: Write> {
+    dataset: Dataset,
+    out: W,
+}
+
+impl<W: Write> ArrowExport<W> {
+    /// Starts the stream with the dataset's schema.
+    pub fn new(mut out: W, dataset: Dataset) -> Result<Self> {
+        let fields = dataset.columns().iter()
+            .map(|(name, kind)| {
+                let (type_type, ty) = field_type(*kind);
+                Table::default()
+                    .with(0, Field::Str(name.to_string()))
+                    .with(1, Field::U8(1))
+                    .with(2, Field::U8(type_type))
+                    .with(3, Field::Table(ty))
+                    .with(5, Field::Tables(Vec::new()))
+            })
+            .collect();
+        let schema = Table::default().with(0, Field::I16(0)).with(1, Field::Tables(fields));
+        message(&mut out, SCHEMA, schema, &[])?;
+        Ok(Self { dataset, out })
+    }
+
+    /// Write `rows`, in [`Range::sql`](crate::export::Range::sql) column order, as one record batch.
+    pub fn write(&mut self, rows: &[Vec<Value>]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut body = Body::default();
+        for (i, (_, kind)) in self.dataset.columns().iter().enumerate() {
+            match kind {
+                Kind::Timestamp | Kind::Long => {
+                    let get = if *kind == Kind::Timestamp { micros } else { Value::as_i64 };
+                    let (values, present) = slots(rows, i, get);
+                    body.column(&present);
+                    body.buffer(&values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>());
+                }
+                Kind::Double => {
+                    let (values, present) = slots(rows, i, Value::as_f64);
+                    body.column(&present);
+                    body.buffer(&values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>());
+                }
+                Kind::Bool => {
+                    let (values, present) = slots(rows, i, Value::as_bool);
+                    body.column(&present);
+                    body.buffer(&bitmap(&values));
+                }
+                Kind::Str => {
+                    let (values, present) = slots(rows, i, |v| v.as_str().map(str::to_string));
+                    body.column(&present);
+                    let mut offsets = vec![0i32];
+                    for v in &values {
+                        offsets.push(offsets[offsets.len() - 1] + v.len() as i32);
+                    }
+                    body.buffer(&offsets.iter().flat_map(|o| o.to_le_bytes()).collect::<Vec<_>>());
+                    body.buffer(values.concat().as_bytes());
+                }
+            }
+        }
+        let batch = Table::default()
+            .with(0, Field::I64(rows.len() as i64))
+            .with(1, Field::Pairs(body.nodes))
+            .with(2, Field::Pairs(body.buffers));
+        message(&mut self.out, RECORD_BATCH, batch, &body.bytes)
+    }
+
+    /// The bytes written so far; for streaming the batches out while they're written.
+    pub fn out_mut(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    /// Write the end-of-stream marker and hand back `W`.
+    pub fn finish(mut self) -> Result<W> {
+        self.out.write_all(&u32::MAX.to_le_bytes())?;
+        self.out.write_all(&0i32.to_le_bytes())?;
+        Ok(self.out)
+    }
+}
@@ -0,0 +1,317 @@
+//! Avro trades in the Confluent wire format, with their writer schemas
+//! from a schema registry.
+//!
+//! A payload is a zero magic byte, the writer schema's registry id (4 bytes,
+//! big-endian) and the record in Avro's binary encoding. The record is read
+//! by its writer schema into the JSON it stands for, then decoded as a
+//! `ticks.norm` trade, so the field names, aliases and optional fields are
+//! the JSON ones: a writer schema only has to use the usual field names.
+//!
+//! Schemas are fetched from `SCHEMA_REGISTRY_URL` (`GET /schemas/ids/{id}`)
+//! before a batch is decoded, and kept: ids are never reused. An id the
+//! registry doesn't have, or that isn't an Avro schema, is remembered too,
+//! and its messages are dropped as data errors. A registry that can't be
+//! reached fails the flush before the batch is committed, so its trades are
+//! read again after a restart rather than dropped.
+
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use metrics::counter;
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+
+/// An Avro schema, resolved: named types are inlined where they're used.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schema {
+    Null,
+    Boolean,
+    Int,
+    Long,
+    Float,
+    Double,
+    Bytes,
+    String,
+    Record(Vec<(String, Schema)>),
+    Enum(Vec<String>),
+    Fixed(usize),
+    Array(Box<Schema>),
+    Map(Box<Schema>),
+    Union(Vec<Schema>),
+}
+
+impl Schema {
+    /// A schema in Avro's JSON form, as the registry returns it.
+    pub fn parse(json: &str) -> Result<Self> {
+        let v: Value = serde_json::from_str(json).context("Avro schema isn't JSON")?;
+        Self::from_json(&v, &mut HashMap::new())
+    }
+
+    fn from_json(v: &Value, named: &mut HashMap<String, Schema>) -> Result<Self> {
+        match v {
+            Value::String(name) => Self::named(name, named),
+            Value::Array(branches) => Ok(Schema::Union(branches.iter().map(|b| Self::from_json(b, named)).collect::<Result<_>>()?)),
+            Value::Object(o) => {
+                let kind = o.get("type").context("Avro schema object without a type")?;
+                let Some(kind) = kind.as_str() else { return Self::from_json(kind, named) };
+                let schema = match kind {
+                    "record" | "error" => {
+                        let fields = o.get("fields").and_then(Value::as_array).context("record without fields")?;
+                        Schema::Record(fields.iter()
+                            .map(|f| {
+                                let name = f.get("name").and_then(Value::as_str).context("record field without a name")?;
+                                let schema = Self::from_json(f.get("type").context("record field without a type")?, named)?;
+                                Ok((name.to_string(), schema))
+                            })
+                            .collect::<Result<_>>()?)
+                    }
+                    "enum" => Schema::Enum(o.get("symbols").and_then(Value::as_array).context("enum without symbols")?
+                        .iter().map(|s| s.as_str().map(String::from).context("enum symbol isn't a string")).collect::<Result<_>>()?),
+                    "fixed" => Schema::Fixed(o.get("size").and_then(Value::as_u64).context("fixed without a size")? as usize),
+                    "array" => Schema::Array(Box::new(Self::from_json(o.get("items").context("array without items")?, named)?)),
+                    "map" => Schema::Map(Box::new(Self::from_json(o.get("values").context("map without values")?, named)?)),
+                    // A primitive, maybe with a logical type, which reads as the primitive.
+                    other => return Self::named(other, named),
+                };
+                if let Some(name) = o.get("name").and_then(Value::as_str) {
+                    named.insert(name.to_string(), schema.clone());
+                    if let Some(ns) = o.get("namespace").and_then(Value::as_str) {
+                        named.insert(format!("{}.{}", ns, name), schema.clone());
+                    }
+                }
+                Ok(schema)
+            }
+            other => anyhow::bail!("not an Avro schema: {}", other),
+        }
+    }
+
+    fn named(name: &str, named: &HashMap<String, Schema>) -> Result<Self> {
+        Ok(match name {
+            "null" => Schema::Null,
+            "boolean" => Schema::Boolean,
+            "int" => Schema::Int,
+            "long" => Schema::Long,
+            "float" => Schema::Float,
+            "double" => Schema::Double,
+            "bytes" => Schema::Bytes,
+            "string" => Schema::String,
+            // Only types defined earlier; a record can't contain itself here.
+            other => named.get(other).cloned().with_context(|| format!("unknown Avro type {:?}", other))?,
+        })
+    }
+}
+
+/// The registry id and Avro body of a Confluent-framed payload.
+pub fn unframe(payload: &[u8]) -> Result<(u32, &[u8])> {
+    match payload {
+        [0, a, b, c, d, body @ ..] => Ok((u32::from_be_bytes([*a, *b, *c, *d]), body)),
+        [_, ..] => anyhow::bail!("no Confluent magic byte"),
+        [] => anyhow::bail!("empty Avro payload"),
+    }
+}
+
+/// `body` read by `schema` into JSON: records as objects, unions as their
+/// branch, bytes and fixed as arrays of numbers. Trailing bytes are an error.
+pub fn decode(schema: &Schema, body: &[u8]) -> Result<Value> {
+    let mut r = Reader { buf: body };
+    let v = r.value(schema)?;
+    anyhow::ensure!(r.buf.is_empty(), "{} bytes left after the record", r.buf.len());
+    Ok(v)
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        anyhow::ensure!(n <= self.buf.len(), "truncated Avro record");
+        let (head, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(head)
+    }
+
+    /// A zigzag varint, as ints and longs are written.
+    fn long(&mut self) -> Result<i64> {
+        let mut n: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let b = self.take(1)?[0];
+            n |= u64::from(b & 0x7f) << shift;
+            if b & 0x80 == 0 {
+                return Ok((n >> 1) as i64 ^ -((n & 1) as i64));
+            }
+        }
+        anyhow::bail!("Avro varint longer than 10 bytes")
+    }
+
+    /// A length or count, which mustn't claim more than is left.
+    fn len(&mut self) -> Result<usize> {
+        let n = self.long()?;
+        anyhow::ensure!(n >= 0 && n as usize <= self.buf.len(), "bad Avro length {}", n);
+        Ok(n as usize)
+    }
+
+    fn value(&mut self, schema: &Schema) -> Result<Value> {
+        Ok(match schema {
+            Schema::Null => Value::Null,
+            Schema::Boolean => Value::Bool(self.take(1)?[0] != 0),
+            Schema::Int | Schema::Long => Value::from(self.long()?),
+            Schema::Float => float(f32::from_le_bytes(self.take(4)?.try_into()?) as f64),
+            Schema::Double => float(f64::from_le_bytes(self.take(8)?.try_into()?)),
+            Schema::Bytes => {
+                let n = self.len()?;
+                Value::from(self.take(n)?.to_vec())
+            }
+            Schema::Fixed(n) => Value::from(self.take(*n)?.to_vec()),
+            Schema::String => {
+                let n = self.len()?;
+                Value::String(std::str::from_utf8(self.take(n)?).context("Avro string isn't UTF-8")?.to_string())
+            }
+            Schema::Record(fields) => {
+                let mut o = Map::new();
+                for (name, schema) in fields {
+                    o.insert(name.clone(), self.value(schema)?);
+                }
+                Value::Object(o)
+            }
+            Schema::Enum(symbols) => {
+                let i = self.long()?;
+                Value::String(usize::try_from(i).ok().and_then(|i| symbols.get(i)).with_context(|| format!("enum index {} out of range", i))?.clone())
+            }
+            Schema::Union(branches) => {
+                let i = self.long()?;
+                self.value(usize::try_from(i).ok().and_then(|i| branches.get(i)).with_context(|| format!("union branch {} out of range", i))?)?
+            }
+            Schema::Array(items) => Value::Array(self.blocks(|r| r.value(items))?),
+            Schema::Map(values) => {
+                let entries = self.blocks(|r| {
+                    let n = r.len()?;
+                    let key = std::str::from_utf8(r.take(n)?).context("Avro map key isn't UTF-8")?.to_string();
+                    Ok((key, r.value(values)?))
+                })?;
+                Value::Object(entries.into_iter().collect())
+            }
+        })
+    }
+
+    /// Array and map items: counted blocks up to an empty one. A negative
+    /// count is followed by the block's size in bytes.
+    fn blocks<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        let mut out = Vec::new();
+        loop {
+            let count = self.long()?;
+            if count == 0 {
+                return Ok(out);
+            }
+            if count < 0 {
+                self.long()?;
+            }
+            for _ in 0..count.unsigned_abs() {
+                anyhow::ensure!(!self.buf.is_empty(), "truncated Avro block");
+                out.push(item(self)?);
+            }
+        }
+    }
+}
+
+/// NaN and the infinities have no JSON form; they read as null.
+fn float(f: f64) -> Value {
+    Number::from_f64(f).map_or(Value::Null, Value::Number)
+}
+
+#[derive(Deserialize)]
+struct ById {
+    schema: String,
+    #[serde(rename = "schemaType", default)]
+    schema_type: Option<String>,
+}
+
+/// Writer schemas by registry id, fetched once each.
+pub struct Registry {
+    http: reqwest::Client,
+    url: String,
+    basic_auth: Option<(String, String)>,
+    /// `None` for an id the registry doesn't have as Avro.
+    schemas: RwLock<HashMap<u32, Option<Arc<Schema>>>>,
+}
+
+impl Registry {
+    /// `SCHEMA_REGISTRY_URL` (unset or empty for none, when Avro trades are
+    /// refused) and the `user:password` secret `SCHEMA_REGISTRY_BASIC_AUTH`.
+    pub async fn from_env() -> Result<Option<Self>> {
+        let Some(url) = std::env::var("SCHEMA_REGISTRY_URL").ok().filter(|v| !v.trim().is_empty()) else { return Ok(None) };
+        let basic_auth = match secrets::Secrets::from_env()?.get("SCHEMA_REGISTRY_BASIC_AUTH").await? {
+            Some(s) => {
+                let (user, password) = s.expose().split_once(':').context("SCHEMA_REGISTRY_BASIC_AUTH isn't user:password")?;
+                Some((user.to_string(), password.to_string()))
+            }
+            None => None,
+        };
+        Ok(Some(Self::new(&url, basic_auth)))
+    }
+
+    pub fn new(url: &str, basic_auth: Option<(String, String)>) -> Self {
+        Self {
+            http: reqwest::Client::builder().timeout(Duration::from_secs(10)).build().unwrap_or_default(),
+            url: url.trim_end_matches('/').to_string(),
+            basic_auth,
+            schemas: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// The schema for `id`, if it has been fetched and is Avro.
+    pub fn get(&self, id: u32) -> Option<Arc<Schema>> {
+        self.schemas.read().unwrap_or_else(PoisonError::into_inner).get(&id).cloned().flatten()
+    }
+
+    /// Whether `id` has been looked up, found or not.
+    pub fn known(&self, id: u32) -> bool {
+        self.schemas.read().unwrap_or_else(PoisonError::into_inner).contains_key(&id)
+    }
+
+    /// Keep `schema` as `id`'s, as a fetch would.
+    pub fn insert(&self, id: u32, schema: Option<Schema>) {
+        self.schemas.write().unwrap_or_else(PoisonError::into_inner).insert(id, schema.map(Arc::new));
+    }
+
+    /// Fetch each of `ids` not looked up yet. Only a registry that can't be
+    /// reached (or answers with an error other than 404) is an error.
+    pub async fn fetch(&self, ids: impl IntoIterator<Item = u32>) -> Result<()> {
+        for id in ids {
+            if self.known(id) {
+                continue;
+            }
+            let schema = self.lookup(id).await.with_context(|| format!("schema registry {}: schema {}", self.url, id))?;
+            counter!("avro_schemas_fetched_total", "found" => if schema.is_some() { "true" } else { "false" }).increment(1);
+            self.insert(id, schema);
+        }
+        Ok(())
+    }
+
+    async fn lookup(&self, id: u32) -> Result<Option<Schema>> {
+        let req = self.http.get(format!("{}/schemas/ids/{}", self.url, id));
+        let req = match &self.basic_auth {
+            Some((user, password)) => req.basic_auth(user, Some(password)),
+            None => req,
+        };
+        let resp = req.send().await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            tracing::warn!(target: "consumer", id, "Avro schema id not in the registry; its trades are dropped");
+            return Ok(None);
+        }
+        let by_id: ById = resp.error_for_status()?.json().await?;
+        if by_id.schema_type.as_deref().is_some_and(|t| t != "AVRO") {
+            tracing::warn!(target: "consumer", id, schema_type = by_id.schema_type.as_deref(), "schema id isn't Avro; its trades are dropped");
+            return Ok(None);
+        }
+        match Schema::parse(&by_id.schema) {
+            Ok(s) => Ok(Some(s)),
+            Err(e) => {
+                tracing::warn!(target: "consumer", id, error = %format!("{:#}", e), "unreadable Avro schema; its trades are dropped");
+                Ok(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Trade-driven bars, López de Prado style: a bar closes on what the trades
+//! did rather than on the clock, so busy periods get more bars.
+//!
+//! `TRADE_BARS` picks a kind per symbol:
+//!
+//! - `tick:N` closes every `N` trades.
+//! - `imbalance:N` signs each trade with the tick rule (+1 on an uptick, -1
+//!   on a downtick, the previous sign when unchanged) and closes once the
+//!   running sum `θ` reaches, in absolute value, the imbalance the previous
+//!   bars lead us to expect: `E[T] · |E[b]|`. `E[T]` (trades per bar) and
+//!   `E[b]` (mean sign per bar) are exponentially weighted over closed bars
+//!   with weight `alpha`. The first bar is a tick bar of `N` trades, which
+//!   seeds both. `E[T]` stays within a factor of 4 of `N` and no bar runs
+//!   past `4N` trades, so a run of very one-sided or very balanced trading
+//!   can't make the bars collapse to single trades or stop closing.
+//!
+//! Open bars aren't persisted: after a restart each symbol starts a new one.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+
+use crate::ilp::{escape_tag, NormTrade};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Tick(u64),
+    /// Trades in the warm-up bar, and the centre of `E[T]`'s range.
+    Imbalance(u64),
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Tick(_) => "tick",
+            Kind::Imbalance(_) => "imbalance",
+        }
+    }
+
+    /// The `N` of `tick:N` or `imbalance:N`.
+    pub fn size(&self) -> u64 {
+        match self {
+            Kind::Tick(n) | Kind::Imbalance(n) => *n,
+        }
+    }
+}
+
+/// Which kind of bar each symbol gets; symbols without one get none.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Spec {
+    default: Option<Kind>,
+    symbols: HashMap<String, Kind>,
+}
+
+impl Spec {
+    /// `*=tick:1000,BTCUSDT=imbalance:200`: `*` is every symbol not listed.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut out = Self::default();
+        for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (symbol, kind) = item.split_once('=').with_context(|| format!("{:?} is not symbol=kind:trades", item))?;
+            let (kind, n) = kind.split_once(':').with_context(|| format!("{:?} has no trade count", item))?;
+            let n: u64 = n.trim().parse().with_context(|| format!("bad trade count in {:?}", item))?;
+            anyhow::ensure!(n > 0, "trade count must be positive in {:?}", item);
+            let kind = match kind.trim() {
+                "tick" => Kind::Tick(n),
+                "imbalance" => Kind::Imbalance(n),
+                other => anyhow::bail!("unknown bar kind {:?} in {:?} (expected tick|imbalance)", other, item),
+            };
+            match symbol.trim() {
+                "*" => out.default = Some(kind),
+                s => { out.symbols.insert(s.to_uppercase(), kind); }
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.symbols.is_empty()
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<Kind> {
+        self.symbols.get(symbol).copied().or(self.default)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TradeBar {
+    pub symbol: String,
+    /// `tick` or `imbalance`.
+    pub kind: String,
+    /// The kind's `N`: with the kind, the symbol and the start, the bar's upsert key.
+    pub size: u64,
+    /// First trade's time, ms since the epoch.
+    pub ts_ms: i64,
+    /// Last trade's time.
+    pub close_ts_ms: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub trades: u64,
+    /// Sum of the trades' tick-rule signs.
+    pub imbalance: i64,
+}
+
+impl TradeBar {
+    pub fn to_ilp_line(&self, table: &str) -> String {
+        format!(
+            "{},symbol={},kind={} size={}i,open={},high={},low={},close={},volume={},trades={}i,imbalance={}i,close_ts_ms={}i {}",
+            escape_tag(table),
+            escape_tag(&self.symbol),
+            self.kind,
+            self.size,
+            self.open,
+            self.high,
+            self.low,
+            self.close,
+            self.volume,
+            self.trades,
+            self.imbalance,
+            self.close_ts_ms,
+            (self.ts_ms as i128) * 1_000_000i128
+        )
+    }
+}
+
+struct State {
+    kind: Kind,
+    bar: Option<TradeBar>,
+    last_price: Option<f64>,
+    last_sign: i64,
+    /// `E[T]` and `E[b]`; `None` until the warm-up bar closes.
+    expected: Option<(f64, f64)>,
+}
+
+impl State {
+    fn sign(&mut self, t: &NormTrade) -> i64 {
+        let sign = match self.last_price {
+            Some(p) if t.price > p => 1,
+            Some(p) if t.price < p => -1,
+            Some(_) if self.last_sign != 0 => self.last_sign,
+            // No tick to go by yet: the taker's side.
+            _ => if t.is_bm { -1 } else { 1 },
+        };
+        self.last_price = Some(t.price);
+        self.last_sign = sign;
+        sign
+    }
+
+    fn done(&self) -> bool {
+        let Some(bar) = &self.bar else { return false };
+        match (self.kind, self.expected) {
+            (Kind::Tick(n), _) | (Kind::Imbalance(n), None) => bar.trades >= n,
+            (Kind::Imbalance(n), Some((ticks, sign))) => {
+                bar.trades >= 4 * n || bar.imbalance.unsigned_abs() as f64 >= (ticks * sign.abs()).max(1.0)
+            }
+        }
+    }
+
+    fn learn(&mut self, bar: &TradeBar, alpha: f64) {
+        let Kind::Imbalance(n) = self.kind else { return };
+        let (trades, mean) = (bar.trades as f64, bar.imbalance as f64 / bar.trades as f64);
+        let (ticks, sign) = match self.expected {
+            None => (trades, mean),
+            Some((t, s)) => (alpha * trades + (1.0 - alpha) * t, alpha * mean + (1.0 - alpha) * s),
+        };
+        self.expected = Some((ticks.clamp(n as f64 / 4.0, n as f64 * 4.0), sign));
+    }
+}
+
+pub struct TradeBars {
+    spec: Spec,
+    alpha: f64,
+    symbols: HashMap<String, State>,
+    closed: Vec<TradeBar>,
+}
+
+impl TradeBars {
+    pub fn new(spec: Spec, alpha: f64) -> Self {
+        Self { spec, alpha: alpha.clamp(0.0, 1.0), symbols: HashMap::new(), closed: Vec::new() }
+    }
+
+    /// Fold a trade into its symbol's open bar, closing the bar if the trade completes it.
+    pub fn add(&mut self, t: &NormTrade) {
+        if !self.symbols.contains_key(&t.symbol) {
+            let Some(kind) = self.spec.get(&t.symbol) else { return };
+            self.symbols.insert(t.symbol.clone(), State { kind, bar: None, last_price: None, last_sign: 0, expected: None });
+        }
+        let Some(s) = self.symbols.get_mut(&t.symbol) else { return };
+        let sign = s.sign(t);
+        let bar = s.bar.get_or_insert_with(|| TradeBar {
+            symbol: t.symbol.clone(),
+            kind: s.kind.name().to_string(),
+            size: s.kind.size(),
+            ts_ms: t.ts_ms,
+            close_ts_ms: t.ts_ms,
+            open: t.price,
+            high: t.price,
+            low: t.price,
+            close: t.price,
+            volume: 0.0,
+            trades: 0,
+            imbalance: 0,
+        });
+        bar.high = bar.high.max(t.price);
+        bar.low = bar.low.min(t.price);
+        bar.close = t.price;
+        bar.close_ts_ms = t.ts_ms;
+        bar.volume += t.qty;
+        bar.trades += 1;
+        bar.imbalance += sign;
+        if let Some(bar) = s.done().then(|| s.bar.take()).flatten() {
+            s.learn(&bar, self.alpha);
+            counter!("trade_bars_total", "kind" => s.kind.name()).increment(1);
+            self.closed.push(bar);
+        }
+    }
+
+    /// Bars closed since last asked, in the order they closed.
+    pub fn take_closed(&mut self) -> Vec<TradeBar> {
+        std::mem::take(&mut self.closed)
+    }
+
+    /// `(E[T], E[b])` for an imbalance-bar symbol once its warm-up bar has closed.
+    pub fn expected(&self, symbol: &str) -> Option<(f64, f64)> {
+        self.symbols.get(symbol)?.expected
+    }
+}
@@ -0,0 +1,194 @@
+//! Coalesce a burst of trades into one multi-line ILP write and one commit.
+//!
+//! Lines are grouped per symbol (first-seen order, arrival order within a
+//! symbol), which keeps each symbol's rows contiguous for QuestDB. Every
+//! delivery taken into the batch, including ones that produced no row, is
+//! handed to the committer once the write succeeds.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use bus::Delivery;
+use metrics::histogram;
+
+use crate::ilp::{to_ilp_line, NormTrade};
+
+#[derive(Debug, Default)]
+struct Group {
+    symbol: String,
+    lines: String,
+    rows: usize,
+}
+
+/// A written row, for watermark tracking once the flush lands.
+#[derive(Debug, Clone, Copy)]
+pub struct Mark<'a> {
+    pub topic: &'a str,
+    pub partition: Option<i32>,
+    /// Same as `trade_id` unless the row is an aggregated trade.
+    pub first_trade_id: i64,
+    pub trade_id: i64,
+    pub ts_ms: i64,
+}
+
+/// A decoded trade as sinks other than QuestDB see it.
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub trade: NormTrade,
+    pub msg_id: String,
+    pub topic: String,
+    pub partition: Option<i32>,
+}
+
+#[derive(Default)]
+pub struct Batch {
+    groups: Vec<Group>,
+    index: HashMap<String, usize>,
+    /// Rows in arrival order, with the index of their symbol group.
+    rows: Vec<(usize, Row)>,
+    deliveries: Vec<Delivery>,
+    /// Non-trade ILP rows (rollup bars), written after the trade chunks.
+    extra: String,
+    started: Option<Instant>,
+    /// Estimated memory held, for the batch budget.
+    bytes: usize,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a trade; its ILP line goes into the chunk for its symbol.
+    pub fn push_row(&mut self, row: Row) {
+        let line = to_ilp_line(&row.trade, &row.msg_id);
+        self.push_encoded(row, &line);
+    }
+
+    /// [`push_row`](Batch::push_row) with the ILP line already encoded.
+    pub fn push_encoded(&mut self, row: Row, line: &str) {
+        let symbol = &row.trade.symbol;
+        let i = match self.index.get(symbol) {
+            Some(&i) => i,
+            None => {
+                self.groups.push(Group { symbol: symbol.clone(), ..Default::default() });
+                self.index.insert(symbol.clone(), self.groups.len() - 1);
+                self.groups.len() - 1
+            }
+        };
+        let g = &mut self.groups[i];
+        g.lines.push_str(line);
+        g.lines.push('\n');
+        g.rows += 1;
+        self.bytes += line.len() + 1 + std::mem::size_of::<(usize, Row)>() + row.trade.symbol.len() + row.msg_id.len();
+        self.rows.push((i, row));
+        self.started.get_or_insert_with(Instant::now);
+    }
+
+    /// Add an ILP line for some other table; only ILP sinks see these.
+    pub fn push_line(&mut self, line: &str) {
+        self.extra.push_str(line);
+        self.extra.push('\n');
+        self.bytes += line.len() + 1;
+    }
+
+    /// Take ownership of a consumed message so the batch's commit covers it.
+    pub fn push_delivery(&mut self, d: Delivery) {
+        self.started.get_or_insert_with(Instant::now);
+        self.bytes += std::mem::size_of::<Delivery>() + d.topic.len() + d.payload.len()
+            + d.key.as_ref().map_or(0, String::len)
+            + d.headers.iter().map(|(k, v)| k.len() + v.len()).sum::<usize>();
+        self.deliveries.push(d);
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deliveries.is_empty() && self.rows.is_empty() && self.extra.is_empty()
+    }
+
+    /// True if there's anything for a sink to write.
+    pub fn has_lines(&self) -> bool {
+        !self.rows.is_empty() || !self.extra.is_empty()
+    }
+
+    /// Estimated memory held: deliveries, rows and ILP text.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// When the first message went in.
+    pub fn started(&self) -> Option<Instant> {
+        self.started
+    }
+
+    /// Per-symbol ILP chunks, then any extra lines, in write order.
+    pub fn chunks(&self) -> impl Iterator<Item = &[u8]> {
+        let extra = Some(self.extra.as_bytes()).filter(|e| !e.is_empty());
+        self.groups.iter().map(|g| g.lines.as_bytes()).chain(extra)
+    }
+
+    /// Each symbol's ILP lines and row count, as [`chunks`](Self::chunks) writes them.
+    pub fn symbol_chunks(&self) -> impl Iterator<Item = (&str, usize, &str)> {
+        self.groups.iter().map(|g| (g.symbol.as_str(), g.rows, g.lines.as_str()))
+    }
+
+    /// The non-trade lines written after the symbol chunks.
+    pub fn extra(&self) -> &str {
+        &self.extra
+    }
+
+    pub fn deliveries(&self) -> &[Delivery] {
+        &self.deliveries
+    }
+
+    /// Hand the deliveries over for committing; the rest of the batch stays.
+    pub fn take_deliveries(&mut self) -> Vec<Delivery> {
+        std::mem::take(&mut self.deliveries)
+    }
+
+    /// Put back deliveries [`take_deliveries`](Self::take_deliveries) lent out,
+    /// ahead of any taken since.
+    pub fn restore_deliveries(&mut self, mut ds: Vec<Delivery>) {
+        ds.append(&mut self.deliveries);
+        self.deliveries = ds;
+    }
+
+    /// Every row, in arrival order.
+    pub fn iter_rows(&self) -> impl Iterator<Item = &Row> {
+        self.rows.iter().map(|(_, r)| r)
+    }
+
+    /// `(symbol, mark)` for every row, in arrival order.
+    pub fn marks(&self) -> impl Iterator<Item = (&str, Mark<'_>)> {
+        self.rows.iter().map(|(i, r)| {
+            let mark = Mark {
+                topic: &r.topic,
+                partition: r.partition,
+                first_trade_id: r.trade.first_trade_id.unwrap_or(r.trade.trade_id),
+                trade_id: r.trade.trade_id,
+                ts_ms: r.trade.ts_ms,
+            };
+            (self.groups[*i].symbol.as_str(), mark)
+        })
+    }
+
+    /// Record `rows_per_flush{symbol}` for a batch that was just written.
+    pub fn observe_flush(&self) {
+        for g in &self.groups {
+            histogram!("rows_per_flush", "symbol" => g.symbol.clone()).record(g.rows as f64);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.groups.clear();
+        self.index.clear();
+        self.rows.clear();
+        self.deliveries.clear();
+        self.extra.clear();
+        self.started = None;
+        self.bytes = 0;
+    }
+}
@@ -0,0 +1,67 @@
+//! L2 snapshots from the book builder (`book.snapshots`) as rows of the
+//! `book` table: the top [`LEVELS`] levels a side as columns, plus the mid
+//! and the quoted spread in basis points.
+//!
+//! Level `n` (1 is the best) is `bid_px_n`/`bid_qty_n` and `ask_px_n`/`ask_qty_n`;
+//! levels a thin book doesn't have are left null. `bid_levels`/`ask_levels`
+//! are how many levels the snapshot had, including those past [`LEVELS`].
+
+use serde::Deserialize;
+
+use crate::ilp::escape_tag;
+
+/// Levels a side stored per row.
+pub const LEVELS: usize = 10;
+
+/// The level columns, in table order.
+pub const LEVEL_COLUMNS: [&str; 4 * LEVELS] = [
+    "bid_px_1", "bid_px_2", "bid_px_3", "bid_px_4", "bid_px_5", "bid_px_6", "bid_px_7", "bid_px_8", "bid_px_9", "bid_px_10",
+    "bid_qty_1", "bid_qty_2", "bid_qty_3", "bid_qty_4", "bid_qty_5", "bid_qty_6", "bid_qty_7", "bid_qty_8", "bid_qty_9", "bid_qty_10",
+    "ask_px_1", "ask_px_2", "ask_px_3", "ask_px_4", "ask_px_5", "ask_px_6", "ask_px_7", "ask_px_8", "ask_px_9", "ask_px_10",
+    "ask_qty_1", "ask_qty_2", "ask_qty_3", "ask_qty_4", "ask_qty_5", "ask_qty_6", "ask_qty_7", "ask_qty_8", "ask_qty_9", "ask_qty_10",
+];
+
+/// One `book.snapshots` record: levels best first, as `[price, qty]`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BookSnapshot {
+    pub symbol: String,
+    /// Event time of the last diff in the book, ms.
+    pub ts_ms: i64,
+    pub update_id: u64,
+    pub bids: Vec<(f64, f64)>,
+    pub asks: Vec<(f64, f64)>,
+}
+
+impl BookSnapshot {
+    pub fn mid(&self) -> Option<f64> {
+        let (bid, ask) = (self.bids.first()?.0, self.asks.first()?.0);
+        Some((bid + ask) / 2.0)
+    }
+
+    /// `(ask - bid) / mid` in basis points; `None` while a side is empty.
+    pub fn spread_bps(&self) -> Option<f64> {
+        let (bid, ask) = (self.bids.first()?.0, self.asks.first()?.0);
+        Some((ask - bid) / self.mid()? * 1e4)
+    }
+
+    pub fn to_ilp_line(&self, table: &str) -> String {
+        let mut line = format!(
+            "{},symbol={} update_id={}i,bid_levels={}i,ask_levels={}i",
+            escape_tag(table),
+            escape_tag(&self.symbol),
+            self.update_id,
+            self.bids.len(),
+            self.asks.len(),
+        );
+        if let (Some(mid), Some(bps)) = (self.mid(), self.spread_bps()) {
+            line.push_str(&format!(",mid={},spread_bps={}", mid, bps));
+        }
+        for (side, levels) in [("bid", &self.bids), ("ask", &self.asks)] {
+            for (n, (px, qty)) in levels.iter().take(LEVELS).enumerate() {
+                line.push_str(&format!(",{side}_px_{0}={px},{side}_qty_{0}={qty}", n + 1));
+            }
+        }
+        line.push_str(&format!(" {}", (self.ts_ms as i128) * 1_000_000i128));
+        line
+    }
+}
@@ -0,0 +1,116 @@
+//! A cap on the distinct symbols written to each trades table, so an
+//! upstream that starts inventing symbols (a broken normalizer, a venue
+//! listing thousands of strikes) can't grow a table's `symbol` column far
+//! past the `QDB_SYMBOL_CAPACITY` it was created with.
+//!
+//! The [`Guard`] admits symbols to a table in first-seen order until it
+//! holds `QDB_MAX_SYMBOLS`; rows for any symbol after that are rewritten
+//! into the catch-all `QDB_OVERFLOW_TABLE` instead, which has the same
+//! columns. A symbol once over stays over until restart, so its rows don't
+//! split between two tables. At startup the guard is seeded with the
+//! symbols each table already has, so a restart doesn't admit new ones.
+//!
+//! Every symbol turned away is logged once and counted in
+//! `ilp_overflow_symbols_total{table}`, its rows in
+//! `ilp_overflow_rows_total{table}`; `ilp_table_symbols{table}` is how many
+//! a table holds.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use metrics::{counter, gauge};
+
+use crate::schema::Client;
+
+pub struct Guard {
+    limit: usize,
+    overflow: String,
+    /// Symbols admitted, by table.
+    admitted: HashMap<String, HashSet<String>>,
+    /// `(table, symbol)` pairs sent to the overflow table.
+    turned_away: HashSet<(String, String)>,
+}
+
+impl Guard {
+    /// At most `limit` symbols per table; the rest go to `overflow`.
+    pub fn new(limit: usize, overflow: &str) -> Self {
+        Self { limit, overflow: overflow.to_string(), admitted: HashMap::new(), turned_away: HashSet::new() }
+    }
+
+    pub fn overflow(&self) -> &str {
+        &self.overflow
+    }
+
+    /// Symbols `table` already has; all are admitted, even past the limit.
+    pub fn seed(&mut self, table: &str, symbols: impl IntoIterator<Item = String>) {
+        let admitted = self.admitted.entry(table.to_string()).or_default();
+        admitted.extend(symbols);
+        gauge!("ilp_table_symbols", "table" => table.to_string()).set(admitted.len() as f64);
+    }
+
+    /// [`seed`](Self::seed) `table` with the symbols QuestDB has for it.
+    pub async fn seed_from(&mut self, client: &Client, table: &str) -> Result<usize> {
+        let rows = client.query(&format!("SELECT DISTINCT symbol FROM {}", table)).await?;
+        let symbols: Vec<String> = rows.iter().filter_map(|r| r.first()?.as_str().map(String::from)).collect();
+        let n = symbols.len();
+        self.seed(table, symbols);
+        Ok(n)
+    }
+
+    /// Symbols admitted to `table`.
+    pub fn symbols(&self, table: &str) -> usize {
+        self.admitted.get(table).map_or(0, HashSet::len)
+    }
+
+    /// Where `symbol`'s rows bound for `table` go: `None` for `table` itself,
+    /// or the overflow table once `table` is full. The overflow table takes
+    /// every symbol.
+    pub fn route(&mut self, table: &str, symbol: &str) -> Option<&str> {
+        if table == self.overflow {
+            return None;
+        }
+        let admitted = self.admitted.entry(table.to_string()).or_default();
+        if admitted.contains(symbol) {
+            return None;
+        }
+        let key = (table.to_string(), symbol.to_string());
+        if !self.turned_away.contains(&key) {
+            if admitted.len() < self.limit {
+                admitted.insert(symbol.to_string());
+                gauge!("ilp_table_symbols", "table" => table.to_string()).set(admitted.len() as f64);
+                return None;
+            }
+            tracing::warn!(target="consumer", table, symbol, limit=self.limit, overflow=%self.overflow,
+                "table is at its symbol limit; writing the symbol to the overflow table");
+            counter!("ilp_overflow_symbols_total", "table" => table.to_string()).increment(1);
+            self.turned_away.insert(key);
+        }
+        Some(&self.overflow)
+    }
+}
+
+/// The table an ILP line writes to: everything before the first unescaped `,` or space.
+pub fn line_table(line: &str) -> &str {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b',' | b' ' => return &line[..i],
+            _ => {}
+        }
+        i += 1;
+    }
+    line
+}
+
+/// `lines` with each line's table replaced by `table`.
+pub fn retable(lines: &str, table: &str) -> String {
+    let mut out = String::with_capacity(lines.len() + table.len() * 8);
+    for line in lines.lines() {
+        out.push_str(table);
+        out.push_str(&line[line_table(line).len()..]);
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,69 @@
+//! Where the consumer has got to, per partition, written to QuestDB.
+//!
+//! Every `CHECKPOINT_EVERY_MS` the consumer adds a row per partition to
+//! `_checkpoints`: the offset it last committed there and the partition's
+//! event-time watermark (see [`crate::watermark`]). Joined against the
+//! topic's end offsets or the stored trades, it shows in SQL how far behind
+//! Kafka the table is.
+
+use std::collections::BTreeMap;
+
+use bus::Delivery;
+
+use crate::ilp::escape_tag;
+
+pub struct Checkpoints {
+    table: String,
+    group: String,
+    /// Next offset to read per (topic, partition), done but not committed yet.
+    done: BTreeMap<(String, i32), i64>,
+    committed: BTreeMap<(String, i32), i64>,
+    /// Something was committed since the last [`lines`](Checkpoints::lines).
+    moved: bool,
+}
+
+impl Checkpoints {
+    pub fn new(table: &str, group: &str) -> Self {
+        Self { table: table.to_string(), group: group.to_string(), done: BTreeMap::new(), committed: BTreeMap::new(), moved: false }
+    }
+
+    /// Note a delivery handed to the committer; only Kafka's have offsets.
+    pub fn done(&mut self, d: &Delivery) {
+        if let (Some(partition), Some(offset)) = (d.partition(), d.offset()) {
+            self.done_at(&d.topic, partition, offset);
+        }
+    }
+
+    /// [`done`](Checkpoints::done) by position.
+    pub fn done_at(&mut self, topic: &str, partition: i32, offset: i64) {
+        let next = self.done.entry((topic.to_string(), partition)).or_insert(offset + 1);
+        *next = (*next).max(offset + 1);
+    }
+
+    /// The committer committed: everything [`done`](Checkpoints::done) so far is.
+    pub fn committed(&mut self) {
+        self.moved |= !self.done.is_empty();
+        for (k, next) in std::mem::take(&mut self.done) {
+            let at = self.committed.entry(k).or_insert(next);
+            *at = (*at).max(next);
+        }
+    }
+
+    /// One ILP row per committed partition, stamped `now_ns`, with its
+    /// watermark (ms) from `watermarks` when it has one. Nothing if no commit
+    /// moved since the last call.
+    pub fn lines(&mut self, watermarks: &BTreeMap<(String, i32), i64>, now_ns: i64) -> Vec<String> {
+        if !std::mem::take(&mut self.moved) {
+            return Vec::new();
+        }
+        self.committed.iter()
+            .map(|(at @ (topic, partition), offset)| {
+                let watermark = watermarks.get(at).map(|ms| format!(",watermark={}t", ms * 1000)).unwrap_or_default();
+                format!(
+                    "{},consumer_group={},topic={} partition={}i,committed_offset={}i{} {}",
+                    escape_tag(&self.table), escape_tag(&self.group), escape_tag(topic), partition, offset, watermark, now_ns,
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,83 @@
+//! Pick a `ticks.norm` decoder by the message's `schema_version`, so producers
+//! can roll forward without a lock-step consumer deploy, and by its `codec`
+//! header, so a topic can carry JSON and binary trades side by side while
+//! producers migrate.
+
+use bus::envelope::{Compat, SchemaVersion};
+use codec::Codec;
+
+use crate::avro::{self, Registry};
+use crate::error::Error;
+use crate::ilp::NormTrade;
+
+/// Assumed for messages written before the envelope existed.
+const UNVERSIONED: SchemaVersion = SchemaVersion::new(1, 0);
+
+fn v1(payload: &str) -> Result<NormTrade, Error> {
+    Ok(serde_json::from_str(payload)?)
+}
+
+pub fn decode(payload: &str, compat: &Compat) -> Result<NormTrade, Error> {
+    let version = match compat {
+        Compat::Supported(v) => *v,
+        Compat::Unversioned => UNVERSIONED,
+        Compat::Unsupported(v) => return Err(Error::Schema(v.clone())),
+    };
+    match version.major {
+        1 => v1(payload),
+        major => Err(Error::Major(major)),
+    }
+}
+
+/// Decode `payload` as the `codec` header says; no header means JSON.
+///
+/// MessagePack and Protobuf carry the [`codec::Trade`] fields, so the fields
+/// added in later minors stay empty for them, except the two timestamps
+/// (`ts_ns`, `ingest_ts_ns`). Avro is read by its writer schema from
+/// `registry`, which must already have fetched it (see [`avro`]); without a
+/// registry it's refused like any unknown codec.
+pub fn decode_as(payload: &[u8], codec: Option<&str>, compat: &Compat, registry: Option<&Registry>) -> Result<NormTrade, Error> {
+    let codec = match (codec, registry) {
+        (None, _) => Codec::Json,
+        (Some("avro"), Some(registry)) => {
+            if let Compat::Unsupported(v) = compat {
+                return Err(Error::Schema(v.clone()));
+            }
+            return decode_avro(payload, registry);
+        }
+        (Some(name), _) => Codec::parse(name).ok_or_else(|| Error::Codec(name.to_string()))?,
+    };
+    if codec == Codec::Json {
+        return decode(std::str::from_utf8(payload)?, compat);
+    }
+    // Checked the same way as for JSON, before trusting the bytes as a trade.
+    if let Compat::Unsupported(v) = compat {
+        return Err(Error::Schema(v.clone()));
+    }
+    let t = codec.decode(payload).map_err(|e| Error::Binary { codec: codec.name(), reason: format!("{:#}", e) })?;
+    Ok(NormTrade {
+        ts_ms: t.ts_ms,
+        symbol: t.symbol,
+        price: t.price,
+        qty: t.qty,
+        trade_id: t.trade_id,
+        is_bm: t.is_bm,
+        session: None,
+        notional: None,
+        first_trade_id: None,
+        contract_size: None,
+        ts_ns: t.ts_ns,
+        ingest_ts_ns: t.ingest_ts_ns,
+    })
+}
+
+fn decode_avro(payload: &[u8], registry: &Registry) -> Result<NormTrade, Error> {
+    let bad = |e: anyhow::Error| Error::Binary { codec: "avro", reason: format!("{:#}", e) };
+    let (id, body) = avro::unframe(payload).map_err(bad)?;
+    if !registry.known(id) {
+        return Err(Error::Registry(id));
+    }
+    let schema = registry.get(id).ok_or_else(|| Error::Binary { codec: "avro", reason: format!("no Avro schema {} in the registry", id) })?;
+    let value = avro::decode(&schema, body).map_err(bad)?;
+    Ok(serde_json::from_value(value)?)
+}
@@ -0,0 +1,73 @@
+//! Local DuckDB file sink, for laptops and CI where QuestDB is overkill.
+//!
+//! Rows go into a `trades` table with the same columns as QuestDB's. Each
+//! batch is appended inside one transaction, so a failed batch leaves
+//! nothing behind and can be retried as is.
+
+use std::path::Path;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use duckdb::types::{TimeUnit, Value};
+use duckdb::{params, Connection};
+
+use crate::batch::Batch;
+use crate::sink::Sink;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS trades (
+    symbol VARCHAR NOT NULL,
+    price DOUBLE NOT NULL,
+    qty DOUBLE NOT NULL,
+    trade_id BIGINT NOT NULL,
+    is_bm BOOLEAN NOT NULL,
+    msg_id VARCHAR NOT NULL,
+    ts_ms BIGINT NOT NULL,
+    timestamp TIMESTAMP NOT NULL
+)";
+
+pub struct DuckDbSink {
+    conn: Connection,
+}
+
+impl DuckDbSink {
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    fn append(&self, batch: &Batch) -> Result<()> {
+        let mut app = self.conn.appender("trades")?;
+        for r in batch.iter_rows() {
+            let t = &r.trade;
+            app.append_row(params![
+                t.symbol, t.price, t.qty, t.trade_id, t.is_bm, r.msg_id, t.ts_ms,
+                Value::Timestamp(TimeUnit::Millisecond, t.ts_ms),
+            ])?;
+        }
+        app.flush()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for DuckDbSink {
+    fn name(&self) -> &str {
+        "duckdb"
+    }
+
+    async fn write(&mut self, batch: &Batch) -> Result<()> {
+        if batch.rows() == 0 {
+            return Ok(());
+        }
+        self.conn.execute_batch("BEGIN TRANSACTION")?;
+        match self.append(batch) {
+            Ok(()) => Ok(self.conn.execute_batch("COMMIT")?),
+            Err(e) => {
+                let _ = self.conn.execute_batch("ROLLBACK");
+                Err(e)
+            }
+        }
+    }
+}
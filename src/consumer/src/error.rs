@@ -0,0 +1,56 @@
+//! Why a `ticks.norm` message didn't become a trade. All but one of these are
+//! the message's fault, a data error: it is dropped, counted by
+//! [`Error::reason`], and the stream goes on. [`Error::Registry`] is the
+//! schema registry's, a transient error, but its message goes the same way
+//! so the trades that don't need the registry keep flowing.
+//! Write failures are the sinks' and the tee's to retry, and counted there.
+
+use obsv::errors::{Classify, ErrorKind};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("empty payload")]
+    Empty,
+    #[error("payload of {0} bytes is over MAX_PAYLOAD_BYTES")]
+    Oversize(usize),
+    #[error("signature check failed")]
+    Signature,
+    #[error("unsupported schema_version {0:?}")]
+    Schema(String),
+    #[error("no decoder for schema major {0}")]
+    Major(u32),
+    #[error("unsupported codec {0:?}")]
+    Codec(String),
+    #[error("payload is not UTF-8: {0}")]
+    Utf8(#[from] std::str::Utf8Error),
+    #[error("malformed trade: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("malformed {codec} trade: {reason}")]
+    Binary { codec: &'static str, reason: String },
+    #[error("Avro schema {0} couldn't be fetched from the registry")]
+    Registry(u32),
+}
+
+impl Error {
+    /// The `reason` label of `dropped_total`.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Error::Empty => "empty",
+            Error::Oversize(_) => "oversize",
+            Error::Signature => "signature",
+            Error::Schema(_) | Error::Major(_) => "schema",
+            Error::Codec(_) => "codec",
+            Error::Utf8(_) | Error::Json(_) | Error::Binary { .. } => "malformed",
+            Error::Registry(_) => "registry",
+        }
+    }
+}
+
+impl Classify for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Registry(_) => ErrorKind::Transient,
+            _ => ErrorKind::Data,
+        }
+    }
+}
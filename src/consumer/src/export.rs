@@ -0,0 +1,296 @@
+//! Flat-file exports of stored trades and bars, paged out of QuestDB's
+//! `/exec` API.
+//!
+//! A [`Pager`] walks a [`Range`] in timestamp order, keyed on the last
+//! timestamp read rather than a row offset, so a page costs the same however
+//! deep into the range it is. Each page becomes one row group of a
+//! [`ParquetExport`], a record batch of an
+//! [`ArrowExport`](crate::arrow::ArrowExport), or a run of [`csv_line`]s.
+//! Column names match the tables, so an exported Parquet file reads back
+//! into the backtester like an archive file does.
+
+use std::io::Write;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use parquet::basic::Compression;
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    /// QuestDB's designated timestamp, an ISO string over `/exec`.
+    Timestamp,
+    Str,
+    Double,
+    Long,
+    Bool,
+}
+
+const TRADES: &[(&str, Kind)] = &[
+    ("timestamp", Kind::Timestamp),
+    ("symbol", Kind::Str),
+    ("price", Kind::Double),
+    ("qty", Kind::Double),
+    ("trade_id", Kind::Long),
+    ("first_trade_id", Kind::Long),
+    ("is_bm", Kind::Bool),
+    ("msg_id", Kind::Str),
+    ("ts_ms", Kind::Long),
+];
+
+const BARS: &[(&str, Kind)] = &[
+    ("timestamp", Kind::Timestamp),
+    ("symbol", Kind::Str),
+    ("open", Kind::Double),
+    ("high", Kind::Double),
+    ("low", Kind::Double),
+    ("close", Kind::Double),
+    ("volume", Kind::Double),
+    ("trades", Kind::Long),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dataset {
+    Trades,
+    Bars,
+}
+
+impl Dataset {
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s {
+            "trades" => Dataset::Trades,
+            "bars" => Dataset::Bars,
+            other => anyhow::bail!("unknown dataset {:?} (expected trades|bars)", other),
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dataset::Trades => "trades",
+            Dataset::Bars => "bars",
+        }
+    }
+
+    /// The table the consumer writes it to by default, in the namespace.
+    pub fn default_table(&self) -> String {
+        obsv::namespace::table(match self {
+            Dataset::Trades => "trades",
+            Dataset::Bars => "trades_1m",
+        })
+    }
+
+    pub(crate) fn columns(&self) -> &'static [(&'static str, Kind)] {
+        match self {
+            Dataset::Trades => TRADES,
+            Dataset::Bars => BARS,
+        }
+    }
+
+    pub fn column_names(&self) -> Vec<&'static str> {
+        self.columns().iter().map(|(n, _)| *n).collect()
+    }
+}
+
+/// Rows of `table` for `symbols` (all if empty) in `[from_ms, to_ms)`.
+#[derive(Debug, Clone)]
+pub struct Range {
+    pub dataset: Dataset,
+    pub table: String,
+    pub symbols: Vec<String>,
+    pub from_ms: i64,
+    pub to_ms: i64,
+}
+
+impl Range {
+    /// Up to `page` rows from `from_us` (µs since the epoch, inclusive) to the range's end.
+    pub fn sql(&self, from_us: i64, page: usize) -> String {
+        format!("{} ORDER BY timestamp LIMIT {}", self.select(from_us, self.to_ms * 1000), page)
+    }
+
+    /// Every row at `ts_us`.
+    pub fn sql_at(&self, ts_us: i64) -> String {
+        self.select(ts_us, ts_us + 1)
+    }
+
+    /// The range's rows in `[from_us, to_us)`.
+    fn select(&self, from_us: i64, to_us: i64) -> String {
+        let symbols = match self.symbols.is_empty() {
+            true => String::new(),
+            false => format!(" AND symbol IN ({})", self.symbols.iter().map(|s| format!("'{}'", s.replace('\'', "''"))).collect::<Vec<_>>().join(",")),
+        };
+        format!(
+            "SELECT {} FROM {} WHERE timestamp >= cast({} AS timestamp) AND timestamp < cast({} AS timestamp){}",
+            self.dataset.column_names().join(", "), self.table, from_us, to_us, symbols,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Next {
+    /// Up to a page from this timestamp (µs), inclusive.
+    From(i64),
+    /// Every row at this timestamp: a whole page shared it.
+    At(i64),
+    Done,
+}
+
+/// Walks a [`Range`] a page at a time. A full page's rows at its last
+/// timestamp are left for the next page, which starts there, so rows sharing
+/// a timestamp are never split or skipped and no ordering past the timestamp
+/// is needed.
+#[derive(Debug, Clone)]
+pub struct Pager {
+    range: Range,
+    page: usize,
+    next: Next,
+}
+
+impl Pager {
+    pub fn new(range: Range, page: usize) -> Self {
+        let next = Next::From(range.from_ms * 1000);
+        Self { range, page: page.max(1), next }
+    }
+
+    pub fn range(&self) -> &Range {
+        &self.range
+    }
+
+    /// The next page's query; `None` once the range is done.
+    pub fn sql(&self) -> Option<String> {
+        match self.next {
+            Next::From(us) => Some(self.range.sql(us, self.page)),
+            Next::At(us) => Some(self.range.sql_at(us)),
+            Next::Done => None,
+        }
+    }
+
+    /// The rows [`sql`](Self::sql)'s query returned, less any left for the
+    /// next page; may be empty while the range isn't done.
+    pub fn take(&mut self, mut rows: Vec<Vec<Value>>) -> Result<Vec<Vec<Value>>> {
+        self.next = match self.next {
+            Next::At(us) => Next::From(us + 1),
+            Next::From(_) if rows.len() < self.page => Next::Done,
+            Next::From(_) => {
+                let ts = |r: &Vec<Value>| r.first().and_then(micros).context("exported row without a timestamp");
+                let last = ts(rows.last().context("a full page of no rows")?)?;
+                let keep = rows.iter().position(|r| ts(r).is_ok_and(|t| t == last)).unwrap_or(rows.len());
+                rows.truncate(keep);
+                if keep == 0 { Next::At(last) } else { Next::From(last) }
+            }
+            Next::Done => Next::Done,
+        };
+        Ok(rows)
+    }
+}
+
+pub(crate) fn micros(v: &Value) -> Option<i64> {
+    v.as_str()?.parse::<chrono::DateTime<chrono::Utc>>().ok().map(|t| t.timestamp_micros())
+}
+
+/// Column `i` of `rows` as its non-null values and definition levels; a cell
+/// `get` can't read counts as null.
+fn column<T>(rows: &[Vec<Value>], i: usize, get: impl Fn(&Value) -> Option<T>) -> (Vec<T>, Vec<i16>) {
+    let mut values = Vec::with_capacity(rows.len());
+    let defs = rows.iter()
+        .map(|r| match r.get(i).and_then(&get) {
+            Some(v) => {
+                values.push(v);
+                1
+            }
+            None => 0,
+        })
+        .collect();
+    (values, defs)
+}
+
+/// A Parquet file written one page (row group) at a time into `W`.
+pub struct ParquetExport<W: Write + Send> {
+    dataset: Dataset,
+    writer: SerializedFileWriter<W>,
+}
+
+impl<W: Write + Send> ParquetExport<W> {
+    pub fn new(out: W, dataset: Dataset) -> Result<Self> {
+        // Every column is optional: QuestDB has nulls (e.g. `first_trade_id` off the aggTrade stream).
+        let fields: Vec<String> = dataset.columns().iter()
+            .map(|(name, kind)| match kind {
+                Kind::Timestamp => format!("OPTIONAL INT64 {} (TIMESTAMP(MICROS, true));", name),
+                Kind::Str => format!("OPTIONAL BINARY {} (STRING);", name),
+                Kind::Double => format!("OPTIONAL DOUBLE {};", name),
+                Kind::Long => format!("OPTIONAL INT64 {};", name),
+                Kind::Bool => format!("OPTIONAL BOOLEAN {};", name),
+            })
+            .collect();
+        let schema = Arc::new(parse_message_type(&format!("message {} {{ {} }}", dataset.name(), fields.join(" ")))?);
+        let props = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).build());
+        Ok(Self { dataset, writer: SerializedFileWriter::new(out, schema, props)? })
+    }
+
+    /// Write `rows`, in [`Range::sql`] column order, as one row group.
+    pub fn write(&mut self, rows: &[Vec<Value>]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut rg = self.writer.next_row_group()?;
+        for (i, (name, kind)) in self.dataset.columns().iter().enumerate() {
+            let mut col = rg.next_column()?.with_context(|| format!("no {} column", name))?;
+            match kind {
+                Kind::Timestamp => {
+                    let (values, defs) = column(rows, i, micros);
+                    col.typed::<Int64Type>().write_batch(&values, Some(&defs), None)?
+                }
+                Kind::Long => {
+                    let (values, defs) = column(rows, i, Value::as_i64);
+                    col.typed::<Int64Type>().write_batch(&values, Some(&defs), None)?
+                }
+                Kind::Double => {
+                    let (values, defs) = column(rows, i, Value::as_f64);
+                    col.typed::<DoubleType>().write_batch(&values, Some(&defs), None)?
+                }
+                Kind::Bool => {
+                    let (values, defs) = column(rows, i, Value::as_bool);
+                    col.typed::<BoolType>().write_batch(&values, Some(&defs), None)?
+                }
+                Kind::Str => {
+                    let (values, defs) = column(rows, i, |v| v.as_str().map(ByteArray::from));
+                    col.typed::<ByteArrayType>().write_batch(&values, Some(&defs), None)?
+                }
+            };
+            col.close()?;
+        }
+        rg.close()?;
+        Ok(())
+    }
+
+    /// The bytes written so far; for streaming the file out while it's written.
+    pub fn out_mut(&mut self) -> &mut W {
+        self.writer.inner_mut()
+    }
+
+    /// Write the footer and hand back `W`.
+    pub fn finish(self) -> Result<W> {
+        Ok(self.writer.into_inner()?)
+    }
+}
+
+/// The CSV header line for `dataset`.
+pub fn csv_header(dataset: Dataset) -> String {
+    format!("{}\n", dataset.column_names().join(","))
+}
+
+/// One row, in [`Range::sql`] column order, as a CSV line; nulls are empty fields.
+pub fn csv_line(row: &[Value]) -> String {
+    let fields: Vec<String> = row.iter()
+        .map(|v| match v {
+            Value::Null => String::new(),
+            Value::String(s) if s.contains([',', '"', '\n', '\r']) => format!("\"{}\"", s.replace('"', "\"\"")),
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        })
+        .collect();
+    format!("{}\n", fields.join(","))
+}
@@ -0,0 +1,174 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use secrets::Watched;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::OnceCell;
+
+/// The `ticks.norm` v1 shape. Unknown fields are ignored, so minor versions
+/// can add fields freely; aliases accept names used by earlier producers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NormTrade {
+    #[serde(alias = "ts", alias = "timestamp_ms")]
+    pub ts_ms: i64,
+    pub symbol: String,
+    pub price: f64,
+    #[serde(alias = "quantity")]
+    pub qty: f64,
+    #[serde(alias = "id")]
+    pub trade_id: i64,
+    #[serde(alias = "is_buyer_maker")]
+    pub is_bm: bool,
+    /// Since 1.1; the UTC sessions open at `ts_ms` (`europe+us`), absent outside all of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    /// Since 1.2, in the producer's `NOTIONAL_CURRENCY`; absent when it had no
+    /// rate for the quote asset. Named `notional_usd` before 1.8.
+    #[serde(default, alias = "notional_usd", skip_serializing_if = "Option::is_none")]
+    pub notional: Option<f64>,
+    /// Since 1.3; first trade id of an aggregated trade, which covers `first_trade_id..=trade_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_trade_id: Option<i64>,
+    /// Since 1.4; quote currency per contract on COIN-M futures, whose `qty` counts contracts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract_size: Option<f64>,
+    /// Since 1.7; `ts_ms` in nanoseconds, for venues that send finer than milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ts_ns: Option<i64>,
+    /// Since 1.5; when the fetcher received the trade, in nanoseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingest_ts_ns: Option<i64>,
+}
+
+/// QuestDB's ILP-over-TCP authentication: the key id, then the challenge the
+/// server sends back signed with the key (ECDSA P-256, SHA-256).
+///
+/// Set by `QDB_ILP_USER` (the key id) and `QDB_ILP_TOKEN` (the private key,
+/// `d` in QuestDB's JWK, a secret), with the public key in `QDB_ILP_TOKEN_X`
+/// and `QDB_ILP_TOKEN_Y`; base64url, as QuestDB hands them out.
+pub struct IlpAuth {
+    user: String,
+    token: Watched,
+    public: Vec<u8>,
+}
+
+impl IlpAuth {
+    pub fn new(user: &str, token: Watched, x: &str, y: &str) -> Result<Self> {
+        let coord = |name: &str, v: &str| URL_SAFE_NO_PAD.decode(v.trim().trim_end_matches('=')).with_context(|| format!("{} isn't base64url", name));
+        // An uncompressed point, as ring takes it.
+        let public = [vec![4], coord("QDB_ILP_TOKEN_X", x)?, coord("QDB_ILP_TOKEN_Y", y)?].concat();
+        let auth = Self { user: user.to_string(), token, public };
+        auth.key()?;
+        Ok(auth)
+    }
+
+    /// `None` when `QDB_ILP_USER` is unset or empty.
+    pub async fn from_env() -> Result<Option<Self>> {
+        let user = std::env::var("QDB_ILP_USER").unwrap_or_default();
+        if user.is_empty() {
+            return Ok(None);
+        }
+        let token = secrets::Secrets::from_env()?.watch("QDB_ILP_TOKEN").await?.context("QDB_ILP_USER needs a QDB_ILP_TOKEN")?;
+        let var = |k: &str| std::env::var(k).with_context(|| format!("QDB_ILP_USER needs a {}", k));
+        Ok(Some(Self::new(&user, token, &var("QDB_ILP_TOKEN_X")?, &var("QDB_ILP_TOKEN_Y")?)?))
+    }
+
+    /// The key as the token is now; it's read again on each connect, so a rotated one is picked up.
+    fn key(&self) -> Result<EcdsaKeyPair> {
+        let d = URL_SAFE_NO_PAD.decode(self.token.current().expose().trim().trim_end_matches('=')).context("QDB_ILP_TOKEN isn't base64url")?;
+        EcdsaKeyPair::from_private_key_and_public_key(&ECDSA_P256_SHA256_FIXED_SIGNING, &d, &self.public, &SystemRandom::new())
+            .map_err(|e| anyhow::anyhow!("QDB_ILP_TOKEN doesn't match QDB_ILP_TOKEN_X/Y: {}", e))
+    }
+
+    /// Sign in on a fresh connection. QuestDB says nothing on success, and
+    /// closes the connection on failure; the first write after that fails.
+    pub async fn handshake(&self, stream: &mut TcpStream) -> Result<()> {
+        let key = self.key()?;
+        stream.write_all(format!("{}\n", self.user).as_bytes()).await?;
+        let mut challenge = Vec::new();
+        BufReader::new(&mut *stream).read_until(b'\n', &mut challenge).await?;
+        anyhow::ensure!(challenge.pop() == Some(b'\n'), "QuestDB closed the connection before its ILP auth challenge");
+        let signature = key.sign(&SystemRandom::new(), &challenge).map_err(|_| anyhow::anyhow!("signing the ILP auth challenge"))?;
+        stream.write_all(format!("{}\n", STANDARD.encode(signature.as_ref())).as_bytes()).await?;
+        Ok(())
+    }
+}
+
+/// Connect to QuestDB's ILP port, authenticating if `QDB_ILP_USER` is set.
+pub async fn ilp_connect(host: &str, port: u16) -> Result<TcpStream> {
+    static AUTH: OnceCell<Option<IlpAuth>> = OnceCell::const_new();
+    let auth = AUTH.get_or_try_init(IlpAuth::from_env).await?;
+    let addr = format!("{}:{}", host, port);
+    let mut stream = TcpStream::connect(addr).await?;
+    if let Some(auth) = auth {
+        auth.handshake(&mut stream).await.with_context(|| format!("ILP auth as {} at {}:{}", auth.user, host, port))?;
+    }
+    Ok(stream)
+}
+
+/// Escape an ILP tag value: `\`, space, comma, `=` and line breaks get a backslash.
+pub fn escape_tag(v: &str) -> String {
+    let mut out = String::with_capacity(v.len());
+    for c in v.chars() {
+        if matches!(c, '\\' | ' ' | ',' | '=' | '\n' | '\r') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Escape an ILP string field value (the caller adds the quotes).
+pub fn escape_str(v: &str) -> String {
+    let mut out = String::with_capacity(v.len());
+    for c in v.chars() {
+        if matches!(c, '\\' | '"' | '\n' | '\r') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// A trade's row in [`trades_table`](crate::schema::trades_table).
+pub fn to_ilp_line(t: &NormTrade, msg_id: &str) -> String {
+    to_ilp_line_in(crate::schema::trades_table(), t, msg_id)
+}
+
+/// [`to_ilp_line`] into `table`, for a routed symbol's own table.
+///
+/// The designated timestamp stays `ts_ms` in whole milliseconds even when
+/// `ts_ns` is finer: it's part of the dedup key, and a replay of an archive
+/// that only kept milliseconds must land on the same row. The nanoseconds go
+/// in their own `ts_ns` and `ingest_ts_ns` columns.
+pub fn to_ilp_line_in(table: &str, t: &NormTrade, msg_id: &str) -> String {
+    // Omitted rather than written as NaN, so the column stays null.
+    let notional = t.notional.map(|n| format!(",notional={}", n)).unwrap_or_default();
+    let first = t.first_trade_id.map(|f| format!(",first_trade_id={}i", f)).unwrap_or_default();
+    let size = t.contract_size.map(|c| format!(",contract_size={}", c)).unwrap_or_default();
+    let ts_ns = t.ts_ns.map(|ns| format!(",ts_ns={}i", ns)).unwrap_or_default();
+    let ingest = t.ingest_ts_ns.map(|ns| format!(",ingest_ts_ns={}i", ns)).unwrap_or_default();
+    let session = t.session.as_deref().map(|s| format!(",session={}", escape_tag(s))).unwrap_or_default();
+    format!(
+        "{},symbol={}{} price={},qty={},trade_id={}i,is_bm={},msg_id=\"{}\",ts_ms={}i{}{}{}{}{} {}",
+        table,
+        escape_tag(&t.symbol),
+        session,
+        t.price,
+        t.qty,
+        t.trade_id,
+        t.is_bm,
+        escape_str(msg_id),
+        t.ts_ms,
+        notional,
+        first,
+        size,
+        ts_ns,
+        ingest,
+        (t.ts_ms as i128) * 1_000_000i128 // ms -> ns
+    )
+}
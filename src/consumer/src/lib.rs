@@ -0,0 +1,25 @@
+//! Stage 3: consume `ticks.norm` and write trades into QuestDB over ILP.
+
+pub mod archive;
+pub mod arrow;
+pub mod avro;
+pub mod bars;
+pub mod batch;
+pub mod book;
+pub mod cardinality;
+pub mod checkpoint;
+pub mod decode;
+#[cfg(feature = "duckdb")]
+pub mod duck;
+pub mod error;
+pub mod export;
+pub mod ilp;
+pub mod pg;
+pub mod profile;
+pub mod rollup;
+pub mod schema;
+pub mod sink;
+pub mod spill;
+pub mod tee;
+pub mod verify;
+pub mod watermark;
@@ -1,151 +1,1088 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use bus::envelope::{self, Compat, Envelope, NORM_SCHEMA};
+use bus::commit::{CommitStrategy, Committer};
+use bus::oversize::{Action, Limit, OVERSIZE_BYTES};
+use bus::route::Routes;
+use bus::sign::Keyring;
+use bus::{BusConfig, Delivery, Headers, Publisher, Subscriber, Transport, MSG_ID, REPLAYED, TS_PRODUCE_NS};
+use calendar::Calendar;
 use chrono::Utc;
-use futures_util::StreamExt;
+use consumer::archive::{ArchiveOptions, ParquetSink};
+use consumer::avro::{self, Registry};
+use consumer::batch::{Batch, Row};
+use consumer::bars::{Spec, TradeBars};
+use consumer::book::BookSnapshot;
+use consumer::cardinality::Guard;
+use consumer::checkpoint::Checkpoints;
+use consumer::decode::decode_as;
+use consumer::error::Error;
+use consumer::ilp::{to_ilp_line, to_ilp_line_in, NormTrade};
+use consumer::pg::{self, PgClient};
+use consumer::profile::Profile;
+use consumer::rollup::{Bar, Rollup};
+use consumer::schema;
+use consumer::sink::{parse_endpoints, FlushSchedule, IlpOptions, IlpPool, PoolMode, Sink};
+use consumer::spill::Spill;
+use consumer::tee::{Slot, Tee, DLQ_ERROR, DLQ_REASON};
+use consumer::verify::{self, Ledger};
+use consumer::watermark::Watermarks;
 use metrics::{counter, gauge, histogram};
-use obsv::{init_metrics, init_tracing, measure_ms, measure_ms_async};
-use rdkafka::config::ClientConfig;
-use rdkafka::consumer::{Consumer, StreamConsumer};
-use rdkafka::message::{BorrowedMessage, Headers};
-use rdkafka::Message;
-use serde::Deserialize;
-use tokio::io::AsyncWriteExt;
-use tokio::net::TcpStream;
+use obsv::errors::{self, Classify, ErrorKind};
+use obsv::load::Load;
+use retry::{Backoff, Breaker, Policy};
+use obsv::{init_metrics_with_slos, init_tracing, namespace, runtime, startup, Budget, Overflow};
 
 fn env<T: AsRef<str>>(k: T, default: &str) -> String {
     std::env::var(k.as_ref()).unwrap_or_else(|_| default.to_string())
 }
 
-fn header_str<'a>(m: &'a BorrowedMessage<'a>, key: &str) -> Option<&'a str> {
-    m.headers()?.iter().find(|h| h.key == key)
-        .and_then(|h| std::str::from_utf8(h.value?).ok())
+/// Whether a commit happened. One covers everything done before it, so
+/// that's all checkpointed. A failed commit is logged, and only returned
+/// if it's fatal.
+fn checkpoint(res: Result<bool>, checkpoints: &mut Option<Checkpoints>) -> Result<bool> {
+    match res {
+        Ok(true) => {
+            if let Some(cp) = checkpoints {
+                cp.committed();
+            }
+            Ok(true)
+        }
+        Ok(false) => Ok(false),
+        Err(e) => {
+            tracing::warn!(target="consumer", error=?e, "commit failed");
+            errors::escalate("consumer", bus::error::kind(&e), e)?;
+            Ok(false)
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct NormTrade {
-    ts_ms: i64,
-    symbol: String,
-    price: f64,
-    qty: f64,
-    trade_id: i64,
-    is_bm: bool,
+/// The QuestDB ILP endpoints: `QDB_HOSTS` lists every node; without it the
+/// single `QDB_HOST`/`QDB_ILP_PORT` is used.
+fn questdb_endpoints() -> Result<Vec<(String, u16)>> {
+    let ilp_host = env("QDB_HOST", "localhost");
+    let ilp_port: u16 = env("QDB_ILP_PORT", "9009").parse().unwrap_or(9009);
+    parse_endpoints(&env("QDB_HOSTS", &format!("{}:{}", ilp_host, ilp_port)), ilp_port)
 }
 
-// ---- ILP helpers ----
-async fn ilp_connect(host: &str, port: u16) -> Result<TcpStream> {
-    let addr = format!("{}:{}", host, port);
-    let stream = TcpStream::connect(addr).await?;
-    Ok(stream)
+/// The QuestDB ILP pool from `QDB_*`, recording its rows in `ledger` and
+/// capping symbols per table with `guard` if given.
+async fn questdb(ledger: Option<Arc<Mutex<Ledger>>>, guard: Option<Guard>) -> Result<IlpPool> {
+    let ilp_opts = IlpOptions {
+        nodelay: env("QDB_TCP_NODELAY", "true") != "false",
+        buf_bytes: env("QDB_WRITE_BUF_BYTES", "65536").parse().unwrap_or(65536),
+    };
+    let endpoints = questdb_endpoints()?;
+    let pool_mode = PoolMode::parse(&env("QDB_POOL_MODE", "failover"))?;
+    let probe_every = Duration::from_millis(env("QDB_PROBE_EVERY_MS", "5000").parse().unwrap_or(5000));
+    let pool = startup::wait_for("questdb", || IlpPool::connect(endpoints.clone(), pool_mode, ilp_opts, probe_every)).await?;
+    Ok(pool.verified(ledger).guarded(guard))
 }
 
-fn to_ilp_line(t: &NormTrade, msg_id: &str) -> String {
-    format!(
-        "trades,symbol={} price={},qty={},trade_id={}i,is_bm={},msg_id=\"{}\",ts_ms={}i {}",
-        t.symbol,
-        t.price,
-        t.qty,
-        t.trade_id,
-        t.is_bm,
-        msg_id.replace('\"', "\\\""),
-        t.ts_ms,
-        (t.ts_ms as i128) * 1_000_000i128 // ms -> ns
-    )
+/// Build the sinks named in `names` (`SINKS`), each with its own retry and DLQ
+/// settings; the questdb sink records what it writes in `ledger` and is capped by `guard`.
+async fn sinks(names: &str, default_retries: u32, ledger: Option<Arc<Mutex<Ledger>>>, mut guard: Option<Guard>) -> Result<Vec<Slot>> {
+    let mut slots: Vec<Slot> = Vec::new();
+    for name in names.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        anyhow::ensure!(slots.iter().all(|s| s.sink.name() != name), "sink {} listed twice in SINKS", name);
+        let sink: Box<dyn Sink> = match name {
+            "questdb" => Box::new(questdb(ledger.clone(), guard.take()).await?),
+            "parquet" => Box::new(ParquetSink::new(ArchiveOptions {
+                dir: env("ARCHIVE_DIR", "archive").into(),
+                row_group_rows: env("ARCHIVE_ROW_GROUP_ROWS", "100000").parse().unwrap_or(100_000).max(1),
+                roll_rows: env("ARCHIVE_ROLL_ROWS", "1000000").parse().unwrap_or(1_000_000).max(1),
+                roll_every: Duration::from_secs(env("ARCHIVE_ROLL_SECS", "3600").parse().unwrap_or(3600)),
+            })?),
+            #[cfg(feature = "duckdb")]
+            "duckdb" => Box::new(consumer::duck::DuckDbSink::open(env("DUCKDB_PATH", "trades.duckdb").as_ref())?),
+            #[cfg(not(feature = "duckdb"))]
+            "duckdb" => anyhow::bail!("SINKS=duckdb requires building with the `duckdb` feature"),
+            other => anyhow::bail!("unknown sink {:?} in SINKS (expected questdb|parquet|duckdb)", other),
+        };
+        let key = name.to_uppercase();
+        let dlq_topic = env(format!("SINK_{}_DLQ", key), "");
+        let retries = env(format!("SINK_{}_RETRIES", key), &default_retries.to_string()).parse().unwrap_or(default_retries);
+        let backoff = Backoff::from_env(&format!("SINK_{}", key), Duration::from_millis(100), Duration::from_secs(30));
+        let mut policy = Policy::times(retries, backoff);
+        // Off unless set: a run of this many failures skips the sink for the cooldown.
+        let trip: u32 = env(format!("SINK_{}_BREAKER_FAILURES", key), "0").parse().unwrap_or(0);
+        if trip > 0 {
+            let cooldown = Duration::from_millis(env(format!("SINK_{}_BREAKER_COOLDOWN_MS", key), "30000").parse().unwrap_or(30_000));
+            policy = policy.breaker(Arc::new(Breaker::new(&format!("sink_{}", name), trip, cooldown)));
+        }
+        slots.push(Slot { sink, policy, dlq_topic });
+    }
+    anyhow::ensure!(!slots.is_empty(), "SINKS is empty");
+    Ok(slots)
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    init_metrics(9466);
-    init_tracing();
+/// Write the book builder's snapshots from `topic` into `table`, one row
+/// each, retried on `BOOK_WRITE_RETRIES`. Snapshots are state rather than
+/// events, so offsets are committed on an interval. A row that still fails
+/// ends the task, which stops the consumer.
+async fn books(bus: BusConfig, topic: String, group: String, table: String) -> Result<()> {
+    let mut subscriber = bus.subscriber(&topic, &group).await?;
+    let mut pool = questdb(None, None).await?;
+    let mut committer = Committer::new(CommitStrategy::Interval(Duration::from_secs(1)));
+    let policy = Policy::from_env("BOOK_WRITE", 5, Duration::from_millis(100), Duration::from_secs(5));
+    tracing::info!(target="consumer", topic=%topic, table=%table, "writing book snapshots");
+    while let Some(next) = subscriber.next().await {
+        let msg = match next {
+            Ok(msg) => msg,
+            Err(e) => {
+                tracing::error!(target="consumer", error=?e, "book poll error");
+                errors::escalate("consumer", bus::error::kind(&e), e)?;
+                continue;
+            }
+        };
+        match msg.payload_str().map(serde_json::from_str::<BookSnapshot>) {
+            Some(Ok(snapshot)) => {
+                if let Some(bps) = snapshot.spread_bps() {
+                    gauge!("spread_bps", "symbol" => snapshot.symbol.clone()).set(bps);
+                }
+                let line = snapshot.to_ilp_line(&table) + "\n";
+                let mut retry = policy.start("questdb_write");
+                while let Err(e) = pool.write_chunks(&[line.as_bytes()]).await {
+                    tracing::warn!(target="consumer", error=?e, "book write failed");
+                    errors::record("consumer", ErrorKind::Transient);
+                    if !retry.failed(&e).await {
+                        return Err(e).context("writing book snapshots to QuestDB");
+                    }
+                }
+                counter!("book_rows_total").increment(1);
+            }
+            _ => counter!("book_dropped_total").increment(1),
+        }
+        if let Err(e) = committer.done(subscriber.as_ref(), msg).await {
+            tracing::warn!(target="consumer", error=?e, "book commit failed");
+            errors::escalate("consumer", bus::error::kind(&e), e)?;
+        }
+    }
+    Ok(())
+}
 
-    let brokers  = env("KAFKA_BROKERS", "localhost:29092");
-    let topic_in = env("TOPIC_IN", "ticks.norm");
-    let group_id = env("GROUP_ID", "consumer-stage");
-    let ilp_host = env("QDB_HOST", "localhost");
-    let ilp_port: u16 = env("QDB_ILP_PORT", "9009").parse().unwrap_or(9009);
+/// What to do with a batch a sink lost (after its retries, with no DLQ).
+enum OnLoss {
+    /// Hold it and stop fetching until it's written.
+    Pause,
+    /// Drop it and move on; the next commit on its partitions moves past it.
+    Drop,
+    /// Write it to disk and commit it; pause if that fails too.
+    Spill,
+}
 
-    let consumer: StreamConsumer = ClientConfig::new()
-        .set("bootstrap.servers", &brokers)
-        .set("group.id", &group_id)
-        .set("enable.partition.eof", "false")
-        .set("auto.offset.reset", "latest")
-        // soften control-plane timeouts & keepalive to cut req timeouts:
-        .set("socket.keepalive.enable", "true")
-        .set("request.timeout.ms", "20000")
-        .create()?;
-    consumer.subscribe(&[&topic_in])?;
-
-    let mut ilp = ilp_connect(&ilp_host, ilp_port).await?;
-    let mut last_lag_update = Instant::now();
-
-    let mut stream = consumer.stream();
-    while let Some(result) = stream.next().await {
-        let msg = match result {
-            Ok(m) => m,
-            Err(e) => { tracing::error!(target="consumer", error=?e, "poll error"); continue; }
-        };
+/// A message in the batch that hasn't been decoded yet. The batch holds the
+/// message itself; this is only where it is and when it came.
+struct Pending {
+    /// Into the batch's deliveries.
+    index: usize,
+    /// For `e2e_latency_ms`, which shouldn't include the wait for the batch.
+    received_ns: i64,
+}
 
-        let payload = match msg.payload_view::<str>() {
-            Some(Ok(s)) => s,
-            _ => { tracing::warn!(target="consumer", "empty/invalid payload"); continue; }
-        };
+/// [`row`]'s answer, with the trade's ILP line.
+type Decoded = Result<Option<(Row, String)>, Error>;
+
+/// [`row`] for each pending message, by its index in `deliveries`; a symbol in
+/// `tables` is written to its own table.
+fn decode_all(deliveries: &[Delivery], pending: &[Pending], keys: Option<&Keyring>, dedup: bool, tables: &HashMap<String, String>, registry: Option<&Registry>) -> Vec<(usize, Decoded)> {
+    pending.iter()
+        .map(|p| {
+            let msg = &deliveries[p.index];
+            let res = row(msg, p.received_ns, keys, dedup, registry).map(|trade| trade.map(|trade| {
+                let msg_id = msg.header(MSG_ID).unwrap_or("").to_string();
+                let line = match tables.get(&trade.symbol) {
+                    Some(table) => to_ilp_line_in(table, &trade, &msg_id),
+                    None => to_ilp_line(&trade, &msg_id),
+                };
+                (Row { trade, msg_id, topic: msg.topic.clone(), partition: msg.partition() }, line)
+            }));
+            (p.index, res)
+        })
+        .collect()
+}
 
-        counter!("consumed_total").increment(1);
+/// The trade; `None` for a replay we don't write (unless `dedup`), an error
+/// for one we drop. `keys` checks the producer's signature; `registry` has
+/// the Avro writer schemas, if Avro is read at all.
+fn row(msg: &Delivery, received_ns: i64, keys: Option<&Keyring>, dedup: bool, registry: Option<&Registry>) -> Result<Option<NormTrade>, Error> {
+    if msg.payload.is_empty() {
+        return Err(Error::Empty);
+    }
 
-        // E2E latency
-        let now_ns = Utc::now().timestamp_nanos_opt().unwrap();
-        let ts_produce_ns: i64 = header_str(&msg, "ts_produce_ns")
-            .and_then(|s| s.parse::<i64>().ok())
-            .unwrap_or(now_ns);
-        let e2e_ms = (now_ns - ts_produce_ns) as f64 / 1e6;
-        histogram!("e2e_latency_ms").record(e2e_ms);
+    counter!("consumed_total").increment(1);
 
-        // Parse and write via ILP
-        let t: NormTrade = match serde_json::from_str(payload) {
-            Ok(v) => v,
-            Err(e) => { tracing::error!(target="consumer", error=?e, "parse error"); continue; }
-        };
-        let msg_id = header_str(&msg, "msg_id").unwrap_or("");
+    if keys.is_some_and(|k| !k.check("consumer", &msg.payload, &msg.headers)) {
+        return Err(Error::Signature);
+    }
+
+    // A major we don't know may not even be a trade; skip it rather than guess.
+    let compat = envelope::compat(&msg.headers, NORM_SCHEMA);
+    match &compat {
+        Compat::Supported(_) => {}
+        Compat::Unversioned => counter!("schema_unversioned_total").increment(1),
+        Compat::Unsupported(v) => {
+            counter!("schema_rejected_total", "schema_version" => v.clone()).increment(1);
+            return Err(Error::Schema(v.clone()));
+        }
+    }
+
+    // Backfills are read out of QuestDB; without dedup, writing them back would duplicate rows.
+    if msg.headers.get(REPLAYED) == Some("true") {
+        if !dedup {
+            counter!("replayed_skipped_total").increment(1);
+            return Ok(None);
+        }
+        counter!("replayed_written_total").increment(1);
+    }
 
-        let line = to_ilp_line(&t, msg_id);
-        let payload = format!("{}\n", line);
+    // E2E latency
+    let ts_produce_ns: i64 = msg.headers.get(TS_PRODUCE_NS)
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(received_ns);
+    let e2e_ms = (received_ns - ts_produce_ns) as f64 / 1e6;
+    histogram!("e2e_latency_ms").record(e2e_ms);
 
-        let write_res = {
-            let (res, write_ms) = measure_ms_async(ilp.write_all(payload.as_bytes())).await;
-            histogram!("questdb_write_ms").record(write_ms);
-            res
+    let codec = msg.headers.get(envelope::CODEC);
+    // Named by what we understood, so a bad header can't mint label values.
+    let label = match codec {
+        Some("avro") if registry.is_some() => "avro",
+        _ => codec.map_or(Some(codec::Codec::Json), codec::Codec::parse).map_or("unknown", codec::Codec::name),
+    };
+    match decode_as(&msg.payload, codec, &compat, registry) {
+        Ok(t) => {
+            counter!("codec_total", "codec" => label).increment(1);
+            Ok(Some(t))
+        }
+        Err(e) => {
+            counter!("codec_rejected_total", "codec" => label).increment(1);
+            Err(e)
+        }
+    }
+}
+
+/// Log and count a message [`row`] refused or that was too big to read; what
+/// to dead-letter for it when there's a `dlq` and a `payload` to send.
+fn refused(key: Option<&str>, payload: Option<&[u8]>, headers: &Headers, e: &Error, dlq: bool) -> Option<(String, Vec<u8>, Headers)> {
+    tracing::warn!(target="consumer", reason = e.reason(), error = %e, kind = e.kind().label(), "dropping message");
+    counter!("dropped_total", "reason" => e.reason()).increment(1);
+    errors::record("consumer", e.kind());
+    let payload = payload.filter(|_| dlq)?;
+    let headers = headers.clone().with(DLQ_REASON, e.reason()).with(DLQ_ERROR, &e.to_string());
+    Some((key.unwrap_or_default().to_string(), payload.to_vec(), headers))
+}
+
+struct Stage {
+    subscriber: Box<dyn Subscriber>,
+    tee: Tee,
+    batch: Batch,
+    watermarks: Watermarks,
+    /// QuestDB upserts on (timestamp, symbol, trade_id), so replays are safe to write.
+    dedup: bool,
+    /// Checks the producer's signatures; `None` when no signing keys are set.
+    keys: Option<Arc<Keyring>>,
+    /// Messages over it are dropped or dead-lettered unread (`MAX_PAYLOAD_BYTES`).
+    limit: Option<Limit>,
+    /// Taken since the last flush and not decoded yet.
+    pending: Vec<Pending>,
+    /// Batches with at least this many messages are decoded on the blocking pool; 0 never.
+    offload_min: usize,
+    /// Avro writer schemas; `None` when `SCHEMA_REGISTRY_URL` is empty and Avro is refused.
+    registry: Option<Arc<Registry>>,
+    /// Retries schema fetches, and fails them fast while the registry is down.
+    registry_policy: Policy,
+    /// Routed symbols' own trades tables, by symbol (`ROUTES`).
+    tables: Arc<HashMap<String, String>>,
+    /// Bars and the table they go to; `None` when `ROLLUP_TABLE` is empty.
+    rollup: Option<(Rollup, String)>,
+    /// Volume profiles and their table; `None` when `PROFILE_TABLE` is empty.
+    profile: Option<(Profile, String)>,
+    /// Tick and imbalance bars and their table; `None` when `TRADE_BARS` is empty.
+    trade_bars: Option<(TradeBars, String)>,
+    /// Trades inside its maintenance windows are stored but left out of bars.
+    calendar: Calendar,
+    /// For watermarks and bars; `None` when neither topic is set.
+    publisher: Option<Arc<dyn Publisher>>,
+    /// Where dropped messages go as they came; `None` when `DROPPED_DLQ` is empty.
+    dropped_dlq: Option<(Arc<dyn Publisher>, String)>,
+    /// Dropped messages in the batch, as `(key, payload, headers)`, dead-lettered before it's committed.
+    dropped_out: Vec<(String, Vec<u8>, Headers)>,
+    wm_topic: String,
+    /// Empty publishes no bars.
+    bars_topic: String,
+    /// Bars in the batch, published once it's written.
+    bars_out: Vec<Bar>,
+    bars_policy: Policy,
+    wm_every: Duration,
+    last_lag_update: Instant,
+    committer: Committer,
+    /// Committed offsets for `CHECKPOINT_TABLE`; `None` when it's empty or at most once.
+    checkpoints: Option<Checkpoints>,
+    checkpoint_every: Duration,
+    last_checkpoint: Instant,
+    /// Offsets are auto-committed on receipt; no commits or lag lookups here.
+    at_most_once: bool,
+    on_loss: OnLoss,
+    /// Where `OnLoss::Spill` and a batch over budget under `Overflow::Spill` go.
+    spill: Option<Spill>,
+    /// A lost batch is being held and the subscription is paused.
+    paused: bool,
+    /// The buffer over budget under `Overflow::Block`; the subscription is
+    /// paused, as for a held batch, until it fits.
+    blocked: Option<&'static str>,
+    /// The batch went over budget under `Overflow::DropOldest` or `Spill`:
+    /// the next flush sheds it rather than write it.
+    shed_batch: bool,
+    /// Kafka drops a member that stops polling for `max.poll.interval.ms`, so
+    /// it's still polled while paused, with its partitions paused.
+    poll_paused: bool,
+    /// What that polling turned up: already fetched, or from a partition
+    /// assigned meanwhile. Taken, in order, once the subscription resumes.
+    held: VecDeque<Delivery>,
+    batch_budget: Budget,
+    wm_budget: Budget,
+    rollup_budget: Budget,
+    profile_budget: Budget,
+    load: Load,
+}
+
+impl Stage {
+    /// Keep one message for the batch commit; it's decoded when the batch is
+    /// flushed, unless it's over `limit` and never read at all.
+    fn take(&mut self, msg: Delivery) {
+        if let Some(limit) = self.limit {
+            if let Some(action) = limit.exceeded("consumer", msg.payload.len()) {
+                self.oversize(&msg, limit, action);
+                // Held only to be committed, so the payload needn't be.
+                self.batch.push_delivery(msg.stripped());
+                return;
+            }
+        }
+        self.pending.push(Pending {
+            index: self.batch.deliveries().len(),
+            received_ns: Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+        });
+        self.batch.push_delivery(msg);
+    }
+
+    /// Decode and ILP-encode the messages taken since the last flush, on the
+    /// blocking pool when there are at least `offload_min` of them.
+    async fn decode_pending(&mut self) -> Result<()> {
+        // A registry that's down only costs the trades that need it: their
+        // schemas stay unknown, and they're refused as `Error::Registry`.
+        if let Some(registry) = &self.registry {
+            let deliveries = self.batch.deliveries();
+            let ids: Vec<u32> = self.pending.iter()
+                .map(|p| &deliveries[p.index])
+                .filter(|msg| msg.headers.get(envelope::CODEC) == Some("avro"))
+                .filter_map(|msg| avro::unframe(&msg.payload).ok().map(|(id, _)| id))
+                .filter(|id| !registry.known(*id))
+                .collect();
+            if !ids.is_empty() {
+                if let Err(e) = self.registry_policy.run("schema_registry", || registry.fetch(ids.iter().copied())).await {
+                    tracing::warn!(target="consumer", error=?e, "schema registry unavailable; refusing the Avro trades that need it");
+                }
+            }
+        }
+        let pending = std::mem::take(&mut self.pending);
+        let (keys, dedup, tables, registry) = (self.keys.clone(), self.dedup, self.tables.clone(), self.registry.clone());
+        let decoded = match self.offload_min > 0 && pending.len() >= self.offload_min {
+            true => {
+                // Lent to the blocking pool and handed back, rather than copied.
+                let deliveries = self.batch.take_deliveries();
+                let (deliveries, decoded) = runtime::cpu(move || {
+                    let decoded = decode_all(&deliveries, &pending, keys.as_deref(), dedup, &tables, registry.as_deref());
+                    (deliveries, decoded)
+                }).await?;
+                self.batch.restore_deliveries(deliveries);
+                decoded
+            }
+            false => decode_all(self.batch.deliveries(), &pending, keys.as_deref(), dedup, &tables, registry.as_deref()),
         };
+        for (i, res) in decoded {
+            match res {
+                Ok(Some((row, line))) => self.keep(row, &line),
+                Ok(None) => {}
+                Err(e) => {
+                    let msg = &self.batch.deliveries()[i];
+                    let held = refused(msg.key.as_deref(), Some(&msg.payload), &msg.headers, &e, self.dropped_dlq.is_some());
+                    self.dropped_out.extend(held);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a decoded trade to the batch and to whatever is built from it.
+    fn keep(&mut self, row: Row, line: &str) {
+        let trade = &row.trade;
+        let maintenance = self.calendar.in_maintenance(trade.ts_ms);
+        if let Some((rollup, _)) = &mut self.rollup {
+            if maintenance {
+                counter!("maintenance_skipped_total", "stage" => "rollup").increment(1);
+            } else {
+                rollup.add(trade);
+            }
+        }
+        if let Some((profile, _)) = &mut self.profile {
+            if maintenance {
+                counter!("maintenance_skipped_total", "stage" => "profile").increment(1);
+            } else {
+                profile.add(trade);
+            }
+        }
+        if let Some((bars, _)) = &mut self.trade_bars {
+            if maintenance {
+                counter!("maintenance_skipped_total", "stage" => "trade_bars").increment(1);
+            } else {
+                bars.add(trade);
+            }
+        }
+        self.batch.push_encoded(row, line);
+    }
+
+    /// Log and count a message over `limit`, and hold it (or its head) for the
+    /// DLQ unless the action is to drop it.
+    fn oversize(&mut self, msg: &Delivery, limit: Limit, action: Action) {
+        let payload = Some(limit.dead_letter(&msg.payload)).filter(|_| action != Action::Drop);
+        let headers = msg.headers.clone().with(OVERSIZE_BYTES, &msg.payload.len().to_string());
+        let held = refused(msg.key.as_deref(), payload, &headers, &Error::Oversize(msg.payload.len()), self.dropped_dlq.is_some());
+        self.dropped_out.extend(held);
+    }
+
+    /// Dead-letter the batch's dropped messages. A failed publish is counted,
+    /// not retried, and only returned if it's fatal.
+    async fn publish_dropped(&mut self) -> Result<()> {
+        let Some((publisher, topic)) = &self.dropped_dlq else { return Ok(()) };
+        for (key, payload, headers) in self.dropped_out.drain(..) {
+            match publisher.publish(topic, &key, &payload, &headers).await {
+                Ok(()) => counter!("dropped_dlq_total").increment(1),
+                Err(e) => {
+                    tracing::warn!(target="consumer", error=?e, topic=%topic, "dropped message DLQ publish failed");
+                    counter!("dropped_dlq_failed_total").increment(1);
+                    errors::escalate("consumer", bus::error::kind(&e), e)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the batch to disk so it can be committed, if there's a spill
+    /// directory; `why` is for the log.
+    fn spilled(&mut self, why: &str) -> bool {
+        let Some(spill) = &mut self.spill else { return false };
+        match spill.write(self.batch.deliveries()) {
+            Ok(path) => {
+                tracing::warn!(target="consumer", rows = self.batch.rows(), path = %path.display(), "{}; spilled to disk", why);
+                counter!("spilled_total").increment(self.batch.deliveries().len() as u64);
+                self.tee.skip();
+                true
+            }
+            Err(e) => {
+                tracing::error!(target="consumer", error=?e, "spilling a batch failed");
+                errors::record("consumer", ErrorKind::Transient);
+                false
+            }
+        }
+    }
+
+    /// Publish the written batch's bars, keyed by symbol, each retried on
+    /// `bars_policy`. A bar that still fails fails the flush before the batch
+    /// is committed, so the consumer stops rather than move past it.
+    async fn publish_bars(&mut self) -> Result<()> {
+        let Some(publisher) = &self.publisher else { return Ok(()) };
+        let headers = Headers::new();
+        for bar in self.bars_out.drain(..) {
+            let body = serde_json::to_vec(&bar)?;
+            let sent = self.bars_policy.run("bars_publish", || publisher.publish(&self.bars_topic, &bar.symbol, &body, &headers)).await;
+            if let Err(e) = sent {
+                counter!("bars_publish_failed_total").increment(1);
+                errors::record("consumer", bus::error::kind(&e));
+                return Err(e.context(format!("publishing the {} bar to {}", bar.symbol, self.bars_topic)));
+            }
+        }
+        Ok(())
+    }
 
-        if let Err(e) = write_res {
-            tracing::warn!(target="consumer", error=?e, "ILP write failed; reconnecting once");
-            ilp = match ilp_connect(&ilp_host, ilp_port).await {
-                Ok(s) => s,
-                Err(e) => { tracing::error!(target="consumer", error=?e, "ILP reconnect failed"); continue; }
+    /// Write the batch in one go, commit it, then do periodic housekeeping.
+    async fn flush(&mut self) -> Result<()> {
+        // Decoding and sinks all happen here; waiting for messages is the rest.
+        let _busy = self.load.busy();
+        // Blocked, an empty flush still closes windows, so the buffer can drain.
+        if self.batch.is_empty() && self.blocked.is_none() {
+            return Ok(());
+        }
+        self.decode_pending().await?;
+        // Before anything can commit them; they never reach a sink, so a lost batch doesn't hold them.
+        self.publish_dropped().await?;
+        // A held batch is retried as is: sinks that already took it mustn't be sent new bars.
+        if let Some((rollup, table)) = self.rollup.as_mut().filter(|_| !self.paused) {
+            let mut bars = rollup.close(Utc::now().timestamp_millis());
+            if self.rollup_budget.over(rollup.bytes()) && self.rollup_budget.overflow() == Overflow::DropOldest {
+                bars.extend(rollup.shed(self.rollup_budget.limit()));
+                self.rollup_budget.overflowed();
+            }
+            for bar in bars {
+                self.batch.push_line(&bar.to_ilp_line(table));
+                if !self.bars_topic.is_empty() {
+                    self.bars_out.push(bar);
+                }
+            }
+        }
+        if let Some((profile, table)) = self.profile.as_mut().filter(|_| !self.paused) {
+            let mut levels = profile.close(Utc::now().timestamp_millis());
+            if self.profile_budget.over(profile.bytes()) && self.profile_budget.overflow() == Overflow::DropOldest {
+                levels.extend(profile.shed(self.profile_budget.limit()));
+                self.profile_budget.overflowed();
+            }
+            counter!("profile_levels_total").increment(levels.len() as u64);
+            for level in levels {
+                self.batch.push_line(&level.to_ilp_line(table));
+            }
+        }
+        if let Some((bars, table)) = self.trade_bars.as_mut().filter(|_| !self.paused) {
+            for bar in bars.take_closed() {
+                self.batch.push_line(&bar.to_ilp_line(table));
+            }
+        }
+        // The admin API's view of what this flush is holding.
+        obsv::admin::set("in_flight", "consumer", serde_json::json!({
+            "rows": self.batch.rows(),
+            "uncommitted": self.committer.pending(),
+            "paused": self.paused,
+        }));
+        // Shed, it never reaches a sink: spilled, or dropped and committed past.
+        let shed = std::mem::take(&mut self.shed_batch).then(|| self.batch_budget.overflow());
+        if self.batch.has_lines() {
+            let written = shed.is_none() && self.tee.write(&self.batch).await;
+            let spilled = !written && match shed {
+                Some(Overflow::Spill) => self.spilled("batch over its memory budget"),
+                Some(_) => false,
+                None => matches!(self.on_loss, OnLoss::Spill) && self.spilled("batch lost by a sink"),
             };
-            if let Err(e2) = ilp.write_all(payload.as_bytes()).await {
-                tracing::error!(target="consumer", error=?e2, "ILP write still failing after reconnect");
-                continue;
+            if shed == Some(Overflow::DropOldest) {
+                tracing::warn!(target="consumer", rows = self.batch.rows(), "batch over its memory budget; dropping it, and committing past it");
+                counter!("buffer_dropped_total", "buffer" => "batch").increment(self.batch.deliveries().len() as u64);
+                self.tee.skip();
+            } else if !written && !spilled {
+                if let OnLoss::Drop = self.on_loss {
+                    tracing::error!(target="consumer", rows = self.batch.rows(), "batch lost by a sink; dropping it, and the next commit moves past it");
+                    self.tee.skip();
+                    self.batch.clear();
+                    self.bars_out.clear();
+                } else if !self.paused {
+                    tracing::warn!(target="consumer", rows = self.batch.rows(), "batch lost by a sink; pausing until it's written");
+                    self.subscriber.pause()?;
+                    self.paused = true;
+                    gauge!("paused").set(1.0);
+                }
+                return Ok(());
+            }
+            if self.paused {
+                if self.blocked.is_none() {
+                    self.subscriber.resume()?;
+                }
+                self.paused = false;
+                gauge!("paused").set(0.0);
+                tracing::info!(target="consumer", rows = self.batch.rows(), "held batch written; resuming");
             }
+            if written {
+                self.batch.observe_flush();
+                for (symbol, m) in self.batch.marks() {
+                    self.watermarks.record_range(symbol, m.partition.map(|p| (m.topic, p)), m.first_trade_id, m.trade_id, m.ts_ms);
+                }
+                if self.wm_budget.over(self.watermarks.bytes()) && self.wm_budget.overflow() == Overflow::DropOldest {
+                    self.watermarks.shed(self.wm_budget.limit());
+                    self.wm_budget.overflowed();
+                }
+                self.publish_bars().await?;
+            }
+            // Spilled batches never reach QuestDB, so neither do their bars' topic copies.
+            self.bars_out.clear();
         }
 
-        // Commit offset (timed)
-        let (_, commit_ms) = measure_ms(|| {
-            let _ = consumer.commit_message(&msg, rdkafka::consumer::CommitMode::Async);
-        });
-        histogram!("commit_latency_ms").record(commit_ms);
+        // Finished once written (or dropped, or spilled), not when they came in.
+        let finished = self.batch.deliveries().len() as u64;
 
         // --- Lag gauge: update at most every 5s, with a 2s call timeout ---
-        if last_lag_update.elapsed() >= Duration::from_secs(5) {
-            if let Ok((_, high)) = consumer.fetch_watermarks(
-                msg.topic(), msg.partition(), Duration::from_secs(2)
-            ) {
-                let pos = msg.offset();
-                let lag = (high - (pos + 1)).max(0);
-                gauge!("consumer_lag").set(lag as f64);
+        // (skipped at most once: the watermark fetch is a broker round trip)
+        if !self.at_most_once && self.last_lag_update.elapsed() >= Duration::from_secs(5) {
+            if let Some(last) = self.batch.deliveries().last() {
+                if let Some(lag) = self.subscriber.lag(last).await {
+                    gauge!("consumer_lag").set(lag as f64);
+                }
+            }
+            self.last_lag_update = Instant::now();
+        }
+
+        if !self.at_most_once {
+            // Commit offsets (timed), as often as COMMIT_STRATEGY asks
+            let sub = self.subscriber.as_ref();
+            let t0 = Instant::now();
+            let mut committed = false;
+            for d in self.batch.take_deliveries() {
+                if let Some(cp) = &mut self.checkpoints {
+                    cp.done(&d);
+                }
+                let res = self.committer.done(sub, d).await;
+                committed |= checkpoint(res, &mut self.checkpoints)?;
+            }
+            let res = self.committer.flushed(sub).await;
+            committed |= checkpoint(res, &mut self.checkpoints)?;
+            if committed {
+                histogram!("commit_latency_ms").record(t0.elapsed().as_secs_f64() * 1e3);
+            }
+        }
+        self.batch.clear();
+        self.load.processed(finished);
+
+        // Into the next batch, so they go out with its trades: an idle consumer adds none.
+        if let Some(cp) = self.checkpoints.as_mut().filter(|_| self.last_checkpoint.elapsed() >= self.checkpoint_every) {
+            for line in cp.lines(&self.watermarks.by_partition(), Utc::now().timestamp_nanos_opt().unwrap_or_default()) {
+                self.batch.push_line(&line);
+            }
+            self.last_checkpoint = Instant::now();
+        }
+        self.block_on_budgets()
+    }
+
+    /// Pause the subscription while a buffer under `Overflow::Block` is over
+    /// its budget, and resume once they all fit again.
+    fn block_on_budgets(&mut self) -> Result<()> {
+        let rollup = self.rollup.as_ref().map_or(0, |(r, _)| r.bytes());
+        let profile = self.profile.as_ref().map_or(0, |(p, _)| p.bytes());
+        let over = [(&self.wm_budget, self.watermarks.bytes()), (&self.rollup_budget, rollup), (&self.profile_budget, profile)]
+            .into_iter()
+            .find(|(b, used)| b.overflow() == Overflow::Block && b.over(*used))
+            .map(|(b, _)| b);
+        match (over, self.blocked) {
+            (Some(b), None) => {
+                tracing::warn!(target="consumer", buffer = b.name(), limit = b.limit(), "buffer over its memory budget; pausing until it fits");
+                b.overflowed();
+                self.blocked = Some(b.name());
+                if !self.paused {
+                    self.subscriber.pause()?;
+                }
+            }
+            (None, Some(name)) => {
+                tracing::info!(target="consumer", buffer = name, "buffer back under its memory budget; resuming");
+                self.blocked = None;
+                if !self.paused {
+                    self.subscriber.resume()?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Watermarks: gauges always, compacted topic when configured. Run every
+    /// `wm_every` from [`run`](Self::run), so an idle partition's gaps still
+    /// time out and its lag keeps growing.
+    async fn tick_watermarks(&mut self) -> Result<()> {
+        for wm in self.watermarks.tick(Utc::now().timestamp_millis()) {
+            let Some(publisher) = self.publisher.as_ref().filter(|_| !self.wm_topic.is_empty()) else { continue };
+            let body = serde_json::to_vec(&wm)?;
+            if let Err(e) = publisher.publish(&self.wm_topic, &wm.symbol, &body, &Headers::new()).await {
+                tracing::warn!(target="consumer", error=?e, symbol=%wm.symbol, "watermark publish failed");
+                errors::escalate("consumer", bus::error::kind(&e), e)?;
+            }
+        }
+        Ok(())
+    }
+    /// Consume and flush until the subscription closes or a signal stops the
+    /// stage; the caller flushes and commits what's left.
+    async fn run(&mut self, schedule: FlushSchedule, pause_retry: Duration) -> Result<()> {
+        let mut stop = std::pin::pin!(runtime::stopping());
+        let mut retry_at = None;
+        let mut wm_tick = tokio::time::interval(self.wm_every);
+        wm_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            // Held back by a sink or a buffer over budget; either way, flushed again after a while.
+            if self.paused || self.blocked.is_some() {
+                let at = *retry_at.get_or_insert_with(|| tokio::time::Instant::now() + pause_retry);
+                let poll = async {
+                    match self.poll_paused {
+                        true => self.subscriber.next().await,
+                        false => std::future::pending().await,
+                    }
+                };
+                tokio::select! {
+                    _ = tokio::time::sleep_until(at) => {}
+                    next = poll => {
+                        match next {
+                            None => break,
+                            Some(Ok(msg)) => {
+                                // A partition assigned since the pause starts unpaused.
+                                self.subscriber.pause()?;
+                                self.held.push_back(msg);
+                            }
+                            Some(Err(e)) => {
+                                tracing::error!(target="consumer", error=?e, "poll error");
+                                errors::escalate("consumer", bus::error::kind(&e), e)?;
+                            }
+                        }
+                        continue;
+                    }
+                    _ = wm_tick.tick() => {
+                        self.tick_watermarks().await?;
+                        continue;
+                    }
+                    _ = &mut stop => break,
+                }
+                retry_at = None;
+                self.flush().await?;
+                continue;
+            }
+            // Block for the first message of a batch; after that, only until the batch is due.
+            let left = self.batch.started().map(|t0| schedule.wait(t0, Instant::now(), Utc::now().timestamp_millis()));
+            // Taken in the same poll that returns it, so a tick winning the select can't drop it.
+            let wait = async {
+                match (self.held.pop_front(), left) {
+                    (Some(msg), _) => Ok(Some(Ok(msg))),
+                    (None, None) => Ok(self.subscriber.next().await),
+                    (None, Some(left)) => tokio::time::timeout(left, self.subscriber.next()).await,
+                }
+            };
+            let next = tokio::select! {
+                next = wait => next,
+                _ = wm_tick.tick() => {
+                    self.tick_watermarks().await?;
+                    continue;
+                }
+                _ = &mut stop => break,
+            };
+            let next = match next {
+                Ok(next) => next,
+                Err(_) => { self.flush().await?; continue; }
+            };
+            let Some(result) = next else { break };
+            match result {
+                Ok(msg) => self.take(msg),
+                Err(e) => {
+                    tracing::error!(target="consumer", error=?e, "poll error");
+                    errors::escalate("consumer", bus::error::kind(&e), e)?;
+                    continue;
+                }
+            }
+            // Over its memory budget, a batch is flushed early rather than grown,
+            // or shed under drop_oldest or spill.
+            let full = schedule.full(self.batch.deliveries().len());
+            if full || self.batch_budget.over(self.batch.bytes()) {
+                if !full {
+                    self.batch_budget.overflowed();
+                    self.shed_batch = self.batch_budget.overflow() != Overflow::Block;
+                }
+                self.flush().await?;
             }
-            last_lag_update = Instant::now();
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!("chaos", "nats", "pulsar", "duckdb"), run())
+}
+
+async fn run() -> Result<()> {
+    // Overridden by SLOS; an empty SLOS turns them off.
+    init_tracing();
+    init_metrics_with_slos(9466, "e2e_p99=e2e_latency_ms<250@99,loss=sink_dropped_rows_total/consumed_total@99.99,overflow=ilp_overflow_rows_total/consumed_total@99.99")?;
+    // The stage loop (polling, and the trades' sink writes) runs on this thread.
+    runtime::pin_current("poll");
+
+    let topic_in = env("TOPIC_IN", "ticks.norm");
+    // A routed topic's deployment takes its sinks and tables from ROUTES, and
+    // its own group by default, so it doesn't rebalance with the main one.
+    let routes = Routes::from_env()?;
+    let routed = routes.on(&topic_in);
+    let group_id = match routed.is_empty() {
+        true => env("GROUP_ID", "consumer-stage"),
+        false => env("GROUP_ID", &format!("consumer-stage.{}", topic_in)),
+    };
+    let sink_names = env("SINKS", &routed.first().map(|r| r.sinks.join(",")).filter(|s| !s.is_empty()).unwrap_or_else(|| "questdb".into()));
+    let routed_tables: HashMap<String, String> = routes.tables(&topic_in).into_iter().map(|(s, t)| (s, namespace::table(&t))).collect();
+    if !routed.is_empty() {
+        tracing::info!(target="consumer", topic=%topic_in, symbols=?routed.iter().map(|r| &r.symbol).collect::<Vec<_>>(),
+            tables=?routed_tables, sinks=%sink_names, "consuming a routed topic");
+    }
+    // at_most_once trades delivery guarantees for latency: see the README.
+    let at_most_once = match env("DELIVERY_MODE", "at_least_once").as_str() {
+        "at_least_once" => false,
+        "at_most_once" => true,
+        other => anyhow::bail!("unknown DELIVERY_MODE {:?} (expected at_least_once|at_most_once)", other),
+    };
+    // Ignored at most once, where librdkafka commits on its own.
+    let commit_strategy = CommitStrategy::from_env("flush")?;
+    // A batch is flushed at this many messages, or this long after its first one.
+    // At most once, it's flushed as soon as nothing more is ready.
+    let max_rows: usize = env("COALESCE_MAX_ROWS", "1000").parse().unwrap_or(1000).max(1);
+    let default_wait: u64 = if at_most_once { 0 } else { 2 };
+    let max_wait = Duration::from_millis(env("COALESCE_MAX_WAIT_MS", &default_wait.to_string()).parse().unwrap_or(default_wait));
+    // Set to QuestDB's commit lag to flush once per commit window instead.
+    let align_ms: u64 = env("QDB_FLUSH_ALIGN_MS", "0").parse().unwrap_or(0);
+    let schedule = FlushSchedule { max_rows, max_wait, align: Some(Duration::from_millis(align_ms)).filter(|_| align_ms > 0) };
+    // Empty WATERMARK_TOPIC keeps the gauges but publishes nothing.
+    let wm_topic = env("WATERMARK_TOPIC", "watermarks");
+    let wm_every = Duration::from_millis(env("WATERMARK_EVERY_MS", "1000").parse().unwrap_or(1000).max(1));
+    let wm_gap_timeout = Duration::from_millis(env("WATERMARK_GAP_TIMEOUT_MS", "30000").parse().unwrap_or(30000));
+    // Empty ROLLUP_TABLE turns the OHLCV bars off.
+    let rollup_table = namespace::table(&env("ROLLUP_TABLE", "trades_1m"));
+    let rollup_window_ms = consumer::rollup::window_from_env();
+    let rollup_grace_ms: i64 = env("ROLLUP_GRACE_MS", "2000").parse().unwrap_or(2000);
+    let rollup_idle_ms: i64 = env("ROLLUP_IDLE_MS", "60000").parse().unwrap_or(60000);
+    // Closed bars also go here, for downstream stages; empty keeps them in QuestDB only.
+    let bars_topic = if rollup_table.is_empty() { String::new() } else { env("BARS_TOPIC", &consumer::rollup::default_topic(rollup_window_ms)) };
+    // Empty PROFILE_TABLE turns the volume profiles off; they close with the bars' grace and idle.
+    let profile_table = namespace::table(&env("PROFILE_TABLE", "volume_profile"));
+    let profile_window_ms: i64 = env("PROFILE_WINDOW_SECS", "3600").parse::<i64>().unwrap_or(3600) * 1000;
+    let profile_bin_bps: f64 = env("PROFILE_BIN_BPS", "10").parse().unwrap_or(10.0);
+    // The book builder's full-depth snapshots; empty leaves the book table alone.
+    let book_topic = env("BOOK_TOPIC", "");
+    let book_table = namespace::table(&env("BOOK_TABLE", "book"));
+    // Bars that close on trades rather than time, e.g. `*=tick:1000,BTCUSDT=imbalance:200`; empty turns them off.
+    let trade_bars = Spec::parse(&env("TRADE_BARS", ""))?;
+    let trade_bars_table = namespace::table(&env("TRADE_BARS_TABLE", "trade_bars"));
+    // Empty writes no checkpoints.
+    let checkpoint_table = namespace::table(&env("CHECKPOINT_TABLE", "_checkpoints"));
+    let trade_bars_alpha: f64 = env("TRADE_BARS_ALPHA", "0.1").parse().unwrap_or(0.1);
+    anyhow::ensure!(trade_bars.is_empty() || !trade_bars_table.is_empty(), "TRADE_BARS needs a TRADE_BARS_TABLE");
+
+    // Create/verify tables before anything is written, so ILP never auto-creates them.
+    let writes_questdb = sink_names.split(',').any(|s| s.trim() == "questdb");
+    let bootstrap = writes_questdb && env("SCHEMA_BOOTSTRAP", "true") != "false";
+    // app: skip replayed messages ourselves; questdb: write everything and let upsert keys dedup.
+    let dedup = match env("DEDUP_MODE", "app").as_str() {
+        "app" => false,
+        "questdb" => true,
+        other => anyhow::bail!("unknown DEDUP_MODE {:?} (expected app|questdb)", other),
+    };
+    // Only the bootstrap checks the upsert keys are really there.
+    anyhow::ensure!(!dedup || bootstrap, "DEDUP_MODE=questdb needs SCHEMA_BOOTSTRAP and the questdb sink");
+    // Symbols past this many in one trades table go to the overflow table; 0 (the default) lets any number in.
+    let max_symbols: usize = env("QDB_MAX_SYMBOLS", "0").parse().unwrap_or(0);
+    let overflow_table = namespace::table(&env("QDB_OVERFLOW_TABLE", "trades_overflow"));
+    anyhow::ensure!(max_symbols == 0 || !overflow_table.is_empty(), "QDB_MAX_SYMBOLS needs a QDB_OVERFLOW_TABLE");
+    // The limit is this replica's: each consumer admits symbols on its own, from what the tables held at its start.
+    let mut guard = (writes_questdb && max_symbols > 0).then(|| Guard::new(max_symbols, &overflow_table));
+    let mut routed: Vec<&String> = routed_tables.values().collect();
+    routed.sort();
+    routed.dedup();
+    let client = match bootstrap || guard.is_some() {
+        true => {
+            let client = schema::Client::authed(&env("QDB_HTTP_URL", "http://localhost:9000")).await?;
+            startup::wait_for("questdb", || client.ping()).await?;
+            Some(client)
+        }
+        false => None,
+    };
+    if let Some(client) = client.as_ref().filter(|_| bootstrap) {
+        let capacity = env("QDB_SYMBOL_CAPACITY", "1024").parse().unwrap_or(1024);
+        let rollup = Some(rollup_table.as_str()).filter(|t| !t.is_empty());
+        let profile = Some(profile_table.as_str()).filter(|t| !t.is_empty());
+        let book = Some(book_table.as_str()).filter(|t| !t.is_empty() && !book_topic.is_empty());
+        let trade_bars = Some(trade_bars_table.as_str()).filter(|_| !trade_bars.is_empty());
+        let checkpoints = Some(checkpoint_table.as_str()).filter(|t| !t.is_empty());
+        // Routed and overflow tables aren't in the log: they run every trades step each start, all safe to repeat.
+        for table in &routed {
+            for sql in schema::routed_trades(table, capacity) {
+                client.query(&sql).await.with_context(|| format!("creating routed table {}", table))?;
+            }
+        }
+        if guard.is_some() {
+            for sql in schema::routed_trades(&overflow_table, capacity) {
+                client.query(&sql).await.with_context(|| format!("creating overflow table {}", overflow_table))?;
+            }
+        }
+        // Like routed tables, the optional ones that are on are created every start: one turned on
+        // after its migration was logged still gets made.
+        for sql in schema::optional_tables(rollup, profile, book, trade_bars, checkpoints, capacity) {
+            client.query(&sql).await.context("creating an optional table")?;
+        }
+        let mut expected = schema::expected(rollup, profile, book, trade_bars);
+        expected.extend(routed.iter().map(|t| schema::expect_trades(t)));
+        expected.extend(guard.as_ref().map(|g| schema::expect_trades(g.overflow())));
+        // Tables that are off are left out, but their migrations are still logged, so the versions line up.
+        let version = schema::bootstrap(
+            client,
+            &schema::migrations(rollup, profile, book, trade_bars, checkpoints, capacity),
+            &expected,
+        ).await?;
+        gauge!("questdb_schema_version").set(version as f64);
+        tracing::info!(target="consumer", version, "QuestDB schema ready");
+    }
+    // Symbols already in a table stay admitted across restarts, bootstrap or not.
+    if let (Some(guard), Some(client)) = (guard.as_mut(), &client) {
+        for table in std::iter::once(schema::trades_table()).chain(routed.iter().map(|t| t.as_str())) {
+            // An empty guard would turn away symbols the table already has.
+            let n = guard.seed_from(client, table).await.with_context(|| format!("reading {}'s symbols for QDB_MAX_SYMBOLS", table))?;
+            tracing::info!(target="consumer", table, symbols=n, limit=max_symbols, "symbol guard seeded");
+        }
+    }
+
+    let mut bus = BusConfig::from_env()?;
+    let topics: Vec<&str> = [&topic_in, &book_topic, &bars_topic, &wm_topic].into_iter().map(String::as_str).filter(|t| !t.is_empty()).collect();
+    startup::wait_for("kafka", || bus.check_topics(&topics)).await?;
+    bus::audit::start(&bus).await;
+    anyhow::ensure!(book_topic.is_empty() || (writes_questdb && !book_table.is_empty()), "BOOK_TOPIC needs the questdb sink and a BOOK_TABLE");
+    // On a thread of its own, which CPU_PIN may pin as `writer`.
+    let book_writer = match book_topic.is_empty() {
+        true => None,
+        false => {
+            let (bus, group) = (bus.clone(), env("BOOK_GROUP_ID", "consumer-book"));
+            Some(runtime::spawn_pinned("writer", move || async move {
+                books(bus, book_topic, group, book_table).await?;
+                anyhow::bail!("book snapshot subscription closed")
+            })?)
+        }
+    };
+    let book_writer = async move {
+        match book_writer {
+            Some(writer) => writer.await,
+            None => std::future::pending::<Result<()>>().await,
+        }
+    };
+    if at_most_once {
+        anyhow::ensure!(bus.transport == Transport::Kafka, "DELIVERY_MODE=at_most_once is only supported on Kafka");
+        // Offsets are stored as messages are handed to us and committed in the background.
+        bus = bus
+            .kafka_set("enable.auto.commit", "true")
+            .kafka_set("enable.auto.offset.store", "true")
+            .kafka_set("auto.commit.interval.ms", &env("AUTO_COMMIT_INTERVAL_MS", "1000"));
+        tracing::warn!(target="consumer", "at-most-once delivery: trades are lost if a write fails or the consumer crashes");
+    }
+    let subscriber = bus.subscriber(&topic_in, &group_id).await?;
+    if !wm_topic.is_empty() {
+        bus.ensure_compacted(&wm_topic).await?;
+    }
+    let publisher = match wm_topic.is_empty() && bars_topic.is_empty() {
+        true => None,
+        false => Some(bus.publisher().await?),
+    };
+
+    // Off unless QDB_VERIFY_EVERY_SECS is set: count the rows ILP took back out of QuestDB.
+    let verify_every = Duration::from_secs(env("QDB_VERIFY_EVERY_SECS", "0").parse().unwrap_or(0));
+    let ledger = match writes_questdb && !verify_every.is_zero() {
+        true => {
+            let window = Duration::from_secs(env("QDB_VERIFY_WINDOW_SECS", "60").parse().unwrap_or(60));
+            let settle = Duration::from_secs(env("QDB_VERIFY_SETTLE_SECS", "30").parse().unwrap_or(30));
+            // Roughly 50 bytes a row: the default holds about 50MB of keys.
+            let max_rows: usize = env("QDB_VERIFY_MAX_ROWS", "1000000").parse().unwrap_or(1_000_000);
+            // A short window is counted again for QDB_VERIFY_RECHECK_SECS before it's called lost.
+            let recheck = Duration::from_secs(env("QDB_VERIFY_RECHECK_SECS", "60").parse().unwrap_or(60));
+            let ledger = Arc::new(Mutex::new(Ledger::new(window, routed_tables.clone()).capped(max_rows)));
+            // Each node is asked for the rows it took, on its PG port.
+            let pg_port: u16 = env("QDB_PG_PORT", "8812").parse().unwrap_or(8812);
+            let (pg_user, pg_database) = (env("QDB_PG_USER", pg::DEFAULT_USER), env("QDB_PG_DATABASE", pg::DEFAULT_DATABASE));
+            let password = secrets::Secrets::from_env()?.watch("QDB_PG_PASSWORD").await?;
+            let clients = questdb_endpoints()?.into_iter()
+                .map(|(host, port)| {
+                    let client = PgClient::new(&format!("{}:{}", host, pg_port), &pg_user, &pg_database).with_password(password.clone());
+                    (format!("{}:{}", host, port), client)
+                })
+                .collect();
+            verify::spawn(ledger.clone(), clients, verify_every, settle, recheck);
+            Some(ledger)
+        }
+        false => None,
+    };
+    let slots = sinks(&sink_names, if at_most_once { 0 } else { 2 }, ledger, guard).await?;
+    let on_loss = match env("SINK_BACKPRESSURE", if at_most_once { "drop" } else { "pause" }).as_str() {
+        "pause" => OnLoss::Pause,
+        "drop" => OnLoss::Drop,
+        "spill" => OnLoss::Spill,
+        other => anyhow::bail!("unknown SINK_BACKPRESSURE {:?} (expected pause|drop|spill)", other),
+    };
+    let batch_budget = Budget::from_env("batch", 64 << 20, &[Overflow::Block, Overflow::DropOldest, Overflow::Spill])?;
+    let pause_retry = Duration::from_millis(env("PAUSE_RETRY_MS", "1000").parse().unwrap_or(1000));
+    // Messages dropped before any sink saw them; by default they share the first sink DLQ.
+    let dropped_dlq = env("DROPPED_DLQ", slots.iter().map(|s| s.dlq_topic.as_str()).find(|t| !t.is_empty()).unwrap_or(""));
+    let dlq = match slots.iter().any(|s| !s.dlq_topic.is_empty()) || !dropped_dlq.is_empty() {
+        true => Some(bus.publisher().await?),
+        false => None,
+    };
+    let dropped_dlq = dlq.clone().filter(|_| !dropped_dlq.is_empty()).map(|p| (p, dropped_dlq));
+    let limit = Limit::from_env()?;
+    if let Some(l) = limit.filter(|l| l.action != Action::Drop) {
+        anyhow::ensure!(dropped_dlq.is_some(), "OVERSIZE_ACTION={} needs DROPPED_DLQ or a sink DLQ", l.action.name());
+    }
+    // Dead-lettered rows carry the same envelope as `ticks.norm`.
+    let envelope = Envelope::new(NORM_SCHEMA, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"),
+        &env("EXCHANGE", "binance"), &env("MARKET", "spot"));
+    let tee = Tee::new(slots, dlq, envelope);
+    tracing::info!(target="consumer", sinks=?tee.names(), "sinks ready");
+    let registry = Registry::from_env().await?.map(Arc::new);
+    let mut stage = Stage {
+        subscriber,
+        tee,
+        batch: Batch::new(),
+        watermarks: Watermarks::new(wm_gap_timeout),
+        dedup,
+        keys: Keyring::from_env()?.map(Arc::new),
+        limit,
+        pending: Vec::new(),
+        offload_min: env("DECODE_OFFLOAD_MIN", "64").parse().unwrap_or(64),
+        registry,
+        registry_policy: Policy::from_env("SCHEMA_REGISTRY", 2, Duration::from_millis(200), Duration::from_secs(2))
+            .breaker(Arc::new(Breaker::new("schema_registry", 3, Duration::from_millis(
+                env("SCHEMA_REGISTRY_BREAKER_COOLDOWN_MS", "30000").parse().unwrap_or(30_000),
+            )))),
+        tables: Arc::new(routed_tables),
+        rollup: Some(rollup_table).filter(|t| !t.is_empty()).map(|t| (Rollup::new(rollup_window_ms, rollup_grace_ms, rollup_idle_ms), t)),
+        profile: Some(profile_table).filter(|t| !t.is_empty())
+            .map(|t| (Profile::new(profile_window_ms, profile_bin_bps, rollup_grace_ms, rollup_idle_ms), t)),
+        trade_bars: Some(trade_bars).filter(|s| !s.is_empty()).map(|s| (TradeBars::new(s, trade_bars_alpha), trade_bars_table)),
+        calendar: Calendar::from_env()?,
+        publisher,
+        dropped_dlq,
+        dropped_out: Vec::new(),
+        wm_topic,
+        bars_topic,
+        bars_out: Vec::new(),
+        bars_policy: Policy::times(
+            env("BARS_PUBLISH_RETRIES", "5").parse().unwrap_or(5),
+            Backoff::from_env("BARS_PUBLISH", Duration::from_millis(100), Duration::from_secs(5)),
+        ),
+        wm_every,
+        last_lag_update: Instant::now(),
+        committer: Committer::new(commit_strategy),
+        checkpoints: Some(checkpoint_table.as_str()).filter(|t| !t.is_empty() && !at_most_once).map(|t| Checkpoints::new(t, &bus.group(&group_id))),
+        checkpoint_every: Duration::from_millis(env("CHECKPOINT_EVERY_MS", "10000").parse().unwrap_or(10_000)),
+        last_checkpoint: Instant::now(),
+        at_most_once,
+        spill: match matches!(on_loss, OnLoss::Spill) || batch_budget.overflow() == Overflow::Spill {
+            true => Some(Spill::new(env("SPILL_DIR", "spill").as_ref())?),
+            false => None,
+        },
+        on_loss,
+        paused: false,
+        blocked: None,
+        shed_batch: false,
+        poll_paused: bus.transport == Transport::Kafka,
+        held: VecDeque::new(),
+        batch_budget,
+        wm_budget: Budget::from_env("watermark", 64 << 20, &[Overflow::DropOldest, Overflow::Block])?,
+        rollup_budget: Budget::from_env("rollup", 16 << 20, &[Overflow::DropOldest, Overflow::Block])?,
+        profile_budget: Budget::from_env("profile", 16 << 20, &[Overflow::DropOldest, Overflow::Block])?,
+        load: Load::new("consumer")?,
+    };
+
+    // The book writer only returns on failure; it takes the consumer down with it.
+    tokio::select! {
+        res = stage.run(schedule, pause_retry) => res?,
+        res = book_writer => res?,
+    }
+    stage.flush().await?;
+    stage.tick_watermarks().await?;
+    if !at_most_once {
+        if let Err(e) = stage.committer.commit(stage.subscriber.as_ref()).await {
+            tracing::warn!(target="consumer", error=?e, "final commit failed");
         }
     }
+    stage.tee.close().await?;
 
     Ok(())
 }
@@ -0,0 +1,175 @@
+//! Just enough of the PostgreSQL wire protocol to run a query against
+//! QuestDB's PG endpoint (`:8812`): startup, a cleartext password (QuestDB's
+//! only password scheme), and simple queries with text results.
+//!
+//! Cells come back as JSON strings, `null` for SQL NULL, so callers parse
+//! them as they would a `/exec` cell. The connection is made on first use and
+//! dropped after any error, to be made again by the next query.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use secrets::Watched;
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+
+/// QuestDB's stock credentials, for a server that hasn't changed them.
+pub const DEFAULT_USER: &str = "admin";
+pub const DEFAULT_PASSWORD: &str = "quest";
+pub const DEFAULT_DATABASE: &str = "qdb";
+
+/// How long a connect or a query may take before the connection is dropped.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Protocol 3.0.
+const PROTOCOL: i32 = 196_608;
+
+pub struct PgClient {
+    addr: String,
+    user: String,
+    database: String,
+    password: Option<Watched>,
+    conn: Option<BufStream<TcpStream>>,
+}
+
+impl PgClient {
+    /// `addr` is `host:port`.
+    pub fn new(addr: &str, user: &str, database: &str) -> Self {
+        Self { addr: addr.to_string(), user: user.to_string(), database: database.to_string(), password: None, conn: None }
+    }
+
+    /// The password to answer a password request with; [`DEFAULT_PASSWORD`] without one.
+    pub fn with_password(mut self, password: Option<Watched>) -> Self {
+        self.password = password;
+        self
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Run `sql` and return its rows.
+    pub async fn query(&mut self, sql: &str) -> Result<Vec<Vec<Value>>> {
+        // Taken out while in use, and only put back once the query finished cleanly.
+        let conn = self.conn.take();
+        let res = tokio::time::timeout(TIMEOUT, self.try_query(conn, sql)).await
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out after {:?}", TIMEOUT)));
+        let (conn, rows) = res.with_context(|| format!("QuestDB PG at {}", self.addr))?;
+        self.conn = Some(conn);
+        rows
+    }
+
+    /// The connection back, if it's still usable, and the query's outcome.
+    async fn try_query(&self, conn: Option<BufStream<TcpStream>>, sql: &str) -> Result<(BufStream<TcpStream>, Result<Vec<Vec<Value>>>)> {
+        let mut conn = match conn {
+            Some(c) => c,
+            None => self.connect().await?,
+        };
+        let mut q = sql.as_bytes().to_vec();
+        q.push(0);
+        send(&mut conn, b'Q', &q).await?;
+
+        let mut rows = Vec::new();
+        let mut error = None;
+        loop {
+            let (tag, body) = recv(&mut conn).await?;
+            match tag {
+                b'D' => rows.push(data_row(&body)?),
+                b'E' => error = Some(error_message(&body)),
+                b'Z' => break,
+                // Row description, command complete, empty query, notices.
+                _ => {}
+            }
+        }
+        // A query QuestDB refused leaves the connection ready for the next.
+        Ok((conn, match error {
+            Some(e) => Err(anyhow::anyhow!("questdb: {} (in {:?})", e, sql)),
+            None => Ok(rows),
+        }))
+    }
+
+    async fn connect(&self) -> Result<BufStream<TcpStream>> {
+        let mut conn = BufStream::new(TcpStream::connect(&self.addr).await?);
+        let mut startup = PROTOCOL.to_be_bytes().to_vec();
+        for (k, v) in [("user", self.user.as_str()), ("database", self.database.as_str())] {
+            startup.extend_from_slice(k.as_bytes());
+            startup.push(0);
+            startup.extend_from_slice(v.as_bytes());
+            startup.push(0);
+        }
+        startup.push(0);
+        conn.write_all(&(startup.len() as i32 + 4).to_be_bytes()).await?;
+        conn.write_all(&startup).await?;
+        conn.flush().await?;
+
+        loop {
+            let (tag, body) = recv(&mut conn).await?;
+            match tag {
+                b'R' => match int32(&body, 0)? {
+                    0 => {}
+                    3 => {
+                        let password = self.password.as_ref().map(|p| p.current().expose().to_string());
+                        let mut p = password.unwrap_or_else(|| DEFAULT_PASSWORD.to_string()).into_bytes();
+                        p.push(0);
+                        send(&mut conn, b'p', &p).await?;
+                    }
+                    other => anyhow::bail!("unsupported authentication method {}", other),
+                },
+                b'E' => anyhow::bail!("{}", error_message(&body)),
+                b'Z' => return Ok(conn),
+                // Parameter status, backend key data, notices.
+                _ => {}
+            }
+        }
+    }
+}
+
+async fn send(conn: &mut BufStream<TcpStream>, tag: u8, body: &[u8]) -> Result<()> {
+    conn.write_u8(tag).await?;
+    conn.write_all(&(body.len() as i32 + 4).to_be_bytes()).await?;
+    conn.write_all(body).await?;
+    conn.flush().await?;
+    Ok(())
+}
+
+/// One backend message; its length counts itself.
+async fn recv(conn: &mut BufStream<TcpStream>) -> Result<(u8, Vec<u8>)> {
+    let tag = conn.read_u8().await?;
+    let len = conn.read_i32().await?;
+    anyhow::ensure!((4..=64 << 20).contains(&len), "bad message length {}", len);
+    let mut body = vec![0; len as usize - 4];
+    conn.read_exact(&mut body).await?;
+    Ok((tag, body))
+}
+
+fn int32(b: &[u8], at: usize) -> Result<i32> {
+    Ok(i32::from_be_bytes(b.get(at..at + 4).context("message cut short")?.try_into()?))
+}
+
+/// A `DataRow`: a column count, then each cell as a length (-1 for NULL) and its text.
+fn data_row(b: &[u8]) -> Result<Vec<Value>> {
+    let cols = u16::from_be_bytes(b.get(..2).context("empty data row")?.try_into()?);
+    let mut at = 2;
+    let mut row = Vec::with_capacity(cols as usize);
+    for _ in 0..cols {
+        let len = int32(b, at)?;
+        at += 4;
+        if len < 0 {
+            row.push(Value::Null);
+            continue;
+        }
+        let cell = b.get(at..at + len as usize).context("data row cut short")?;
+        row.push(Value::String(String::from_utf8_lossy(cell).into_owned()));
+        at += len as usize;
+    }
+    Ok(row)
+}
+
+/// The `M` (message) field of an `ErrorResponse`.
+fn error_message(b: &[u8]) -> String {
+    b.split(|c| *c == 0)
+        .find_map(|f| f.strip_prefix(b"M"))
+        .map(|m| String::from_utf8_lossy(m).into_owned())
+        .unwrap_or_else(|| "unknown error".to_string())
+}
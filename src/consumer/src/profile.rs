@@ -0,0 +1,144 @@
+//! Per-symbol volume profiles: traded volume by price level over fixed
+//! event-time windows (an hour by default).
+//!
+//! Price levels are logarithmic, `bin_bps` wide, so one setting suits BTC at
+//! 60000 and a token at 0.00001 alike; level `n` starts at `(1 + bps/1e4)^n`.
+//! Windows close the way rollup bars do: once a trade `grace` past the end
+//! has been seen for the symbol, or the wall clock is `idle` past it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::mem::size_of;
+
+use metrics::counter;
+
+use crate::ilp::{escape_tag, NormTrade};
+
+/// Volume traded in one price level of one window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    pub symbol: String,
+    /// Window start, ms since the epoch.
+    pub ts_ms: i64,
+    pub level: i64,
+    /// Lower edge of the level.
+    pub price: f64,
+    pub volume: f64,
+    /// Taker-buy volume (the buyer was not the maker).
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+    pub trades: u64,
+}
+
+impl Level {
+    pub fn to_ilp_line(&self, table: &str) -> String {
+        format!(
+            "{},symbol={} level={}i,price={},volume={},buy_volume={},sell_volume={},trades={}i {}",
+            escape_tag(table),
+            escape_tag(&self.symbol),
+            self.level,
+            self.price,
+            self.volume,
+            self.buy_volume,
+            self.sell_volume,
+            self.trades,
+            (self.ts_ms as i128) * 1_000_000i128
+        )
+    }
+}
+
+#[derive(Default)]
+struct Windows {
+    /// Open windows by start, each with its levels by index.
+    open: BTreeMap<i64, BTreeMap<i64, Level>>,
+    max_ts_ms: i64,
+    /// Everything before this window start has been emitted.
+    closed_before: i64,
+}
+
+pub struct Profile {
+    window_ms: i64,
+    grace_ms: i64,
+    idle_ms: i64,
+    /// ln(1 + bin_bps / 1e4).
+    step: f64,
+    symbols: HashMap<String, Windows>,
+}
+
+impl Profile {
+    pub fn new(window_ms: i64, bin_bps: f64, grace_ms: i64, idle_ms: i64) -> Self {
+        Self { window_ms: window_ms.max(1), grace_ms, idle_ms, step: (1.0 + bin_bps.max(0.01) / 1e4).ln(), symbols: HashMap::new() }
+    }
+
+    /// Level index of a price.
+    pub fn level(&self, price: f64) -> i64 {
+        (price.ln() / self.step).floor() as i64
+    }
+
+    pub fn add(&mut self, t: &NormTrade) {
+        if t.price <= 0.0 || !t.price.is_finite() {
+            return;
+        }
+        let start = t.ts_ms.div_euclid(self.window_ms) * self.window_ms;
+        let level = self.level(t.price);
+        let step = self.step;
+        let w = self.symbols.entry(t.symbol.clone()).or_default();
+        if start < w.closed_before {
+            counter!("profile_late_total", "symbol" => t.symbol.clone()).increment(1);
+            return;
+        }
+        w.max_ts_ms = w.max_ts_ms.max(t.ts_ms);
+        let l = w.open.entry(start).or_default().entry(level).or_insert_with(|| Level {
+            symbol: t.symbol.clone(),
+            ts_ms: start,
+            level,
+            price: (level as f64 * step).exp(),
+            volume: 0.0,
+            buy_volume: 0.0,
+            sell_volume: 0.0,
+            trades: 0,
+        });
+        l.volume += t.qty;
+        if t.is_bm { l.sell_volume += t.qty } else { l.buy_volume += t.qty }
+        l.trades += 1;
+    }
+
+    /// Estimated memory held by open windows.
+    pub fn bytes(&self) -> usize {
+        self.symbols.iter()
+            .map(|(s, w)| w.open.values().map(|ls| ls.len() * (size_of::<(i64, Level)>() + s.len())).sum::<usize>())
+            .sum()
+    }
+
+    /// Close the oldest open windows, done or not, until the levels fit in `limit` bytes.
+    pub fn shed(&mut self, limit: usize) -> Vec<Level> {
+        let mut done = Vec::new();
+        let mut bytes = self.bytes();
+        while bytes > limit {
+            let oldest = self.symbols.iter_mut()
+                .filter(|(_, w)| !w.open.is_empty())
+                .min_by_key(|(_, w)| w.open.keys().next().copied());
+            let Some((symbol, w)) = oldest else { break };
+            let Some((start, levels)) = w.open.pop_first() else { break };
+            w.closed_before = start + self.window_ms;
+            bytes -= levels.len() * (size_of::<(i64, Level)>() + symbol.len());
+            done.extend(levels.into_values());
+        }
+        done
+    }
+
+    /// Remove and return the levels of every window that's done.
+    pub fn close(&mut self, now_ms: i64) -> Vec<Level> {
+        let mut done = Vec::new();
+        for w in self.symbols.values_mut() {
+            while let Some(entry) = w.open.first_entry() {
+                let end = entry.key() + self.window_ms;
+                if w.max_ts_ms < end + self.grace_ms && now_ms < end + self.idle_ms {
+                    break;
+                }
+                w.closed_before = end;
+                done.extend(entry.remove().into_values());
+            }
+        }
+        done
+    }
+}
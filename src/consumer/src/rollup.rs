@@ -0,0 +1,154 @@
+//! Per-symbol OHLCV bars over fixed event-time windows.
+//!
+//! Bars are kept in memory and emitted once a window is done: when a trade at
+//! least `grace` past the window's end has been seen for that symbol, or when
+//! the wall clock is `idle` past it (for symbols that went quiet). A trade for
+//! a window already emitted is counted as late and left out of the bar.
+//!
+//! Nothing is persisted between restarts, so the first bar after a restart
+//! only covers the trades seen since.
+
+use std::collections::{BTreeMap, HashMap};
+use std::mem::size_of;
+
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+
+use crate::ilp::{escape_tag, NormTrade};
+
+/// `BARS_TOPIC`'s default for bars `window_ms` wide, named for the width:
+/// `bars.1m` for the usual minute, `bars.5m`, `bars.1h`, or `bars.90s`.
+/// Every stage that reads the bars takes `ROLLUP_WINDOW_SECS` too, so they
+/// all find the topic the consumer publishes to.
+pub fn default_topic(window_ms: i64) -> String {
+    let secs = (window_ms / 1000).max(1);
+    let width = [(86_400, "d"), (3_600, "h"), (60, "m")].into_iter()
+        .find(|(unit, _)| secs % unit == 0)
+        .map_or_else(|| format!("{}s", secs), |(unit, suffix)| format!("{}{}", secs / unit, suffix));
+    format!("bars.{}", width)
+}
+
+/// `ROLLUP_WINDOW_SECS`, default 60, in ms.
+pub fn window_from_env() -> i64 {
+    std::env::var("ROLLUP_WINDOW_SECS").ok().and_then(|v| v.parse::<i64>().ok()).unwrap_or(60) * 1000
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bar {
+    pub symbol: String,
+    /// Window start, ms since the epoch.
+    pub ts_ms: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub trades: u64,
+}
+
+impl Bar {
+    fn new(symbol: &str, ts_ms: i64, t: &NormTrade) -> Self {
+        Self { symbol: symbol.to_string(), ts_ms, open: t.price, high: t.price, low: t.price, close: t.price, volume: 0.0, trades: 0 }
+    }
+
+    fn add(&mut self, t: &NormTrade) {
+        self.high = self.high.max(t.price);
+        self.low = self.low.min(t.price);
+        self.close = t.price;
+        self.volume += t.qty;
+        self.trades += 1;
+    }
+
+    pub fn to_ilp_line(&self, table: &str) -> String {
+        format!(
+            "{},symbol={} open={},high={},low={},close={},volume={},trades={}i {}",
+            escape_tag(table),
+            escape_tag(&self.symbol),
+            self.open,
+            self.high,
+            self.low,
+            self.close,
+            self.volume,
+            self.trades,
+            (self.ts_ms as i128) * 1_000_000i128
+        )
+    }
+}
+
+#[derive(Default)]
+struct Windows {
+    /// Open bars by window start.
+    open: BTreeMap<i64, Bar>,
+    max_ts_ms: i64,
+    /// Everything before this window start has been emitted.
+    closed_before: i64,
+}
+
+pub struct Rollup {
+    window_ms: i64,
+    grace_ms: i64,
+    idle_ms: i64,
+    symbols: HashMap<String, Windows>,
+}
+
+impl Rollup {
+    pub fn new(window_ms: i64, grace_ms: i64, idle_ms: i64) -> Self {
+        Self { window_ms: window_ms.max(1), grace_ms, idle_ms, symbols: HashMap::new() }
+    }
+
+    /// Fold a trade into its window. Trades within a window should arrive in
+    /// trade order for open/close to be right, which Kafka gives us per symbol.
+    pub fn add(&mut self, t: &NormTrade) {
+        let start = t.ts_ms.div_euclid(self.window_ms) * self.window_ms;
+        let w = self.symbols.entry(t.symbol.clone()).or_default();
+        if start < w.closed_before {
+            counter!("rollup_late_total", "symbol" => t.symbol.clone()).increment(1);
+            return;
+        }
+        w.max_ts_ms = w.max_ts_ms.max(t.ts_ms);
+        w.open.entry(start).or_insert_with(|| Bar::new(&t.symbol, start, t)).add(t);
+    }
+
+    /// Estimated memory held by open bars.
+    pub fn bytes(&self) -> usize {
+        self.symbols.values()
+            .map(|w| w.open.len() * (size_of::<(i64, Bar)>() + w.open.values().next().map_or(0, |b| b.symbol.len())))
+            .sum()
+    }
+
+    /// Close the oldest open windows, done or not, until the bars fit in `limit` bytes.
+    /// A trade for a window closed this way is counted late, like any other.
+    pub fn shed(&mut self, limit: usize) -> Vec<Bar> {
+        let mut done = Vec::new();
+        let mut bytes = self.bytes();
+        while bytes > limit {
+            let oldest = self.symbols.values_mut()
+                .filter(|w| !w.open.is_empty())
+                .min_by_key(|w| w.open.keys().next().copied());
+            let Some(w) = oldest else { break };
+            let Some((start, bar)) = w.open.pop_first() else { break };
+            w.closed_before = start + self.window_ms;
+            bytes -= size_of::<(i64, Bar)>() + bar.symbol.len();
+            done.push(bar);
+        }
+        counter!("rollup_bars_total").increment(done.len() as u64);
+        done
+    }
+
+    /// Remove and return every bar whose window is done, oldest first per symbol.
+    pub fn close(&mut self, now_ms: i64) -> Vec<Bar> {
+        let mut done = Vec::new();
+        for w in self.symbols.values_mut() {
+            while let Some(entry) = w.open.first_entry() {
+                let end = entry.key() + self.window_ms;
+                if w.max_ts_ms < end + self.grace_ms && now_ms < end + self.idle_ms {
+                    break;
+                }
+                w.closed_before = end;
+                done.push(entry.remove());
+            }
+        }
+        counter!("rollup_bars_total").increment(done.len() as u64);
+        done
+    }
+}
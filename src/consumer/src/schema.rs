@@ -0,0 +1,526 @@
+//! QuestDB table bootstrap and migrations over HTTP `/exec`.
+//!
+//! Tables are created up front with the layout we want (designated timestamp,
+//! partitioning, symbol capacity) instead of whatever ILP auto-create picks.
+//! Applied migrations are logged in `_schema_migrations`; on startup the
+//! consumer applies anything newer than the highest logged version, then
+//! checks that each table has the columns and designated timestamp it writes.
+//!
+//! Every statement must be safe to run twice (`IF NOT EXISTS` and friends):
+//! two consumers starting together may both apply the same migration.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use secrets::Watched;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::book::LEVEL_COLUMNS;
+
+/// `_schema_migrations`, in the namespace: each namespace migrates its own tables.
+pub fn log_table() -> &'static str {
+    static TABLE: OnceLock<String> = OnceLock::new();
+    TABLE.get_or_init(|| obsv::namespace::table("_schema_migrations"))
+}
+
+/// `trades`, in the namespace. The other tables are named in the config.
+pub fn trades_table() -> &'static str {
+    static TABLE: OnceLock<String> = OnceLock::new();
+    TABLE.get_or_init(|| obsv::namespace::table("trades"))
+}
+
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub statements: Vec<String>,
+}
+
+/// A column a table must have, with the QuestDB types we accept for it.
+#[derive(Debug, Clone)]
+pub struct Expect {
+    pub table: String,
+    pub timestamp: &'static str,
+    pub columns: Vec<(&'static str, &'static [&'static str])>,
+    /// DEDUP UPSERT KEYS the table must have (besides the timestamp).
+    pub upsert_keys: &'static [&'static str],
+}
+
+/// One row of `table_columns()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Col {
+    pub name: String,
+    pub ty: String,
+    pub designated: bool,
+    pub upsert_key: bool,
+}
+
+const DOUBLE: &[&str] = &["DOUBLE"];
+const LONG: &[&str] = &["LONG"];
+// ILP auto-create made STRING before QuestDB 7.4 and VARCHAR since.
+const TEXT: &[&str] = &["VARCHAR", "STRING"];
+
+/// The steps that shape a trades table, each as part of the migration
+/// version it belongs to. `trades` gets them through [`migrations`]; routed
+/// and overflow tables get them all, in order, on every start.
+pub fn trades_migrations(table: &str, symbol_capacity: u32) -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 1,
+            name: "create trades",
+            statements: vec![format!(
+                "CREATE TABLE IF NOT EXISTS {} (symbol SYMBOL CAPACITY {} CACHE, price DOUBLE, qty DOUBLE, \
+                 trade_id LONG, is_bm BOOLEAN, msg_id VARCHAR, ts_ms LONG, timestamp TIMESTAMP) \
+                 TIMESTAMP(timestamp) PARTITION BY DAY WAL",
+                table, symbol_capacity
+            )],
+        },
+        Migration {
+            version: 3,
+            name: "dedup upsert keys",
+            statements: vec![format!("ALTER TABLE {} DEDUP ENABLE UPSERT KEYS(timestamp, symbol, trade_id)", table)],
+        },
+        Migration {
+            version: 4,
+            name: "trades notional",
+            statements: vec![format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS notional_usd DOUBLE", table)],
+        },
+        Migration {
+            version: 6,
+            name: "trades first trade id",
+            statements: vec![format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS first_trade_id LONG", table)],
+        },
+        Migration {
+            version: 7,
+            name: "trades contract size",
+            statements: vec![format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS contract_size DOUBLE", table)],
+        },
+        Migration {
+            version: 11,
+            name: "trades nanosecond times",
+            statements: vec![
+                format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS ts_ns LONG", table),
+                format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS ingest_ts_ns LONG", table),
+            ],
+        },
+        // `notional_usd` stays, with what was written before; it wasn't always USD.
+        Migration {
+            version: 13,
+            name: "trades notional in any currency",
+            statements: vec![format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS notional DOUBLE", table)],
+        },
+        Migration {
+            version: 14,
+            name: "trades session",
+            statements: vec![format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS session SYMBOL", table)],
+        },
+    ]
+}
+
+fn rollup_sql(table: &str, symbol_capacity: u32) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (symbol SYMBOL CAPACITY {} CACHE, open DOUBLE, high DOUBLE, \
+         low DOUBLE, close DOUBLE, volume DOUBLE, trades LONG, timestamp TIMESTAMP) \
+         TIMESTAMP(timestamp) PARTITION BY MONTH WAL",
+        table, symbol_capacity
+    )
+}
+
+fn rollup_dedup_sql(table: &str) -> String {
+    format!("ALTER TABLE {} DEDUP ENABLE UPSERT KEYS(timestamp, symbol)", table)
+}
+
+fn profile_sql(table: &str, symbol_capacity: u32) -> Vec<String> {
+    vec![
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} (symbol SYMBOL CAPACITY {} CACHE, level LONG, price DOUBLE, \
+             volume DOUBLE, buy_volume DOUBLE, sell_volume DOUBLE, trades LONG, timestamp TIMESTAMP) \
+             TIMESTAMP(timestamp) PARTITION BY MONTH WAL",
+            table, symbol_capacity
+        ),
+        format!("ALTER TABLE {} DEDUP ENABLE UPSERT KEYS(timestamp, symbol, level)", table),
+    ]
+}
+
+fn book_sql(table: &str, symbol_capacity: u32) -> Vec<String> {
+    let levels: Vec<String> = LEVEL_COLUMNS.iter().map(|c| format!("{} DOUBLE", c)).collect();
+    vec![
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} (symbol SYMBOL CAPACITY {} CACHE, update_id LONG, bid_levels LONG, \
+             ask_levels LONG, mid DOUBLE, spread_bps DOUBLE, {}, timestamp TIMESTAMP) \
+             TIMESTAMP(timestamp) PARTITION BY DAY WAL",
+            table, symbol_capacity, levels.join(", ")
+        ),
+        format!("ALTER TABLE {} DEDUP ENABLE UPSERT KEYS(timestamp, symbol)", table),
+    ]
+}
+
+// Its upsert keys come with the size column, in migration 12.
+fn trade_bars_sql(table: &str, symbol_capacity: u32) -> Vec<String> {
+    vec![format!(
+        "CREATE TABLE IF NOT EXISTS {} (symbol SYMBOL CAPACITY {} CACHE, kind SYMBOL, size LONG, open DOUBLE, \
+         high DOUBLE, low DOUBLE, close DOUBLE, volume DOUBLE, trades LONG, imbalance LONG, \
+         close_ts_ms LONG, timestamp TIMESTAMP) TIMESTAMP(timestamp) PARTITION BY MONTH WAL",
+        table, symbol_capacity
+    )]
+}
+
+// `tick:100` and `tick:1000` bars of a symbol can start on the same trade: the size keeps them apart.
+fn trade_bars_size_sql(table: &str) -> Vec<String> {
+    vec![
+        format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS size LONG", table),
+        format!("ALTER TABLE {} DEDUP ENABLE UPSERT KEYS(timestamp, symbol, kind, size)", table),
+    ]
+}
+
+// A history, one row per partition per checkpoint, so no upsert keys.
+fn checkpoints_sql(table: &str) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (consumer_group SYMBOL, topic SYMBOL, partition LONG, \
+         committed_offset LONG, watermark TIMESTAMP, timestamp TIMESTAMP) \
+         TIMESTAMP(timestamp) PARTITION BY DAY WAL",
+        table
+    )
+}
+
+/// Every migration, oldest first. `symbol_capacity` only applies to tables created by these.
+/// A table that is off keeps its migration's version but none of its statements.
+pub fn migrations(rollup_table: Option<&str>, profile_table: Option<&str>, book_table: Option<&str>,
+    trade_bars_table: Option<&str>, checkpoint_table: Option<&str>, symbol_capacity: u32) -> Vec<Migration> {
+    let trades = trades_migrations(trades_table(), symbol_capacity);
+    let trades = |version: u32| trades.iter().filter(|m| m.version == version).flat_map(|m| m.statements.clone()).collect::<Vec<_>>();
+    vec![
+        Migration {
+            version: 1,
+            name: "create trades",
+            statements: trades(1),
+        },
+        Migration {
+            version: 2,
+            name: "create rollup bars",
+            statements: rollup_table.map(|t| rollup_sql(t, symbol_capacity)).into_iter().collect(),
+        },
+        // A rewritten trade or bar replaces the stored row instead of adding one.
+        Migration {
+            version: 3,
+            name: "dedup upsert keys",
+            statements: [trades(3), rollup_table.map(rollup_dedup_sql).into_iter().collect()].concat(),
+        },
+        Migration {
+            version: 4,
+            name: "trades notional",
+            statements: trades(4),
+        },
+        Migration {
+            version: 5,
+            name: "create volume profile",
+            statements: profile_table.map(|t| profile_sql(t, symbol_capacity)).unwrap_or_default(),
+        },
+        Migration {
+            version: 6,
+            name: "trades first trade id",
+            statements: trades(6),
+        },
+        Migration {
+            version: 7,
+            name: "trades contract size",
+            statements: trades(7),
+        },
+        Migration {
+            version: 8,
+            name: "create book",
+            statements: book_table.map(|t| book_sql(t, symbol_capacity)).unwrap_or_default(),
+        },
+        Migration {
+            version: 9,
+            name: "create trade bars",
+            statements: trade_bars_table.map(|t| trade_bars_sql(t, symbol_capacity)).unwrap_or_default(),
+        },
+        Migration {
+            version: 10,
+            name: "create checkpoints",
+            statements: checkpoint_table.map(checkpoints_sql).into_iter().collect(),
+        },
+        Migration {
+            version: 11,
+            name: "trades nanosecond times",
+            statements: trades(11),
+        },
+        Migration {
+            version: 12,
+            name: "trade bars size",
+            statements: trade_bars_table.map(trade_bars_size_sql).unwrap_or_default(),
+        },
+        Migration {
+            version: 13,
+            name: "trades notional in any currency",
+            statements: trades(13),
+        },
+        Migration {
+            version: 14,
+            name: "trades session",
+            statements: trades(14),
+        },
+    ]
+}
+
+/// Every statement that makes the optional tables that are on. Safe to run
+/// every start, so a table turned on after its migration was logged is still created.
+pub fn optional_tables(rollup_table: Option<&str>, profile_table: Option<&str>, book_table: Option<&str>,
+    trade_bars_table: Option<&str>, checkpoint_table: Option<&str>, symbol_capacity: u32) -> Vec<String> {
+    let mut out = Vec::new();
+    if let Some(t) = rollup_table {
+        out.extend([rollup_sql(t, symbol_capacity), rollup_dedup_sql(t)]);
+    }
+    out.extend(profile_table.map(|t| profile_sql(t, symbol_capacity)).unwrap_or_default());
+    out.extend(book_table.map(|t| book_sql(t, symbol_capacity)).unwrap_or_default());
+    out.extend(trade_bars_table.map(|t| trade_bars_sql(t, symbol_capacity)).unwrap_or_default());
+    out.extend(checkpoint_table.map(checkpoints_sql));
+    out.extend(trade_bars_table.map(trade_bars_size_sql).unwrap_or_default());
+    out
+}
+
+/// A routed symbol's own trades table, or the overflow table: every step of
+/// [`trades_migrations`], in order. Safe to run every start.
+pub fn routed_trades(table: &str, symbol_capacity: u32) -> Vec<String> {
+    trades_migrations(table, symbol_capacity).into_iter().flat_map(|m| m.statements).collect()
+}
+
+/// What a trades table must look like: `trades` or a routed one.
+pub fn expect_trades(table: &str) -> Expect {
+    Expect {
+        table: table.into(),
+        timestamp: "timestamp",
+        columns: vec![
+            ("symbol", &["SYMBOL"]), ("price", DOUBLE), ("qty", DOUBLE), ("trade_id", LONG),
+            ("is_bm", &["BOOLEAN"]), ("msg_id", TEXT), ("ts_ms", LONG),
+            ("notional", DOUBLE), ("first_trade_id", LONG), ("contract_size", DOUBLE),
+            ("ts_ns", LONG), ("ingest_ts_ns", LONG), ("session", &["SYMBOL"]),
+        ],
+        upsert_keys: &["symbol", "trade_id"],
+    }
+}
+
+/// What the consumer writes, checked after migrating.
+pub fn expected(
+    rollup_table: Option<&str>,
+    profile_table: Option<&str>,
+    book_table: Option<&str>,
+    trade_bars_table: Option<&str>,
+) -> Vec<Expect> {
+    let mut out = vec![expect_trades(trades_table())];
+    if let Some(t) = rollup_table {
+        out.push(Expect {
+            table: t.into(),
+            timestamp: "timestamp",
+            columns: vec![
+                ("symbol", &["SYMBOL"]), ("open", DOUBLE), ("high", DOUBLE), ("low", DOUBLE),
+                ("close", DOUBLE), ("volume", DOUBLE), ("trades", LONG),
+            ],
+            upsert_keys: &["symbol"],
+        });
+    }
+    if let Some(t) = profile_table {
+        out.push(Expect {
+            table: t.into(),
+            timestamp: "timestamp",
+            columns: vec![
+                ("symbol", &["SYMBOL"]), ("level", LONG), ("price", DOUBLE), ("volume", DOUBLE),
+                ("buy_volume", DOUBLE), ("sell_volume", DOUBLE), ("trades", LONG),
+            ],
+            upsert_keys: &["symbol", "level"],
+        });
+    }
+    if let Some(t) = book_table {
+        let mut columns: Vec<(&'static str, &'static [&'static str])> = vec![
+            ("symbol", &["SYMBOL"]), ("update_id", LONG), ("bid_levels", LONG), ("ask_levels", LONG),
+            ("mid", DOUBLE), ("spread_bps", DOUBLE),
+        ];
+        columns.extend(LEVEL_COLUMNS.iter().map(|c| (*c, DOUBLE)));
+        out.push(Expect { table: t.into(), timestamp: "timestamp", columns, upsert_keys: &["symbol"] });
+    }
+    if let Some(t) = trade_bars_table {
+        out.push(Expect {
+            table: t.into(),
+            timestamp: "timestamp",
+            columns: vec![
+                ("symbol", &["SYMBOL"]), ("kind", &["SYMBOL"]), ("size", LONG), ("open", DOUBLE), ("high", DOUBLE),
+                ("low", DOUBLE), ("close", DOUBLE), ("volume", DOUBLE), ("trades", LONG), ("imbalance", LONG),
+                ("close_ts_ms", LONG),
+            ],
+            upsert_keys: &["symbol", "kind", "size"],
+        });
+    }
+    out
+}
+
+/// Migrations newer than `applied`, in order.
+pub fn pending(migrations: &[Migration], applied: u32) -> Vec<&Migration> {
+    let mut out: Vec<_> = migrations.iter().filter(|m| m.version > applied).collect();
+    out.sort_by_key(|m| m.version);
+    out
+}
+
+/// Check a table's columns against what we expect of it.
+pub fn check(expect: &Expect, columns: &[Col]) -> Result<()> {
+    let find = |name: &str| columns.iter().find(|c| c.name == name);
+    for (name, types) in &expect.columns {
+        match find(name) {
+            None => anyhow::bail!("{}: missing column {}", expect.table, name),
+            Some(c) if !types.contains(&c.ty.to_uppercase().as_str()) => {
+                anyhow::bail!("{}.{} is {}, expected {}", expect.table, name, c.ty, types.join(" or "))
+            }
+            Some(_) => {}
+        }
+    }
+    let designated = columns.iter().find(|c| c.designated).map(|c| c.name.as_str());
+    anyhow::ensure!(
+        designated == Some(expect.timestamp),
+        "{}: designated timestamp is {:?}, expected {}", expect.table, designated, expect.timestamp
+    );
+    for key in expect.upsert_keys.iter().chain([&expect.timestamp]) {
+        anyhow::ensure!(find(key).is_some_and(|c| c.upsert_key), "{}: {} is not a dedup upsert key", expect.table, key);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct Column {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExecResponse {
+    #[serde(default)]
+    columns: Vec<Column>,
+    #[serde(default)]
+    dataset: Vec<Vec<Value>>,
+    error: Option<String>,
+}
+
+/// A query's rows with its column names, for reading them by name.
+pub struct Rows {
+    pub columns: Vec<String>,
+    pub dataset: Vec<Vec<Value>>,
+}
+
+impl Rows {
+    /// Where `name` is in each row.
+    pub fn column(&self, name: &str) -> Result<usize> {
+        self.columns.iter().position(|c| c == name).with_context(|| format!("no {} column", name))
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+pub struct Client {
+    http: reqwest::Client,
+    base: String,
+    token: Option<Watched>,
+}
+
+impl Client {
+    pub fn new(base: &str) -> Self {
+        Self { http: reqwest::Client::new(), base: base.trim_end_matches('/').to_string(), token: None }
+    }
+
+    /// Send `Authorization: Bearer <token>` (QuestDB Enterprise REST tokens).
+    pub fn with_token(mut self, token: Option<Watched>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// A client for `base` that sends `QDB_HTTP_TOKEN`, if one is set.
+    pub async fn authed(base: &str) -> Result<Self> {
+        Ok(Self::new(base).with_token(secrets::Secrets::from_env()?.watch("QDB_HTTP_TOKEN").await?))
+    }
+
+    /// Give up on a request after `timeout`; there's no limit by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Result<Self> {
+        self.http = reqwest::Client::builder().timeout(timeout).build()?;
+        Ok(self)
+    }
+
+    async fn exec(&self, sql: &str) -> Result<ExecResponse> {
+        let mut req = self.http.get(format!("{}/exec", self.base)).query(&[("query", sql)]);
+        if let Some(t) = &self.token {
+            req = req.bearer_auth(t.current().expose());
+        }
+        let resp: ExecResponse = req.send().await?.json().await?;
+        if let Some(e) = resp.error {
+            anyhow::bail!("questdb: {} (in {:?})", e, sql);
+        }
+        Ok(resp)
+    }
+
+    /// Run a query and return its rows.
+    pub async fn query(&self, sql: &str) -> Result<Vec<Vec<Value>>> {
+        Ok(self.exec(sql).await?.dataset)
+    }
+
+    /// Run a query and return its rows with the column names.
+    pub async fn rows(&self, sql: &str) -> Result<Rows> {
+        let resp = self.exec(sql).await?;
+        Ok(Rows { columns: resp.columns.into_iter().map(|c| c.name).collect(), dataset: resp.dataset })
+    }
+
+    /// QuestDB answers a trivial query; a startup check.
+    pub async fn ping(&self) -> Result<()> {
+        self.exec("SELECT 1").await.with_context(|| format!("QuestDB at {}", self.base))?;
+        Ok(())
+    }
+
+    /// Highest logged migration, creating the log table on first run.
+    pub async fn applied(&self) -> Result<u32> {
+        self.exec(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (version INT, name VARCHAR, applied_at TIMESTAMP) TIMESTAMP(applied_at)",
+            log_table()
+        )).await?;
+        let rows = self.exec(&format!("SELECT max(version) FROM {}", log_table())).await?.dataset;
+        Ok(rows.first().and_then(|r| r.first()).and_then(Value::as_u64).unwrap_or(0) as u32)
+    }
+
+    pub async fn apply(&self, m: &Migration) -> Result<()> {
+        for sql in &m.statements {
+            self.exec(sql).await.with_context(|| format!("migration {} ({})", m.version, m.name))?;
+        }
+        self.exec(&format!("INSERT INTO {} VALUES({}, {}, now())", log_table(), m.version, quote(m.name))).await?;
+        Ok(())
+    }
+
+    pub async fn verify(&self, expect: &Expect) -> Result<()> {
+        let sql = format!(
+            "SELECT \"column\", type, designated, upsertKey FROM table_columns({})",
+            quote(&expect.table)
+        );
+        let rows = self.exec(&sql).await?.dataset;
+        anyhow::ensure!(!rows.is_empty(), "{}: table does not exist", expect.table);
+        let columns: Vec<Col> = rows.iter()
+            .filter_map(|r| Some(Col {
+                name: r.first()?.as_str()?.to_string(),
+                ty: r.get(1)?.as_str()?.to_string(),
+                designated: r.get(2)?.as_bool()?,
+                upsert_key: r.get(3)?.as_bool()?,
+            }))
+            .collect();
+        check(expect, &columns)
+    }
+}
+
+/// Apply pending migrations and verify the tables. Returns the schema version now in place.
+pub async fn bootstrap(client: &Client, migrations: &[Migration], expected: &[Expect]) -> Result<u32> {
+    let mut version = client.applied().await?;
+    for m in pending(migrations, version) {
+        tracing::info!(target="consumer", version = m.version, name = m.name, "applying QuestDB migration");
+        client.apply(m).await?;
+        version = m.version;
+    }
+    for e in expected {
+        client.verify(e).await?;
+    }
+    Ok(version)
+}
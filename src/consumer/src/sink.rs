@@ -0,0 +1,335 @@
+//! Buffered ILP connection to QuestDB.
+//!
+//! Batches go out with `write_vectored`, one slice per symbol chunk, through a
+//! `BufWriter`; nothing hits the socket until the buffer fills or [`IlpSink::write_chunks`]
+//! flushes at the end of a batch. `TCP_NODELAY` is on by default so the
+//! tail of a batch isn't held back by Nagle behind the previous segment.
+//!
+//! [`IlpPool`] spreads those connections over several QuestDB nodes and is
+//! the `questdb` [`Sink`]; see [`crate::tee`] for running it alongside others,
+//! [`crate::verify`] for checking what it wrote landed, and
+//! [`crate::cardinality`] for its cap on symbols per table.
+//! [`FlushSchedule`] decides when a batch is due.
+
+use std::borrow::Cow;
+use std::io::IoSlice;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use metrics::{counter, gauge, histogram};
+use obsv::measure_ms_async;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::net::TcpStream;
+
+use crate::batch::Batch;
+use crate::cardinality::{self, Guard};
+use crate::ilp::ilp_connect;
+use crate::verify::Ledger;
+
+/// Somewhere a batch of trades is persisted.
+#[async_trait]
+pub trait Sink: Send {
+    /// Short name used in metrics labels and env keys (`questdb`, `parquet`).
+    fn name(&self) -> &str;
+
+    /// Persist every row of `batch`. On error it may be called again with the same batch.
+    async fn write(&mut self, batch: &Batch) -> Result<()>;
+
+    /// Make anything still buffered durable; called once when the stream ends.
+    async fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// When the consumer's batch is written.
+///
+/// By default a batch is due `max_wait` after its first message. With `align`
+/// set to QuestDB's commit lag (`cairo.o3.max.lag`) it's due at the next
+/// multiple of `align` on the wall clock instead, so each commit window gets
+/// one write and QuestDB merges its out-of-order rows once rather than once
+/// per small write. A batch of `max_rows` is due either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlushSchedule {
+    pub max_rows: usize,
+    pub max_wait: Duration,
+    pub align: Option<Duration>,
+}
+
+impl FlushSchedule {
+    /// How much longer a batch started at `started` may grow; `now_ms` is the
+    /// wall clock at `now`.
+    pub fn wait(&self, started: Instant, now: Instant, now_ms: i64) -> Duration {
+        let Some(every) = self.align.map(|a| a.as_millis().max(1) as i64) else {
+            return (started + self.max_wait).saturating_duration_since(now);
+        };
+        // The boundary after the batch started, so a late wakeup can't push it a window on.
+        let started_ms = now_ms - now.saturating_duration_since(started).as_millis() as i64;
+        let due_ms = (started_ms.div_euclid(every) + 1) * every;
+        Duration::from_millis((due_ms - now_ms).max(0) as u64)
+    }
+
+    pub fn full(&self, rows: usize) -> bool {
+        rows >= self.max_rows
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IlpOptions {
+    pub nodelay: bool,
+    /// `BufWriter` capacity; chunks at least this big bypass the buffer.
+    pub buf_bytes: usize,
+}
+
+impl Default for IlpOptions {
+    fn default() -> Self {
+        Self { nodelay: true, buf_bytes: 64 * 1024 }
+    }
+}
+
+pub struct IlpSink {
+    host: String,
+    port: u16,
+    opts: IlpOptions,
+    conn: BufWriter<TcpStream>,
+}
+
+async fn open(host: &str, port: u16, opts: IlpOptions) -> Result<BufWriter<TcpStream>> {
+    let stream = ilp_connect(host, port).await?;
+    stream.set_nodelay(opts.nodelay)?;
+    Ok(BufWriter::with_capacity(opts.buf_bytes, stream))
+}
+
+impl IlpSink {
+    pub async fn connect(host: &str, port: u16, opts: IlpOptions) -> Result<Self> {
+        let conn = open(host, port, opts).await?;
+        Ok(Self { host: host.to_string(), port, opts, conn })
+    }
+
+    /// Drop the current connection (and anything still buffered) and dial again.
+    pub async fn reconnect(&mut self) -> Result<()> {
+        self.conn = open(&self.host, self.port, self.opts).await?;
+        Ok(())
+    }
+
+    pub fn nodelay(&self) -> std::io::Result<bool> {
+        self.conn.get_ref().nodelay()
+    }
+
+    /// Write every chunk, then flush. Short vectored writes are resumed where they stopped.
+    pub async fn write_chunks(&mut self, chunks: &[&[u8]]) -> std::io::Result<()> {
+        chaos::fault("questdb").map_err(std::io::Error::other)?;
+        let mut slices: Vec<IoSlice<'_>> = chunks.iter().map(|c| IoSlice::new(c)).collect();
+        let mut bufs = &mut slices[..];
+        while !bufs.is_empty() {
+            let n = self.conn.write_vectored(bufs).await?;
+            if n == 0 {
+                return Err(std::io::ErrorKind::WriteZero.into());
+            }
+            IoSlice::advance_slices(&mut bufs, n);
+        }
+        self.conn.flush().await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolMode {
+    /// Always write to the first healthy endpoint in list order; fails back once it recovers.
+    Failover,
+    /// Spread batches over all healthy endpoints in turn.
+    RoundRobin,
+}
+
+impl PoolMode {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "failover" => Ok(Self::Failover),
+            "round_robin" | "round-robin" => Ok(Self::RoundRobin),
+            other => anyhow::bail!("unknown QuestDB pool mode {:?} (expected failover|round_robin)", other),
+        }
+    }
+}
+
+/// Parse `host[:port],host[:port],...`; entries without a port get `default_port`.
+pub fn parse_endpoints(list: &str, default_port: u16) -> Result<Vec<(String, u16)>> {
+    let eps = list
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| match s.rsplit_once(':') {
+            Some((h, p)) => Ok((h.to_string(), p.parse().map_err(|_| anyhow::anyhow!("bad port in {:?}", s))?)),
+            None => Ok((s.to_string(), default_port)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    anyhow::ensure!(!eps.is_empty(), "no QuestDB endpoints in {:?}", list);
+    Ok(eps)
+}
+
+struct Endpoint {
+    host: String,
+    port: u16,
+    label: String,
+    /// `None` while the endpoint is considered down.
+    conn: Option<IlpSink>,
+}
+
+/// ILP connections to several QuestDB nodes (replicas or a hot standby).
+///
+/// A write that fails is retried once on a fresh connection to the same node;
+/// if that fails too the node is marked down and the next one takes the batch.
+/// Down nodes are re-dialled every `probe_every`, or right away when none are up.
+pub struct IlpPool {
+    endpoints: Vec<Endpoint>,
+    mode: PoolMode,
+    opts: IlpOptions,
+    next: usize,
+    probe_every: Duration,
+    last_probe: Instant,
+    /// For `ilp_flush_interval_ms`; `None` until the first write.
+    last_write: Option<Instant>,
+    /// Where written rows are recorded for verification; `None` when it's off.
+    ledger: Option<Arc<Mutex<Ledger>>>,
+    /// Caps the symbols per table; `None` lets any number in.
+    guard: Option<Guard>,
+}
+
+const PROBE_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+impl IlpPool {
+    /// Connect to every endpoint; fails only if none is reachable.
+    pub async fn connect(endpoints: Vec<(String, u16)>, mode: PoolMode, opts: IlpOptions, probe_every: Duration) -> Result<Self> {
+        let endpoints = endpoints
+            .into_iter()
+            .map(|(host, port)| Endpoint { label: format!("{}:{}", host, port), host, port, conn: None })
+            .collect();
+        let mut pool = Self { endpoints, mode, opts, next: 0, probe_every, last_probe: Instant::now(), last_write: None, ledger: None, guard: None };
+        pool.probe().await;
+        anyhow::ensure!(pool.healthy() > 0, "no QuestDB endpoint reachable");
+        Ok(pool)
+    }
+
+    /// Record every trade row written in `ledger`.
+    pub fn verified(mut self, ledger: Option<Arc<Mutex<Ledger>>>) -> Self {
+        self.ledger = ledger;
+        self
+    }
+
+    /// Send symbols over `guard`'s limit to its overflow table.
+    pub fn guarded(mut self, guard: Option<Guard>) -> Self {
+        self.guard = guard;
+        self
+    }
+
+    /// The batch's chunks, with the lines of symbols the guard turns away
+    /// rewritten to its overflow table.
+    fn guard_chunks<'a>(&mut self, batch: &'a Batch) -> Vec<Cow<'a, [u8]>> {
+        let Some(guard) = self.guard.as_mut() else {
+            return batch.chunks().map(Cow::Borrowed).collect();
+        };
+        let mut chunks = Vec::new();
+        for (symbol, rows, lines) in batch.symbol_chunks() {
+            let table = cardinality::line_table(lines);
+            match guard.route(table, symbol) {
+                None => chunks.push(Cow::Borrowed(lines.as_bytes())),
+                Some(overflow) => {
+                    counter!("ilp_overflow_rows_total", "table" => table.to_string()).increment(rows as u64);
+                    if let Some(ledger) = &self.ledger {
+                        ledger.lock().unwrap_or_else(PoisonError::into_inner).reroute(symbol, overflow);
+                    }
+                    chunks.push(Cow::Owned(cardinality::retable(lines, overflow).into_bytes()));
+                }
+            }
+        }
+        if !batch.extra().is_empty() {
+            chunks.push(Cow::Borrowed(batch.extra().as_bytes()));
+        }
+        chunks
+    }
+
+    pub fn healthy(&self) -> usize {
+        self.endpoints.iter().filter(|e| e.conn.is_some()).count()
+    }
+
+    /// Re-dial every endpoint that is down.
+    pub async fn probe(&mut self) {
+        for ep in &mut self.endpoints {
+            if ep.conn.is_none() {
+                match tokio::time::timeout(PROBE_CONNECT_TIMEOUT, IlpSink::connect(&ep.host, ep.port, self.opts)).await {
+                    Ok(Ok(sink)) => {
+                        tracing::info!(target="consumer", endpoint=%ep.label, "QuestDB endpoint up");
+                        ep.conn = Some(sink);
+                    }
+                    Ok(Err(e)) => tracing::debug!(target="consumer", endpoint=%ep.label, error=?e, "QuestDB endpoint still down"),
+                    Err(_) => tracing::debug!(target="consumer", endpoint=%ep.label, "QuestDB probe timed out"),
+                }
+            }
+            gauge!("questdb_endpoint_up", "endpoint" => ep.label.clone()).set(if ep.conn.is_some() { 1.0 } else { 0.0 });
+        }
+        self.last_probe = Instant::now();
+    }
+
+    /// Write a batch to one endpoint, moving on to the others if it fails.
+    /// Returns the label of the endpoint that took it.
+    pub async fn write_chunks(&mut self, chunks: &[&[u8]]) -> Result<&str> {
+        if self.healthy() == 0 || self.last_probe.elapsed() >= self.probe_every {
+            self.probe().await;
+        }
+        let n = self.endpoints.len();
+        let start = match self.mode {
+            PoolMode::Failover => 0,
+            PoolMode::RoundRobin => self.next,
+        };
+        for i in (start..start + n).map(|i| i % n) {
+            let ep = &mut self.endpoints[i];
+            let Some(sink) = ep.conn.as_mut() else { continue };
+            let res = match sink.write_chunks(chunks).await {
+                Ok(()) => Ok(()),
+                Err(e) => {
+                    tracing::warn!(target="consumer", endpoint=%ep.label, error=?e, "ILP write failed; reconnecting once");
+                    match sink.reconnect().await {
+                        Ok(()) => sink.write_chunks(chunks).await.map_err(anyhow::Error::from),
+                        Err(e) => Err(e),
+                    }
+                }
+            };
+            match res {
+                Ok(()) => {
+                    self.next = (i + 1) % n;
+                    return Ok(&self.endpoints[i].label);
+                }
+                Err(e) => {
+                    tracing::error!(target="consumer", endpoint=%ep.label, error=?e, "QuestDB endpoint down; failing over");
+                    ep.conn = None;
+                    gauge!("questdb_endpoint_up", "endpoint" => ep.label.clone()).set(0.0);
+                    counter!("questdb_failovers_total", "endpoint" => ep.label.clone()).increment(1);
+                }
+            }
+        }
+        anyhow::bail!("no QuestDB endpoint accepted the write")
+    }
+}
+
+#[async_trait]
+impl Sink for IlpPool {
+    fn name(&self) -> &str {
+        "questdb"
+    }
+
+    async fn write(&mut self, batch: &Batch) -> Result<()> {
+        let owned = self.guard_chunks(batch);
+        let chunks: Vec<&[u8]> = owned.iter().map(|c| c.as_ref()).collect();
+        let (res, write_ms) = measure_ms_async(self.write_chunks(&chunks)).await;
+        histogram!("questdb_write_ms").record(write_ms);
+        let endpoint = res?.to_string();
+        let now = Instant::now();
+        if let Some(last) = self.last_write.replace(now) {
+            histogram!("ilp_flush_interval_ms").record(now.duration_since(last).as_secs_f64() * 1e3);
+        }
+        histogram!("ilp_rows_per_flush").record(batch.rows() as f64);
+        if let Some(ledger) = &self.ledger {
+            ledger.lock().unwrap_or_else(PoisonError::into_inner).record_batch(&endpoint, batch, chrono::Utc::now().timestamp_millis());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+//! Spill a batch no sink could take to disk instead of holding it in memory.
+//!
+//! Files use the replayer's capture format (NDJSON, one message per line), so
+//! `replayer replay --file <spill file> --speed 0` puts them back on the topic.
+//! Replaying sends them to every sink again, including the ones that had
+//! already taken the batch.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine;
+use bus::Delivery;
+use chrono::Utc;
+use serde_json::{json, Value};
+
+/// Text if it's UTF-8, `{"b64": ...}` otherwise, as the capture format expects.
+fn blob(bytes: &[u8]) -> Value {
+    match std::str::from_utf8(bytes) {
+        Ok(t) => Value::String(t.to_string()),
+        Err(_) => json!({ "b64": B64.encode(bytes) }),
+    }
+}
+
+fn record(d: &Delivery) -> Value {
+    let headers: Vec<_> = d.headers.iter().map(|(k, v)| json!([k, v])).collect();
+    json!({
+        "topic": d.topic,
+        "partition": d.partition().unwrap_or(-1),
+        "offset": d.offset().unwrap_or(-1),
+        "ts_ms": null,
+        "key": d.key,
+        "payload": blob(&d.payload),
+        "headers": headers,
+    })
+}
+
+pub struct Spill {
+    dir: PathBuf,
+    seq: u64,
+}
+
+impl Spill {
+    pub fn new(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        Ok(Self { dir: dir.to_path_buf(), seq: 0 })
+    }
+
+    /// Write `ds` to a new file, synced before it gets its final name.
+    pub fn write(&mut self, ds: &[Delivery]) -> Result<PathBuf> {
+        self.seq += 1;
+        let name = format!("spill-{}-{}.ndjson", Utc::now().timestamp_millis(), self.seq);
+        let tmp = self.dir.join(format!("{}.tmp", name));
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        for d in ds {
+            serde_json::to_writer(&mut w, &record(d))?;
+            w.write_all(b"\n")?;
+        }
+        w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        let done = self.dir.join(name);
+        std::fs::rename(&tmp, &done)?;
+        Ok(done)
+    }
+}
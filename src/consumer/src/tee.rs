@@ -0,0 +1,146 @@
+//! Fan a batch out to every configured sink.
+//!
+//! Each sink gets its own retry policy, and optionally a breaker that stops
+//! trying it for a while after a run of failures. A sink that still fails
+//! after its retries, or whose breaker is open, sends the batch's rows to its
+//! dead-letter topic, if it has one, so one slow or broken archive doesn't
+//! hold back the others. The batch is only reported as lost
+//! when a sink failed and its rows couldn't be dead-lettered either. The
+//! sinks that lost it stay owed the batch, so writing it again only retries them.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use bus::envelope::Envelope;
+use bus::{Headers, Publisher, MSG_ID};
+use metrics::{counter, histogram};
+use obsv::errors::{self, ErrorKind};
+use obsv::measure_ms_async;
+use retry::{Open, Policy};
+
+use crate::batch::Batch;
+use crate::sink::Sink;
+
+/// Header naming the sink a dead-lettered row failed in.
+pub const DLQ_SINK: &str = "dlq_sink";
+/// Header carrying the last error that sink returned.
+pub const DLQ_ERROR: &str = "dlq_error";
+/// Header on a message dropped before any sink saw it, instead of
+/// [`DLQ_SINK`]: its `dropped_total` reason.
+pub const DLQ_REASON: &str = "dlq_reason";
+
+pub struct Slot {
+    pub sink: Box<dyn Sink>,
+    /// `op` is `sink_{name}`, e.g. `sink_questdb`.
+    pub policy: Policy,
+    /// Empty disables dead-lettering for this sink.
+    pub dlq_topic: String,
+}
+
+pub struct Tee {
+    slots: Vec<Slot>,
+    dlq: Option<Arc<dyn Publisher>>,
+    envelope: Envelope,
+    /// Per slot: still has to take the current batch.
+    owed: Vec<bool>,
+}
+
+impl Tee {
+    /// `dlq` is only needed when some slot has a `dlq_topic`.
+    pub fn new(slots: Vec<Slot>, dlq: Option<Arc<dyn Publisher>>, envelope: Envelope) -> Self {
+        let owed = vec![true; slots.len()];
+        Self { slots, dlq, envelope, owed }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.slots.iter().map(|s| s.sink.name()).collect()
+    }
+
+    /// Write to every owed sink concurrently. `false` means at least one sink
+    /// lost the batch; call again with the same batch to retry just those, or
+    /// [`skip`](Tee::skip) to give up on it.
+    pub async fn write(&mut self, batch: &Batch) -> bool {
+        let dlq = self.dlq.as_deref();
+        let envelope = &self.envelope;
+        let owed = self.slots.iter_mut().zip(self.owed.iter_mut()).filter(|(_, owed)| **owed);
+        futures_util::future::join_all(owed.map(|(slot, owed)| async move {
+            *owed = !write_slot(slot, batch, dlq, envelope).await;
+        })).await;
+        let done = self.owed.iter().all(|owed| !owed);
+        if done {
+            self.skip();
+        }
+        done
+    }
+
+    /// Move on to the next batch, whether or not every sink took this one.
+    pub fn skip(&mut self) {
+        self.owed.iter_mut().for_each(|owed| *owed = true);
+    }
+
+    /// Close every sink, reporting the first error after trying them all.
+    pub async fn close(&mut self) -> Result<()> {
+        let mut first = None;
+        for slot in &mut self.slots {
+            if let Err(e) = slot.sink.close().await {
+                tracing::error!(target="consumer", sink=slot.sink.name(), error=?e, "sink close failed");
+                first.get_or_insert(e);
+            }
+        }
+        first.map_or(Ok(()), Err)
+    }
+}
+
+async fn write_slot(slot: &mut Slot, batch: &Batch, dlq: Option<&dyn Publisher>, envelope: &Envelope) -> bool {
+    let name = slot.sink.name().to_string();
+    let op = format!("sink_{}", name);
+    let mut retry = slot.policy.start(&op);
+    let err = loop {
+        if slot.policy.is_open() {
+            counter!("sink_short_circuited_total", "sink" => name.clone()).increment(1);
+            break Open(op.clone()).into();
+        }
+        let (res, ms) = measure_ms_async(slot.sink.write(batch)).await;
+        histogram!("sink_write_ms", "sink" => name.clone()).record(ms);
+        match res {
+            Ok(()) => {
+                retry.succeeded();
+                return true;
+            }
+            Err(e) => {
+                counter!("sink_failures_total", "sink" => name.clone()).increment(1);
+                errors::record("consumer", ErrorKind::Transient);
+                tracing::warn!(target="consumer", sink=%name, attempt=retry.attempt() + 1, error=?e, "sink write failed");
+                if !retry.failed(&e).await {
+                    break e;
+                }
+            }
+        }
+    };
+
+    let Some(publisher) = dlq.filter(|_| !slot.dlq_topic.is_empty()) else {
+        tracing::error!(target="consumer", sink=%name, rows=batch.rows(), error=?err, "sink write failed; no DLQ, rows lost for this sink");
+        counter!("sink_dropped_rows_total", "sink" => name).increment(batch.rows() as u64);
+        return false;
+    };
+    let error = format!("{:#}", err);
+    let mut ok = true;
+    for row in batch.iter_rows() {
+        let headers = envelope.apply(
+            Headers::new().with(MSG_ID, &row.msg_id).with(DLQ_SINK, &name).with(DLQ_ERROR, &error),
+        );
+        let res = match serde_json::to_vec(&row.trade) {
+            Ok(body) => publisher.publish(&slot.dlq_topic, &row.trade.symbol, &body, &headers).await,
+            Err(e) => Err(e.into()),
+        };
+        match res {
+            Ok(()) => counter!("sink_dlq_total", "sink" => name.clone()).increment(1),
+            Err(e) => {
+                tracing::error!(target="consumer", sink=%name, error=?e, "DLQ publish failed");
+                counter!("sink_dropped_rows_total", "sink" => name.clone()).increment(1);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
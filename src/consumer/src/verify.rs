@@ -0,0 +1,307 @@
+//! Whether the trades ILP took actually landed. TCP ILP never answers, so a
+//! row QuestDB rejects (a type clash, a full disk, a WAL that won't apply)
+//! only shows up in QuestDB's own log.
+//!
+//! With `QDB_VERIFY_EVERY_SECS` set, the questdb sink keeps a [`Ledger`] of
+//! the rows it wrote, by the endpoint that took them, table and window of
+//! event time, each row once by its dedup key. Once a window has had no
+//! writes for the settle time, a [`Check`] counts its rows back over that
+//! endpoint's PG wire port (see [`crate::pg`]), so independent nodes behind
+//! a round-robin pool are each asked for their own rows. Only this consumer's
+//! symbols are counted, and the upsert keys fold duplicates, so anything
+//! short of what was sent was lost: it's counted in
+//! `questdb_verify_missing_rows_total{endpoint,table}`. Rows other writers
+//! added can only make the count higher, hiding a loss but never inventing one.
+//!
+//! A window that comes up short is counted again each round for the recheck
+//! time before its shortfall is counted, so a WAL that applies late (a busy
+//! node, a table being altered) doesn't show up as a loss.
+//!
+//! The ledger holds at most `QDB_VERIFY_MAX_ROWS` keys. Rows past that aren't
+//! recorded: their window counts fewer rows sent than it got, which can hide
+//! a loss but not invent one. They're counted in
+//! `questdb_verify_unrecorded_rows_total{endpoint,table}`.
+//!
+//! Each round also compares the newest row sent to each table with QuestDB's
+//! `max(timestamp)`, in `questdb_verify_last_ts_lag_ms{endpoint,table}`.
+//!
+//! Only trade rows are verified. Bars, volume profile levels and checkpoints
+//! ride along as extra lines, and depth snapshots from `BOOK_TOPIC` are
+//! written by a task of their own; none of them are recorded, so a loss there
+//! goes unnoticed. The consumer writes no quotes: the joiner's are its own.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use metrics::{counter, gauge};
+use serde_json::Value;
+
+use crate::batch::Batch;
+use crate::pg::PgClient;
+use crate::schema;
+
+#[derive(Default)]
+struct Window {
+    /// `(trade_id, ts_ms)` by symbol: with the table, the row's upsert key.
+    rows: HashMap<String, HashSet<(i64, i64)>>,
+    /// Wall clock of the last write into the window.
+    touched_ms: i64,
+}
+
+/// An endpoint's label, a table and a window's start.
+type WindowKey = (String, String, i64);
+
+/// Rows written per endpoint, table and event-time window, until they're checked.
+pub struct Ledger {
+    window_ms: i64,
+    /// Routed symbols' own tables; everything else goes to [`schema::trades_table`].
+    tables: HashMap<String, String>,
+    windows: BTreeMap<WindowKey, Window>,
+    /// The newest `ts_ms` written to each endpoint's tables.
+    newest: HashMap<(String, String), i64>,
+    /// Keys held across every window, and how many it may hold.
+    held: usize,
+    max_rows: usize,
+}
+
+impl Ledger {
+    pub fn new(window: Duration, tables: HashMap<String, String>) -> Self {
+        Self { window_ms: (window.as_millis() as i64).max(1), tables, windows: BTreeMap::new(), newest: HashMap::new(), held: 0, max_rows: usize::MAX }
+    }
+
+    /// Hold at most `max_rows` keys; rows past that go unrecorded until windows are checked.
+    pub fn capped(mut self, max_rows: usize) -> Self {
+        self.max_rows = max_rows;
+        self
+    }
+
+    fn table(&self, symbol: &str) -> String {
+        self.tables.get(symbol).cloned().unwrap_or_else(|| schema::trades_table().to_string())
+    }
+
+    /// Count `symbol`'s rows in `table` from now on, e.g. once it overflows there.
+    pub fn reroute(&mut self, symbol: &str, table: &str) {
+        self.tables.insert(symbol.to_string(), table.to_string());
+    }
+
+    /// A row `endpoint` took over ILP at wall-clock `now_ms`.
+    pub fn record(&mut self, endpoint: &str, symbol: &str, trade_id: i64, ts_ms: i64, now_ms: i64) {
+        let table = self.table(symbol);
+        let newest = self.newest.entry((endpoint.to_string(), table.clone())).or_insert(ts_ms);
+        *newest = (*newest).max(ts_ms);
+        let start = ts_ms.div_euclid(self.window_ms) * self.window_ms;
+        let key = (endpoint.to_string(), table, start);
+        let seen = self.windows.get(&key).and_then(|w| w.rows.get(symbol)).is_some_and(|r| r.contains(&(trade_id, ts_ms)));
+        if !seen && self.held >= self.max_rows {
+            counter!("questdb_verify_unrecorded_rows_total", "endpoint" => key.0.clone(), "table" => key.1.clone()).increment(1);
+            // Still written to: the window isn't settled yet.
+            if let Some(w) = self.windows.get_mut(&key) {
+                w.touched_ms = now_ms;
+            }
+            return;
+        }
+        let w = self.windows.entry(key).or_default();
+        if w.rows.entry(symbol.to_string()).or_default().insert((trade_id, ts_ms)) {
+            self.held += 1;
+        }
+        w.touched_ms = now_ms;
+    }
+
+    /// Every trade row of a batch `endpoint` took.
+    pub fn record_batch(&mut self, endpoint: &str, batch: &Batch, now_ms: i64) {
+        for row in batch.iter_rows() {
+            self.record(endpoint, &row.trade.symbol, row.trade.trade_id, row.trade.ts_ms, now_ms);
+        }
+    }
+
+    /// Windows untouched for `settle_ms`, taken out of the ledger to be checked.
+    pub fn due(&mut self, now_ms: i64, settle_ms: i64) -> Vec<Check> {
+        let ready: Vec<WindowKey> = self.windows.iter()
+            .filter(|(_, w)| now_ms - w.touched_ms >= settle_ms)
+            .map(|(k, _)| k.clone())
+            .collect();
+        ready.into_iter()
+            .filter_map(|key| {
+                let w = self.windows.remove(&key)?;
+                let mut symbols: Vec<String> = w.rows.keys().cloned().collect();
+                symbols.sort();
+                let sent: u64 = w.rows.values().map(|r| r.len() as u64).sum();
+                self.held -= sent as usize;
+                let (endpoint, table, from_ms) = key;
+                Some(Check { endpoint, table, from_ms, to_ms: from_ms + self.window_ms, symbols, sent, attempts: 0, short_since_ms: None })
+            })
+            .collect()
+    }
+
+    /// Windows written and not yet checked.
+    pub fn pending(&self) -> usize {
+        self.windows.len()
+    }
+
+    /// Row keys held, across every pending window.
+    pub fn held(&self) -> usize {
+        self.held
+    }
+
+    /// `(endpoint, table, newest ts_ms written)`.
+    pub fn newest(&self) -> Vec<(String, String, i64)> {
+        let mut out: Vec<(String, String, i64)> = self.newest.iter().map(|((e, t), ts)| (e.clone(), t.clone(), *ts)).collect();
+        out.sort();
+        out
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// One window to count back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// The label of the endpoint that took the rows.
+    pub endpoint: String,
+    pub table: String,
+    pub from_ms: i64,
+    pub to_ms: i64,
+    pub symbols: Vec<String>,
+    /// Distinct rows written.
+    pub sent: u64,
+    /// Failed queries so far; the check is dropped after [`MAX_ATTEMPTS`].
+    pub attempts: u32,
+    /// When the window first counted short; it's counted again until the recheck time has passed.
+    pub short_since_ms: Option<i64>,
+}
+
+/// Queries a check gets before it's given up on.
+pub const MAX_ATTEMPTS: u32 = 3;
+
+impl Check {
+    /// Count this consumer's rows in the window, on the designated timestamp (µs)
+    /// so QuestDB can prune partitions.
+    pub fn sql(&self) -> String {
+        let symbols: Vec<String> = self.symbols.iter().map(|s| quote(s)).collect();
+        format!(
+            "SELECT count() FROM {} WHERE timestamp >= cast({} AS timestamp) AND timestamp < cast({} AS timestamp) AND symbol IN ({})",
+            self.table, self.from_ms * 1000, self.to_ms * 1000, symbols.join(", "),
+        )
+    }
+
+    /// Rows sent that QuestDB doesn't have.
+    pub fn missing(&self, landed: u64) -> u64 {
+        self.sent.saturating_sub(landed)
+    }
+
+    /// The rows lost, given `landed` counted at `now_ms`, once that's settled:
+    /// straight away if none are missing, otherwise once the window has been
+    /// short for `recheck_ms`. `None` means count it again.
+    pub fn lost(&mut self, landed: u64, now_ms: i64, recheck_ms: i64) -> Option<u64> {
+        let missing = self.missing(landed);
+        if missing == 0 {
+            return Some(0);
+        }
+        let since = *self.short_since_ms.get_or_insert(now_ms);
+        (now_ms - since >= recheck_ms).then_some(missing)
+    }
+}
+
+/// The newest designated timestamp in `table`, in µs since the epoch; NULL when it's empty.
+pub fn last_sql(table: &str) -> String {
+    format!("SELECT cast(max(timestamp) AS long) FROM {}", table)
+}
+
+/// A number cell as PG wire sends it (text), or as JSON.
+fn number(v: &Value) -> Option<i64> {
+    match v {
+        Value::String(s) => s.trim().parse().ok(),
+        v => v.as_i64(),
+    }
+}
+
+/// [`last_sql`]'s answer in ms; `None` for an empty table.
+pub fn parse_last(rows: &[Vec<Value>]) -> Result<Option<i64>> {
+    match rows.first().and_then(|r| r.first()) {
+        None | Some(Value::Null) => Ok(None),
+        Some(v) => Ok(Some(number(v).with_context(|| format!("max(timestamp) came back as {}", v))?.div_euclid(1000))),
+    }
+}
+
+/// `count()`'s answer.
+pub fn parse_count(rows: &[Vec<Value>]) -> Result<u64> {
+    let n = rows.first().and_then(|r| r.first()).and_then(number).context("count() came back empty")?;
+    u64::try_from(n).with_context(|| format!("count() came back as {}", n))
+}
+
+/// Count `c` back. True once it's settled: the rows were all there, or the
+/// shortfall has lasted `recheck_ms` and is counted as lost.
+async fn check(client: &mut PgClient, c: &mut Check, now_ms: i64, recheck_ms: i64) -> Result<bool> {
+    let landed = parse_count(&client.query(&c.sql()).await?)?;
+    let Some(missing) = c.lost(landed, now_ms, recheck_ms) else {
+        counter!("questdb_verify_rechecks_total", "endpoint" => c.endpoint.clone(), "table" => c.table.clone()).increment(1);
+        tracing::debug!(target="consumer", endpoint=%c.endpoint, table=%c.table, from_ms=c.from_ms, sent=c.sent, landed,
+            "window counted short; checking again before calling it lost");
+        return Ok(false);
+    };
+    let labels = [("endpoint", c.endpoint.clone()), ("table", c.table.clone())];
+    counter!("questdb_verify_sent_rows_total", &labels).increment(c.sent);
+    counter!("questdb_verify_missing_rows_total", &labels).increment(missing);
+    gauge!("questdb_verify_divergence", &labels).set(missing as f64);
+    if missing > 0 {
+        tracing::error!(target="consumer", endpoint=%c.endpoint, table=%c.table, from_ms=c.from_ms, to_ms=c.to_ms, sent=c.sent, landed, missing,
+            "rows written over ILP are missing from QuestDB");
+    }
+    Ok(true)
+}
+
+/// Every `every`, check the windows that have settled for `settle`, each on
+/// the PG client for the endpoint that took it (by endpoint label), and how
+/// far each table's newest row is behind the newest one sent. A short window
+/// is counted again for `recheck` before it's called lost.
+pub fn spawn(ledger: Arc<Mutex<Ledger>>, mut clients: HashMap<String, PgClient>, every: Duration, settle: Duration, recheck: Duration) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(every);
+        tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut again: Vec<Check> = Vec::new();
+        loop {
+            tick.tick().await;
+            let now_ms = Utc::now().timestamp_millis();
+            let (due, newest, pending) = {
+                let mut l = ledger.lock().unwrap_or_else(PoisonError::into_inner);
+                (l.due(now_ms, settle.as_millis() as i64), l.newest(), l.pending())
+            };
+            gauge!("questdb_verify_pending_windows").set(pending as f64);
+            for mut c in std::mem::take(&mut again).into_iter().chain(due) {
+                let Some(client) = clients.get_mut(&c.endpoint) else {
+                    tracing::warn!(target="consumer", endpoint=%c.endpoint, "no PG address for this endpoint; its rows can't be verified");
+                    continue;
+                };
+                match check(client, &mut c, now_ms, recheck.as_millis() as i64).await {
+                    Ok(true) => {}
+                    Ok(false) => again.push(c),
+                    Err(e) => {
+                        counter!("questdb_verify_errors_total", "endpoint" => c.endpoint.clone(), "table" => c.table.clone()).increment(1);
+                        tracing::warn!(target="consumer", endpoint=%c.endpoint, table=%c.table, error=?e, "verification query failed");
+                        c.attempts += 1;
+                        if c.attempts < MAX_ATTEMPTS {
+                            again.push(c);
+                        }
+                    }
+                }
+            }
+            for (endpoint, table, sent_ms) in newest {
+                let Some(client) = clients.get_mut(&endpoint) else { continue };
+                match client.query(&last_sql(&table)).await.and_then(|rows| parse_last(&rows)) {
+                    // Empty: the window checks will say so.
+                    Ok(None) => {}
+                    Ok(Some(last)) => gauge!("questdb_verify_last_ts_lag_ms", "endpoint" => endpoint, "table" => table).set((sent_ms - last).max(0) as f64),
+                    Err(e) => {
+                        counter!("questdb_verify_errors_total", "endpoint" => endpoint.clone(), "table" => table.clone()).increment(1);
+                        tracing::warn!(target="consumer", endpoint=%endpoint, table=%table, error=?e, "verification query failed");
+                    }
+                }
+            }
+        }
+    });
+}
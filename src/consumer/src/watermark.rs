@@ -0,0 +1,167 @@
+//! Per-symbol completeness watermarks.
+//!
+//! Binance trade ids are contiguous per symbol, so the watermark is the event
+//! time of the highest id below which every id has been written. An aggregated
+//! trade covers the whole `first_trade_id..=trade_id` range at once. Out-of-order
+//! ids wait in a small buffer; a gap that stays open longer than the gap
+//! timeout is assumed lost upstream and skipped (and counted), otherwise one
+//! missing trade would freeze the watermark forever.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use metrics::{counter, gauge};
+use serde::Serialize;
+
+/// Id ranges buffered past a gap per symbol before the gap is force-skipped.
+const MAX_PENDING: usize = 100_000;
+/// Rough cost of one buffered id range (key, value and B-tree node share).
+const PENDING_ENTRY_BYTES: usize = 48;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Watermark {
+    pub symbol: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition: Option<i32>,
+    /// Highest trade id with no gaps at or below it.
+    pub trade_id: i64,
+    /// Event time (ms) of that trade: data is complete up to here.
+    pub ts_ms: i64,
+    /// Gaps skipped so far.
+    pub gaps_skipped: u64,
+}
+
+#[derive(Debug)]
+struct Progress {
+    mark: Watermark,
+    /// The topic of `mark.partition`: partition numbers repeat across topics.
+    topic: Option<String>,
+    /// first id -> (last id, ts_ms) for ranges written ahead of the frontier.
+    pending: BTreeMap<i64, (i64, i64)>,
+    /// When the current gap was first seen.
+    gap_since: Option<Instant>,
+    dirty: bool,
+}
+
+impl Progress {
+    fn advance(&mut self) {
+        while let Some((last, ts)) = self.pending.remove(&(self.mark.trade_id + 1)) {
+            self.mark.trade_id = last;
+            self.mark.ts_ms = self.mark.ts_ms.max(ts);
+            self.dirty = true;
+        }
+        self.gap_since = match self.pending.is_empty() {
+            true => None,
+            false => self.gap_since.or_else(|| Some(Instant::now())),
+        };
+    }
+
+    /// Jump the frontier to the oldest buffered id, giving up on the gap before it.
+    fn skip_gap(&mut self) {
+        if let Some((&id, _)) = self.pending.iter().next() {
+            counter!("watermark_gaps_skipped_total", "symbol" => self.mark.symbol.clone()).increment(1);
+            tracing::warn!(target: "consumer", symbol = %self.mark.symbol,
+                from = self.mark.trade_id + 1, to = id - 1, "skipping trade id gap");
+            self.mark.trade_id = id - 1;
+            self.mark.gaps_skipped += 1;
+            self.gap_since = None;
+            self.advance();
+        }
+    }
+}
+
+pub struct Watermarks {
+    symbols: HashMap<String, Progress>,
+    gap_timeout: Duration,
+}
+
+impl Watermarks {
+    pub fn new(gap_timeout: Duration) -> Self {
+        Self { symbols: HashMap::new(), gap_timeout }
+    }
+
+    /// Note that `trade_id` of `symbol` has been written, from `(topic, partition)` if it came off one.
+    pub fn record(&mut self, symbol: &str, at: Option<(&str, i32)>, trade_id: i64, ts_ms: i64) {
+        self.record_range(symbol, at, trade_id, trade_id, ts_ms);
+    }
+
+    /// Note that ids `first..=last` of `symbol` have been written, as one aggregated trade.
+    pub fn record_range(&mut self, symbol: &str, at: Option<(&str, i32)>, first: i64, last: i64, ts_ms: i64) {
+        let p = self.symbols.entry(symbol.to_string()).or_insert_with(|| Progress {
+            // The first id seen starts the sequence; nothing before it is ours to vouch for.
+            mark: Watermark { symbol: symbol.to_string(), partition: None, trade_id: first - 1, ts_ms, gaps_skipped: 0 },
+            topic: None,
+            pending: BTreeMap::new(),
+            gap_since: None,
+            dirty: true,
+        });
+        if let Some((topic, partition)) = at {
+            if p.topic.as_deref() != Some(topic) {
+                p.topic = Some(topic.to_string());
+            }
+            p.mark.partition = Some(partition);
+        }
+        if last <= p.mark.trade_id {
+            return; // redelivery of something already covered
+        }
+        // A range straddling the frontier only adds its uncovered tail.
+        let first = first.clamp(p.mark.trade_id + 1, last);
+        let entry = p.pending.entry(first).or_insert((last, ts_ms));
+        *entry = (entry.0.max(last), entry.1.max(ts_ms));
+        p.advance();
+        if p.pending.len() > MAX_PENDING {
+            p.skip_gap();
+        }
+    }
+
+    /// Per (topic, partition), the lowest watermark among its symbols:
+    /// everything on the partition is written up to this event time (ms).
+    pub fn by_partition(&self) -> BTreeMap<(String, i32), i64> {
+        let mut out = BTreeMap::new();
+        for p in self.symbols.values() {
+            if let (Some(topic), Some(partition)) = (&p.topic, p.mark.partition) {
+                let ts = out.entry((topic.clone(), partition)).or_insert(p.mark.ts_ms);
+                *ts = (*ts).min(p.mark.ts_ms);
+            }
+        }
+        out
+    }
+
+    /// Estimated memory held by ids buffered past gaps, across symbols.
+    pub fn bytes(&self) -> usize {
+        self.symbols.values().map(|p| p.pending.len() * PENDING_ENTRY_BYTES).sum()
+    }
+
+    /// Skip the gaps holding the most ids until the buffers fit in `limit` bytes.
+    pub fn shed(&mut self, limit: usize) {
+        let mut bytes = self.bytes();
+        while bytes > limit {
+            let Some(p) = self.symbols.values_mut().max_by_key(|p| p.pending.len()) else { break };
+            let before = p.pending.len();
+            p.skip_gap();
+            if p.pending.len() == before {
+                break;
+            }
+            bytes -= (before - p.pending.len()) * PENDING_ENTRY_BYTES;
+        }
+    }
+
+    /// Skip gaps older than the timeout, update gauges, and return the
+    /// watermarks that moved since the last call.
+    pub fn tick(&mut self, now_ms: i64) -> Vec<Watermark> {
+        let mut moved = Vec::new();
+        for p in self.symbols.values_mut() {
+            if p.gap_since.is_some_and(|t| t.elapsed() >= self.gap_timeout) {
+                p.skip_gap();
+            }
+            let labels = [("symbol", p.mark.symbol.clone())];
+            gauge!("watermark_ts_ms", &labels).set(p.mark.ts_ms as f64);
+            gauge!("watermark_lag_ms", &labels).set((now_ms - p.mark.ts_ms) as f64);
+            gauge!("watermark_pending", &labels).set(p.pending.len() as f64);
+            if std::mem::take(&mut p.dirty) {
+                moved.push(p.mark.clone());
+            }
+        }
+        moved
+    }
+}
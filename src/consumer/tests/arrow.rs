@@ -0,0 +1,154 @@
+use consumer::arrow::ArrowExport;
+use consumer::export::Dataset;
+use serde_json::json;
+
+/// Reads the few flatbuffer shapes the stream uses, checking alignment as
+/// the flatbuffers verifier does.
+struct Fb<'a>(&'a [u8]);
+
+impl Fb<'_> {
+    fn u16(&self, at: usize) -> usize {
+        u16::from_le_bytes(self.0[at..at + 2].try_into().unwrap()) as usize
+    }
+
+    fn u32(&self, at: usize) -> usize {
+        assert_eq!(at % 4, 0, "unaligned offset at {}", at);
+        u32::from_le_bytes(self.0[at..at + 4].try_into().unwrap()) as usize
+    }
+
+    fn i64(&self, at: usize) -> i64 {
+        assert_eq!(at % 8, 0, "unaligned long at {}", at);
+        i64::from_le_bytes(self.0[at..at + 8].try_into().unwrap())
+    }
+
+    fn root(&self) -> usize {
+        self.u32(0)
+    }
+
+    /// Where the field at `slot` of the table at `table` is, if it's set.
+    fn field(&self, table: usize, slot: usize) -> Option<usize> {
+        let vtable = table - i32::from_le_bytes(self.0[table..table + 4].try_into().unwrap()) as usize;
+        assert_eq!(vtable % 2, 0);
+        if 4 + 2 * slot >= self.u16(vtable) {
+            return None;
+        }
+        let off = self.u16(vtable + 4 + 2 * slot);
+        (off != 0).then_some(table + off)
+    }
+
+    fn deref(&self, at: usize) -> usize {
+        at + self.u32(at)
+    }
+
+    fn table(&self, table: usize, slot: usize) -> usize {
+        self.deref(self.field(table, slot).unwrap())
+    }
+
+    fn byte(&self, table: usize, slot: usize) -> u8 {
+        self.0[self.field(table, slot).unwrap()]
+    }
+
+    fn short(&self, table: usize, slot: usize) -> usize {
+        self.u16(self.field(table, slot).unwrap())
+    }
+
+    fn long(&self, table: usize, slot: usize) -> i64 {
+        self.i64(self.field(table, slot).unwrap())
+    }
+
+    fn string(&self, table: usize, slot: usize) -> &str {
+        let at = self.table(table, slot);
+        std::str::from_utf8(&self.0[at + 4..at + 4 + self.u32(at)]).unwrap()
+    }
+
+    /// The tables of a vector of tables.
+    fn tables(&self, table: usize, slot: usize) -> Vec<usize> {
+        let at = self.table(table, slot);
+        (0..self.u32(at)).map(|i| self.deref(at + 4 + 4 * i)).collect()
+    }
+
+    /// A vector of two-`long` structs.
+    fn pairs(&self, table: usize, slot: usize) -> Vec<(i64, i64)> {
+        let at = self.table(table, slot);
+        (0..self.u32(at)).map(|i| (self.i64(at + 4 + 16 * i), self.i64(at + 12 + 16 * i))).collect()
+    }
+}
+
+/// Each message's metadata and body, up to the end-of-stream marker.
+fn messages(mut stream: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut out = Vec::new();
+    loop {
+        assert_eq!(stream[..4], [0xff; 4], "continuation");
+        let len = u32::from_le_bytes(stream[4..8].try_into().unwrap()) as usize;
+        if len == 0 {
+            assert_eq!(stream.len(), 8, "bytes after the end-of-stream marker");
+            return out;
+        }
+        assert_eq!(len % 8, 0, "metadata isn't padded to 8 bytes");
+        let meta = Fb(&stream[8..8 + len]);
+        let body_len = meta.long(meta.root(), 3) as usize;
+        assert_eq!(body_len % 8, 0, "body isn't padded to 8 bytes");
+        out.push((meta.0.to_vec(), stream[8 + len..8 + len + body_len].to_vec()));
+        stream = &stream[8 + len + body_len..];
+    }
+}
+
+#[test]
+fn pages_become_record_batches_with_nulls_kept() {
+    let mut file = ArrowExport::new(Vec::new(), Dataset::Trades).unwrap();
+    file.write(&[vec![json!("2024-06-01T00:00:00.000000Z"), json!("BTCUSDT"), json!(65000.5), json!(0.1), json!(7), json!(null), json!(true), json!("m1"), json!(1_717_200_000_000i64)]]).unwrap();
+    file.write(&[]).unwrap();
+    file.write(&[
+        vec![json!("2024-06-01T00:00:01.500000Z"), json!("ETHUSDT"), json!(3500.0), json!(2.0), json!(9), json!(8), json!(false), json!(null), json!(1_717_200_001_500i64)],
+        vec![json!("2024-06-01T00:00:02.000000Z"), json!("SOLUSDT"), json!(150.0), json!(1.0), json!(10), json!(9), json!(true), json!("m3"), json!(1_717_200_002_000i64)],
+    ]).unwrap();
+    let stream = file.finish().unwrap();
+    let msgs = messages(&stream);
+    assert_eq!(msgs.len(), 3);
+
+    // The schema: V5, every column nullable, typed as in the Parquet export.
+    let meta = Fb(&msgs[0].0);
+    let (root, columns) = (meta.root(), Dataset::Trades.column_names());
+    assert_eq!((meta.short(root, 0), meta.byte(root, 1)), (4, 1));
+    assert!(msgs[0].1.is_empty());
+    let fields = meta.tables(meta.table(root, 2), 1);
+    let names: Vec<&str> = fields.iter().map(|f| meta.string(*f, 0)).collect();
+    assert_eq!(names, columns);
+    let types: Vec<u8> = fields.iter().map(|f| meta.byte(*f, 2)).collect();
+    assert_eq!(types, [10, 5, 3, 3, 2, 2, 6, 5, 2]);
+    assert!(fields.iter().all(|f| meta.byte(*f, 1) == 1 && meta.tables(*f, 5).is_empty()));
+    let ts = meta.table(fields[0], 3);
+    assert_eq!((meta.short(ts, 0), meta.string(ts, 1)), (2, "UTC"));
+    let int = meta.table(fields[4], 3);
+    assert_eq!((meta.u32(meta.field(int, 0).unwrap()), meta.byte(int, 1)), (64, 1));
+    assert_eq!(meta.short(meta.table(fields[2], 3), 0), 2);
+
+    // The second page: two rows, with the nulls in the validity bitmaps.
+    let (meta, body) = (Fb(&msgs[2].0), &msgs[2].1);
+    let root = meta.root();
+    assert_eq!(meta.byte(root, 1), 3);
+    let batch = meta.table(root, 2);
+    assert_eq!(meta.long(batch, 0), 2);
+    let nodes = meta.pairs(batch, 1);
+    assert_eq!(nodes.len(), columns.len());
+    assert_eq!(nodes[5], (2, 0));
+    assert_eq!(nodes[7], (2, 1));
+    // Validity and values per column, and offsets too for the two strings.
+    let buffers = meta.pairs(batch, 2);
+    assert_eq!(buffers.len(), 2 * columns.len() + 2);
+    assert!(buffers.iter().all(|(off, len)| off % 8 == 0 && (off + len) as usize <= body.len()));
+    let buf = |i: usize| &body[buffers[i].0 as usize..(buffers[i].0 + buffers[i].1) as usize];
+    let longs = |i: usize| buf(i).chunks(8).map(|c| i64::from_le_bytes(c.try_into().unwrap())).collect::<Vec<_>>();
+
+    // timestamp: validity, µs.
+    assert_eq!(longs(1), [1_717_200_001_500_000, 1_717_200_002_000_000]);
+    // symbol: validity, offsets, data.
+    assert_eq!(buf(3), [0, 0, 0, 0, 7, 0, 0, 0, 14, 0, 0, 0]);
+    assert_eq!(buf(4), b"ETHUSDTSOLUSDT");
+    // price.
+    assert_eq!(buf(6).chunks(8).map(|c| f64::from_le_bytes(c.try_into().unwrap())).collect::<Vec<_>>(), [3500.0, 150.0]);
+    // is_bm: bit-packed values.
+    assert_eq!(buf(14), [0b10]);
+    // msg_id: the first row is null, and takes no bytes.
+    assert_eq!((buf(15), buf(16), buf(17)), (&[0b10][..], &[0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0][..], &b"m3"[..]));
+}
@@ -0,0 +1,139 @@
+use bus::envelope::{Compat, SchemaVersion};
+use consumer::avro::{decode, unframe, Registry, Schema};
+use consumer::decode::decode_as;
+use consumer::error::Error;
+use obsv::errors::{Classify, ErrorKind};
+
+const TRADE: &str = r#"{
+    "type": "record", "name": "Trade", "namespace": "ticks",
+    "fields": [
+        {"name": "ts", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+        {"name": "symbol", "type": "string"},
+        {"name": "price", "type": "double"},
+        {"name": "qty", "type": "double"},
+        {"name": "trade_id", "type": "long"},
+        {"name": "is_bm", "type": "boolean"},
+        {"name": "notional_usd", "type": ["null", "double"], "default": null},
+        {"name": "side", "type": {"type": "enum", "name": "Side", "symbols": ["BUY", "SELL"]}},
+        {"name": "tags", "type": {"type": "array", "items": "string"}}
+    ]
+}"#;
+
+fn long(out: &mut Vec<u8>, n: i64) {
+    let mut z = ((n << 1) ^ (n >> 63)) as u64;
+    loop {
+        let b = (z & 0x7f) as u8;
+        z >>= 7;
+        if z == 0 {
+            out.push(b);
+            return;
+        }
+        out.push(b | 0x80);
+    }
+}
+
+fn string(out: &mut Vec<u8>, s: &str) {
+    long(out, s.len() as i64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// A trade in `TRADE`'s encoding, framed for schema `id`.
+fn framed(id: u32, notional: Option<f64>) -> Vec<u8> {
+    let mut out = vec![0];
+    out.extend_from_slice(&id.to_be_bytes());
+    long(&mut out, 1739880000138);
+    string(&mut out, "BTCUSDT");
+    out.extend_from_slice(&96123.45f64.to_le_bytes());
+    out.extend_from_slice(&0.00012f64.to_le_bytes());
+    long(&mut out, 4567890123);
+    out.push(1);
+    match notional {
+        None => long(&mut out, 0),
+        Some(n) => {
+            long(&mut out, 1);
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+    }
+    long(&mut out, 1);
+    // One block of two tags, then the empty block that ends the array.
+    long(&mut out, 2);
+    string(&mut out, "spot");
+    string(&mut out, "agg");
+    long(&mut out, 0);
+    out
+}
+
+fn registry() -> Registry {
+    let r = Registry::new("http://registry.invalid", None);
+    r.insert(7, Some(Schema::parse(TRADE).unwrap()));
+    r.insert(8, None);
+    r
+}
+
+#[test]
+fn a_framed_trade_decodes_by_its_writer_schema() {
+    let r = registry();
+    let v = Compat::Supported(SchemaVersion::new(1, 2));
+    let t = decode_as(&framed(7, Some(11.53)), Some("avro"), &v, Some(&r)).unwrap();
+    assert_eq!((t.ts_ms, t.symbol.as_str(), t.price, t.qty), (1739880000138, "BTCUSDT", 96123.45, 0.00012));
+    assert_eq!((t.trade_id, t.is_bm, t.notional), (4567890123, true, Some(11.53)));
+    let t = decode_as(&framed(7, None), Some("avro"), &v, Some(&r)).unwrap();
+    assert_eq!(t.notional, None);
+}
+
+#[test]
+fn records_enums_arrays_and_named_references_read_as_json() {
+    let schema = Schema::parse(r#"{"type": "record", "name": "Pair", "fields": [
+        {"name": "a", "type": {"type": "enum", "name": "Side", "namespace": "x", "symbols": ["BUY", "SELL"]}},
+        {"name": "b", "type": "x.Side"},
+        {"name": "m", "type": {"type": "map", "values": "int"}}
+    ]}"#).unwrap();
+    let mut body = Vec::new();
+    long(&mut body, 0);
+    long(&mut body, 1);
+    // A block with a negative count carries its size in bytes as well.
+    long(&mut body, -1);
+    long(&mut body, 3);
+    string(&mut body, "k");
+    long(&mut body, -5);
+    long(&mut body, 0);
+    let v = decode(&schema, &body).unwrap();
+    assert_eq!(v, serde_json::json!({"a": "BUY", "b": "SELL", "m": {"k": -5}}));
+    assert!(Schema::parse(r#"{"type": "record", "name": "R", "fields": [{"name": "a", "type": "Nope"}]}"#).is_err());
+}
+
+#[test]
+fn bad_framing_unknown_ids_and_short_records_are_data_errors() {
+    let r = registry();
+    let v = Compat::Unversioned;
+    let mut short = framed(7, None);
+    short.truncate(short.len() - 3);
+    let mut long_tail = framed(7, None);
+    long_tail.push(0);
+    let cases = [
+        decode_as(b"{\"ts_ms\":1}", Some("avro"), &v, Some(&r)).unwrap_err(),
+        decode_as(&framed(8, None), Some("avro"), &v, Some(&r)).unwrap_err(),
+        decode_as(&short, Some("avro"), &v, Some(&r)).unwrap_err(),
+        decode_as(&long_tail, Some("avro"), &v, Some(&r)).unwrap_err(),
+    ];
+    for e in &cases {
+        assert!(matches!(e, Error::Binary { codec: "avro", .. }), "{:?}", e);
+    }
+    // An id that was never fetched is the registry's fault, not the trade's.
+    let e = decode_as(&framed(9, None), Some("avro"), &v, Some(&r)).unwrap_err();
+    assert!(matches!(e, Error::Registry(9)), "{:?}", e);
+    assert_eq!((e.reason(), e.kind()), ("registry", ErrorKind::Transient));
+    assert_eq!(unframe(&framed(7, None)).unwrap().0, 7);
+    // A length past the end is refused before anything is allocated for it.
+    let mut huge = vec![0, 0, 0, 0, 7];
+    long(&mut huge, 1);
+    long(&mut huge, i64::MAX / 2);
+    assert!(decode_as(&huge, Some("avro"), &v, Some(&r)).is_err());
+}
+
+#[test]
+fn unsupported_majors_are_refused_before_the_bytes_are_read() {
+    let r = registry();
+    let e = decode_as(&framed(7, None), Some("avro"), &Compat::Unsupported("2.0".into()), Some(&r)).unwrap_err();
+    assert!(matches!(e, Error::Schema(_)));
+}
@@ -0,0 +1,79 @@
+use consumer::bars::{Kind, Spec, TradeBar, TradeBars};
+use consumer::ilp::NormTrade;
+
+fn trade(symbol: &str, ts_ms: i64, price: f64) -> NormTrade {
+    NormTrade { ts_ms, symbol: symbol.into(), price, qty: 1.0, trade_id: ts_ms, is_bm: false, session: None, notional: None, first_trade_id: None, contract_size: None, ts_ns: None, ingest_ts_ns: None }
+}
+
+#[test]
+fn specs_pick_a_kind_per_symbol() {
+    let spec = Spec::parse("*=tick:1000, btcusdt=imbalance:200").unwrap();
+    assert_eq!(spec.get("BTCUSDT"), Some(Kind::Imbalance(200)));
+    assert_eq!(spec.get("ETHUSDT"), Some(Kind::Tick(1000)));
+    assert_eq!(Spec::parse("ETHUSDT=tick:5").unwrap().get("BTCUSDT"), None);
+    assert!(Spec::parse("").unwrap().is_empty());
+    for bad in ["BTCUSDT", "BTCUSDT=tick", "BTCUSDT=tick:0", "BTCUSDT=dollar:5", "*=tick:x"] {
+        assert!(Spec::parse(bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn tick_bars_close_every_n_trades() {
+    let mut bars = TradeBars::new(Spec::parse("*=tick:3").unwrap(), 0.1);
+    for (ts, p) in [(1, 10.0), (2, 12.0), (3, 9.0), (4, 11.0)] {
+        bars.add(&trade("ETH USD", ts, p));
+    }
+    let closed = bars.take_closed();
+    assert_eq!(closed, [TradeBar {
+        symbol: "ETH USD".into(), kind: "tick".into(), size: 3, ts_ms: 1, close_ts_ms: 3,
+        open: 10.0, high: 12.0, low: 9.0, close: 9.0, volume: 3.0, trades: 3, imbalance: 1,
+    }]);
+    assert!(bars.take_closed().is_empty());
+    assert_eq!(
+        closed[0].to_ilp_line("trade_bars"),
+        "trade_bars,symbol=ETH\\ USD,kind=tick size=3i,open=10,high=12,low=9,close=9,volume=3,trades=3i,imbalance=1i,close_ts_ms=3i 1000000"
+    );
+}
+
+#[test]
+fn imbalance_bars_close_sooner_when_trades_run_one_way() {
+    let mut bars = TradeBars::new(Spec::parse("BTCUSDT=imbalance:10").unwrap(), 0.5);
+    bars.add(&trade("ETHUSDT", 0, 1.0));
+    // Warm-up: ten trades, eight upticks and two downticks.
+    let mut price = 100.0;
+    for (i, up) in [true, true, true, false, true, true, true, false, true, true].into_iter().enumerate() {
+        price += if up { 1.0 } else { -1.0 };
+        bars.add(&trade("BTCUSDT", i as i64, price));
+    }
+    let warm = bars.take_closed();
+    assert_eq!((warm.len(), warm[0].kind.as_str(), warm[0].trades, warm[0].imbalance), (1, "imbalance", 10, 6));
+    assert_eq!(bars.expected("BTCUSDT"), Some((10.0, 0.6)));
+    assert_eq!(bars.expected("ETHUSDT"), None);
+
+    // Expected imbalance is 10 · 0.6 = 6: a straight run closes after six trades,
+    // and unchanged prices keep the previous sign.
+    for i in 0..6 {
+        price += if i % 2 == 0 { 1.0 } else { 0.0 };
+        bars.add(&trade("BTCUSDT", 100 + i, price));
+    }
+    let run = bars.take_closed();
+    assert_eq!((run.len(), run[0].trades, run[0].imbalance), (1, 6, 6));
+    // E[T] = 0.5·6 + 0.5·10 and E[b] = 0.5·1 + 0.5·0.6.
+    let (ticks, sign) = bars.expected("BTCUSDT").unwrap();
+    assert_eq!(ticks, 8.0);
+    assert!((sign - 0.8).abs() < 1e-12);
+}
+
+#[test]
+fn balanced_trading_still_closes_bars() {
+    let mut bars = TradeBars::new(Spec::parse("*=imbalance:8").unwrap(), 1.0);
+    // Prices flip every trade: after the warm-up the signs cancel out and
+    // |θ| never reaches the expected 2, so bars close at 4N trades.
+    for i in 0..8 + 32 {
+        bars.add(&trade("BTCUSDT", i, if i % 2 == 0 { 100.0 } else { 101.0 }));
+    }
+    let closed = bars.take_closed();
+    assert_eq!(closed.iter().map(|b| b.trades).collect::<Vec<_>>(), [8, 32]);
+    assert_eq!((closed[0].imbalance, closed[1].imbalance), (2, 0));
+    assert_eq!(bars.expected("BTCUSDT"), Some((32.0, 0.0)));
+}
@@ -0,0 +1,44 @@
+use bus::{Delivery, Headers};
+use consumer::batch::{Batch, Row};
+use consumer::ilp::NormTrade;
+
+fn row(symbol: &str, trade_id: i64) -> Row {
+    let trade = NormTrade { ts_ms: trade_id * 10, symbol: symbol.into(), price: 1.0, qty: 2.0, trade_id, is_bm: false, session: None, notional: None, first_trade_id: None, contract_size: None, ts_ns: None, ingest_ts_ns: None };
+    Row { trade, msg_id: format!("m{}", trade_id), topic: "ticks.norm".into(), partition: Some(0) }
+}
+
+#[test]
+fn rows_are_grouped_per_symbol_in_first_seen_order() {
+    let mut b = Batch::new();
+    assert!(b.is_empty() && b.started().is_none());
+    for (sym, id) in [("ETH", 1), ("BTC", 2), ("ETH", 3)] {
+        b.push_row(row(sym, id));
+        b.push_delivery(Delivery::detached("ticks.norm", Some(sym), Vec::new(), Headers::new()));
+    }
+    // A skipped message still rides along for the commit.
+    b.push_delivery(Delivery::detached("ticks.norm", None, Vec::new(), Headers::new()));
+
+    let chunks: Vec<String> = b.chunks().map(|c| String::from_utf8(c.to_vec()).unwrap()).collect();
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].lines().map(|l| l.split(',').next().unwrap()).collect::<Vec<_>>(), ["trades", "trades"]);
+    assert!(chunks[0].contains("trade_id=1i") && chunks[0].contains("trade_id=3i") && chunks[0].ends_with('\n'));
+    assert!(chunks[1].starts_with("trades,symbol=BTC ") && chunks[1].contains("trade_id=2i"));
+    assert_eq!(b.rows(), 3);
+    assert_eq!(b.deliveries().len(), 4);
+    let marks: Vec<_> = b.marks().map(|(s, m)| (s.to_string(), m.trade_id)).collect();
+    assert_eq!(marks, [("ETH".to_string(), 1), ("BTC".to_string(), 2), ("ETH".to_string(), 3)]);
+    assert_eq!(b.iter_rows().map(|r| r.msg_id.as_str()).collect::<Vec<_>>(), ["m1", "m2", "m3"]);
+
+    b.clear();
+    assert!(b.is_empty() && b.started().is_none() && b.chunks().next().is_none());
+}
+
+#[test]
+fn lent_deliveries_come_back_ahead_of_later_ones() {
+    let mut b = Batch::new();
+    b.push_delivery(Delivery::detached("ticks.norm", Some("a"), Vec::new(), Headers::new()));
+    let lent = b.take_deliveries();
+    b.push_delivery(Delivery::detached("ticks.norm", Some("b"), Vec::new(), Headers::new()));
+    b.restore_deliveries(lent);
+    assert_eq!(b.deliveries().iter().map(|d| d.key.as_deref().unwrap()).collect::<Vec<_>>(), ["a", "b"]);
+}
@@ -0,0 +1,47 @@
+use consumer::book::{BookSnapshot, LEVELS, LEVEL_COLUMNS};
+use consumer::schema::{check, expected, Col};
+
+fn snapshot(bids: Vec<(f64, f64)>, asks: Vec<(f64, f64)>) -> BookSnapshot {
+    BookSnapshot { symbol: "BTCUSDT".into(), ts_ms: 1_700_000_000_000, update_id: 42, bids, asks }
+}
+
+#[test]
+fn snapshots_decode_from_the_book_builder() {
+    let s: BookSnapshot = serde_json::from_str(
+        r#"{"symbol":"BTCUSDT","ts_ms":1700000000000,"update_id":42,"bids":[[99.0,1.5]],"asks":[[101.0,2.0]]}"#).unwrap();
+    assert_eq!(s, snapshot(vec![(99.0, 1.5)], vec![(101.0, 2.0)]));
+    assert_eq!(s.mid(), Some(100.0));
+    assert_eq!(s.spread_bps(), Some(200.0));
+}
+
+#[test]
+fn rows_carry_the_top_levels_and_leave_the_rest_null() {
+    let deep: Vec<(f64, f64)> = (0..LEVELS + 5).map(|i| (99.0 - i as f64, 1.0)).collect();
+    let line = snapshot(deep, vec![(101.0, 2.0)]).to_ilp_line("book");
+    assert!(line.starts_with("book,symbol=BTCUSDT update_id=42i,bid_levels=15i,ask_levels=1i,mid=100,spread_bps=200,bid_px_1=99,bid_qty_1=1,"), "{}", line);
+    assert!(line.contains(",bid_px_10=90,bid_qty_10=1,ask_px_1=101,ask_qty_1=2 1700000000000000000"), "{}", line);
+    assert!(!line.contains("bid_px_11") && !line.contains("ask_px_2"));
+
+    let one_sided = snapshot(vec![(99.0, 1.0)], vec![]);
+    assert_eq!(one_sided.spread_bps(), None);
+    assert_eq!(one_sided.to_ilp_line("book"), "book,symbol=BTCUSDT update_id=42i,bid_levels=1i,ask_levels=0i,bid_px_1=99,bid_qty_1=1 1700000000000000000");
+}
+
+#[test]
+fn the_book_table_is_checked_only_when_written() {
+    assert_eq!(expected(None, None, None, None).len(), 1);
+    let book = expected(None, None, Some("book"), None).pop().unwrap();
+    assert_eq!(book.table, "book");
+    let mut cols: Vec<Col> = ["symbol", "update_id", "bid_levels", "ask_levels", "mid", "spread_bps", "timestamp"].iter()
+        .chain(LEVEL_COLUMNS.iter())
+        .map(|c| Col {
+            name: c.to_string(),
+            ty: match *c { "symbol" => "SYMBOL", "timestamp" => "TIMESTAMP", "update_id" | "bid_levels" | "ask_levels" => "LONG", _ => "DOUBLE" }.into(),
+            designated: *c == "timestamp",
+            upsert_key: matches!(*c, "symbol" | "timestamp"),
+        })
+        .collect();
+    check(&book, &cols).unwrap();
+    cols.retain(|c| c.name != "ask_qty_10");
+    assert!(check(&book, &cols).unwrap_err().to_string().contains("missing column ask_qty_10"));
+}
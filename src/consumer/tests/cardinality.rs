@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use consumer::batch::{Batch, Row};
+use consumer::cardinality::{line_table, retable, Guard};
+use consumer::ilp::NormTrade;
+use consumer::sink::{IlpOptions, IlpPool, PoolMode, Sink};
+use consumer::verify::Ledger;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpListener;
+
+fn row(symbol: &str, trade_id: i64) -> Row {
+    let trade = NormTrade { ts_ms: trade_id * 10, symbol: symbol.into(), price: 1.0, qty: 2.0, trade_id, is_bm: false, session: None, notional: None, first_trade_id: None, contract_size: None, ts_ns: None, ingest_ts_ns: None };
+    Row { trade, msg_id: format!("m{}", trade_id), topic: "ticks.norm".into(), partition: Some(0) }
+}
+
+#[test]
+fn symbols_past_the_limit_overflow_and_stay_there() {
+    let mut g = Guard::new(2, "trades_overflow");
+    g.seed("trades", ["BTC".to_string()]);
+    assert_eq!(g.route("trades", "BTC"), None);
+    assert_eq!(g.route("trades", "ETH"), None);
+    assert_eq!(g.route("trades", "SOL"), Some("trades_overflow"));
+    assert_eq!(g.route("trades", "ETH"), None, "admitted symbols keep their table");
+    assert_eq!(g.route("trades", "SOL"), Some("trades_overflow"));
+    assert_eq!(g.symbols("trades"), 2);
+
+    // Each table has its own limit, and the overflow table has none.
+    assert_eq!(g.route("trades_eth", "SOL"), None);
+    for i in 0..10 {
+        assert_eq!(g.route("trades_overflow", &format!("S{}", i)), None);
+    }
+
+    // A seeded table keeps every symbol it already had.
+    g.seed("trades_big", (0..5).map(|i| format!("S{}", i)));
+    assert_eq!(g.route("trades_big", "S4"), None);
+    assert_eq!(g.route("trades_big", "S5"), Some("trades_overflow"));
+}
+
+#[test]
+fn lines_are_moved_to_another_table() {
+    assert_eq!(line_table("trades,symbol=BTC price=1 0"), "trades");
+    assert_eq!(line_table("my\\ trades x=1i 0"), "my\\ trades");
+    assert_eq!(line_table("trades"), "trades");
+    assert_eq!(
+        retable("trades,symbol=A x=1i 0\ntrades,symbol=A x=2i 1\n", "trades_overflow"),
+        "trades_overflow,symbol=A x=1i 0\ntrades_overflow,symbol=A x=2i 1\n"
+    );
+}
+
+#[tokio::test]
+async fn the_pool_writes_overflow_symbols_to_the_overflow_table() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let eps = vec![("127.0.0.1".to_string(), port)];
+    let pool = IlpPool::connect(eps, PoolMode::Failover, IlpOptions::default(), Duration::from_secs(60)).await.unwrap();
+    let ledger = Arc::new(Mutex::new(Ledger::new(Duration::from_secs(60), HashMap::new())));
+    let mut pool = pool.verified(Some(ledger.clone())).guarded(Some(Guard::new(1, "trades_overflow")));
+    let (mut server, _) = listener.accept().await.unwrap();
+
+    let mut b = Batch::new();
+    for (sym, id) in [("BTC", 1), ("ETH", 2), ("ETH", 3)] {
+        b.push_row(row(sym, id));
+    }
+    b.push_line("trades_1m,symbol=BTC open=1 0");
+    pool.write(&b).await.unwrap();
+    drop(pool);
+
+    let mut got = String::new();
+    server.read_to_string(&mut got).await.unwrap();
+    let tables: Vec<&str> = got.lines().map(line_table).collect();
+    assert_eq!(tables, ["trades", "trades_overflow", "trades_overflow", "trades_1m"]);
+    assert!(got.contains("trades_overflow,symbol=ETH ") && got.contains("trade_id=3i"));
+
+    let mut due: Vec<(String, u64)> = ledger.lock().unwrap().due(i64::MAX, 0).into_iter().map(|c| (c.table, c.sent)).collect();
+    due.sort();
+    assert_eq!(due, [("trades".to_string(), 1), ("trades_overflow".to_string(), 2)], "verification counts them where they went");
+}
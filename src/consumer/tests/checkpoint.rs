@@ -0,0 +1,26 @@
+use std::collections::BTreeMap;
+
+use consumer::checkpoint::Checkpoints;
+
+#[test]
+fn rows_follow_commits_not_deliveries() {
+    let mut cp = Checkpoints::new("_checkpoints", "consumer-stage");
+    cp.done_at("ticks.norm", 0, 41);
+    cp.done_at("ticks.norm", 0, 40);
+    cp.done_at("ticks.norm", 3, 7);
+    cp.done_at("ticks.alt", 0, 5);
+    assert!(cp.lines(&BTreeMap::new(), 1).is_empty(), "nothing committed yet");
+
+    cp.committed();
+    cp.done_at("ticks.norm", 0, 99);
+    let lines = cp.lines(&BTreeMap::from([(("ticks.norm".to_string(), 0), 1_739_880_000_138)]), 1_739_880_001_000_000_000);
+    assert_eq!(lines, [
+        "_checkpoints,consumer_group=consumer-stage,topic=ticks.alt partition=0i,committed_offset=6i 1739880001000000000",
+        "_checkpoints,consumer_group=consumer-stage,topic=ticks.norm partition=0i,committed_offset=42i,watermark=1739880000138000t 1739880001000000000",
+        "_checkpoints,consumer_group=consumer-stage,topic=ticks.norm partition=3i,committed_offset=8i 1739880001000000000",
+    ]);
+    assert!(cp.lines(&BTreeMap::new(), 2).is_empty(), "no commit since");
+
+    cp.committed();
+    assert!(cp.lines(&BTreeMap::new(), 3)[1].contains("committed_offset=100i"));
+}
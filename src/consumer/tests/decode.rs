@@ -0,0 +1,121 @@
+use bus::envelope::{Compat, SchemaVersion};
+use codec::{Codec, Trade};
+use consumer::decode::{decode, decode_as};
+use consumer::error::Error;
+use obsv::errors::{Classify, ErrorKind};
+use consumer::ilp::to_ilp_line;
+
+const V1: &str = r#"{"ts_ms":1739880000138,"symbol":"BTCUSDT","price":96123.45,"qty":0.00012,"trade_id":4567890123,"is_bm":true}"#;
+
+#[test]
+fn newer_minor_with_extra_fields_decodes() {
+    let payload = r#"{"ts_ms":1739880000138,"symbol":"BTCUSDT","price":96123.45,"qty":0.00012,"trade_id":4567890123,"is_bm":true,"notional_usd":11.53,"venue":{"id":1}}"#;
+    let t = decode(payload, &Compat::Supported(SchemaVersion::new(1, 3))).unwrap();
+    assert_eq!((t.trade_id, t.price), (4567890123, 96123.45));
+}
+
+#[test]
+fn coinm_contract_size_reaches_the_ilp_line() {
+    let payload = r#"{"ts_ms":1739880000138,"symbol":"BTCUSD_PERP","price":96123.45,"qty":3.0,"trade_id":42,"is_bm":false,"notional":300.0,"first_trade_id":40,"contract_size":100.0}"#;
+    let t = decode(payload, &Compat::Supported(SchemaVersion::new(1, 4))).unwrap();
+    assert_eq!(t.contract_size, Some(100.0));
+    assert!(to_ilp_line(&t, "m").contains(",first_trade_id=40i,contract_size=100 "));
+}
+
+#[test]
+fn the_session_tag_reaches_the_ilp_line() {
+    let payload = r#"{"ts_ms":1739880000138,"symbol":"BTCUSDT","price":96123.45,"qty":0.5,"trade_id":7,"is_bm":false,"session":"europe+us"}"#;
+    let t = decode(payload, &Compat::Supported(SchemaVersion::new(1, 1))).unwrap();
+    assert_eq!(t.session.as_deref(), Some("europe+us"));
+    assert!(to_ilp_line(&t, "m").starts_with("trades,symbol=BTCUSDT,session=europe+us "), "{}", to_ilp_line(&t, "m"));
+    assert!(!to_ilp_line(&decode(V1, &Compat::Unversioned).unwrap(), "m").contains("session"));
+}
+
+#[test]
+fn nanosecond_times_reach_the_ilp_line_but_not_the_row_timestamp() {
+    let payload = r#"{"ts_ms":1545913818099,"symbol":"BTCUSDT","price":3585.5,"qty":0.01,"trade_id":7,"is_bm":false,"ingest_ts_ns":1545913818142000001,"ts_ns":1545913818099033203}"#;
+    let t = decode(payload, &Compat::Supported(SchemaVersion::new(1, 7))).unwrap();
+    assert_eq!((t.ts_ns, t.ingest_ts_ns), (Some(1545913818099033203), Some(1545913818142000001)));
+    let line = to_ilp_line(&t, "m");
+    assert!(line.contains(",ts_ns=1545913818099033203i,ingest_ts_ns=1545913818142000001i "), "{}", line);
+    assert!(line.ends_with(" 1545913818099000000"), "the dedup key stays in milliseconds: {}", line);
+    assert!(!to_ilp_line(&decode(V1, &Compat::Unversioned).unwrap(), "m").contains("ts_ns"));
+}
+
+#[test]
+fn old_field_names_and_unversioned_messages_decode() {
+    let payload = r#"{"ts":1739880000138,"symbol":"BTCUSDT","price":96123.45,"quantity":0.00012,"id":4567890123,"is_buyer_maker":true}"#;
+    let t = decode(payload, &Compat::Unversioned).unwrap();
+    assert_eq!((t.ts_ms, t.qty, t.trade_id, t.is_bm), (1739880000138, 0.00012, 4567890123, true));
+    assert!(decode(V1, &Compat::Unversioned).is_ok());
+}
+
+#[test]
+fn unknown_majors_are_refused() {
+    assert!(decode(V1, &Compat::Supported(SchemaVersion::new(2, 0))).is_err());
+    assert!(decode(V1, &Compat::Unsupported("banana".into())).is_err());
+}
+
+fn trade() -> Trade {
+    Trade { ts_ms: 1739880000138, symbol: "BTCUSDT".into(), price: 96123.45, qty: 0.00012, trade_id: 4567890123, is_bm: true, ts_ns: None, ingest_ts_ns: None }
+}
+
+#[test]
+fn binary_codecs_decode_by_header() {
+    let v = Compat::Supported(SchemaVersion::new(1, 6));
+    for codec in [Codec::MsgPack, Codec::Proto] {
+        let t = decode_as(&codec.encode(&trade()).unwrap(), Some(codec.name()), &v, None).unwrap();
+        assert_eq!((t.ts_ms, t.symbol.as_str(), t.price, t.qty, t.trade_id, t.is_bm), (1739880000138, "BTCUSDT", 96123.45, 0.00012, 4567890123, true));
+        assert_eq!(t.notional, None);
+    }
+}
+
+#[test]
+fn binary_codecs_round_trip_the_nanosecond_timestamps() {
+    let v = Compat::Supported(SchemaVersion::new(1, 7));
+    let fine = Trade { ts_ns: Some(1739880000138123456), ingest_ts_ns: Some(1739880000140000001), ..trade() };
+    for codec in Codec::ALL {
+        assert_eq!(codec.decode(&codec.encode(&fine).unwrap()).unwrap(), fine, "{}", codec.name());
+        let t = decode_as(&codec.encode(&fine).unwrap(), Some(codec.name()), &v, None).unwrap();
+        assert_eq!((t.ts_ns, t.ingest_ts_ns), (fine.ts_ns, fine.ingest_ts_ns), "{}", codec.name());
+        // Left out when unset, as before.
+        assert_eq!(codec.decode(&codec.encode(&trade()).unwrap()).unwrap().ts_ns, None);
+    }
+    assert!(!String::from_utf8(Codec::Json.encode(&trade()).unwrap()).unwrap().contains("ts_ns"));
+}
+
+#[test]
+fn no_codec_header_is_json() {
+    let t = decode_as(V1.as_bytes(), None, &Compat::Unversioned, None).unwrap();
+    assert_eq!(t.trade_id, 4567890123);
+    assert!(decode_as(&Codec::Proto.encode(&trade()).unwrap(), None, &Compat::Unversioned, None).is_err());
+}
+
+#[test]
+fn avro_unknown_codecs_and_majors_are_refused() {
+    let v = Compat::Supported(SchemaVersion::new(1, 6));
+    assert!(decode_as(V1.as_bytes(), Some("avro"), &v, None).is_err());
+    assert!(decode_as(V1.as_bytes(), Some("bson"), &v, None).is_err());
+    let msgpack = Codec::MsgPack.encode(&trade()).unwrap();
+    assert!(decode_as(&msgpack, Some("msgpack"), &Compat::Unsupported("2.0".into()), None).is_err());
+}
+
+#[test]
+fn decode_failures_say_why_and_are_data_errors() {
+    let v = Compat::Supported(SchemaVersion::new(1, 6));
+    let cases = [
+        decode_as(b"{\"ts_ms\":", None, &v, None).unwrap_err(),
+        decode_as(&[0xff, 0xfe], None, &v, None).unwrap_err(),
+        decode_as(V1.as_bytes(), Some("avro"), &v, None).unwrap_err(),
+        decode_as(b"\x01", Some("proto"), &v, None).unwrap_err(),
+        decode(V1, &Compat::Unsupported("2.0".into())).unwrap_err(),
+    ];
+    assert!(matches!(cases[0], Error::Json(_)));
+    assert!(matches!(cases[1], Error::Utf8(_)));
+    assert!(matches!(&cases[2], Error::Codec(c) if c == "avro"));
+    assert!(matches!(cases[3], Error::Binary { codec: "proto", .. }));
+    assert!(matches!(&cases[4], Error::Schema(v) if v == "2.0"));
+    assert!(cases.iter().all(|e| e.kind() == ErrorKind::Data));
+    let reasons: Vec<&str> = cases.iter().map(Error::reason).collect();
+    assert_eq!(reasons, ["malformed", "malformed", "codec", "malformed", "schema"]);
+}
@@ -0,0 +1,30 @@
+#![cfg(feature = "duckdb")]
+
+use consumer::batch::{Batch, Row};
+use consumer::duck::DuckDbSink;
+use consumer::ilp::NormTrade;
+use consumer::sink::Sink;
+
+#[tokio::test]
+async fn batches_append_and_survive_reopen() {
+    let path = std::env::temp_dir().join(format!("consumer-duck-{}.duckdb", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut b = Batch::new();
+    for trade_id in 0..3 {
+        let trade = NormTrade { ts_ms: 1_700_000_000_000 + trade_id, symbol: "BTCUSDT".into(), price: 1.5, qty: 0.5, trade_id, is_bm: true, notional: None, first_trade_id: None, contract_size: None, ts_ns: None, ingest_ts_ns: None };
+        b.push_row(Row { trade, msg_id: format!("m{}", trade_id), topic: String::new(), partition: None });
+    }
+
+    let mut sink = DuckDbSink::open(&path).unwrap();
+    sink.write(&b).await.unwrap();
+    sink.write(&b).await.unwrap();
+    drop(sink);
+
+    let conn = duckdb::Connection::open(&path).unwrap();
+    let (n, qty, last): (i64, f64, String) = conn
+        .query_row("SELECT count(*), sum(qty), max(timestamp)::VARCHAR FROM trades", [], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap();
+    assert_eq!((n, qty), (6, 3.0));
+    assert_eq!(last, "2023-11-14 22:13:20.002");
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,78 @@
+use consumer::export::{Dataset, Pager, ParquetExport, Range};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::Field;
+use serde_json::json;
+
+/// The row groups and rows of a Parquet file held in memory.
+fn read_rows(file: Vec<u8>, name: &str) -> (usize, Vec<Vec<(String, Field)>>) {
+    let path = std::env::temp_dir().join(format!("consumer-export-{}-{}.parquet", std::process::id(), name));
+    std::fs::write(&path, file).unwrap();
+    let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+    let groups = reader.metadata().num_row_groups();
+    let rows = reader.get_row_iter(None).unwrap()
+        .map(|r| r.unwrap().get_column_iter().map(|(n, v)| (n.clone(), v.clone())).collect())
+        .collect();
+    let _ = std::fs::remove_file(&path);
+    (groups, rows)
+}
+
+#[test]
+fn pages_become_row_groups_with_nulls_kept() {
+    let mut file = ParquetExport::new(Vec::new(), Dataset::Trades).unwrap();
+    file.write(&[vec![json!("2024-06-01T00:00:00.000000Z"), json!("BTCUSDT"), json!(65000.5), json!(0.1), json!(7), json!(null), json!(true), json!("m1"), json!(1_717_200_000_000i64)]]).unwrap();
+    file.write(&[]).unwrap();
+    file.write(&[vec![json!("2024-06-01T00:00:01.500000Z"), json!("ETHUSDT"), json!(3500.0), json!(2.0), json!(9), json!(8), json!(false), json!(null), json!(1_717_200_001_500i64)]]).unwrap();
+    let (groups, rows) = read_rows(file.finish().unwrap(), "trades");
+    assert_eq!((groups, rows.len()), (2, 2));
+    let get = |row: usize, col: &str| rows[row].iter().find(|(n, _)| n == col).map(|(_, v)| v.to_string()).unwrap();
+    assert_eq!(get(0, "symbol"), "\"BTCUSDT\"");
+    assert_eq!(get(0, "first_trade_id"), "null");
+    assert_eq!(get(1, "first_trade_id"), "8");
+    assert_eq!(get(1, "msg_id"), "null");
+    assert_eq!(get(1, "ts_ms"), "1717200001500");
+    assert!(get(1, "timestamp").starts_with("2024-06-01 00:00:01.500"), "{}", get(1, "timestamp"));
+}
+
+#[test]
+fn ranges_page_in_timestamp_order() {
+    let range = Range { dataset: Dataset::Bars, table: "trades_1m".into(), symbols: vec!["BTCUSDT".into()], from_ms: 1, to_ms: 2 };
+    assert_eq!(
+        range.sql(1200, 100),
+        "SELECT timestamp, symbol, open, high, low, close, volume, trades FROM trades_1m WHERE timestamp >= cast(1200 AS timestamp) \
+         AND timestamp < cast(2000 AS timestamp) AND symbol IN ('BTCUSDT') ORDER BY timestamp LIMIT 100"
+    );
+    assert_eq!(Dataset::parse("bars").unwrap().default_table(), "trades_1m");
+    assert!(Dataset::parse("quotes").is_err());
+}
+
+#[test]
+fn csv_lines_quote_only_what_needs_it() {
+    assert_eq!(consumer::export::csv_header(Dataset::Bars), "timestamp,symbol,open,high,low,close,volume,trades\n");
+    let row = [json!("2024-06-01T00:00:00.000000Z"), json!("A,\"B\""), json!(1.5), json!(null), json!(true)];
+    assert_eq!(consumer::export::csv_line(&row), "2024-06-01T00:00:00.000000Z,\"A,\"\"B\"\"\",1.5,,true\n");
+}
+
+#[test]
+fn pages_are_keyed_on_the_timestamp_and_never_split_one() {
+    let range = Range { dataset: Dataset::Bars, table: "trades_1m".into(), symbols: vec![], from_ms: 1_717_200_000_000, to_ms: 1_717_200_060_000 };
+    let at = |s: &str, sym: &str| vec![json!(format!("2024-06-01T00:00:{}Z", s)), json!(sym)];
+    let mut pager = Pager::new(range, 3);
+    assert!(pager.sql().unwrap().contains("cast(1717200000000000 AS timestamp) AND timestamp < cast(1717200060000000 AS timestamp) ORDER BY timestamp LIMIT 3"));
+
+    // The rows at the page's last timestamp wait for the next page, which starts there.
+    let kept = pager.take(vec![at("00.000000", "A"), at("01.000000", "A"), at("01.000000", "B")]).unwrap();
+    assert_eq!(kept, [at("00.000000", "A")]);
+    assert!(pager.sql().unwrap().contains(">= cast(1717200001000000 AS timestamp)"));
+
+    // A page that's all one timestamp reads that timestamp whole, then moves past it.
+    assert!(pager.take(vec![at("01.000000", "A"), at("01.000000", "B"), at("01.000000", "C")]).unwrap().is_empty());
+    let sql = pager.sql().unwrap();
+    assert!(sql.contains(">= cast(1717200001000000 AS timestamp) AND timestamp < cast(1717200001000001 AS timestamp)") && !sql.contains("LIMIT"), "{}", sql);
+    assert_eq!(pager.take(vec![at("01.000000", "A"); 4]).unwrap().len(), 4);
+    assert!(pager.sql().unwrap().contains(">= cast(1717200001000001 AS timestamp)"));
+
+    // A short page is the last.
+    assert_eq!(pager.take(vec![at("02.000000", "A")]).unwrap().len(), 1);
+    assert_eq!(pager.sql(), None);
+    assert!(Pager::new(Range { dataset: Dataset::Bars, table: "t".into(), symbols: vec![], from_ms: 0, to_ms: 1 }, 1).take(vec![vec![json!(null)]]).is_err());
+}
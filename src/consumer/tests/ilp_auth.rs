@@ -0,0 +1,42 @@
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use consumer::ilp::IlpAuth;
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED};
+use secrets::Watched;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+// The example key from QuestDB's ILP authentication docs.
+const D: &str = "5UjEMuA0Pj5pjK8a-fa24dyIf-Es5mYny3oE_Wmus48";
+const X: &str = "fLKYEaoEb9lrn3nkwLDA-M_xnuFOdSt9y0Z7_vWSHLU";
+const Y: &str = "Dt5tbS1dEDMSYfym3fgMv0B99szno-dFc1rYF9t0aac";
+
+#[tokio::test]
+async fn the_challenge_comes_back_signed_with_the_key() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (conn, _) = listener.accept().await.unwrap();
+        let mut conn = BufReader::new(conn);
+        let mut user = String::new();
+        conn.read_line(&mut user).await.unwrap();
+        conn.get_mut().write_all(b"a-fresh-challenge\n").await.unwrap();
+        let mut signature = String::new();
+        conn.read_line(&mut signature).await.unwrap();
+        (user, STANDARD.decode(signature.trim_end()).unwrap())
+    });
+
+    let auth = IlpAuth::new("testUser1", Watched::fixed(D), X, Y).unwrap();
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    auth.handshake(&mut stream).await.unwrap();
+    let (user, signature) = server.await.unwrap();
+    assert_eq!(user, "testUser1\n");
+    let public = [vec![4], URL_SAFE_NO_PAD.decode(X).unwrap(), URL_SAFE_NO_PAD.decode(Y).unwrap()].concat();
+    UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, public).verify(b"a-fresh-challenge", &signature).unwrap();
+}
+
+#[test]
+fn a_token_that_isnt_the_public_keys_is_refused() {
+    assert!(IlpAuth::new("testUser1", Watched::fixed(D), Y, X).is_err());
+    assert!(IlpAuth::new("testUser1", Watched::fixed("not base64!"), X, Y).is_err());
+}
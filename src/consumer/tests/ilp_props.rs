@@ -0,0 +1,150 @@
+use consumer::ilp::{to_ilp_line, to_ilp_line_in, NormTrade};
+use proptest::prelude::*;
+
+/// Minimal ILP line reader: just enough of the grammar to check what `to_ilp_line` emits.
+struct Line {
+    measurement: String,
+    tags: Vec<(String, String)>,
+    /// Raw field values; strings keep their quotes and escapes.
+    fields: Vec<(String, String)>,
+    ts: String,
+}
+
+/// Split on `sep` outside backslash escapes and (optionally) double-quoted strings.
+fn split(s: &str, sep: char, quotes: bool) -> Vec<&str> {
+    let (mut parts, mut start, mut esc, mut quoted) = (Vec::new(), 0, false, false);
+    for (i, c) in s.char_indices() {
+        if esc { esc = false; continue; }
+        match c {
+            '\\' => esc = true,
+            '"' if quotes => quoted = !quoted,
+            c if c == sep && !quoted => { parts.push(&s[start..i]); start = i + c.len_utf8(); }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        out.push(if c == '\\' { chars.next().expect("dangling backslash") } else { c });
+    }
+    out
+}
+
+fn kv(s: &str) -> (String, String) {
+    let k = split(s, '=', false)[0];
+    let v = &s[k.len() + 1..];
+    (unescape(k), v.to_string())
+}
+
+/// Quotes only mean something in the field section; a `"` in a tag value is literal.
+fn parse(line: &str) -> Line {
+    assert_eq!(split(line, '\n', false).len(), 1, "unescaped line break in {:?}", line);
+    let head = split(line, ' ', false)[0];
+    let rest = split(&line[head.len() + 1..], ' ', true);
+    assert_eq!(rest.len(), 2, "expected fields and timestamp in {:?}", line);
+    let mut head = split(head, ',', false).into_iter();
+    let measurement = unescape(head.next().unwrap());
+    let tags = head.map(|t| { let (k, v) = kv(t); (k, unescape(&v)) }).collect();
+    let fields = split(rest[0], ',', true).into_iter().map(kv).collect();
+    Line { measurement, tags, fields, ts: rest[1].to_string() }
+}
+
+impl Line {
+    fn field(&self, k: &str) -> &str {
+        &self.fields.iter().find(|(f, _)| f == k).unwrap_or_else(|| panic!("no field {}", k)).1
+    }
+
+    fn string_field(&self, k: &str) -> String {
+        let v = self.field(k);
+        assert!(v.len() >= 2 && v.starts_with('"') && v.ends_with('"'), "{} not quoted: {:?}", k, v);
+        unescape(&v[1..v.len() - 1])
+    }
+}
+
+fn trade(symbol: String, price: f64, qty: f64, trade_id: i64, ts_ms: i64, is_bm: bool) -> NormTrade {
+    NormTrade { ts_ms, symbol, price, qty, trade_id, is_bm, session: None, notional: None, first_trade_id: None, contract_size: None, ts_ns: None, ingest_ts_ns: None }
+}
+
+/// Symbols and ids with every character ILP treats specially, plus arbitrary unicode.
+fn adversarial() -> impl Strategy<Value = String> {
+    prop_oneof![
+        "[ ,=\\\\\"\n\r\tA-Z]{0,16}",
+        ".{0,24}",
+        any::<String>(),
+    ]
+}
+
+fn finite() -> impl Strategy<Value = f64> {
+    any::<f64>().prop_filter("finite", |v| v.is_finite())
+}
+
+proptest! {
+    #[test]
+    fn adversarial_tags_and_strings_round_trip(symbol in adversarial(), msg_id in adversarial()) {
+        let line = to_ilp_line(&trade(symbol.clone(), 1.5, 2.0, 7, 1_700_000_000_000, true), &msg_id);
+        let l = parse(&line);
+        prop_assert_eq!(&l.measurement, "trades");
+        prop_assert_eq!(&l.tags, &vec![("symbol".to_string(), symbol)]);
+        prop_assert_eq!(l.string_field("msg_id"), msg_id);
+    }
+
+    #[test]
+    fn numbers_survive_exactly(
+        price in finite(), qty in finite(), trade_id in any::<i64>(),
+        ts_ms in any::<i64>(), is_bm in any::<bool>(),
+    ) {
+        let l = parse(&to_ilp_line(&trade("BTCUSDT".into(), price, qty, trade_id, ts_ms, is_bm), "id"));
+        prop_assert_eq!(l.field("price").parse::<f64>().unwrap().to_bits(), price.to_bits());
+        prop_assert_eq!(l.field("qty").parse::<f64>().unwrap().to_bits(), qty.to_bits());
+        prop_assert_eq!(l.field("trade_id"), format!("{}i", trade_id));
+        prop_assert_eq!(l.field("ts_ms"), format!("{}i", ts_ms));
+        prop_assert_eq!(l.field("is_bm"), is_bm.to_string());
+        prop_assert_eq!(&l.ts, &(ts_ms as i128 * 1_000_000).to_string());
+    }
+
+    /// Raw Binance payload -> producer -> `ticks.norm` JSON -> consumer -> ILP, compared to the source strings.
+    #[test]
+    fn raw_to_ilp_end_to_end(
+        symbol in adversarial(), trade_id in any::<i64>(),
+        p in (0u64..100_000_000, 0u64..100_000_000), q in (0u64..100_000, 0u64..100_000_000),
+        ts_ms in 0i64..4_102_444_800_000, is_bm in any::<bool>(), msg_id in adversarial(),
+    ) {
+        let (price, qty) = (format!("{}.{:08}", p.0, p.1), format!("{}.{:08}", q.0, q.1));
+        let raw = serde_json::json!({
+            "e": "trade", "s": symbol, "t": trade_id, "p": price, "q": qty, "T": ts_ms, "m": is_bm,
+        }).to_string();
+
+        let norm = producer::normalize::normalize(&raw).unwrap();
+        let wire = serde_json::to_string(&norm).unwrap();
+        let t: NormTrade = serde_json::from_str(&wire).unwrap();
+        let l = parse(&to_ilp_line(&t, &msg_id));
+
+        prop_assert_eq!(&l.tags[0].1, &symbol);
+        prop_assert_eq!(l.field("price").parse::<f64>().unwrap().to_bits(), price.parse::<f64>().unwrap().to_bits());
+        prop_assert_eq!(l.field("qty").parse::<f64>().unwrap().to_bits(), qty.parse::<f64>().unwrap().to_bits());
+        prop_assert_eq!(l.field("trade_id"), format!("{}i", trade_id));
+        prop_assert_eq!(l.string_field("msg_id"), msg_id);
+    }
+}
+
+#[test]
+fn escaping_examples() {
+    let line = to_ilp_line(&trade("a b,c=d\\e".into(), 0.1, 1e-8, 1, 0, false), "x\"y\\z\nw");
+    assert_eq!(
+        line,
+        "trades,symbol=a\\ b\\,c\\=d\\\\e price=0.1,qty=0.00000001,trade_id=1i,is_bm=false,msg_id=\"x\\\"y\\\\z\\\nw\",ts_ms=0i 0"
+    );
+}
+
+#[test]
+fn a_routed_trade_goes_to_its_table_and_is_otherwise_the_same() {
+    let t = trade("BTCUSDT".into(), 96123.5, 0.5, 42, 1_700_000_000_000, true);
+    let (routed, plain) = (parse(&to_ilp_line_in("trades_btc", &t, "id")), parse(&to_ilp_line(&t, "id")));
+    assert_eq!((routed.measurement.as_str(), plain.measurement.as_str()), ("trades_btc", "trades"));
+    assert_eq!((routed.tags, routed.fields, routed.ts), (plain.tags, plain.fields, plain.ts));
+}
@@ -0,0 +1,36 @@
+use consumer::ilp::NormTrade;
+use consumer::profile::Profile;
+
+fn trade(ts_ms: i64, price: f64, qty: f64, is_bm: bool) -> NormTrade {
+    NormTrade { ts_ms, symbol: "BTC".into(), price, qty, trade_id: ts_ms, is_bm, session: None, notional: None, first_trade_id: None, contract_size: None, ts_ns: None, ingest_ts_ns: None }
+}
+
+#[test]
+fn volume_is_binned_by_log_price_level_per_window() {
+    let mut p = Profile::new(3_600_000, 10.0, 2_000, 60_000);
+    // Level n starts at 1.001^n.
+    let l = p.level(100.0);
+    let edge = 1.001f64.powi(l as i32);
+    assert!(edge <= 100.0 && 100.0 < edge * 1.001);
+
+    p.add(&trade(0, edge * 1.0001, 1.0, false));
+    p.add(&trade(1_000, edge * 1.0009, 2.0, true));
+    p.add(&trade(2_000, edge * 1.0011, 0.5, false));
+    assert!(p.close(3_600_000).is_empty(), "inside the grace period");
+
+    p.add(&trade(3_602_000, 101.0, 1.0, false));
+    let levels = p.close(0);
+    assert_eq!(levels.len(), 2);
+    let low = &levels[0];
+    assert_eq!((low.ts_ms, low.level, low.trades), (0, l, 2));
+    assert_eq!((low.volume, low.buy_volume, low.sell_volume), (3.0, 1.0, 2.0));
+    assert!((low.price - edge).abs() < 1e-9);
+    assert_eq!((levels[1].level, levels[1].trades), (l + 1, 1));
+
+    // The hour is closed; a straggler for it is late.
+    p.add(&trade(5_000, 100.0, 1.0, false));
+    let later = p.close(3_600_000 * 2 + 60_000);
+    assert_eq!(later.len(), 1);
+    assert_eq!(later[0].ts_ms, 3_600_000);
+    assert!(later[0].to_ilp_line("volume_profile").starts_with(&format!("volume_profile,symbol=BTC level={}i,price=", p.level(101.0))));
+}
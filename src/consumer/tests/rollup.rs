@@ -0,0 +1,64 @@
+use consumer::ilp::NormTrade;
+use consumer::rollup::{default_topic, Bar, Rollup};
+
+fn trade(symbol: &str, ts_ms: i64, price: f64, qty: f64) -> NormTrade {
+    NormTrade { ts_ms, symbol: symbol.into(), price, qty, trade_id: ts_ms, is_bm: false, session: None, notional: None, first_trade_id: None, contract_size: None, ts_ns: None, ingest_ts_ns: None }
+}
+
+#[test]
+fn bars_close_on_event_time_plus_grace() {
+    let mut r = Rollup::new(60_000, 2_000, 3_600_000);
+    for (ts, p, q) in [(60_000, 10.0, 1.0), (70_000, 12.0, 2.0), (80_000, 9.0, 0.5), (119_999, 11.0, 1.5)] {
+        r.add(&trade("BTC", ts, p, q));
+    }
+    r.add(&trade("BTC", 121_000, 11.5, 1.0));
+    assert!(r.close(0).is_empty(), "inside the grace period");
+
+    r.add(&trade("BTC", 122_000, 11.6, 1.0));
+    assert_eq!(r.close(0), [Bar {
+        symbol: "BTC".into(), ts_ms: 60_000, open: 10.0, high: 12.0, low: 9.0, close: 11.0, volume: 5.0, trades: 4,
+    }]);
+
+    // The 60s window is gone; a straggler for it is dropped, not reopened.
+    r.add(&trade("BTC", 100_000, 1.0, 1.0));
+    let bars = r.close(3_600_000 + 180_000);
+    assert_eq!(bars.len(), 1);
+    assert_eq!((bars[0].ts_ms, bars[0].trades, bars[0].open, bars[0].close), (120_000, 2, 11.5, 11.6));
+}
+
+#[test]
+fn quiet_symbols_close_on_wall_clock_and_format_as_ilp() {
+    let mut r = Rollup::new(60_000, 2_000, 30_000);
+    r.add(&trade("ETH USD", 0, 2.5, 4.0));
+    assert!(r.close(89_999).is_empty());
+    let bars = r.close(90_000);
+    assert_eq!(
+        bars[0].to_ilp_line("trades_1m"),
+        "trades_1m,symbol=ETH\\ USD open=2.5,high=2.5,low=2.5,close=2.5,volume=4,trades=1i 0"
+    );
+}
+
+#[test]
+fn over_budget_the_oldest_windows_close_early() {
+    let mut r = Rollup::new(60_000, 2_000, 3_600_000);
+    for (symbol, ts) in [("BTC", 0), ("ETH", 60_000), ("BTC", 120_000)] {
+        r.add(&trade(symbol, ts, 1.0, 1.0));
+    }
+    let per_bar = r.bytes() / 3;
+    let bars = r.shed(per_bar * 2);
+    assert_eq!(bars.iter().map(|b| (b.symbol.as_str(), b.ts_ms)).collect::<Vec<_>>(), [("BTC", 0)]);
+    assert_eq!(r.bytes(), per_bar * 2);
+
+    // The shed window is closed: a straggler for it counts as late.
+    r.add(&trade("BTC", 30_000, 1.0, 1.0));
+    assert_eq!(r.bytes(), per_bar * 2);
+}
+
+#[test]
+fn the_default_topic_is_named_after_the_window() {
+    assert_eq!(default_topic(60_000), "bars.1m");
+    assert_eq!(default_topic(300_000), "bars.5m");
+    assert_eq!(default_topic(3_600_000), "bars.1h");
+    assert_eq!(default_topic(86_400_000), "bars.1d");
+    assert_eq!(default_topic(90_000), "bars.90s");
+}
@@ -0,0 +1,89 @@
+use consumer::schema::{check, expect_trades, expected, migrations, optional_tables, pending, routed_trades, Col};
+
+/// Columns as QuestDB reports them; `timestamp` is designated and every listed key is an upsert key.
+fn cols(spec: &[(&str, &str)], keys: &[&str]) -> Vec<Col> {
+    spec.iter()
+        .map(|(c, t)| Col {
+            name: c.to_string(),
+            ty: t.to_string(),
+            designated: *c == "timestamp",
+            upsert_key: keys.contains(c),
+        })
+        .collect()
+}
+
+#[test]
+fn pending_migrations_follow_the_log() {
+    let all = migrations(Some("trades_1m"), Some("volume_profile"), Some("book"), Some("trade_bars"), Some("_checkpoints"), 1024);
+    assert_eq!(pending(&all, 0).iter().map(|m| m.version).collect::<Vec<_>>(), [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14]);
+    assert_eq!(pending(&all, 1).iter().map(|m| m.version).collect::<Vec<_>>(), [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14]);
+    assert!(pending(&all, 99).is_empty());
+    assert!(all[..2].iter().flat_map(|m| &m.statements).all(|s| s.contains("IF NOT EXISTS")));
+    assert!(all[1].statements[0].contains("trades_1m") && all[0].statements[0].contains("CAPACITY 1024"));
+    // Bars of one kind but different sizes can start on the same trade.
+    assert_eq!(all[11].statements[1], "ALTER TABLE trade_bars DEDUP ENABLE UPSERT KEYS(timestamp, symbol, kind, size)");
+}
+
+#[test]
+fn tables_that_are_off_are_not_created() {
+    let trades = |s: &String| s.starts_with("ALTER TABLE trades ") || s.starts_with("CREATE TABLE IF NOT EXISTS trades (");
+    let off = migrations(None, None, None, None, None, 1024);
+    // Every version is still logged, so turning a table on later doesn't renumber anything.
+    assert_eq!(off.iter().map(|m| m.version).collect::<Vec<_>>(), [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14]);
+    for m in &off {
+        assert!(m.statements.iter().all(trades), "{}: {:?}", m.name, m.statements);
+    }
+    assert!(optional_tables(None, None, None, None, None, 1024).is_empty());
+
+    // A table turned on after its migration ran is made on the next start.
+    let book = optional_tables(None, None, Some("book"), None, None, 1024);
+    assert!(book[0].starts_with("CREATE TABLE IF NOT EXISTS book (") && book[1].contains("DEDUP ENABLE"));
+    let on = optional_tables(Some("r"), Some("p"), Some("b"), Some("tb"), Some("c"), 64);
+    let logged: Vec<String> = migrations(Some("r"), Some("p"), Some("b"), Some("tb"), Some("c"), 64).into_iter()
+        .flat_map(|m| m.statements)
+        .filter(|s| !trades(s))
+        .collect();
+    assert_eq!(on, logged);
+}
+
+#[test]
+fn ilp_auto_created_trades_table_passes_and_drift_fails() {
+    let trades = &expected(None, None, None, None)[0];
+    // What ILP auto-create made on QuestDB 7.x (STRING rather than VARCHAR), plus migrations 4, 6, 7 and 11's columns.
+    let auto = cols(&[
+        ("symbol", "SYMBOL"), ("price", "DOUBLE"), ("qty", "DOUBLE"), ("trade_id", "LONG"),
+        ("is_bm", "BOOLEAN"), ("msg_id", "STRING"), ("ts_ms", "LONG"), ("timestamp", "TIMESTAMP"),
+        ("notional", "DOUBLE"), ("first_trade_id", "LONG"), ("contract_size", "DOUBLE"),
+        ("ts_ns", "LONG"), ("ingest_ts_ns", "LONG"), ("session", "SYMBOL"),
+    ], &["timestamp", "symbol", "trade_id"]);
+    check(trades, &auto).unwrap();
+
+    let mut no_ts = auto.clone();
+    no_ts[7].designated = false;
+    assert!(check(trades, &no_ts).unwrap_err().to_string().contains("designated"));
+    let mut drifted = auto.clone();
+    drifted[3].ty = "DOUBLE".into();
+    assert!(check(trades, &drifted).unwrap_err().to_string().contains("trades.trade_id"));
+    assert!(check(trades, &auto[1..]).unwrap_err().to_string().contains("missing column symbol"));
+    let mut no_dedup = auto.clone();
+    no_dedup[3].upsert_key = false;
+    assert!(check(trades, &no_dedup).unwrap_err().to_string().contains("trade_id is not a dedup upsert key"));
+}
+
+#[test]
+fn a_routed_trades_table_is_created_with_every_column_trades_has() {
+    let sql = routed_trades("trades_btc", 64);
+    assert!(sql[0].starts_with("CREATE TABLE IF NOT EXISTS trades_btc (") && sql[0].contains("CAPACITY 64"));
+    assert!(sql[1].contains("trades_btc DEDUP ENABLE UPSERT KEYS(timestamp, symbol, trade_id)"));
+    let expect = expect_trades("trades_btc");
+    assert_eq!(expect.columns.len(), expected(None, None, None, None)[0].columns.len());
+    for (name, types) in &expect.columns {
+        assert!(sql.iter().any(|s| s.contains(&format!("{} {}", name, types[0]))), "{} {} missing", name, types[0]);
+    }
+    // The same steps trades gets, so a new trades migration reaches routed tables too.
+    let trades: Vec<String> = migrations(Some("r"), Some("p"), Some("b"), Some("tb"), Some("c"), 64).into_iter()
+        .flat_map(|m| m.statements)
+        .filter(|s| s.starts_with("ALTER TABLE trades ") || s.starts_with("CREATE TABLE IF NOT EXISTS trades ("))
+        .collect();
+    assert_eq!(trades, routed_trades("trades", 64));
+}
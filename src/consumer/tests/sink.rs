@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+use consumer::sink::{parse_endpoints, FlushSchedule, IlpOptions, IlpPool, IlpSink, PoolMode};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn chunks_arrive_in_order_across_buffer_boundaries() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = tokio::spawn(async move {
+        let (mut s, _) = listener.accept().await.unwrap();
+        let mut got = Vec::new();
+        s.read_to_end(&mut got).await.unwrap();
+        got
+    });
+
+    // Small buffer so some chunks are buffered and some go straight through.
+    let mut sink = IlpSink::connect("127.0.0.1", port, IlpOptions { nodelay: true, buf_bytes: 64 }).await.unwrap();
+    assert!(sink.nodelay().unwrap());
+    let chunks: Vec<Vec<u8>> = (0..50).map(|i| format!("trades,symbol=S{} x={}i {}\n", i, i, "0".repeat(i)).into_bytes()).collect();
+    let slices: Vec<&[u8]> = chunks.iter().map(Vec::as_slice).collect();
+    sink.write_chunks(&slices[..25]).await.unwrap();
+    sink.write_chunks(&slices[25..]).await.unwrap();
+    drop(sink);
+
+    assert_eq!(server.await.unwrap(), chunks.concat());
+}
+
+async fn listener() -> (TcpListener, u16) {
+    let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = l.local_addr().unwrap().port();
+    (l, port)
+}
+
+/// A port nothing is listening on.
+async fn dead_port() -> u16 {
+    listener().await.1
+}
+
+#[test]
+fn endpoint_lists_parse_with_default_port() {
+    assert_eq!(
+        parse_endpoints("qdb-a:9009, qdb-b ,10.0.0.3:19009", 9009).unwrap(),
+        [("qdb-a".to_string(), 9009), ("qdb-b".to_string(), 9009), ("10.0.0.3".to_string(), 19009)]
+    );
+    assert!(parse_endpoints(" , ", 9009).is_err());
+    assert!(parse_endpoints("qdb:port", 9009).is_err());
+}
+
+#[tokio::test]
+async fn pool_skips_dead_nodes_and_round_robins_live_ones() {
+    let (a, pa) = listener().await;
+    let (b, pb) = listener().await;
+    let eps = vec![("127.0.0.1".to_string(), dead_port().await), ("127.0.0.1".to_string(), pa), ("127.0.0.1".to_string(), pb)];
+    let mut pool = IlpPool::connect(eps, PoolMode::RoundRobin, IlpOptions::default(), Duration::from_secs(60)).await.unwrap();
+    assert_eq!(pool.healthy(), 2);
+    let (_sa, _sb) = (a.accept().await.unwrap(), b.accept().await.unwrap());
+
+    let mut used = Vec::new();
+    for _ in 0..4 {
+        used.push(pool.write_chunks(&[b"trades x=1i 0\n"]).await.unwrap().to_string());
+    }
+    let (a, b) = (format!("127.0.0.1:{}", pa), format!("127.0.0.1:{}", pb));
+    assert_eq!(used, [a.clone(), b.clone(), a, b]);
+}
+
+#[tokio::test]
+async fn failover_prefers_the_first_live_node() {
+    let (a, pa) = listener().await;
+    let (b, pb) = listener().await;
+    let eps = vec![("127.0.0.1".to_string(), pa), ("127.0.0.1".to_string(), pb)];
+    let mut pool = IlpPool::connect(eps, PoolMode::Failover, IlpOptions::default(), Duration::from_secs(60)).await.unwrap();
+    let (_sa, _sb) = (a.accept().await.unwrap(), b.accept().await.unwrap());
+    for _ in 0..3 {
+        assert_eq!(pool.write_chunks(&[b"trades x=1i 0\n"]).await.unwrap(), format!("127.0.0.1:{}", pa));
+    }
+}
+
+#[tokio::test]
+async fn connect_fails_when_no_node_is_up() {
+    let eps = vec![("127.0.0.1".to_string(), dead_port().await)];
+    assert!(IlpPool::connect(eps, PoolMode::Failover, IlpOptions::default(), Duration::from_secs(60)).await.is_err());
+}
+
+#[test]
+fn an_aligned_batch_is_due_at_the_commit_window_it_started_in() {
+    let coalesce = FlushSchedule { max_rows: 1000, max_wait: Duration::from_millis(2), align: None };
+    let aligned = FlushSchedule { align: Some(Duration::from_millis(1000)), ..coalesce };
+    let t0 = Instant::now();
+    let later = |ms: u64| t0 + Duration::from_millis(ms);
+
+    assert_eq!(coalesce.wait(t0, later(1), 5_250), Duration::from_millis(1));
+    assert_eq!(coalesce.wait(t0, later(9), 5_258), Duration::ZERO);
+    // Started at 5_250: due at 6_000, wherever the clock is now.
+    assert_eq!(aligned.wait(t0, t0, 5_250), Duration::from_millis(750));
+    assert_eq!(aligned.wait(t0, later(700), 5_950), Duration::from_millis(50));
+    assert_eq!(aligned.wait(t0, later(760), 6_010), Duration::ZERO, "a late wakeup flushes, not waits a window");
+    assert!(aligned.full(1000) && !aligned.full(999));
+}
@@ -0,0 +1,27 @@
+use bus::{Delivery, Headers};
+use consumer::spill::Spill;
+
+#[test]
+fn spill_files_use_the_capture_format() {
+    let dir = std::env::temp_dir().join(format!("consumer-spill-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut spill = Spill::new(&dir).unwrap();
+    let ds = [
+        Delivery::detached("ticks.norm", Some("BTCUSDT"), br#"{"p":"1"}"#.to_vec(), Headers::new().with("msg_id", "m1")),
+        Delivery::detached("ticks.norm", None, vec![0xff, 0x00], Headers::new()),
+    ];
+    let path = spill.write(&ds).unwrap();
+    assert_eq!(path.extension().unwrap(), "ndjson");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1, "no .tmp left behind");
+
+    let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path).unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["key"], "BTCUSDT");
+    assert_eq!(lines[0]["payload"], r#"{"p":"1"}"#);
+    assert_eq!(lines[0]["headers"][0], serde_json::json!(["msg_id", "m1"]));
+    assert_eq!(lines[1]["payload"]["b64"], "/wA=");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,180 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bus::envelope::{Envelope, NORM_SCHEMA};
+use bus::{Headers, Publisher};
+use consumer::archive::{ArchiveOptions, ParquetSink};
+use consumer::batch::{Batch, Row};
+use consumer::ilp::NormTrade;
+use consumer::sink::Sink;
+use consumer::tee::{Slot, Tee, DLQ_SINK};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use retry::{Backoff, Breaker, Policy};
+
+/// Fails its first `fail` writes, then records what it was given.
+struct Flaky {
+    name: &'static str,
+    fail: usize,
+    rows: Arc<Mutex<Vec<i64>>>,
+}
+
+#[async_trait]
+impl Sink for Flaky {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    async fn write(&mut self, batch: &Batch) -> Result<()> {
+        if self.fail > 0 {
+            self.fail -= 1;
+            anyhow::bail!("{} down", self.name);
+        }
+        self.rows.lock().unwrap().extend(batch.iter_rows().map(|r| r.trade.trade_id));
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Recorder(Mutex<Vec<(String, Headers)>>);
+
+#[async_trait]
+impl Publisher for Recorder {
+    async fn publish(&self, topic: &str, _key: &str, _payload: &[u8], headers: &Headers) -> Result<()> {
+        self.0.lock().unwrap().push((topic.to_string(), headers.clone()));
+        Ok(())
+    }
+}
+
+fn batch(ids: std::ops::Range<i64>) -> Batch {
+    let mut b = Batch::new();
+    for trade_id in ids {
+        let trade = NormTrade { ts_ms: 1_700_000_000_000 + trade_id, symbol: "BTCUSDT".into(), price: 1.5, qty: 0.25, trade_id, is_bm: trade_id % 2 == 0, session: None, notional: None, first_trade_id: None, contract_size: None, ts_ns: None, ingest_ts_ns: None };
+        b.push_row(Row { trade, msg_id: format!("m{}", trade_id), topic: String::new(), partition: None });
+    }
+    b
+}
+
+fn slot(sink: Flaky, retries: u32, dlq_topic: &str) -> Slot {
+    Slot { sink: Box::new(sink), policy: Policy::times(retries, Backoff::new(Duration::ZERO, Duration::ZERO)), dlq_topic: dlq_topic.into() }
+}
+
+fn envelope() -> Envelope {
+    Envelope::new(NORM_SCHEMA, "consumer", "test", "binance", "spot")
+}
+
+#[tokio::test]
+async fn each_sink_retries_and_dead_letters_on_its_own() {
+    let (live, archive) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(Vec::new())));
+    let dlq = Arc::new(Recorder::default());
+    let mut tee = Tee::new(
+        vec![
+            slot(Flaky { name: "live", fail: 1, rows: live.clone() }, 1, ""),
+            slot(Flaky { name: "archive", fail: 10, rows: archive.clone() }, 2, "archive.dlq"),
+        ],
+        Some(dlq.clone()),
+        envelope(),
+    );
+
+    assert!(tee.write(&batch(0..3)).await);
+    assert_eq!(*live.lock().unwrap(), [0, 1, 2]);
+    assert!(archive.lock().unwrap().is_empty());
+    let dead = dlq.0.lock().unwrap();
+    assert_eq!(dead.len(), 3);
+    assert!(dead.iter().all(|(t, h)| t == "archive.dlq" && h.get(DLQ_SINK) == Some("archive") && h.get("schema_version") == Some(NORM_SCHEMA.to_string().as_str())));
+}
+
+#[tokio::test]
+async fn a_failing_sink_without_dlq_loses_the_batch() {
+    let rows = Arc::new(Mutex::new(Vec::new()));
+    let mut tee = Tee::new(vec![slot(Flaky { name: "live", fail: 5, rows }, 1, "")], None, envelope());
+    assert!(!tee.write(&batch(0..2)).await);
+}
+
+#[tokio::test]
+async fn rewriting_a_lost_batch_only_retries_the_sinks_that_lost_it() {
+    let (live, archive) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(Vec::new())));
+    let mut tee = Tee::new(
+        vec![
+            slot(Flaky { name: "live", fail: 0, rows: live.clone() }, 0, ""),
+            slot(Flaky { name: "archive", fail: 2, rows: archive.clone() }, 0, ""),
+        ],
+        None,
+        envelope(),
+    );
+    let held = batch(0..2);
+    assert!(!tee.write(&held).await);
+    assert!(!tee.write(&held).await);
+    assert!(tee.write(&held).await);
+    assert!(tee.write(&batch(2..3)).await);
+    assert_eq!(*live.lock().unwrap(), [0, 1, 2]);
+    assert_eq!(*archive.lock().unwrap(), [0, 1, 2]);
+}
+
+#[tokio::test]
+async fn an_open_breaker_dead_letters_without_trying_the_sink() {
+    let rows = Arc::new(Mutex::new(Vec::new()));
+    let dlq = Arc::new(Recorder::default());
+    let breaker = Arc::new(Breaker::new("sink_archive", 2, Duration::from_secs(60)));
+    let mut archive = slot(Flaky { name: "archive", fail: 2, rows: rows.clone() }, 5, "archive.dlq");
+    archive.policy = archive.policy.breaker(breaker);
+    let mut tee = Tee::new(vec![archive], Some(dlq.clone()), envelope());
+
+    // Two failures open the breaker, then the rest go straight to the DLQ.
+    assert!(tee.write(&batch(0..2)).await);
+    assert!(tee.write(&batch(2..3)).await);
+    assert!(rows.lock().unwrap().is_empty(), "the sink would have taken a third try");
+    assert_eq!(dlq.0.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn parquet_files_roll_and_read_back() {
+    let dir = std::env::temp_dir().join(format!("consumer-archive-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut sink = ParquetSink::new(ArchiveOptions {
+        dir: dir.clone(),
+        row_group_rows: 4,
+        roll_rows: 8,
+        roll_every: Duration::from_secs(3600),
+    }).unwrap();
+    sink.write(&batch(0..5)).await.unwrap();
+    sink.write(&batch(5..10)).await.unwrap();
+    sink.write(&batch(10..13)).await.unwrap();
+    sink.close().await.unwrap();
+
+    let mut files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).collect();
+    files.sort();
+    assert_eq!(files.len(), 2);
+    assert!(files.iter().all(|f| f.extension().unwrap() == "parquet"));
+    let rows: i64 = files.iter()
+        .map(|f| SerializedFileReader::new(std::fs::File::open(f).unwrap()).unwrap().metadata().file_metadata().num_rows())
+        .sum();
+    assert_eq!(rows, 13);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn a_row_group_that_fails_to_write_stays_buffered() {
+    let dir = std::env::temp_dir().join(format!("consumer-archive-retry-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut sink = ParquetSink::new(ArchiveOptions {
+        dir: dir.clone(),
+        row_group_rows: 4,
+        roll_rows: 100,
+        roll_every: Duration::from_secs(3600),
+    }).unwrap();
+    sink.write(&batch(0..3)).await.unwrap();
+    // No directory to open the file in: the group can't be written.
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(sink.write(&batch(3..6)).await.is_err());
+    std::fs::create_dir_all(&dir).unwrap();
+    sink.write(&batch(3..6)).await.unwrap();
+    sink.close().await.unwrap();
+
+    let rows: i64 = std::fs::read_dir(&dir).unwrap()
+        .map(|e| SerializedFileReader::new(std::fs::File::open(e.unwrap().path()).unwrap()).unwrap().metadata().file_metadata().num_rows())
+        .sum();
+    assert_eq!(rows, 6, "the first three rows were kept through the failed write");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
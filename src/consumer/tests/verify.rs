@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use consumer::pg::PgClient;
+use consumer::verify::{last_sql, parse_count, parse_last, Ledger};
+use secrets::Watched;
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+#[test]
+fn rows_are_counted_once_per_window_and_checked_once_settled() {
+    let routes = HashMap::from([("ETHUSDT".to_string(), "trades_eth".to_string())]);
+    let mut l = Ledger::new(Duration::from_secs(60), routes);
+    l.record("q1:9009", "BTCUSDT", 1, 1_000, 10_000);
+    l.record("q1:9009", "BTCUSDT", 1, 1_000, 10_500); // a retried batch
+    l.record("q1:9009", "BTCUSDT", 2, 59_999, 11_000);
+    l.record("q1:9009", "SOLUSDT", 9, 30_000, 11_000);
+    l.record("q1:9009", "BTCUSDT", 3, 60_000, 12_000);
+    l.record("q1:9009", "ETHUSDT", 4, 2_000, 12_000);
+    assert_eq!(l.pending(), 3);
+
+    assert!(l.due(30_000, 30_000).is_empty(), "nothing has settled yet");
+    let due = l.due(41_000, 30_000);
+    assert_eq!(due.len(), 1);
+    assert_eq!((due[0].endpoint.as_str(), due[0].table.as_str(), due[0].from_ms, due[0].to_ms, due[0].sent), ("q1:9009", "trades", 0, 60_000, 3));
+    assert_eq!(due[0].symbols, ["BTCUSDT", "SOLUSDT"]);
+    assert_eq!((due[0].missing(3), due[0].missing(2), due[0].missing(5)), (0, 1, 0), "others' rows can't hide in a negative");
+
+    let rest = l.due(42_000, 30_000);
+    let tables: Vec<&str> = rest.iter().map(|c| c.table.as_str()).collect();
+    assert_eq!(tables, ["trades", "trades_eth"]);
+    assert_eq!(l.pending(), 0);
+    let newest = l.newest();
+    let newest: Vec<(&str, &str, i64)> = newest.iter().map(|(e, t, ts)| (e.as_str(), t.as_str(), *ts)).collect();
+    assert_eq!(newest, [("q1:9009", "trades", 60_000), ("q1:9009", "trades_eth", 2_000)]);
+}
+
+#[test]
+fn checks_count_only_our_symbols_on_the_designated_timestamp() {
+    let mut l = Ledger::new(Duration::from_secs(60), HashMap::new());
+    l.record("q1:9009", "O'BRIEN", 1, 61_000, 0);
+    let sql = l.due(i64::MAX, 0)[0].sql();
+    assert_eq!(sql, "SELECT count() FROM trades WHERE timestamp >= cast(60000000 AS timestamp) \
+        AND timestamp < cast(120000000 AS timestamp) AND symbol IN ('O''BRIEN')");
+    assert_eq!(last_sql("trades"), "SELECT cast(max(timestamp) AS long) FROM trades");
+}
+
+#[test]
+fn answers_read_as_pg_wire_sends_them() {
+    assert_eq!(parse_last(&[vec![json!("1739880000138000")]]).unwrap(), Some(1739880000138));
+    assert_eq!(parse_last(&[vec![json!(null)]]).unwrap(), None);
+    assert_eq!(parse_last(&[]).unwrap(), None);
+    assert!(parse_last(&[vec![json!("2025-02-18")]]).is_err());
+    assert_eq!(parse_count(&[vec![json!("42")]]).unwrap(), 42);
+    assert!(parse_count(&[vec![json!("-1")]]).is_err());
+    assert!(parse_count(&[]).is_err());
+}
+
+#[test]
+fn each_endpoint_is_checked_for_the_rows_it_took() {
+    let mut l = Ledger::new(Duration::from_secs(60), HashMap::new());
+    l.record("q1:9009", "BTCUSDT", 1, 1_000, 0);
+    l.record("q2:9009", "BTCUSDT", 2, 2_000, 0);
+    l.record("q2:9009", "BTCUSDT", 3, 3_000, 0);
+    let due = l.due(i64::MAX, 0);
+    let by_endpoint: Vec<(&str, u64)> = due.iter().map(|c| (c.endpoint.as_str(), c.sent)).collect();
+    assert_eq!(by_endpoint, [("q1:9009", 1), ("q2:9009", 2)]);
+}
+
+#[test]
+fn a_short_window_is_only_lost_once_it_stays_short() {
+    let mut l = Ledger::new(Duration::from_secs(60), HashMap::new());
+    for id in 0..5 {
+        l.record("q1:9009", "BTCUSDT", id, 1_000 + id, 0);
+    }
+    let mut c = l.due(i64::MAX, 0).remove(0);
+    assert_eq!(c.lost(3, 100_000, 60_000), None, "the WAL may not have applied yet");
+    assert_eq!(c.lost(4, 130_000, 60_000), None);
+    assert_eq!(c.lost(4, 160_000, 60_000), Some(1));
+
+    c.short_since_ms = None;
+    assert_eq!(c.lost(5, 100_000, 60_000), Some(0));
+    assert_eq!(c.lost(3, 100_000, 0), Some(2), "no recheck time counts a shortfall at once");
+}
+
+/// A PG server that asks for `password` and answers every query with `cell`
+/// in one row, or with an error for a query containing "nope".
+async fn fake_pg(password: &'static str, cell: Option<&'static str>) -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut s, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let len = s.read_i32().await.unwrap();
+                let mut startup = vec![0; len as usize - 4];
+                s.read_exact(&mut startup).await.unwrap();
+                assert!(startup.windows(6).any(|w| w == b"admin\0"));
+                reply(&mut s, b'R', &3i32.to_be_bytes()).await;
+                let (tag, body) = read(&mut s).await.unwrap();
+                assert_eq!(tag, b'p');
+                if body != [password.as_bytes(), b"\0"].concat() {
+                    reply(&mut s, b'E', b"SFATAL\0Minvalid password\0\0").await;
+                    return;
+                }
+                reply(&mut s, b'R', &0i32.to_be_bytes()).await;
+                reply(&mut s, b'S', b"server_version\x0011.3\0").await;
+                reply(&mut s, b'Z', b"I").await;
+                while let Some((b'Q', sql)) = read(&mut s).await {
+                    if sql.windows(4).any(|w| w == b"nope") {
+                        reply(&mut s, b'E', b"SERROR\0Mtable does not exist\0\0").await;
+                    } else {
+                        reply(&mut s, b'T', &[0, 1]).await;
+                        let mut row = 1u16.to_be_bytes().to_vec();
+                        match cell {
+                            Some(c) => {
+                                row.extend_from_slice(&(c.len() as i32).to_be_bytes());
+                                row.extend_from_slice(c.as_bytes());
+                            }
+                            None => row.extend_from_slice(&(-1i32).to_be_bytes()),
+                        }
+                        reply(&mut s, b'D', &row).await;
+                        reply(&mut s, b'C', b"SELECT 1\0").await;
+                    }
+                    reply(&mut s, b'Z', b"I").await;
+                }
+            });
+        }
+    });
+    addr
+}
+
+async fn reply(s: &mut TcpStream, tag: u8, body: &[u8]) {
+    s.write_u8(tag).await.unwrap();
+    s.write_i32(body.len() as i32 + 4).await.unwrap();
+    s.write_all(body).await.unwrap();
+}
+
+async fn read(s: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+    let tag = s.read_u8().await.ok()?;
+    let len = s.read_i32().await.ok()?;
+    let mut body = vec![0; len as usize - 4];
+    s.read_exact(&mut body).await.ok()?;
+    Some((tag, body))
+}
+
+#[tokio::test]
+async fn queries_go_over_pg_wire_with_the_password() {
+    let addr = fake_pg("s3cret", Some("42")).await.to_string();
+    let mut pg = PgClient::new(&addr, "admin", "qdb").with_password(Some(Watched::fixed("s3cret")));
+    assert_eq!(parse_count(&pg.query("SELECT count() FROM trades").await.unwrap()).unwrap(), 42);
+    // A refused query leaves the session usable.
+    let err = pg.query("SELECT count() FROM nope").await.unwrap_err();
+    assert!(format!("{:#}", err).contains("table does not exist"), "{:#}", err);
+    assert_eq!(parse_count(&pg.query("SELECT count() FROM trades").await.unwrap()).unwrap(), 42);
+
+    let addr = fake_pg("s3cret", None).await.to_string();
+    let mut pg = PgClient::new(&addr, "admin", "qdb").with_password(Some(Watched::fixed("s3cret")));
+    assert_eq!(parse_last(&pg.query(&last_sql("trades")).await.unwrap()).unwrap(), None);
+
+    // QuestDB's stock password when none is set.
+    let mut wrong = PgClient::new(&addr, "admin", "qdb");
+    let err = wrong.query("SELECT 1").await.unwrap_err();
+    assert!(format!("{:#}", err).contains("invalid password"), "{:#}", err);
+}
+
+#[test]
+fn a_full_ledger_stops_recording_and_makes_room_once_checked() {
+    let mut l = Ledger::new(Duration::from_secs(60), HashMap::new()).capped(2);
+    l.record("q1:9009", "BTCUSDT", 1, 1_000, 0);
+    l.record("q1:9009", "BTCUSDT", 2, 2_000, 0);
+    l.record("q1:9009", "BTCUSDT", 2, 2_000, 500); // already held: no room needed
+    l.record("q1:9009", "BTCUSDT", 3, 3_000, 1_000);
+    l.record("q1:9009", "BTCUSDT", 4, 61_000, 1_000);
+    assert_eq!((l.held(), l.pending()), (2, 1), "a full ledger opens no new windows");
+    assert!(l.due(30_000, 30_000).is_empty(), "an unrecorded row still keeps its window open");
+    let due = l.due(31_000, 30_000);
+    assert_eq!(due[0].sent, 2);
+    assert_eq!(l.held(), 0);
+    l.record("q1:9009", "BTCUSDT", 5, 62_000, 40_000);
+    assert_eq!(l.held(), 1);
+}
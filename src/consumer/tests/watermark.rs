@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+use consumer::watermark::Watermarks;
+
+#[test]
+fn aggregated_ranges_advance_the_watermark_past_every_id_they_cover() {
+    let mut w = Watermarks::new(Duration::from_secs(60));
+    w.record_range("BTC", None, 10, 12, 100);
+    w.record_range("BTC", None, 16, 20, 300); // ahead of a gap at 13..=15
+    let m = w.tick(0);
+    assert_eq!((m[0].trade_id, m[0].ts_ms), (12, 100));
+
+    // Straddles the frontier and fills the gap.
+    w.record_range("BTC", None, 11, 15, 200);
+    let m = w.tick(0);
+    assert_eq!((m[0].trade_id, m[0].ts_ms, m[0].gaps_skipped), (20, 300, 0));
+
+    w.record("BTC", None, 21, 400);
+    w.record_range("BTC", None, 18, 21, 400); // redelivery
+    assert_eq!(w.tick(0)[0].trade_id, 21);
+    assert!(w.tick(0).is_empty());
+}
+
+#[test]
+fn a_partition_is_as_complete_as_its_slowest_symbol() {
+    let mut w = Watermarks::new(Duration::from_secs(60));
+    w.record("BTC", Some(("ticks.norm", 0)), 1, 100);
+    w.record("BTC", Some(("ticks.norm", 0)), 2, 500);
+    w.record("ETH", Some(("ticks.norm", 0)), 7, 300);
+    w.record("SOL", Some(("ticks.norm", 1)), 3, 900);
+    w.record("XRP", None, 1, 50);
+    // The same partition number on another topic is another partition.
+    w.record("DOGE", Some(("ticks.alt", 0)), 4, 200);
+    let at = |t: &str, p| (t.to_string(), p);
+    assert_eq!(w.by_partition().into_iter().collect::<Vec<_>>(), [(at("ticks.alt", 0), 200), (at("ticks.norm", 0), 300), (at("ticks.norm", 1), 900)]);
+}
@@ -0,0 +1,146 @@
+//! Binance spot's signed REST endpoints and user data stream.
+//!
+//! Signed requests carry `timestamp` and `recvWindow`, then an HMAC-SHA256
+//! of the whole query string under the API secret as `signature`; the API
+//! key goes in `X-MBX-APIKEY`. Both are read afresh for every request, so
+//! a rotated key pair takes effect on the next one.
+//!
+//! A request Binance answers with a 4xx was [`Refused`]. One that timed out,
+//! lost its connection mid-way or got a 5xx may still have been carried out
+//! ([`unknown`]); the order is then looked up by its client order id.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use ratelimit::Limiter;
+use ring::hmac;
+use secrets::Watched;
+use serde_json::Value;
+
+/// Hex HMAC-SHA256 of `query` under `secret`.
+pub fn sign(secret: &str, query: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hmac::sign(&key, query.as_bytes()).as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Binance's `{code, msg}` answer to a failed request: a 4xx refused it, a 5xx is [`unknown`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refused {
+    pub status: u16,
+    pub code: i64,
+    pub msg: String,
+}
+
+impl std::fmt::Display for Refused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "binance {} ({}): {}", self.status, self.code, self.msg)
+    }
+}
+
+impl std::error::Error for Refused {}
+
+/// `-2013`: the order being looked up doesn't exist.
+pub const NO_SUCH_ORDER: i64 = -2013;
+
+/// Whether `e` is [`NO_SUCH_ORDER`].
+pub fn no_such_order(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<Refused>().is_some_and(|r| r.code == NO_SUCH_ORDER)
+}
+
+/// Whether a failed request may have been carried out anyway: it timed out,
+/// the connection dropped after it was sent, or Binance answered 5xx, which
+/// its docs call an unknown execution status. A connection that never opened
+/// sent nothing, and an error raised before sending isn't the exchange's.
+pub fn unknown(e: &anyhow::Error) -> bool {
+    if let Some(r) = e.downcast_ref::<Refused>() {
+        return r.status >= 500;
+    }
+    e.chain().find_map(|c| c.downcast_ref::<reqwest::Error>()).is_some_and(|e| !e.is_connect() && !e.is_builder())
+}
+
+/// `params` form-encoded, in order.
+pub fn encode(params: &[(&str, String)]) -> String {
+    url::form_urlencoded::Serializer::new(String::new()).extend_pairs(params).finish()
+}
+
+pub struct Rest {
+    http: reqwest::Client,
+    base: String,
+    key: Watched,
+    secret: Watched,
+    recv_window_ms: u64,
+    /// Shared with every other Binance REST caller in the process.
+    limiter: Arc<Limiter>,
+}
+
+impl Rest {
+    pub fn new(base: &str, key: Watched, secret: Watched, recv_window_ms: u64) -> Result<Self> {
+        Ok(Self {
+            http: reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?,
+            base: base.trim_end_matches('/').to_string(),
+            key,
+            secret,
+            recv_window_ms,
+            limiter: ratelimit::shared("binance")?,
+        })
+    }
+
+    /// Send a signed request; Binance's `{code, msg}` errors come back as [`Refused`].
+    async fn signed(&self, method: reqwest::Method, path: &str, weight: u32, mut params: Vec<(&str, String)>) -> Result<Value> {
+        params.push(("recvWindow", self.recv_window_ms.to_string()));
+        params.push(("timestamp", Utc::now().timestamp_millis().to_string()));
+        let query = encode(&params);
+        let url = format!("{}{}?{}&signature={}", self.base, path, query, sign(self.secret.current().expose(), &query));
+        self.send(self.http.request(method, url), weight).await
+    }
+
+    async fn send(&self, req: reqwest::RequestBuilder, weight: u32) -> Result<Value> {
+        let resp = self.limiter.send(req.header("X-MBX-APIKEY", self.key.current().expose()), weight).await?;
+        let status = resp.status();
+        let body: Value = resp.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            let msg = body.get("msg").and_then(Value::as_str).unwrap_or("no message").to_string();
+            let code = body.get("code").and_then(Value::as_i64).unwrap_or(0);
+            return Err(Refused { status: status.as_u16(), code, msg }.into());
+        }
+        Ok(body)
+    }
+
+    pub async fn place(&self, params: Vec<(&str, String)>) -> Result<Value> {
+        self.signed(reqwest::Method::POST, "/api/v3/order", 1, params).await
+    }
+
+    pub async fn cancel(&self, symbol: &str, client_order_id: &str) -> Result<Value> {
+        let params = vec![("symbol", symbol.to_uppercase()), ("origClientOrderId", client_order_id.to_string())];
+        self.signed(reqwest::Method::DELETE, "/api/v3/order", 1, params).await
+    }
+
+    /// The order with `client_order_id`, as it stands; [`NO_SUCH_ORDER`] if the exchange never took it.
+    pub async fn order(&self, symbol: &str, client_order_id: &str) -> Result<Value> {
+        let params = vec![("symbol", symbol.to_uppercase()), ("origClientOrderId", client_order_id.to_string())];
+        self.signed(reqwest::Method::GET, "/api/v3/order", 4, params).await
+    }
+
+    /// How long after it was signed the exchange will still take a request.
+    pub fn recv_window(&self) -> Duration {
+        Duration::from_millis(self.recv_window_ms)
+    }
+
+    /// The API key, for noticing when it rotates.
+    pub fn api_key(&self) -> Watched {
+        self.key.clone()
+    }
+
+    /// A new user data stream key; valid for 60 minutes unless kept alive.
+    pub async fn listen_key(&self) -> Result<String> {
+        let body = self.send(self.http.post(format!("{}/api/v3/userDataStream", self.base)), 2).await?;
+        body.get("listenKey").and_then(Value::as_str).map(str::to_string).context("no listenKey in response")
+    }
+
+    pub async fn keepalive(&self, listen_key: &str) -> Result<()> {
+        let url = format!("{}/api/v3/userDataStream?listenKey={}", self.base, listen_key);
+        self.send(self.http.put(url), 2).await.map(|_| ())
+    }
+}
@@ -0,0 +1,7 @@
+//! Binance execution gateway: order commands in, executions out.
+//!
+//! The library (signing, order and execution shapes) always builds; the
+//! `exec` binary, which talks to the real account, needs `--features live`.
+
+pub mod binance;
+pub mod order;
@@ -0,0 +1,207 @@
+//! Execution gateway: `orders` commands -> Binance REST; user data stream -> `executions`.
+//!
+//! Commands are committed before they're sent, so a crash can lose an order
+//! but never send it twice. Every execution the account reports, including
+//! ones for orders placed elsewhere, is published.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use bus::commit::{CommitStrategy, Committer};
+use bus::{BusConfig, Headers, Publisher};
+use chrono::Utc;
+use exec::binance::{self, Rest};
+use exec::order::{parse_order, parse_user_event, Command, Execution};
+use futures_util::StreamExt;
+use metrics::counter;
+use obsv::load::Load;
+use obsv::{init_metrics, init_tracing, startup};
+use retry::{Backoff, Policy};
+use tokio_tungstenite::connect_async;
+
+fn env<T: AsRef<str>>(k: T, default: &str) -> String {
+    std::env::var(k.as_ref()).unwrap_or_else(|_| default.to_string())
+}
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Binance drops a listen key after 60 minutes without a keepalive.
+const KEEPALIVE: Duration = Duration::from_secs(30 * 60);
+/// Lookups of an order whose command was lost, after the first.
+const RECONCILE_RETRIES: u32 = 5;
+
+async fn publish(publisher: &dyn Publisher, topic: &str, e: &Execution) {
+    let body = serde_json::to_vec(e).unwrap_or_default();
+    let policy = Policy::forever(Backoff::default());
+    let mut retry = policy.start("kafka_publish");
+    while let Err(err) = publisher.publish(topic, &e.symbol, &body, &Headers::new()).await {
+        tracing::warn!(target="exec", error=?err, "execution publish failed; will retry");
+        retry.failed(&err).await;
+    }
+    counter!("executions_total", "exec_type" => e.exec_type.clone()).increment(1);
+}
+
+/// Find out what became of a command whose request was lost, and publish
+/// it: `RECONCILED` with the order as it stands, or `REJECTED` if the
+/// exchange doesn't have it. Left `UNKNOWN`, and logged, if it can't be told.
+async fn reconcile(rest: Arc<Rest>, publisher: Arc<dyn Publisher>, topic: String, symbol: String, client_id: String) {
+    // Binance refuses a request older than its recvWindow, so after that a missing order stays missing.
+    tokio::time::sleep(rest.recv_window() + Duration::from_secs(1)).await;
+    let policy = Policy::times(RECONCILE_RETRIES, Backoff::new(MIN_BACKOFF, MAX_BACKOFF)).only(|e| !binance::no_such_order(e));
+    let found = policy.run("order_reconcile", || rest.order(&symbol, &client_id)).await.and_then(parse_order);
+    let e = match found {
+        Ok(e) => {
+            tracing::info!(target="exec", symbol=%symbol, client_order_id=%client_id, status=%e.status, "reconciled");
+            counter!("order_reconciles_total", "result" => "found").increment(1);
+            e
+        }
+        Err(e) if binance::no_such_order(&e) => {
+            tracing::info!(target="exec", symbol=%symbol, client_order_id=%client_id, "reconciled: the exchange never took it");
+            counter!("order_reconciles_total", "result" => "not_found").increment(1);
+            Execution::rejected(&symbol, &client_id, "not found on the exchange", Utc::now().timestamp_millis())
+        }
+        Err(e) => {
+            tracing::error!(target="exec", symbol=%symbol, client_order_id=%client_id, error=%e, "order outcome still unknown; check the account");
+            counter!("order_reconciles_total", "result" => "failed").increment(1);
+            return;
+        }
+    };
+    publish(publisher.as_ref(), &topic, &e).await;
+}
+
+/// Hold the user data stream open forever, with a fresh listen key per connection.
+async fn user_stream(rest: Arc<Rest>, ws_base: String, publisher: Arc<dyn Publisher>, topic: String) {
+    let policy = Policy::forever(Backoff::new(MIN_BACKOFF, MAX_BACKOFF));
+    let mut retry = policy.start("user_stream");
+    loop {
+        match stream_once(&rest, &ws_base, publisher.as_ref(), &topic).await {
+            Ok(()) => retry.succeeded(),
+            Err(e) => tracing::error!(target="exec", error=?e, "user data stream failed"),
+        }
+        counter!("user_stream_reconnects_total").increment(1);
+        retry.pause().await;
+    }
+}
+
+/// One connection's lifetime; `Ok` once it was established and then ended.
+async fn stream_once(rest: &Rest, ws_base: &str, publisher: &dyn Publisher, topic: &str) -> Result<()> {
+    // Listen keys belong to an API key, so a rotated one needs a new stream.
+    let mut api_key = rest.api_key();
+    let key = rest.listen_key().await?;
+    let (ws, _) = connect_async(format!("{}/ws/{}", ws_base.trim_end_matches('/'), key)).await?;
+    tracing::info!(target="exec", "user data stream connected");
+    let (_w, mut r) = ws.split();
+    let mut keepalive = tokio::time::interval(KEEPALIVE);
+    keepalive.tick().await;
+    loop {
+        tokio::select! {
+            msg = r.next() => {
+                let Some(msg) = msg else { break };
+                let msg = msg?;
+                if !msg.is_text() { continue; }
+                match parse_user_event(msg.to_text()?) {
+                    Ok(Some(e)) => publish(publisher, topic, &e).await,
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!(target="exec", error=?e, "undecodable user data event"),
+                }
+            }
+            _ = api_key.rotated() => {
+                tracing::info!(target="exec", "API key rotated; reconnecting user data stream");
+                return Ok(());
+            }
+            _ = keepalive.tick() => {
+                if let Err(e) = rest.keepalive(&key).await {
+                    tracing::warn!(target="exec", error=?e, "listen key keepalive failed; reconnecting");
+                    return Ok(());
+                }
+            }
+        }
+    }
+    tracing::warn!(target="exec", "user data stream ended");
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!("live", "nats", "pulsar"), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    init_metrics(9474)?;
+
+    // Each may also come from a file (`_FILE`) or a secret store (`_REF`).
+    let secrets = secrets::Secrets::from_env()?;
+    let key = secrets.require_watched("BINANCE_API_KEY").await?;
+    let secret = secrets.require_watched("BINANCE_API_SECRET").await?;
+    // https://testnet.binance.vision and wss://stream.testnet.binance.vision for the spot testnet.
+    let rest_url = env("BINANCE_REST_URL", "https://api.binance.com");
+    let ws_url = env("BINANCE_WS_URL", "wss://stream.binance.com:9443");
+    let recv_window: u64 = env("EXEC_RECV_WINDOW_MS", "5000").parse().unwrap_or(5000);
+    let orders_topic = env("ORDERS_TOPIC", "orders");
+    let executions_topic = env("EXECUTIONS_TOPIC", "executions");
+    let group_id = env("GROUP_ID", "exec");
+
+    let rest = Arc::new(Rest::new(&rest_url, key, secret, recv_window)?);
+    let bus = BusConfig::from_env()?;
+    let topics = [orders_topic.as_str(), executions_topic.as_str()];
+    startup::wait_for("kafka", || bus.check_topics(&topics)).await?;
+    bus::audit::start(&bus).await;
+    let publisher = bus.publisher().await?;
+    let mut orders = bus.subscriber(&orders_topic, &group_id).await?;
+    let mut committer = Committer::new(CommitStrategy::Message);
+    tokio::spawn(user_stream(rest.clone(), ws_url, publisher.clone(), executions_topic.clone()));
+    tracing::warn!(target="exec", rest=%rest_url, orders=%orders_topic, "live execution gateway: orders are sent to the exchange");
+
+    let load = Load::new("exec")?;
+    while let Some(next) = orders.next().await {
+        let d = match next {
+            Ok(d) => d,
+            Err(e) => { tracing::error!(target="exec", error=?e, "poll error"); continue; }
+        };
+        // The REST round trip included: an order is done once the exchange has answered.
+        let _busy = load.busy();
+        load.processed(1);
+        let cmd = serde_json::from_slice::<Command>(&d.payload);
+        // At most once: commit before acting.
+        if let Err(e) = committer.done(orders.as_ref(), d).await {
+            tracing::warn!(target="exec", error=?e, "commit failed");
+        }
+        let cmd = match cmd {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::warn!(target="exec", error=?e, "undecodable order command");
+                counter!("order_commands_dropped_total").increment(1);
+                continue;
+            }
+        };
+        let (symbol, client_id, res) = match &cmd {
+            Command::Place(o) => (&o.symbol, &o.client_order_id, match o.params() {
+                Ok(p) => rest.place(p).await,
+                Err(e) => Err(e),
+            }),
+            Command::Cancel { symbol, client_order_id } => (symbol, client_order_id, rest.cancel(symbol, client_order_id).await),
+        };
+        match res {
+            Ok(_) => {
+                tracing::info!(target="exec", ?cmd, "accepted");
+                counter!("order_commands_total", "result" => "accepted").increment(1);
+            }
+            // The exchange may have it: say so now, and look it up once it can't take it any more.
+            Err(e) if binance::unknown(&e) => {
+                tracing::warn!(target="exec", ?cmd, error=%e, "outcome unknown; reconciling");
+                counter!("order_commands_total", "result" => "unknown").increment(1);
+                let unknown = Execution::unknown(symbol, client_id, &e.to_string(), Utc::now().timestamp_millis());
+                publish(publisher.as_ref(), &executions_topic, &unknown).await;
+                tokio::spawn(reconcile(rest.clone(), publisher.clone(), executions_topic.clone(), symbol.clone(), client_id.clone()));
+            }
+            Err(e) => {
+                tracing::warn!(target="exec", ?cmd, error=%e, "rejected");
+                counter!("order_commands_total", "result" => "rejected").increment(1);
+                let rejected = Execution::rejected(symbol, client_id, &e.to_string(), Utc::now().timestamp_millis());
+                publish(publisher.as_ref(), &executions_topic, &rejected).await;
+            }
+        }
+    }
+    Ok(())
+}
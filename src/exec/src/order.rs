@@ -0,0 +1,210 @@
+//! Order commands read from `ORDERS_TOPIC` and executions published to
+//! `EXECUTIONS_TOPIC`.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum OrderType {
+    Limit,
+    Market,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Order {
+    pub symbol: String,
+    pub side: Side,
+    #[serde(rename = "type")]
+    pub kind: OrderType,
+    /// Decimal string, sent as is so no precision is lost on the way.
+    pub qty: String,
+    /// Required for `LIMIT`.
+    #[serde(default)]
+    pub price: Option<String>,
+    /// `GTC`, `IOC` or `FOK`; `LIMIT` orders default to `GTC`.
+    #[serde(default)]
+    pub time_in_force: Option<String>,
+    /// Ties executions back to the command; Binance rejects duplicates of open orders.
+    pub client_order_id: String,
+}
+
+impl Order {
+    /// The order's request parameters, in Binance's names; `timestamp` and the signature are added when sending.
+    pub fn params(&self) -> anyhow::Result<Vec<(&'static str, String)>> {
+        let mut p = vec![
+            ("symbol", self.symbol.to_uppercase()),
+            ("side", match self.side { Side::Buy => "BUY", Side::Sell => "SELL" }.to_string()),
+            ("type", match self.kind { OrderType::Limit => "LIMIT", OrderType::Market => "MARKET" }.to_string()),
+            ("quantity", self.qty.clone()),
+            ("newClientOrderId", self.client_order_id.clone()),
+        ];
+        if self.kind == OrderType::Limit {
+            let price = self.price.clone().ok_or_else(|| anyhow::anyhow!("LIMIT order {} has no price", self.client_order_id))?;
+            p.push(("price", price));
+            p.push(("timeInForce", self.time_in_force.clone().unwrap_or_else(|| "GTC".into())));
+        }
+        Ok(p)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Command {
+    Place(Order),
+    Cancel { symbol: String, client_order_id: String },
+}
+
+/// One `executionReport` from the user data stream, or what exec itself
+/// knows of a command: rejected by the REST API, of unknown outcome, or as
+/// looked up afterwards.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Execution {
+    pub symbol: String,
+    pub client_order_id: String,
+    /// Binance's id; 0 for orders rejected before they had one.
+    pub order_id: i64,
+    pub side: String,
+    pub order_type: String,
+    /// `NEW`, `CANCELED`, `REPLACED`, `REJECTED`, `TRADE` or `EXPIRED` from
+    /// the exchange; `UNKNOWN` when a command's request was lost, then
+    /// `RECONCILED` once the order has been looked up.
+    pub exec_type: String,
+    pub status: String,
+    pub last_qty: f64,
+    pub last_price: f64,
+    pub cum_qty: f64,
+    pub commission: f64,
+    pub commission_asset: Option<String>,
+    /// -1 unless `exec_type` is `TRADE`.
+    pub trade_id: i64,
+    pub reason: Option<String>,
+    /// Event time, ms since the epoch.
+    pub ts_ms: i64,
+}
+
+#[derive(Deserialize)]
+struct Report {
+    #[serde(rename = "E")] event_ms: i64,
+    #[serde(rename = "s")] symbol: String,
+    #[serde(rename = "c")] client_order_id: String,
+    #[serde(rename = "C", default)] orig_client_order_id: String,
+    #[serde(rename = "S")] side: String,
+    #[serde(rename = "o")] order_type: String,
+    #[serde(rename = "x")] exec_type: String,
+    #[serde(rename = "X")] status: String,
+    #[serde(rename = "r")] reason: String,
+    #[serde(rename = "i")] order_id: i64,
+    #[serde(rename = "l")] last_qty: String,
+    #[serde(rename = "L")] last_price: String,
+    #[serde(rename = "z")] cum_qty: String,
+    #[serde(rename = "n")] commission: String,
+    #[serde(rename = "N")] commission_asset: Option<String>,
+    #[serde(rename = "t")] trade_id: i64,
+}
+
+/// `GET /api/v3/order`'s answer.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Queried {
+    symbol: String,
+    client_order_id: String,
+    order_id: i64,
+    side: String,
+    #[serde(rename = "type")]
+    order_type: String,
+    status: String,
+    executed_qty: String,
+    update_time: i64,
+}
+
+/// An `executionReport` from the user data stream; `None` for any other event.
+pub fn parse_user_event(payload: &str) -> anyhow::Result<Option<Execution>> {
+    let v: serde_json::Value = serde_json::from_str(payload)?;
+    // Events arrive bare on /ws/<listenKey>, or wrapped in `event` on newer endpoints.
+    let v = v.get("event").cloned().unwrap_or(v);
+    if v.get("e").and_then(|e| e.as_str()) != Some("executionReport") {
+        return Ok(None);
+    }
+    let r: Report = serde_json::from_value(v)?;
+    let num = |s: &str| s.parse::<f64>().unwrap_or(0.0);
+    Ok(Some(Execution {
+        // A cancel reports the cancel request's id in `c` and the order's in `C`.
+        client_order_id: if r.orig_client_order_id.is_empty() { r.client_order_id } else { r.orig_client_order_id },
+        symbol: r.symbol,
+        order_id: r.order_id,
+        side: r.side,
+        order_type: r.order_type,
+        exec_type: r.exec_type,
+        status: r.status,
+        last_qty: num(&r.last_qty),
+        last_price: num(&r.last_price),
+        cum_qty: num(&r.cum_qty),
+        commission: num(&r.commission),
+        commission_asset: r.commission_asset,
+        trade_id: r.trade_id,
+        reason: Some(r.reason).filter(|r| r != "NONE"),
+        ts_ms: r.event_ms,
+    }))
+}
+
+/// A `RECONCILED` execution from an order looked up by its client order id.
+pub fn parse_order(v: serde_json::Value) -> anyhow::Result<Execution> {
+    let q: Queried = serde_json::from_value(v)?;
+    Ok(Execution {
+        symbol: q.symbol,
+        client_order_id: q.client_order_id,
+        order_id: q.order_id,
+        side: q.side,
+        order_type: q.order_type,
+        exec_type: "RECONCILED".into(),
+        status: q.status,
+        last_qty: 0.0,
+        last_price: 0.0,
+        cum_qty: q.executed_qty.parse().unwrap_or(0.0),
+        commission: 0.0,
+        commission_asset: None,
+        trade_id: -1,
+        reason: None,
+        ts_ms: q.update_time,
+    })
+}
+
+impl Execution {
+    /// What's published when the REST API refuses a command.
+    pub fn rejected(symbol: &str, client_order_id: &str, reason: &str, ts_ms: i64) -> Self {
+        Self::of_command(symbol, client_order_id, "REJECTED", "REJECTED", reason, ts_ms)
+    }
+
+    /// What's published when a command's request may or may not have been
+    /// carried out; a `RECONCILED` or `REJECTED` one follows once it's known.
+    pub fn unknown(symbol: &str, client_order_id: &str, reason: &str, ts_ms: i64) -> Self {
+        Self::of_command(symbol, client_order_id, "UNKNOWN", "PENDING", reason, ts_ms)
+    }
+
+    fn of_command(symbol: &str, client_order_id: &str, exec_type: &str, status: &str, reason: &str, ts_ms: i64) -> Self {
+        Self {
+            symbol: symbol.to_uppercase(),
+            client_order_id: client_order_id.to_string(),
+            order_id: 0,
+            side: String::new(),
+            order_type: String::new(),
+            exec_type: exec_type.into(),
+            status: status.into(),
+            last_qty: 0.0,
+            last_price: 0.0,
+            cum_qty: 0.0,
+            commission: 0.0,
+            commission_asset: None,
+            trade_id: -1,
+            reason: Some(reason.to_string()),
+            ts_ms,
+        }
+    }
+}
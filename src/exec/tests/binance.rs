@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use exec::binance::{encode, no_such_order, sign, unknown, Refused, NO_SUCH_ORDER};
+use exec::order::{parse_order, parse_user_event, Command, Execution, Order};
+
+#[test]
+fn signature_matches_the_binance_docs_example() {
+    let secret = "NhqPtmdSJYdKjVHjA7PZj4Mge3R5YNiP1e3UZjInClVN65XAbvqqM6A7H5fATj0j";
+    let query = "symbol=LTCBTC&side=BUY&type=LIMIT&timeInForce=GTC&quantity=1&price=0.1&recvWindow=5000&timestamp=1499827319559";
+    assert_eq!(sign(secret, query), "c8db56825ae71d6d79447849e617115f4a920fa2acdcab2b053c4b2838bd6b71");
+}
+
+#[test]
+fn commands_become_order_params() {
+    let cmd: Command = serde_json::from_str(
+        r#"{"action":"place","symbol":"btcusdt","side":"BUY","type":"LIMIT","qty":"0.001","price":"60000.5","client_order_id":"s1-42"}"#,
+    ).unwrap();
+    let Command::Place(order) = cmd else { panic!("not a place") };
+    assert_eq!(
+        encode(&order.params().unwrap()),
+        "symbol=BTCUSDT&side=BUY&type=LIMIT&quantity=0.001&newClientOrderId=s1-42&price=60000.5&timeInForce=GTC"
+    );
+    let no_price = Order { price: None, ..order };
+    assert!(no_price.params().is_err());
+
+    let cmd: Command = serde_json::from_str(r#"{"action":"cancel","symbol":"BTCUSDT","client_order_id":"s1-42"}"#).unwrap();
+    assert_eq!(cmd, Command::Cancel { symbol: "BTCUSDT".into(), client_order_id: "s1-42".into() });
+}
+
+#[test]
+fn execution_reports_are_parsed_and_other_events_skipped() {
+    let fill = r#"{"e":"executionReport","E":1499405658658,"s":"ETHBTC","c":"mUvoqJxFIILMdfAW5iGSOW","S":"BUY","o":"LIMIT","f":"GTC",
+        "q":"1.00000000","p":"0.10264410","x":"TRADE","X":"PARTIALLY_FILLED","r":"NONE","i":4293153,"l":"0.40000000",
+        "z":"0.40000000","L":"0.10264410","n":"0.00040000","N":"ETH","T":1499405658657,"t":77,"C":""}"#;
+    let e = parse_user_event(fill).unwrap().unwrap();
+    assert_eq!((e.exec_type.as_str(), e.status.as_str(), e.trade_id, e.last_qty), ("TRADE", "PARTIALLY_FILLED", 77, 0.4));
+    assert_eq!((e.client_order_id.as_str(), e.reason, e.commission_asset.as_deref()), ("mUvoqJxFIILMdfAW5iGSOW", None, Some("ETH")));
+
+    let cancel = fill.replace(r#""x":"TRADE""#, r#""x":"CANCELED""#).replace(r#""c":"mUvoqJxFIILMdfAW5iGSOW""#, r#""c":"cancel-1""#)
+        .replace(r#""C":"""#, r#""C":"s1-42""#);
+    assert_eq!(parse_user_event(&cancel).unwrap().unwrap().client_order_id, "s1-42");
+
+    assert!(parse_user_event(r#"{"e":"outboundAccountPosition","E":1}"#).unwrap().is_none());
+}
+
+#[test]
+fn looked_up_orders_are_reconciled_executions() {
+    let order = serde_json::json!({"symbol": "BTCUSDT", "orderId": 28, "orderListId": -1, "clientOrderId": "s1-42", "price": "60000.50",
+        "origQty": "0.00100000", "executedQty": "0.00040000", "cummulativeQuoteQty": "24.00020000", "status": "PARTIALLY_FILLED",
+        "timeInForce": "GTC", "type": "LIMIT", "side": "BUY", "time": 1499827319559i64, "updateTime": 1499827320000i64, "isWorking": true});
+    let e = parse_order(order).unwrap();
+    assert_eq!((e.exec_type.as_str(), e.status.as_str(), e.order_id, e.cum_qty), ("RECONCILED", "PARTIALLY_FILLED", 28, 0.0004));
+    assert_eq!((e.client_order_id.as_str(), e.ts_ms), ("s1-42", 1499827320000));
+    let pending = Execution::unknown("btcusdt", "s1-42", "timed out", 1);
+    assert_eq!((pending.symbol.as_str(), pending.exec_type.as_str(), pending.status.as_str()), ("BTCUSDT", "UNKNOWN", "PENDING"));
+}
+
+#[tokio::test]
+async fn only_requests_that_may_have_landed_are_unknown() {
+    let refused = |status| anyhow::Error::new(Refused { status, code: -1013, msg: "Filter failure".into() });
+    assert!(!unknown(&refused(400)) && unknown(&refused(503)));
+    assert!(!unknown(&anyhow::anyhow!("LIMIT order s1-42 has no price")));
+    assert!(no_such_order(&anyhow::Error::new(Refused { status: 400, code: NO_SUCH_ORDER, msg: "Order does not exist.".into() })));
+
+    // A server that takes the request and never answers: it may have acted on it.
+    let silent = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let http = reqwest::Client::builder().timeout(Duration::from_millis(50)).build().unwrap();
+    let e = http.get(format!("http://{}/", silent.local_addr().unwrap())).send().await.unwrap_err();
+    assert!(unknown(&anyhow::Error::new(e).context("placing s1-42")));
+    // Nothing listening: nothing was sent.
+    let port = silent.local_addr().unwrap().port();
+    drop(silent);
+    let e = http.get(format!("http://127.0.0.1:{}/", port)).send().await.unwrap_err();
+    assert!(!unknown(&e.into()));
+}
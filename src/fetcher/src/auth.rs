@@ -0,0 +1,195 @@
+//! Who may push trades for which symbols.
+//!
+//! `INGEST_CLIENTS` lists one client per line (or per `;`): a name, a bearer
+//! token, and the symbols it may send, whitespace-separated.
+//!
+//! ```text
+//! # name     token         symbols
+//! desk-a     tok_3f9a1c    BTCUSDT,ETHUSDT
+//! partner-x  -             XBT*,ETH*
+//! relay      tok_77d0e2    *
+//! ```
+//!
+//! With mutual TLS, a client certificate whose common name is a client's
+//! name authenticates as that client; a token of `-` allows certificates
+//! only. Symbols are exact, a prefix ending in `*`, or `*` for all.
+//!
+//! The gateway reads its subscribers from `GATEWAY_CLIENTS` in the same
+//! format; there the symbols are the ones a client may be sent.
+
+use std::sync::{Arc, PoisonError, RwLock};
+
+use anyhow::{Context, Result};
+use axum::http::{header, HeaderMap};
+use secrets::Secrets;
+use subtle::ConstantTimeEq;
+
+/// One entry of a client's symbol list; symbols are kept uppercased.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    Any,
+    Exact(String),
+    Prefix(String),
+}
+
+impl Pattern {
+    fn parse(s: &str) -> Self {
+        match s.strip_suffix('*') {
+            Some("") => Self::Any,
+            Some(p) => Self::Prefix(p.to_uppercase()),
+            None => Self::Exact(s.to_uppercase()),
+        }
+    }
+
+    fn matches(&self, symbol: &str) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Exact(s) => s == symbol,
+            Self::Prefix(p) => symbol.starts_with(p.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    pub name: String,
+    token: Option<String>,
+    symbols: Vec<Pattern>,
+}
+
+impl Client {
+    /// Whether this client may send trades for `symbol` (any case).
+    pub fn allows(&self, symbol: &str) -> bool {
+        let symbol = symbol.to_uppercase();
+        self.symbols.iter().any(|p| p.matches(&symbol))
+    }
+
+    pub fn symbols(&self) -> &[Pattern] {
+        &self.symbols
+    }
+
+    /// Whether `token` is this client's, compared in constant time.
+    fn has_token(&self, token: &str) -> bool {
+        self.token.as_ref().is_some_and(|t| bool::from(t.as_bytes().ct_eq(token.as_bytes())))
+    }
+
+    /// Whether this client may send (or be sent) every symbol.
+    pub fn allows_all(&self) -> bool {
+        self.symbols.contains(&Pattern::Any)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Clients {
+    clients: Vec<Client>,
+}
+
+impl Clients {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut clients: Vec<Client> = Vec::new();
+        for line in spec.split(['\n', ';']).map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            let [name, token, symbols] = cols[..] else {
+                anyhow::bail!("client {:?} is not `name token symbols`", line);
+            };
+            anyhow::ensure!(clients.iter().all(|c| c.name != name), "client {} listed twice", name);
+            let token = Some(token).filter(|t| *t != "-").map(str::to_string);
+            if let Some(t) = &token {
+                anyhow::ensure!(clients.iter().all(|c| c.token.as_ref() != Some(t)), "client {} reuses a token", name);
+            }
+            let symbols: Vec<Pattern> = symbols.split(',').map(str::trim).filter(|s| !s.is_empty()).map(Pattern::parse).collect();
+            anyhow::ensure!(!symbols.is_empty(), "client {} has no symbols", name);
+            clients.push(Client { name: name.to_string(), token, symbols });
+        }
+        Ok(Self { clients })
+    }
+
+    /// Comma-separated tokens (`INGEST_TOKENS`): each a client allowed every
+    /// symbol, named by its position.
+    pub fn from_tokens(spec: &str) -> Result<Self> {
+        let clients: Vec<Client> = spec.split(',').map(str::trim).filter(|t| !t.is_empty()).enumerate()
+            .map(|(i, t)| Client { name: format!("token-{}", i + 1), token: Some(t.to_string()), symbols: vec![Pattern::Any] })
+            .collect();
+        anyhow::ensure!(!clients.is_empty(), "the token list has no tokens");
+        Ok(Self { clients })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// The client behind a certificate identity or a bearer token, the
+    /// certificate first.
+    pub fn authenticate(&self, identity: Option<&str>, token: Option<&str>) -> Option<&Client> {
+        identity.and_then(|id| self.clients.iter().find(|c| c.name == id))
+            .or_else(|| token.filter(|t| !t.is_empty()).and_then(|t| self.clients.iter().find(|c| c.has_token(t))))
+    }
+}
+
+/// The token in an `Authorization: Bearer <token>` header.
+pub fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// A client list that's swapped whole each time its secret is rotated.
+#[derive(Debug, Clone)]
+pub struct Live(Arc<RwLock<Arc<Clients>>>);
+
+impl Live {
+    pub fn new(clients: Clients) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(clients))))
+    }
+
+    pub fn current(&self) -> Arc<Clients> {
+        // Only ever swapped whole, so a list left by a panicking writer is still usable.
+        self.0.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    fn replace(&self, clients: Clients) {
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(clients);
+    }
+}
+
+/// The client list in secret `list`, or else every token in secret `tokens`
+/// (see [`Clients::from_tokens`]), kept current as either is rotated; `None`
+/// if neither is set. A rotated list that's empty or invalid is logged and
+/// the previous one kept.
+pub async fn watch(list: &'static str, tokens: Option<&'static str>) -> Result<Option<Live>> {
+    let secrets = Secrets::from_env()?;
+    let (mut source, name, parse): (_, &str, fn(&str) -> Result<Clients>) = match secrets.watch(list).await? {
+        Some(w) => (w, list, Clients::parse),
+        None => match tokens {
+            Some(t) => match secrets.watch(t).await? {
+                Some(w) => (w, t, Clients::from_tokens),
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        },
+    };
+    let first = parse(source.current().expose()).with_context(|| format!("parsing {}", name))?;
+    anyhow::ensure!(!first.is_empty(), "{} lists no clients", name);
+    tracing::info!(target: "auth", list = name, clients = first.len(), "clients loaded");
+    let live = Live::new(first);
+    let shared = live.clone();
+    tokio::spawn(async move {
+        loop {
+            let spec = source.rotated().await;
+            match parse(spec.expose()) {
+                Ok(c) if !c.is_empty() => {
+                    tracing::info!(target: "auth", list = name, clients = c.len(), "clients reloaded");
+                    shared.replace(c);
+                }
+                Ok(_) => tracing::warn!(target: "auth", list = name, "rotated client list is empty; keeping the previous one"),
+                Err(e) => tracing::warn!(target: "auth", list = name, error = ?e, "rotated client list is invalid; keeping the previous one"),
+            }
+        }
+    });
+    Ok(Some(live))
+}
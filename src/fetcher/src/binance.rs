@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use metrics::counter;
+use obsv::errors::{self, ErrorKind};
+use retry::{Backoff, Policy};
+use tokio_tungstenite::connect_async_with_config;
+
+use fetcher::recv::{self, Frames, Subscription};
+use fetcher::status;
+
+use crate::raw::RawSink;
+use crate::ws;
+
+/// Which of Binance's markets `MARKET` names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Market {
+    Spot,
+    /// USDⓈ-margined futures (`fstream`): linear, `q` in coins.
+    UsdM,
+    /// Coin-margined futures (`dstream`): inverse, `q` in contracts.
+    CoinM,
+}
+
+impl Market {
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s {
+            "spot" => Market::Spot,
+            "usdm" => Market::UsdM,
+            "coinm" => Market::CoinM,
+            other => anyhow::bail!("unknown MARKET {:?} for binance (expected spot|usdm|coinm)", other),
+        })
+    }
+
+    pub(crate) fn ws_base(self) -> &'static str {
+        match self {
+            Market::Spot => "wss://stream.binance.com:9443/ws",
+            Market::UsdM => "wss://fstream.binance.com/ws",
+            Market::CoinM => "wss://dstream.binance.com/ws",
+        }
+    }
+}
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Stream one of a symbol's Binance streams (`trade`, `bookTicker`,
+/// `depth@100ms`, `indexPrice`) on `market` into the raw sink, reconnecting forever.
+pub async fn run_symbol(sink: RawSink, market: Market, symbol: String, stream_name: &'static str) -> Result<()> {
+    let ws_url = format!("{}/{}@{}", market.ws_base(), symbol, stream_name);
+    let frames = Frames::new("binance", stream_name);
+    let sub = recv::subscribe("binance", stream_name, &symbol);
+    let policy = Policy::forever(Backoff::new(MIN_BACKOFF, MAX_BACKOFF));
+    let mut retry = policy.start("ws_connect");
+
+    loop {
+        match stream(&sink, &symbol, &ws_url, &frames, &sub).await {
+            // We were connected: start the backoff over.
+            Ok(()) => retry.succeeded(),
+            Err(e) => {
+                tracing::error!(target: "fetcher", symbol = %symbol, error = ?e, "websocket connect failed");
+                errors::record("fetcher", ErrorKind::Transient);
+            }
+        }
+        counter!("ws_reconnects_total").increment(1);
+        retry.pause().await;
+    }
+}
+
+/// One connection's lifetime; `Ok` once it was established and then ended.
+async fn stream(sink: &RawSink, symbol: &str, ws_url: &str, frames: &Frames, sub: &Subscription) -> Result<()> {
+    let (ws_stream, _) = connect_async_with_config(ws_url, ws::config(sink.limit()), false).await?;
+    tracing::info!(target: "fetcher", "connected to {}", ws_url);
+    status::connected("binance");
+    let (_w, mut r) = ws_stream.split();
+
+    let disconnect = chaos::ws_disconnect();
+    tokio::pin!(disconnect);
+
+    loop {
+        let msg = tokio::select! {
+            m = r.next() => match m {
+                Some(m) => m,
+                None => break,
+            },
+            _ = &mut disconnect => {
+                tracing::warn!(target: "fetcher", symbol = %symbol, "chaos: forcing websocket disconnect");
+                return Ok(());
+            }
+        };
+        let msg = match msg {
+            Ok(m) => m,
+            // The socket can't be read past it; a fresh connection skips it.
+            Err(e) if ws::oversized(&e) => {
+                counter!("payload_oversize_total", "stage" => "fetcher", "action" => "reconnect").increment(1);
+                return Err(anyhow::Error::new(e).context("websocket message over MAX_PAYLOAD_BYTES"));
+            }
+            Err(e) => {
+                tracing::error!(target:"fetcher", error=?e, "websocket error");
+                errors::record("fetcher", ErrorKind::Transient);
+                continue;
+            }
+        };
+        if !msg.is_text() { continue; }
+
+        let payload = msg.into_text().unwrap_or_default();
+        frames.record(payload.len());
+        if let Some(notice) = status::binance_notice(&payload) {
+            status::notice("binance", notice);
+            continue;
+        }
+        sub.received();
+        sink.send(symbol, payload.as_bytes()).await;
+    }
+
+    tracing::warn!(target: "fetcher", symbol = %symbol, "websocket stream ended");
+    Ok(())
+}
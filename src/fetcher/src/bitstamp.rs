@@ -0,0 +1,67 @@
+//! Bitstamp trades: one `live_trades_<pair>` channel per symbol on the v2
+//! WebSocket.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::venue::{Connection, ExchangeSource, Pair};
+
+const CHANNEL_PREFIX: &str = "live_trades_";
+
+pub struct Bitstamp {
+    url: String,
+}
+
+impl Bitstamp {
+    pub fn new(url: &str) -> Self {
+        Self { url: url.to_string() }
+    }
+
+    /// `BITSTAMP_WS_URL`, default `wss://ws.bitstamp.net`.
+    pub fn from_env() -> Self {
+        Self::new(&std::env::var("BITSTAMP_WS_URL").unwrap_or_else(|_| "wss://ws.bitstamp.net".into()))
+    }
+}
+
+#[async_trait]
+impl ExchangeSource for Bitstamp {
+    fn name(&self) -> &'static str {
+        "bitstamp"
+    }
+
+    fn endpoint(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn connect(&self, _pairs: &[Pair]) -> Result<Connection> {
+        Ok(Connection { url: self.url.clone(), ping_every: Some(Duration::from_secs(30)) })
+    }
+
+    fn subscribe(&self, pairs: &[Pair]) -> Vec<String> {
+        pairs.iter()
+            .map(|p| json!({ "event": "bts:subscribe", "data": { "channel": format!("{}{}", CHANNEL_PREFIX, p.key()) } }).to_string())
+            .collect()
+    }
+
+    fn ping(&self) -> Option<String> {
+        Some(json!({ "event": "bts:heartbeat" }).to_string())
+    }
+
+    fn trade_key(&self, frame: &str) -> Option<String> {
+        let v: Value = serde_json::from_str(frame).ok()?;
+        if v.get("event")?.as_str()? != "trade" {
+            return None;
+        }
+        let pair = v.get("channel")?.as_str()?.strip_prefix(CHANNEL_PREFIX)?;
+        Some(pair.to_lowercase())
+    }
+
+    /// Sent before the server we're on is taken down for maintenance.
+    fn maintenance_notice(&self, frame: &str) -> Option<String> {
+        let v: Value = serde_json::from_str(frame).ok()?;
+        (v.get("event")?.as_str()? == "bts:request_reconnect").then(|| "reconnect requested".to_string())
+    }
+}
@@ -0,0 +1,212 @@
+//! How far each exchange's clock is from ours, because latency metrics like
+//! `watermark_lag_ms` and the idle closes downstream compare the venue's
+//! trade times with local time and are only as good as the clocks agree.
+//!
+//! A [`Poll`] asks the venue's server-time endpoint (see [`Format`]) and
+//! takes the NTP-style estimate: offset = server − midpoint of the request,
+//! good to half its round trip. [`Tracker`] smooths the offsets and works out
+//! the drift from them. Each exchange's estimate sets
+//! `exchange_clock_offset_ms{exchange}` (positive when the venue is ahead),
+//! `exchange_clock_rtt_ms{exchange}` and `exchange_clock_drift_ppm{exchange}`,
+//! and [`offset_ms`] hands it to the raw sink, which stamps it on each message
+//! so the producer can correct `ts_ms` if asked to.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use metrics::{counter, gauge};
+use serde_json::Value;
+
+/// Samples whose round trip took longer than this say too little to use.
+pub const MAX_RTT_MS: i64 = 2_000;
+/// Weight of each new sample in the smoothed offset.
+pub const SMOOTHING: f64 = 0.2;
+/// Drift is only reported once the samples span this long.
+pub const DRIFT_AFTER_MS: i64 = 10 * 60 * 1000;
+
+/// Where the server's time is in a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A field of the JSON body, as a JSON pointer: `/serverTime` (Binance),
+    /// `/data` (KuCoin), `/server_time` (Gate).
+    Json(&'static str),
+    /// The HTTP `Date` header, for venues without a time endpoint.
+    Date,
+}
+
+impl Format {
+    /// The server's time in Unix milliseconds, from the response's `Date`
+    /// header and `body`.
+    pub fn parse(self, date: Option<&str>, body: &str) -> Result<i64> {
+        match self {
+            Format::Json(pointer) => {
+                let v: Value = serde_json::from_str(body).context("bad server time response")?;
+                millis(v.pointer(pointer).with_context(|| format!("no {} in server time response", pointer))?)
+            }
+            Format::Date => {
+                let date = date.context("no Date header")?;
+                let t = DateTime::parse_from_rfc2822(date).with_context(|| format!("bad Date header {:?}", date))?;
+                // Whole seconds, cut: on average the server was half a second further on.
+                Ok(t.timestamp_millis() + 500)
+            }
+        }
+    }
+}
+
+/// Unix milliseconds as a number or in a string, or an RFC 3339 time. Times
+/// must say their zone; a bare local time could be off by hours.
+pub fn millis(v: &Value) -> Result<i64> {
+    match v {
+        Value::Number(n) => n.as_i64().context("server time isn't whole milliseconds"),
+        Value::String(s) if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) => Ok(s.parse()?),
+        Value::String(s) => Ok(DateTime::parse_from_rfc3339(s)
+            .with_context(|| format!("server time {:?} isn't Unix milliseconds or RFC 3339 with a zone", s))?
+            .timestamp_millis()),
+        other => anyhow::bail!("server time {} isn't a time", other),
+    }
+}
+
+/// The time endpoint polled by default for `source` on `market`. Other venues
+/// are read from their `Date` header once `CLOCK_URL` points somewhere.
+pub fn endpoint(source: &str, market: &str) -> Option<(String, Format)> {
+    match (source, market) {
+        ("binance", "spot") => Some(("https://api.binance.com/api/v3/time".into(), Format::Json("/serverTime"))),
+        ("binance", "usdm") => Some(("https://fapi.binance.com/fapi/v1/time".into(), Format::Json("/serverTime"))),
+        ("binance", "coinm") => Some(("https://dapi.binance.com/dapi/v1/time".into(), Format::Json("/serverTime"))),
+        ("kucoin", _) => Some(("https://api.kucoin.com/api/v1/timestamp".into(), Format::Json("/data"))),
+        ("gate", _) => Some(("https://api.gateio.ws/api/v4/spot/time".into(), Format::Json("/server_time"))),
+        _ => None,
+    }
+}
+
+/// One measurement: `server` was read between local times `sent` and `received`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub offset_ms: f64,
+    pub rtt_ms: i64,
+}
+
+impl Sample {
+    pub fn new(sent_ms: i64, server_ms: i64, received_ms: i64) -> Self {
+        let rtt_ms = (received_ms - sent_ms).max(0);
+        Self { offset_ms: server_ms as f64 - (sent_ms as f64 + rtt_ms as f64 / 2.0), rtt_ms }
+    }
+}
+
+/// An exchange's clock as last estimated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// Venue minus local, smoothed.
+    pub offset_ms: f64,
+    pub rtt_ms: i64,
+    /// How fast the offset moves, in parts per million; `None` until the
+    /// samples span [`DRIFT_AFTER_MS`].
+    pub drift_ppm: Option<f64>,
+}
+
+struct Clock {
+    estimate: Estimate,
+    /// The first accepted sample's local time and offset, for the drift.
+    first: (i64, f64),
+}
+
+/// Every exchange's [`Estimate`].
+#[derive(Default)]
+pub struct Tracker {
+    clocks: HashMap<String, Clock>,
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn estimate(&self, exchange: &str) -> Option<Estimate> {
+        self.clocks.get(exchange).map(|c| c.estimate)
+    }
+
+    /// Fold in `sample`, taken at local time `now_ms`. Samples over
+    /// [`MAX_RTT_MS`] are left out; the estimate is `None` then.
+    pub fn observe(&mut self, exchange: &str, sample: Sample, now_ms: i64) -> Option<Estimate> {
+        if sample.rtt_ms > MAX_RTT_MS {
+            return None;
+        }
+        let clock = self.clocks.entry(exchange.to_string()).or_insert_with(|| Clock {
+            estimate: Estimate { offset_ms: sample.offset_ms, rtt_ms: sample.rtt_ms, drift_ppm: None },
+            first: (now_ms, sample.offset_ms),
+        });
+        let e = &mut clock.estimate;
+        e.offset_ms += SMOOTHING * (sample.offset_ms - e.offset_ms);
+        e.rtt_ms = sample.rtt_ms;
+        let (since, first_offset) = clock.first;
+        let span = now_ms - since;
+        e.drift_ppm = (span >= DRIFT_AFTER_MS).then(|| (e.offset_ms - first_offset) / span as f64 * 1e6);
+        Some(*e)
+    }
+}
+
+fn global() -> &'static Mutex<Tracker> {
+    static TRACKER: OnceLock<Mutex<Tracker>> = OnceLock::new();
+    TRACKER.get_or_init(Default::default)
+}
+
+/// `exchange`'s clock minus ours, rounded; `None` until it's been measured.
+pub fn offset_ms(exchange: &str) -> Option<i64> {
+    global().lock().unwrap_or_else(PoisonError::into_inner).estimate(exchange).map(|e| e.offset_ms.round() as i64)
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// A server-time endpoint to poll.
+#[derive(Debug, Clone)]
+pub struct Poll {
+    pub exchange: String,
+    pub url: String,
+    pub format: Format,
+    pub every: Duration,
+}
+
+async fn measure(http: &reqwest::Client, poll: &Poll) -> Result<Sample> {
+    let limiter = ratelimit::shared(&poll.exchange)?;
+    let sent = now_ms();
+    let resp = limiter.send(http.get(&poll.url), 1).await?.error_for_status()?;
+    let received = now_ms();
+    let date = resp.headers().get(reqwest::header::DATE).and_then(|d| d.to_str().ok()).map(String::from);
+    let body = resp.text().await?;
+    Ok(Sample::new(sent, poll.format.parse(date.as_deref(), &body)?, received))
+}
+
+/// Poll `poll` from now on, keeping [`offset_ms`] and the gauges current.
+pub fn spawn(poll: Poll) -> Result<()> {
+    let http = reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?;
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(poll.every);
+        tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tick.tick().await;
+            let sample = match measure(&http, &poll).await {
+                Ok(s) => s,
+                Err(e) => {
+                    tracing::warn!(target: "fetcher", exchange = %poll.exchange, error = ?e, "server time poll failed");
+                    counter!("exchange_clock_poll_errors_total", "exchange" => poll.exchange.clone()).increment(1);
+                    continue;
+                }
+            };
+            let Some(e) = global().lock().unwrap_or_else(PoisonError::into_inner).observe(&poll.exchange, sample, now_ms()) else {
+                counter!("exchange_clock_samples_rejected_total", "exchange" => poll.exchange.clone()).increment(1);
+                continue;
+            };
+            gauge!("exchange_clock_offset_ms", "exchange" => poll.exchange.clone()).set(e.offset_ms);
+            gauge!("exchange_clock_rtt_ms", "exchange" => poll.exchange.clone()).set(e.rtt_ms as f64);
+            if let Some(ppm) = e.drift_ppm {
+                gauge!("exchange_clock_drift_ppm", "exchange" => poll.exchange.clone()).set(ppm);
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! `SOURCE=fix`: one FIX 4.4 market-data session for all of the shard's
+//! symbols, reconnecting forever. The protocol itself is in [`fetcher::fix`].
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use chrono::{NaiveDateTime, Utc};
+use fetcher::fix::{self, msg_type, tag, Message, Session, Skipped};
+use metrics::counter;
+use retry::{Backoff, Policy};
+use secrets::{Secrets, Watched};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::env;
+use crate::raw::RawSink;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+struct Config {
+    addr: String,
+    sender: String,
+    target: String,
+    heartbeat: Duration,
+    username: Option<String>,
+    /// Read again for every logon, so a rotated password takes effect on the next reconnect.
+    password: Option<Watched>,
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+pub async fn run(sink: RawSink, symbols: Vec<String>) -> Result<()> {
+    let secrets = Secrets::from_env()?;
+    let config = Config {
+        addr: env("FIX_ADDR", "localhost:9878"),
+        sender: std::env::var("FIX_SENDER_COMP_ID").context("SOURCE=fix requires FIX_SENDER_COMP_ID")?,
+        target: std::env::var("FIX_TARGET_COMP_ID").context("SOURCE=fix requires FIX_TARGET_COMP_ID")?,
+        heartbeat: Duration::from_secs(env("FIX_HEARTBEAT_SECS", "30").parse().unwrap_or(30).max(1)),
+        username: std::env::var("FIX_USERNAME").ok().filter(|u| !u.is_empty()),
+        password: secrets.watch("FIX_PASSWORD").await?,
+    };
+    // FIX symbols are case-sensitive, and SYMBOLS arrives lower-cased.
+    let symbols: Vec<String> = symbols.iter().map(|s| s.to_uppercase()).collect();
+
+    let policy = Policy::forever(Backoff::new(MIN_BACKOFF, MAX_BACKOFF));
+    let mut retry = policy.start("fix_session");
+    loop {
+        match run_session(&config, &sink, &symbols).await {
+            Ok(()) => retry.succeeded(),
+            Err(e) => tracing::error!(target: "fetcher", addr = %config.addr, error = ?e, "fix session failed"),
+        }
+        counter!("fix_reconnects_total").increment(1);
+        retry.pause().await;
+    }
+}
+
+/// One session's lifetime; `Ok` once it was logged on and then ended.
+async fn run_session(config: &Config, sink: &RawSink, symbols: &[String]) -> Result<()> {
+    let mut stream = TcpStream::connect(&config.addr).await?;
+    let mut session = Session::new(&config.sender, &config.target);
+    let password = config.password.as_ref().map(Watched::current);
+    let credentials = config.username.as_deref().zip(password.as_ref().map(|p| p.expose()));
+    stream.write_all(&session.logon(config.heartbeat.as_secs(), credentials, now())).await?;
+
+    let mut buf = Vec::with_capacity(64 * 1024);
+    let mut chunk = vec![0u8; 16 * 1024];
+    let mut logged_on = false;
+    let mut next_seq = 1;
+    let mut last_in = Instant::now();
+    let mut heartbeat = tokio::time::interval(config.heartbeat);
+    heartbeat.tick().await;
+
+    loop {
+        while let Some(msg) = fix::decode(&mut buf)? {
+            counter!("fix_messages_total", "msg_type" => msg.msg_type().to_string()).increment(1);
+            if let Some(seq) = msg.seq_num() {
+                if seq > next_seq {
+                    tracing::warn!(target: "fetcher", expected = next_seq, got = seq, "fix sequence gap");
+                    counter!("fix_seq_gaps_total").increment(seq - next_seq);
+                }
+                next_seq = seq + 1;
+            }
+            match msg.msg_type() {
+                msg_type::LOGON => {
+                    logged_on = true;
+                    tracing::info!(target: "fetcher", addr = %config.addr, symbols = symbols.len(), "fix logged on, subscribing");
+                    stream.write_all(&session.market_data_request("trades", symbols, now())).await?;
+                }
+                msg_type::TEST_REQUEST => {
+                    stream.write_all(&session.heartbeat(msg.get(tag::TEST_REQ_ID), now())).await?;
+                }
+                msg_type::MARKET_DATA_INCREMENTAL_REFRESH => publish(sink, &msg).await,
+                msg_type::MARKET_DATA_REQUEST_REJECT => {
+                    anyhow::bail!("market data request rejected: {}", msg.get(tag::TEXT).unwrap_or("no reason given"));
+                }
+                msg_type::LOGOUT => {
+                    tracing::warn!(target: "fetcher", text = msg.get(tag::TEXT).unwrap_or(""), "fix logout from counterparty");
+                    // Answering is a courtesy; the session is over either way.
+                    let _ = stream.write_all(&session.logout(now())).await;
+                    anyhow::ensure!(logged_on, "logon refused: {}", msg.get(tag::TEXT).unwrap_or("no reason given"));
+                    return Ok(());
+                }
+                msg_type::REJECT => {
+                    tracing::warn!(target: "fetcher", text = msg.get(tag::TEXT).unwrap_or(""), "fix session-level reject");
+                }
+                _ => {}
+            }
+        }
+
+        tokio::select! {
+            n = stream.read(&mut chunk) => {
+                let n = n?;
+                if n == 0 {
+                    anyhow::ensure!(logged_on, "connection closed before logon");
+                    tracing::warn!(target: "fetcher", "fix connection closed");
+                    return Ok(());
+                }
+                buf.extend_from_slice(&chunk[..n]);
+                last_in = Instant::now();
+            }
+            _ = heartbeat.tick() => {
+                // The counterparty heartbeats at the same interval; two missed and it's gone.
+                anyhow::ensure!(last_in.elapsed() < config.heartbeat * 2 + config.heartbeat / 2, "counterparty went silent");
+                stream.write_all(&session.heartbeat(None, now())).await?;
+            }
+        }
+    }
+}
+
+async fn publish(sink: &RawSink, msg: &Message) {
+    let (trades, skipped) = fix::trades(msg);
+    for (key, payload) in trades {
+        sink.send(&key, payload.as_bytes()).await;
+    }
+    for s in skipped.into_iter().filter(|s| *s != Skipped::NotATrade) {
+        counter!("fix_entries_skipped_total", "reason" => s.as_str()).increment(1);
+    }
+}
@@ -0,0 +1,61 @@
+//! Gate.io spot trades: `spot.trades` on the v4 WebSocket.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::venue::{Connection, ExchangeSource, Pair};
+
+pub struct Gate {
+    url: String,
+}
+
+impl Gate {
+    pub fn new(url: &str) -> Self {
+        Self { url: url.to_string() }
+    }
+
+    /// `GATE_WS_URL`, default `wss://api.gateio.ws/ws/v4/`.
+    pub fn from_env() -> Self {
+        Self::new(&std::env::var("GATE_WS_URL").unwrap_or_else(|_| "wss://api.gateio.ws/ws/v4/".into()))
+    }
+}
+
+fn now_secs() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+#[async_trait]
+impl ExchangeSource for Gate {
+    fn name(&self) -> &'static str {
+        "gate"
+    }
+
+    fn endpoint(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn connect(&self, _pairs: &[Pair]) -> Result<Connection> {
+        // Gate drops connections that stay quiet, whatever the trade flow.
+        Ok(Connection { url: self.url.clone(), ping_every: Some(Duration::from_secs(15)) })
+    }
+
+    fn subscribe(&self, pairs: &[Pair]) -> Vec<String> {
+        let payload: Vec<String> = pairs.iter().map(|p| p.join("_")).collect();
+        vec![json!({ "time": now_secs(), "channel": "spot.trades", "event": "subscribe", "payload": payload }).to_string()]
+    }
+
+    fn ping(&self) -> Option<String> {
+        Some(json!({ "time": now_secs(), "channel": "spot.ping" }).to_string())
+    }
+
+    fn trade_key(&self, frame: &str) -> Option<String> {
+        let v: Value = serde_json::from_str(frame).ok()?;
+        if v.get("channel")?.as_str()? != "spot.trades" || v.get("event")?.as_str()? != "update" {
+            return None;
+        }
+        Pair::parse(v.get("result")?.get("currency_pair")?.as_str()?).ok().map(|p| p.key())
+    }
+}
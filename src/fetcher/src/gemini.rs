@@ -0,0 +1,52 @@
+//! Gemini trades from the v2 market data WebSocket. Trades only come with an
+//! `l2` subscription, so book updates arrive too and are dropped here.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::venue::{Connection, ExchangeSource, Pair};
+
+pub struct Gemini {
+    url: String,
+}
+
+impl Gemini {
+    pub fn new(url: &str) -> Self {
+        Self { url: url.to_string() }
+    }
+
+    /// `GEMINI_WS_URL`, default `wss://api.gemini.com/v2/marketdata`.
+    pub fn from_env() -> Self {
+        Self::new(&std::env::var("GEMINI_WS_URL").unwrap_or_else(|_| "wss://api.gemini.com/v2/marketdata".into()))
+    }
+}
+
+#[async_trait]
+impl ExchangeSource for Gemini {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn endpoint(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn connect(&self, _pairs: &[Pair]) -> Result<Connection> {
+        // Gemini sends heartbeats of its own and wants none back.
+        Ok(Connection { url: self.url.clone(), ping_every: None })
+    }
+
+    fn subscribe(&self, pairs: &[Pair]) -> Vec<String> {
+        let symbols: Vec<String> = pairs.iter().map(|p| p.join("")).collect();
+        vec![json!({ "type": "subscribe", "subscriptions": [{ "name": "l2", "symbols": symbols }] }).to_string()]
+    }
+
+    fn trade_key(&self, frame: &str) -> Option<String> {
+        let v: Value = serde_json::from_str(frame).ok()?;
+        if v.get("type")?.as_str()? != "trade" {
+            return None;
+        }
+        Some(v.get("symbol")?.as_str()?.to_lowercase())
+    }
+}
@@ -0,0 +1,165 @@
+//! Just enough gRPC to serve a few methods from an axum router.
+//!
+//! A method is a `POST /<package>.<Service>/<Method>` over HTTP/2. Each
+//! message in either direction is framed as a compressed flag byte (always 0
+//! here: we don't advertise `grpc-accept-encoding`), a big-endian `u32`
+//! length and the protobuf bytes. The call's outcome goes in the
+//! `grpc-status` and `grpc-message` trailers, or straight in the headers
+//! when nothing else is sent ("trailers-only"). Messages are `prost` types
+//! derived by hand, so any stock gRPC client generated from the `.proto`
+//! files under `proto/` can call in.
+
+use std::convert::Infallible;
+
+use axum::body::Body;
+use axum::http::{HeaderMap, HeaderValue};
+use axum::response::Response;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_util::{Stream, StreamExt};
+use http_body_util::StreamBody;
+use http_body::Frame;
+use prost::Message;
+
+/// Largest message taken or sent, gRPC's usual default.
+pub const MAX_MESSAGE_BYTES: usize = 4 << 20;
+
+/// A gRPC status code; only the ones these services answer with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    Ok = 0,
+    InvalidArgument = 3,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    Internal = 13,
+    Unavailable = 14,
+    Unauthenticated = 16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub code: Code,
+    pub message: String,
+}
+
+impl Status {
+    pub fn new(code: Code, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    fn headers(&self) -> HeaderMap {
+        let mut h = HeaderMap::new();
+        h.insert("grpc-status", HeaderValue::from(self.code as i32));
+        if !self.message.is_empty() {
+            if let Ok(v) = HeaderValue::from_str(&percent_encode(&self.message)) {
+                h.insert("grpc-message", v);
+            }
+        }
+        h
+    }
+}
+
+/// `grpc-message` is percent-encoded: anything outside printable ASCII, and `%`.
+fn percent_encode(s: &str) -> String {
+    s.bytes().map(|b| match b {
+        b' '..=b'~' if b != b'%' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+/// One framed message.
+pub fn frame<M: Message>(m: &M) -> Bytes {
+    let body = m.encode_to_vec();
+    let mut out = BytesMut::with_capacity(5 + body.len());
+    out.put_u8(0);
+    out.put_u32(body.len() as u32);
+    out.extend_from_slice(&body);
+    out.freeze()
+}
+
+/// Splits a request body back into messages as its chunks arrive.
+#[derive(Default)]
+pub struct Deframer {
+    buf: BytesMut,
+}
+
+impl Deframer {
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// The next whole message, if one is buffered.
+    pub fn message<M: Message + Default>(&mut self) -> Result<Option<M>, Status> {
+        if self.buf.len() < 5 {
+            return Ok(None);
+        }
+        if self.buf[0] != 0 {
+            return Err(Status::new(Code::InvalidArgument, "compressed messages aren't accepted"));
+        }
+        let len = u32::from_be_bytes([self.buf[1], self.buf[2], self.buf[3], self.buf[4]]) as usize;
+        if len > MAX_MESSAGE_BYTES {
+            return Err(Status::new(Code::ResourceExhausted, format!("a {} byte message is over the {} allowed", len, MAX_MESSAGE_BYTES)));
+        }
+        if self.buf.len() < 5 + len {
+            return Ok(None);
+        }
+        self.buf.advance(5);
+        let body = self.buf.split_to(len);
+        M::decode(body).map(Some).map_err(|e| Status::new(Code::InvalidArgument, format!("bad message: {}", e)))
+    }
+
+    /// The body ended: a part message left over is an error.
+    pub fn finish(&self) -> Result<(), Status> {
+        match self.buf.is_empty() {
+            true => Ok(()),
+            false => Err(Status::new(Code::InvalidArgument, "the stream ended inside a message")),
+        }
+    }
+}
+
+/// The one message of a unary call's request body.
+pub async fn request<M: Message + Default>(body: Body) -> Result<M, Status> {
+    let (mut frames, mut chunks) = (Deframer::default(), body.into_data_stream());
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(|e| Status::new(Code::Internal, format!("request body aborted: {}", e)))?;
+        frames.push(&chunk);
+        if let Some(m) = frames.message()? {
+            return match frames.buf.is_empty() {
+                true => Ok(m),
+                false => Err(Status::new(Code::InvalidArgument, "a unary call takes one message")),
+            };
+        }
+    }
+    frames.finish()?;
+    Err(Status::new(Code::InvalidArgument, "the request has no message"))
+}
+
+fn response(body: Body) -> Response {
+    let mut resp = Response::new(body);
+    resp.headers_mut().insert("content-type", HeaderValue::from_static("application/grpc"));
+    resp
+}
+
+/// Trailers-only: the call ends with `status` and no messages.
+pub fn refuse(status: Status) -> Response {
+    let mut resp = response(Body::empty());
+    resp.headers_mut().extend(status.headers());
+    resp
+}
+
+/// One message, then OK.
+pub fn unary<M: Message>(m: &M) -> Response {
+    streaming(futures_util::stream::iter([Ok(frame(m))]))
+}
+
+/// Each framed message as it comes, then OK, or the first error as the status.
+pub fn streaming(messages: impl Stream<Item = Result<Bytes, Status>> + Send + 'static) -> Response {
+    let frames = futures_util::stream::unfold(Some(Box::pin(messages)), |state| async move {
+        let mut messages = state?;
+        Some(match messages.next().await {
+            Some(Ok(msg)) => (Frame::data(msg), Some(messages)),
+            Some(Err(status)) => (Frame::trailers(status.headers()), None),
+            None => (Frame::trailers(Status::new(Code::Ok, "").headers()), None),
+        })
+    });
+    response(Body::new(StreamBody::new(frames.map(Ok::<_, Infallible>))))
+}
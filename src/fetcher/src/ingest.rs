@@ -0,0 +1,241 @@
+//! Push-ingestion server for venues we can't connect to directly.
+//!
+//! `POST /v1/trades` takes one raw trade as JSON, or many as NDJSON streamed
+//! line by line in a chunked body. Each request is from a client in
+//! `INGEST_CLIENTS` (see [`fetcher::auth`]), known by its TLS client
+//! certificate or an `Authorization: Bearer <token>`, and may only carry the
+//! symbols that client is allowed. The plain `INGEST_TOKENS` list still
+//! works, with every token allowed every symbol. Both can come from a file
+//! or a secret store and are re-read, so clients can be changed live.
+//! The symbol comes from `?symbol=` or the payload's `s` field; each line
+//! goes out in the usual raw envelope.
+//!
+//! The same port serves `ingest.v1.Ingest/Push` (`proto/ingest.proto`) over
+//! HTTP/2 for clients that would rather stream protobuf `Trade`s, each with
+//! the same JSON payload. A trade the broker didn't take fails the request
+//! either way (503, or gRPC `UNAVAILABLE`) so the client sends it again.
+
+use anyhow::{Context, Result};
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::{Json, Router};
+use fetcher::auth::{self, bearer, Client, Live};
+use fetcher::grpc::{self, Code, Deframer, Status};
+use fetcher::tls::{Peer, TlsListener, TlsOptions};
+use axum::response::Response;
+use futures_util::StreamExt;
+use metrics::counter;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::env;
+use crate::raw::RawSink;
+
+/// One trade per line; anything longer is not a trade.
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+#[derive(Clone)]
+struct IngestState {
+    sink: RawSink,
+    clients: Live,
+}
+
+/// `ingest.v1.Trade`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Trade {
+    #[prost(string, tag = "1")]
+    pub symbol: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub payload: Vec<u8>,
+}
+
+/// `ingest.v1.PushSummary`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PushSummary {
+    #[prost(uint64, tag = "1")]
+    pub accepted: u64,
+    #[prost(uint64, tag = "2")]
+    pub rejected: u64,
+    #[prost(uint64, tag = "3")]
+    pub denied: u64,
+}
+
+#[derive(Deserialize)]
+struct IngestQuery {
+    symbol: Option<String>,
+}
+
+enum Line {
+    Accepted,
+    Rejected,
+    /// A trade for a symbol the client may not send.
+    Denied,
+    /// A usable trade the broker didn't take.
+    Failed,
+}
+
+/// Publish one line if it's a usable trade the client may send.
+async fn ingest_line(st: &IngestState, client: &Client, symbol: Option<&str>, line: &[u8]) -> Line {
+    let line = line.trim_ascii();
+    if line.is_empty() {
+        return Line::Accepted;
+    }
+    if line.len() > MAX_LINE_BYTES {
+        return Line::Rejected;
+    }
+    let Ok(v) = serde_json::from_slice::<Value>(line) else { return Line::Rejected };
+    let Some(symbol) = symbol.or_else(|| v.get("s").and_then(Value::as_str)) else { return Line::Rejected };
+    if !client.allows(symbol) {
+        return Line::Denied;
+    }
+    match st.sink.deliver(&symbol.to_lowercase(), line).await {
+        Ok(()) => Line::Accepted,
+        Err(_) => Line::Failed,
+    }
+}
+
+async fn trades(
+    State(st): State<IngestState>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
+    Query(q): Query<IngestQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> (StatusCode, Json<Value>) {
+    let clients = st.clients.current();
+    let Some(client) = clients.authenticate(peer.identity.as_deref(), bearer(&headers)) else {
+        counter!("ingest_unauthorized_total").increment(1);
+        tracing::debug!(target: "fetcher", addr = %peer.addr, identity = ?peer.identity, "unauthorized ingest request");
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "missing or invalid credentials" })));
+    };
+    if let Some(symbol) = q.symbol.as_deref().filter(|s| !client.allows(s)) {
+        counter!("ingest_denied_total", "client" => client.name.clone()).increment(1);
+        return (StatusCode::FORBIDDEN, Json(json!({ "error": format!("{} may not send {}", client.name, symbol) })));
+    }
+
+    let (mut accepted, mut rejected, mut denied, mut failed) = (0u64, 0u64, 0u64, 0u64);
+    let mut count = |line: Line| match line {
+        Line::Accepted => accepted += 1,
+        Line::Rejected => rejected += 1,
+        Line::Denied => denied += 1,
+        Line::Failed => failed += 1,
+    };
+    let mut buf: Vec<u8> = Vec::new();
+    let mut chunks = body.into_data_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = match chunk {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::warn!(target: "fetcher", error = ?e, "ingest body aborted");
+                break;
+            }
+        };
+        buf.extend_from_slice(&chunk);
+        while let Some(nl) = buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buf.drain(..=nl).collect();
+            count(ingest_line(&st, client, q.symbol.as_deref(), &line).await);
+        }
+        if buf.len() > MAX_LINE_BYTES {
+            // Unterminated oversize line: drop it rather than buffering without bound.
+            buf.clear();
+            count(Line::Rejected);
+        }
+    }
+    if !buf.is_empty() {
+        count(ingest_line(&st, client, q.symbol.as_deref(), &buf).await);
+    }
+
+    counter!("ingest_accepted_total").increment(accepted);
+    counter!("ingest_rejected_total").increment(rejected);
+    counter!("ingest_failed_total").increment(failed);
+    if denied > 0 {
+        counter!("ingest_denied_total", "client" => client.name.clone()).increment(denied);
+    }
+    // Any trade the broker didn't take is worth a retry: the client hears so.
+    let status = match (accepted, rejected, denied, failed) {
+        (_, _, _, f) if f > 0 => StatusCode::SERVICE_UNAVAILABLE,
+        (0, 0, d, _) if d > 0 => StatusCode::FORBIDDEN,
+        (0, r, d, _) if r + d > 0 => StatusCode::UNPROCESSABLE_ENTITY,
+        _ => StatusCode::OK,
+    };
+    (status, Json(json!({ "accepted": accepted, "rejected": rejected, "denied": denied, "failed": failed })))
+}
+
+/// `ingest.v1.Ingest/Push`: a stream of `Trade`s, answered with one `PushSummary`.
+async fn push(State(st): State<IngestState>, ConnectInfo(peer): ConnectInfo<Peer>, headers: HeaderMap, body: Body) -> Response {
+    let clients = st.clients.current();
+    let Some(client) = clients.authenticate(peer.identity.as_deref(), bearer(&headers)) else {
+        counter!("ingest_unauthorized_total").increment(1);
+        tracing::debug!(target: "fetcher", addr = %peer.addr, identity = ?peer.identity, "unauthorized ingest call");
+        return grpc::refuse(Status::new(Code::Unauthenticated, "missing or invalid credentials"));
+    };
+
+    let mut summary = PushSummary::default();
+    let mut failed = 0u64;
+    let mut frames = Deframer::default();
+    let mut chunks = body.into_data_stream();
+    let outcome = 'read: loop {
+        let chunk = match chunks.next().await {
+            Some(Ok(c)) => c,
+            Some(Err(e)) => break 'read Err(Status::new(Code::Internal, format!("request body aborted: {}", e))),
+            None => break 'read frames.finish(),
+        };
+        frames.push(&chunk);
+        loop {
+            let trade = match frames.message::<Trade>() {
+                Ok(Some(t)) => t,
+                Ok(None) => break,
+                Err(status) => break 'read Err(status),
+            };
+            let symbol = Some(trade.symbol.as_str()).filter(|s| !s.is_empty());
+            match ingest_line(&st, client, symbol, &trade.payload).await {
+                Line::Accepted => summary.accepted += 1,
+                Line::Rejected => summary.rejected += 1,
+                Line::Denied => summary.denied += 1,
+                Line::Failed => failed += 1,
+            }
+        }
+    };
+
+    counter!("ingest_accepted_total").increment(summary.accepted);
+    counter!("ingest_rejected_total").increment(summary.rejected);
+    counter!("ingest_failed_total").increment(failed);
+    if summary.denied > 0 {
+        counter!("ingest_denied_total", "client" => client.name.clone()).increment(summary.denied);
+    }
+    if let Err(status) = outcome {
+        tracing::warn!(target: "fetcher", client = %client.name, error = %status.message, "ingest call ended early");
+        return grpc::refuse(status);
+    }
+    if failed > 0 {
+        return grpc::refuse(Status::new(Code::Unavailable, format!("{} trades weren't published", failed)));
+    }
+    grpc::unary(&summary)
+}
+
+pub async fn run(sink: RawSink) -> Result<()> {
+    // Either can come from a file or a secret store, and is re-read when rotated.
+    let clients = auth::watch("INGEST_CLIENTS", Some("INGEST_TOKENS")).await?
+        .context("SOURCE=ingest requires INGEST_CLIENTS or INGEST_TOKENS")?;
+
+    let port: u16 = env("INGEST_PORT", "8088").parse().unwrap_or(8088);
+    let app = Router::new()
+        .route("/v1/trades", post(trades))
+        .route("/ingest.v1.Ingest/Push", post(push))
+        .with_state(IngestState { sink, clients })
+        .into_make_service_with_connect_info::<Peer>();
+
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+    match TlsOptions::from_env("INGEST")? {
+        Some(tls) => {
+            tracing::info!(target: "fetcher", port, mtls = tls.client_ca.is_some(), "ingest server listening with TLS");
+            axum::serve(TlsListener::new(listener, tls.server_config()?, "ingest")?, app).await?;
+        }
+        None => {
+            tracing::info!(target: "fetcher", port, "ingest server listening");
+            axum::serve(listener, app).await?;
+        }
+    }
+    Ok(())
+}
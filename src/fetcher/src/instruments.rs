@@ -0,0 +1,56 @@
+//! Contract specs for Binance's coin-margined (COIN-M) futures.
+//!
+//! A COIN-M contract is worth a fixed amount of the quote currency (100 USD
+//! for BTC, 10 USD for most others), so a trade's `q` counts contracts, not
+//! coins. The size goes out with every raw trade as the `contract_size`
+//! header, and the producer's notional uses it instead of `price × qty`.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instrument {
+    /// `BTCUSD_PERP`, `BTCUSD_250627`.
+    pub symbol: String,
+    /// The underlying pair, `BTCUSD`: what `@indexPrice` is keyed by.
+    pub pair: String,
+    /// Quote currency per contract.
+    pub contract_size: f64,
+}
+
+#[derive(Deserialize)]
+struct ExchangeInfo {
+    symbols: Vec<RawSymbol>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSymbol {
+    symbol: String,
+    pair: String,
+    contract_size: f64,
+}
+
+/// `GET /dapi/v1/exchangeInfo`, keyed by lowercase symbol.
+pub fn parse_coinm(body: &str) -> Result<HashMap<String, Instrument>> {
+    let info: ExchangeInfo = serde_json::from_str(body).context("bad COIN-M exchangeInfo")?;
+    info.symbols.into_iter()
+        .map(|s| {
+            anyhow::ensure!(s.contract_size > 0.0, "{} has contract size {}", s.symbol, s.contract_size);
+            Ok((s.symbol.to_lowercase(), Instrument { symbol: s.symbol, pair: s.pair, contract_size: s.contract_size }))
+        })
+        .collect()
+}
+
+/// Every COIN-M instrument, from `BINANCE_DAPI_URL` (default
+/// `https://dapi.binance.com`) through the `binance-coinm` REST limiter.
+pub async fn fetch_coinm() -> Result<HashMap<String, Instrument>> {
+    let base = std::env::var("BINANCE_DAPI_URL").unwrap_or_else(|_| "https://dapi.binance.com".into());
+    let http = reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?;
+    let req = http.get(format!("{}/dapi/v1/exchangeInfo", base.trim_end_matches('/')));
+    let body = ratelimit::shared("binance-coinm")?.send(req, 1).await?.error_for_status()?.text().await?;
+    parse_coinm(&body)
+}
@@ -0,0 +1,107 @@
+//! KuCoin spot trades: `/market/match` on a WebSocket whose address and
+//! token come from `POST /api/v1/bullet-public`, fetched for every
+//! connection because tokens expire.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::venue::{Connection, ExchangeSource, Pair};
+
+/// Symbols KuCoin takes in one subscribe message.
+const TOPIC_SYMBOLS: usize = 100;
+
+#[derive(Deserialize)]
+struct Bullet {
+    code: String,
+    data: Option<BulletData>,
+}
+
+#[derive(Deserialize)]
+struct BulletData {
+    token: String,
+    #[serde(rename = "instanceServers")]
+    servers: Vec<Server>,
+}
+
+#[derive(Deserialize)]
+struct Server {
+    endpoint: String,
+    #[serde(rename = "pingInterval")]
+    ping_interval_ms: u64,
+}
+
+pub struct Kucoin {
+    http: reqwest::Client,
+    rest: String,
+}
+
+impl Kucoin {
+    pub fn new(rest: &str) -> Result<Self> {
+        Ok(Self {
+            http: reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?,
+            rest: rest.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// `KUCOIN_REST_URL`, default `https://api.kucoin.com`.
+    pub fn from_env() -> Result<Self> {
+        Self::new(&std::env::var("KUCOIN_REST_URL").unwrap_or_else(|_| "https://api.kucoin.com".into()))
+    }
+}
+
+fn request_id() -> String {
+    chrono::Utc::now().timestamp_millis().to_string()
+}
+
+#[async_trait]
+impl ExchangeSource for Kucoin {
+    fn name(&self) -> &'static str {
+        "kucoin"
+    }
+
+    fn endpoint(&self) -> String {
+        self.rest.clone()
+    }
+
+    async fn connect(&self, _pairs: &[Pair]) -> Result<Connection> {
+        let req = self.http.post(format!("{}/api/v1/bullet-public", self.rest));
+        let bullet: Bullet = ratelimit::shared("kucoin")?.send(req, 1).await?.error_for_status()?.json().await?;
+        anyhow::ensure!(bullet.code == "200000", "kucoin bullet-public returned code {}", bullet.code);
+        let data = bullet.data.context("kucoin bullet-public returned no data")?;
+        let server = data.servers.first().context("kucoin bullet-public returned no servers")?;
+        let sep = if server.endpoint.contains('?') { '&' } else { '?' };
+        Ok(Connection {
+            url: format!("{}{}token={}&connectId={}", server.endpoint, sep, data.token, uuid::Uuid::new_v4()),
+            ping_every: Some(Duration::from_millis(server.ping_interval_ms.max(1000))),
+        })
+    }
+
+    fn subscribe(&self, pairs: &[Pair]) -> Vec<String> {
+        pairs.chunks(TOPIC_SYMBOLS).enumerate().map(|(i, chunk)| {
+            let symbols: Vec<String> = chunk.iter().map(|p| p.join("-")).collect();
+            json!({
+                "id": format!("{}-{}", request_id(), i),
+                "type": "subscribe",
+                "topic": format!("/market/match:{}", symbols.join(",")),
+                "privateChannel": false,
+                "response": true,
+            }).to_string()
+        }).collect()
+    }
+
+    fn ping(&self) -> Option<String> {
+        Some(json!({ "id": request_id(), "type": "ping" }).to_string())
+    }
+
+    fn trade_key(&self, frame: &str) -> Option<String> {
+        let v: Value = serde_json::from_str(frame).ok()?;
+        if v.get("type")?.as_str()? != "message" || v.get("subject")?.as_str()? != "trade.l3match" {
+            return None;
+        }
+        Pair::parse(v.get("data")?.get("symbol")?.as_str()?).ok().map(|p| p.key())
+    }
+}
@@ -0,0 +1,23 @@
+//! The parts of the fetcher that stand on their own: ingest authentication,
+//! TLS and gRPC framing, the subscribed-WebSocket venues and their receive metrics,
+//! exchange status, reachability and clock offsets, symbol discovery, futures contract specs,
+//! the bounded publish queue and, with the `fix` feature, FIX 4.4 market data.
+
+pub mod auth;
+pub mod bitstamp;
+pub mod clock;
+#[cfg(feature = "fix")]
+pub mod fix;
+pub mod gate;
+pub mod gemini;
+pub mod grpc;
+pub mod instruments;
+pub mod kucoin;
+pub mod listings;
+pub mod queue;
+pub mod reach;
+pub mod recv;
+pub mod status;
+pub mod tls;
+pub mod upbit;
+pub mod venue;
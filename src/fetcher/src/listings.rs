@@ -0,0 +1,89 @@
+//! Symbol auto-discovery for Binance spot and USDⓈ-M futures: the symbols to
+//! stream are the ones `exchangeInfo` lists as trading in the chosen quote
+//! currencies, re-read on an interval.
+//!
+//! A symbol that appears is subscribed and one that disappears (delisted,
+//! or halted) is unsubscribed. Either way a [`ListingMarker`] goes out on
+//! `ticks.raw` under the symbol's key, with the `listing` header, so it
+//! lands in order with the symbol's trades and the producer can tell where
+//! the listing window starts and ends.
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+struct ExchangeInfo {
+    symbols: Vec<RawSymbol>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSymbol {
+    symbol: String,
+    status: String,
+    quote_asset: String,
+}
+
+/// The lowercase symbols `exchangeInfo` lists as `TRADING` and quoted in one
+/// of `quotes` (any quote when empty).
+pub fn parse_exchange_info(body: &str, quotes: &[String]) -> Result<BTreeSet<String>> {
+    let info: ExchangeInfo = serde_json::from_str(body).context("bad exchangeInfo")?;
+    Ok(info.symbols.into_iter()
+        .filter(|s| s.status == "TRADING")
+        .filter(|s| quotes.is_empty() || quotes.iter().any(|q| q.eq_ignore_ascii_case(&s.quote_asset)))
+        .map(|s| s.symbol.to_lowercase())
+        .collect())
+}
+
+/// What's in `next` but not `current`, and what's in `current` but not `next`.
+pub fn diff(current: &BTreeSet<String>, next: &BTreeSet<String>) -> (Vec<String>, Vec<String>) {
+    (next.difference(current).cloned().collect(), current.difference(next).cloned().collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListingEvent {
+    Listed,
+    Delisted,
+}
+
+impl ListingEvent {
+    /// The `listing` header's value.
+    pub fn name(self) -> &'static str {
+        match self {
+            ListingEvent::Listed => "listed",
+            ListingEvent::Delisted => "delisted",
+        }
+    }
+}
+
+/// The payload of a `listing` message on `ticks.raw`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListingMarker {
+    /// Upper-case, as the producer writes symbols.
+    pub symbol: String,
+    pub event: ListingEvent,
+    /// When discovery noticed, ms since the epoch.
+    pub ts_ms: i64,
+}
+
+/// Where `market`'s `exchangeInfo` is, its request weight and its REST limiter.
+pub fn endpoint(market: &str) -> Result<(String, u32, &'static str)> {
+    let var = |k: &str, default: &str| std::env::var(k).unwrap_or_else(|_| default.into()).trim_end_matches('/').to_string();
+    Ok(match market {
+        "spot" => (format!("{}/api/v3/exchangeInfo", var("BINANCE_API_URL", "https://api.binance.com")), 20, "binance"),
+        "usdm" => (format!("{}/fapi/v1/exchangeInfo", var("BINANCE_FAPI_URL", "https://fapi.binance.com")), 1, "binance-usdm"),
+        other => anyhow::bail!("auto-discovery doesn't support MARKET={} (expected spot|usdm)", other),
+    })
+}
+
+/// The trading symbols on `market` quoted in `quotes`, through the REST limiter.
+pub async fn fetch(market: &str, quotes: &[String]) -> Result<BTreeSet<String>> {
+    let (url, weight, limiter) = endpoint(market)?;
+    let http = reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?;
+    let body = ratelimit::shared(limiter)?.send(http.get(url), weight).await?.error_for_status()?.text().await?;
+    parse_exchange_info(&body, quotes)
+}
@@ -1,74 +1,279 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::Result;
-use chrono::Utc;
-use futures_util::StreamExt;
-use metrics::{counter, histogram};
-use obsv::{init_metrics, init_tracing, measure_ms_async};
-use rdkafka::config::ClientConfig;
-use rdkafka::message::{Header, OwnedHeaders};
-use rdkafka::producer::{FutureProducer, FutureRecord};
-use tokio_tungstenite::connect_async;
-use uuid::Uuid;
+use anyhow::{Context, Result};
+use bus::compress::Compression;
+use bus::envelope::{Envelope, RAW_SCHEMA};
+use bus::oversize::{Action, Limit};
+use bus::sign::Keyring;
+use bus::BusConfig;
+use fetcher::listings::{self, ListingEvent, ListingMarker};
+use fetcher::{clock, status};
+use metrics::{counter, gauge};
+use obsv::load::Load;
+use obsv::{init_metrics, init_tracing, startup};
+
+mod binance;
+#[cfg(feature = "fix")]
+mod fix_session;
+mod ingest;
+mod mqtt;
+mod raw;
+mod shard;
+mod ws;
+
+use raw::RawSink;
 
 fn env<T: AsRef<str>>(k: T, default: &str) -> String {
     std::env::var(k.as_ref()).unwrap_or_else(|_| default.to_string())
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    init_metrics(9464);
+/// Where `source` is reached first, for the startup check; `None` for feeds
+/// that come to us (MQTT, ingest) or over FIX.
+fn feed_endpoint(source: &str, market: &str) -> Result<Option<String>> {
+    if source == "binance" {
+        return Ok(Some(binance::Market::parse(market)?.ws_base().to_string()));
+    }
+    Ok(fetcher::venue::by_name(source)?.map(|v| v.endpoint()))
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!("chaos", "fix", "nats", "pulsar"), run())
+}
+
+async fn run() -> Result<()> {
     init_tracing();
+    init_metrics(9464)?;
+    fetcher::recv::spawn_reporter(Duration::from_secs(5));
 
-    let brokers   = env("KAFKA_BROKERS", "localhost:29092");
     let topic_out = env("TOPIC_OUT", "ticks.raw");
-    let symbol    = env("SYMBOL", "btcusdt"); // lower-case for Binance
-    let ws_url    = format!("wss://stream.binance.com:9443/ws/{}@trade", symbol);
-
-    let producer: FutureProducer = ClientConfig::new()
-        .set("bootstrap.servers", &brokers)
-        .set("message.timeout.ms", "5000")
-        .set("socket.keepalive.enable", "true")
-        .set("request.timeout.ms", "20000")
-        .create()?;
-
-    let (ws_stream, _) = connect_async(&ws_url).await?;
-    tracing::info!(target: "fetcher", "connected to {}", ws_url);
-    let (_w, mut r) = ws_stream.split();
-
-    while let Some(msg) = r.next().await {
-        let msg = match msg {
-            Ok(m) => m,
-            Err(e) => { tracing::error!(target:"fetcher", error=?e, "websocket error"); continue; }
-        };
-        if !msg.is_text() { continue; }
-
-        let payload = msg.into_text().unwrap_or_default();
-        let msg_id = Uuid::new_v4().to_string();
-        let ts_produce_ns = Utc::now().timestamp_nanos_opt().unwrap().to_string();
-
-        counter!("produced_total").increment(1);
-
-        // Await the send so delivery failures are logged
-        let (delivery, ms) = measure_ms_async(
-            producer.send(
-                FutureRecord::to(&topic_out)
-                    .payload(&payload)
-                    .key(&symbol)
-                    .headers(
-                        OwnedHeaders::new()
-                            .insert(Header { key: "msg_id", value: Some(msg_id.as_bytes()) })
-                            .insert(Header { key: "ts_produce_ns", value: Some(ts_produce_ns.as_bytes()) })
-                    ),
-                Duration::from_secs(5),
-            )
-        ).await;
-        histogram!("produce_latency_ms").record(ms);
-
-        if let Err((e, _)) = delivery {
-            tracing::error!(target="fetcher", error=?e, "kafka delivery failed");
+    // Binance only: also stream best bid/ask (`bookTicker`) here. Empty disables it.
+    let quotes_topic = env("QUOTES_TOPIC", "");
+    // Binance spot only: also stream order-book diffs (`depth@100ms`) here, for the book builder. Empty disables it.
+    let depth_topic = env("DEPTH_TOPIC", "");
+    let source    = env("SOURCE", "binance");
+    // Binance only: `spot`, or `usdm` / `coinm` futures. Also the envelope's market for every source.
+    let market = env("MARKET", "spot");
+    // Binance COIN-M only: also stream each symbol's pair index (`@indexPrice`) here.
+    let index_topic = env("INDEX_TOPIC", "");
+    // Binance only: `aggTrade` folds fills of one taker order at one price into a single event.
+    // Futures streams carry aggregated trades, so that's their default.
+    let stream: &'static str = match env("BINANCE_STREAM", if market == "spot" { "trade" } else { "aggTrade" }).as_str() {
+        "trade" => "trade",
+        "aggTrade" => "aggTrade",
+        other => anyhow::bail!("unknown BINANCE_STREAM {:?} (expected trade|aggTrade)", other),
+    };
+    // Binance spot and USDⓈ-M only: stream what exchangeInfo lists as trading in
+    // DISCOVER_QUOTES instead of SYMBOLS, re-read every DISCOVER_SECS.
+    let discover = env("AUTO_DISCOVER", "false") == "true";
+    anyhow::ensure!(!discover || source == "binance", "AUTO_DISCOVER needs SOURCE=binance");
+    let discover_quotes: Vec<String> = env("DISCOVER_QUOTES", "USDT")
+        .split(',')
+        .map(|q| q.trim().to_uppercase())
+        .filter(|q| !q.is_empty())
+        .collect();
+    let discover_every = Duration::from_secs(env("DISCOVER_SECS", "300").parse().unwrap_or(300).max(10));
+    // Comma-separated; SYMBOL kept for single-symbol deployments. Lower-case for Binance.
+    let symbols: Vec<String> = match discover {
+        true => listings::fetch(&market, &discover_quotes).await.context("symbol discovery")?.into_iter().collect(),
+        false => env("SYMBOLS", &env("SYMBOL", "btcusdt"))
+            .split(',')
+            .map(|s| s.trim().to_lowercase())
+            .filter(|s| !s.is_empty())
+            .collect(),
+    };
+
+    // Sharding mode: N instances split the symbol list deterministically.
+    let shard_count: u32 = env("SHARD_COUNT", "1").parse().unwrap_or(1).max(1);
+    let shard_index: u32 = env("SHARD_INDEX", "0").parse().unwrap_or(0);
+    anyhow::ensure!(shard_index < shard_count, "SHARD_INDEX {} out of range for SHARD_COUNT {}", shard_index, shard_count);
+
+    // Raw exchange JSON is mostly repeated keys; zstd shrinks it several-fold.
+    let bus = BusConfig::from_env()?
+        .compress_by_default(Compression::ZSTD)?
+        .kafka_set("message.timeout.ms", "5000");
+    let topics: Vec<&str> = [&topic_out, &quotes_topic, &depth_topic, &index_topic].into_iter()
+        .map(String::as_str)
+        .filter(|t| !t.is_empty())
+        .collect();
+    startup::wait_for("kafka", || bus.check_topics(&topics)).await?;
+    bus::audit::start(&bus).await;
+    if let Some(url) = feed_endpoint(&source, &market)? {
+        let http = reqwest::Client::new();
+        startup::wait_for("exchange", || fetcher::reach::check(&http, &url)).await?;
+    }
+    let publisher = bus.publisher().await?;
+    // Pushed, MQTT and FIX feeds can come from anywhere; say so with EXCHANGE.
+    let exchange = env("EXCHANGE", match source.as_str() { "mqtt" | "ingest" | "fix" => "unknown", venue => venue });
+    let envelope = Envelope::new(RAW_SCHEMA, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), &exchange, &market);
+    let keys = Keyring::from_env()?.map(Arc::new);
+    // Reads wait once FETCH_QUEUE_CAPACITY messages are queued for the broker; 0 waits on every publish.
+    let (queue_capacity, queue_lanes) = (env("FETCH_QUEUE_CAPACITY", "1024").parse().unwrap_or(1024), env("FETCH_PUBLISHERS", "4").parse().unwrap_or(4));
+    // Each publisher keeps up to FETCH_IN_FLIGHT publishes going, for different symbols.
+    let in_flight = env("FETCH_IN_FLIGHT", "8").parse().unwrap_or(8);
+    // Payloads over MAX_PAYLOAD_BYTES are dropped, or dead-lettered to OVERSIZE_DLQ (see bus::oversize).
+    let limit = Limit::from_env()?;
+    let oversize_dlq = Some(env("OVERSIZE_DLQ", "")).filter(|t| !t.is_empty());
+    if let Some(l) = limit.filter(|l| l.action != Action::Drop) {
+        anyhow::ensure!(oversize_dlq.is_some(), "OVERSIZE_ACTION={} needs OVERSIZE_DLQ", l.action.name());
+    }
+    // One for every sink: quotes, depth and index count as fetcher work too.
+    let load = Load::new("fetcher")?;
+    let sink = RawSink::new(publisher.clone(), topic_out, envelope.clone()).signed(keys.clone()).queued(queue_capacity, queue_lanes, in_flight)
+        .limited(limit, oversize_dlq.clone()).loaded(load.clone());
+
+    // Status changes go to STATUS_TOPIC (empty publishes none); the venue's status endpoint
+    // (or STATUS_URL) is polled every STATUS_POLL_SECS, 0 turns polling off.
+    let status_topic = env("STATUS_TOPIC", "exchange_status");
+    let status_every = Duration::from_secs(env("STATUS_POLL_SECS", "30").parse().unwrap_or(30));
+    let status_exchange = match source.as_str() { "mqtt" | "ingest" | "fix" => exchange.clone(), venue => venue.to_string() };
+    let poll = match (env("STATUS_URL", ""), status::endpoint(&source, &market)) {
+        (url, Some((_, check))) if !url.is_empty() => Some((url, check)),
+        (url, None) if !url.is_empty() => Some((url, status::Check::Reachable)),
+        (_, default) => default,
+    };
+    let poll = poll.filter(|_| !status_every.is_zero())
+        .map(|(url, check)| status::Poll { exchange: status_exchange, url, check, every: status_every });
+    status::spawn(publisher.clone(), status_topic, poll)?;
+
+    // The venue's server time (or CLOCK_URL's Date header) is read every CLOCK_POLL_SECS, 0 turns it off.
+    let clock_every = Duration::from_secs(env("CLOCK_POLL_SECS", "60").parse().unwrap_or(60));
+    let clock = match (env("CLOCK_URL", ""), clock::endpoint(&source, &market)) {
+        (url, Some((_, format))) if !url.is_empty() => Some((url, format)),
+        (url, None) if !url.is_empty() => Some((url, clock::Format::Date)),
+        (_, default) => default,
+    };
+    if let Some((url, format)) = clock.filter(|_| !clock_every.is_zero()) {
+        clock::spawn(clock::Poll { exchange: exchange.clone(), url, format, every: clock_every })?;
+    }
+
+    match source.as_str() {
+        // Symbols come from the MQTT topic map; sharding filters per message.
+        "mqtt" => return mqtt::run(sink, shard_index, shard_count).await,
+        // Symbols come from whoever pushes; sharding doesn't apply.
+        "ingest" => return ingest::run(sink).await,
+        "binance" | "bithumb" | "bitstamp" | "gate" | "gemini" | "kucoin" | "upbit" | "fix" => {}
+        other => anyhow::bail!("unknown SOURCE {:?} (expected binance|bithumb|bitstamp|gate|gemini|kucoin|upbit|fix|mqtt|ingest)", other),
+    }
+
+    let mine = shard::assigned(&symbols, shard_index, shard_count);
+    gauge!("fetcher_assigned_symbols").set(mine.len() as f64);
+    tracing::info!(target: "fetcher", shard_index, shard_count, stream, symbols = ?mine, "symbol assignment");
+
+    if mine.is_empty() && !discover {
+        // More instances than symbols: stay up (metrics, no crash-loop) but idle.
+        tracing::warn!(target: "fetcher", "no symbols assigned to this shard; idling");
+        std::future::pending::<()>().await;
+    }
+
+    // FIX, like the other venues, takes every symbol on one connection.
+    if source == "fix" {
+        #[cfg(feature = "fix")]
+        return fix_session::run(sink, mine).await;
+        #[cfg(not(feature = "fix"))]
+        anyhow::bail!("SOURCE=fix requires building with the `fix` feature");
+    }
+    if let Some(venue) = fetcher::venue::by_name(&source)? {
+        return ws::run(venue, sink, mine).await;
+    }
+
+    let market_name = market.clone();
+    let market = binance::Market::parse(&market)?;
+    anyhow::ensure!(index_topic.is_empty() || market == binance::Market::CoinM, "INDEX_TOPIC needs MARKET=coinm");
+    anyhow::ensure!(depth_topic.is_empty() || market == binance::Market::Spot, "DEPTH_TOPIC needs MARKET=spot");
+    // COIN-M trades count contracts, so each symbol's go out with its contract size.
+    let specs = match market {
+        binance::Market::CoinM => {
+            let instruments = fetcher::instruments::fetch_coinm().await?;
+            mine.iter()
+                .map(|s| instruments.get(s).cloned().map(Some).with_context(|| format!("{} is not a COIN-M symbol (e.g. btcusd_perp)", s)))
+                .collect::<Result<Vec<_>>>()?
+        }
+        _ => vec![None; mine.len()],
+    };
+    let mut index_pairs: Vec<String> = specs.iter().flatten().map(|i| i.pair.to_lowercase()).collect();
+    index_pairs.sort();
+    index_pairs.dedup();
+
+    let quotes = Some(quotes_topic).filter(|t| !t.is_empty()).map(|t| RawSink::new(publisher.clone(), t, envelope.clone()).signed(keys.clone()).queued(queue_capacity, queue_lanes, in_flight).limited(limit, oversize_dlq.clone()).loaded(load.clone()));
+    let depth = Some(depth_topic).filter(|t| !t.is_empty()).map(|t| RawSink::new(publisher.clone(), t, envelope.clone()).signed(keys.clone()).queued(queue_capacity, queue_lanes, in_flight).limited(limit, oversize_dlq.clone()).loaded(load.clone()));
+    let index = Some(index_topic).filter(|t| !t.is_empty()).map(|t| RawSink::new(publisher, t, envelope).signed(keys).queued(queue_capacity, queue_lanes, in_flight).limited(limit, oversize_dlq).loaded(load));
+    // Every stream of one symbol.
+    let spawn = |s: String, contract_size: Option<f64>| {
+        let mut tasks = Vec::new();
+        if let Some(q) = &quotes {
+            tasks.push(tokio::spawn(binance::run_symbol(q.clone(), market, s.clone(), "bookTicker")));
+        }
+        if let Some(d) = &depth {
+            tasks.push(tokio::spawn(binance::run_symbol(d.clone(), market, s.clone(), "depth@100ms")));
         }
+        tasks.push(tokio::spawn(binance::run_symbol(sink.clone().contract_size(contract_size), market, s, stream)));
+        tasks
+    };
+    let mut running: HashMap<String, Vec<_>> = mine.into_iter().zip(specs)
+        .map(|(s, spec)| (s.clone(), spawn(s, spec.map(|i| i.contract_size))))
+        .collect();
+    if !discover {
+        let mut tasks: Vec<_> = running.into_values().flatten().collect();
+        if let Some(index) = &index {
+            for pair in index_pairs {
+                tasks.push(tokio::spawn(binance::run_symbol(index.clone(), market, pair, "indexPrice")));
+            }
+        }
+        for res in futures_util::future::join_all(tasks).await {
+            res??;
+        }
+        return Ok(());
     }
 
-    Ok(())
+    let mut current: BTreeSet<String> = running.keys().cloned().collect();
+    let mut tick = tokio::time::interval(discover_every);
+    tick.tick().await;
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            // The streams only stop when aborted below, so one that ends has failed.
+            (res, _, _) = futures_util::future::select_all(running.values_mut().flatten()), if !running.is_empty() => {
+                res.context("a symbol's stream panicked")??;
+                anyhow::bail!("a symbol's stream ended");
+            }
+        }
+        let listed = match listings::fetch(&market_name, &discover_quotes).await {
+            Ok(l) => l.into_iter().collect::<Vec<_>>(),
+            Err(e) => {
+                tracing::warn!(target: "fetcher", error = ?e, "symbol discovery failed; keeping the current symbols");
+                counter!("discovery_errors_total").increment(1);
+                continue;
+            }
+        };
+        let next: BTreeSet<String> = shard::assigned(&listed, shard_index, shard_count).into_iter().collect();
+        let (added, removed) = listings::diff(&current, &next);
+        // More likely a bad answer than a mass delisting.
+        if removed.len() > 1 && removed.len() * 2 > current.len() {
+            tracing::warn!(target: "fetcher", removed = removed.len(), streaming = current.len(), "exchangeInfo dropped over half the symbols; ignoring it");
+            counter!("discovery_errors_total").increment(1);
+            continue;
+        }
+        let marker = |s: &str, event| ListingMarker { symbol: s.to_uppercase(), event, ts_ms: chrono::Utc::now().timestamp_millis() };
+        for s in removed {
+            // Stopped before the marker goes out, so no trade follows it.
+            for task in running.remove(&s).unwrap_or_default() {
+                task.abort();
+                let _ = task.await;
+            }
+            sink.listing(&s, &marker(&s, ListingEvent::Delisted)).await;
+            counter!("listing_events_total", "event" => "delisted").increment(1);
+            tracing::info!(target: "fetcher", symbol = %s, "delisted; unsubscribed");
+        }
+        for s in added {
+            // Ahead of the first trade on the same key.
+            sink.listing(&s, &marker(&s, ListingEvent::Listed)).await;
+            counter!("listing_events_total", "event" => "listed").increment(1);
+            tracing::info!(target: "fetcher", symbol = %s, "listed; subscribing");
+            running.insert(s.clone(), spawn(s, None));
+        }
+        gauge!("fetcher_assigned_symbols").set(next.len() as f64);
+        current = next;
+    }
 }
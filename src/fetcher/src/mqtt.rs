@@ -0,0 +1,106 @@
+//! MQTT source for shops that bridge exchange data through an MQTT broker.
+//!
+//! `MQTT_TOPICS` is a comma-separated list of rules, either a fixed
+//! `filter=symbol` mapping or a filter with a `{symbol}` level
+//! (e.g. `md/{symbol}/trades`) whose value in each publish topic names the
+//! symbol. Payloads are forwarded untouched in the usual raw envelope, so the
+//! bridge must keep the venue's JSON.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+
+use crate::env;
+use crate::raw::RawSink;
+use crate::shard;
+
+enum Rule {
+    Fixed { filter: String, symbol: String },
+    Level { filter: String, level: usize },
+}
+
+impl Rule {
+    fn parse(spec: &str) -> Result<Self> {
+        if let Some((filter, symbol)) = spec.split_once('=') {
+            return Ok(Rule::Fixed { filter: filter.trim().into(), symbol: symbol.trim().to_lowercase() });
+        }
+        let levels: Vec<&str> = spec.trim().split('/').collect();
+        let level = levels.iter().position(|l| *l == "{symbol}")
+            .ok_or_else(|| anyhow::anyhow!("MQTT_TOPICS rule {:?} needs `=symbol` or a {{symbol}} level", spec))?;
+        Ok(Rule::Level { filter: spec.trim().replace("{symbol}", "+"), level })
+    }
+
+    fn filter(&self) -> &str {
+        match self {
+            Rule::Fixed { filter, .. } | Rule::Level { filter, .. } => filter,
+        }
+    }
+
+    fn symbol_for(&self, topic: &str) -> Option<String> {
+        if !matches(self.filter(), topic) {
+            return None;
+        }
+        match self {
+            Rule::Fixed { symbol, .. } => Some(symbol.clone()),
+            Rule::Level { level, .. } => topic.split('/').nth(*level).map(str::to_lowercase),
+        }
+    }
+}
+
+/// MQTT filter matching with `+` (one level) and `#` (rest of topic).
+fn matches(filter: &str, topic: &str) -> bool {
+    let mut t = topic.split('/');
+    for f in filter.split('/') {
+        match (f, t.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (f, Some(l)) if f == l => {}
+            _ => return false,
+        }
+    }
+    t.next().is_none()
+}
+
+pub async fn run(sink: RawSink, shard_index: u32, shard_count: u32) -> Result<()> {
+    let rules = env("MQTT_TOPICS", "md/{symbol}/trades")
+        .split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(Rule::parse)
+        .collect::<Result<Vec<_>>>()?;
+
+    let host = env("MQTT_HOST", "localhost");
+    let port: u16 = env("MQTT_PORT", "1883").parse().unwrap_or(1883);
+    let client_id = env("MQTT_CLIENT_ID", &format!("fetcher-{}", shard_index));
+    let mut opts = MqttOptions::new(client_id, &host, port);
+    opts.set_keep_alive(Duration::from_secs(15));
+
+    let (client, mut eventloop) = AsyncClient::new(opts, 1024);
+    for r in &rules {
+        client.subscribe(r.filter(), QoS::AtLeastOnce).await?;
+    }
+    tracing::info!(target: "fetcher", host = %host, port, filters = ?rules.iter().map(Rule::filter).collect::<Vec<_>>(), "subscribed to MQTT");
+
+    loop {
+        let publish = match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::Publish(p))) => p,
+            Ok(_) => continue,
+            Err(e) => {
+                // rumqttc reconnects on the next poll; don't spin while the broker is away
+                tracing::error!(target: "fetcher", error = ?e, "mqtt connection error");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+
+        let Some(symbol) = rules.iter().find_map(|r| r.symbol_for(&publish.topic)) else {
+            tracing::warn!(target: "fetcher", topic = %publish.topic, "no symbol mapping for MQTT topic");
+            continue;
+        };
+        // Every shard subscribes to the same filters; only the symbol's owner forwards it.
+        if shard::owner(&symbol, shard_count) != shard_index {
+            continue;
+        }
+        sink.send(&symbol, &publish.payload).await;
+    }
+}
@@ -0,0 +1,170 @@
+//! A bounded queue between the readers and the broker, so a slow broker
+//! slows the reads instead of piling messages up in the Kafka client.
+//!
+//! Messages are spread over `lanes` publisher tasks by symbol, one lane per
+//! symbol so its order holds, and each lane holds at most its share of the
+//! capacity. A lane keeps up to `in_flight` publishes going at once, for
+//! different symbols: a symbol's next message starts once the broker has
+//! taken the one before, so its order holds whatever the client library
+//! does with concurrent sends. When produce latency rises the lanes fill,
+//! [`Queue::push`] waits for room, and so does the WebSocket read loop
+//! behind it: the exchange's frames wait in the socket until we're ready
+//! for them.
+//!
+//! `fetch_queue_depth{topic}` is what's queued (or waiting for room),
+//! `fetch_queue_wait_ms` how long a message sat before its publish began,
+//! and `fetch_throttled_total{topic}` / `fetch_throttle_ms` count and time
+//! the pushes that had to wait.
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use std::time::Instant;
+
+use bus::{Headers, Publisher};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use metrics::{counter, gauge, histogram, Counter, Gauge, Histogram};
+use obsv::{errors, measure_ms_async};
+use anyhow::Result;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::oneshot;
+
+/// Publish one message to `topic`, timing it in `produce_latency_ms`;
+/// failures are logged and counted as well as returned.
+pub async fn publish(publisher: &dyn Publisher, topic: &str, symbol: &str, payload: &[u8], headers: &Headers) -> Result<()> {
+    let (delivery, ms) = measure_ms_async(async {
+        chaos::produce_delay().await;
+        chaos::fault("fetcher")?;
+        publisher.publish(topic, symbol, payload, headers).await
+    }).await;
+    histogram!("produce_latency_ms").record(ms);
+
+    if let Err(e) = &delivery {
+        tracing::error!(target="fetcher", topic, error=?e, "delivery failed");
+        errors::record("fetcher", bus::error::kind(e));
+    }
+    delivery
+}
+
+struct Queued {
+    symbol: String,
+    payload: Vec<u8>,
+    headers: Headers,
+    at: Instant,
+    /// Told how the publish went, for a caller that answers for the message.
+    ack: Option<oneshot::Sender<Result<()>>>,
+}
+
+#[derive(Clone)]
+pub struct Queue {
+    lanes: Arc<Vec<mpsc::Sender<Queued>>>,
+    depth: Gauge,
+    throttled: Counter,
+    throttle_ms: Histogram,
+}
+
+impl Queue {
+    /// `capacity` messages over `lanes` publisher tasks publishing to
+    /// `topic`, each with up to `in_flight` publishes going at once.
+    pub fn spawn(publisher: Arc<dyn Publisher>, topic: &str, capacity: usize, lanes: usize, in_flight: usize) -> Self {
+        let lanes = lanes.clamp(1, capacity.max(1));
+        let depth = gauge!("fetch_queue_depth", "topic" => topic.to_string());
+        let senders = (0..lanes)
+            .map(|_| {
+                let (tx, rx) = mpsc::channel((capacity / lanes).max(1));
+                tokio::spawn(drain(rx, publisher.clone(), topic.into(), depth.clone(), in_flight.max(1)));
+                tx
+            })
+            .collect();
+        Self {
+            lanes: Arc::new(senders),
+            depth,
+            throttled: counter!("fetch_throttled_total", "topic" => topic.to_string()),
+            throttle_ms: histogram!("fetch_throttle_ms"),
+        }
+    }
+
+    /// Queue a message on `symbol`'s lane, waiting while it's full.
+    pub async fn push(&self, symbol: &str, payload: Vec<u8>, headers: Headers) {
+        self.enqueue(symbol, payload, headers, None).await;
+    }
+
+    /// As [`push`](Self::push), then wait for the broker's answer.
+    pub async fn push_acked(&self, symbol: &str, payload: Vec<u8>, headers: Headers) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        if !self.enqueue(symbol, payload, headers, Some(tx)).await {
+            anyhow::bail!("the publish queue is closed");
+        }
+        rx.await.map_err(|_| anyhow::anyhow!("the publish queue is closed"))?
+    }
+
+    /// False if the lane is gone.
+    async fn enqueue(&self, symbol: &str, payload: Vec<u8>, headers: Headers, ack: Option<oneshot::Sender<Result<()>>>) -> bool {
+        let mut h = DefaultHasher::new();
+        symbol.hash(&mut h);
+        let lane = &self.lanes[(h.finish() % self.lanes.len() as u64) as usize];
+        self.depth.increment(1.0);
+        let q = Queued { symbol: symbol.to_string(), payload, headers, at: Instant::now(), ack };
+        let q = match lane.try_send(q) {
+            Ok(()) => return true,
+            Err(TrySendError::Full(q)) => q,
+            Err(TrySendError::Closed(_)) => {
+                self.depth.decrement(1.0);
+                return false;
+            }
+        };
+        self.throttled.increment(1);
+        let (sent, ms) = measure_ms_async(lane.send(q)).await;
+        self.throttle_ms.record(ms);
+        if sent.is_err() {
+            self.depth.decrement(1.0);
+        }
+        sent.is_ok()
+    }
+}
+
+/// One lane: up to `in_flight` publishes at once, each symbol's in arrival order.
+async fn drain(mut rx: mpsc::Receiver<Queued>, publisher: Arc<dyn Publisher>, topic: Arc<str>, depth: Gauge, in_flight: usize) {
+    let mut sending = FuturesUnordered::new();
+    // Symbols with a publish going, and what of theirs is waiting behind it.
+    let mut busy: HashMap<String, VecDeque<Queued>> = HashMap::new();
+    let mut held = 0;
+    let mut open = true;
+    while open || !sending.is_empty() {
+        tokio::select! {
+            q = rx.recv(), if open && sending.len() + held < in_flight => match q {
+                Some(q) => match busy.get_mut(&q.symbol) {
+                    Some(waiting) => {
+                        waiting.push_back(q);
+                        held += 1;
+                    }
+                    None => {
+                        busy.insert(q.symbol.clone(), VecDeque::new());
+                        sending.push(send(publisher.clone(), topic.clone(), depth.clone(), q));
+                    }
+                },
+                None => open = false,
+            },
+            Some(symbol) = sending.next() => match busy.get_mut(&symbol).and_then(VecDeque::pop_front) {
+                Some(q) => {
+                    held -= 1;
+                    sending.push(send(publisher.clone(), topic.clone(), depth.clone(), q));
+                }
+                None => {
+                    busy.remove(&symbol);
+                }
+            },
+        }
+    }
+}
+
+/// Publish one message and answer for it; the symbol is handed back so its next can start.
+async fn send(publisher: Arc<dyn Publisher>, topic: Arc<str>, depth: Gauge, q: Queued) -> String {
+    depth.decrement(1.0);
+    histogram!("fetch_queue_wait_ms").record(q.at.elapsed().as_secs_f64() * 1000.0);
+    let res = publish(publisher.as_ref(), &topic, &q.symbol, &q.payload, &q.headers).await;
+    if let Some(ack) = q.ack {
+        let _ = ack.send(res);
+    }
+    q.symbol
+}
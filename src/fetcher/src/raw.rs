@@ -0,0 +1,152 @@
+//! The raw-message envelope every fetcher source publishes to `ticks.raw`:
+//! the venue's payload untouched, keyed by symbol, with `msg_id` and
+//! `ts_produce_ns` headers generated here plus the versioned envelope, the
+//! exchange's clock offset once it's been measured, and a signature when
+//! signing keys are configured. With a [`Queue`], sends wait for room in it
+//! rather than for the broker. Payloads over the [`Limit`] never reach the
+//! topic: they're dropped or go to the oversize dead-letter topic.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use bus::envelope::{Envelope, CLOCK_OFFSET_MS, CONTRACT_SIZE, LISTING};
+use bus::oversize::{Action, Limit, OVERSIZE_BYTES};
+use bus::sign::Keyring;
+use bus::{Headers, Publisher, MSG_ID, TS_PRODUCE_NS};
+use chrono::Utc;
+use fetcher::clock;
+use fetcher::listings::ListingMarker;
+use fetcher::queue::{self, Queue};
+use metrics::counter;
+use obsv::load::Load;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct RawSink {
+    publisher: Arc<dyn Publisher>,
+    topic: String,
+    envelope: Arc<Envelope>,
+    keys: Option<Arc<Keyring>>,
+    contract_size: Option<String>,
+    queue: Option<Queue>,
+    limit: Option<Limit>,
+    oversize_dlq: Option<String>,
+    load: Option<Load>,
+}
+
+impl RawSink {
+    pub fn new(publisher: Arc<dyn Publisher>, topic: String, envelope: Envelope) -> Self {
+        Self { publisher, topic, envelope: Arc::new(envelope), keys: None, contract_size: None, queue: None, limit: None, oversize_dlq: None, load: None }
+    }
+
+    /// Sign everything sent with the keyring's signing key.
+    pub fn signed(mut self, keys: Option<Arc<Keyring>>) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    /// Stamp everything sent with the instrument's `contract_size`.
+    pub fn contract_size(mut self, size: Option<f64>) -> Self {
+        self.contract_size = size.map(|s| s.to_string());
+        self
+    }
+
+    /// Publish through a [`Queue`] of `capacity` messages over `lanes`
+    /// publishers, each with `in_flight` publishes going; `capacity` 0
+    /// publishes inline, each send waiting for the broker.
+    pub fn queued(mut self, capacity: usize, lanes: usize, in_flight: usize) -> Self {
+        self.queue = Some(capacity).filter(|c| *c > 0).map(|c| Queue::spawn(self.publisher.clone(), &self.topic, c, lanes, in_flight));
+        self
+    }
+
+    /// Hold payloads to `limit`; those over it that aren't dropped go to `dlq`.
+    pub fn limited(mut self, limit: Option<Limit>, dlq: Option<String>) -> Self {
+        self.limit = limit;
+        self.oversize_dlq = dlq;
+        self
+    }
+
+    /// Count each send, and the time it takes, in `load`.
+    pub fn loaded(mut self, load: Load) -> Self {
+        self.load = Some(load);
+        self
+    }
+
+    /// The cap [`limited`](Self::limited) set.
+    pub fn limit(&self) -> Option<Limit> {
+        self.limit
+    }
+
+    /// Wrap `payload` in the envelope and publish (or queue) it; failures are logged, not returned.
+    pub async fn send(&self, symbol: &str, payload: &[u8]) {
+        let _ = self.send_as(symbol, payload, None, false).await;
+    }
+
+    /// As [`send`](Self::send), but wait for the broker to take it, through
+    /// the queue if there is one, and return its failure. For a caller that
+    /// answers for the message, like the ingest server.
+    pub async fn deliver(&self, symbol: &str, payload: &[u8]) -> Result<()> {
+        self.send_as(symbol, payload, None, true).await
+    }
+
+    /// Publish `marker` under `symbol`'s key, so it sits in order with the symbol's trades.
+    pub async fn listing(&self, symbol: &str, marker: &ListingMarker) {
+        match serde_json::to_vec(marker) {
+            Ok(body) => { let _ = self.send_as(symbol, &body, Some(marker.event.name()), false).await; }
+            Err(e) => tracing::error!(target="fetcher", error=?e, "listing marker didn't serialize"),
+        }
+    }
+
+    async fn send_as(&self, symbol: &str, payload: &[u8], listing: Option<&str>, acked: bool) -> Result<()> {
+        let _busy = self.load.as_ref().map(|l| {
+            l.processed(1);
+            l.busy()
+        });
+        if let Some(limit) = &self.limit {
+            if let Some(action) = limit.exceeded("fetcher", payload.len()) {
+                self.oversize(limit, action, symbol, payload).await;
+                return Ok(());
+            }
+        }
+        let msg_id = Uuid::new_v4().to_string();
+        let ts_produce_ns = Utc::now().timestamp_nanos_opt().unwrap().to_string();
+
+        counter!("produced_total").increment(1);
+
+        let mut headers = self.envelope.apply(Headers::new()
+            .with(MSG_ID, &msg_id)
+            .with(TS_PRODUCE_NS, &ts_produce_ns));
+        if let Some(size) = &self.contract_size {
+            headers = headers.with(CONTRACT_SIZE, size);
+        }
+        if let Some(offset) = clock::offset_ms(&self.envelope.exchange) {
+            headers = headers.with(CLOCK_OFFSET_MS, &offset.to_string());
+        }
+        if let Some(event) = listing {
+            headers = headers.with(LISTING, event);
+        }
+        let headers = match &self.keys {
+            Some(k) => k.signed(payload, headers),
+            None => headers,
+        };
+        match &self.queue {
+            Some(q) if acked => q.push_acked(symbol, payload.to_vec(), headers).await,
+            Some(q) => {
+                q.push(symbol, payload.to_vec(), headers).await;
+                Ok(())
+            }
+            None => queue::publish(self.publisher.as_ref(), &self.topic, symbol, payload, &headers).await,
+        }
+    }
+
+    /// Dead-letter an oversize payload, under the envelope and never signed:
+    /// nothing downstream should take it for a trade.
+    async fn oversize(&self, limit: &Limit, action: Action, symbol: &str, payload: &[u8]) {
+        tracing::warn!(target="fetcher", symbol, bytes=payload.len(), max=limit.max_bytes, action=action.name(), "oversize payload");
+        let Some(topic) = self.oversize_dlq.as_deref().filter(|_| action != Action::Drop) else { return };
+        let headers = self.envelope.apply(Headers::new()
+            .with(MSG_ID, &Uuid::new_v4().to_string())
+            .with(OVERSIZE_BYTES, &payload.len().to_string()));
+        let _ = queue::publish(self.publisher.as_ref(), topic, symbol, limit.dead_letter(payload), &headers).await;
+    }
+}
@@ -0,0 +1,39 @@
+//! The startup check on the exchange: its feed host resolves, and a TLS
+//! handshake with it completes, before the fetcher starts subscribing.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `(host, port, https URL)` for a `wss://`, `ws://`, `https://` or `http://` feed URL.
+pub fn target(url: &str) -> Result<(String, u16, String)> {
+    let mut u = reqwest::Url::parse(url).with_context(|| format!("bad exchange URL {:?}", url))?;
+    let host = u.host_str().with_context(|| format!("exchange URL {:?} has no host", url))?.to_string();
+    let port = u.port_or_known_default().with_context(|| format!("exchange URL {:?} has no port", url))?;
+    let scheme = match u.scheme() {
+        "wss" | "https" => "https",
+        "ws" | "http" => "http",
+        other => anyhow::bail!("exchange URL {:?} has scheme {:?} (expected ws(s) or http(s))", url, other),
+    };
+    u.set_scheme(scheme).ok();
+    u.set_path("/");
+    u.set_query(None);
+    Ok((host, port, u.to_string()))
+}
+
+/// Resolve `url`'s host, then complete a TLS handshake with it; any HTTP reply
+/// at all, an error status included, shows both worked.
+pub async fn check(http: &reqwest::Client, url: &str) -> Result<()> {
+    let (host, port, https) = target(url)?;
+    let addrs: Vec<_> = tokio::time::timeout(TIMEOUT, tokio::net::lookup_host((host.as_str(), port)))
+        .await
+        .with_context(|| format!("resolving {} timed out", host))?
+        .with_context(|| format!("resolving {}", host))?
+        .collect();
+    anyhow::ensure!(!addrs.is_empty(), "{} resolved to no addresses", host);
+    http.get(&https).timeout(TIMEOUT).send().await.with_context(|| format!("connecting to {}:{}", host, port))?;
+    tracing::info!(target: "fetcher", host, port, addrs = addrs.len(), "exchange reachable");
+    Ok(())
+}
@@ -0,0 +1,158 @@
+//! What each subscription is receiving, so a stalled one shows up by name
+//! rather than as a dip in the aggregate `produced_total`.
+//!
+//! Every frame off a connection counts towards
+//! `ws_messages_total{exchange,stream}` and `ws_bytes_total{exchange,stream}`,
+//! control frames included. Frames that belong to a symbol also count towards
+//! that symbol's [`Subscription`]. [`spawn_reporter`] turns those into
+//! `ws_symbol_message_rate{exchange,stream,symbol}` (messages a second since
+//! the last report) and `ws_last_message_age_seconds{exchange,stream,symbol}`.
+//! A subscription that has never received anything ages from when it was
+//! made, so one that never starts is as visible as one that stops.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+use metrics::{counter, gauge, Counter};
+
+/// A connection's frame and byte counters.
+#[derive(Clone)]
+pub struct Frames {
+    messages: Counter,
+    bytes: Counter,
+}
+
+impl Frames {
+    pub fn new(exchange: &str, stream: &str) -> Self {
+        let labels = [("exchange", exchange.to_string()), ("stream", stream.to_string())];
+        Self { messages: counter!("ws_messages_total", &labels), bytes: counter!("ws_bytes_total", &labels) }
+    }
+
+    pub fn record(&self, bytes: usize) {
+        self.messages.increment(1);
+        self.bytes.increment(bytes as u64);
+    }
+}
+
+/// One report's worth for one subscription.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub exchange: String,
+    pub stream: String,
+    pub symbol: String,
+    /// Messages a second since the previous report.
+    pub rate: f64,
+    /// Since the last message, or since the subscription was made.
+    pub age: Duration,
+}
+
+struct Slot {
+    exchange: String,
+    stream: String,
+    symbol: String,
+    count: AtomicU64,
+    /// Count at the previous report.
+    reported: AtomicU64,
+    /// Nanoseconds after the tracker's epoch.
+    last_ns: AtomicU64,
+}
+
+/// A symbol's subscription on one exchange stream; cheap to clone and to record on.
+#[derive(Clone)]
+pub struct Subscription {
+    slot: Arc<Slot>,
+    epoch: Instant,
+}
+
+impl Subscription {
+    pub fn received(&self) {
+        self.received_at(Instant::now());
+    }
+
+    pub fn received_at(&self, now: Instant) {
+        self.slot.count.fetch_add(1, Ordering::Relaxed);
+        self.slot.last_ns.fetch_max(nanos_since(self.epoch, now), Ordering::Relaxed);
+    }
+}
+
+fn nanos_since(epoch: Instant, t: Instant) -> u64 {
+    t.saturating_duration_since(epoch).as_nanos() as u64
+}
+
+/// Every subscription made, for reporting.
+pub struct Tracker {
+    epoch: Instant,
+    slots: Mutex<Vec<Arc<Slot>>>,
+    last_report: Mutex<Instant>,
+}
+
+impl Tracker {
+    pub fn new(epoch: Instant) -> Self {
+        Self { epoch, slots: Mutex::new(Vec::new()), last_report: Mutex::new(epoch) }
+    }
+
+    /// The one [`subscribe`] and [`spawn_reporter`] share.
+    pub fn global() -> &'static Tracker {
+        static TRACKER: OnceLock<Tracker> = OnceLock::new();
+        TRACKER.get_or_init(|| Tracker::new(Instant::now()))
+    }
+
+    pub fn subscribe(&self, exchange: &str, stream: &str, symbol: &str, now: Instant) -> Subscription {
+        let slot = Arc::new(Slot {
+            exchange: exchange.into(),
+            stream: stream.into(),
+            symbol: symbol.into(),
+            count: AtomicU64::new(0),
+            reported: AtomicU64::new(0),
+            last_ns: AtomicU64::new(nanos_since(self.epoch, now)),
+        });
+        self.slots.lock().unwrap_or_else(PoisonError::into_inner).push(slot.clone());
+        Subscription { slot, epoch: self.epoch }
+    }
+
+    /// Rates since the previous call, and ages at `now`.
+    pub fn report(&self, now: Instant) -> Vec<Report> {
+        let elapsed = {
+            let mut last = self.last_report.lock().unwrap_or_else(PoisonError::into_inner);
+            let elapsed = now.saturating_duration_since(*last);
+            *last = now;
+            elapsed
+        };
+        let now_ns = nanos_since(self.epoch, now);
+        self.slots.lock().unwrap_or_else(PoisonError::into_inner).iter()
+            .map(|s| {
+                let count = s.count.load(Ordering::Relaxed);
+                let since = count - s.reported.swap(count, Ordering::Relaxed);
+                let rate = if elapsed.is_zero() { 0.0 } else { since as f64 / elapsed.as_secs_f64() };
+                Report {
+                    exchange: s.exchange.clone(),
+                    stream: s.stream.clone(),
+                    symbol: s.symbol.clone(),
+                    rate,
+                    age: Duration::from_nanos(now_ns.saturating_sub(s.last_ns.load(Ordering::Relaxed))),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A subscription on the global tracker.
+pub fn subscribe(exchange: &str, stream: &str, symbol: &str) -> Subscription {
+    Tracker::global().subscribe(exchange, stream, symbol, Instant::now())
+}
+
+/// Set the per-symbol gauges from the global tracker every `every`.
+pub fn spawn_reporter(every: Duration) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(every);
+        loop {
+            tick.tick().await;
+            for r in Tracker::global().report(Instant::now()) {
+                let labels = [("exchange", r.exchange), ("stream", r.stream), ("symbol", r.symbol)];
+                gauge!("ws_symbol_message_rate", &labels).set(r.rate);
+                gauge!("ws_last_message_age_seconds", &labels).set(r.age.as_secs_f64());
+            }
+        }
+    });
+}
@@ -0,0 +1,42 @@
+//! Deterministic symbol-to-instance assignment for horizontally scaled fetchers.
+//!
+//! Every instance is started with the same symbol list plus its own
+//! `SHARD_INDEX` out of `SHARD_COUNT`. Rendezvous (highest-random-weight)
+//! hashing picks exactly one owner per symbol, so instances agree without
+//! talking to each other and changing the count only moves ~1/N symbols.
+
+/// FNV-1a 64 — stable across builds and Rust versions, unlike `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in bytes {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    h
+}
+
+/// Final avalanche (splitmix64) so nearby instance indexes don't produce correlated weights.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn weight(symbol: &str, instance: u32) -> u64 {
+    mix(fnv1a(symbol.as_bytes()) ^ mix(instance as u64))
+}
+
+/// Index of the instance (0..count) that owns `symbol`.
+pub fn owner(symbol: &str, count: u32) -> u32 {
+    (0..count.max(1))
+        .max_by_key(|&i| weight(symbol, i))
+        .unwrap_or(0)
+}
+
+/// Symbols from `all` assigned to instance `index` of `count`.
+pub fn assigned(all: &[String], index: u32, count: u32) -> Vec<String> {
+    all.iter()
+        .filter(|s| owner(s, count) == index)
+        .cloned()
+        .collect()
+}
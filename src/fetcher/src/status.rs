@@ -0,0 +1,272 @@
+//! Whether each exchange is up, so an empty feed can be told apart from an
+//! exchange that's down for maintenance or unreachable.
+//!
+//! Two things feed it: polling a venue's system-status endpoint (see
+//! [`Check`]) and the notices some venues send on the WebSocket before they
+//! take a server away. Every change is counted in
+//! `exchange_status_changes_total{exchange,status}`, sets
+//! `exchange_status{exchange}` (0 up, 1 maintenance, 2 down) and, once
+//! [`spawn`]ed with a topic, is published there as a [`StatusEvent`] keyed
+//! by exchange.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bus::{Headers, Publisher};
+use metrics::{counter, gauge};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+/// Failed polls in a row before an exchange counts as down.
+pub const DOWN_AFTER: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Up,
+    Maintenance,
+    Down,
+}
+
+impl Status {
+    /// The `exchange_status` gauge's value.
+    pub fn level(self) -> f64 {
+        match self {
+            Status::Up => 0.0,
+            Status::Maintenance => 1.0,
+            Status::Down => 2.0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Up => "up",
+            Status::Maintenance => "maintenance",
+            Status::Down => "down",
+        }
+    }
+}
+
+/// Where a status came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Poll,
+    Ws,
+}
+
+/// One `exchange_status` record: the exchange moved from `previous` to `status`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusEvent {
+    pub exchange: String,
+    pub status: Status,
+    /// `None` for the first status seen since startup.
+    pub previous: Option<Status>,
+    pub source: Source,
+    /// The venue's own words, or the poll error.
+    pub message: String,
+    pub ts_ms: i64,
+}
+
+/// How to read a status endpoint's response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    /// `GET /sapi/v1/system/status`: `{"status":0,"msg":"normal"}`, 1 is maintenance.
+    BinanceSystem,
+    /// `GET /api/v1/status`: `data.status` is `open`, `close` or `cancelonly`.
+    Kucoin,
+    /// Any 2xx is up; for venues with no status endpoint but a ping.
+    Reachable,
+}
+
+impl Check {
+    /// What `(status, message)` the 2xx response `body` reports.
+    pub fn parse(self, body: &str) -> Result<(Status, String)> {
+        match self {
+            Check::BinanceSystem => {
+                let v: Value = serde_json::from_str(body).context("bad Binance system status")?;
+                let msg = v["msg"].as_str().unwrap_or_default().to_string();
+                match v["status"].as_i64() {
+                    Some(0) => Ok((Status::Up, msg)),
+                    Some(1) => Ok((Status::Maintenance, msg)),
+                    other => anyhow::bail!("unknown Binance system status {:?}", other),
+                }
+            }
+            Check::Kucoin => {
+                let v: Value = serde_json::from_str(body).context("bad KuCoin status")?;
+                let msg = v["data"]["msg"].as_str().unwrap_or_default().to_string();
+                match v["data"]["status"].as_str() {
+                    Some("open") => Ok((Status::Up, msg)),
+                    // Closed, or only taking cancels: either way, no trades.
+                    Some("close") | Some("cancelonly") => Ok((Status::Maintenance, msg)),
+                    other => anyhow::bail!("unknown KuCoin status {:?}", other),
+                }
+            }
+            Check::Reachable => Ok((Status::Up, String::new())),
+        }
+    }
+}
+
+/// The status endpoint polled by default for `source` on `market`, if the venue has one.
+pub fn endpoint(source: &str, market: &str) -> Option<(String, Check)> {
+    match (source, market) {
+        ("binance", "spot") => Some(("https://api.binance.com/sapi/v1/system/status".into(), Check::BinanceSystem)),
+        ("binance", "usdm") => Some(("https://fapi.binance.com/fapi/v1/ping".into(), Check::Reachable)),
+        ("binance", "coinm") => Some(("https://dapi.binance.com/dapi/v1/ping".into(), Check::Reachable)),
+        ("kucoin", _) => Some(("https://api.kucoin.com/api/v1/status".into(), Check::Kucoin)),
+        _ => None,
+    }
+}
+
+/// Binance's notice that a server is about to go away, `{"e":"serverShutdown"}`
+/// on its own or wrapped in `event`.
+pub fn binance_notice(frame: &str) -> Option<String> {
+    if !frame.contains("serverShutdown") {
+        return None;
+    }
+    let v: Value = serde_json::from_str(frame).ok()?;
+    let e = if v.get("event").is_some_and(Value::is_object) { &v["event"] } else { &v };
+    (e.get("e")?.as_str()? == "serverShutdown").then(|| "server shutdown".to_string())
+}
+
+/// The latest status of each exchange, and the events its changes make.
+#[derive(Default)]
+pub struct Tracker {
+    current: HashMap<String, (Status, Source)>,
+    failures: HashMap<String, u32>,
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn status(&self, exchange: &str) -> Option<Status> {
+        self.current.get(exchange).map(|(s, _)| *s)
+    }
+
+    fn set(&mut self, exchange: &str, status: Status, source: Source, message: String, ts_ms: i64) -> Option<StatusEvent> {
+        let previous = self.current.insert(exchange.to_string(), (status, source)).map(|(s, _)| s);
+        (previous != Some(status)).then(|| StatusEvent { exchange: exchange.into(), status, previous, source, message, ts_ms })
+    }
+
+    /// A poll's outcome. Errors only make the exchange down after [`DOWN_AFTER`] in a row.
+    pub fn polled(&mut self, exchange: &str, res: Result<(Status, String)>, ts_ms: i64) -> Option<StatusEvent> {
+        match res {
+            Ok((status, message)) => {
+                self.failures.remove(exchange);
+                self.set(exchange, status, Source::Poll, message, ts_ms)
+            }
+            Err(e) => {
+                let failures = self.failures.entry(exchange.to_string()).or_default();
+                *failures += 1;
+                match *failures >= DOWN_AFTER {
+                    true => self.set(exchange, Status::Down, Source::Poll, format!("{:#}", e), ts_ms),
+                    false => None,
+                }
+            }
+        }
+    }
+
+    /// A maintenance notice off the WebSocket.
+    pub fn notice(&mut self, exchange: &str, message: String, ts_ms: i64) -> Option<StatusEvent> {
+        self.set(exchange, Status::Maintenance, Source::Ws, message, ts_ms)
+    }
+
+    /// A WebSocket (re)connected and subscribed. That ends maintenance a
+    /// notice started, but not one the status endpoint reports.
+    pub fn connected(&mut self, exchange: &str, ts_ms: i64) -> Option<StatusEvent> {
+        match self.current.get(exchange) {
+            Some((Status::Maintenance, Source::Ws)) => self.set(exchange, Status::Up, Source::Ws, "reconnected".into(), ts_ms),
+            _ => None,
+        }
+    }
+}
+
+fn global() -> &'static Mutex<Tracker> {
+    static TRACKER: OnceLock<Mutex<Tracker>> = OnceLock::new();
+    TRACKER.get_or_init(Default::default)
+}
+
+static EVENTS: OnceLock<mpsc::UnboundedSender<StatusEvent>> = OnceLock::new();
+
+fn emit(event: Option<StatusEvent>) {
+    let Some(event) = event else { return };
+    tracing::warn!(target: "fetcher", exchange = %event.exchange, status = event.status.name(),
+        previous = ?event.previous.map(Status::name), message = %event.message, "exchange status changed");
+    counter!("exchange_status_changes_total", "exchange" => event.exchange.clone(), "status" => event.status.name()).increment(1);
+    gauge!("exchange_status", "exchange" => event.exchange.clone()).set(event.status.level());
+    if let Some(tx) = EVENTS.get() {
+        let _ = tx.send(event);
+    }
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// Record a maintenance notice `exchange` sent on its WebSocket.
+pub fn notice(exchange: &str, message: String) {
+    let event = global().lock().unwrap_or_else(PoisonError::into_inner).notice(exchange, message, now_ms());
+    emit(event);
+}
+
+/// Record that a WebSocket to `exchange` is connected and subscribed.
+pub fn connected(exchange: &str) {
+    let event = global().lock().unwrap_or_else(PoisonError::into_inner).connected(exchange, now_ms());
+    emit(event);
+}
+
+/// A status endpoint to poll.
+#[derive(Debug, Clone)]
+pub struct Poll {
+    pub exchange: String,
+    pub url: String,
+    pub check: Check,
+    pub every: Duration,
+}
+
+async fn check(http: &reqwest::Client, poll: &Poll) -> Result<(Status, String)> {
+    let req = http.get(&poll.url);
+    let body = ratelimit::shared(&poll.exchange)?.send(req, 1).await?.error_for_status()?.text().await?;
+    poll.check.parse(&body)
+}
+
+/// Publish status changes to `topic` (none when empty) and poll `poll`, if given.
+pub fn spawn(publisher: Arc<dyn Publisher>, topic: String, poll: Option<Poll>) -> Result<()> {
+    if !topic.is_empty() {
+        let (tx, mut rx) = mpsc::unbounded_channel::<StatusEvent>();
+        let _ = EVENTS.set(tx);
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                let Ok(body) = serde_json::to_vec(&event) else { continue };
+                // Changes are rare and each one matters: retry until the broker takes it.
+                while let Err(e) = publisher.publish(&topic, &event.exchange, &body, &Headers::new()).await {
+                    tracing::warn!(target: "fetcher", error = ?e, topic = %topic, "status publish failed; will retry");
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+            }
+        });
+    }
+    if let Some(poll) = poll {
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?;
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(poll.every);
+            tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tick.tick().await;
+                let res = check(&http, &poll).await;
+                if let Err(e) = &res {
+                    tracing::warn!(target: "fetcher", exchange = %poll.exchange, error = ?e, "status poll failed");
+                    counter!("exchange_status_poll_errors_total", "exchange" => poll.exchange.clone()).increment(1);
+                }
+                let event = global().lock().unwrap_or_else(PoisonError::into_inner).polled(&poll.exchange, res, now_ms());
+                emit(event);
+            }
+        });
+    }
+    Ok(())
+}
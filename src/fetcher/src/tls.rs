@@ -0,0 +1,175 @@
+//! TLS, optionally mutual, in front of the fetcher's HTTP servers.
+//!
+//! `<PREFIX>_TLS_CERT` and `<PREFIX>_TLS_KEY` (PEM files) turn TLS on.
+//! `<PREFIX>_TLS_CLIENT_CA` also asks clients for a certificate signed by
+//! that CA, and `<PREFIX>_TLS_CLIENT_AUTH=optional` lets clients without one
+//! fall back to a bearer token. The certificate's common name (or its first
+//! DNS name) becomes the connection's identity.
+//!
+//! Handshakes run off the accept loop, so a client that stalls one holds up
+//! only itself.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum::extract::connect_info::Connected;
+use axum::serve::{IncomingStream, Listener};
+use metrics::counter;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct TlsOptions {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub client_ca: Option<PathBuf>,
+    /// Accept clients without a certificate (they still need a token).
+    pub client_optional: bool,
+}
+
+impl TlsOptions {
+    /// `None` unless both `<prefix>_TLS_CERT` and `<prefix>_TLS_KEY` are set.
+    pub fn from_env(prefix: &str) -> Result<Option<Self>> {
+        let var = |k: &str| std::env::var(format!("{}_{}", prefix, k)).ok().filter(|v| !v.is_empty());
+        let (cert, key) = match (var("TLS_CERT"), var("TLS_KEY")) {
+            (Some(c), Some(k)) => (c, k),
+            (None, None) => return Ok(None),
+            _ => anyhow::bail!("{0}_TLS_CERT and {0}_TLS_KEY go together", prefix),
+        };
+        let client_optional = match var("TLS_CLIENT_AUTH").as_deref().unwrap_or("required") {
+            "required" => false,
+            "optional" => true,
+            other => anyhow::bail!("unknown {}_TLS_CLIENT_AUTH {:?} (expected required|optional)", prefix, other),
+        };
+        Ok(Some(Self { cert: cert.into(), key: key.into(), client_ca: var("TLS_CLIENT_CA").map(PathBuf::from), client_optional }))
+    }
+
+    pub fn server_config(&self) -> Result<Arc<ServerConfig>> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let certs = CertificateDer::pem_file_iter(&self.cert)
+            .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+            .with_context(|| format!("reading {}", self.cert.display()))?;
+        let key = PrivateKeyDer::from_pem_file(&self.key).with_context(|| format!("reading {}", self.key.display()))?;
+        let builder = ServerConfig::builder_with_provider(provider.clone()).with_safe_default_protocol_versions()?;
+        let builder = match &self.client_ca {
+            None => builder.with_no_client_auth(),
+            Some(ca) => {
+                let mut roots = RootCertStore::empty();
+                for cert in CertificateDer::pem_file_iter(ca).with_context(|| format!("reading {}", ca.display()))? {
+                    roots.add(cert.with_context(|| format!("reading {}", ca.display()))?)?;
+                }
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                let verifier = if self.client_optional { verifier.allow_unauthenticated() } else { verifier };
+                builder.with_client_cert_verifier(verifier.build()?)
+            }
+        };
+        let mut config = builder.with_single_cert(certs, key).context("TLS certificate and key don't match")?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(Arc::new(config))
+    }
+}
+
+/// The far end of a connection.
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub addr: SocketAddr,
+    /// From the verified client certificate, if there was one.
+    pub identity: Option<String>,
+}
+
+/// The common name of `cert`, or its first DNS name.
+pub fn identity(cert: &CertificateDer<'_>) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+    let cn = cert.subject().iter_common_name().next().and_then(|cn| cn.as_str().ok()).map(str::to_string);
+    cn.or_else(|| {
+        let san = cert.subject_alternative_name().ok()??;
+        san.value.general_names.iter().find_map(|n| match n {
+            x509_parser::extensions::GeneralName::DNSName(d) => Some(d.to_string()),
+            _ => None,
+        })
+    })
+}
+
+/// A [`Listener`] that hands axum finished TLS connections.
+pub struct TlsListener {
+    rx: mpsc::Receiver<(TlsStream<TcpStream>, Peer)>,
+    local: SocketAddr,
+}
+
+impl TlsListener {
+    /// Accept on `tcp` in the background; `server` labels handshake failures.
+    pub fn new(tcp: TcpListener, config: Arc<ServerConfig>, server: &'static str) -> Result<Self> {
+        let local = tcp.local_addr()?;
+        let acceptor = TlsAcceptor::from(config);
+        let (tx, rx) = mpsc::channel(64);
+        tokio::spawn(async move {
+            while !tx.is_closed() {
+                let (stream, addr) = match tcp.accept().await {
+                    Ok(s) => s,
+                    Err(e) => {
+                        // Usually out of file descriptors; give some back time to close.
+                        tracing::warn!(target: "fetcher", server, error = ?e, "accept failed");
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let (acceptor, tx) = (acceptor.clone(), tx.clone());
+                tokio::spawn(async move {
+                    let reason = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(tls)) => {
+                            let identity = tls.get_ref().1.peer_certificates().and_then(|c| c.first()).and_then(identity);
+                            let _ = tx.send((tls, Peer { addr, identity })).await;
+                            return;
+                        }
+                        Ok(Err(e)) => {
+                            tracing::debug!(target: "fetcher", server, %addr, error = ?e, "TLS handshake failed");
+                            "error"
+                        }
+                        Err(_) => "timeout",
+                    };
+                    counter!("tls_handshake_failures_total", "server" => server, "reason" => reason).increment(1);
+                });
+            }
+        });
+        Ok(Self { rx, local })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = Peer;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.rx.recv().await {
+            Some(conn) => conn,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(Peer { addr: self.local, identity: None })
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for Peer {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        stream.remote_addr().clone()
+    }
+}
+
+impl Connected<IncomingStream<'_, TcpListener>> for Peer {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        Peer { addr: *stream.remote_addr(), identity: None }
+    }
+}
@@ -0,0 +1,75 @@
+//! Upbit trades, and Bithumb's, which serves the same WebSocket protocol:
+//! one `trade` subscription for every market, written quote first
+//! (`KRW-BTC`), with frames sent as binary JSON.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::venue::{Connection, ExchangeSource, Pair};
+
+pub struct Upbit {
+    name: &'static str,
+    url: String,
+}
+
+impl Upbit {
+    pub fn new(name: &'static str, url: &str) -> Self {
+        Self { name, url: url.to_string() }
+    }
+
+    /// `UPBIT_WS_URL`, default `wss://api.upbit.com/websocket/v1`.
+    pub fn upbit_from_env() -> Self {
+        Self::new("upbit", &std::env::var("UPBIT_WS_URL").unwrap_or_else(|_| "wss://api.upbit.com/websocket/v1".into()))
+    }
+
+    /// `BITHUMB_WS_URL`, default `wss://ws-api.bithumb.com/websocket/v1`.
+    pub fn bithumb_from_env() -> Self {
+        Self::new("bithumb", &std::env::var("BITHUMB_WS_URL").unwrap_or_else(|_| "wss://ws-api.bithumb.com/websocket/v1".into()))
+    }
+}
+
+/// `KRW-BTC` for BTC quoted in KRW.
+pub fn market_code(pair: &Pair) -> String {
+    format!("{}-{}", pair.quote, pair.base)
+}
+
+#[async_trait]
+impl ExchangeSource for Upbit {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn endpoint(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn connect(&self, _pairs: &[Pair]) -> Result<Connection> {
+        // Idle connections are closed after 120s.
+        Ok(Connection { url: self.url.clone(), ping_every: Some(Duration::from_secs(60)) })
+    }
+
+    fn subscribe(&self, pairs: &[Pair]) -> Vec<String> {
+        let codes: Vec<String> = pairs.iter().map(market_code).collect();
+        vec![json!([
+            { "ticket": uuid::Uuid::new_v4().to_string() },
+            { "type": "trade", "codes": codes },
+            { "format": "DEFAULT" },
+        ]).to_string()]
+    }
+
+    fn ping(&self) -> Option<String> {
+        Some("PING".into())
+    }
+
+    fn trade_key(&self, frame: &str) -> Option<String> {
+        let v: Value = serde_json::from_str(frame).ok()?;
+        if v.get("type")?.as_str()? != "trade" {
+            return None;
+        }
+        let (quote, base) = v.get("code")?.as_str()?.split_once('-')?;
+        Some(format!("{}{}", base, quote).to_lowercase())
+    }
+}
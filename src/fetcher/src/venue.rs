@@ -0,0 +1,99 @@
+//! Exchanges whose public trades come over one subscribed WebSocket.
+//!
+//! An [`ExchangeSource`] knows how to reach its venue, what to subscribe to,
+//! how to keep the connection alive and which frames are trades; the
+//! fetcher's loop does the rest (reconnects, chaos, the raw envelope).
+//! Frames are forwarded untouched, and the producer normalizes each venue's
+//! format.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+/// Quote currencies recognised at the end of a symbol written without a separator.
+const QUOTES: &[&str] = &["USDT", "USDC", "FDUSD", "TUSD", "BUSD", "USD", "EUR", "GBP", "TRY", "BRL", "KRW", "BTC", "ETH", "BNB"];
+
+/// A `base`/`quote` currency pair.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pair {
+    pub base: String,
+    pub quote: String,
+}
+
+impl Pair {
+    /// `btc_usdt`, `BTC-USDT`, `btc/usdt`, or `btcusdt` when the quote is a
+    /// common one.
+    pub fn parse(symbol: &str) -> Result<Self> {
+        let s = symbol.trim().to_uppercase();
+        if let Some((base, quote)) = s.split_once(['_', '-', '/']) {
+            anyhow::ensure!(!base.is_empty() && !quote.is_empty(), "bad pair {:?}", symbol);
+            return Ok(Self { base: base.into(), quote: quote.into() });
+        }
+        QUOTES.iter()
+            .find(|q| s.len() > q.len() && s.ends_with(*q))
+            .map(|q| Self { base: s[..s.len() - q.len()].into(), quote: q.to_string() })
+            .ok_or_else(|| anyhow::anyhow!("can't tell base from quote in {:?}; write it as BASE_QUOTE", symbol))
+    }
+
+    pub fn join(&self, sep: &str) -> String {
+        format!("{}{}{}", self.base, sep, self.quote)
+    }
+
+    /// The raw message key: `btcusdt`, as for Binance.
+    pub fn key(&self) -> String {
+        format!("{}{}", self.base, self.quote).to_lowercase()
+    }
+}
+
+/// Where and how to connect, decided afresh for each connection.
+#[derive(Debug, Clone)]
+pub struct Connection {
+    pub url: String,
+    /// Send [`ExchangeSource::ping`] this often.
+    pub ping_every: Option<Duration>,
+}
+
+#[async_trait]
+pub trait ExchangeSource: Send + Sync {
+    /// `EXCHANGE` on the raw envelope and the `venue` label in metrics.
+    fn name(&self) -> &'static str;
+
+    /// The URL the venue is reached at first (its WebSocket, or the REST
+    /// endpoint that hands one out), for the startup check.
+    fn endpoint(&self) -> String;
+
+    /// Where to connect for `pairs`; any handshake the venue wants first happens here.
+    async fn connect(&self, pairs: &[Pair]) -> Result<Connection>;
+
+    /// Frames to send once connected.
+    fn subscribe(&self, pairs: &[Pair]) -> Vec<String>;
+
+    /// The application-level keepalive frame, if the venue wants one.
+    fn ping(&self) -> Option<String> {
+        None
+    }
+
+    /// The key ([`Pair::key`]) of the trade in `frame`; `None` for acks,
+    /// pongs and anything else that isn't a trade.
+    fn trade_key(&self, frame: &str) -> Option<String>;
+
+    /// What the venue says, if `frame` warns that the server is going away
+    /// for maintenance.
+    fn maintenance_notice(&self, _frame: &str) -> Option<String> {
+        None
+    }
+}
+
+/// The source for `SOURCE=<name>`, if it's one of these.
+pub fn by_name(name: &str) -> Result<Option<Box<dyn ExchangeSource>>> {
+    Ok(match name {
+        "bithumb" => Some(Box::new(crate::upbit::Upbit::bithumb_from_env())),
+        "bitstamp" => Some(Box::new(crate::bitstamp::Bitstamp::from_env())),
+        "gate" => Some(Box::new(crate::gate::Gate::from_env())),
+        "gemini" => Some(Box::new(crate::gemini::Gemini::from_env())),
+        "kucoin" => Some(Box::new(crate::kucoin::Kucoin::from_env()?)),
+        "upbit" => Some(Box::new(crate::upbit::Upbit::upbit_from_env())),
+        _ => None,
+    })
+}
@@ -0,0 +1,130 @@
+//! The connection loop shared by every [`ExchangeSource`]: all of a shard's
+//! symbols on one WebSocket, reconnecting forever.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use fetcher::recv::{self, Frames, Subscription};
+use fetcher::status;
+use fetcher::venue::{ExchangeSource, Pair};
+use futures_util::{SinkExt, StreamExt};
+use metrics::counter;
+use obsv::errors::{self, ErrorKind};
+use retry::{Backoff, Policy};
+use bus::oversize::Limit;
+use tokio_tungstenite::connect_async_with_config;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::{self, Message};
+
+use crate::raw::RawSink;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Socket limits for payloads capped at `limit`, so a bigger frame or
+/// message fails the read instead of being buffered whole first.
+pub fn config(limit: Option<Limit>) -> Option<WebSocketConfig> {
+    limit.map(|l| WebSocketConfig { max_message_size: Some(l.max_bytes), max_frame_size: Some(l.max_bytes), ..Default::default() })
+}
+
+/// Whether a read failed on [`config`]'s cap.
+pub fn oversized(e: &tungstenite::Error) -> bool {
+    matches!(e, tungstenite::Error::Capacity(_))
+}
+
+pub async fn run(venue: Box<dyn ExchangeSource>, sink: RawSink, symbols: Vec<String>) -> Result<()> {
+    let pairs = symbols.iter().map(|s| Pair::parse(s)).collect::<Result<Vec<_>>>()?;
+    let frames = Frames::new(venue.name(), "trades");
+    let subs: HashMap<String, Subscription> = pairs.iter()
+        .map(|p| (p.key(), recv::subscribe(venue.name(), "trades", &p.key())))
+        .collect();
+    let policy = Policy::forever(Backoff::new(MIN_BACKOFF, MAX_BACKOFF));
+    let mut retry = policy.start("ws_connect");
+    loop {
+        match stream(venue.as_ref(), &sink, &pairs, &frames, &subs).await {
+            Ok(()) => retry.succeeded(),
+            Err(e) => {
+                tracing::error!(target: "fetcher", venue = venue.name(), error = ?e, "websocket connect failed");
+                errors::record("fetcher", ErrorKind::Transient);
+            }
+        }
+        counter!("ws_reconnects_total").increment(1);
+        retry.pause().await;
+    }
+}
+
+/// One connection's lifetime; `Ok` once it was established and then ended.
+async fn stream(venue: &dyn ExchangeSource, sink: &RawSink, pairs: &[Pair], frames: &Frames,
+    subs: &HashMap<String, Subscription>) -> Result<()> {
+    let conn = venue.connect(pairs).await?;
+    let (ws, _) = connect_async_with_config(conn.url.as_str(), config(sink.limit()), false).await?;
+    let (mut w, mut r) = ws.split();
+    for frame in venue.subscribe(pairs) {
+        w.send(Message::Text(frame)).await?;
+    }
+    tracing::info!(target: "fetcher", venue = venue.name(), symbols = pairs.len(), "connected and subscribed");
+    status::connected(venue.name());
+
+    let mut ping = tokio::time::interval(conn.ping_every.unwrap_or(Duration::MAX));
+    ping.tick().await;
+    let disconnect = chaos::ws_disconnect();
+    tokio::pin!(disconnect);
+
+    loop {
+        let msg = tokio::select! {
+            m = r.next() => match m {
+                Some(m) => m,
+                None => break,
+            },
+            _ = ping.tick(), if conn.ping_every.is_some() => {
+                if let Some(p) = venue.ping() {
+                    w.send(Message::Text(p)).await?;
+                }
+                continue;
+            }
+            _ = &mut disconnect => {
+                tracing::warn!(target: "fetcher", venue = venue.name(), "chaos: forcing websocket disconnect");
+                return Ok(());
+            }
+        };
+        let msg = match msg {
+            Ok(m) => m,
+            // The socket can't be read past it; a fresh connection skips it.
+            Err(e) if oversized(&e) => {
+                counter!("payload_oversize_total", "stage" => "fetcher", "action" => "reconnect").increment(1);
+                return Err(anyhow::Error::new(e).context("websocket message over MAX_PAYLOAD_BYTES"));
+            }
+            Err(e) => {
+                tracing::error!(target: "fetcher", error = ?e, "websocket error");
+                errors::record("fetcher", ErrorKind::Transient);
+                continue;
+            }
+        };
+        let text = match msg {
+            Message::Text(text) => text,
+            // Upbit and Bithumb send their JSON as binary frames.
+            Message::Binary(bytes) => match String::from_utf8(bytes) {
+                Ok(text) => text,
+                Err(_) => continue,
+            },
+            _ => continue,
+        };
+        frames.record(text.len());
+        match venue.trade_key(&text) {
+            Some(key) => {
+                if let Some(sub) = subs.get(&key) {
+                    sub.received();
+                }
+                sink.send(&key, text.as_bytes()).await
+            }
+            None => match venue.maintenance_notice(&text) {
+                Some(notice) => status::notice(venue.name(), notice),
+                None => tracing::trace!(target: "fetcher", venue = venue.name(), frame = %text, "control frame"),
+            },
+        }
+    }
+
+    tracing::warn!(target: "fetcher", venue = venue.name(), "websocket stream ended");
+    Ok(())
+}
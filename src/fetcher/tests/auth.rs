@@ -0,0 +1,133 @@
+use std::sync::Arc;
+
+use axum::extract::ConnectInfo;
+use axum::routing::get;
+use axum::Router;
+use fetcher::auth::Clients;
+use fetcher::tls::{Peer, TlsListener, TlsOptions};
+use rcgen::{BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_rustls::TlsConnector;
+
+#[test]
+fn clients_are_known_by_certificate_or_token_and_limited_to_their_symbols() {
+    let clients = Clients::parse("
+        # name     token       symbols
+        desk-a     tok_a       BTCUSDT,ethusdt
+        partner-x  -           XBT*
+        relay      tok_r       *
+    ").unwrap();
+    assert_eq!(clients.len(), 3);
+
+    let desk = clients.authenticate(None, Some("tok_a")).unwrap();
+    assert_eq!(desk.name, "desk-a");
+    assert!(desk.allows("btcusdt") && desk.allows("ETHUSDT") && !desk.allows("SOLUSDT"));
+    // Tokens are compared whole: a prefix or an extension of one is no good.
+    assert!(clients.authenticate(None, Some("tok_")).is_none());
+    assert!(clients.authenticate(None, Some("tok_ab")).is_none());
+
+    // Certificate-only: the token column can't be used to get in.
+    assert!(clients.authenticate(None, Some("-")).is_none());
+    let partner = clients.authenticate(Some("partner-x"), None).unwrap();
+    assert!(partner.allows("XBTUSD") && !partner.allows("BTCUSDT"));
+
+    // A known certificate wins over whatever token comes with it.
+    assert_eq!(clients.authenticate(Some("partner-x"), Some("tok_r")).unwrap().name, "partner-x");
+    assert_eq!(clients.authenticate(Some("stranger"), Some("tok_r")).unwrap().name, "relay");
+    assert!(clients.authenticate(Some("stranger"), None).is_none());
+
+    assert!(Clients::parse("a tok_1 BTCUSDT; b tok_1 ETHUSDT").is_err());
+    assert!(Clients::parse("a tok_1").is_err());
+    assert!(Clients::from_tokens("t1, t2").unwrap().authenticate(None, Some("t2")).unwrap().allows("ANYTHING"));
+}
+
+struct Pki {
+    dir: std::path::PathBuf,
+    ca: rcgen::Certificate,
+    ca_key: KeyPair,
+}
+
+impl Pki {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("fetcher-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ca_key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.distinguished_name.push(DnType::CommonName, "test ca");
+        let ca = params.self_signed(&ca_key).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+        Self { dir, ca, ca_key }
+    }
+
+    /// PEM certificate and key for `name`, signed by the CA.
+    fn issue(&self, name: &str, purpose: ExtendedKeyUsagePurpose) -> (String, String) {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.extended_key_usages = vec![purpose];
+        let cert = params.signed_by(&key, &self.ca, &self.ca_key).unwrap();
+        (cert.pem(), key.serialize_pem())
+    }
+}
+
+/// The body of GET / over TLS, optionally with a client certificate; `None` if refused.
+async fn get_identity(addr: std::net::SocketAddr, ca: &str, client: Option<&(String, String)>) -> Option<String> {
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from_pem_slice(ca.as_bytes()).unwrap()).unwrap();
+    let builder = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions().unwrap()
+        .with_root_certificates(roots);
+    let config = match client {
+        Some((cert, key)) => builder.with_client_auth_cert(
+            vec![CertificateDer::from_pem_slice(cert.as_bytes()).unwrap()],
+            PrivateKeyDer::from_pem_slice(key.as_bytes()).unwrap(),
+        ).unwrap(),
+        None => builder.with_no_client_auth(),
+    };
+    let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let mut tls = TlsConnector::from(Arc::new(config)).connect(ServerName::try_from("localhost").unwrap(), tcp).await.ok()?;
+    tls.write_all(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n").await.ok()?;
+    let mut out = String::new();
+    tls.read_to_string(&mut out).await.ok()?;
+    out.starts_with("HTTP/1.1 200").then(|| out.rsplit("\r\n\r\n").next().unwrap_or_default().to_string())
+}
+
+async fn serve(opts: &TlsOptions) -> std::net::SocketAddr {
+    let app = Router::new()
+        .route("/", get(|ConnectInfo(peer): ConnectInfo<Peer>| async move { peer.identity.unwrap_or_else(|| "anonymous".into()) }))
+        .into_make_service_with_connect_info::<Peer>();
+    let tcp = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = tcp.local_addr().unwrap();
+    let listener = TlsListener::new(tcp, opts.server_config().unwrap(), "test").unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    addr
+}
+
+#[tokio::test]
+async fn mutual_tls_identifies_clients_by_certificate() {
+    let pki = Pki::new();
+    let (cert, key) = pki.issue("localhost", ExtendedKeyUsagePurpose::ServerAuth);
+    std::fs::write(pki.dir.join("server.pem"), cert).unwrap();
+    std::fs::write(pki.dir.join("server.key"), key).unwrap();
+    let mut opts = TlsOptions {
+        cert: pki.dir.join("server.pem"),
+        key: pki.dir.join("server.key"),
+        client_ca: Some(pki.dir.join("ca.pem")),
+        client_optional: false,
+    };
+    let ca = std::fs::read_to_string(pki.dir.join("ca.pem")).unwrap();
+    let desk = pki.issue("desk-a", ExtendedKeyUsagePurpose::ClientAuth);
+
+    let required = serve(&opts).await;
+    assert_eq!(get_identity(required, &ca, Some(&desk)).await.as_deref(), Some("desk-a"));
+    assert_eq!(get_identity(required, &ca, None).await, None);
+
+    opts.client_optional = true;
+    let optional = serve(&opts).await;
+    assert_eq!(get_identity(optional, &ca, None).await.as_deref(), Some("anonymous"));
+    assert_eq!(get_identity(optional, &ca, Some(&desk)).await.as_deref(), Some("desk-a"));
+}
@@ -0,0 +1,57 @@
+use fetcher::clock::{endpoint, millis, Format, Sample, Tracker, DRIFT_AFTER_MS, MAX_RTT_MS};
+use serde_json::json;
+
+#[test]
+fn server_times_parse_per_venue_and_need_a_zone() {
+    assert_eq!(Format::Json("/serverTime").parse(None, r#"{"serverTime":1739880000138}"#).unwrap(), 1739880000138);
+    assert_eq!(Format::Json("/data").parse(None, r#"{"code":"200000","data":1739880000138}"#).unwrap(), 1739880000138);
+    assert!(Format::Json("/server_time").parse(None, r#"{"time":1}"#).is_err());
+    assert_eq!(Format::Date.parse(Some("Tue, 18 Feb 2025 12:00:00 GMT"), "").unwrap(), 1739880000500, "mid-second");
+    assert!(Format::Date.parse(None, "").is_err());
+
+    assert_eq!(millis(&json!("1739880000138")).unwrap(), 1739880000138);
+    assert_eq!(millis(&json!("2025-02-18T21:00:00.138+09:00")).unwrap(), 1739880000138);
+    assert_eq!(millis(&json!("2025-02-18T12:00:00.138Z")).unwrap(), 1739880000138);
+    assert!(millis(&json!("2025-02-18T12:00:00.138")).is_err(), "no zone, no guessing");
+    assert!(millis(&json!(1.5)).is_err());
+
+    assert_eq!(endpoint("binance", "usdm").map(|(_, f)| f), Some(Format::Json("/serverTime")));
+    assert_eq!(endpoint("bitstamp", "spot"), None);
+}
+
+#[test]
+fn the_offset_is_taken_from_the_middle_of_the_round_trip() {
+    let s = Sample::new(1_000, 1_300, 1_200);
+    assert_eq!((s.offset_ms, s.rtt_ms), (200.0, 200));
+    assert_eq!(Sample::new(1_000, 900, 1_000).offset_ms, -100.0, "the venue is behind");
+}
+
+#[test]
+fn offsets_are_smoothed_and_slow_samples_left_out() {
+    let mut t = Tracker::new();
+    let first = t.observe("binance", Sample::new(0, 150, 100), 100).unwrap();
+    assert_eq!((first.offset_ms, first.drift_ppm), (100.0, None));
+    let next = t.observe("binance", Sample::new(1_000, 1_300, 1_200), 1_200).unwrap();
+    assert!(next.offset_ms > 100.0 && next.offset_ms < 200.0, "{:?}", next);
+
+    assert_eq!(t.observe("binance", Sample::new(2_000, 9_000, 3_000 + MAX_RTT_MS), 3_000), None);
+    assert_eq!(t.estimate("binance").unwrap().offset_ms, next.offset_ms);
+    assert_eq!(t.estimate("kucoin"), None);
+}
+
+#[test]
+fn drift_shows_once_the_samples_span_long_enough() {
+    let mut t = Tracker::new();
+    t.observe("kucoin", Sample::new(0, 0, 0), 0);
+    // 1ms further ahead every minute: ~16.7ppm.
+    let mut last = None;
+    for minute in 1..=30 {
+        let now = minute * 60_000;
+        last = t.observe("kucoin", Sample::new(now, now + minute, now), now);
+        if now < DRIFT_AFTER_MS {
+            assert_eq!(last.unwrap().drift_ppm, None);
+        }
+    }
+    let ppm = last.unwrap().drift_ppm.unwrap();
+    assert!(ppm > 10.0 && ppm < 17.0, "{}", ppm);
+}
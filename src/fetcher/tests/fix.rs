@@ -0,0 +1,80 @@
+#![cfg(feature = "fix")]
+
+use chrono::NaiveDate;
+use fetcher::fix::{decode, msg_type, tag, trades, Message, Session, Skipped};
+use serde_json::Value;
+
+fn at() -> chrono::NaiveDateTime {
+    NaiveDate::from_ymd_opt(2025, 2, 18).unwrap().and_hms_milli_opt(12, 0, 0, 138).unwrap()
+}
+
+/// A counterparty message, framed the way our own are.
+fn from_venue(msg_type: &str, body: &[(u32, &str)]) -> Message {
+    let body: Vec<(u32, String)> = body.iter().map(|(t, v)| (*t, v.to_string())).collect();
+    let mut wire = Session::new("VENUE", "US").encode(msg_type, &body, at());
+    decode(&mut wire).unwrap().unwrap()
+}
+
+#[test]
+fn messages_round_trip_and_wait_for_the_whole_frame() {
+    let mut s = Session::new("US", "VENUE");
+    let logon = s.logon(30, Some(("user", "secret")), at());
+    let text = String::from_utf8(logon.clone()).unwrap().replace('\x01', "|");
+    assert!(text.starts_with("8=FIX.4.4|9="), "{}", text);
+    assert!(text.contains("|35=A|49=US|56=VENUE|34=1|52=20250218-12:00:00.138|98=0|108=30|141=Y|553=user|554=secret|10="));
+
+    let mut buf = logon[..logon.len() - 3].to_vec();
+    assert_eq!(decode(&mut buf).unwrap(), None);
+    buf.extend_from_slice(&logon[logon.len() - 3..]);
+    buf.extend_from_slice(&s.heartbeat(Some("ping"), at()));
+    let first = decode(&mut buf).unwrap().unwrap();
+    assert_eq!((first.msg_type(), first.seq_num(), first.get(tag::USERNAME)), (msg_type::LOGON, Some(1), Some("user")));
+    let second = decode(&mut buf).unwrap().unwrap();
+    assert_eq!((second.msg_type(), second.seq_num(), second.get(tag::TEST_REQ_ID)), (msg_type::HEARTBEAT, Some(2), Some("ping")));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn corrupt_frames_are_errors() {
+    let mut s = Session::new("US", "VENUE");
+    let mut flipped = s.heartbeat(None, at());
+    let i = flipped.iter().position(|b| *b == b'U').unwrap();
+    flipped[i] = b'X';
+    assert!(decode(&mut flipped).unwrap_err().to_string().contains("CheckSum"));
+    assert!(decode(&mut b"8=FIX.4.2\x019=5\x01".to_vec()).is_err());
+    assert!(decode(&mut b"8=FIX.4.4\x019=99999999\x01".to_vec()).is_err());
+}
+
+#[test]
+fn market_data_request_asks_for_incremental_trades() {
+    let mut wire = Session::new("US", "VENUE").market_data_request("trades", &["BTC/USD".into(), "ETH/USD".into()], at());
+    let m = decode(&mut wire).unwrap().unwrap();
+    assert_eq!(m.msg_type(), msg_type::MARKET_DATA_REQUEST);
+    // Everything after the header (MsgType, SenderCompID, TargetCompID, MsgSeqNum, SendingTime).
+    let body: Vec<(u32, &str)> = m.fields[7..m.fields.len() - 1].iter().map(|(t, v)| (*t, v.as_str())).collect();
+    assert_eq!(body, [(262, "trades"), (263, "1"), (264, "0"), (265, "1"), (267, "1"), (269, "2"), (146, "2"), (55, "BTC/USD"), (55, "ETH/USD")]);
+}
+
+#[test]
+fn new_trade_entries_become_binance_trades() {
+    let refresh = from_venue(msg_type::MARKET_DATA_INCREMENTAL_REFRESH, &[
+        (tag::NO_MD_ENTRIES, "4"),
+        (tag::MD_UPDATE_ACTION, "0"), (tag::MD_ENTRY_TYPE, "2"), (tag::SYMBOL, "BTC/USD"), (tag::MD_ENTRY_PX, "96123.45"),
+        (tag::MD_ENTRY_SIZE, "0.5"), (tag::MD_ENTRY_DATE, "20250218"), (tag::MD_ENTRY_TIME, "11:59:59.500"),
+        (tag::TRADE_ID, "42"), (tag::SIDE, "2"),
+        // Same symbol, the message's time, and the entry id as the trade id.
+        (tag::MD_UPDATE_ACTION, "0"), (tag::MD_ENTRY_TYPE, "2"), (tag::MD_ENTRY_PX, "96124"), (tag::MD_ENTRY_SIZE, "1"),
+        (tag::MD_ENTRY_ID, "43"),
+        (tag::MD_UPDATE_ACTION, "0"), (tag::MD_ENTRY_TYPE, "0"), (tag::MD_ENTRY_PX, "96000"), (tag::MD_ENTRY_SIZE, "3"),
+        (tag::MD_UPDATE_ACTION, "0"), (tag::MD_ENTRY_TYPE, "2"), (tag::MD_ENTRY_PX, "96125"), (tag::MD_ENTRY_SIZE, "1"),
+        (tag::MD_ENTRY_ID, "t-44"),
+    ]);
+    let (ok, skipped) = trades(&refresh);
+    assert_eq!(skipped, [Skipped::NotATrade, Skipped::NoTradeId]);
+    assert_eq!(ok.len(), 2);
+    assert_eq!(ok[0].0, "btcusd");
+    let first: Value = serde_json::from_str(&ok[0].1).unwrap();
+    assert_eq!(first, serde_json::json!({"e": "trade", "s": "BTCUSD", "t": 42, "p": "96123.45", "q": "0.5", "T": 1739879999500i64, "m": true}));
+    let second: Value = serde_json::from_str(&ok[1].1).unwrap();
+    assert_eq!((second["t"].as_i64(), second["T"].as_i64(), second["m"].as_bool()), (Some(43), Some(1739880000138), Some(false)));
+}
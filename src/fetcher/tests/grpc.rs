@@ -0,0 +1,71 @@
+use fetcher::grpc::{self, Code, Deframer, MAX_MESSAGE_BYTES};
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Trade {
+    #[prost(string, tag = "1")]
+    symbol: String,
+    #[prost(bytes = "vec", tag = "2")]
+    payload: Vec<u8>,
+}
+
+fn trade(symbol: &str) -> Trade {
+    Trade { symbol: symbol.into(), payload: br#"{"p":"1.5","q":"2"}"#.to_vec() }
+}
+
+#[test]
+fn messages_are_read_back_however_the_body_is_chunked() {
+    let body: Vec<u8> = ["btcusdt", "", "ethusdt"].iter().flat_map(|s| grpc::frame(&trade(s))).collect();
+
+    // One byte at a time is the worst a client can split it.
+    let mut frames = Deframer::default();
+    let mut got = Vec::new();
+    for b in &body {
+        frames.push(&[*b]);
+        while let Some(t) = frames.message::<Trade>().unwrap() {
+            got.push(t);
+        }
+    }
+    frames.finish().unwrap();
+    assert_eq!(got, vec![trade("btcusdt"), trade(""), trade("ethusdt")]);
+
+    // A stream cut inside a message is an error, not a silent drop.
+    let mut frames = Deframer::default();
+    frames.push(&body[..body.len() - 1]);
+    while frames.message::<Trade>().unwrap().is_some() {}
+    assert_eq!(frames.finish().unwrap_err().code, Code::InvalidArgument);
+}
+
+#[test]
+fn compressed_and_oversize_messages_are_refused() {
+    let mut compressed = grpc::frame(&trade("btcusdt")).to_vec();
+    compressed[0] = 1;
+    let mut frames = Deframer::default();
+    frames.push(&compressed);
+    assert_eq!(frames.message::<Trade>().unwrap_err().code, Code::InvalidArgument);
+
+    // Refused on the length alone, before any of it is buffered.
+    let mut frames = Deframer::default();
+    frames.push(&[0]);
+    frames.push(&(MAX_MESSAGE_BYTES as u32 + 1).to_be_bytes());
+    assert_eq!(frames.message::<Trade>().unwrap_err().code, Code::ResourceExhausted);
+}
+
+#[tokio::test]
+async fn refusals_put_the_status_in_the_headers() {
+    let resp = grpc::refuse(grpc::Status::new(Code::Unavailable, "2 trades weren't published: 100%"));
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "application/grpc");
+    assert_eq!(resp.headers()["grpc-status"], "14");
+    assert_eq!(resp.headers()["grpc-message"], "2 trades weren't published: 100%25");
+}
+
+#[tokio::test]
+async fn unary_requests_take_exactly_one_message() {
+    let one = grpc::frame(&trade("btcusdt"));
+    let got: Trade = grpc::request(axum::body::Body::from(one.clone())).await.unwrap();
+    assert_eq!(got, trade("btcusdt"));
+
+    let two: Vec<u8> = one.iter().chain(one.iter()).copied().collect();
+    assert_eq!(grpc::request::<Trade>(axum::body::Body::from(two)).await.unwrap_err().code, Code::InvalidArgument);
+    assert_eq!(grpc::request::<Trade>(axum::body::Body::empty()).await.unwrap_err().code, Code::InvalidArgument);
+}
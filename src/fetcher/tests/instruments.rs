@@ -0,0 +1,16 @@
+use fetcher::instruments::parse_coinm;
+
+#[test]
+fn coinm_exchange_info_keys_contracts_by_lowercase_symbol() {
+    let body = r#"{"timezone":"UTC","symbols":[
+        {"symbol":"BTCUSD_PERP","pair":"BTCUSD","contractType":"PERPETUAL","contractSize":100,"marginAsset":"BTC"},
+        {"symbol":"ETHUSD_250627","pair":"ETHUSD","contractType":"CURRENT_QUARTER","contractSize":10,"marginAsset":"ETH"}]}"#;
+    let all = parse_coinm(body).unwrap();
+    assert_eq!(all.len(), 2);
+    let btc = &all["btcusd_perp"];
+    assert_eq!((btc.symbol.as_str(), btc.pair.as_str(), btc.contract_size), ("BTCUSD_PERP", "BTCUSD", 100.0));
+    assert_eq!(all["ethusd_250627"].contract_size, 10.0);
+
+    assert!(parse_coinm(r#"{"symbols":[{"symbol":"X","pair":"X","contractSize":0}]}"#).is_err());
+    assert!(parse_coinm(r#"{"symbols":[{"symbol":"X","pair":"X"}]}"#).is_err());
+}
@@ -0,0 +1,34 @@
+use std::collections::BTreeSet;
+
+use fetcher::listings::{diff, endpoint, parse_exchange_info, ListingEvent, ListingMarker};
+
+const INFO: &str = r#"{"timezone":"UTC","symbols":[
+    {"symbol":"BTCUSDT","status":"TRADING","baseAsset":"BTC","quoteAsset":"USDT"},
+    {"symbol":"ETHBTC","status":"TRADING","baseAsset":"ETH","quoteAsset":"BTC"},
+    {"symbol":"OLDUSDT","status":"BREAK","baseAsset":"OLD","quoteAsset":"USDT"},
+    {"symbol":"SOLUSDC","status":"TRADING","baseAsset":"SOL","quoteAsset":"USDC"}
+]}"#;
+
+fn set(symbols: &[&str]) -> BTreeSet<String> {
+    symbols.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn only_trading_symbols_in_the_chosen_quotes_are_streamed() {
+    assert_eq!(parse_exchange_info(INFO, &["USDT".into(), "usdc".into()]).unwrap(), set(&["btcusdt", "solusdc"]));
+    assert_eq!(parse_exchange_info(INFO, &[]).unwrap(), set(&["btcusdt", "ethbtc", "solusdc"]));
+    assert!(parse_exchange_info(r#"{"symbols":[{"symbol":"X"}]}"#, &[]).is_err());
+    assert!(endpoint("coinm").is_err());
+    assert_eq!(endpoint("usdm").unwrap().2, "binance-usdm");
+}
+
+#[test]
+fn listings_and_delistings_are_the_set_difference() {
+    let (added, removed) = diff(&set(&["btcusdt", "oldusdt"]), &set(&["btcusdt", "newusdt"]));
+    assert_eq!((added, removed), (vec!["newusdt".to_string()], vec!["oldusdt".to_string()]));
+    assert_eq!(diff(&set(&["btcusdt"]), &set(&["btcusdt"])), (vec![], vec![]));
+
+    let marker = ListingMarker { symbol: "NEWUSDT".into(), event: ListingEvent::Listed, ts_ms: 1 };
+    assert_eq!(serde_json::to_string(&marker).unwrap(), r#"{"symbol":"NEWUSDT","event":"listed","ts_ms":1}"#);
+    assert_eq!(ListingEvent::Delisted.name(), "delisted");
+}
@@ -0,0 +1,101 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bus::{Headers, Publisher};
+use fetcher::queue::Queue;
+use tokio::sync::Semaphore;
+
+/// Takes one publish per permit, like a broker that acks only when told to.
+struct Gated {
+    permits: Semaphore,
+    /// Publishes begun, taken or not.
+    begun: Mutex<Vec<String>>,
+    taken: Mutex<Vec<(String, String)>>,
+}
+
+impl Gated {
+    fn new(permits: usize) -> Arc<Self> {
+        Arc::new(Self { permits: Semaphore::new(permits), begun: Mutex::new(Vec::new()), taken: Mutex::new(Vec::new()) })
+    }
+
+    fn taken(&self) -> Vec<(String, String)> {
+        self.taken.lock().unwrap().clone()
+    }
+
+    async fn wait_for(&self, n: usize) {
+        while self.taken.lock().unwrap().len() < n {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+}
+
+#[async_trait]
+impl Publisher for Gated {
+    async fn publish(&self, _topic: &str, key: &str, payload: &[u8], _headers: &Headers) -> Result<()> {
+        self.begun.lock().unwrap().push(String::from_utf8_lossy(payload).into_owned());
+        self.permits.acquire().await?.forget();
+        self.taken.lock().unwrap().push((key.to_string(), String::from_utf8_lossy(payload).into_owned()));
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn a_full_queue_holds_the_sender_until_the_broker_catches_up() {
+    let broker = Gated::new(0);
+    let queue = Queue::spawn(broker.clone(), "ticks.raw", 2, 1, 1);
+    // One in the lane's publish, two queued behind it.
+    for i in 0..3 {
+        queue.push("BTCUSDT", i.to_string().into_bytes(), Headers::new()).await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    let blocked = tokio::spawn({
+        let queue = queue.clone();
+        async move { queue.push("BTCUSDT", b"3".to_vec(), Headers::new()).await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!blocked.is_finished(), "the fourth send should wait for room");
+
+    broker.permits.add_permits(4);
+    blocked.await.unwrap();
+    broker.wait_for(4).await;
+    let payloads: Vec<String> = broker.taken().into_iter().map(|(_, p)| p).collect();
+    assert_eq!(payloads, ["0", "1", "2", "3"]);
+}
+
+#[tokio::test]
+async fn each_symbol_keeps_its_order_across_lanes() {
+    let broker = Gated::new(usize::MAX >> 4);
+    let queue = Queue::spawn(broker.clone(), "ticks.raw", 64, 4, 8);
+    let symbols = ["BTCUSDT", "ETHUSDT", "SOLUSDT", "XRPUSDT", "DOGEUSDT"];
+    for i in 0..40 {
+        for s in symbols {
+            queue.push(s, i.to_string().into_bytes(), Headers::new()).await;
+        }
+    }
+    broker.wait_for(200).await;
+    let taken = broker.taken();
+    for s in symbols {
+        let seq: Vec<u32> = taken.iter().filter(|(k, _)| k == s).map(|(_, p)| p.parse().unwrap()).collect();
+        assert_eq!(seq, (0..40).collect::<Vec<_>>(), "{} out of order", s);
+    }
+}
+
+#[tokio::test]
+async fn a_lane_publishes_symbols_side_by_side_but_each_one_in_turn() {
+    let broker = Gated::new(0);
+    let queue = Queue::spawn(broker.clone(), "ticks.raw", 16, 1, 4);
+    for (s, p) in [("BTCUSDT", "b0"), ("BTCUSDT", "b1"), ("ETHUSDT", "e0"), ("SOLUSDT", "s0")] {
+        queue.push(s, p.as_bytes().to_vec(), Headers::new()).await;
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    // Three symbols in flight at once; BTCUSDT's second waits for its first.
+    let mut begun = broker.begun.lock().unwrap().clone();
+    begun.sort();
+    assert_eq!(begun, ["b0", "e0", "s0"]);
+
+    broker.permits.add_permits(4);
+    broker.wait_for(4).await;
+    assert_eq!(broker.begun.lock().unwrap().last().map(String::as_str), Some("b1"));
+}
@@ -0,0 +1,11 @@
+use fetcher::reach::target;
+
+#[test]
+fn feed_urls_become_https_probes() {
+    assert_eq!(target("wss://stream.binance.com:9443/ws").unwrap(), ("stream.binance.com".into(), 9443, "https://stream.binance.com:9443/".into()));
+    assert_eq!(target("wss://api.gateio.ws/ws/v4/").unwrap(), ("api.gateio.ws".into(), 443, "https://api.gateio.ws/".into()));
+    assert_eq!(target("https://api.kucoin.com").unwrap().1, 443);
+    assert_eq!(target("ws://localhost:8080/feed?x=1").unwrap(), ("localhost".into(), 8080, "http://localhost:8080/".into()));
+    assert!(target("tcp://host:1").is_err());
+    assert!(target("not a url").is_err());
+}
@@ -0,0 +1,33 @@
+use std::time::{Duration, Instant};
+
+use fetcher::recv::Tracker;
+
+#[test]
+fn each_subscription_reports_its_own_rate_and_age() {
+    let t0 = Instant::now();
+    let tracker = Tracker::new(t0);
+    let btc = tracker.subscribe("binance", "trade", "btcusdt", t0);
+    let eth = tracker.subscribe("binance", "trade", "ethusdt", t0);
+    for i in 0..20 {
+        btc.received_at(t0 + Duration::from_millis(100 * i));
+    }
+    eth.received_at(t0 + Duration::from_secs(1));
+
+    let first = tracker.report(t0 + Duration::from_secs(2));
+    assert_eq!(first.iter().map(|r| (r.symbol.as_str(), r.rate)).collect::<Vec<_>>(), [("btcusdt", 10.0), ("ethusdt", 0.5)]);
+    assert_eq!(first[0].age, Duration::from_millis(100));
+    assert_eq!(first[1].age, Duration::from_secs(1));
+
+    // Nothing since: the rate drops to zero and the age keeps growing.
+    let second = tracker.report(t0 + Duration::from_secs(4));
+    assert_eq!((second[1].rate, second[1].age), (0.0, Duration::from_secs(3)));
+}
+
+#[test]
+fn a_subscription_that_never_received_ages_from_when_it_was_made() {
+    let t0 = Instant::now();
+    let tracker = Tracker::new(t0);
+    tracker.subscribe("gate", "trades", "solusdt", t0 + Duration::from_secs(5));
+    let r = &tracker.report(t0 + Duration::from_secs(65))[0];
+    assert_eq!((r.exchange.as_str(), r.stream.as_str(), r.rate, r.age), ("gate", "trades", 0.0, Duration::from_secs(60)));
+}
@@ -0,0 +1,60 @@
+use fetcher::bitstamp::Bitstamp;
+use fetcher::status::{binance_notice, endpoint, Check, Source, Status, Tracker, DOWN_AFTER};
+use fetcher::venue::ExchangeSource;
+
+#[test]
+fn status_endpoints_parse_per_venue() {
+    assert_eq!(Check::BinanceSystem.parse(r#"{"status":0,"msg":"normal"}"#).unwrap(), (Status::Up, "normal".into()));
+    assert_eq!(Check::BinanceSystem.parse(r#"{"status":1,"msg":"system_maintenance"}"#).unwrap().0, Status::Maintenance);
+    assert!(Check::BinanceSystem.parse(r#"{"status":7}"#).is_err());
+    assert_eq!(Check::Kucoin.parse(r#"{"code":"200000","data":{"status":"open","msg":""}}"#).unwrap().0, Status::Up);
+    assert_eq!(Check::Kucoin.parse(r#"{"code":"200000","data":{"status":"cancelonly","msg":"upgrade"}}"#).unwrap(),
+        (Status::Maintenance, "upgrade".into()));
+    assert_eq!(Check::Reachable.parse("{}").unwrap().0, Status::Up);
+    assert_eq!(endpoint("binance", "spot").map(|(_, c)| c), Some(Check::BinanceSystem));
+    assert_eq!(endpoint("gemini", "spot"), None);
+}
+
+#[test]
+fn only_changes_make_events_and_errors_take_a_few_polls() {
+    let mut t = Tracker::new();
+    let first = t.polled("binance", Ok((Status::Up, "normal".into())), 1).unwrap();
+    assert_eq!((first.status, first.previous, first.source), (Status::Up, None, Source::Poll));
+    assert_eq!(t.polled("binance", Ok((Status::Up, "normal".into())), 2), None);
+
+    for i in 1..DOWN_AFTER {
+        assert_eq!(t.polled("binance", Err(anyhow::anyhow!("timed out")), 2 + i as i64), None);
+    }
+    let down = t.polled("binance", Err(anyhow::anyhow!("timed out")), 10).unwrap();
+    assert_eq!((down.status, down.previous, down.message.as_str(), down.ts_ms), (Status::Down, Some(Status::Up), "timed out", 10));
+    assert_eq!(t.polled("binance", Ok((Status::Maintenance, "system_maintenance".into())), 11).unwrap().previous, Some(Status::Down));
+
+    let json = serde_json::to_value(&down).unwrap();
+    assert_eq!((json["status"].as_str(), json["previous"].as_str(), json["source"].as_str()), (Some("down"), Some("up"), Some("poll")));
+}
+
+#[test]
+fn websocket_notices_last_until_the_next_connection() {
+    let mut t = Tracker::new();
+    assert_eq!(t.connected("bitstamp", 1), None);
+    assert_eq!(t.notice("bitstamp", "reconnect requested".into(), 2).unwrap().source, Source::Ws);
+    assert_eq!(t.status("bitstamp"), Some(Status::Maintenance));
+    let up = t.connected("bitstamp", 3).unwrap();
+    assert_eq!((up.status, up.previous), (Status::Up, Some(Status::Maintenance)));
+
+    // Maintenance the status endpoint reports isn't ended by a reconnect.
+    t.polled("binance", Ok((Status::Maintenance, String::new())), 4);
+    assert_eq!(t.connected("binance", 5), None);
+    assert_eq!(t.status("binance"), Some(Status::Maintenance));
+}
+
+#[test]
+fn maintenance_notices_are_recognised() {
+    assert_eq!(binance_notice(r#"{"e":"serverShutdown","E":1700000000000}"#).as_deref(), Some("server shutdown"));
+    assert_eq!(binance_notice(r#"{"event":{"e":"serverShutdown","E":1700000000000}}"#).as_deref(), Some("server shutdown"));
+    assert_eq!(binance_notice(r#"{"e":"trade","s":"BTCUSDT","p":"1"}"#), None);
+
+    let bitstamp = Bitstamp::new("wss://example");
+    assert_eq!(bitstamp.maintenance_notice(r#"{"event":"bts:request_reconnect","channel":"","data":""}"#).as_deref(), Some("reconnect requested"));
+    assert_eq!(bitstamp.maintenance_notice(r#"{"event":"bts:heartbeat","data":{"status":"success"}}"#), None);
+}
@@ -0,0 +1,65 @@
+use fetcher::bitstamp::Bitstamp;
+use fetcher::gate::Gate;
+use fetcher::gemini::Gemini;
+use fetcher::kucoin::Kucoin;
+use fetcher::upbit::Upbit;
+use fetcher::venue::{ExchangeSource, Pair};
+use serde_json::Value;
+
+#[test]
+fn pairs_parse_with_or_without_a_separator() {
+    let p = Pair::parse("btc_usdt").unwrap();
+    assert_eq!((p.join("_"), p.join("-"), p.key()), ("BTC_USDT".into(), "BTC-USDT".into(), "btcusdt".into()));
+    assert_eq!(Pair::parse("ETH-BTC").unwrap(), Pair { base: "ETH".into(), quote: "BTC".into() });
+    assert_eq!(Pair::parse("solusdc").unwrap(), Pair { base: "SOL".into(), quote: "USDC".into() });
+    assert!(Pair::parse("usdt").is_err());
+    assert!(Pair::parse("foobar").is_err());
+}
+
+#[test]
+fn only_trade_frames_are_forwarded() {
+    let gate = Gate::new("wss://example");
+    let pairs = [Pair::parse("btc_usdt").unwrap(), Pair::parse("eth_usdt").unwrap()];
+    let sub: Value = serde_json::from_str(&gate.subscribe(&pairs)[0]).unwrap();
+    assert_eq!(sub["payload"], serde_json::json!(["BTC_USDT", "ETH_USDT"]));
+    assert_eq!(gate.trade_key(r#"{"channel":"spot.trades","event":"update","result":{"currency_pair":"BTC_USDT","id":1}}"#).as_deref(), Some("btcusdt"));
+    assert_eq!(gate.trade_key(r#"{"channel":"spot.trades","event":"subscribe","result":{"status":"success"}}"#), None);
+    assert_eq!(gate.trade_key(r#"{"channel":"spot.pong","event":""}"#), None);
+
+    let kucoin = Kucoin::new("https://example").unwrap();
+    let many: Vec<Pair> = (0..150).map(|i| Pair::parse(&format!("c{}_usdt", i)).unwrap()).collect();
+    let subs = kucoin.subscribe(&many);
+    assert_eq!(subs.len(), 2);
+    let first: Value = serde_json::from_str(&subs[0]).unwrap();
+    assert!(first["topic"].as_str().unwrap().starts_with("/market/match:C0-USDT,C1-USDT,"));
+    assert_eq!(kucoin.trade_key(r#"{"type":"message","subject":"trade.l3match","data":{"symbol":"ETH-USDT"}}"#).as_deref(), Some("ethusdt"));
+    assert_eq!(kucoin.trade_key(r#"{"type":"welcome","id":"x"}"#), None);
+    assert_eq!(kucoin.trade_key(r#"{"type":"ack","id":"x"}"#), None);
+}
+
+#[test]
+fn usd_venues_subscribe_in_their_own_symbol_forms() {
+    let pairs = [Pair::parse("btcusd").unwrap(), Pair::parse("eth_usd").unwrap()];
+
+    let bitstamp = Bitstamp::new("wss://example");
+    let subs: Vec<Value> = bitstamp.subscribe(&pairs).iter().map(|s| serde_json::from_str(s).unwrap()).collect();
+    assert_eq!(subs[1]["data"]["channel"], "live_trades_ethusd");
+    assert_eq!(bitstamp.trade_key(r#"{"data":{"id":1},"channel":"live_trades_btcusd","event":"trade"}"#).as_deref(), Some("btcusd"));
+    assert_eq!(bitstamp.trade_key(r#"{"event":"bts:subscription_succeeded","channel":"live_trades_btcusd","data":{}}"#), None);
+
+    let gemini = Gemini::new("wss://example");
+    let sub: Value = serde_json::from_str(&gemini.subscribe(&pairs)[0]).unwrap();
+    assert_eq!(sub["subscriptions"][0]["symbols"], serde_json::json!(["BTCUSD", "ETHUSD"]));
+    assert_eq!(gemini.trade_key(r#"{"type":"trade","symbol":"ETHUSD","event_id":1}"#).as_deref(), Some("ethusd"));
+    assert_eq!(gemini.trade_key(r#"{"type":"l2_updates","symbol":"ETHUSD","changes":[]}"#), None);
+    assert_eq!(gemini.trade_key(r#"{"type":"heartbeat","timestamp":1}"#), None);
+}
+
+#[test]
+fn upbit_writes_markets_quote_first() {
+    let upbit = Upbit::new("upbit", "wss://example");
+    let sub: Value = serde_json::from_str(&upbit.subscribe(&[Pair::parse("btckrw").unwrap(), Pair::parse("usdt_krw").unwrap()])[0]).unwrap();
+    assert_eq!(sub[1]["codes"], serde_json::json!(["KRW-BTC", "KRW-USDT"]));
+    assert_eq!(upbit.trade_key(r#"{"type":"trade","code":"KRW-BTC","sequential_id":1}"#).as_deref(), Some("btckrw"));
+    assert_eq!(upbit.trade_key(r#"{"status":"UP"}"#), None);
+}
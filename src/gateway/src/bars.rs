@@ -0,0 +1,114 @@
+//! Candles at any whole multiple of the consumer's bar width, live and
+//! historical, through one API.
+//!
+//! Live bars are the consumer's bars (`BARS_TOPIC`, 1m by default),
+//! resampled per subscription by [`Resample`]. Historical bars are read from
+//! the same bars' QuestDB table (`trades_1m`), resampled by QuestDB with
+//! `SAMPLE BY` (see [`history_sql`]). Both come out as [`Bar`]s.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use consumer::rollup::Bar;
+use serde_json::Value;
+
+/// An interval such as `5m` in ms; it must be a whole number of `base_ms` bars.
+pub fn interval(s: &str, base_ms: i64) -> Result<i64> {
+    let ms = vwap::calc::parse_window(s)?;
+    anyhow::ensure!(ms % base_ms == 0, "interval {:?} is not a multiple of the {}s bars", s, base_ms / 1000);
+    Ok(ms)
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// Bars of `symbols` (all if empty) starting in `[from_ms, to_ms)`, oldest
+/// first, at most `limit` of them.
+pub fn history_sql(table: &str, symbols: &[String], from_ms: i64, to_ms: i64, interval_ms: i64, base_ms: i64, limit: usize) -> String {
+    let mut filter = format!("timestamp >= cast({} AS timestamp) AND timestamp < cast({} AS timestamp)", from_ms * 1000, to_ms * 1000);
+    if !symbols.is_empty() {
+        let list: Vec<String> = symbols.iter().map(|s| quote(s)).collect();
+        filter = format!("symbol IN ({}) AND {}", list.join(", "), filter);
+    }
+    if interval_ms == base_ms {
+        return format!(
+            "SELECT symbol, timestamp, open, high, low, close, volume, trades FROM {} WHERE {} ORDER BY timestamp LIMIT {}",
+            table, filter, limit,
+        );
+    }
+    format!(
+        "SELECT symbol, timestamp, first(open) open, max(high) high, min(low) low, last(close) close, \
+         sum(volume) volume, sum(trades) trades FROM {} WHERE {} SAMPLE BY {}s ALIGN TO CALENDAR ORDER BY timestamp LIMIT {}",
+        table, filter, interval_ms / 1000, limit,
+    )
+}
+
+/// The rows of a [`history_sql`] query.
+pub fn from_rows(rows: &[Vec<Value>]) -> Result<Vec<Bar>> {
+    rows.iter()
+        .map(|r| {
+            let f = |i: usize, name: &str| r.get(i).and_then(Value::as_f64).with_context(|| format!("bar row without {}", name));
+            let ts: chrono::DateTime<chrono::Utc> = r.get(1).and_then(Value::as_str).context("bar row without timestamp")?.parse()?;
+            Ok(Bar {
+                symbol: r.first().and_then(Value::as_str).context("bar row without symbol")?.to_string(),
+                ts_ms: ts.timestamp_millis(),
+                open: f(2, "open")?,
+                high: f(3, "high")?,
+                low: f(4, "low")?,
+                close: f(5, "close")?,
+                volume: f(6, "volume")?,
+                trades: r.get(7).and_then(Value::as_u64).context("bar row without trades")?,
+            })
+        })
+        .collect()
+}
+
+/// Merges `base_ms` bars into `interval_ms` ones, per symbol.
+///
+/// A bar is emitted as soon as the base bar ending its interval arrives, or,
+/// if that one never comes (a quiet minute has no bar), when a bar of a later
+/// interval does.
+pub struct Resample {
+    interval_ms: i64,
+    base_ms: i64,
+    open: HashMap<String, Bar>,
+    /// Start of the latest base bar taken, per symbol.
+    last_ts: HashMap<String, i64>,
+}
+
+impl Resample {
+    pub fn new(interval_ms: i64, base_ms: i64) -> Self {
+        Self { interval_ms, base_ms, open: HashMap::new(), last_ts: HashMap::new() }
+    }
+
+    /// The bars `bar` completes, oldest first.
+    pub fn add(&mut self, bar: Bar) -> Vec<Bar> {
+        let (ts, symbol) = (bar.ts_ms, bar.symbol.clone());
+        let start = ts.div_euclid(self.interval_ms) * self.interval_ms;
+        let mut done = Vec::new();
+        // Redelivered or out of order: already counted, or its interval is gone.
+        if self.last_ts.get(&symbol).is_some_and(|&last| ts <= last) {
+            return done;
+        }
+        self.last_ts.insert(symbol.clone(), ts);
+        match self.open.get_mut(&symbol) {
+            Some(cur) if start == cur.ts_ms => {
+                cur.high = cur.high.max(bar.high);
+                cur.low = cur.low.min(bar.low);
+                cur.close = bar.close;
+                cur.volume += bar.volume;
+                cur.trades += bar.trades;
+            }
+            _ => {
+                if let Some(prev) = self.open.insert(symbol.clone(), Bar { ts_ms: start, ..bar }) {
+                    done.push(prev);
+                }
+            }
+        }
+        if ts + self.base_ms >= start + self.interval_ms {
+            done.extend(self.open.remove(&symbol));
+        }
+        done
+    }
+}
@@ -0,0 +1,206 @@
+//! One bounded send queue per client, so a slow client only ever holds up
+//! itself.
+//!
+//! [`Hub::broadcast`] never waits: it appends to every client's queue.
+//! Once a queue is at `queue_max`, the client's [`Policy`] decides whether
+//! its oldest message is dropped to make room or the client is cut off.
+//! Each client's writer drains its own queue at whatever pace its socket
+//! allows.
+//!
+//! A client may also carry a [`Filter`]; records it doesn't match are never
+//! queued for it. Each record is parsed at most once per broadcast, however
+//! many clients filter.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use metrics::counter;
+use serde_json::Value;
+use tokio::sync::Notify;
+
+use crate::query::Filter;
+
+/// What to do with a client whose queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Drop the oldest queued message: the client sees gaps but stays current.
+    DropOldest,
+    /// Close the connection: the client reconnects and starts from now.
+    Disconnect,
+}
+
+impl Policy {
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s {
+            "drop_oldest" => Policy::DropOldest,
+            "disconnect" => Policy::Disconnect,
+            other => anyhow::bail!("unknown GATEWAY_SLOW_POLICY {:?} (expected drop_oldest|disconnect)", other),
+        })
+    }
+}
+
+/// One client's queue depth and how far behind it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lag {
+    /// The client's `client` label.
+    pub client: String,
+    pub queued: usize,
+    /// Age of the oldest queued message; zero when the queue is empty.
+    pub behind: Duration,
+    pub dropped: u64,
+}
+
+pub struct Client {
+    /// The `client` label: bounded, so never a name or address the client chose.
+    pub name: String,
+    filter: Option<Filter>,
+    queue: Mutex<VecDeque<(Instant, Arc<str>)>>,
+    ready: Notify,
+    closed: AtomicBool,
+    dropped: AtomicU64,
+}
+
+impl Client {
+    /// The next message to send; `None` once the hub has cut the client off.
+    pub async fn next(&self) -> Option<Arc<str>> {
+        loop {
+            // Registered before checking, so a push in between still wakes us.
+            let ready = self.ready.notified();
+            if self.closed.load(Ordering::Acquire) {
+                return None;
+            }
+            if let Some((_, msg)) = self.queue.lock().unwrap_or_else(PoisonError::into_inner).pop_front() {
+                return Some(msg);
+            }
+            ready.await;
+        }
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.ready.notify_one();
+    }
+}
+
+pub struct Hub {
+    queue_max: usize,
+    policy: Policy,
+    next_id: AtomicU64,
+    clients: Mutex<HashMap<u64, Arc<Client>>>,
+}
+
+impl Hub {
+    pub fn new(queue_max: usize, policy: Policy) -> Self {
+        Self { queue_max: queue_max.max(1), policy, next_id: AtomicU64::new(0), clients: Mutex::new(HashMap::new()) }
+    }
+
+    /// Add a client, sent only the records `filter` matches if it has one;
+    /// its id is for [`Hub::leave`].
+    pub fn join(&self, name: &str, filter: Option<Filter>) -> (u64, Arc<Client>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let client = Arc::new(Client {
+            name: name.to_string(),
+            filter,
+            queue: Mutex::new(VecDeque::new()),
+            ready: Notify::new(),
+            closed: AtomicBool::new(false),
+            dropped: AtomicU64::new(0),
+        });
+        self.clients.lock().unwrap_or_else(PoisonError::into_inner).insert(id, client.clone());
+        (id, client)
+    }
+
+    pub fn leave(&self, id: u64) {
+        if let Some(client) = self.clients.lock().unwrap_or_else(PoisonError::into_inner).remove(&id) {
+            client.close();
+        }
+    }
+
+    pub fn clients(&self) -> usize {
+        self.clients.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    /// Queue `msg` for every client whose filter it matches, applying the policy to those that are full.
+    pub fn broadcast(&self, msg: Arc<str>) {
+        self.broadcast_at(msg, Instant::now())
+    }
+
+    pub fn broadcast_at(&self, msg: Arc<str>, now: Instant) {
+        let mut clients = self.clients.lock().unwrap_or_else(PoisonError::into_inner);
+        // `None` inside: not JSON, so no filter matches it.
+        let mut record: Option<Option<Value>> = None;
+        clients.retain(|_, client| {
+            if let Some(filter) = &client.filter {
+                let record = record.get_or_insert_with(|| serde_json::from_str(&msg).ok());
+                if !record.as_ref().is_some_and(|r| filter.matches(r)) {
+                    return true;
+                }
+            }
+            let mut queue = client.queue.lock().unwrap_or_else(PoisonError::into_inner);
+            if queue.len() >= self.queue_max {
+                match self.policy {
+                    Policy::DropOldest => {
+                        queue.pop_front();
+                        client.dropped.fetch_add(1, Ordering::Relaxed);
+                        counter!("gateway_client_dropped_total", "client" => client.name.clone()).increment(1);
+                    }
+                    Policy::Disconnect => {
+                        tracing::warn!(target: "gateway", client = %client.name, queued = queue.len(), "client too slow; disconnecting");
+                        counter!("gateway_slow_disconnects_total", "client" => client.name.clone()).increment(1);
+                        client.close();
+                        return false;
+                    }
+                }
+            }
+            queue.push_back((now, msg.clone()));
+            drop(queue);
+            client.ready.notify_one();
+            true
+        });
+    }
+
+    /// Every client's lag at `now`.
+    pub fn lag(&self, now: Instant) -> Vec<Lag> {
+        self.clients.lock().unwrap_or_else(PoisonError::into_inner).values()
+            .map(|c| {
+                let queue = c.queue.lock().unwrap_or_else(PoisonError::into_inner);
+                Lag {
+                    client: c.name.clone(),
+                    queued: queue.len(),
+                    behind: queue.front().map_or(Duration::ZERO, |(at, _)| now.saturating_duration_since(*at)),
+                    dropped: c.dropped(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The worst of each label's connections: the deepest queue and the oldest
+/// message, with their drops summed. By label, in label order.
+pub fn worst(lags: impl IntoIterator<Item = Lag>) -> Vec<Lag> {
+    let mut by: BTreeMap<String, Lag> = BTreeMap::new();
+    for lag in lags {
+        match by.get_mut(&lag.client) {
+            Some(w) => {
+                w.queued = w.queued.max(lag.queued);
+                w.behind = w.behind.max(lag.behind);
+                w.dropped += lag.dropped;
+            }
+            None => {
+                by.insert(lag.client.clone(), lag);
+            }
+        }
+    }
+    by.into_values().collect()
+}
@@ -0,0 +1,485 @@
+//! The HTTP streaming endpoints, for clients (curl, browsers behind proxies
+//! that strip upgrades) that can't use WebSocket.
+//!
+//! `GET /v1/stream/sse` sends each record as a server-sent event, with a
+//! comment line every 15s so idle proxies keep the connection open.
+//! `GET /v1/stream/ndjson` sends one record per line in a chunked body. Both
+//! take the same `?client=&filter=` as the WebSocket endpoint and get the same
+//! per-client queue; the subscription ends when the client goes away.
+//!
+//! `GET /v1/last?symbols=BTCUSDT,ETHUSDT&exchange=` answers from the
+//! [`LastCache`] instead: the latest trade per symbol and exchange, its 24h
+//! change and how stale it is.
+//!
+//! Candles (see [`gateway::bars`]) have the same two shapes:
+//! `GET /v1/bars/stream?symbols=&interval=5m` streams live bars as they
+//! close, and `GET /v1/bars?symbols=&interval=5m&from=&to=` fetches a range
+//! from QuestDB, sent `QDB_HTTP_TOKEN` if one is set. Over HTTP/2 the same
+//! port serves them as gRPC too, `bars.v1.Bars/SubscribeBars` and
+//! `bars.v1.Bars/GetBars` (see [`gateway::rpc`]), with the same parameters
+//! and the same ACL.
+//!
+//! `GET /v1/export/{trades|bars}?symbols=&from=&to=` streams a stored range
+//! as one Parquet file, a row group per page read from QuestDB, for bulk
+//! loads into pandas and the like. `&format=arrow` sends it as an Arrow IPC
+//! stream instead, a record batch per page, which pyarrow reads without
+//! waiting for a footer.
+//!
+//! With a client list, every route needs credentials (a client certificate
+//! or bearer token) and only reads the caller's symbols: streams through the
+//! ACL filter [`Subscription::open`] adds, queries through [`permitted`].
+
+use std::convert::Infallible;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, FromRequestParts, Path, Query, RawQuery, State};
+use axum::http::request::Parts;
+use axum::http::{header, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use consumer::arrow::ArrowExport;
+use consumer::export::{Dataset, Pager, ParquetExport, Range};
+use consumer::rollup::Bar;
+use consumer::schema;
+use fetcher::auth::{self, Live};
+use fetcher::grpc::{self, Code};
+use fetcher::tls::{Peer, TlsListener, TlsOptions};
+use futures_util::stream::{self, Stream};
+use gateway::bars::{self, Resample};
+use gateway::fanout::Hub;
+use gateway::last::LastCache;
+use gateway::query::{Filter, Literal};
+use gateway::rpc;
+use gateway::subscribe::{permitted, Params, Subscription};
+use metrics::counter;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub hub: Arc<Hub>,
+    pub last: Arc<Mutex<LastCache>>,
+    /// The consumer's bars, as published.
+    pub bars: Arc<Hub>,
+    pub qdb: Arc<schema::Client>,
+    pub bars_table: String,
+    /// Width of the consumer's bars; intervals are multiples of it.
+    pub bar_ms: i64,
+    /// `GATEWAY_CLIENTS`; `None` serves anyone.
+    pub clients: Option<Live>,
+}
+
+/// The client a request authenticated as; `None` when there's no client list.
+struct Caller(Option<auth::Client>);
+
+impl Caller {
+    /// The symbols it may query, or 403.
+    fn permitted(&self, symbols: Vec<String>) -> Result<Vec<String>, (StatusCode, String)> {
+        permitted(self.0.as_ref(), symbols).map_err(|why| {
+            counter!("gateway_denied_total").increment(1);
+            (StatusCode::FORBIDDEN, format!("{}\n", why))
+        })
+    }
+}
+
+impl FromRequestParts<AppState> for Caller {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Some(clients) = &state.clients else { return Ok(Caller(None)) };
+        let identity = parts.extensions.get::<ConnectInfo<Peer>>().and_then(|c| c.0.identity.as_deref());
+        match clients.current().authenticate(identity, auth::bearer(&parts.headers)) {
+            Some(client) => Ok(Caller(Some(client.clone()))),
+            None => {
+                counter!("gateway_unauthorized_total", "transport" => "http").increment(1);
+                Err((StatusCode::UNAUTHORIZED, "missing or invalid credentials\n".to_string()))
+            }
+        }
+    }
+}
+
+/// Most bars one `/v1/bars` request returns.
+const MAX_BARS: usize = 10_000;
+
+/// Rows per QuestDB page, and so per row group or record batch, of an export.
+const EXPORT_PAGE: usize = 100_000;
+
+#[derive(Deserialize)]
+struct LastQuery {
+    /// Comma-separated; all cached symbols if absent or empty.
+    symbols: Option<String>,
+    /// Only this exchange's trades; every exchange's if absent.
+    exchange: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BarsQuery {
+    symbols: Option<String>,
+    /// Defaults to the consumer's bar width.
+    interval: Option<String>,
+    /// History only: ms since the epoch; `to` defaults to now.
+    from: Option<i64>,
+    to: Option<i64>,
+    limit: Option<usize>,
+    /// Stream only: the `client` label, and `sse` or (default) `ndjson`.
+    client: Option<String>,
+    format: Option<String>,
+}
+
+/// `A,b, C` as `["A", "B", "C"]`.
+fn symbols(list: Option<&str>) -> Vec<String> {
+    named(list.unwrap_or_default().split(','))
+}
+
+/// Symbol names trimmed and uppercased, blanks dropped.
+fn named<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    names.into_iter().map(str::trim).filter(|s| !s.is_empty()).map(str::to_ascii_uppercase).collect()
+}
+
+fn bad_request(e: anyhow::Error) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, format!("{:#}\n", e))
+}
+
+/// An HTTP refusal as the gRPC status that means the same.
+fn status((code, message): (StatusCode, String)) -> grpc::Status {
+    let code = match code {
+        StatusCode::BAD_REQUEST => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::BAD_GATEWAY => Code::Unavailable,
+        _ => Code::Internal,
+    };
+    grpc::Status::new(code, message.trim_end())
+}
+
+/// Each record the subscription is sent, until the hub cuts it off.
+fn records(sub: Subscription) -> impl Stream<Item = Arc<str>> {
+    stream::unfold(sub, |sub| async move {
+        let msg = sub.client.next().await?;
+        counter!("gateway_sent_total", "transport" => sub.transport()).increment(1);
+        Some((msg, sub))
+    })
+}
+
+fn subscribe(hub: &Arc<Hub>, query: Option<String>, peer: Peer, caller: Caller, transport: &'static str) -> Result<Subscription, (StatusCode, String)> {
+    match Params::parse(query.as_deref()) {
+        Ok(params) => Ok(Subscription::open(hub, params, &peer.addr.to_string(), caller.0.as_ref(), transport)),
+        Err(e) => {
+            counter!("gateway_bad_filters_total").increment(1);
+            Err((StatusCode::BAD_REQUEST, format!("bad filter: {:#}\n", e)))
+        }
+    }
+}
+
+async fn sse(State(state): State<AppState>, ConnectInfo(peer): ConnectInfo<Peer>, caller: Caller, RawQuery(query): RawQuery) -> Response {
+    match subscribe(&state.hub, query, peer, caller, "sse") {
+        Ok(sub) => {
+            let events = futures_util::StreamExt::map(records(sub), |msg| Ok::<_, Infallible>(Event::default().data(&*msg)));
+            Sse::new(events).keep_alive(KeepAlive::new().interval(Duration::from_secs(15))).into_response()
+        }
+        Err(refused) => refused.into_response(),
+    }
+}
+
+async fn ndjson(State(state): State<AppState>, ConnectInfo(peer): ConnectInfo<Peer>, caller: Caller, RawQuery(query): RawQuery) -> Response {
+    match subscribe(&state.hub, query, peer, caller, "ndjson") {
+        Ok(sub) => {
+            let lines = futures_util::StreamExt::map(records(sub), |msg| Ok::<_, Infallible>(format!("{}\n", msg)));
+            ([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(lines)).into_response()
+        }
+        Err(refused) => refused.into_response(),
+    }
+}
+
+async fn last(State(state): State<AppState>, caller: Caller, Query(q): Query<LastQuery>) -> Result<Json<Value>, (StatusCode, String)> {
+    let mut symbols = symbols(q.symbols.as_deref());
+    if !symbols.is_empty() {
+        symbols = caller.permitted(symbols)?;
+    }
+    let now_ms = chrono::Utc::now().timestamp_millis();
+    let (mut found, missing) = state.last.lock().unwrap_or_else(PoisonError::into_inner).snapshot(&symbols, q.exchange.as_deref().filter(|x| !x.is_empty()), now_ms);
+    // Asked for everything: everything the caller may see.
+    if let Some(client) = &caller.0 {
+        found.retain(|l| client.allows(&l.symbol));
+    }
+    counter!("gateway_last_requests_total").increment(1);
+    Ok(Json(json!({ "ts_ms": now_ms, "symbols": found, "missing": missing })))
+}
+
+/// The interval asked for, the consumer's bar width if none was, in ms.
+fn bar_interval(state: &AppState, interval: Option<String>) -> Result<(String, i64), (StatusCode, String)> {
+    let interval = interval.unwrap_or_else(|| format!("{}s", state.bar_ms / 1000));
+    let ms = bars::interval(&interval, state.bar_ms).map_err(bad_request)?;
+    Ok((interval, ms))
+}
+
+/// Stored bars of `symbols` starting in `[from, to)`, for `/v1/bars` and `GetBars` both.
+async fn history(state: &AppState, caller: &Caller, interval: Option<String>, symbols: Vec<String>, from: i64, to: i64, limit: usize) -> Result<(String, Vec<Bar>), (StatusCode, String)> {
+    let (interval, interval_ms) = bar_interval(state, interval)?;
+    let symbols = caller.permitted(symbols)?;
+    let sql = bars::history_sql(&state.bars_table, &symbols, from, to, interval_ms, state.bar_ms, limit.min(MAX_BARS));
+    counter!("gateway_bar_history_requests_total").increment(1);
+    let rows = state.qdb.query(&sql).await.map_err(|e| (StatusCode::BAD_GATEWAY, format!("{:#}\n", e)))?;
+    let found = bars::from_rows(&rows).map_err(|e| (StatusCode::BAD_GATEWAY, format!("{:#}\n", e)))?;
+    Ok((interval, found))
+}
+
+async fn bar_history(State(state): State<AppState>, caller: Caller, Query(q): Query<BarsQuery>) -> Result<Json<Value>, (StatusCode, String)> {
+    let from = q.from.ok_or_else(|| bad_request(anyhow::anyhow!("from (ms since the epoch) is required")))?;
+    let to = q.to.unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+    let (interval, found) = history(&state, &caller, q.interval, symbols(q.symbols.as_deref()), from, to, q.limit.unwrap_or(1000)).await?;
+    Ok(Json(json!({ "interval": interval, "bars": found })))
+}
+
+/// `bars.v1.Bars/GetBars`: [`history`] as one `BarList`.
+async fn get_bars(State(state): State<AppState>, caller: Result<Caller, (StatusCode, String)>, body: Body) -> Response {
+    let caller = match caller {
+        Ok(c) => c,
+        Err(refused) => return grpc::refuse(status(refused)),
+    };
+    let req = match grpc::request::<rpc::GetBarsRequest>(body).await {
+        Ok(r) => r,
+        Err(s) => return grpc::refuse(s),
+    };
+    let to = match req.to_ms {
+        0 => chrono::Utc::now().timestamp_millis(),
+        to => to,
+    };
+    let limit = match req.limit {
+        0 => 1000,
+        n => n as usize,
+    };
+    let interval = Some(req.interval).filter(|i| !i.is_empty());
+    match history(&state, &caller, interval, named(req.symbols.iter().map(String::as_str)), req.from_ms, to, limit).await {
+        Ok((interval, found)) => grpc::unary(&rpc::BarList { interval, bars: found.into_iter().map(Into::into).collect() }),
+        Err(refused) => grpc::refuse(status(refused)),
+    }
+}
+
+/// Each bar the subscription's base bars complete.
+fn resampled(sub: Subscription, resample: Resample) -> impl Stream<Item = Bar> {
+    stream::unfold((sub, resample, VecDeque::new()), |(sub, mut resample, mut ready)| async move {
+        loop {
+            if let Some(bar) = ready.pop_front() {
+                counter!("gateway_sent_total", "transport" => sub.transport()).increment(1);
+                return Some((bar, (sub, resample, ready)));
+            }
+            let msg = sub.client.next().await?;
+            match serde_json::from_str::<Bar>(&msg) {
+                Ok(bar) => ready.extend(resample.add(bar)),
+                Err(_) => counter!("gateway_skipped_total").increment(1),
+            }
+        }
+    })
+}
+
+/// A bar subscription to `symbols` (all if empty), resampled to `interval`.
+fn bar_subscription(state: &AppState, peer: &Peer, caller: &Caller, interval: Option<String>, symbols: Vec<String>, client: Option<String>, transport: &'static str) -> Result<impl Stream<Item = Bar>, (StatusCode, String)> {
+    let (_, interval_ms) = bar_interval(state, interval)?;
+    let filter = (!symbols.is_empty()).then(|| Filter::In("symbol".into(), symbols.iter().map(|s| Literal::Str(s.to_ascii_lowercase())).collect()));
+    let sub = Subscription::open(&state.bars, Params { client, filter }, &peer.addr.to_string(), caller.0.as_ref(), transport);
+    Ok(resampled(sub, Resample::new(interval_ms, state.bar_ms)))
+}
+
+async fn bar_stream(State(state): State<AppState>, ConnectInfo(peer): ConnectInfo<Peer>, caller: Caller, Query(q): Query<BarsQuery>) -> Response {
+    let sse = q.format.as_deref() == Some("sse");
+    let transport = if sse { "sse" } else { "ndjson" };
+    let out = match bar_subscription(&state, &peer, &caller, q.interval, symbols(q.symbols.as_deref()), q.client, transport) {
+        Ok(out) => out,
+        Err(refused) => return refused.into_response(),
+    };
+    if sse {
+        let events = futures_util::StreamExt::map(out, |bar| Ok::<_, Infallible>(Event::default().json_data(&bar).unwrap_or_default()));
+        return Sse::new(events).keep_alive(KeepAlive::new().interval(Duration::from_secs(15))).into_response();
+    }
+    let lines = futures_util::StreamExt::map(out, |bar| {
+        Ok::<_, Infallible>(serde_json::to_string(&bar).map(|mut l| { l.push('\n'); l }).unwrap_or_default())
+    });
+    ([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(lines)).into_response()
+}
+
+/// `bars.v1.Bars/SubscribeBars`: [`bar_subscription`] as a stream of `Bar`s.
+async fn subscribe_bars(State(state): State<AppState>, ConnectInfo(peer): ConnectInfo<Peer>, caller: Result<Caller, (StatusCode, String)>, body: Body) -> Response {
+    let caller = match caller {
+        Ok(c) => c,
+        Err(refused) => return grpc::refuse(status(refused)),
+    };
+    let req = match grpc::request::<rpc::SubscribeBarsRequest>(body).await {
+        Ok(r) => r,
+        Err(s) => return grpc::refuse(s),
+    };
+    let interval = Some(req.interval).filter(|i| !i.is_empty());
+    let client = Some(req.client).filter(|c| !c.is_empty());
+    let out = match bar_subscription(&state, &peer, &caller, interval, named(req.symbols.iter().map(String::as_str)), client, "grpc") {
+        Ok(out) => out,
+        Err(refused) => return grpc::refuse(status(refused)),
+    };
+    // The bars only stop when the hub cut the client off; it hears why.
+    let cut_off = stream::once(async { Err(grpc::Status::new(Code::Unavailable, "cut off for falling too far behind")) });
+    let frames = futures_util::StreamExt::map(out, |bar| Ok(grpc::frame(&rpc::Bar::from(bar))));
+    grpc::streaming(futures_util::StreamExt::chain(frames, cut_off))
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    symbols: Option<String>,
+    /// ms since the epoch; `to` defaults to now.
+    from: i64,
+    to: Option<i64>,
+    /// `parquet` (default) or `arrow`.
+    format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Parquet,
+    Arrow,
+}
+
+impl Format {
+    fn parse(s: Option<&str>) -> Result<Self> {
+        Ok(match s.unwrap_or("parquet") {
+            "parquet" => Format::Parquet,
+            "arrow" => Format::Arrow,
+            other => anyhow::bail!("unknown format {:?} (expected parquet|arrow)", other),
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Format::Parquet => "parquet",
+            Format::Arrow => "arrow",
+        }
+    }
+
+    /// The media type and file extension.
+    fn content(&self) -> (&'static str, &'static str) {
+        match self {
+            Format::Parquet => ("application/vnd.apache.parquet", "parquet"),
+            Format::Arrow => ("application/vnd.apache.arrow.stream", "arrows"),
+        }
+    }
+}
+
+/// An export file as it's written.
+enum File {
+    Parquet(ParquetExport<Vec<u8>>),
+    Arrow(ArrowExport<Vec<u8>>),
+}
+
+impl File {
+    fn new(format: Format, dataset: Dataset) -> Result<Self> {
+        Ok(match format {
+            Format::Parquet => File::Parquet(ParquetExport::new(Vec::new(), dataset)?),
+            Format::Arrow => File::Arrow(ArrowExport::new(Vec::new(), dataset)?),
+        })
+    }
+
+    fn write(&mut self, rows: &[Vec<Value>]) -> Result<()> {
+        match self {
+            File::Parquet(f) => f.write(rows),
+            File::Arrow(f) => f.write(rows),
+        }
+    }
+
+    /// The bytes written since the last call.
+    fn take(&mut self) -> Vec<u8> {
+        std::mem::take(match self {
+            File::Parquet(f) => f.out_mut(),
+            File::Arrow(f) => f.out_mut(),
+        })
+    }
+
+    fn finish(self) -> Result<Vec<u8>> {
+        match self {
+            File::Parquet(f) => f.finish(),
+            File::Arrow(f) => f.finish(),
+        }
+    }
+}
+
+/// Page through the range, sending the file as each page is written.
+async fn export_pages(qdb: Arc<schema::Client>, range: Range, format: Format, tx: tokio::sync::mpsc::Sender<Result<Vec<u8>>>) -> Result<()> {
+    let dataset = range.dataset;
+    let mut file = File::new(format, dataset)?;
+    let (mut pager, mut rows_out) = (Pager::new(range, EXPORT_PAGE), 0);
+    while let Some(sql) = pager.sql() {
+        let rows = pager.take(qdb.query(&sql).await?)?;
+        file.write(&rows)?;
+        rows_out += rows.len();
+        if pager.sql().is_none() {
+            break;
+        }
+        if tx.send(Ok(file.take())).await.is_err() {
+            // The client went away.
+            return Ok(());
+        }
+    }
+    let _ = tx.send(Ok(file.finish()?)).await;
+    counter!("gateway_export_rows_total", "dataset" => dataset.name()).increment(rows_out as u64);
+    Ok(())
+}
+
+async fn export(State(state): State<AppState>, caller: Caller, Path(dataset): Path<String>, Query(q): Query<ExportQuery>) -> Response {
+    let dataset = match Dataset::parse(&dataset) {
+        Ok(d) => d,
+        Err(e) => return (StatusCode::NOT_FOUND, format!("{:#}\n", e)).into_response(),
+    };
+    let table = match dataset {
+        Dataset::Trades => dataset.default_table(),
+        Dataset::Bars => state.bars_table.clone(),
+    };
+    let format = match Format::parse(q.format.as_deref()) {
+        Ok(f) => f,
+        Err(e) => return bad_request(e).into_response(),
+    };
+    let symbols = match caller.permitted(symbols(q.symbols.as_deref())) {
+        Ok(s) => s,
+        Err(denied) => return denied.into_response(),
+    };
+    let to = q.to.unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+    let range = Range { dataset, table, symbols, from_ms: q.from, to_ms: to };
+    let (content_type, ext) = format.content();
+    let filename = format!("{}-{}-{}.{}", dataset.name(), q.from, to, ext);
+    let (tx, rx) = tokio::sync::mpsc::channel(2);
+    tokio::spawn(async move {
+        if let Err(e) = export_pages(state.qdb, range, format, tx.clone()).await {
+            tracing::warn!(target: "gateway", error = ?e, "export failed");
+            // Cuts the body short, so the client gets an error rather than a truncated file.
+            let _ = tx.send(Err(e)).await;
+        }
+    });
+    counter!("gateway_exports_total", "dataset" => dataset.name(), "format" => format.name()).increment(1);
+    let chunks = stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|c| (c, rx)) });
+    (
+        [(header::CONTENT_TYPE, content_type.to_string()), (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename))],
+        Body::from_stream(chunks),
+    ).into_response()
+}
+
+/// Serve the routes on `listener`, behind TLS if `tls` is given.
+pub async fn run(listener: TcpListener, tls: Option<&TlsOptions>, state: AppState) -> Result<()> {
+    let app = Router::new()
+        .route("/v1/stream/sse", get(sse))
+        .route("/v1/stream/ndjson", get(ndjson))
+        .route("/v1/last", get(last))
+        .route("/v1/bars", get(bar_history))
+        .route("/v1/bars/stream", get(bar_stream))
+        .route("/v1/export/{dataset}", get(export))
+        .route("/bars.v1.Bars/GetBars", post(get_bars))
+        .route("/bars.v1.Bars/SubscribeBars", post(subscribe_bars))
+        .with_state(state)
+        .into_make_service_with_connect_info::<Peer>();
+    match tls {
+        Some(tls) => axum::serve(TlsListener::new(listener, tls.server_config()?, "gateway_http")?, app).await?,
+        None => axum::serve(listener, app).await?,
+    }
+    Ok(())
+}
@@ -0,0 +1,137 @@
+//! The latest trade per symbol and exchange, with its change over the last
+//! 24 hours, for `GET /v1/last`.
+//!
+//! The same symbol on two exchanges is two entries: their prices differ, and
+//! interleaving their trades would make one exchange's price look like the
+//! other's change.
+//!
+//! The 24h reference is kept as the first price of each minute, so a symbol
+//! costs at most 1440 entries. The cache starts empty: until the gateway has
+//! seen a day of trades, the change is over what it has, and
+//! `change_since_ms` says from when.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+const MINUTE_MS: i64 = 60 * 1000;
+
+/// The fields of a normalized trade the cache needs.
+#[derive(Debug, Deserialize)]
+pub struct Tick {
+    pub symbol: String,
+    pub price: f64,
+    pub qty: f64,
+    pub ts_ms: i64,
+    #[serde(default)]
+    pub exchange: Option<String>,
+}
+
+/// One symbol in the `/v1/last` reply.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Last {
+    pub symbol: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exchange: Option<String>,
+    pub price: f64,
+    pub qty: f64,
+    pub ts_ms: i64,
+    /// Percent change from the price at `change_since_ms`.
+    pub change_24h_pct: f64,
+    /// The reference trade: 24h before `ts_ms`, or the oldest the cache has.
+    pub change_since_ms: i64,
+    /// How long ago the last trade was, by its exchange timestamp.
+    pub stale_ms: i64,
+    pub stale: bool,
+}
+
+struct Entry {
+    last: Tick,
+    /// `(ts_ms, price)` of the first trade in each minute, oldest first.
+    minutes: VecDeque<(i64, f64)>,
+}
+
+pub struct LastCache {
+    stale_after_ms: i64,
+    /// By `(symbol, exchange)`: uppercase and lowercase, an empty exchange
+    /// for trades without one.
+    symbols: BTreeMap<(String, String), Entry>,
+}
+
+fn exchange_key(exchange: Option<&str>) -> String {
+    exchange.unwrap_or_default().to_ascii_lowercase()
+}
+
+impl LastCache {
+    pub fn new(stale_after_ms: i64) -> Self {
+        Self { stale_after_ms, symbols: BTreeMap::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Record a trade; one older than the last for its symbol and exchange
+    /// (a redelivery) is ignored.
+    pub fn update(&mut self, mut tick: Tick) {
+        tick.symbol.make_ascii_uppercase();
+        let (ts, price) = (tick.ts_ms, tick.price);
+        let key = (tick.symbol.clone(), exchange_key(tick.exchange.as_deref()));
+        let entry = match self.symbols.get_mut(&key) {
+            Some(e) if ts < e.last.ts_ms => return,
+            Some(e) => {
+                e.last = tick;
+                e
+            }
+            None => self.symbols.entry(key).or_insert(Entry { last: tick, minutes: VecDeque::new() }),
+        };
+        if entry.minutes.back().is_none_or(|&(at, _)| at.div_euclid(MINUTE_MS) < ts.div_euclid(MINUTE_MS)) {
+            entry.minutes.push_back((ts, price));
+        }
+        // Keep one minute at or before the 24h mark as the reference.
+        while entry.minutes.get(1).is_some_and(|&(at, _)| at <= ts - DAY_MS) {
+            entry.minutes.pop_front();
+        }
+    }
+
+    /// `symbols` (all of them if empty) at `now_ms`, one entry per exchange
+    /// or only `exchange`'s, and the symbols the cache hasn't seen there.
+    pub fn snapshot(&self, symbols: &[String], exchange: Option<&str>, now_ms: i64) -> (Vec<Last>, Vec<String>) {
+        let exchange = exchange.map(|x| exchange_key(Some(x)));
+        let entries: Vec<(&(String, String), &Entry)> = match symbols.is_empty() {
+            true => self.symbols.iter().collect(),
+            false => symbols.iter()
+                .flat_map(|s| {
+                    let s = s.to_ascii_uppercase();
+                    self.symbols.range((s.clone(), String::new())..).take_while(move |((sym, _), _)| *sym == s)
+                })
+                .collect(),
+        };
+        let mut found = Vec::new();
+        for ((symbol, _), e) in entries.into_iter().filter(|((_, x), _)| exchange.as_ref().is_none_or(|want| want == x)) {
+            let (since, reference) = e.minutes.front().copied().unwrap_or((e.last.ts_ms, e.last.price));
+            let stale_ms = (now_ms - e.last.ts_ms).max(0);
+            found.push(Last {
+                symbol: symbol.clone(),
+                exchange: e.last.exchange.clone(),
+                price: e.last.price,
+                qty: e.last.qty,
+                ts_ms: e.last.ts_ms,
+                change_24h_pct: if reference != 0.0 { (e.last.price / reference - 1.0) * 100.0 } else { 0.0 },
+                change_since_ms: since,
+                stale_ms,
+                stale: stale_ms > self.stale_after_ms,
+            });
+        }
+        let missing = symbols.iter()
+            .map(|s| s.to_ascii_uppercase())
+            .filter(|s| !found.iter().any(|l| l.symbol == *s))
+            .collect();
+        (found, missing)
+    }
+}
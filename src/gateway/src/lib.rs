@@ -0,0 +1,10 @@
+//! Live rebroadcast of a bus topic (`ticks.norm` by default) to
+//! dashboards and other live clients, over WebSocket, server-sent events or
+//! JSON lines.
+
+pub mod bars;
+pub mod fanout;
+pub mod last;
+pub mod query;
+pub mod rpc;
+pub mod subscribe;
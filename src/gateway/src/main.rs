@@ -0,0 +1,306 @@
+//! Gateway: `TOPIC_IN` (default `ticks.norm`) -> every WebSocket client on
+//! `GATEWAY_ADDR`, one text frame per record.
+//!
+//! Clients connect to `ws://<addr>/` and may name themselves with
+//! `?client=<name>` for the logs, and narrow what they're sent
+//! with `?filter=<expr>` (see [`gateway::query`]). Each has its own send queue
+//! (see [`gateway::fanout`]), so one slow dashboard can't back-pressure the
+//! rest or the bus.
+//!
+//! For clients that can't use WebSocket, the same subscriptions are served
+//! over plain HTTP on `GATEWAY_HTTP_ADDR` (see `http`), as server-sent events
+//! or as chunked JSON lines. The same port serves `/v1/last`, the latest
+//! trade per symbol from an in-memory [`gateway::last::LastCache`], and
+//! candles: live from `BARS_TOPIC` and historical from QuestDB (see
+//! [`gateway::bars`]), over HTTP or as the gRPC `bars.v1.Bars` service.
+//!
+//! With `GATEWAY_CLIENTS` set (the fetcher's client list format, see
+//! [`fetcher::auth`]), both ports only serve those clients, each known by its
+//! TLS client certificate or an `Authorization: Bearer <token>`, and each is
+//! only sent the symbols it's listed with. `GATEWAY_TLS_*` puts both ports
+//! behind TLS, optionally mutual (see [`fetcher::tls`]).
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use bus::commit::{CommitStrategy, Committer};
+use axum::serve::Listener;
+use bus::BusConfig;
+use consumer::schema;
+use fetcher::auth::{self, Live};
+use fetcher::tls::{Peer, TlsListener, TlsOptions};
+use futures_util::{SinkExt, StreamExt};
+use gateway::fanout::{self, Client, Hub, Policy};
+use gateway::last::{LastCache, Tick};
+use gateway::subscribe::{Params, Subscription};
+use metrics::{counter, gauge};
+use obsv::load::Load;
+use obsv::{init_metrics, init_tracing, namespace, startup};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::Message;
+
+mod http;
+
+fn env<T: AsRef<str>>(k: T, default: &str) -> String {
+    std::env::var(k.as_ref()).unwrap_or_else(|_| default.to_string())
+}
+
+/// One client's connection, until either side closes it. With a client
+/// list, the upgrade is refused unless the request authenticates.
+// The handshake callback's error type is tungstenite's, not ours.
+#[allow(clippy::result_large_err)]
+async fn serve<S>(hub: Arc<Hub>, stream: S, peer: Peer, clients: Option<Live>) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let clients = clients.map(|c| c.current());
+    let (mut parsed, mut caller) = (None, None);
+    let ws = tokio_tungstenite::accept_hdr_async(stream, |req: &Request, resp: Response| {
+        if let Some(clients) = &clients {
+            match clients.authenticate(peer.identity.as_deref(), auth::bearer(req.headers())) {
+                Some(c) => caller = Some(c.clone()),
+                None => {
+                    counter!("gateway_unauthorized_total", "transport" => "ws").increment(1);
+                    let mut err = ErrorResponse::new(Some("missing or invalid credentials\n".to_string()));
+                    *err.status_mut() = StatusCode::UNAUTHORIZED;
+                    return Err(err);
+                }
+            }
+        }
+        match Params::parse(req.uri().query()) {
+            Ok(p) => {
+                parsed = Some(p);
+                Ok(resp)
+            }
+            // A bad filter is refused before the upgrade, with the reason in the body.
+            Err(e) => {
+                counter!("gateway_bad_filters_total").increment(1);
+                let mut err = ErrorResponse::new(Some(format!("bad filter: {:#}\n", e)));
+                *err.status_mut() = StatusCode::BAD_REQUEST;
+                Err(err)
+            }
+        }
+    }).await?;
+    let sub = Subscription::open(&hub, parsed.unwrap_or_default(), &peer.addr.to_string(), caller.as_ref(), "ws");
+    pump(ws, &sub.client).await
+}
+
+async fn pump<S>(ws: tokio_tungstenite::WebSocketStream<S>, client: &Client) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut w, mut r) = ws.split();
+    loop {
+        tokio::select! {
+            msg = client.next() => match msg {
+                Some(msg) => {
+                    w.send(Message::Text(msg.to_string())).await?;
+                    counter!("gateway_sent_total", "transport" => "ws").increment(1);
+                }
+                // Cut off as too slow.
+                None => {
+                    let _ = w.send(Message::Close(None)).await;
+                    return Ok(());
+                }
+            },
+            // Clients only talk to close; tungstenite answers pings itself.
+            frame = r.next() => match frame {
+                None | Some(Ok(Message::Close(_))) => return Ok(()),
+                Some(Err(e)) => return Err(e.into()),
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Serve WebSocket clients from `listener`, each on a task of its own.
+async fn accept<L>(mut listener: L, hub: Arc<Hub>, clients: Option<Live>, peer: fn(L::Addr) -> Peer)
+where
+    L: Listener,
+    L::Io: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    loop {
+        let (stream, addr) = listener.accept().await;
+        let (hub, clients, peer) = (hub.clone(), clients.clone(), peer(addr));
+        tokio::spawn(async move {
+            let addr = peer.addr;
+            if let Err(e) = serve(hub, stream, peer, clients).await {
+                tracing::debug!(target: "gateway", peer = %addr, error = ?e, "client connection ended");
+            }
+        });
+    }
+}
+
+/// Rebroadcast the consumer's bars from `topic` to the bar subscriptions.
+async fn bars(bus: BusConfig, topic: String, group: String, hub: Arc<Hub>, load: Load) -> Result<()> {
+    let mut subscriber = bus.subscriber(&topic, &group).await?;
+    let mut commits = Committer::new(CommitStrategy::Interval(Duration::from_secs(1)));
+    tracing::info!(target: "gateway", topic = %topic, "rebroadcasting bars");
+    while let Some(next) = subscriber.next().await {
+        let d = match next {
+            Ok(d) => d,
+            Err(e) => { tracing::error!(target: "gateway", error = ?e, "bars poll error"); continue; }
+        };
+        let _busy = load.busy();
+        load.processed(1);
+        match d.payload_str() {
+            Some(s) if !s.is_empty() => hub.broadcast(Arc::from(s)),
+            _ => counter!("gateway_skipped_total").increment(1),
+        }
+        if let Err(e) = commits.done(subscriber.as_ref(), d).await {
+            tracing::warn!(target: "gateway", error = ?e, "bars commit failed");
+        }
+    }
+    let _ = commits.commit(subscriber.as_ref()).await;
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!("nats", "pulsar"), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    init_metrics(9477)?;
+
+    let topic_in = env("TOPIC_IN", "ticks.norm");
+    let group_id = env("GROUP_ID", "gateway");
+    let addr = env("GATEWAY_ADDR", "0.0.0.0:8080");
+    let http_addr = env("GATEWAY_HTTP_ADDR", "0.0.0.0:8081");
+    // Messages a client may have queued before GATEWAY_SLOW_POLICY applies.
+    let queue_max: usize = env("GATEWAY_QUEUE_MAX", "1000").parse().unwrap_or(1000);
+    let policy = Policy::parse(&env("GATEWAY_SLOW_POLICY", "drop_oldest"))?;
+    // A symbol whose last trade is older than this is reported `stale` by /v1/last.
+    let stale_secs: i64 = env("GATEWAY_STALE_SECS", "60").parse().unwrap_or(60);
+    // The consumer's bars: live on BARS_TOPIC (empty: history only), stored in BARS_TABLE.
+    let bar_ms = consumer::rollup::window_from_env();
+    let bars_topic = env("BARS_TOPIC", &consumer::rollup::default_topic(bar_ms));
+    let bars_table = namespace::table(&env("BARS_TABLE", "trades_1m"));
+
+    let hub = Arc::new(Hub::new(queue_max, policy));
+    let last = Arc::new(Mutex::new(LastCache::new(stale_secs * 1000)));
+    let bar_hub = Arc::new(Hub::new(queue_max, policy));
+    // Without a client list, anyone who can reach the ports is served.
+    let clients = auth::watch("GATEWAY_CLIENTS", None).await?;
+    if clients.is_none() {
+        tracing::warn!(target: "gateway", "GATEWAY_CLIENTS is not set; every endpoint is open to anyone who can connect");
+    }
+    let tls = TlsOptions::from_env("GATEWAY")?;
+    let tls_on = tls.is_some();
+    let listener = TcpListener::bind(&addr).await?;
+    match &tls {
+        Some(tls) => {
+            let listener = TlsListener::new(listener, tls.server_config()?, "gateway_ws")?;
+            tokio::spawn(accept(listener, hub.clone(), clients.clone(), |p: Peer| p));
+        }
+        None => {
+            tokio::spawn(accept(listener, hub.clone(), clients.clone(), |addr| Peer { addr, identity: None }));
+        }
+    }
+    let qdb = Arc::new(schema::Client::authed(&env("QDB_HTTP_URL", "http://localhost:9000")).await?);
+    startup::wait_for("questdb", || qdb.ping()).await?;
+    {
+        let state = http::AppState {
+            hub: hub.clone(),
+            last: last.clone(),
+            bars: bar_hub.clone(),
+            qdb,
+            bars_table,
+            bar_ms: bar_ms.max(1000),
+            clients,
+        };
+        let listener = TcpListener::bind(&http_addr).await?;
+        tokio::spawn(async move {
+            if let Err(e) = http::run(listener, tls.as_ref(), state).await {
+                tracing::error!(target: "gateway", error = ?e, "http server stopped");
+            }
+        });
+    }
+    {
+        let hubs = [hub.clone(), bar_hub.clone()];
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(Duration::from_secs(1));
+            let mut shown = HashSet::new();
+            loop {
+                tick.tick().await;
+                let now = Instant::now();
+                let lags = fanout::worst(hubs.iter().flat_map(|h| h.lag(now)));
+                // The admin API's view of what each client label has queued.
+                obsv::admin::set("in_flight", "gateway", serde_json::Value::Object(lags.iter().map(|l| (l.client.clone(), serde_json::json!({
+                    "queued": l.queued,
+                    "behind_ms": l.behind.as_millis() as u64,
+                    "dropped": l.dropped,
+                }))).collect()));
+                // A label whose clients have all gone reads 0 rather than its last value.
+                let live: HashSet<String> = lags.iter().map(|l| l.client.clone()).collect();
+                for gone in shown.difference(&live) {
+                    gauge!("gateway_client_queue_depth", "client" => gone.clone()).set(0.0);
+                    gauge!("gateway_client_lag_ms", "client" => gone.clone()).set(0.0);
+                }
+                for lag in lags {
+                    gauge!("gateway_client_queue_depth", "client" => lag.client.clone()).set(lag.queued as f64);
+                    gauge!("gateway_client_lag_ms", "client" => lag.client).set(lag.behind.as_secs_f64() * 1e3);
+                }
+                shown = live;
+            }
+        });
+    }
+
+    // Live data only: offsets are committed so lag monitoring sees the gateway keep up.
+    let bus = BusConfig::from_env()?;
+    let topics: Vec<&str> = [&topic_in, &bars_topic].into_iter().map(String::as_str).filter(|t| !t.is_empty()).collect();
+    startup::wait_for("kafka", || bus.check_topics(&topics)).await?;
+    bus::audit::start(&bus).await;
+    // Trades and bars both: rebroadcasting is the gateway's work either way.
+    let load = Load::new("gateway")?;
+    let bar_feed = {
+        let (bus, group, load) = (bus.clone(), env("BARS_GROUP_ID", "gateway-bars"), load.clone());
+        async move {
+            match bars_topic.is_empty() {
+                true => std::future::pending::<Result<()>>().await,
+                false => {
+                    bars(bus, bars_topic, group, bar_hub, load).await?;
+                    anyhow::bail!("bars subscription closed")
+                }
+            }
+        }
+    };
+    let mut subscriber = bus.subscriber(&topic_in, &group_id).await?;
+    let mut commits = Committer::new(CommitStrategy::Interval(Duration::from_secs(1)));
+    tracing::info!(target: "gateway", input = %topic_in, addr = %addr, http_addr = %http_addr, queue_max, ?policy, tls = tls_on, "rebroadcasting");
+    let trades = async {
+        while let Some(next) = subscriber.next().await {
+            let d = match next {
+                Ok(d) => d,
+                Err(e) => { tracing::error!(target: "gateway", error = ?e, "poll error"); continue; }
+            };
+            let _busy = load.busy();
+            load.processed(1);
+            match d.payload_str() {
+                Some(s) if !s.is_empty() => {
+                    if let Ok(tick) = serde_json::from_str::<Tick>(s) {
+                        let mut last = last.lock().unwrap_or_else(PoisonError::into_inner);
+                        last.update(tick);
+                        gauge!("gateway_cached_symbols").set(last.len() as f64);
+                    }
+                    hub.broadcast(Arc::from(s));
+                }
+                _ => counter!("gateway_skipped_total").increment(1),
+            }
+            if let Err(e) = commits.done(subscriber.as_ref(), d).await {
+                tracing::warn!(target: "gateway", error = ?e, "commit failed");
+            }
+        }
+        let _ = commits.commit(subscriber.as_ref()).await;
+    };
+    // The bar feed only returns on failure; it takes the gateway down with it.
+    tokio::select! {
+        () = trades => Ok(()),
+        res = bar_feed => res,
+    }
+}
@@ -0,0 +1,323 @@
+//! Subscription filters: `symbol in ('BTCUSDT', 'ETHUSDT') AND exchange = 'binance' AND qty > 1`.
+//!
+//! A filter is parsed once, when the client connects, into a [`Filter`] tree
+//! whose literals are already typed and lowercased; matching a record is then
+//! a walk over that tree against the record's parsed JSON, with no
+//! allocation.
+//!
+//! ```text
+//! expr   := and ("OR" and)*
+//! and    := unary ("AND" unary)*
+//! unary  := "NOT" unary | "(" expr ")" | field op literal | field "NOT"? "IN" "(" literal ("," literal)* ")"
+//! op     := "=" | "!=" | "<>" | "<" | "<=" | ">" | ">="
+//! literal:= 'string' | number | true | false
+//! ```
+//!
+//! A filter is at most [`MAX_LEN`] bytes, nested at most [`MAX_DEPTH`]
+//! parentheses or `NOT`s deep: it comes from the client, and the parser recurses.
+//!
+//! Keywords are case-insensitive, and so are string comparisons, so
+//! `symbol = 'btcusdt'` matches `BTCUSDT`. Strings only support `=`, `!=`
+//! and `IN`. A comparison against a field the record doesn't have is false.
+//!
+//! [`Filter::Prefix`] has no syntax: the gateway builds it from a client's
+//! symbol ACL (see [`crate::subscribe`]).
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+/// Longest filter accepted, in bytes.
+pub const MAX_LEN: usize = 4096;
+/// Deepest nesting of parentheses and `NOT` accepted.
+pub const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    /// Stored lowercased.
+    Str(String),
+    Num(f64),
+    Bool(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Cmp(String, Op, Literal),
+    In(String, Vec<Literal>),
+    /// A string field starting with the (lowercased) prefix, in any case.
+    Prefix(String, String),
+    Not(Box<Filter>),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+}
+
+impl Filter {
+    pub fn parse(src: &str) -> Result<Self> {
+        if src.len() > MAX_LEN {
+            bail!("filter is {} bytes, more than the {} allowed", src.len(), MAX_LEN);
+        }
+        let mut p = Parser { tokens: lex(src)?, at: 0, depth: 0 };
+        let filter = p.expr()?;
+        if let Some(t) = p.tokens.get(p.at) {
+            bail!("unexpected {} after the end of the filter", t);
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, record: &Value) -> bool {
+        match self {
+            Filter::Cmp(field, op, lit) => record.get(field).is_some_and(|v| compare(v, *op, lit)),
+            Filter::In(field, lits) => record.get(field).is_some_and(|v| lits.iter().any(|l| compare(v, Op::Eq, l))),
+            Filter::Prefix(field, prefix) => record.get(field).and_then(Value::as_str)
+                .is_some_and(|s| s.len() >= prefix.len() && s.as_bytes()[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes())),
+            Filter::Not(f) => !f.matches(record),
+            Filter::And(fs) => fs.iter().all(|f| f.matches(record)),
+            Filter::Or(fs) => fs.iter().any(|f| f.matches(record)),
+        }
+    }
+}
+
+fn compare(v: &Value, op: Op, lit: &Literal) -> bool {
+    match (v, lit) {
+        (Value::String(s), Literal::Str(l)) => match op {
+            Op::Eq => s.eq_ignore_ascii_case(l),
+            Op::Ne => !s.eq_ignore_ascii_case(l),
+            // Rejected by the parser.
+            _ => false,
+        },
+        (Value::Number(n), Literal::Num(l)) => n.as_f64().is_some_and(|n| match op {
+            Op::Eq => n == *l,
+            Op::Ne => n != *l,
+            Op::Lt => n < *l,
+            Op::Le => n <= *l,
+            Op::Gt => n > *l,
+            Op::Ge => n >= *l,
+        }),
+        (Value::Bool(b), Literal::Bool(l)) => match op {
+            Op::Eq => b == l,
+            Op::Ne => b != l,
+            _ => false,
+        },
+        // A record value of another type never matches, `!=` included.
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Op(Op),
+    LParen,
+    RParen,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(s) => write!(f, "{:?}", s),
+            Token::Str(s) => write!(f, "'{}'", s),
+            Token::Num(n) => write!(f, "{}", n),
+            Token::Op(op) => write!(f, "{:?}", op),
+            Token::LParen => f.write_str("'('"),
+            Token::RParen => f.write_str("')'"),
+            Token::Comma => f.write_str("','"),
+        }
+    }
+}
+
+fn lex(src: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = src.char_indices().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => { chars.next(); }
+            '(' => { chars.next(); tokens.push(Token::LParen); }
+            ')' => { chars.next(); tokens.push(Token::RParen); }
+            ',' => { chars.next(); tokens.push(Token::Comma); }
+            '=' => { chars.next(); tokens.push(Token::Op(Op::Eq)); }
+            '!' | '<' | '>' => {
+                chars.next();
+                let next = chars.peek().map(|&(_, c)| c);
+                let op = match (c, next) {
+                    ('!', Some('=')) | ('<', Some('>')) => Op::Ne,
+                    ('<', Some('=')) => Op::Le,
+                    ('>', Some('=')) => Op::Ge,
+                    ('<', _) => Op::Lt,
+                    ('>', _) => Op::Gt,
+                    _ => bail!("expected '!=' at offset {}", i),
+                };
+                if matches!(op, Op::Ne | Op::Le | Op::Ge) {
+                    chars.next();
+                }
+                tokens.push(Token::Op(op));
+            }
+            '\'' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        // `''` is a quote inside the string.
+                        Some((_, '\'')) if chars.peek().map(|&(_, c)| c) == Some('\'') => { chars.next(); s.push('\''); }
+                        Some((_, '\'')) => break,
+                        Some((_, c)) => s.push(c),
+                        None => bail!("unterminated string starting at offset {}", i),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let mut end = i;
+                while let Some(&(j, c)) = chars.peek() {
+                    let exp_sign = (c == '-' || c == '+') && matches!(src[..j].chars().last(), Some('e' | 'E'));
+                    if !(c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exp_sign || (j == i && c == '-')) {
+                        break;
+                    }
+                    end = j + c.len_utf8();
+                    chars.next();
+                }
+                let n: f64 = src[i..end].parse().map_err(|_| anyhow::anyhow!("bad number {:?} at offset {}", &src[i..end], i))?;
+                tokens.push(Token::Num(n));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = i;
+                while let Some(&(j, c)) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    end = j + 1;
+                    chars.next();
+                }
+                tokens.push(Token::Ident(src[i..end].to_string()));
+            }
+            other => bail!("unexpected {:?} at offset {}", other, i),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+    /// Parentheses and `NOT`s open around the current token.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let t = self.tokens.get(self.at).cloned().ok_or_else(|| anyhow::anyhow!("filter ends too early"))?;
+        self.at += 1;
+        Ok(t)
+    }
+
+    fn keyword(&mut self, kw: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(s)) if s.eq_ignore_ascii_case(kw)) {
+            self.at += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, want: Token) -> Result<()> {
+        match self.next()? {
+            t if t == want => Ok(()),
+            t => bail!("expected {}, found {}", want, t),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Filter> {
+        let mut any = vec![self.and()?];
+        while self.keyword("or") {
+            any.push(self.and()?);
+        }
+        Ok(if any.len() == 1 { any.pop().unwrap() } else { Filter::Or(any) })
+    }
+
+    fn and(&mut self) -> Result<Filter> {
+        let mut all = vec![self.unary()?];
+        while self.keyword("and") {
+            all.push(self.unary()?);
+        }
+        Ok(if all.len() == 1 { all.pop().unwrap() } else { Filter::And(all) })
+    }
+
+    /// Parse `inner` one level deeper, refusing past [`MAX_DEPTH`].
+    fn nested(&mut self, inner: fn(&mut Self) -> Result<Filter>) -> Result<Filter> {
+        if self.depth >= MAX_DEPTH {
+            bail!("filter is nested more than {} deep", MAX_DEPTH);
+        }
+        self.depth += 1;
+        let filter = inner(self);
+        self.depth -= 1;
+        filter
+    }
+
+    fn unary(&mut self) -> Result<Filter> {
+        if self.keyword("not") {
+            return Ok(Filter::Not(Box::new(self.nested(Self::unary)?)));
+        }
+        if self.peek() == Some(&Token::LParen) {
+            self.at += 1;
+            let inner = self.nested(Self::expr)?;
+            self.expect(Token::RParen)?;
+            return Ok(inner);
+        }
+        let field = match self.next()? {
+            Token::Ident(s) if !is_keyword(&s) => s,
+            t => bail!("expected a field name, found {}", t),
+        };
+        let negated = self.keyword("not");
+        if self.keyword("in") {
+            self.expect(Token::LParen)?;
+            let mut lits = vec![self.literal()?];
+            while self.peek() == Some(&Token::Comma) {
+                self.at += 1;
+                lits.push(self.literal()?);
+            }
+            self.expect(Token::RParen)?;
+            let filter = Filter::In(field, lits);
+            return Ok(if negated { Filter::Not(Box::new(filter)) } else { filter });
+        }
+        if negated {
+            bail!("expected IN after {} NOT", field);
+        }
+        let op = match self.next()? {
+            Token::Op(op) => op,
+            t => bail!("expected a comparison after {}, found {}", field, t),
+        };
+        let lit = self.literal()?;
+        if !matches!(lit, Literal::Num(_)) && !matches!(op, Op::Eq | Op::Ne) {
+            bail!("{} can only be compared with = or != against {:?}", field, lit);
+        }
+        Ok(Filter::Cmp(field, op, lit))
+    }
+
+    fn literal(&mut self) -> Result<Literal> {
+        Ok(match self.next()? {
+            Token::Str(s) => Literal::Str(s.to_ascii_lowercase()),
+            Token::Num(n) => Literal::Num(n),
+            Token::Ident(s) if s.eq_ignore_ascii_case("true") => Literal::Bool(true),
+            Token::Ident(s) if s.eq_ignore_ascii_case("false") => Literal::Bool(false),
+            t => bail!("expected a value, found {}", t),
+        })
+    }
+}
+
+fn is_keyword(s: &str) -> bool {
+    ["and", "or", "not", "in", "true", "false"].iter().any(|k| s.eq_ignore_ascii_case(k))
+}
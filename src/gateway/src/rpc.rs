@@ -0,0 +1,67 @@
+//! The messages of `bars.v1.Bars` (`proto/bars.proto`): the gRPC side of
+//! [`crate::bars`], served on the HTTP port next to `/v1/bars` and
+//! `/v1/bars/stream` with the fetcher's [`fetcher::grpc`] framing.
+
+/// `bars.v1.SubscribeBarsRequest`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SubscribeBarsRequest {
+    #[prost(string, tag = "1")]
+    pub interval: String,
+    #[prost(string, repeated, tag = "2")]
+    pub symbols: Vec<String>,
+    #[prost(string, tag = "3")]
+    pub client: String,
+}
+
+/// `bars.v1.GetBarsRequest`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetBarsRequest {
+    #[prost(string, tag = "1")]
+    pub interval: String,
+    #[prost(string, repeated, tag = "2")]
+    pub symbols: Vec<String>,
+    #[prost(int64, tag = "3")]
+    pub from_ms: i64,
+    /// 0 for now.
+    #[prost(int64, tag = "4")]
+    pub to_ms: i64,
+    /// 0 for the default.
+    #[prost(uint32, tag = "5")]
+    pub limit: u32,
+}
+
+/// `bars.v1.Bar`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Bar {
+    #[prost(string, tag = "1")]
+    pub symbol: String,
+    #[prost(int64, tag = "2")]
+    pub ts_ms: i64,
+    #[prost(double, tag = "3")]
+    pub open: f64,
+    #[prost(double, tag = "4")]
+    pub high: f64,
+    #[prost(double, tag = "5")]
+    pub low: f64,
+    #[prost(double, tag = "6")]
+    pub close: f64,
+    #[prost(double, tag = "7")]
+    pub volume: f64,
+    #[prost(uint64, tag = "8")]
+    pub trades: u64,
+}
+
+/// `bars.v1.BarList`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BarList {
+    #[prost(string, tag = "1")]
+    pub interval: String,
+    #[prost(message, repeated, tag = "2")]
+    pub bars: Vec<Bar>,
+}
+
+impl From<consumer::rollup::Bar> for Bar {
+    fn from(b: consumer::rollup::Bar) -> Self {
+        Self { symbol: b.symbol, ts_ms: b.ts_ms, open: b.open, high: b.high, low: b.low, close: b.close, volume: b.volume, trades: b.trades }
+    }
+}
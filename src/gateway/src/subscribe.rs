@@ -0,0 +1,126 @@
+//! What every transport shares: the `?client=&filter=` parameters of a
+//! subscription, the hub membership that lasts as long as it does, and the
+//! symbol ACL of the client behind it.
+//!
+//! With `GATEWAY_CLIENTS` set, every request comes from one of those clients
+//! (see [`fetcher::auth`]), and is only sent or answered with the symbols it's
+//! allowed: [`Subscription::open`] ANDs its [`acl`] onto whatever filter it
+//! asked for, and [`permitted`] checks the symbols a query names.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use fetcher::auth::{self, Pattern};
+use metrics::gauge;
+
+use crate::fanout::{Client, Hub};
+use crate::query::{Filter, Literal};
+
+/// The query parameters a client subscribes with.
+#[derive(Debug, Default)]
+pub struct Params {
+    pub client: Option<String>,
+    pub filter: Option<Filter>,
+}
+
+impl Params {
+    /// `client=<name>&filter=<expr>`, URL-encoded; an empty filter is no filter.
+    pub fn parse(query: Option<&str>) -> Result<Self> {
+        let mut params = Params::default();
+        for (k, v) in form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+            match &*k {
+                "client" => params.client = Some(v.into_owned()),
+                "filter" if !v.trim().is_empty() => params.filter = Some(Filter::parse(&v)?),
+                _ => {}
+            }
+        }
+        Ok(params)
+    }
+
+    /// The name the logs know the client by: the given one if it's usable, else `peer`.
+    pub fn name(&self, peer: &str) -> String {
+        self.client.as_deref()
+            .filter(|n| !n.is_empty() && n.len() <= 64 && n.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)))
+            .unwrap_or(peer)
+            .to_string()
+    }
+}
+
+/// A client's place in the hub; dropping it leaves.
+pub struct Subscription {
+    hub: Arc<Hub>,
+    id: u64,
+    pub client: Arc<Client>,
+    /// [`Params::name`], for the logs.
+    name: String,
+    transport: &'static str,
+}
+
+/// The `client` label for `caller`: its name in the client list, which is
+/// bounded, or `anonymous` on an open gateway.
+pub fn label(caller: Option<&auth::Client>) -> &str {
+    caller.map_or(ANONYMOUS, |c| c.name.as_str())
+}
+
+/// [`label`] without a client list.
+pub const ANONYMOUS: &str = "anonymous";
+
+/// The records `client` may be sent, as a filter on their `symbol`; `None`
+/// for no client (an open gateway) or one allowed every symbol.
+pub fn acl(client: Option<&auth::Client>) -> Option<Filter> {
+    let client = client.filter(|c| !c.allows_all())?;
+    let field = || "symbol".to_string();
+    let allowed: Vec<Filter> = client.symbols().iter().map(|p| match p {
+        Pattern::Exact(s) => Filter::Cmp(field(), crate::query::Op::Eq, Literal::Str(s.to_ascii_lowercase())),
+        Pattern::Prefix(p) => Filter::Prefix(field(), p.to_ascii_lowercase()),
+        Pattern::Any => unreachable!("allows_all"),
+    }).collect();
+    Some(Filter::Or(allowed))
+}
+
+/// The symbols a query for `client` may read: `symbols` if it may read all
+/// of them, or, when it named none, every symbol it's allowed, provided
+/// those are exact. `Err` is why not.
+pub fn permitted(client: Option<&auth::Client>, symbols: Vec<String>) -> Result<Vec<String>, String> {
+    let Some(client) = client.filter(|c| !c.allows_all()) else { return Ok(symbols) };
+    if let Some(denied) = symbols.iter().find(|s| !client.allows(s)) {
+        return Err(format!("{} may not read {}", client.name, denied));
+    }
+    if !symbols.is_empty() {
+        return Ok(symbols);
+    }
+    client.symbols().iter()
+        .map(|p| match p {
+            Pattern::Exact(s) => Ok(s.clone()),
+            _ => Err(format!("{} must name the symbols it wants", client.name)),
+        })
+        .collect()
+}
+
+impl Subscription {
+    /// Join `hub` with `params` for `caller`, who is only sent what its [`acl`] allows.
+    pub fn open(hub: &Arc<Hub>, params: Params, peer: &str, caller: Option<&auth::Client>, transport: &'static str) -> Self {
+        let name = params.name(peer);
+        let filtered = params.filter.is_some();
+        let filter = match (acl(caller), params.filter) {
+            (Some(acl), Some(asked)) => Some(Filter::And(vec![acl, asked])),
+            (acl, asked) => acl.or(asked),
+        };
+        let (id, client) = hub.join(label(caller), filter);
+        gauge!("gateway_clients").set(hub.clients() as f64);
+        tracing::info!(target: "gateway", client = %name, label = %client.name, peer = %peer, transport, filtered, "client connected");
+        Self { hub: hub.clone(), id, client, name, transport }
+    }
+
+    pub fn transport(&self) -> &'static str {
+        self.transport
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.hub.leave(self.id);
+        gauge!("gateway_clients").set(self.hub.clients() as f64);
+        tracing::info!(target: "gateway", client = %self.name, label = %self.client.name, transport = self.transport, dropped = self.client.dropped(), "client disconnected");
+    }
+}
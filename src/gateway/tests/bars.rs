@@ -0,0 +1,58 @@
+use consumer::rollup::Bar;
+use gateway::bars::{from_rows, history_sql, interval, Resample};
+use serde_json::json;
+
+const M: i64 = 60_000;
+
+fn bar(symbol: &str, ts_ms: i64, close: f64) -> Bar {
+    Bar { symbol: symbol.into(), ts_ms, open: close, high: close + 1.0, low: close - 1.0, close, volume: 2.0, trades: 3 }
+}
+
+#[test]
+fn intervals_are_whole_bars() {
+    assert_eq!(interval("5m", M).unwrap(), 5 * M);
+    assert_eq!(interval("1m", M).unwrap(), M);
+    assert!(interval("90s", M).is_err());
+    assert!(interval("5x", M).is_err());
+}
+
+#[test]
+fn history_is_read_as_is_or_resampled_by_questdb() {
+    let sql = history_sql("trades_1m", &[], 0, 60_000, M, M, 10);
+    assert!(sql.starts_with("SELECT symbol, timestamp, open, high, low, close, volume, trades FROM trades_1m WHERE timestamp >= cast(0 AS timestamp)"));
+    assert!(!sql.contains("SAMPLE BY"));
+
+    let sql = history_sql("trades_1m", &["BTCUSDT".into(), "O'X".into()], 0, 60_000, 5 * M, M, 10);
+    assert!(sql.contains("symbol IN ('BTCUSDT', 'O''X') AND timestamp >= "), "{}", sql);
+    assert!(sql.contains("first(open) open, max(high) high") && sql.ends_with("SAMPLE BY 300s ALIGN TO CALENDAR ORDER BY timestamp LIMIT 10"), "{}", sql);
+
+    let rows = vec![vec![json!("BTCUSDT"), json!("2024-06-01T00:05:00.000000Z"), json!(1.0), json!(2.0), json!(0.5), json!(1.5), json!(10.0), json!(4)]];
+    let bars = from_rows(&rows).unwrap();
+    assert_eq!((bars[0].ts_ms, bars[0].close, bars[0].trades), (1_717_200_300_000, 1.5, 4));
+    assert!(from_rows(&[vec![json!("BTCUSDT")]]).is_err());
+}
+
+#[test]
+fn base_bars_merge_until_the_one_ending_the_interval() {
+    let mut r = Resample::new(5 * M, M);
+    for i in 0..4 {
+        assert!(r.add(bar("BTCUSDT", i * M, 100.0 + i as f64)).is_empty());
+    }
+    // A redelivery is not counted twice.
+    assert!(r.add(bar("BTCUSDT", 3 * M, 500.0)).is_empty());
+    let done = r.add(bar("BTCUSDT", 4 * M, 90.0));
+    assert_eq!(done.len(), 1);
+    let b = &done[0];
+    assert_eq!((b.ts_ms, b.open, b.high, b.low, b.close, b.volume, b.trades), (0, 100.0, 104.0, 89.0, 90.0, 10.0, 15));
+}
+
+#[test]
+fn a_quiet_end_of_interval_closes_on_the_next_bar() {
+    let mut r = Resample::new(5 * M, M);
+    assert!(r.add(bar("ETHUSDT", M, 10.0)).is_empty());
+    assert!(r.add(bar("BTCUSDT", 2 * M, 20.0)).is_empty());
+    let done = r.add(bar("ETHUSDT", 7 * M, 11.0));
+    assert_eq!(done.iter().map(|b| (b.symbol.as_str(), b.ts_ms, b.close)).collect::<Vec<_>>(), [("ETHUSDT", 0, 10.0)]);
+    // Same width as the base: every bar goes straight out.
+    assert_eq!(Resample::new(M, M).add(bar("BTCUSDT", 3 * M, 1.0)).len(), 1);
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use gateway::fanout::{self, Hub, Lag, Policy};
+use gateway::query::Filter;
+
+#[tokio::test]
+async fn a_full_queue_drops_its_oldest_and_leaves_the_others_alone() {
+    let hub = Hub::new(2, Policy::DropOldest);
+    let (_, slow) = hub.join("slow", None);
+    let (_, fast) = hub.join("fast", None);
+    let t0 = Instant::now();
+    for (i, msg) in ["a", "b", "c"].into_iter().enumerate() {
+        hub.broadcast_at(Arc::from(msg), t0 + Duration::from_millis(10 * i as u64));
+        if i < 2 {
+            assert_eq!(fast.next().await.as_deref(), Some(msg));
+        }
+    }
+    let mut lag = hub.lag(t0 + Duration::from_millis(50));
+    lag.sort_by(|a, b| a.client.cmp(&b.client));
+    assert_eq!((lag[0].client.as_str(), lag[0].queued, lag[0].dropped), ("fast", 1, 0));
+    assert_eq!((lag[1].client.as_str(), lag[1].queued, lag[1].dropped), ("slow", 2, 1));
+    assert_eq!(lag[1].behind, Duration::from_millis(40), "measured from b, now the oldest");
+    assert_eq!(slow.next().await.as_deref(), Some("b"));
+    assert_eq!(slow.next().await.as_deref(), Some("c"));
+}
+
+#[tokio::test]
+async fn a_slow_client_is_cut_off_under_disconnect() {
+    let hub = Hub::new(1, Policy::Disconnect);
+    let (_, slow) = hub.join("slow", None);
+    let (_, fast) = hub.join("fast", None);
+    hub.broadcast(Arc::from("a"));
+    assert_eq!(fast.next().await.as_deref(), Some("a"));
+    hub.broadcast(Arc::from("b"));
+    assert!(slow.is_closed());
+    assert_eq!(slow.next().await, None);
+    assert_eq!(hub.clients(), 1);
+    assert_eq!(fast.next().await.as_deref(), Some("b"));
+}
+
+#[tokio::test]
+async fn waiting_clients_wake_on_broadcast_and_leave_cleanly() {
+    let hub = Arc::new(Hub::new(10, Policy::DropOldest));
+    let (id, client) = hub.join("waiting", None);
+    let waiter = tokio::spawn({
+        let client = client.clone();
+        async move { client.next().await }
+    });
+    tokio::task::yield_now().await;
+    hub.broadcast(Arc::from("x"));
+    assert_eq!(waiter.await.unwrap().as_deref(), Some("x"));
+    hub.leave(id);
+    assert_eq!((hub.clients(), client.next().await), (0, None));
+    assert!(Policy::parse("block").is_err());
+}
+
+#[tokio::test]
+async fn filtered_clients_are_only_queued_what_matches() {
+    let hub = Hub::new(10, Policy::DropOldest);
+    let (_, btc) = hub.join("btc", Some(Filter::parse("symbol = 'btcusdt'").unwrap()));
+    let (_, all) = hub.join("all", None);
+    for msg in [r#"{"symbol":"ETHUSDT"}"#, "not json", r#"{"symbol":"BTCUSDT"}"#] {
+        hub.broadcast(Arc::from(msg));
+    }
+    let queued: Vec<_> = {
+        let mut lag = hub.lag(Instant::now());
+        lag.sort_by(|a, b| a.client.cmp(&b.client));
+        lag.into_iter().map(|l| l.queued).collect()
+    };
+    assert_eq!(queued, vec![3, 1]);
+    assert_eq!(btc.next().await.as_deref(), Some(r#"{"symbol":"BTCUSDT"}"#));
+    assert_eq!(all.next().await.as_deref(), Some(r#"{"symbol":"ETHUSDT"}"#));
+}
+
+#[test]
+fn each_label_reports_its_worst_connection() {
+    let lag = |client: &str, queued, ms, dropped| Lag { client: client.into(), queued, behind: Duration::from_millis(ms), dropped };
+    let worst = fanout::worst([lag("desk", 3, 10, 1), lag("anonymous", 1, 5, 0), lag("desk", 1, 40, 2)]);
+    assert_eq!(worst, [lag("anonymous", 1, 5, 0), lag("desk", 3, 40, 3)]);
+}
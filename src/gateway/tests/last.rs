@@ -0,0 +1,68 @@
+use gateway::last::{LastCache, Tick};
+
+const HOUR: i64 = 60 * 60 * 1000;
+
+fn tick(symbol: &str, price: f64, ts_ms: i64) -> Tick {
+    on("binance", symbol, price, ts_ms)
+}
+
+fn on(exchange: &str, symbol: &str, price: f64, ts_ms: i64) -> Tick {
+    Tick { symbol: symbol.into(), price, qty: 1.0, ts_ms, exchange: Some(exchange.into()) }
+}
+
+#[test]
+fn change_is_against_the_price_a_day_before_the_last_trade() {
+    let mut cache = LastCache::new(60_000);
+    // Hourly trades for 30 hours, price 100 + hour.
+    for h in 0..=30 {
+        cache.update(tick("btcusdt", 100.0 + h as f64, h * HOUR));
+    }
+    let (found, missing) = cache.snapshot(&["BTCUSDT".into(), "ETHUSDT".into()], None, 30 * HOUR + 5_000);
+    assert_eq!(missing, vec!["ETHUSDT".to_string()]);
+    let btc = &found[0];
+    assert_eq!((btc.symbol.as_str(), btc.price, btc.change_since_ms), ("BTCUSDT", 130.0, 6 * HOUR));
+    assert!((btc.change_24h_pct - 24.0 / 106.0 * 100.0).abs() < 1e-9);
+    assert_eq!((btc.stale_ms, btc.stale), (5_000, false));
+}
+
+#[test]
+fn a_young_cache_reports_change_since_its_first_trade_and_staleness() {
+    let mut cache = LastCache::new(60_000);
+    cache.update(tick("ETHUSDT", 2000.0, 1_000));
+    cache.update(tick("ETHUSDT", 2100.0, 2 * HOUR));
+    cache.update(tick("ETHUSDT", 1.0, 1_500));
+    cache.update(tick("SOLUSDT", 150.0, 2 * HOUR));
+    let (found, missing) = cache.snapshot(&[], None, 2 * HOUR + 120_000);
+    assert!(missing.is_empty());
+    assert_eq!(found.iter().map(|l| l.symbol.as_str()).collect::<Vec<_>>(), ["ETHUSDT", "SOLUSDT"]);
+    let eth = &found[0];
+    assert_eq!((eth.price, eth.change_since_ms, eth.stale), (2100.0, 1_000, true), "the redelivered older trade is ignored");
+    assert!((eth.change_24h_pct - 5.0).abs() < 1e-9);
+    assert_eq!(found[1].change_24h_pct, 0.0);
+    let json = serde_json::to_value(eth).unwrap();
+    assert_eq!(json["exchange"], "binance");
+}
+
+#[test]
+fn each_exchange_keeps_its_own_last_trade_and_change() {
+    let mut cache = LastCache::new(60_000);
+    cache.update(on("binance", "BTCUSDT", 100.0, 1_000));
+    cache.update(on("coinbase", "BTCUSDT", 200.0, 2_000));
+    cache.update(on("binance", "BTCUSDT", 110.0, 3_000));
+    // Older than coinbase's last, but not binance's: only coinbase ignores it.
+    cache.update(on("coinbase", "BTCUSDT", 1.0, 1_500));
+    assert_eq!(cache.len(), 2);
+
+    let (found, missing) = cache.snapshot(&["btcusdt".into()], None, 4_000);
+    assert!(missing.is_empty());
+    let by: Vec<(&str, f64, i64)> = found.iter().map(|l| (l.exchange.as_deref().unwrap(), l.price, l.change_since_ms)).collect();
+    assert_eq!(by, [("binance", 110.0, 1_000), ("coinbase", 200.0, 2_000)]);
+    assert!((found[0].change_24h_pct - 10.0).abs() < 1e-9);
+    assert_eq!(found[1].change_24h_pct, 0.0);
+
+    let (found, _) = cache.snapshot(&[], Some("Coinbase"), 4_000);
+    assert_eq!(found.iter().map(|l| l.price).collect::<Vec<_>>(), [200.0]);
+    let (found, missing) = cache.snapshot(&["BTCUSDT".into()], Some("kraken"), 4_000);
+    assert!(found.is_empty());
+    assert_eq!(missing, ["BTCUSDT"]);
+}
@@ -0,0 +1,56 @@
+use gateway::query::{Filter, Literal, Op, MAX_DEPTH, MAX_LEN};
+use serde_json::json;
+
+fn trade() -> serde_json::Value {
+    json!({"ts_ms": 1, "symbol": "BTCUSDT", "price": 65000.5, "qty": 2.5, "trade_id": 7, "is_bm": false, "exchange": "binance"})
+}
+
+#[test]
+fn the_example_filter_matches_and_rejects_on_each_clause() {
+    let f = Filter::parse("symbol in ('BTCUSDT', 'ethusdt') AND exchange = 'binance' AND qty > 1").unwrap();
+    assert!(f.matches(&trade()));
+    for miss in [json!({"symbol": "SOLUSDT"}), json!({"exchange": "kraken"}), json!({"qty": 1})] {
+        let mut t = trade();
+        t.as_object_mut().unwrap().extend(miss.as_object().unwrap().clone());
+        assert!(!f.matches(&t), "{}", t);
+    }
+}
+
+#[test]
+fn precedence_negation_and_absent_fields() {
+    // AND binds tighter than OR.
+    let f = Filter::parse("symbol = 'ETHUSDT' or price >= 65000 and NOT is_bm = true").unwrap();
+    assert!(f.matches(&trade()));
+    assert!(Filter::parse("(symbol = 'ETHUSDT' or price >= 65000) and is_bm").is_err(), "bare fields aren't conditions");
+    assert!(Filter::parse("symbol not in ('ETHUSDT') and qty <> 3 and price < 1e5 and price > -1").unwrap().matches(&trade()));
+    // Comparisons on a missing field are false, whichever way round.
+    assert!(!Filter::parse("session = 'us'").unwrap().matches(&trade()));
+    assert!(!Filter::parse("session != 'us'").unwrap().matches(&trade()));
+    assert!(Filter::parse("not session = 'us'").unwrap().matches(&trade()));
+    // So is a comparison against a value of another type.
+    assert!(!Filter::parse("qty = 'x'").unwrap().matches(&trade()));
+}
+
+#[test]
+fn literals_are_typed_once_at_parse_time() {
+    assert_eq!(
+        Filter::parse("exchange = 'O''Brien'").unwrap(),
+        Filter::Cmp("exchange".into(), Op::Eq, Literal::Str("o'brien".into())),
+    );
+    for bad in ["", "qty >", "symbol > 'A'", "symbol in ()", "qty = 1 and", "price = 1 2", "and = 1", "qty ~ 1", "symbol = 'open"] {
+        assert!(Filter::parse(bad).is_err(), "{:?} should not parse", bad);
+    }
+}
+
+#[test]
+fn deep_nesting_and_long_filters_are_refused_before_they_can_overflow_the_stack() {
+    let nested = |n: usize| format!("{}qty > 1{}", "(".repeat(n), ")".repeat(n));
+    assert!(Filter::parse(&nested(MAX_DEPTH)).is_ok());
+    let e = Filter::parse(&nested(MAX_DEPTH + 1)).unwrap_err();
+    assert!(e.to_string().contains("nested"), "{}", e);
+    assert!(Filter::parse(&format!("{}qty > 1", "NOT ".repeat(MAX_DEPTH + 1))).is_err());
+    // Far past any stack, and cut off by its length before it's lexed.
+    let e = Filter::parse(&nested(4000)).unwrap_err();
+    assert!(e.to_string().contains("bytes"), "{}", e);
+    assert!(Filter::parse(&format!("qty > {}", "1".repeat(MAX_LEN))).is_err());
+}
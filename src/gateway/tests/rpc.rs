@@ -0,0 +1,24 @@
+use consumer::rollup::Bar;
+use gateway::rpc;
+use prost::Message;
+
+#[test]
+fn bars_keep_every_field_on_the_wire() {
+    let bar = Bar { symbol: "BTCUSDT".into(), ts_ms: 1_717_200_300_000, open: 1.0, high: 2.0, low: 0.5, close: 1.5, volume: 10.0, trades: 4 };
+    let list = rpc::BarList { interval: "5m".into(), bars: vec![bar.into()] };
+    let back = rpc::BarList::decode(&*list.encode_to_vec()).unwrap();
+    assert_eq!(back, list);
+    assert_eq!(back.bars[0].symbol, "BTCUSDT");
+    assert_eq!((back.bars[0].ts_ms, back.bars[0].close, back.bars[0].trades), (1_717_200_300_000, 1.5, 4));
+}
+
+#[test]
+fn unset_request_fields_read_as_defaults() {
+    // What a client sends with nothing set: the gateway's defaults apply.
+    let req = rpc::GetBarsRequest::decode(&[][..]).unwrap();
+    assert_eq!((req.interval.as_str(), req.to_ms, req.limit), ("", 0, 0));
+    assert!(req.symbols.is_empty());
+
+    let req = rpc::SubscribeBarsRequest { interval: "1h".into(), symbols: vec!["BTCUSDT".into(), "ETHUSDT".into()], client: "algo".into() };
+    assert_eq!(rpc::SubscribeBarsRequest::decode(&*req.encode_to_vec()).unwrap(), req);
+}
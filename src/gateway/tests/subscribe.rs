@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use fetcher::auth::Clients;
+use gateway::fanout::{Hub, Policy};
+use gateway::subscribe::{acl, permitted, Params, Subscription, ANONYMOUS};
+
+#[test]
+fn params_are_url_decoded_and_the_filter_compiled() {
+    let p = Params::parse(Some("client=desk-1&filter=symbol%20%3D%20%27BTCUSDT%27+and+qty+%3E+1&x=y")).unwrap();
+    assert_eq!(p.name("10.0.0.1:5000"), "desk-1");
+    assert!(p.filter.is_some());
+
+    let p = Params::parse(Some("client=has%20space&filter=")).unwrap();
+    assert_eq!((p.name("10.0.0.1:5000").as_str(), p.filter.is_none()), ("10.0.0.1:5000", true));
+    assert!(Params::parse(None).unwrap().filter.is_none());
+    assert!(Params::parse(Some("filter=qty+%3E")).is_err());
+}
+
+#[tokio::test]
+async fn dropping_a_subscription_leaves_the_hub() {
+    let hub = Arc::new(Hub::new(10, Policy::DropOldest));
+    let sub = Subscription::open(&hub, Params::default(), "peer", None, "ndjson");
+    let client = sub.client.clone();
+    assert_eq!((hub.clients(), sub.transport()), (1, "ndjson"));
+    drop(sub);
+    assert_eq!((hub.clients(), client.next().await), (0, None));
+}
+
+#[tokio::test]
+async fn a_clients_acl_narrows_whatever_filter_it_asks_for() {
+    let clients = Clients::parse("desk tok_d BTCUSDT,ETH*; relay tok_r *").unwrap();
+    let desk = clients.authenticate(None, Some("tok_d"));
+    let hub = Arc::new(Hub::new(10, Policy::DropOldest));
+    let asked = Params::parse(Some("filter=qty+%3E+1")).unwrap();
+    let sub = Subscription::open(&hub, asked, "peer", desk, "ws");
+    let everyone = Subscription::open(&hub, Params::default(), "peer", clients.authenticate(None, Some("tok_r")), "ws");
+    for record in [
+        r#"{"symbol":"BTCUSDT","qty":2}"#,
+        r#"{"symbol":"btcusdt","qty":0.5}"#,
+        r#"{"symbol":"ethbtc","qty":3}"#,
+        r#"{"symbol":"SOLUSDT","qty":9}"#,
+        r#"{"qty":9}"#,
+    ] {
+        hub.broadcast(Arc::from(record));
+    }
+    assert_eq!(&*sub.client.next().await.unwrap(), r#"{"symbol":"BTCUSDT","qty":2}"#);
+    assert_eq!(&*sub.client.next().await.unwrap(), r#"{"symbol":"ethbtc","qty":3}"#);
+    assert_eq!(hub.lag(std::time::Instant::now()).iter().map(|l| l.queued).sum::<usize>(), 5);
+    // Labeled by who they are in the list, not by what they asked to be called.
+    let anonymous = Subscription::open(&hub, Params::parse(Some("client=my-own-name")).unwrap(), "peer", None, "ws");
+    assert_eq!((sub.client.name.as_str(), anonymous.client.name.as_str()), ("desk", ANONYMOUS));
+    assert!(acl(None).is_none() && acl(clients.authenticate(None, Some("tok_r"))).is_none());
+    drop(everyone);
+}
+
+#[test]
+fn queries_only_read_the_callers_symbols() {
+    let clients = Clients::parse("desk tok_d BTCUSDT,ETHUSDT; partner tok_p XBT*").unwrap();
+    let (desk, partner) = (clients.authenticate(None, Some("tok_d")), clients.authenticate(None, Some("tok_p")));
+    let list = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(permitted(desk, list(&["BTCUSDT"])).unwrap(), list(&["BTCUSDT"]));
+    assert_eq!(permitted(desk, vec![]).unwrap(), list(&["BTCUSDT", "ETHUSDT"]));
+    assert!(permitted(desk, list(&["BTCUSDT", "SOLUSDT"])).unwrap_err().contains("SOLUSDT"));
+    assert_eq!(permitted(partner, list(&["XBTUSD"])).unwrap(), list(&["XBTUSD"]));
+    assert!(permitted(partner, vec![]).is_err(), "a prefix can't be listed");
+    assert_eq!(permitted(None, vec![]).unwrap(), Vec::<String>::new());
+}
@@ -0,0 +1,155 @@
+//! The as-of join between the trade and quote streams.
+//!
+//! Spot `bookTicker` updates carry no exchange timestamp, so both sides are
+//! timed by the fetcher's receive time (`ts_produce_ns`), which comes from one
+//! clock. A trade's prevailing quote is its symbol's last quote received at or
+//! before the trade. The two topics are consumed independently, so a trade may
+//! get here before the quote that preceded it; trades wait until their
+//! symbol's quotes have caught up with them, or `wait` has passed.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use consumer::ilp::{escape_str, escape_tag, NormTrade};
+use metrics::counter;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quote {
+    /// Fetcher receive time, ns since the epoch.
+    pub recv_ns: i64,
+    pub bid: f64,
+    pub ask: f64,
+}
+
+impl Quote {
+    pub fn mid(&self) -> f64 {
+        (self.bid + self.ask) / 2.0
+    }
+}
+
+#[derive(Deserialize)]
+struct BookTicker {
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "b")]
+    bid: String,
+    #[serde(rename = "a")]
+    ask: String,
+}
+
+/// `(symbol, bid, ask)` from a raw `bookTicker` payload.
+pub fn parse_book_ticker(payload: &str) -> Result<(String, f64, f64)> {
+    let t: BookTicker = serde_json::from_str(payload)?;
+    let (bid, ask): (f64, f64) = (t.bid.parse()?, t.ask.parse()?);
+    anyhow::ensure!(bid.is_finite() && ask.is_finite() && bid > 0.0 && ask >= bid, "bad quote {}/{}", bid, ask);
+    Ok((t.symbol.to_uppercase(), bid, ask))
+}
+
+/// A trade and the quote it was matched with, if any.
+#[derive(Debug, Clone)]
+pub struct Quoted {
+    pub trade: NormTrade,
+    pub msg_id: String,
+    pub recv_ns: i64,
+    pub quote: Option<Quote>,
+}
+
+impl Quoted {
+    /// A `trades`-shaped row plus the quote. Unmatched trades leave the quote columns null.
+    pub fn to_ilp_line(&self, table: &str) -> String {
+        let t = &self.trade;
+        let mut fields = format!(
+            "price={},qty={},trade_id={}i,is_bm={},msg_id=\"{}\"",
+            t.price, t.qty, t.trade_id, t.is_bm, escape_str(&self.msg_id)
+        );
+        if let Some(q) = &self.quote {
+            let mid = q.mid();
+            // Effective spread: twice the distance from the mid, what a taker actually paid.
+            fields.push_str(&format!(
+                ",bid={},ask={},mid={},spread={},eff_spread={},quote_age_ms={}",
+                q.bid,
+                q.ask,
+                mid,
+                q.ask - q.bid,
+                2.0 * (t.price - mid).abs(),
+                (self.recv_ns - q.recv_ns) as f64 / 1e6
+            ));
+        }
+        format!("{},symbol={} {} {}", escape_tag(table), escape_tag(&t.symbol), fields, (t.ts_ms as i128) * 1_000_000i128)
+    }
+}
+
+struct Pending<T> {
+    quoted: Quoted,
+    arrived: Instant,
+    tag: T,
+}
+
+/// Quote history per symbol and the trades waiting for it. `T` travels with
+/// each trade (its delivery, for committing) and comes back out in order.
+pub struct Joiner<T> {
+    quotes: HashMap<String, VecDeque<Quote>>,
+    pending: VecDeque<Pending<T>>,
+    wait: Duration,
+    keep_ns: i64,
+    max_quotes: usize,
+}
+
+impl<T> Joiner<T> {
+    /// Quotes older than `keep` behind a symbol's newest are dropped, and at
+    /// most `max_quotes` are kept per symbol.
+    pub fn new(wait: Duration, keep: Duration, max_quotes: usize) -> Self {
+        Self {
+            quotes: HashMap::new(),
+            pending: VecDeque::new(),
+            wait,
+            keep_ns: keep.as_nanos() as i64,
+            max_quotes: max_quotes.max(1),
+        }
+    }
+
+    pub fn quote(&mut self, symbol: &str, q: Quote) {
+        let qs = self.quotes.entry(symbol.to_string()).or_default();
+        if qs.back().is_some_and(|last| q.recv_ns < last.recv_ns) {
+            counter!("join_quotes_out_of_order_total").increment(1);
+            return;
+        }
+        qs.push_back(q);
+        while qs.len() > self.max_quotes || qs.front().is_some_and(|f| q.recv_ns - f.recv_ns > self.keep_ns) {
+            qs.pop_front();
+        }
+    }
+
+    pub fn trade(&mut self, trade: NormTrade, msg_id: String, recv_ns: i64, arrived: Instant, tag: T) {
+        self.pending.push_back(Pending { quoted: Quoted { trade, msg_id, recv_ns, quote: None }, arrived, tag });
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Trades that can be joined now, in arrival order. A trade waiting on
+    /// quotes holds back the ones behind it, so commits stay in order.
+    pub fn ready(&mut self, now: Instant) -> Vec<(Quoted, T)> {
+        let mut out = Vec::new();
+        while let Some(p) = self.pending.front() {
+            let qs = self.quotes.get(&p.quoted.trade.symbol);
+            let caught_up = qs.and_then(|qs| qs.back()).is_some_and(|q| q.recv_ns >= p.quoted.recv_ns);
+            if !caught_up && now.duration_since(p.arrived) < self.wait {
+                break;
+            }
+            let Some(mut p) = self.pending.pop_front() else { break };
+            p.quoted.quote = qs.and_then(|qs| {
+                let i = qs.partition_point(|q| q.recv_ns <= p.quoted.recv_ns);
+                i.checked_sub(1).map(|i| qs[i])
+            });
+            if p.quoted.quote.is_none() {
+                counter!("join_unmatched_total").increment(1);
+            }
+            out.push((p.quoted, p.tag));
+        }
+        out
+    }
+}
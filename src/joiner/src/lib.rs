@@ -0,0 +1,4 @@
+//! Join trades with Binance `bookTicker` quotes: each trade is stamped with
+//! the best bid/ask that prevailed when it happened and written to QuestDB.
+
+pub mod join;
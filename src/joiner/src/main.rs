@@ -0,0 +1,155 @@
+//! Join stage: `ticks.norm` trades + `bookTicker` quotes -> `trades_quoted`.
+//!
+//! Trades are committed once their joined rows are written, so the table is
+//! at-least-once like `trades`. Quotes are only state: they're committed on an
+//! interval, and after a restart the first trades of each symbol may go out
+//! unmatched until a new quote arrives.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use bus::commit::{CommitStrategy, Committer};
+use bus::envelope::{self, NORM_SCHEMA};
+use bus::{BusConfig, Delivery, MSG_ID, TS_PRODUCE_NS};
+use consumer::decode::decode;
+use consumer::sink::{parse_endpoints, IlpOptions, IlpPool, PoolMode};
+use joiner::join::{parse_book_ticker, Joiner, Quote};
+use metrics::{counter, histogram};
+use obsv::load::Load;
+use obsv::{init_metrics, init_tracing, namespace, startup, measure_ms_async};
+use retry::Policy;
+
+fn env<T: AsRef<str>>(k: T, default: &str) -> String {
+    std::env::var(k.as_ref()).unwrap_or_else(|_| default.to_string())
+}
+
+fn recv_ns(d: &Delivery) -> Option<i64> {
+    d.header(TS_PRODUCE_NS)?.parse().ok()
+}
+
+/// Write the joined rows on `policy`. Once it gives up the error ends the
+/// stage: the trades aren't committed, so they're joined again after the
+/// restart, and quotes aren't left unread for the length of an outage.
+async fn write(pool: &mut IlpPool, policy: &Policy, lines: &str) -> Result<()> {
+    let mut retry = policy.start("questdb_write");
+    loop {
+        let (res, ms) = measure_ms_async(pool.write_chunks(&[lines.as_bytes()])).await;
+        histogram!("join_write_ms").record(ms);
+        match res {
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                tracing::warn!(target="joiner", error=?e, "QuestDB write failed");
+                if !retry.failed(&e).await {
+                    return Err(e).context("writing joined trades to QuestDB");
+                }
+            }
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!("nats", "pulsar"), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    init_metrics(9469)?;
+
+    let trades_topic = env("TOPIC_IN", "ticks.norm");
+    let quotes_topic = env("QUOTES_TOPIC", "quotes.raw");
+    let group_id = env("GROUP_ID", "joiner");
+    let table = namespace::table(&env("JOIN_TABLE", "trades_quoted"));
+    let wait = Duration::from_millis(env("JOIN_WAIT_MS", "50").parse().unwrap_or(50));
+    let keep = Duration::from_millis(env("JOIN_QUOTE_KEEP_MS", "5000").parse().unwrap_or(5000));
+    let max_quotes: usize = env("JOIN_MAX_QUOTES", "10000").parse().unwrap_or(10_000);
+
+    let ilp_port: u16 = env("QDB_ILP_PORT", "9009").parse().unwrap_or(9009);
+    let endpoints = parse_endpoints(&env("QDB_HOSTS", &format!("{}:{}", env("QDB_HOST", "localhost"), ilp_port)), ilp_port)?;
+    let mode = PoolMode::parse(&env("QDB_POOL_MODE", "failover"))?;
+    let policy = Policy::from_env("QDB_WRITE", 5, Duration::from_millis(100), Duration::from_secs(5));
+    let mut pool = startup::wait_for("questdb", || IlpPool::connect(endpoints.clone(), mode, IlpOptions::default(), Duration::from_secs(5))).await?;
+
+    let bus = BusConfig::from_env()?;
+    let topics = [trades_topic.as_str(), quotes_topic.as_str()];
+    startup::wait_for("kafka", || bus.check_topics(&topics)).await?;
+    bus::audit::start(&bus).await;
+    let mut trades = bus.subscriber(&trades_topic, &group_id).await?;
+    let mut quotes = bus.subscriber(&quotes_topic, &group_id).await?;
+    let mut quote_commits = Committer::new(CommitStrategy::Interval(Duration::from_secs(1)));
+    let mut joiner: Joiner<Delivery> = Joiner::new(wait, keep, max_quotes);
+    // Undecodable trades; committed once no earlier trade is still waiting.
+    let mut skipped: Vec<Delivery> = Vec::new();
+    // Wakes the loop to release trades whose wait ran out.
+    let mut tick = tokio::time::interval(wait.max(Duration::from_millis(1)));
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    tracing::info!(target="joiner", trades=%trades_topic, quotes=%quotes_topic, table=%table, "joining");
+
+    let load = Load::new("joiner")?;
+    loop {
+        tokio::select! {
+            next = trades.next() => match next {
+                None => break,
+                Some(Err(e)) => tracing::error!(target="joiner", error=?e, "trade poll error"),
+                Some(Ok(d)) => {
+                    let _busy = load.busy();
+                    let compat = envelope::compat(&d.headers, NORM_SCHEMA);
+                    match d.payload_str().map(|p| decode(p, &compat)) {
+                        Some(Ok(t)) => {
+                            let msg_id = d.header(MSG_ID).unwrap_or("").to_string();
+                            let at = recv_ns(&d).unwrap_or(t.ts_ms * 1_000_000);
+                            joiner.trade(t, msg_id, at, Instant::now(), d);
+                        }
+                        _ => {
+                            counter!("join_dropped_total").increment(1);
+                            obsv::errors::record("joiner", obsv::errors::ErrorKind::Data);
+                            skipped.push(d);
+                        }
+                    }
+                }
+            },
+            next = quotes.next() => match next {
+                None => break,
+                Some(Err(e)) => tracing::error!(target="joiner", error=?e, "quote poll error"),
+                Some(Ok(d)) => {
+                    // Only trades count as processed, once they're committed; a quote is the context they join.
+                    let _busy = load.busy();
+                    match (d.payload_str().map(parse_book_ticker), recv_ns(&d)) {
+                        (Some(Ok((symbol, bid, ask))), Some(recv_ns)) => joiner.quote(&symbol, Quote { recv_ns, bid, ask }),
+                        _ => counter!("join_bad_quotes_total").increment(1),
+                    }
+                    if let Err(e) = quote_commits.done(quotes.as_ref(), d).await {
+                        tracing::warn!(target="joiner", error=?e, "quote commit failed");
+                    }
+                }
+            },
+            _ = tick.tick() => {}
+        }
+
+        // A trade is finished once written and committed, not when it arrives.
+        let _busy = load.busy();
+        let ready = joiner.ready(Instant::now());
+        let mut done: Vec<Delivery> = Vec::new();
+        if !ready.is_empty() {
+            let mut lines = String::new();
+            for (q, _) in &ready {
+                lines.push_str(&q.to_ilp_line(&table));
+                lines.push('\n');
+            }
+            write(&mut pool, &policy, &lines).await?;
+            counter!("join_written_total").increment(ready.len() as u64);
+            done.extend(ready.into_iter().map(|(_, d)| d));
+        }
+        if joiner.pending() == 0 {
+            done.append(&mut skipped);
+        }
+        if done.is_empty() {
+            continue;
+        }
+        if let Err(e) = trades.commit_all(&done).await {
+            tracing::warn!(target="joiner", error=?e, "trade commit failed");
+        }
+        load.processed(done.len() as u64);
+    }
+    let _ = quote_commits.commit(quotes.as_ref()).await;
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use std::time::{Duration, Instant};
+
+use consumer::ilp::NormTrade;
+use joiner::join::{parse_book_ticker, Joiner, Quote};
+
+fn trade(trade_id: i64, price: f64) -> NormTrade {
+    NormTrade { ts_ms: 1_700_000_000_000 + trade_id, symbol: "BTCUSDT".into(), price, qty: 0.5, trade_id, is_bm: false, session: None, notional: None, first_trade_id: None, contract_size: None, ts_ns: None, ingest_ts_ns: None }
+}
+
+fn quote(recv_ns: i64, bid: f64, ask: f64) -> Quote {
+    Quote { recv_ns, bid, ask }
+}
+
+#[test]
+fn trades_take_the_last_quote_before_them() {
+    let t0 = Instant::now();
+    let mut j: Joiner<u32> = Joiner::new(Duration::from_millis(50), Duration::from_secs(5), 100);
+    j.quote("BTCUSDT", quote(100, 10.0, 10.2));
+    j.quote("BTCUSDT", quote(200, 10.1, 10.3));
+    j.trade(trade(1, 10.3), "m1".into(), 150, t0, 1);
+    // Quotes haven't reached 300 yet, so trade 2 (and anything behind it) waits.
+    j.trade(trade(2, 10.0), "m2".into(), 300, t0, 2);
+    let ready = j.ready(t0);
+    assert_eq!(ready.iter().map(|(_, tag)| *tag).collect::<Vec<_>>(), [1]);
+    assert_eq!(ready[0].0.quote, Some(quote(100, 10.0, 10.2)));
+
+    j.quote("BTCUSDT", quote(310, 9.9, 10.1));
+    let ready = j.ready(t0);
+    assert_eq!(ready[0].0.quote, Some(quote(200, 10.1, 10.3)), "the 310 quote came after the trade");
+    assert_eq!(
+        ready[0].0.to_ilp_line("trades_quoted"),
+        "trades_quoted,symbol=BTCUSDT price=10,qty=0.5,trade_id=2i,is_bm=false,msg_id=\"m2\",\
+         bid=10.1,ask=10.3,mid=10.2,spread=0.20000000000000107,eff_spread=0.3999999999999986,quote_age_ms=0.0001 1700000000002000000"
+    );
+}
+
+#[test]
+fn trades_without_quotes_go_out_unmatched_after_the_wait() {
+    let t0 = Instant::now();
+    let mut j: Joiner<()> = Joiner::new(Duration::from_millis(50), Duration::from_secs(5), 100);
+    j.trade(trade(1, 10.0), "m1".into(), 100, t0, ());
+    assert!(j.ready(t0).is_empty());
+    let ready = j.ready(t0 + Duration::from_millis(50));
+    assert!(ready[0].0.quote.is_none());
+    assert!(!ready[0].0.to_ilp_line("t").contains("bid="));
+    assert_eq!(j.pending(), 0);
+
+    assert_eq!(parse_book_ticker(r#"{"u":1,"s":"BTCUSDT","b":"10.5","B":"1","a":"10.6","A":"2"}"#).unwrap(), ("BTCUSDT".into(), 10.5, 10.6));
+    assert!(parse_book_ticker(r#"{"s":"BTCUSDT","b":"10.7","a":"10.6"}"#).is_err(), "crossed book");
+}
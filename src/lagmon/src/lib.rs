@@ -0,0 +1,30 @@
+//! Consumer-group lag, as the brokers see it.
+
+use std::collections::HashMap;
+
+/// Messages behind the high watermark; a group with no commit yet is behind
+/// everything still retained.
+pub fn lag(low: i64, high: i64, committed: Option<i64>) -> i64 {
+    (high - committed.unwrap_or(low).max(low)).max(0)
+}
+
+/// When each partition's committed offset was last seen to move.
+///
+/// Kafka doesn't keep commit times, so ages are from this process's own
+/// scrapes and start at zero when it starts. A group that is caught up on
+/// an idle topic has nothing to commit, so alert on age only while lag > 0.
+#[derive(Default)]
+pub struct Ages {
+    seen: HashMap<(String, String, i32), (i64, i64)>,
+}
+
+impl Ages {
+    /// Record `offset` for the partition at `now_ms`; seconds since it last changed.
+    pub fn observe(&mut self, group: &str, topic: &str, partition: i32, offset: i64, now_ms: i64) -> f64 {
+        let e = self.seen.entry((group.to_string(), topic.to_string(), partition)).or_insert((offset, now_ms));
+        if e.0 != offset {
+            *e = (offset, now_ms);
+        }
+        (now_ms - e.1) as f64 / 1000.0
+    }
+}
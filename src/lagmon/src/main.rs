@@ -0,0 +1,121 @@
+//! Lag exporter: committed offsets of every consumer group against the
+//! partitions' high watermarks, fetched from the brokers rather than
+//! self-reported by each consumer (which stops reporting when it's stuck).
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use bus::{BusConfig, Transport};
+use lagmon::{lag, Ages};
+use metrics::{counter, gauge};
+use obsv::load::Load;
+use obsv::{init_metrics, init_tracing, runtime};
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::{Offset, TopicPartitionList};
+
+fn env<T: AsRef<str>>(k: T, default: &str) -> String {
+    std::env::var(k.as_ref()).unwrap_or_else(|_| default.to_string())
+}
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn list(v: &str) -> Vec<String> {
+    v.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect()
+}
+
+/// One scrape of every group over every topic it has commits on.
+fn scrape(bus: &BusConfig, admin: &BaseConsumer, groups: &[String], topics: &[String], ages: &mut Ages, now_ms: i64) -> Result<()> {
+    let md = admin.fetch_metadata(None, TIMEOUT)?;
+    let partitions: Vec<(String, i32)> = md.topics().iter()
+        .filter(|t| match topics.is_empty() {
+            true => !t.name().starts_with("__") && bus.namespace.owns(t.name()),
+            false => topics.iter().any(|w| w == t.name()),
+        })
+        .flat_map(|t| t.partitions().iter().map(|p| (t.name().to_string(), p.id())))
+        .collect();
+    let mut marks = HashMap::new();
+    for (topic, p) in &partitions {
+        marks.insert((topic.clone(), *p), admin.fetch_watermarks(topic, *p, TIMEOUT)?);
+    }
+
+    let groups = match groups.is_empty() {
+        true => admin.fetch_group_list(None, TIMEOUT)?.groups().iter()
+            .filter(|g| g.protocol_type() == "consumer" || g.protocol_type().is_empty())
+            .filter(|g| bus.namespace.owns(g.name()))
+            .map(|g| g.name().to_string())
+            .collect(),
+        false => groups.to_vec(),
+    };
+    for group in &groups {
+        // Fetching a group's offsets doesn't join it.
+        let c: BaseConsumer = bus.kafka_config().set("group.id", group).set("enable.auto.commit", "false").create()?;
+        let mut tpl = TopicPartitionList::new();
+        for (topic, p) in &partitions {
+            tpl.add_partition(topic, *p);
+        }
+        let committed = match c.committed_offsets(tpl, TIMEOUT) {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::warn!(target="lagmon", group=%group, error=?e, "offset fetch failed");
+                counter!("lagmon_errors_total").increment(1);
+                continue;
+            }
+        };
+        let mut totals: HashMap<String, i64> = HashMap::new();
+        for e in committed.elements() {
+            // Topics the group has never committed on aren't its topics.
+            let Offset::Offset(offset) = e.offset() else { continue };
+            let Some(&(low, high)) = marks.get(&(e.topic().to_string(), e.partition())) else { continue };
+            let behind = lag(low, high, Some(offset));
+            let (group, topic, partition) = (group.clone(), e.topic().to_string(), e.partition().to_string());
+            let age = ages.observe(&group, &topic, e.partition(), offset, now_ms);
+            gauge!("lagmon_lag", "group" => group.clone(), "topic" => topic.clone(), "partition" => partition.clone()).set(behind as f64);
+            gauge!("lagmon_committed_offset", "group" => group.clone(), "topic" => topic.clone(), "partition" => partition.clone()).set(offset as f64);
+            gauge!("lagmon_commit_age_seconds", "group" => group, "topic" => topic.clone(), "partition" => partition).set(age);
+            *totals.entry(topic).or_default() += behind;
+        }
+        for (topic, total) in totals {
+            gauge!("lagmon_group_lag", "group" => group.clone(), "topic" => topic).set(total as f64);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!(), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    init_metrics(env("LAGMON_METRICS_PORT", "9475").parse().unwrap_or(9475))?;
+
+    let bus = BusConfig::from_env()?;
+    anyhow::ensure!(bus.transport == Transport::Kafka, "lagmon reads Kafka consumer groups; TRANSPORT must be kafka");
+    bus::audit::start(&bus).await;
+    // Empty: every consumer group, and every topic but Kafka's own (in NAMESPACE, if set).
+    let groups: Vec<String> = list(&env("LAGMON_GROUPS", "")).iter().map(|g| bus.group(g)).collect();
+    let topics: Vec<String> = list(&env("LAGMON_TOPICS", "")).iter().map(|t| bus.topic(t)).collect();
+    let every = Duration::from_secs(env("LAGMON_INTERVAL_SECS", "15").parse().unwrap_or(15));
+    let admin: BaseConsumer = bus.kafka_config().create()?;
+    tracing::info!(target="lagmon", brokers=%bus.brokers, ?groups, ?topics, every_secs=every.as_secs(), "watching consumer groups");
+
+    let mut ages = Ages::default();
+    // A scrape is lagmon's message: the rate is scrapes a second, the saturation the share spent in them.
+    let load = Load::new("lagmon")?;
+    loop {
+        let busy = load.busy();
+        let now_ms = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_millis() as i64;
+        // The admin calls block; the runtime's other worker serves metrics meanwhile.
+        match runtime::cpu_here(|| scrape(&bus, &admin, &groups, &topics, &mut ages, now_ms)) {
+            Ok(()) => counter!("lagmon_scrapes_total").increment(1),
+            Err(e) => {
+                tracing::warn!(target="lagmon", error=?e, "scrape failed");
+                counter!("lagmon_errors_total").increment(1);
+            }
+        }
+        load.processed(1);
+        drop(busy);
+        tokio::time::sleep(every).await;
+    }
+}
@@ -0,0 +1,20 @@
+use lagmon::{lag, Ages};
+
+#[test]
+fn lag_is_from_the_commit_or_the_oldest_retained_message() {
+    assert_eq!(lag(0, 100, Some(90)), 10);
+    assert_eq!(lag(0, 100, Some(100)), 0);
+    assert_eq!(lag(50, 100, None), 50);
+    // Committed before what's retained: only what's left counts.
+    assert_eq!(lag(50, 100, Some(10)), 50);
+}
+
+#[test]
+fn commit_age_resets_when_the_offset_moves() {
+    let mut a = Ages::default();
+    assert_eq!(a.observe("consumer", "ticks.norm", 0, 10, 1_000), 0.0);
+    assert_eq!(a.observe("consumer", "ticks.norm", 0, 10, 16_000), 15.0);
+    assert_eq!(a.observe("consumer", "ticks.norm", 1, 10, 16_000), 0.0);
+    assert_eq!(a.observe("consumer", "ticks.norm", 0, 12, 31_000), 0.0);
+    assert_eq!(a.observe("consumer", "ticks.norm", 0, 12, 32_500), 1.5);
+}
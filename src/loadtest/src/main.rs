@@ -0,0 +1,167 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use bus::BusConfig;
+use clap::Parser;
+use obsv::init_tracing;
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+mod generate;
+mod prom;
+
+/// Drive synthetic trades through the pipeline and gate on its own latency/loss metrics.
+///
+/// Assumes nothing else is feeding `ticks.raw` during the run: loss is computed
+/// from the consumer's `consumed_total` delta.
+#[derive(Debug, Parser)]
+struct Args {
+    #[arg(long, env = "TOPIC_OUT", default_value = "ticks.raw")]
+    topic: String,
+    /// Target publish rate (msgs/sec).
+    #[arg(long, env = "LOAD_RATE", default_value_t = 1000.0)]
+    rate: f64,
+    /// How long to publish for (seconds).
+    #[arg(long, env = "LOAD_DURATION", default_value_t = 60)]
+    duration: u64,
+    /// Leading seconds excluded from the latency gate.
+    #[arg(long, default_value_t = 5)]
+    warmup: u64,
+    /// Max seconds to wait after publishing for the consumer to catch up.
+    #[arg(long, default_value_t = 30)]
+    drain: u64,
+    #[arg(long, default_value_t = 8)]
+    symbols: usize,
+    #[arg(long, default_value_t = 1000)]
+    max_in_flight: usize,
+    /// The consumer stage's Prometheus endpoint.
+    #[arg(long, env = "CONSUMER_METRICS", default_value = "http://localhost:9466/metrics")]
+    consumer_metrics: String,
+    #[arg(long, default_value_t = 5)]
+    scrape_interval: u64,
+    /// Gate: worst p99 `e2e_latency_ms` seen while under load.
+    #[arg(long, env = "SLO_P99_MS", default_value_t = 250.0)]
+    slo_p99_ms: f64,
+    /// Gate: percent of acknowledged sends the consumer never saw.
+    #[arg(long, env = "SLO_MAX_LOSS_PCT", default_value_t = 0.1)]
+    slo_max_loss_pct: f64,
+    /// Also write the report as JSON here.
+    #[arg(long)]
+    report: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    target_rate: f64,
+    achieved_rate: f64,
+    sent: u64,
+    failed: u64,
+    consumed: u64,
+    loss_pct: f64,
+    p99_e2e_ms: Option<f64>,
+    p99_samples: usize,
+    slo_p99_ms: f64,
+    slo_max_loss_pct: f64,
+    latency_ok: bool,
+    loss_ok: bool,
+}
+
+async fn consumed(client: &reqwest::Client, url: &str) -> Result<u64> {
+    let samples = prom::scrape(client, url).await?;
+    Ok(prom::find(&samples, "consumed_total", &[]).unwrap_or(0.0) as u64)
+}
+
+/// Sample the consumer's p99 until told to stop, ignoring samples taken before `after`.
+async fn watch_p99(client: reqwest::Client, url: String, every: Duration, after: Instant, mut stop: oneshot::Receiver<()>) -> Vec<f64> {
+    let mut p99s = Vec::new();
+    let mut tick = tokio::time::interval(every);
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            _ = &mut stop => return p99s,
+        }
+        if Instant::now() < after {
+            continue;
+        }
+        match prom::scrape(&client, &url).await {
+            Ok(s) => p99s.extend(prom::find(&s, "e2e_latency_ms", &[("quantile", "0.99")]).filter(|v| v.is_finite())),
+            Err(e) => tracing::warn!(target: "loadtest", error = ?e, "scrape failed"),
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!("nats", "pulsar"), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    let args = Args::parse();
+    anyhow::ensure!(args.rate > 0.0, "--rate must be positive");
+
+    let client = reqwest::Client::builder().timeout(Duration::from_secs(5)).build()?;
+    // A send that can't be delivered within a few seconds is loss for our purposes.
+    let publisher = BusConfig::from_env()?
+        .kafka_set("message.timeout.ms", "5000")
+        .publisher()
+        .await?;
+    let baseline = consumed(&client, &args.consumer_metrics).await
+        .map_err(|e| anyhow::anyhow!("consumer metrics unreachable at {}: {}", args.consumer_metrics, e))?;
+
+    let plan = generate::Plan {
+        topic: args.topic.clone(),
+        rate: args.rate,
+        duration: Duration::from_secs(args.duration),
+        symbols: args.symbols,
+        max_in_flight: args.max_in_flight,
+    };
+    tracing::info!(target: "loadtest", rate = plan.rate, duration_s = args.duration, topic = %plan.topic, "starting load");
+
+    let (stop_tx, stop_rx) = oneshot::channel();
+    let watcher = tokio::spawn(watch_p99(
+        client.clone(),
+        args.consumer_metrics.clone(),
+        Duration::from_secs(args.scrape_interval.max(1)),
+        Instant::now() + Duration::from_secs(args.warmup),
+        stop_rx,
+    ));
+
+    let stats = generate::run(publisher, &plan).await;
+    tracing::info!(target: "loadtest", sent = stats.sent, failed = stats.failed, "publishing done; draining");
+
+    // Wait for the consumer to account for everything we sent, or give up after --drain.
+    let drain_until = Instant::now() + Duration::from_secs(args.drain);
+    let mut seen = consumed(&client, &args.consumer_metrics).await?.saturating_sub(baseline);
+    while seen < stats.sent && Instant::now() < drain_until {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        seen = consumed(&client, &args.consumer_metrics).await?.saturating_sub(baseline);
+    }
+    let _ = stop_tx.send(());
+    let p99s = watcher.await?;
+
+    let p99 = p99s.iter().copied().reduce(f64::max);
+    let loss_pct = if stats.sent == 0 { 0.0 } else { stats.sent.saturating_sub(seen) as f64 * 100.0 / stats.sent as f64 };
+    let report = Report {
+        target_rate: args.rate,
+        achieved_rate: stats.sent as f64 / stats.elapsed.as_secs_f64().max(f64::EPSILON),
+        sent: stats.sent,
+        failed: stats.failed,
+        consumed: seen,
+        loss_pct,
+        p99_e2e_ms: p99,
+        p99_samples: p99s.len(),
+        slo_p99_ms: args.slo_p99_ms,
+        slo_max_loss_pct: args.slo_max_loss_pct,
+        latency_ok: p99.is_some_and(|v| v <= args.slo_p99_ms),
+        loss_ok: stats.sent > 0 && loss_pct <= args.slo_max_loss_pct,
+    };
+    tracing::info!(target: "loadtest", report = %serde_json::to_string(&report)?, "loadtest report");
+    if let Some(path) = &args.report {
+        std::fs::write(path, serde_json::to_vec_pretty(&report)?)?;
+    }
+
+    anyhow::ensure!(report.latency_ok, "latency SLO failed: p99 {:?} ms > {} ms", report.p99_e2e_ms, args.slo_p99_ms);
+    anyhow::ensure!(report.loss_ok, "loss SLO failed: {:.3}% > {}% ({} of {} sent)", loss_pct, args.slo_max_loss_pct, stats.sent - seen.min(stats.sent), stats.sent);
+    Ok(())
+}
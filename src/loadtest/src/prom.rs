@@ -0,0 +1,69 @@
+//! Just enough of the Prometheus text exposition format to read our own exporters.
+
+use anyhow::Result;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+}
+
+fn parse_labels(s: &str) -> Option<Vec<(String, String)>> {
+    let mut labels = Vec::new();
+    let mut rest = s.trim();
+    while !rest.is_empty() {
+        let (key, after) = rest.split_once('=')?;
+        let mut chars = after.strip_prefix('"')?.char_indices();
+        let mut value = String::new();
+        let end = loop {
+            match chars.next()? {
+                (_, '\\') => match chars.next()?.1 {
+                    'n' => value.push('\n'),
+                    c => value.push(c),
+                },
+                (i, '"') => break i,
+                (_, c) => value.push(c),
+            }
+        };
+        labels.push((key.trim().to_string(), value));
+        rest = after[end + 2..].trim_start().trim_start_matches(',').trim_start();
+    }
+    Some(labels)
+}
+
+fn parse_line(line: &str) -> Option<Sample> {
+    // Label values may contain spaces, so split after the closing brace when there is one.
+    let (series, value) = match line.rfind('}') {
+        Some(close) => line.split_at(close + 1),
+        None => line.split_once(char::is_whitespace)?,
+    };
+    let (name, labels) = match series.split_once('{') {
+        Some((name, labels)) => (name, parse_labels(labels.strip_suffix('}')?)?),
+        None => (series, Vec::new()),
+    };
+    // An optional timestamp may follow the value.
+    let value = value.split_whitespace().next()?.parse().ok()?;
+    Some(Sample { name: name.trim().to_string(), labels, value })
+}
+
+/// Parse an exposition body, skipping comments and lines we don't understand.
+pub fn parse(text: &str) -> Vec<Sample> {
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(parse_line)
+        .collect()
+}
+
+/// The value of `name` whose labels include all of `want`.
+pub fn find(samples: &[Sample], name: &str, want: &[(&str, &str)]) -> Option<f64> {
+    samples.iter()
+        .find(|s| s.name == name && want.iter().all(|(k, v)| s.labels.iter().any(|(lk, lv)| lk == k && lv == v)))
+        .map(|s| s.value)
+}
+
+pub async fn scrape(client: &reqwest::Client, url: &str) -> Result<Vec<Sample>> {
+    let body = client.get(url).send().await?.error_for_status()?.text().await?;
+    Ok(parse(&body))
+}
@@ -0,0 +1,36 @@
+//! Copying a batch of deliveries to the archive topic.
+
+use bus::{Delivery, Publisher};
+use metrics::{counter, histogram};
+use obsv::measure_ms_async;
+use retry::{Backoff, Policy};
+
+/// Publish every delivery, retrying failures until they all land.
+pub async fn copy(publisher: &dyn Publisher, topic: &str, batch: &[Delivery]) {
+    let mut pending: Vec<&Delivery> = batch.iter().collect();
+    let policy = Policy::forever(Backoff::default());
+    let mut retry = policy.start("kafka_publish");
+    loop {
+        let (results, ms) = measure_ms_async(futures_util::future::join_all(pending.iter().map(|d| {
+            publisher.publish(topic, d.key.as_deref().unwrap_or(""), &d.payload, &d.headers)
+        }))).await;
+        histogram!("mirror_publish_ms").record(ms);
+
+        let mut failed = Vec::new();
+        for (d, res) in pending.into_iter().zip(results) {
+            match res {
+                Ok(()) => counter!("mirror_copied_total").increment(1),
+                Err(e) => {
+                    tracing::warn!(target="mirror", error=?e, "archive publish failed; will retry");
+                    counter!("mirror_failed_total").increment(1);
+                    failed.push(d);
+                }
+            }
+        }
+        if failed.is_empty() {
+            return;
+        }
+        pending = failed;
+        retry.pause().await;
+    }
+}
@@ -0,0 +1,100 @@
+//! Copy a topic to a long-retention archive topic, possibly on another cluster.
+//!
+//! Keys, payloads and headers are copied as is. Messages are read in batches,
+//! published concurrently, and committed only once the whole batch is acked by
+//! the destination, so the archive is at-least-once: after a crash, the
+//! uncommitted tail is copied again.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use bus::{BusConfig, Delivery};
+use mirror::copy;
+use obsv::load::Load;
+use obsv::{init_metrics, init_tracing, startup};
+
+fn env<T: AsRef<str>>(k: T, default: &str) -> String {
+    std::env::var(k.as_ref()).unwrap_or_else(|_| default.to_string())
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!("nats", "pulsar"), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    init_metrics(9468)?;
+
+    let topic_in = env("TOPIC_IN", "ticks.norm");
+    let topic_out = env("MIRROR_TOPIC", "ticks.norm.archive");
+    let group_id = env("GROUP_ID", "mirror");
+    let max_batch: usize = env("MIRROR_BATCH", "500").parse().unwrap_or(500).max(1);
+    let max_wait = Duration::from_millis(env("MIRROR_MAX_WAIT_MS", "50").parse().unwrap_or(50));
+    // -1 keeps the archive forever.
+    let retention_ms = env("MIRROR_RETENTION_MS", "-1");
+    // Recompress on the way out; `none` leaves librdkafka's default.
+    let compression = env("MIRROR_COMPRESSION", "zstd");
+
+    let source = BusConfig::from_env()?;
+    // The destination defaults to the source cluster.
+    let mut dest = BusConfig::from_env()?;
+    dest.brokers = env("MIRROR_BROKERS", &source.brokers);
+    dest.nats_url = env("MIRROR_NATS_URL", &source.nats_url);
+    if compression != "none" {
+        dest = dest.kafka_set("compression.type", &compression);
+    }
+    anyhow::ensure!(
+        topic_in != topic_out || dest.brokers != source.brokers || dest.nats_url != source.nats_url,
+        "mirroring {} onto itself", topic_in
+    );
+
+    let topics = [topic_in.as_str()];
+    startup::wait_for("kafka", || source.check_topics(&topics)).await?;
+    bus::audit::start(&source).await;
+    // The destination topic is created below; only its cluster has to answer.
+    startup::wait_for("kafka", || dest.check_topics(&[])).await?;
+    let mut subscriber = source.subscriber(&topic_in, &group_id).await?;
+    dest.ensure_topic(&topic_out, &[("retention.ms", &retention_ms), ("cleanup.policy", "delete")]).await?;
+    let publisher = dest.publisher().await?;
+    tracing::info!(target="mirror", from=%topic_in, to=%topic_out, brokers=%dest.brokers, %compression, "mirroring");
+
+    let mut batch: Vec<Delivery> = Vec::new();
+    let mut started = Instant::now();
+    let load = Load::new("mirror")?;
+    loop {
+        // `None`: the batch is due.
+        let next = match batch.is_empty() {
+            true => Some(subscriber.next().await),
+            false => {
+                let left = (started + max_wait).saturating_duration_since(Instant::now());
+                tokio::time::timeout(left, subscriber.next()).await.ok()
+            }
+        };
+        match next {
+            None => {}
+            Some(None) => break,
+            Some(Some(Err(e))) => { tracing::error!(target="mirror", error=?e, "poll error"); continue; }
+            Some(Some(Ok(d))) => {
+                if batch.is_empty() {
+                    started = Instant::now();
+                }
+                batch.push(d);
+                if batch.len() < max_batch {
+                    continue;
+                }
+            }
+        }
+        // Counted once copied and committed, a batch at a time.
+        let _busy = load.busy();
+        copy(publisher.as_ref(), &topic_out, &batch).await;
+        if let Err(e) = subscriber.commit_all(&batch).await {
+            tracing::warn!(target="mirror", error=?e, "commit failed");
+        }
+        load.processed(batch.len() as u64);
+        batch.clear();
+    }
+    copy(publisher.as_ref(), &topic_out, &batch).await;
+    subscriber.commit_all(&batch).await?;
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use std::sync::Mutex;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bus::{Delivery, Headers, Publisher, MSG_ID};
+
+/// Topic, key, payload and `msg_id` of a message that landed.
+type Landed = (String, String, Vec<u8>, Option<String>);
+
+/// Acks everything except each key's first attempt, after `flaky` is set.
+#[derive(Default)]
+struct Archive {
+    flaky: Vec<&'static str>,
+    tried: Mutex<Vec<String>>,
+    landed: Mutex<Vec<Landed>>,
+}
+
+#[async_trait]
+impl Publisher for Archive {
+    async fn publish(&self, topic: &str, key: &str, payload: &[u8], headers: &Headers) -> Result<()> {
+        let first = {
+            let mut tried = self.tried.lock().unwrap();
+            let first = !tried.iter().any(|k| k == key);
+            tried.push(key.to_string());
+            first
+        };
+        anyhow::ensure!(!(first && self.flaky.contains(&key)), "broker unavailable");
+        self.landed.lock().unwrap().push((topic.to_string(), key.to_string(), payload.to_vec(), headers.get(MSG_ID).map(String::from)));
+        Ok(())
+    }
+}
+
+fn delivery(key: Option<&str>, payload: &str, msg_id: &str) -> Delivery {
+    Delivery::detached("ticks.norm", key, payload.as_bytes().to_vec(), Headers::new().with(MSG_ID, msg_id))
+}
+
+#[tokio::test]
+async fn keys_payloads_and_headers_are_copied_as_is() {
+    let archive = Archive::default();
+    let batch = [delivery(Some("BTCUSDT"), r#"{"trade_id":1}"#, "m1"), delivery(None, "raw bytes", "m2")];
+    mirror::copy(&archive, "ticks.norm.archive", &batch).await;
+    let landed = archive.landed.lock().unwrap();
+    assert_eq!(*landed, [
+        ("ticks.norm.archive".to_string(), "BTCUSDT".to_string(), br#"{"trade_id":1}"#.to_vec(), Some("m1".to_string())),
+        ("ticks.norm.archive".to_string(), String::new(), b"raw bytes".to_vec(), Some("m2".to_string())),
+    ]);
+}
+
+#[tokio::test]
+async fn only_the_failed_deliveries_are_retried_until_they_land() {
+    let archive = Archive { flaky: vec!["ETHUSDT"], ..Archive::default() };
+    let batch = [delivery(Some("BTCUSDT"), "a", "m1"), delivery(Some("ETHUSDT"), "b", "m2"), delivery(Some("SOLUSDT"), "c", "m3")];
+    mirror::copy(&archive, "ticks.norm.archive", &batch).await;
+    assert_eq!(*archive.tried.lock().unwrap(), ["BTCUSDT", "ETHUSDT", "SOLUSDT", "ETHUSDT"]);
+    let landed: Vec<String> = archive.landed.lock().unwrap().iter().map(|(_, key, _, _)| key.clone()).collect();
+    assert_eq!(landed, ["BTCUSDT", "SOLUSDT", "ETHUSDT"]);
+}
@@ -0,0 +1,33 @@
+//! Bake the commit and compiler into `build_info`: `OBSV_GIT_SHA` is `GIT_SHA`
+//! when set (image builds without a `.git`), otherwise `git rev-parse`.
+
+use std::process::Command;
+
+fn output(cmd: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(cmd).args(args).output().ok().filter(|o| o.status.success())?;
+    Some(String::from_utf8(out.stdout).ok()?.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    // Rebuild when the checkout moves, so the SHA doesn't go stale.
+    for path in ["HEAD", "packed-refs"] {
+        if let Some(p) = output("git", &["rev-parse", "--git-path", path]) {
+            println!("cargo:rerun-if-changed={}", p);
+        }
+    }
+    if let Some(p) = output("git", &["symbolic-ref", "-q", "HEAD"]).and_then(|r| output("git", &["rev-parse", "--git-path", &r])) {
+        println!("cargo:rerun-if-changed={}", p);
+    }
+
+    let sha = std::env::var("GIT_SHA").ok().filter(|s| !s.is_empty())
+        .or_else(|| output("git", &["rev-parse", "--short=12", "HEAD"]))
+        .unwrap_or_else(|| "unknown".into());
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    // `rustc 1.85.0 (4d91de4e4 2025-02-17)` -> `1.85.0`
+    let rustc = output(&rustc, &["--version"])
+        .and_then(|v| v.split_whitespace().nth(1).map(String::from))
+        .unwrap_or_else(|| "unknown".into());
+    println!("cargo:rustc-env=OBSV_GIT_SHA={}", sha);
+    println!("cargo:rustc-env=OBSV_RUSTC={}", rustc);
+}
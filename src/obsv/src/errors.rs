@@ -0,0 +1,47 @@
+//! Failures by kind, so dashboards can tell junk input from broken
+//! infrastructure: `errors_total{stage,kind}`.
+//!
+//! - `transient`: a broker, database or exchange failed this one time; a retry may work.
+//! - `fatal`: retrying won't help (bad config, refused credentials). Callers that
+//!   go on after an error hand it to [`escalate`], which gives a fatal one back
+//!   for them to stop the stage with.
+//! - `data`: the message itself is bad (unparseable, unknown schema, bad signature); it is skipped.
+
+use metrics::counter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Transient,
+    Fatal,
+    Data,
+}
+
+impl ErrorKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            ErrorKind::Transient => "transient",
+            ErrorKind::Fatal => "fatal",
+            ErrorKind::Data => "data",
+        }
+    }
+}
+
+/// An error that knows which [`ErrorKind`] it is.
+pub trait Classify {
+    fn kind(&self) -> ErrorKind;
+}
+
+/// Count one `kind` error in `stage`.
+pub fn record(stage: &'static str, kind: ErrorKind) {
+    counter!("errors_total", "stage" => stage, "kind" => kind.label()).increment(1);
+}
+
+/// [`record`] `e` as `kind`. A fatal one is handed back, so the caller
+/// stops the stage with it instead of going on as for a passing failure.
+pub fn escalate(stage: &'static str, kind: ErrorKind, e: anyhow::Error) -> anyhow::Result<()> {
+    record(stage, kind);
+    match kind {
+        ErrorKind::Fatal => Err(e.context(format!("{} error in {}", kind.label(), stage))),
+        _ => Ok(()),
+    }
+}
@@ -1,42 +1,393 @@
-use std::time::Instant;
-use metrics::{self, Unit};
-use metrics_exporter_prometheus::PrometheusBuilder;
+pub mod admin;
+pub mod errors;
+pub mod load;
+pub mod namespace;
+pub mod runtime;
+pub mod slo;
+pub mod startup;
+pub mod version;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use axum::http::header::CONTENT_TYPE;
+use axum::routing::get;
+use axum::Router;
+use metrics::{self, Gauge, Unit};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{fmt, EnvFilter};
 
-/// Initialize JSON tracing with RFC3339 timestamps.
+/// Initialize JSON tracing with RFC3339 timestamps; warnings and errors are
+/// also kept for [`admin`].
 pub fn init_tracing() {
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
+    let json = fmt::layer()
         .json()
         .with_timer(fmt::time::UtcTime::rfc_3339())
         .with_target(true)
         .with_file(true)
-        .with_line_number(true)
-        .init();
+        .with_line_number(true);
+    tracing_subscriber::registry().with(filter).with(json).with(admin::Errors).init();
+}
+
+/// Expose Prometheus `/metrics` and [`version`]'s `/version` on 0.0.0.0:<port>,
+/// and start the [`admin`] API if it's configured. Call it after
+/// [`init_tracing`], so the admin API's start is logged and its `errors`
+/// see everything from then on.
+pub fn init_metrics(port: u16) -> anyhow::Result<()> {
+    init_metrics_with_slos(port, "")
 }
 
-/// Expose Prometheus `/metrics` on 0.0.0.0:<port>.
-pub fn init_metrics(port: u16) {
-    PrometheusBuilder::new()
-        .with_http_listener(([0, 0, 0, 0], port))
-        .install()
-        .expect("install prometheus exporter");
+/// As [`init_metrics`], also tracking SLOs: the `SLOS` env var, or `default_slos`
+/// (see [`slo::parse`]). A malformed spec or admin setting is an error.
+pub fn init_metrics_with_slos(port: u16, default_slos: &str) -> anyhow::Result<()> {
+    use anyhow::Context;
+    let specs = std::env::var("SLOS").unwrap_or_else(|_| default_slos.to_string());
+    let slos: Vec<Arc<slo::Slo>> = slo::parse(&specs)
+        .context("parsing SLOS")?
+        .into_iter()
+        .map(|s| Arc::new(slo::Slo::new(s)))
+        .collect();
+
+    // What PrometheusBuilder::install does, with the recorder wrapped and
+    // our own listener, so it can serve `/version` too.
+    let mut builder = PrometheusBuilder::new();
+    if let Some(ns) = namespace::current().name() {
+        builder = builder.add_global_label("namespace", ns);
+    }
+    let recorder = builder.build_recorder();
+    let listener = std::net::TcpListener::bind(("0.0.0.0", port)).with_context(|| format!("binding the metrics port {}", port))?;
+    listener.set_nonblocking(true).context("binding the metrics port")?;
+    spawn_server(serve(listener, recorder.handle()));
+    admin::start()?;
+    metrics::set_global_recorder(slo::SloRecorder::new(recorder, slos.clone())).expect("install prometheus exporter");
+    let build = version::current();
+    metrics::gauge!("build_info", "service" => build.service.clone(), "version" => build.version.clone(),
+        "git_sha" => build.git_sha.clone(), "rustc" => build.rustc.clone(), "features" => build.features_label()).set(1.0);
+    if !slos.is_empty() {
+        slo::spawn_evaluator(slos);
+    }
 
     // Describe key metrics (optional, adds units/help)
     metrics::describe_histogram!("e2e_latency_ms", Unit::Milliseconds, "E2E latency producer->consumer");
     metrics::describe_histogram!("produce_latency_ms", Unit::Milliseconds, "Kafka produce latency");
     metrics::describe_histogram!("commit_latency_ms", Unit::Milliseconds, "Kafka commit latency");
     metrics::describe_histogram!("questdb_write_ms", Unit::Milliseconds, "QuestDB write latency");
+    metrics::describe_histogram!("ilp_flush_interval_ms", Unit::Milliseconds, "Time between the consumer's QuestDB writes");
+    metrics::describe_histogram!("ilp_rows_per_flush", Unit::Count, "Trades per QuestDB write");
+    metrics::describe_histogram!("rows_per_flush", Unit::Count, "Rows per symbol in one coalesced ILP write");
+    metrics::describe_counter!("rollup_bars_total", Unit::Count, "OHLCV bars emitted on window close");
+    metrics::describe_counter!("rollup_late_total", Unit::Count, "Trades arriving after their bar was emitted");
+    metrics::describe_counter!("profile_levels_total", Unit::Count, "Volume-profile rows emitted on window close");
+    metrics::describe_counter!("profile_late_total", Unit::Count, "Trades arriving after their volume-profile window was emitted");
+    metrics::describe_counter!("trade_bars_total", Unit::Count, "Tick and imbalance bars closed, by kind");
+    metrics::describe_histogram!("sink_write_ms", Unit::Milliseconds, "Per-sink batch write latency, one sample per attempt");
+    metrics::describe_counter!("sink_failures_total", Unit::Count, "Failed sink write attempts");
+    metrics::describe_counter!("sink_dlq_total", Unit::Count, "Rows sent to a sink's dead-letter topic");
+    metrics::describe_counter!("sink_short_circuited_total", Unit::Count, "Batches a sink's open breaker sent past it without a try, by sink");
+    metrics::describe_counter!("sink_dropped_rows_total", Unit::Count, "Rows a sink lost with no DLQ to catch them");
+    metrics::describe_gauge!("questdb_schema_version", Unit::Count, "Highest QuestDB migration applied at startup");
+    metrics::describe_gauge!("questdb_endpoint_up", Unit::Count, "1 while the consumer can write to this QuestDB endpoint");
+    metrics::describe_counter!("questdb_failovers_total", Unit::Count, "Batches moved off a QuestDB endpoint after it failed");
+    metrics::describe_counter!("questdb_verify_sent_rows_total", Unit::Count, "Distinct rows written over ILP in windows counted back from QuestDB, by endpoint and table");
+    metrics::describe_counter!("questdb_verify_missing_rows_total", Unit::Count, "Rows written over ILP that QuestDB doesn't have, by endpoint and table");
+    metrics::describe_gauge!("questdb_verify_divergence", Unit::Count, "Rows missing from the last window checked, by endpoint and table");
+    metrics::describe_gauge!("questdb_verify_last_ts_lag_ms", Unit::Milliseconds, "Newest row sent minus QuestDB's newest row, by endpoint and table");
+    metrics::describe_gauge!("questdb_verify_pending_windows", Unit::Count, "Windows of written rows waiting to be counted back");
+    metrics::describe_counter!("questdb_verify_errors_total", Unit::Count, "Verification queries that failed, by endpoint and table");
+    metrics::describe_counter!("questdb_verify_rechecks_total", Unit::Count, "Windows that counted short and were counted again before any rows were called lost, by endpoint and table");
+    metrics::describe_counter!("questdb_verify_unrecorded_rows_total", Unit::Count, "Rows written over ILP but not recorded for verification because the ledger was full, by endpoint and table");
+    metrics::describe_gauge!("ilp_table_symbols", Unit::Count, "Distinct symbols the consumer has admitted to a trades table");
+    metrics::describe_counter!("ilp_overflow_symbols_total", Unit::Count, "Symbols sent to the overflow table because their table was at QDB_MAX_SYMBOLS, by table");
+    metrics::describe_counter!("ilp_overflow_rows_total", Unit::Count, "Rows written to the overflow table instead of their own, by table");
     metrics::describe_gauge!("consumer_lag", Unit::Count, "Kafka consumer lag");
+    metrics::describe_counter!("kafka_wire_bytes_total", Unit::Bytes, "Bytes a Kafka client sent (producer) or received (consumer) over the wire, compressed");
+    metrics::describe_counter!("kafka_payload_bytes_total", Unit::Bytes, "Message bytes a Kafka client produced or consumed, before compression / after decompression");
+    metrics::describe_gauge!("kafka_compression_ratio", Unit::Count, "kafka_payload_bytes_total over kafka_wire_bytes_total, per client");
+    metrics::describe_gauge!("lagmon_lag", Unit::Count, "Messages between a group's committed offset and the partition's high watermark");
+    metrics::describe_gauge!("lagmon_group_lag", Unit::Count, "lagmon_lag summed over a topic's partitions, per group");
+    metrics::describe_gauge!("lagmon_committed_offset", Unit::Count, "A group's committed offset on a partition");
+    metrics::describe_gauge!("lagmon_commit_age_seconds", Unit::Seconds, "Time since lagmon last saw a group's committed offset move on a partition");
+    metrics::describe_counter!("lagmon_scrapes_total", Unit::Count, "Completed lag scrapes");
+    metrics::describe_counter!("lagmon_errors_total", Unit::Count, "Failed lag scrapes or per-group offset fetches");
+    metrics::describe_counter!("spilled_total", Unit::Count, "Messages of lost batches written to SPILL_DIR");
+    metrics::describe_gauge!("paused", Unit::Count, "1 while the consumer has stopped fetching until a failed batch is written");
+    metrics::describe_gauge!("watermark_ts_ms", Unit::Milliseconds, "Event time up to which a symbol's trades are fully written");
+    metrics::describe_gauge!("watermark_lag_ms", Unit::Milliseconds, "Wall clock minus the symbol's watermark");
+    metrics::describe_gauge!("watermark_pending", Unit::Count, "Trades written past an open trade id gap");
+    metrics::describe_counter!("watermark_gaps_skipped_total", Unit::Count, "Trade id gaps given up on after the gap timeout");
+    metrics::describe_gauge!("fetcher_assigned_symbols", Unit::Count, "Symbols owned by this fetcher shard");
+    metrics::describe_counter!("discovery_errors_total", Unit::Count, "Symbol discovery polls that failed or were ignored");
+    metrics::describe_counter!("listing_events_total", Unit::Count, "Symbols listed or delisted by auto-discovery, by event");
+    metrics::describe_counter!("errors_total", Unit::Count, "Errors by stage and kind: transient (infrastructure, retryable), fatal (stops the stage) or data (a bad message, skipped)");
     metrics::describe_counter!("produced_total", Unit::Count, "Messages produced");
     metrics::describe_counter!("consumed_total", Unit::Count, "Messages consumed");
-    metrics::describe_counter!("dropped_total", Unit::Count, "Messages dropped");
+    metrics::describe_counter!("dropped_total", Unit::Count, "Messages dropped (by reason in the consumer)");
+    metrics::describe_counter!("dropped_dlq_total", Unit::Count, "Messages the consumer dropped and published to DROPPED_DLQ");
+    metrics::describe_counter!("dropped_dlq_failed_total", Unit::Count, "Messages the consumer dropped and failed to publish to DROPPED_DLQ");
     metrics::describe_counter!("dupes_total", Unit::Count, "Duplicate messages");
+    metrics::describe_counter!("schema_rejected_total", Unit::Count, "Messages rejected for an unknown schema major version");
+    metrics::describe_counter!("codec_total", Unit::Count, "Trades decoded, by the codec header they arrived with");
+    metrics::describe_counter!("codec_rejected_total", Unit::Count, "Trades that failed to decode, by codec (unknown for a codec we don't read)");
+    metrics::describe_counter!("avro_schemas_fetched_total", Unit::Count, "Avro writer schemas fetched from the schema registry, by whether it had them as Avro");
+    metrics::describe_counter!("encrypted_total", Unit::Count, "Payloads sealed with a data key before publishing, by topic");
+    metrics::describe_counter!("decrypted_total", Unit::Count, "Sealed deliveries opened on receipt, by topic");
+    metrics::describe_counter!("decrypt_failures_total", Unit::Count, "Sealed deliveries passed on unopened, by topic and reason");
+    metrics::describe_counter!("data_key_rotations_total", Unit::Count, "Data keys generated by this process's publishers");
+    metrics::describe_counter!("rest_requests_total", Unit::Count, "Exchange REST responses, by exchange and HTTP status");
+    metrics::describe_counter!("rest_throttled_total", Unit::Count, "Exchange REST responses that were 429 or 418, holding every REST call until Retry-After");
+    metrics::describe_counter!("rest_weight_spent_total", Unit::Count, "Request weight taken from the exchange's REST budget");
+    metrics::describe_gauge!("rest_weight_used", Unit::Count, "Weight the exchange reports used in its current window, counting everyone on this IP");
+    metrics::describe_gauge!("rest_queued", Unit::Count, "REST calls waiting for the exchange's budget");
+    metrics::describe_histogram!("rest_queue_wait_ms", Unit::Milliseconds, "Time REST calls waited for the exchange's budget");
+    metrics::describe_counter!("secret_rotations_total", Unit::Count, "Secrets from files or stores whose value changed on refresh, by name");
+    metrics::describe_counter!("secret_refresh_failures_total", Unit::Count, "Secret refreshes that failed and kept the last good value, by name");
+    metrics::describe_counter!("signature_failures_total", Unit::Count, "Messages whose HMAC signature was missing, malformed, from an unknown key or wrong, by stage and reason");
+    metrics::describe_counter!("schema_unversioned_total", Unit::Count, "Messages without a schema_version header (accepted as v1)");
+    metrics::describe_counter!("bars_publish_failed_total", Unit::Count, "Rollup bars the consumer failed to publish to BARS_TOPIC");
+    metrics::describe_counter!("latest_publish_failed_total", Unit::Count, "Failed publishes to the latest-state topic");
+    metrics::describe_counter!("produce_retries_total", Unit::Count, "Producer publishes to the normalized topic retried after a transient failure");
+    metrics::describe_counter!("produce_dlq_total", Unit::Count, "Normalized trades dead-lettered after the normalized topic refused them");
+    metrics::describe_counter!("payload_oversize_total", Unit::Count, "Messages over MAX_PAYLOAD_BYTES, by stage and what was done with them (drop, dlq, truncate)");
+    metrics::describe_counter!("listing_windows_total", Unit::Count, "Listing markers turned into listing windows, by event");
+    metrics::describe_counter!("symbol_rate_limited_total", Unit::Count, "Raw messages the producer queued behind their symbol's SYMBOL_RATE_LIMITS entry");
+    metrics::describe_counter!("symbol_rate_overflow_total", Unit::Count, "Messages forwarded past their symbol's rate limit because its queue was full");
+    metrics::describe_gauge!("symbol_queue_depth", Unit::Count, "Raw messages queued in the producer waiting for their symbol's turn");
+    metrics::describe_histogram!("symbol_queue_delay_ms", Unit::Milliseconds, "Time a raw message spent queued in the producer before processing");
+    metrics::describe_counter!("replayed_skipped_total", Unit::Count, "Backfilled messages the QuestDB writer skipped");
+    metrics::describe_counter!("replayed_written_total", Unit::Count, "Backfilled messages written anyway under DEDUP_MODE=questdb");
+    metrics::describe_counter!("ingest_accepted_total", Unit::Count, "Pushed trades accepted by the ingest server");
+    metrics::describe_counter!("ingest_rejected_total", Unit::Count, "Pushed lines rejected by the ingest server");
+    metrics::describe_counter!("ingest_failed_total", Unit::Count, "Pushed trades the broker did not take; the request got a 503");
+    metrics::describe_counter!("ingest_unauthorized_total", Unit::Count, "Ingest requests without a valid client certificate or token");
+    metrics::describe_counter!("ingest_denied_total", Unit::Count, "Pushed trades for symbols the client may not send, by client");
+    metrics::describe_counter!("tls_handshake_failures_total", Unit::Count, "TLS handshakes that failed or timed out, by server and reason");
+    metrics::describe_counter!("ws_reconnects_total", Unit::Count, "Websocket reconnect attempts");
+    metrics::describe_counter!("ws_messages_total", Unit::Count, "Websocket data frames received, by exchange and stream, control frames included");
+    metrics::describe_counter!("ws_bytes_total", Unit::Bytes, "Websocket data frame bytes received, by exchange and stream");
+    metrics::describe_gauge!("ws_symbol_message_rate", Unit::CountPerSecond, "Messages a second for one symbol's subscription since the last report");
+    metrics::describe_gauge!("ws_last_message_age_seconds", Unit::Seconds, "Time since a symbol's subscription last received a message, or since it was made");
+    metrics::describe_gauge!("fetch_queue_depth", Unit::Count, "Fetcher messages queued for the broker, by topic");
+    metrics::describe_histogram!("fetch_queue_wait_ms", Unit::Milliseconds, "Time a fetcher message waited in the queue before its publish");
+    metrics::describe_counter!("fetch_throttled_total", Unit::Count, "Fetcher sends that waited for room in a full queue, by topic");
+    metrics::describe_histogram!("fetch_throttle_ms", Unit::Milliseconds, "How long a fetcher send waited for room in the queue");
+    metrics::describe_counter!("startup_check_failures_total", Unit::Count, "Failed startup checks of a dependency, by dependency");
+    metrics::describe_counter!("retries_total", Unit::Count, "Retries of a failed call, by op");
+    metrics::describe_histogram!("retry_backoff_ms", Unit::Milliseconds, "Wait before each retry, jitter included, by op");
+    metrics::describe_counter!("retry_exhausted_total", Unit::Count, "Calls given up on after their policy's last retry, by op");
+    metrics::describe_counter!("retry_budget_denied_total", Unit::Count, "Retries refused because RETRY_BUDGET was spent, by op");
+    metrics::describe_gauge!("breaker_state", Unit::Count, "Circuit breaker state by op: 0 closed, 1 half-open, 2 open");
+    metrics::describe_counter!("breaker_opened_total", Unit::Count, "Times a circuit breaker opened, by op");
+    metrics::describe_gauge!("exchange_status", Unit::Count, "Exchange status by venue: 0 up, 1 maintenance, 2 down");
+    metrics::describe_counter!("exchange_status_changes_total", Unit::Count, "Exchange status changes, by the status changed to");
+    metrics::describe_counter!("exchange_status_poll_errors_total", Unit::Count, "Failed polls of an exchange status endpoint");
+    metrics::describe_gauge!("exchange_clock_offset_ms", Unit::Milliseconds, "Exchange clock minus local clock, smoothed; positive when the venue is ahead");
+    metrics::describe_gauge!("exchange_clock_rtt_ms", Unit::Milliseconds, "Round trip of the last accepted server time poll");
+    metrics::describe_gauge!("exchange_clock_drift_ppm", Unit::Count, "How fast the exchange clock offset moves, in parts per million");
+    metrics::describe_counter!("exchange_clock_poll_errors_total", Unit::Count, "Failed polls of an exchange server time endpoint");
+    metrics::describe_counter!("exchange_clock_samples_rejected_total", Unit::Count, "Server time polls left out for too long a round trip");
+    metrics::describe_counter!("clock_corrected_total", Unit::Count, "Trades whose ts_ms was moved by the exchange clock offset (CLOCK_CORRECT)");
+    metrics::describe_histogram!("clock_correction_ms", Unit::Milliseconds, "Offset subtracted from corrected trades' ts_ms");
+    metrics::describe_counter!("mirror_copied_total", Unit::Count, "Messages copied to the archive topic");
+    metrics::describe_counter!("mirror_failed_total", Unit::Count, "Archive publishes that failed and were retried");
+    metrics::describe_histogram!("mirror_publish_ms", Unit::Milliseconds, "Time to publish one mirror batch");
+    metrics::describe_counter!("join_written_total", Unit::Count, "Trades written with their prevailing quote");
+    metrics::describe_counter!("join_unmatched_total", Unit::Count, "Trades written without a quote before them");
+    metrics::describe_counter!("join_dropped_total", Unit::Count, "Trade messages the joiner couldn't decode");
+    metrics::describe_counter!("join_bad_quotes_total", Unit::Count, "bookTicker messages the joiner couldn't parse");
+    metrics::describe_counter!("join_quotes_out_of_order_total", Unit::Count, "Quotes older than the last one seen for their symbol");
+    metrics::describe_histogram!("join_write_ms", Unit::Milliseconds, "Time to write one joined batch to QuestDB");
+    metrics::describe_counter!("book_diffs_total", Unit::Count, "Depth diffs the book builder read, by step (applied|buffered|stale|gap)");
+    metrics::describe_counter!("book_bad_diffs_total", Unit::Count, "Depth messages the book builder couldn't parse or apply");
+    metrics::describe_counter!("book_snapshot_fetches_total", Unit::Count, "REST depth snapshots fetched to (re)build a book, by result (ok|too_old|error)");
+    metrics::describe_counter!("book_top_published_total", Unit::Count, "Top-of-book changes published to BOOK_TOP_TOPIC");
+    metrics::describe_counter!("book_snapshots_published_total", Unit::Count, "Full-depth snapshots published to BOOK_SNAPSHOT_TOPIC");
+    metrics::describe_gauge!("spread_bps", Unit::Count, "Quoted spread of the symbol's last book snapshot, in basis points of the mid");
+    metrics::describe_counter!("book_rows_total", Unit::Count, "Book snapshots written to BOOK_TABLE");
+    metrics::describe_counter!("book_dropped_total", Unit::Count, "Book snapshot messages the consumer couldn't decode");
+    metrics::describe_gauge!("gateway_clients", Unit::Count, "WebSocket clients connected to the gateway");
+    metrics::describe_gauge!("gateway_client_queue_depth", Unit::Count, "Messages queued for a gateway client, by client");
+    metrics::describe_gauge!("gateway_client_lag_ms", Unit::Milliseconds, "Age of the oldest message queued for a gateway client, by client");
+    metrics::describe_counter!("gateway_client_dropped_total", Unit::Count, "Messages dropped from a full gateway client queue (drop_oldest), by client");
+    metrics::describe_counter!("gateway_slow_disconnects_total", Unit::Count, "Gateway clients cut off for a full queue (disconnect), by client");
+    metrics::describe_counter!("gateway_sent_total", Unit::Count, "Records the gateway sent to clients, by transport (ws|sse|ndjson|grpc)");
+    metrics::describe_counter!("gateway_skipped_total", Unit::Count, "Bus records the gateway could not rebroadcast (empty, not UTF-8, or not a bar on a bar stream)");
+    metrics::describe_counter!("gateway_bad_filters_total", Unit::Count, "Gateway connections refused for a filter that did not parse");
+    metrics::describe_gauge!("gateway_cached_symbols", Unit::Count, "Symbol and exchange pairs in the gateway's /v1/last cache");
+    metrics::describe_counter!("gateway_last_requests_total", Unit::Count, "Requests to the gateway's /v1/last endpoint");
+    metrics::describe_counter!("gateway_bar_history_requests_total", Unit::Count, "Requests to the gateway's /v1/bars history endpoint");
+    metrics::describe_counter!("gateway_exports_total", Unit::Count, "Exports started by the gateway, by dataset and format (parquet|arrow)");
+    metrics::describe_counter!("gateway_export_rows_total", Unit::Count, "Rows in finished gateway exports, by dataset");
+    metrics::describe_counter!("vwap_windows_total", Unit::Count, "VWAP/TWAP windows closed");
+    metrics::describe_counter!("vwap_published_total", Unit::Count, "VWAP/TWAP windows published and written");
+    metrics::describe_counter!("vwap_late_total", Unit::Count, "Trades arriving after their VWAP window closed");
+    metrics::describe_counter!("vwap_redelivered_total", Unit::Count, "Redelivered trades skipped because the snapshot already has them");
+    metrics::describe_counter!("vwap_dropped_total", Unit::Count, "Trade messages the VWAP service couldn't decode");
+    metrics::describe_counter!("vol_rows_total", Unit::Count, "Rows written to VOL_TABLE, one per symbol and horizon per bar");
+    metrics::describe_counter!("vol_dropped_total", Unit::Count, "Bar messages the volatility stage couldn't decode");
+    metrics::describe_counter!("strategy_fills_total", Unit::Count, "Paper fills, by strategy");
+    metrics::describe_counter!("strategy_dropped_total", Unit::Count, "Bar or trade messages the strategy stage couldn't decode");
+    metrics::describe_counter!("order_commands_total", Unit::Count, "Order commands sent to the exchange, by result (accepted, rejected, unknown)");
+    metrics::describe_counter!("order_reconciles_total", Unit::Count, "Lookups of orders whose command had an unknown outcome, by result (found, not_found, failed)");
+    metrics::describe_counter!("order_commands_dropped_total", Unit::Count, "Order commands that couldn't be decoded");
+    metrics::describe_counter!("executions_total", Unit::Count, "Executions published, by exec_type");
+    metrics::describe_counter!("user_stream_reconnects_total", Unit::Count, "User data stream reconnects");
+    metrics::describe_gauge!("burst_rate", Unit::CountPerSecond, "Trades per second in the symbol's last closed burst-detector window");
+    metrics::describe_gauge!("burst_baseline", Unit::CountPerSecond, "The symbol's rolling baseline trade rate, per second");
+    metrics::describe_counter!("anomalies_total", Unit::Count, "Bursts published to ANOMALIES_TOPIC, by symbol and kind (flat|moving)");
+    metrics::describe_counter!("anomalies_suppressed_total", Unit::Count, "Bursts not published because they fell in a maintenance window");
+    metrics::describe_counter!("burst_dropped_total", Unit::Count, "Trade messages the burst detector couldn't decode");
+    metrics::describe_gauge!("audit_expected", Unit::Count, "Exchange trades in the last audited window");
+    metrics::describe_gauge!("audit_missing", Unit::Count, "Exchange trades missing from QuestDB in the last audited window");
+    metrics::describe_gauge!("audit_duplicates", Unit::Count, "Trade ids stored more than once in the last audited window");
+    metrics::describe_gauge!("audit_mismatched", Unit::Count, "Stored trades disagreeing with the exchange in the last audited window");
+    metrics::describe_gauge!("audit_unexpected", Unit::Count, "Stored trade ids the exchange doesn't list for the window");
+    metrics::describe_counter!("audit_runs_total", Unit::Count, "Completed audit runs");
+    metrics::describe_counter!("audit_errors_total", Unit::Count, "Per-symbol audit failures");
+    metrics::describe_counter!("audit_skipped_total", Unit::Count, "Audit runs skipped because the window overlaps a maintenance window");
+    metrics::describe_counter!("notional_unconverted_total", Unit::Count, "Trades published without notional because no fresh rate was known, by quote asset");
+    metrics::describe_gauge!("maintenance", Unit::Count, "1 while the wall clock is inside a MAINTENANCE_WINDOWS entry");
+    metrics::describe_counter!("maintenance_skipped_total", Unit::Count, "Trades left out of aggregates because they fall in a maintenance window, by stage");
+    metrics::describe_counter!("chaos_injected_total", Unit::Count, "Faults injected by chaos mode, by fault");
+    metrics::describe_gauge!("slo_burn_rate", Unit::Count, "Error rate over the window divided by the SLO's error budget, by slo and window");
+    metrics::describe_gauge!("slo_alert", Unit::Count, "1 while an SLO's burn-rate alert is firing, by slo and severity (page|ticket)");
+    metrics::describe_counter!("slo_alerts_total", Unit::Count, "SLO burn-rate alerts fired, by slo and severity");
+    metrics::describe_gauge!("buffer_bytes", Unit::Bytes, "Estimated size of an in-process buffer, by buffer");
+    metrics::describe_gauge!("stage_rate_per_sec", Unit::CountPerSecond, "Messages a stage finished per second, smoothed over LOAD_EWMA_SECS");
+    metrics::describe_gauge!("stage_saturation", Unit::Count, "Share of wall time a stage spent busy, smoothed over LOAD_EWMA_SECS");
+    metrics::describe_counter!("buffer_overflow_total", Unit::Count, "Times a buffer went over its budget and had to shed or flush");
+    metrics::describe_gauge!("build_info", Unit::Count, "1, labelled with the service's version, git_sha, rustc and Cargo features");
+    metrics::describe_counter!("audit_events_total", Unit::Count, "Lifecycle events published to the ops audit topic, by event");
+    metrics::describe_counter!("audit_failures_total", Unit::Count, "Lifecycle events that couldn't be published to the ops audit topic, by event");
+    Ok(())
+}
+
+/// Run a listener's server on the current runtime, or on a thread of its own
+/// when called outside one.
+fn spawn_server(server: impl std::future::Future<Output = ()> + Send + 'static) {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => drop(handle.spawn(server)),
+        Err(_) => {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().expect("exporter runtime");
+            std::thread::spawn(move || runtime.block_on(server));
+        }
+    }
+}
+
+/// Serve `/version`, and the metrics on every other path, as the exporter's
+/// own listener did; runs the recorder's upkeep alongside.
+async fn serve(listener: std::net::TcpListener, handle: PrometheusHandle) {
+    let upkeep = handle.clone();
+    tokio::spawn(async move {
+        let mut every = tokio::time::interval(Duration::from_secs(5));
+        loop {
+            every.tick().await;
+            upkeep.run_upkeep();
+        }
+    });
+    let app = Router::new()
+        .route("/version", get(|| async { ([(CONTENT_TYPE, "application/json")], version::current().to_json()) }))
+        .fallback(move || async move { ([(CONTENT_TYPE, "text/plain; version=0.0.4")], handle.render()) });
+    let res = match tokio::net::TcpListener::from_std(listener) {
+        Ok(listener) => axum::serve(listener, app).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = res {
+        tracing::error!(target: "obsv", error = ?e, "metrics listener stopped");
+    }
+}
+
+/// What a buffer does once it's over its [`Budget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Stop taking input until the buffer drains.
+    Block,
+    /// Let go of the oldest entries.
+    DropOldest,
+    /// Move the buffer to disk.
+    Spill,
+}
+
+impl Overflow {
+    pub fn name(self) -> &'static str {
+        match self {
+            Overflow::Block => "block",
+            Overflow::DropOldest => "drop_oldest",
+            Overflow::Spill => "spill",
+        }
+    }
+}
+
+/// A memory limit for one in-process buffer, exported as `buffer_bytes{buffer}`
+/// and in the [`admin`] API's `buffers`.
+///
+/// Sizes are estimates (payload bytes plus a fixed per-entry overhead); they're
+/// meant to keep a burst from growing a buffer without bound, not to account
+/// for every allocation.
+pub struct Budget {
+    name: &'static str,
+    limit: usize,
+    overflow: Overflow,
+    gauge: Gauge,
+    used: Arc<AtomicUsize>,
+}
+
+impl Budget {
+    pub fn new(name: &'static str, limit: usize, overflow: Overflow) -> Self {
+        let used = Arc::new(AtomicUsize::new(0));
+        let seen = used.clone();
+        admin::view("buffers", name, move || serde_json::json!({ "bytes": seen.load(Ordering::Relaxed), "limit": limit, "overflow": overflow.name() }));
+        Self { name, limit, overflow, gauge: metrics::gauge!("buffer_bytes", "buffer" => name), used }
+    }
+
+    /// Limit from `BUDGET_<NAME>_BYTES`, or `default` bytes, and overflow
+    /// policy from `BUDGET_<NAME>_OVERFLOW`, one of `policies`: the buffer's
+    /// default first, then whatever else it can do.
+    pub fn from_env(name: &'static str, default: usize, policies: &[Overflow]) -> anyhow::Result<Self> {
+        let var = |what: &str| std::env::var(format!("BUDGET_{}_{}", name.to_uppercase(), what)).ok();
+        let limit = var("BYTES").and_then(|v| v.parse().ok()).unwrap_or(default);
+        let overflow = match var("OVERFLOW") {
+            None => policies[0],
+            Some(v) => match policies.iter().find(|p| p.name() == v) {
+                Some(p) => *p,
+                None => {
+                    let names: Vec<_> = policies.iter().map(|p| p.name()).collect();
+                    anyhow::bail!("unknown BUDGET_{}_OVERFLOW {:?} (expected {})", name.to_uppercase(), v, names.join("|"));
+                }
+            },
+        };
+        Ok(Self::new(name, limit, overflow))
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    /// Record the buffer's current size; true if it's over the limit.
+    pub fn over(&self, used: usize) -> bool {
+        self.gauge.set(used as f64);
+        self.used.store(used, Ordering::Relaxed);
+        used > self.limit
+    }
+
+    /// Count one overflow, after the caller has shed or flushed.
+    pub fn overflowed(&self) {
+        metrics::counter!("buffer_overflow_total", "buffer" => self.name).increment(1);
+    }
 }
 
-/// Measure a synchronous operation and return ((), elapsed_ms).
-pub fn measure_ms<F: FnOnce()>(f: F) -> ((), f64) {
+/// Measure a synchronous operation and return (output, elapsed_ms).
+pub fn measure_ms<T, F: FnOnce() -> T>(f: F) -> (T, f64) {
     let t0 = Instant::now();
     let out = f();
     let ms = t0.elapsed().as_secs_f64() * 1000.0;
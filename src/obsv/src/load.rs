@@ -0,0 +1,146 @@
+//! Per-stage throughput and saturation, smoothed in-process so a dashboard
+//! can read them straight off a gauge.
+//!
+//! A stage counts the messages it finishes with [`Load::processed`] and holds
+//! a [`Load::busy`] guard while it works on them. Once a second each [`Load`]
+//! folds what it saw into exponentially weighted averages with a time
+//! constant of `LOAD_EWMA_SECS` (default 30) and exports them as:
+//!
+//! - `stage_rate_per_sec{stage}`: messages finished per second.
+//! - `stage_saturation{stage}`: the share of wall time spent busy. 1 means the
+//!   stage never waits for input and more of it would fall behind; work
+//!   spread over several tasks can go past 1.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use metrics::Gauge;
+
+/// How often the averages are updated.
+pub const TICK: Duration = Duration::from_secs(1);
+
+/// An exponentially weighted rate and saturation, updated a tick at a time.
+#[derive(Debug, Clone)]
+pub struct Ewma {
+    tau: f64,
+    rate: Option<f64>,
+    saturation: Option<f64>,
+}
+
+impl Ewma {
+    /// Averages with time constant `tau`: a step change is ~63% in after `tau`.
+    pub fn new(tau: Duration) -> Self {
+        Self { tau: tau.as_secs_f64().max(f64::EPSILON), rate: None, saturation: None }
+    }
+
+    /// Fold in `msgs` finished and `busy` time spent over `dt` of wall time;
+    /// returns the new (rate per second, saturation). The first tick is taken as is.
+    pub fn update(&mut self, msgs: u64, busy: Duration, dt: Duration) -> (f64, f64) {
+        let secs = dt.as_secs_f64();
+        if secs <= 0.0 {
+            return (self.rate.unwrap_or(0.0), self.saturation.unwrap_or(0.0));
+        }
+        // Weighted by the real gap, so a late tick counts for as long as it covered.
+        let alpha = 1.0 - (-secs / self.tau).exp();
+        let fold = |avg: Option<f64>, x: f64| avg.map_or(x, |a| a + alpha * (x - a));
+        let rate = fold(self.rate, msgs as f64 / secs);
+        let saturation = fold(self.saturation, busy.as_secs_f64() / secs);
+        (self.rate, self.saturation) = (Some(rate), Some(saturation));
+        (rate, saturation)
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate.unwrap_or(0.0)
+    }
+
+    pub fn saturation(&self) -> f64 {
+        self.saturation.unwrap_or(0.0)
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    msgs: AtomicU64,
+    busy_ns: AtomicU64,
+}
+
+/// One stage's message and busy-time counts; cheap to clone and share.
+#[derive(Clone)]
+pub struct Load {
+    counts: Arc<Counts>,
+}
+
+struct Tracked {
+    counts: Arc<Counts>,
+    ewma: Ewma,
+    rate: Gauge,
+    saturation: Gauge,
+}
+
+impl Load {
+    /// Start exporting `stage`'s averages; the ticker thread starts with the first one.
+    /// Fails if `LOAD_EWMA_SECS` isn't a positive number of seconds.
+    pub fn new(stage: &'static str) -> Result<Self> {
+        let tau = match std::env::var("LOAD_EWMA_SECS") {
+            Ok(v) => v.parse().ok().and_then(|t| Duration::try_from_secs_f64(t).ok()).filter(|t| !t.is_zero())
+                .with_context(|| format!("LOAD_EWMA_SECS must be a positive number of seconds, got {:?}", v))?,
+            Err(_) => Duration::from_secs(30),
+        };
+        let counts = Arc::new(Counts::default());
+        tracked().lock().unwrap_or_else(PoisonError::into_inner).push(Tracked {
+            counts: counts.clone(),
+            ewma: Ewma::new(tau),
+            rate: metrics::gauge!("stage_rate_per_sec", "stage" => stage),
+            saturation: metrics::gauge!("stage_saturation", "stage" => stage),
+        });
+        Ok(Self { counts })
+    }
+
+    /// Count `n` messages finished.
+    pub fn processed(&self, n: u64) {
+        self.counts.msgs.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Time spent until the guard drops counts as busy.
+    pub fn busy(&self) -> Busy {
+        Busy { counts: self.counts.clone(), t0: Instant::now() }
+    }
+}
+
+/// Counts its lifetime as busy time; see [`Load::busy`].
+pub struct Busy {
+    counts: Arc<Counts>,
+    t0: Instant,
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        self.counts.busy_ns.fetch_add(self.t0.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+fn tracked() -> &'static Mutex<Vec<Tracked>> {
+    static TRACKED: OnceLock<Mutex<Vec<Tracked>>> = OnceLock::new();
+    TRACKED.get_or_init(|| {
+        // A thread of its own, so the averages keep moving while a stage's runtime is stuck.
+        std::thread::Builder::new().name("obsv-load".into()).spawn(|| {
+            let mut last = Instant::now();
+            loop {
+                std::thread::sleep(TICK);
+                let now = Instant::now();
+                let dt = now - last;
+                last = now;
+                for t in tracked().lock().unwrap_or_else(PoisonError::into_inner).iter_mut() {
+                    let msgs = t.counts.msgs.swap(0, Ordering::Relaxed);
+                    let busy = Duration::from_nanos(t.counts.busy_ns.swap(0, Ordering::Relaxed));
+                    let (rate, saturation) = t.ewma.update(msgs, busy, dt);
+                    t.rate.set(rate);
+                    t.saturation.set(saturation);
+                }
+            }
+        }).expect("spawn load ticker");
+        Mutex::new(Vec::new())
+    })
+}
@@ -0,0 +1,106 @@
+//! `NAMESPACE`: one prefix on everything a deployment names on shared
+//! infrastructure, so dev, staging and prod (or two clients) can share a
+//! broker, a QuestDB and a Prometheus without colliding.
+//!
+//! Every topic, consumer group and table name in the config stays the
+//! logical one (`ticks.norm`, `consumer-stage`, `trades_1m`); the namespace
+//! is added where the name meets the infrastructure:
+//!
+//! - topics and groups as `{ns}.{name}` (`prod.ticks.norm`), by `bus`;
+//! - QuestDB tables as `{ns}_{name}` (`prod_trades_1m`), by each stage;
+//! - metrics get a `namespace="prod"` label, by [`init_metrics`](crate::init_metrics).
+//!
+//! Unset or empty, every name is used as is.
+
+use std::sync::OnceLock;
+
+use anyhow::Result;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Namespace(Option<String>);
+
+static CURRENT: OnceLock<Namespace> = OnceLock::new();
+
+impl Namespace {
+    /// Lower-case letters, digits and `_`, not starting with a digit: the
+    /// prefixed names are then valid topic and group names, and table names
+    /// QuestDB takes without quoting. Empty for none.
+    pub fn new(ns: &str) -> Result<Self> {
+        let ns = ns.trim();
+        anyhow::ensure!(
+            ns.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+                && !ns.starts_with(|c: char| c.is_ascii_digit()),
+            "NAMESPACE {:?} may only hold lower-case letters, digits and _, and may not start with a digit", ns
+        );
+        Ok(Self(Some(ns.to_string()).filter(|n| !n.is_empty())))
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Self::new(&std::env::var("NAMESPACE").unwrap_or_default())
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    /// `prod.ticks.norm` for `ticks.norm`. Empty stays empty: that's "off"
+    /// for every optional topic.
+    pub fn topic(&self, topic: &str) -> String {
+        self.join(topic, '.')
+    }
+
+    pub fn group(&self, group: &str) -> String {
+        self.join(group, '.')
+    }
+
+    /// `prod_trades_1m` for `trades_1m`; empty stays empty.
+    pub fn table(&self, table: &str) -> String {
+        self.join(table, '_')
+    }
+
+    /// Whether `name` (a topic or group) is one of ours.
+    pub fn owns(&self, name: &str) -> bool {
+        match &self.0 {
+            Some(ns) => name.strip_prefix(ns.as_str()).is_some_and(|rest| rest.starts_with('.')),
+            None => true,
+        }
+    }
+
+    fn join(&self, name: &str, sep: char) -> String {
+        match &self.0 {
+            Some(ns) if !name.is_empty() => format!("{}{}{}", ns, sep, name),
+            _ => name.to_string(),
+        }
+    }
+}
+
+/// Read and check `NAMESPACE`; [`runtime::run`](crate::runtime::run) does this
+/// before the binary starts, so a bad one fails there.
+pub fn init() -> Result<&'static Namespace> {
+    if let Some(ns) = CURRENT.get() {
+        return Ok(ns);
+    }
+    let ns = Namespace::from_env()?;
+    Ok(CURRENT.get_or_init(|| ns))
+}
+
+/// The process's namespace. Exits on a malformed `NAMESPACE` if [`init`]
+/// hasn't already caught it.
+pub fn current() -> &'static Namespace {
+    init().expect("parse NAMESPACE")
+}
+
+/// [`Namespace::topic`] in the process's namespace.
+pub fn topic(topic: &str) -> String {
+    current().topic(topic)
+}
+
+/// [`Namespace::group`] in the process's namespace.
+pub fn group(group: &str) -> String {
+    current().group(group)
+}
+
+/// [`Namespace::table`] in the process's namespace.
+pub fn table(table: &str) -> String {
+    current().table(table)
+}
@@ -0,0 +1,310 @@
+//! The tokio runtime every binary runs on, sized from the environment.
+//!
+//! `#[tokio::main]` gives every core an IO worker and allows 512 blocking
+//! threads, all called `tokio-runtime-worker`. Our stages mostly wait on
+//! sockets but decode and encode in bursts, so each binary builds its runtime
+//! here instead, and [`cpu`] and [`cpu_here`] move those bursts off the IO workers:
+//!
+//! - `CPU_HERE_MIN_BYTES`: work on fewer bytes than this runs where it is
+//!   when handed to [`cpu_here_for`] (default 16384); one trade isn't worth a
+//!   worker hand-off.
+//! - `TOKIO_WORKER_THREADS`: IO workers (default: one per core).
+//! - `TOKIO_MAX_BLOCKING_THREADS`: cap on the blocking pool, which also runs
+//!   [`cpu`] work (default 512).
+//! - `TOKIO_THREAD_NAME`: thread name prefix (default: the binary's name).
+//! - `CPU_PIN`: cores for the threads a binary names, as `role=core` pairs
+//!   (`poll=2,writer=3`); Linux only, empty for none. A thread takes its core
+//!   as it starts, through [`pin_current`] or [`spawn_pinned`], and logs it;
+//!   [`pinned`] says how it went. The IO workers and the blocking pool are
+//!   left to the scheduler.
+//!
+//! [`run`] also ends the binary cleanly on SIGTERM or SIGINT. A stage that
+//! took [`stopping`] is told to stop and waited for, up to
+//! `SHUTDOWN_DRAIN_SECS` (default 20), so it can flush and commit what it
+//! has; any other stage is dropped where it is. Then the [`on_shutdown`]
+//! hooks run with the reason before it exits.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub worker_threads: usize,
+    pub max_blocking_threads: usize,
+    pub thread_name: String,
+    /// `CPU_PIN`'s cores for the threads [`pin_current`] and [`spawn_pinned`] name.
+    pub pin: Vec<(String, usize)>,
+}
+
+/// One named thread's pinning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pin {
+    pub thread: String,
+    pub core: usize,
+    /// Why the core couldn't be set; the thread runs unpinned.
+    pub error: Option<String>,
+}
+
+/// `CPU_PIN` as the last [`Config::build`] with one had it.
+static ROLES: Mutex<Vec<(String, usize)>> = Mutex::new(Vec::new());
+
+static PINNED: Mutex<Vec<Pin>> = Mutex::new(Vec::new());
+
+/// How the named threads were pinned, in the order they started; empty
+/// without `CPU_PIN`.
+pub fn pinned() -> Vec<Pin> {
+    PINNED.lock().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// Pin this thread to `role`'s core in `CPU_PIN`, if it has one, and log how
+/// it went. For the thread a binary's stage loop runs on; [`spawn_pinned`]
+/// starts the others.
+pub fn pin_current(role: &str) {
+    let core = ROLES.lock().unwrap_or_else(PoisonError::into_inner).iter().find(|(r, _)| r == role).map(|(_, c)| *c);
+    let Some(core) = core else { return };
+    let thread = format!("{} ({})", role, std::thread::current().name().unwrap_or("unnamed"));
+    let error = pin_current_to(core).err().map(|e| e.to_string());
+    match &error {
+        None => tracing::info!(target: "runtime", thread = %thread, core, "thread pinned"),
+        Some(e) => tracing::warn!(target: "runtime", thread = %thread, core, error = %e, "thread not pinned; CPU_PIN ignored for it"),
+    }
+    PINNED.lock().unwrap_or_else(PoisonError::into_inner).push(Pin { thread, core, error });
+}
+
+/// Run the future `f` makes on a thread of its own called `role`, with its
+/// own current-thread runtime, pinned as [`pin_current`] does as it starts.
+/// Resolves to what the future returns, or an error if the thread panicked.
+pub fn spawn_pinned<T, F, Fut>(role: &str, f: F) -> Result<impl Future<Output = Result<T>>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<T>>,
+{
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let name = role.to_string();
+    std::thread::Builder::new()
+        .name(role.to_string())
+        .spawn(move || {
+            pin_current(&name);
+            let res = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .context("building the thread's runtime")
+                .and_then(|rt| rt.block_on(f()));
+            let _ = tx.send(res);
+        })
+        .with_context(|| format!("spawning the {} thread", role))?;
+    let role = role.to_string();
+    Ok(async move { rx.await.with_context(|| format!("the {} thread panicked", role))? })
+}
+
+/// `CPU_PIN`: comma-separated `role=core` pairs, such as `poll=2,writer=3`.
+pub fn parse_pins(s: &str) -> Result<Vec<(String, usize)>> {
+    s.split(',').map(str::trim).filter(|p| !p.is_empty())
+        .map(|part| {
+            let (role, core) = part.split_once('=').with_context(|| format!("expected role=core in CPU_PIN, got {:?}", part))?;
+            let core = core.trim().parse::<usize>().with_context(|| format!("bad core {:?} in CPU_PIN", core))?;
+            anyhow::ensure!(!role.trim().is_empty(), "no role for core {} in CPU_PIN", core);
+            Ok((role.trim().to_string(), core))
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_to(core: usize) -> std::io::Result<()> {
+    // Only cores in our cpuset are listed, which also keeps `core` inside CPU_SETSIZE.
+    let id = core_affinity::get_core_ids().unwrap_or_default().into_iter().find(|c| c.id == core);
+    let Some(id) = id else {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("core {} isn't in this process's cpuset", core)));
+    };
+    match core_affinity::set_for_current(id) {
+        true => Ok(()),
+        false => Err(std::io::Error::other(format!("couldn't set affinity to core {}", core))),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_to(_core: usize) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "CPU_PIN is Linux only"))
+}
+
+impl Config {
+    /// `name` is the default thread name, usually `env!("CARGO_PKG_NAME")`.
+    pub fn from_env(name: &str) -> Result<Self> {
+        let var = |k: &str| std::env::var(k).ok().filter(|v| !v.is_empty());
+        let count = |k: &str, default: usize| -> Result<usize> {
+            match var(k) {
+                Some(v) => v.parse::<usize>().ok().filter(|n| *n > 0).with_context(|| format!("{} must be a positive integer, got {:?}", k, v)),
+                None => Ok(default),
+            }
+        };
+        let pin = parse_pins(&var("CPU_PIN").unwrap_or_default())?;
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Ok(Self {
+            worker_threads: count("TOKIO_WORKER_THREADS", cores)?,
+            max_blocking_threads: count("TOKIO_MAX_BLOCKING_THREADS", 512)?,
+            thread_name: var("TOKIO_THREAD_NAME").unwrap_or_else(|| name.to_string()),
+            pin,
+        })
+    }
+
+    pub fn build(&self) -> Result<tokio::runtime::Runtime> {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.worker_threads(self.worker_threads)
+            .max_blocking_threads(self.max_blocking_threads)
+            .thread_name(&self.thread_name)
+            .enable_all();
+        if !self.pin.is_empty() {
+            *ROLES.lock().unwrap_or_else(PoisonError::into_inner) = self.pin.clone();
+        }
+        builder.build().context("building the tokio runtime")
+    }
+}
+
+type Hook = Box<dyn FnOnce(String) -> std::pin::Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+static SHUTDOWN: Mutex<Vec<Hook>> = Mutex::new(Vec::new());
+
+/// How long each [`on_shutdown`] hook gets before the binary exits anyway.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run `hook` when [`run`]'s stage ends, with why: `signal: SIGTERM`,
+/// `signal: SIGINT`, `exited` or `error: ...`.
+pub fn on_shutdown<F, Fut>(hook: F)
+where
+    F: FnOnce(String) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    SHUTDOWN.lock().unwrap_or_else(PoisonError::into_inner).push(Box::new(move |reason| Box::pin(hook(reason))));
+}
+
+/// Whether some stage took [`stopping`]; [`supervise`] only waits for those.
+static DRAINS: AtomicBool = AtomicBool::new(false);
+
+fn stop() -> &'static tokio::sync::watch::Sender<bool> {
+    static STOP: OnceLock<tokio::sync::watch::Sender<bool>> = OnceLock::new();
+    STOP.get_or_init(|| tokio::sync::watch::channel(false).0)
+}
+
+/// Resolves once a signal asks the stage to stop. Taking it promises that the
+/// stage then drains and returns by itself, so [`supervise`] waits for that
+/// instead of dropping it.
+pub fn stopping() -> impl Future<Output = ()> + Send + 'static {
+    DRAINS.store(true, Ordering::Relaxed);
+    let mut rx = stop().subscribe();
+    async move {
+        let _ = rx.wait_for(|stopped| *stopped).await;
+    }
+}
+
+/// How long a stage that took [`stopping`] gets to drain (`SHUTDOWN_DRAIN_SECS`).
+fn drain_timeout() -> Duration {
+    Duration::from_secs(std::env::var("SHUTDOWN_DRAIN_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(20))
+}
+
+/// Wait for SIGTERM or SIGINT, returning which.
+async fn signalled() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => tokio::select! {
+                _ = term.recv() => "SIGTERM",
+                _ = tokio::signal::ctrl_c() => "SIGINT",
+            },
+            Err(_) => tokio::signal::ctrl_c().await.map_or("SIGINT", |_| "SIGINT"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "SIGINT"
+    }
+}
+
+/// Run `main` until it ends or a signal arrives, then the [`on_shutdown`]
+/// hooks. After a signal, a stage that took [`stopping`] is waited for.
+pub async fn supervise<F: Future<Output = Result<()>>>(main: F) -> Result<()> {
+    let ended = |res: &Result<()>| match res {
+        Ok(()) => "exited".to_string(),
+        Err(e) => format!("error: {:#}", e),
+    };
+    let mut main = std::pin::pin!(main);
+    let (res, reason) = tokio::select! {
+        res = &mut main => {
+            let reason = ended(&res);
+            (res, reason)
+        }
+        signal = signalled() => {
+            let reason = format!("signal: {}", signal);
+            match DRAINS.load(Ordering::Relaxed) {
+                false => {
+                    tracing::info!(target: "runtime", signal, "shutting down");
+                    (Ok(()), reason)
+                }
+                true => {
+                    let timeout = drain_timeout();
+                    tracing::info!(target: "runtime", signal, timeout_ms = timeout.as_millis() as u64, "draining before shutdown");
+                    stop().send_replace(true);
+                    match tokio::time::timeout(timeout, &mut main).await {
+                        Ok(res) => {
+                            // A stage that failed while draining is reported as that failure.
+                            let reason = if res.is_ok() { reason } else { format!("{} ({})", reason, ended(&res)) };
+                            (res, reason)
+                        }
+                        Err(_) => {
+                            tracing::warn!(target: "runtime", timeout_ms = timeout.as_millis() as u64, "stage didn't drain in time; dropping it");
+                            (Ok(()), format!("{} (drain timed out)", reason))
+                        }
+                    }
+                }
+            }
+        }
+    };
+    let hooks = std::mem::take(&mut *SHUTDOWN.lock().unwrap_or_else(PoisonError::into_inner));
+    for hook in hooks {
+        if tokio::time::timeout(HOOK_TIMEOUT, hook(reason.clone())).await.is_err() {
+            tracing::warn!(target: "runtime", timeout_ms = HOOK_TIMEOUT.as_millis() as u64, "shutdown hook timed out");
+        }
+    }
+    res
+}
+
+/// Run `main` on the runtime `TOKIO_*` describe, under [`supervise`]; for a
+/// binary's `fn main`, with its [`build_info!`](crate::build_info). A
+/// malformed `NAMESPACE` fails here too, before anything starts.
+pub fn run<F: Future<Output = Result<()>>>(build: crate::version::Info, main: F) -> Result<()> {
+    crate::namespace::init()?;
+    let build = crate::version::init(build);
+    Config::from_env(&build.service)?.build()?.block_on(supervise(main))
+}
+
+/// Run CPU-bound `f` on the blocking pool, leaving the caller's IO worker free.
+pub async fn cpu<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(f).await.context("cpu task failed")
+}
+
+/// Run CPU-bound `f` on this thread, which hands its IO work to a fresh
+/// worker meanwhile. Unlike [`cpu`], `f` may borrow, so a message needn't be
+/// copied to leave the stage. On a current-thread runtime, or none, `f` just runs.
+pub fn cpu_here<T>(f: impl FnOnce() -> T) -> T {
+    match tokio::runtime::Handle::try_current().map(|h| h.runtime_flavor()) {
+        Ok(tokio::runtime::RuntimeFlavor::MultiThread) => tokio::task::block_in_place(f),
+        _ => f(),
+    }
+}
+
+/// [`cpu_here`] for work on `bytes` bytes at least `CPU_HERE_MIN_BYTES`;
+/// smaller work just runs, as the hand-off would cost more than it saves.
+pub fn cpu_here_for<T>(bytes: usize, f: impl FnOnce() -> T) -> T {
+    static MIN: OnceLock<usize> = OnceLock::new();
+    let min = *MIN.get_or_init(|| std::env::var("CPU_HERE_MIN_BYTES").ok().and_then(|v| v.parse().ok()).unwrap_or(16 << 10));
+    match bytes >= min {
+        true => cpu_here(f),
+        false => f(),
+    }
+}
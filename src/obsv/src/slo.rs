@@ -0,0 +1,322 @@
+//! SLOs evaluated in-process from the metrics the service records itself.
+//!
+//! An SLO is either a latency objective on a histogram (`name=metric<ms@target`,
+//! a sample is bad when it's over `ms`) or a ratio of two counters
+//! (`name=bad/total@target`). Targets are percentages of good events. The
+//! error rate over a window, divided by the error budget (`1 - target`), is
+//! the burn rate: 1 spends the budget exactly over the SLO period.
+//!
+//! Alerts follow the multi-window rule from the SRE workbook: `page` when the
+//! 1h and 5m burn rates are both over 14.4 (2% of a 30-day budget in an hour),
+//! `ticket` when the 6h and 30m rates are both over 6. Each alert that fires
+//! or clears goes to the [`on_alert`] hooks, which is how `bus` publishes
+//! them.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::{Context, Result};
+use metrics::{Counter, CounterFn, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+use serde::Serialize;
+
+const BUCKET_MS: i64 = 10_000;
+const KEEP_MS: i64 = 6 * 3_600_000;
+
+/// Burn-rate windows exported as `slo_burn_rate{window}`.
+pub const WINDOWS: [(&str, i64); 4] = [("5m", 300_000), ("30m", 1_800_000), ("1h", 3_600_000), ("6h", KEEP_MS)];
+
+/// (severity, long window, short window, burn rate threshold).
+pub const RULES: [(&str, i64, i64, f64); 2] = [
+    ("page", 3_600_000, 300_000, 14.4),
+    ("ticket", KEEP_MS, 1_800_000, 6.0),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind {
+    Latency { metric: String, threshold: f64 },
+    Ratio { bad: String, total: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spec {
+    pub name: String,
+    pub kind: Kind,
+    /// Fraction of events that should be good, e.g. 0.99.
+    pub target: f64,
+}
+
+/// Comma-separated, e.g. `e2e_p99=e2e_latency_ms<250@99,loss=sink_dropped_rows_total/consumed_total@99.99`.
+pub fn parse(specs: &str) -> Result<Vec<Spec>> {
+    specs.split(',').map(str::trim).filter(|s| !s.is_empty())
+        .map(|s| {
+            let (name, rest) = s.split_once('=').with_context(|| format!("SLO {:?}: expected name=objective@target", s))?;
+            let (objective, target) = rest.rsplit_once('@').with_context(|| format!("SLO {:?}: missing @target", s))?;
+            let target: f64 = target.trim_end_matches('%').parse().with_context(|| format!("SLO {:?}: bad target", s))?;
+            anyhow::ensure!(0.0 < target && target < 100.0, "SLO {:?}: target must be a percentage below 100", s);
+            let kind = match (objective.split_once('<'), objective.split_once('/')) {
+                (Some((metric, ms)), _) => Kind::Latency {
+                    metric: metric.to_string(),
+                    threshold: ms.trim_end_matches("ms").parse().with_context(|| format!("SLO {:?}: bad threshold", s))?,
+                },
+                (None, Some((bad, total))) => Kind::Ratio { bad: bad.to_string(), total: total.to_string() },
+                _ => anyhow::bail!("SLO {:?}: expected metric<threshold or bad/total", s),
+            };
+            Ok(Spec { name: name.to_string(), kind, target: target / 100.0 })
+        })
+        .collect()
+}
+
+/// Bad and total event counts in 10s buckets over the longest window.
+#[derive(Default)]
+struct Buckets {
+    /// (bucket start ms, bad, total), oldest first.
+    v: VecDeque<(i64, u64, u64)>,
+}
+
+pub struct Slo {
+    pub spec: Spec,
+    buckets: Mutex<Buckets>,
+}
+
+impl Slo {
+    pub fn new(spec: Spec) -> Self {
+        Self { spec, buckets: Mutex::default() }
+    }
+
+    pub fn record(&self, now_ms: i64, bad: u64, total: u64) {
+        let start = now_ms.div_euclid(BUCKET_MS) * BUCKET_MS;
+        let mut b = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        match b.v.back_mut() {
+            Some(last) if last.0 >= start => (last.1, last.2) = (last.1 + bad, last.2 + total),
+            _ => b.v.push_back((start, bad, total)),
+        }
+        while b.v.front().is_some_and(|f| f.0 < now_ms - KEEP_MS) {
+            b.v.pop_front();
+        }
+    }
+
+    /// Error rate over the trailing window over the error budget; `None` without events.
+    pub fn burn_rate(&self, now_ms: i64, window_ms: i64) -> Option<f64> {
+        let b = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let (bad, total) = b.v.iter().rev()
+            .take_while(|x| x.0 > now_ms - window_ms)
+            .fold((0, 0), |(bad, total), x| (bad + x.1, total + x.2));
+        (total > 0).then(|| (bad.min(total) as f64 / total as f64) / (1.0 - self.spec.target))
+    }
+
+    /// Severities whose rule fires now.
+    pub fn alerts(&self, now_ms: i64) -> Vec<&'static str> {
+        RULES.iter()
+            .filter(|(_, long, short, over)| {
+                self.burn_rate(now_ms, *long).is_some_and(|r| r > *over) && self.burn_rate(now_ms, *short).is_some_and(|r| r > *over)
+            })
+            .map(|r| r.0)
+            .collect()
+    }
+}
+
+fn now_ms() -> i64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_millis() as i64)
+}
+
+enum Role {
+    Latency(f64),
+    Bad,
+    Total,
+}
+
+struct Tee<H> {
+    inner: H,
+    taps: Vec<(Arc<Slo>, Role)>,
+}
+
+impl CounterFn for Tee<Counter> {
+    fn increment(&self, n: u64) {
+        self.inner.increment(n);
+        for (slo, role) in &self.taps {
+            match role {
+                Role::Bad => slo.record(now_ms(), n, 0),
+                Role::Total => slo.record(now_ms(), 0, n),
+                Role::Latency(_) => {}
+            }
+        }
+    }
+
+    fn absolute(&self, n: u64) {
+        self.inner.absolute(n);
+    }
+}
+
+impl HistogramFn for Tee<Histogram> {
+    fn record(&self, v: f64) {
+        self.inner.record(v);
+        for (slo, role) in &self.taps {
+            if let Role::Latency(threshold) = role {
+                slo.record(now_ms(), (v > *threshold) as u64, 1);
+            }
+        }
+    }
+}
+
+/// Passes everything to `inner`, and feeds SLO metrics (under any labels) to their SLOs.
+pub struct SloRecorder<R> {
+    inner: R,
+    slos: Vec<Arc<Slo>>,
+}
+
+impl<R> SloRecorder<R> {
+    pub fn new(inner: R, slos: Vec<Arc<Slo>>) -> Self {
+        Self { inner, slos }
+    }
+
+    fn taps(&self, name: &str) -> Vec<(Arc<Slo>, Role)> {
+        self.slos.iter()
+            .filter_map(|s| {
+                let role = match &s.spec.kind {
+                    Kind::Latency { metric, threshold } if metric == name => Role::Latency(*threshold),
+                    Kind::Ratio { bad, .. } if bad == name => Role::Bad,
+                    Kind::Ratio { total, .. } if total == name => Role::Total,
+                    _ => return None,
+                };
+                Some((s.clone(), role))
+            })
+            .collect()
+    }
+}
+
+impl<R: Recorder> Recorder for SloRecorder<R> {
+    fn describe_counter(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.inner.describe_counter(key, unit, description)
+    }
+
+    fn describe_gauge(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.inner.describe_gauge(key, unit, description)
+    }
+
+    fn describe_histogram(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.inner.describe_histogram(key, unit, description)
+    }
+
+    fn register_counter(&self, key: &Key, metadata: &Metadata<'_>) -> Counter {
+        let inner = self.inner.register_counter(key, metadata);
+        let taps = self.taps(key.name());
+        match taps.is_empty() {
+            true => inner,
+            false => Counter::from_arc(Arc::new(Tee { inner, taps })),
+        }
+    }
+
+    fn register_gauge(&self, key: &Key, metadata: &Metadata<'_>) -> metrics::Gauge {
+        self.inner.register_gauge(key, metadata)
+    }
+
+    fn register_histogram(&self, key: &Key, metadata: &Metadata<'_>) -> Histogram {
+        let inner = self.inner.register_histogram(key, metadata);
+        let taps = self.taps(key.name());
+        match taps.is_empty() {
+            true => inner,
+            false => Histogram::from_arc(Arc::new(Tee { inner, taps })),
+        }
+    }
+}
+
+/// An alert firing or clearing, as handed to each [`on_alert`] hook.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    pub slo: String,
+    pub severity: &'static str,
+    /// `firing` or `resolved`.
+    pub state: &'static str,
+    /// The rule's burn-rate threshold.
+    pub threshold: f64,
+    pub target: f64,
+    /// The rule's long-window burn rate when it changed.
+    pub burn_rate: Option<f64>,
+    pub ts_ms: i64,
+}
+
+type AlertHook = Box<dyn Fn(&Alert) + Send>;
+
+static ALERT_HOOKS: Mutex<Vec<AlertHook>> = Mutex::new(Vec::new());
+
+/// Call `hook` with every alert as it fires and clears, from the evaluator's
+/// thread; it shouldn't block.
+pub fn on_alert(hook: impl Fn(&Alert) + Send + 'static) {
+    ALERT_HOOKS.lock().unwrap_or_else(PoisonError::into_inner).push(Box::new(hook));
+}
+
+/// Burn rates and alert state, kept between evaluations.
+pub struct Evaluator {
+    slos: Vec<Arc<Slo>>,
+    firing: Vec<(String, &'static str)>,
+}
+
+impl Evaluator {
+    pub fn new(slos: Vec<Arc<Slo>>) -> Self {
+        Self { slos, firing: Vec::new() }
+    }
+
+    /// Export burn rates and alert state as of `now`, logging each alert
+    /// that fires or clears, and return those.
+    pub fn tick(&mut self, now: i64) -> Vec<Alert> {
+        let mut changed = Vec::new();
+        for slo in &self.slos {
+            let name = slo.spec.name.clone();
+            for (window, ms) in WINDOWS {
+                if let Some(rate) = slo.burn_rate(now, ms) {
+                    metrics::gauge!("slo_burn_rate", "slo" => name.clone(), "window" => window).set(rate);
+                }
+            }
+            let now_firing = slo.alerts(now);
+            for (severity, long, _, threshold) in RULES {
+                let on = now_firing.contains(&severity);
+                metrics::gauge!("slo_alert", "slo" => name.clone(), "severity" => severity).set(on as u8 as f64);
+                let was = self.firing.iter().position(|f| f.0 == name && f.1 == severity);
+                let state = match (on, was) {
+                    (true, None) => {
+                        tracing::warn!(target="slo", slo=%name, severity, threshold, target=slo.spec.target, "SLO burn-rate alert firing");
+                        metrics::counter!("slo_alerts_total", "slo" => name.clone(), "severity" => severity).increment(1);
+                        self.firing.push((name.clone(), severity));
+                        "firing"
+                    }
+                    (false, Some(i)) => {
+                        tracing::info!(target="slo", slo=%name, severity, "SLO burn-rate alert resolved");
+                        self.firing.swap_remove(i);
+                        "resolved"
+                    }
+                    _ => continue,
+                };
+                changed.push(Alert {
+                    slo: name.clone(),
+                    severity,
+                    state,
+                    threshold,
+                    target: slo.spec.target,
+                    burn_rate: slo.burn_rate(now, long),
+                    ts_ms: now,
+                });
+            }
+        }
+        changed
+    }
+}
+
+/// Run an [`Evaluator`] every 10s, handing what changed to the [`on_alert`] hooks.
+pub fn spawn_evaluator(slos: Vec<Arc<Slo>>) {
+    std::thread::Builder::new()
+        .name("slo".into())
+        .spawn(move || {
+            let mut evaluator = Evaluator::new(slos);
+            loop {
+                let changed = evaluator.tick(now_ms());
+                if !changed.is_empty() {
+                    let hooks = ALERT_HOOKS.lock().unwrap_or_else(PoisonError::into_inner);
+                    for alert in &changed {
+                        hooks.iter().for_each(|hook| hook(alert));
+                    }
+                }
+                std::thread::sleep(std::time::Duration::from_millis(BUCKET_MS as u64));
+            }
+        })
+        .expect("spawn SLO evaluator");
+}
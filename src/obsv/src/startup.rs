@@ -0,0 +1,86 @@
+//! Checks a service runs on its dependencies before entering its main loop,
+//! so a missing broker, an unreachable database or an exchange that doesn't
+//! resolve fails at startup, by name, instead of deep in the first message.
+//!
+//! Each check is retried with the `retry` crate's jittered exponential
+//! backoff, counted under `op="startup"`: `STARTUP_RETRIES` attempts
+//! (default 10), waiting `STARTUP_BACKOFF_MS` (500) after the first failure
+//! and doubling up to `STARTUP_BACKOFF_MAX_MS` (10000). The waits don't draw
+//! on `RETRY_BUDGET`: a service that hasn't started yet puts no load on
+//! anything, and a budget spent elsewhere mustn't stop it from starting.
+
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Result;
+use metrics::counter;
+use retry::{Backoff, Policy};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retry {
+    pub attempts: u32,
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Retry {
+    pub fn from_env() -> Self {
+        let var = |k: &str, default: u64| std::env::var(k).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+        Self {
+            attempts: (var("STARTUP_RETRIES", 10) as u32).max(1),
+            initial: Duration::from_millis(var("STARTUP_BACKOFF_MS", 500)),
+            max: Duration::from_millis(var("STARTUP_BACKOFF_MAX_MS", 10_000)),
+        }
+    }
+
+    pub fn backoff(&self) -> Backoff {
+        Backoff::new(self.initial, self.max)
+    }
+
+    /// The wait after failed attempt `attempt` (1-based), before jitter.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff().step(attempt)
+    }
+
+    /// Run `check` until it succeeds or the attempts are used up, logging each
+    /// failure against `dependency` (`kafka`, `questdb`, `exchange`).
+    pub async fn wait_for<T, F, Fut>(&self, dependency: &str, mut check: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let policy = Policy::times(self.attempts.saturating_sub(1), self.backoff());
+        let mut retry = policy.start("startup");
+        let mut attempt = 1;
+        loop {
+            match check().await {
+                Ok(v) => {
+                    tracing::info!(target: "startup", dependency, attempt, "dependency ready");
+                    return Ok(v);
+                }
+                Err(e) => {
+                    counter!("startup_check_failures_total", "dependency" => dependency.to_string()).increment(1);
+                    if attempt < self.attempts {
+                        // The jittered wait, as slept; past the budget, which startup doesn't draw on.
+                        let wait = retry.next_wait();
+                        tracing::warn!(target: "startup", dependency, attempt, attempts = self.attempts, retry_in_ms = wait.as_millis() as u64, error = %format!("{:#}", e), "dependency not ready");
+                        tokio::time::sleep(wait).await;
+                        attempt += 1;
+                        continue;
+                    }
+                    tracing::error!(target: "startup", dependency, attempt, error = %format!("{:#}", e), "dependency not ready; giving up");
+                    return Err(e.context(format!("{} not ready after {} attempts", dependency, attempt)));
+                }
+            }
+        }
+    }
+}
+
+/// [`Retry::wait_for`] with [`Retry::from_env`].
+pub async fn wait_for<T, F, Fut>(dependency: &str, check: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    Retry::from_env().wait_for(dependency, check).await
+}
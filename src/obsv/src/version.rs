@@ -0,0 +1,74 @@
+//! What a binary was built from, so a mixed-version incident shows up in
+//! Prometheus: `build_info{service,version,git_sha,rustc,features}` is 1 on
+//! every service's `/metrics`, and `/version` on the same port returns it as
+//! JSON.
+//!
+//! The service, version and features come from the binary's own crate via
+//! [`build_info!`](crate::build_info), handed to [`runtime::run`](crate::runtime::run);
+//! the commit and compiler from obsv's build script. Image builds without a
+//! `.git` pass the commit in `GIT_SHA`.
+
+use std::sync::OnceLock;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Info {
+    pub service: String,
+    pub version: String,
+    pub git_sha: String,
+    pub rustc: String,
+    /// The crate's Cargo features that are on, in the order listed.
+    pub features: Vec<String>,
+}
+
+impl Info {
+    /// `features` holds the names of the ones that are on; empty names are skipped.
+    pub fn new(service: &str, version: &str, features: &[&str]) -> Self {
+        Self {
+            service: service.to_string(),
+            version: version.to_string(),
+            git_sha: env!("OBSV_GIT_SHA").to_string(),
+            rustc: env!("OBSV_RUSTC").to_string(),
+            features: features.iter().filter(|f| !f.is_empty()).map(|f| f.to_string()).collect(),
+        }
+    }
+
+    /// The `features` label: comma-separated, `none` when there are none.
+    pub fn features_label(&self) -> String {
+        match self.features.is_empty() {
+            true => "none".into(),
+            false => self.features.join(","),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// The calling crate's [`Info`]. List the crate's own Cargo features; those
+/// that are on end up in `features`.
+///
+/// ```ignore
+/// obsv::runtime::run(obsv::build_info!("chaos", "nats", "pulsar"), run())
+/// ```
+#[macro_export]
+macro_rules! build_info {
+    ($($feature:literal),* $(,)?) => {
+        $crate::version::Info::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"),
+            &[$(if cfg!(feature = $feature) { $feature } else { "" }),*])
+    };
+}
+
+static CURRENT: OnceLock<Info> = OnceLock::new();
+
+/// Record this process's [`Info`]; the first call wins.
+pub fn init(info: Info) -> &'static Info {
+    CURRENT.get_or_init(|| info)
+}
+
+/// The recorded [`Info`], or obsv's own when nothing was recorded.
+pub fn current() -> &'static Info {
+    CURRENT.get_or_init(|| Info::new("unknown", env!("CARGO_PKG_VERSION"), &[]))
+}
@@ -0,0 +1,144 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use obsv::admin::{self, REDACTED, SAMPLES_KEPT, SAMPLE_BYTES};
+use serde_json::{json, Value};
+use tracing_subscriber::layer::SubscriberExt;
+
+#[test]
+fn secrets_are_redacted_from_the_config() {
+    assert_eq!(admin::redact("BINANCE_API_SECRET", "hunter2"), REDACTED);
+    assert_eq!(admin::redact("qdb_http_token", "t"), REDACTED);
+    assert_eq!(admin::redact("SIGNING_KEYS", "k1:abc"), REDACTED);
+    assert_eq!(admin::redact("SIGNING_KEYS_FILE", "/run/secrets/keys"), "/run/secrets/keys");
+    assert_eq!(admin::redact("ENCRYPTION_KEY_ID", "k1"), "k1");
+    assert_eq!(admin::redact("KAFKA_BROKERS", "kafka:9092"), "kafka:9092");
+    assert_eq!(
+        admin::redact("SCHEMA_REGISTRY_URL", "https://svc:pw@registry:8081/path,http://other:1/"),
+        format!("https://svc:{}@registry:8081/path,http://other:1/", REDACTED)
+    );
+    assert_eq!(admin::redact("NATS_URL", "nats://tok@nats:4222"), format!("nats://{}@nats:4222", REDACTED));
+    assert_eq!(
+        admin::redact("WEBHOOK_URL", "https://hooks.example/x?token=abc&channel=ops&api_key=k1#top"),
+        format!("https://hooks.example/x?token={r}&channel=ops&api_key={r}#top", r = REDACTED)
+    );
+    assert_eq!(admin::redact("FEED_URL", "wss://feed/ws?symbol=btc&key_id=k1"), "wss://feed/ws?symbol=btc&key_id=k1");
+}
+
+#[test]
+fn views_are_run_when_asked_for() {
+    let hits = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+    let seen = hits.clone();
+    admin::view("test_views", "counter", move || json!(seen.fetch_add(1, std::sync::atomic::Ordering::Relaxed)));
+    admin::set("test_views", "fixed", json!({"a": 1}));
+    assert_eq!(admin::section("test_views").unwrap(), json!({"counter": 0, "fixed": {"a": 1}}));
+    assert_eq!(admin::section("test_views").unwrap()["counter"], 1);
+    assert!(admin::section("nothing_here").is_none());
+
+    let all = admin::snapshot();
+    for name in ["build", "config", "errors", "samples", "test_views"] {
+        assert!(all.get(name).is_some(), "{} missing from {}", name, all);
+    }
+}
+
+#[test]
+fn samples_keep_the_latest_few_per_topic() {
+    admin::sample("test.off", None, b"nobody is looking");
+    admin::sample_every(1);
+    for i in 0..SAMPLES_KEPT + 3 {
+        admin::sample("test.samples", Some("BTCUSDT"), format!("{{\"n\":{}}}", i).as_bytes());
+    }
+    admin::sample("test.big", None, &vec![b'x'; SAMPLE_BYTES * 2]);
+
+    let samples = admin::section("samples").unwrap();
+    assert!(samples.get("test.off").is_none());
+    let kept = samples["test.samples"].as_array().unwrap();
+    assert_eq!(kept.len(), SAMPLES_KEPT);
+    assert_eq!(kept[0]["payload"], "{\"n\":3}");
+    assert_eq!(kept[0]["key"], "BTCUSDT");
+    assert_eq!(samples["test.big"][0]["bytes"], SAMPLE_BYTES * 2);
+    assert_eq!(samples["test.big"][0]["payload"].as_str().unwrap().len(), SAMPLE_BYTES);
+}
+
+#[test]
+fn warnings_and_errors_are_kept_with_their_fields() {
+    let subscriber = tracing_subscriber::registry().with(admin::Errors);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(target: "test", "not kept");
+        tracing::warn!(target: "test", endpoint = "qdb-a:9009", attempts = 3u64, "QuestDB endpoint down");
+    });
+    let errors = admin::section("errors").unwrap();
+    let last = errors.as_array().unwrap().last().unwrap();
+    assert_eq!(last["level"], "WARN");
+    assert_eq!(last["target"], "test");
+    assert_eq!(last["message"], "QuestDB endpoint down");
+    assert_eq!((last["endpoint"].as_str(), last["attempts"].as_u64()), (Some("qdb-a:9009"), Some(3)));
+    assert!(errors.as_array().unwrap().iter().all(|e| e["message"] != "not kept"));
+}
+
+fn get(port: u16, path: &str) -> (String, Value) {
+    let mut conn = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    write!(conn, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path).unwrap();
+    let mut out = String::new();
+    conn.read_to_string(&mut out).unwrap();
+    let (head, body) = out.split_once("\r\n\r\n").unwrap();
+    (head.lines().next().unwrap().to_string(), serde_json::from_str(body).unwrap_or(Value::Null))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn the_router_serves_sections_as_json() {
+    admin::set("test_router", "partitions", json!([0, 1]));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { axum::serve(listener, admin::router()).await });
+
+    let (status, body) = tokio::task::spawn_blocking(move || get(port, "/admin/test_router")).await.unwrap();
+    assert!(status.ends_with("200 OK"), "{}", status);
+    assert_eq!(body, json!({"partitions": [0, 1]}));
+    let (_, all) = tokio::task::spawn_blocking(move || get(port, "/admin")).await.unwrap();
+    assert_eq!(all["test_router"]["partitions"], json!([0, 1]));
+    assert!(all["config"].is_object());
+    let (status, _) = tokio::task::spawn_blocking(move || get(port, "/admin/nothing_here")).await.unwrap();
+    assert!(status.ends_with("404 Not Found"), "{}", status);
+}
+
+#[test]
+fn logged_fields_are_redacted_like_the_config() {
+    let subscriber = tracing_subscriber::registry().with(admin::Errors);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::warn!(target: "test_redact", url = "https://svc:pw@registry:8081/", api_token = "t0k", error = "GET https://api/v1?api_key=s3cret failed", "registry call failed");
+    });
+    let errors = admin::section("errors").unwrap();
+    let last = errors.as_array().unwrap().iter().rev().find(|e| e["target"] == "test_redact").unwrap();
+    assert_eq!(last["url"], format!("https://svc:{}@registry:8081/", REDACTED));
+    assert_eq!(last["api_token"], REDACTED);
+    assert_eq!(last["error"], format!("GET https://api/v1?api_key={} failed", REDACTED));
+}
+
+#[test]
+fn only_loopback_binds_go_without_a_token() {
+    assert!(admin::loopback("127.0.0.1") && admin::loopback("::1") && admin::loopback("localhost"));
+    assert!(!admin::loopback("0.0.0.0") && !admin::loopback("10.0.0.5") && !admin::loopback("admin.internal"));
+}
+
+fn get_with(port: u16, path: &str, auth: Option<&str>) -> String {
+    let mut conn = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let auth = auth.map_or(String::new(), |a| format!("Authorization: {}\r\n", a));
+    write!(conn, "GET {} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n", path, auth).unwrap();
+    let mut out = String::new();
+    conn.read_to_string(&mut out).unwrap();
+    out.lines().next().unwrap().to_string()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_guarded_router_wants_the_bearer_token() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { axum::serve(listener, admin::guarded("s3cret".into())).await });
+
+    let status = |auth: Option<&'static str>| tokio::task::spawn_blocking(move || get_with(port, "/admin/build", auth));
+    assert!(status(None).await.unwrap().ends_with("401 Unauthorized"));
+    assert!(status(Some("Bearer wrong")).await.unwrap().ends_with("401 Unauthorized"));
+    assert!(status(Some("s3cret")).await.unwrap().ends_with("401 Unauthorized"));
+    assert!(status(Some("Bearer s3cret")).await.unwrap().ends_with("200 OK"));
+}
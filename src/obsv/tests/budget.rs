@@ -0,0 +1,21 @@
+use obsv::{Budget, Overflow};
+
+const POLICIES: &[Overflow] = &[Overflow::DropOldest, Overflow::Block];
+
+#[test]
+fn the_overflow_policy_is_the_buffers_first_unless_set() {
+    let b = Budget::from_env("unset", 100, POLICIES).unwrap();
+    assert_eq!((b.limit(), b.overflow()), (100, Overflow::DropOldest));
+    std::env::set_var("BUDGET_BLOCKING_BYTES", "10");
+    std::env::set_var("BUDGET_BLOCKING_OVERFLOW", "block");
+    let b = Budget::from_env("blocking", 100, POLICIES).unwrap();
+    assert_eq!((b.limit(), b.overflow()), (10, Overflow::Block));
+    assert!(b.over(11) && !b.over(10));
+}
+
+#[test]
+fn a_policy_the_buffer_cant_follow_is_refused() {
+    std::env::set_var("BUDGET_DERIVED_OVERFLOW", "spill");
+    let e = Budget::from_env("derived", 100, POLICIES).err().unwrap().to_string();
+    assert!(e.contains("BUDGET_DERIVED_OVERFLOW") && e.contains("drop_oldest|block"), "{}", e);
+}
@@ -0,0 +1,30 @@
+//! On its own, in its own process: it sends itself SIGTERM.
+#![cfg(unix)]
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use obsv::runtime;
+
+#[tokio::test]
+async fn a_signalled_stage_that_took_stopping_drains_before_the_hooks_run() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let (s, h) = (seen.clone(), seen.clone());
+    runtime::on_shutdown(move |reason| async move { h.lock().unwrap().push(format!("hook: {}", reason)) });
+    let stage = async move {
+        let stop = runtime::stopping();
+        stop.await;
+        // What a stage does after being told: flush, commit, return.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        s.lock().unwrap().push("flushed".to_string());
+        anyhow::Ok(())
+    };
+    let kill = async {
+        // Once supervise is listening, or the signal would end the test binary.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        std::process::Command::new("kill").args(["-TERM", &std::process::id().to_string()]).status().unwrap();
+    };
+    let (res, ()) = tokio::join!(runtime::supervise(stage), kill);
+    res.unwrap();
+    assert_eq!(*seen.lock().unwrap(), ["flushed", "hook: signal: SIGTERM"]);
+}
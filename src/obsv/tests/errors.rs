@@ -0,0 +1,9 @@
+use obsv::errors::{escalate, ErrorKind};
+
+#[test]
+fn only_fatal_errors_are_handed_back() {
+    assert!(escalate("test", ErrorKind::Transient, anyhow::anyhow!("timed out")).is_ok());
+    assert!(escalate("test", ErrorKind::Data, anyhow::anyhow!("bad json")).is_ok());
+    let e = escalate("test", ErrorKind::Fatal, anyhow::anyhow!("authorization failed")).unwrap_err();
+    assert_eq!(format!("{:#}", e), "fatal error in test: authorization failed");
+}
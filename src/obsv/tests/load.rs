@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use obsv::load::{Ewma, Load};
+
+const SEC: Duration = Duration::from_secs(1);
+
+#[test]
+fn the_first_tick_is_taken_as_is() {
+    let mut ewma = Ewma::new(Duration::from_secs(30));
+    assert_eq!((ewma.rate(), ewma.saturation()), (0.0, 0.0));
+    assert_eq!(ewma.update(500, Duration::from_millis(250), SEC), (500.0, 0.25));
+    assert_eq!(ewma.update(0, Duration::ZERO, Duration::ZERO), (500.0, 0.25));
+}
+
+#[test]
+fn a_step_is_mostly_in_after_one_time_constant() {
+    let mut ewma = Ewma::new(Duration::from_secs(10));
+    ewma.update(0, Duration::ZERO, SEC);
+    for _ in 0..10 {
+        ewma.update(1000, SEC, SEC);
+    }
+    let want = 1.0 - (-1.0f64).exp();
+    assert!((ewma.rate() / 1000.0 - want).abs() < 1e-9, "{}", ewma.rate());
+    assert!((ewma.saturation() - want).abs() < 1e-9, "{}", ewma.saturation());
+}
+
+#[test]
+fn a_late_tick_weighs_as_long_as_it_covered() {
+    let (mut ticks, mut late) = (Ewma::new(Duration::from_secs(10)), Ewma::new(Duration::from_secs(10)));
+    ticks.update(100, Duration::ZERO, SEC);
+    late.update(100, Duration::ZERO, SEC);
+    for _ in 0..5 {
+        ticks.update(0, Duration::ZERO, SEC);
+    }
+    late.update(0, Duration::ZERO, 5 * SEC);
+    assert!((ticks.rate() - late.rate()).abs() < 1e-9, "{} vs {}", ticks.rate(), late.rate());
+}
+
+#[test]
+fn busy_time_and_processed_messages_move_the_gauges() {
+    let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    let load = metrics::with_local_recorder(&recorder, || Load::new("gauged")).unwrap();
+    load.processed(50);
+    {
+        let _busy = load.busy();
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    // Past the next tick of the ticker thread, whenever it falls.
+    std::thread::sleep(Duration::from_millis(2200));
+    let page = handle.render();
+    let gauge = |name: &str| -> f64 {
+        let line = page.lines().find(|l| l.starts_with(name) && l.contains("gauged")).unwrap_or_else(|| panic!("no {} in {}", name, page));
+        line.rsplit(' ').next().unwrap().parse().unwrap()
+    };
+    assert!(gauge("stage_rate_per_sec") > 0.0, "{}", page);
+    assert!(gauge("stage_saturation") > 0.0, "{}", page);
+}
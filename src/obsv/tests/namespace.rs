@@ -0,0 +1,46 @@
+use obsv::namespace::Namespace;
+
+#[test]
+fn names_are_prefixed_per_kind() {
+    let ns = Namespace::new("staging").unwrap();
+    assert_eq!(ns.name(), Some("staging"));
+    assert_eq!(ns.topic("ticks.norm"), "staging.ticks.norm");
+    assert_eq!(ns.group("consumer-stage"), "staging.consumer-stage");
+    assert_eq!(ns.table("trades_1m"), "staging_trades_1m");
+}
+
+#[test]
+fn without_a_namespace_names_are_unchanged() {
+    for ns in [Namespace::default(), Namespace::new("").unwrap(), Namespace::new("  ").unwrap()] {
+        assert_eq!(ns.name(), None);
+        assert_eq!(ns.topic("ticks.norm"), "ticks.norm");
+        assert_eq!(ns.table("trades"), "trades");
+        assert!(ns.owns("anything"));
+    }
+}
+
+#[test]
+fn empty_names_stay_off() {
+    let ns = Namespace::new("prod").unwrap();
+    assert_eq!(ns.topic(""), "");
+    assert_eq!(ns.table(""), "");
+}
+
+#[test]
+fn owns_only_its_own_prefix() {
+    let ns = Namespace::new("prod").unwrap();
+    assert!(ns.owns("prod.ticks.norm"));
+    assert!(!ns.owns("production.ticks.norm"));
+    assert!(!ns.owns("ticks.norm"));
+    assert!(!ns.owns("prod"));
+}
+
+#[test]
+fn names_that_would_break_a_topic_or_table_are_rejected() {
+    for bad in ["prod.eu", "a b", "x/y", "é", "client-7", "Prod", "7eu"] {
+        assert!(Namespace::new(bad).is_err(), "{:?}", bad);
+    }
+    for good in ["client7_eu", "_scratch", "prod"] {
+        assert!(Namespace::new(good).is_ok(), "{:?}", good);
+    }
+}
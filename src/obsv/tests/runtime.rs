@@ -0,0 +1,87 @@
+use obsv::runtime::{self, parse_pins, Config};
+
+#[test]
+fn the_runtime_is_built_as_configured_and_runs_cpu_work_off_its_workers() {
+    let config = Config { worker_threads: 2, max_blocking_threads: 4, thread_name: "consumer".into(), pin: Vec::new() };
+    let rt = config.build().unwrap();
+    let (worker, cpu) = rt.block_on(async {
+        let worker = tokio::spawn(async { std::thread::current().name().map(String::from) }).await.unwrap();
+        let cpu = runtime::cpu(|| std::thread::current().id()).await.unwrap();
+        (worker, cpu)
+    });
+    assert_eq!(worker.as_deref(), Some("consumer"));
+    assert_ne!(cpu, std::thread::current().id());
+    assert_eq!(rt.metrics().num_workers(), 2);
+}
+
+#[tokio::test]
+async fn a_panicking_cpu_task_is_an_error() {
+    let err = runtime::cpu(|| panic!("bad batch")).await.unwrap_err();
+    assert!(format!("{:#}", err).contains("cpu task failed"));
+}
+
+#[test]
+fn cpu_here_borrows_and_runs_with_or_without_a_multi_thread_runtime() {
+    let payload = String::from("{\"p\":1}");
+    assert_eq!(runtime::cpu_here(|| payload.len()), 7);
+    let rt = Config { worker_threads: 1, max_blocking_threads: 2, thread_name: "here".into(), pin: Vec::new() }.build().unwrap();
+    let (len, served) = rt.block_on(async {
+        // The worker's other tasks keep running while this one holds its thread.
+        let other = tokio::spawn(async { 1 });
+        let len = runtime::cpu_here(|| payload.len());
+        // Under CPU_HERE_MIN_BYTES it runs in place, over it as cpu_here does.
+        assert_eq!((runtime::cpu_here_for(7, || payload.len()), runtime::cpu_here_for(1 << 20, || payload.len())), (7, 7));
+        (len, other.await.unwrap())
+    });
+    assert_eq!((len, served), (7, 1));
+    let current = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    assert_eq!(current.block_on(async { runtime::cpu_here(|| payload.len()) }), 7);
+}
+
+#[test]
+fn cpu_pin_takes_a_core_per_role() {
+    assert_eq!(parse_pins("poll=2, writer=3").unwrap(), [("poll".to_string(), 2), ("writer".to_string(), 3)]);
+    assert!(parse_pins("").unwrap().is_empty());
+    assert!(parse_pins("2-4").is_err());
+    assert!(parse_pins("poll=a").is_err());
+    assert!(parse_pins("=2").is_err());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn only_the_named_threads_are_pinned_and_a_bad_core_is_reported() {
+    let pin = vec![("poll".to_string(), 0), ("writer".to_string(), 100_000)];
+    let config = Config { worker_threads: 2, max_blocking_threads: 2, thread_name: "pinned".into(), pin };
+    let rt = config.build().unwrap();
+    let writer = rt.block_on(async {
+        runtime::cpu(|| ()).await.unwrap();
+        tokio::spawn(async {}).await.unwrap();
+        runtime::pin_current("poll");
+        // Pinned, and in `pinned`, before the future starts.
+        runtime::spawn_pinned("writer", || async { Ok((std::thread::current().name().map(String::from), runtime::pinned().len())) }).unwrap().await.unwrap()
+    });
+    assert_eq!(writer, (Some("writer".to_string()), 2));
+    let pins = runtime::pinned();
+    assert_eq!(pins.len(), 2, "only the named threads: {:?}", pins);
+    assert_eq!((pins[0].thread.starts_with("poll"), pins[0].core, pins[0].error.is_none()), (true, 0, true));
+    assert_eq!((pins[1].thread.as_str(), pins[1].core, pins[1].error.is_some()), ("writer (writer)", 100_000, true));
+    // A role CPU_PIN doesn't list runs where the scheduler puts it.
+    runtime::pin_current("other");
+    assert_eq!(runtime::pinned().len(), 2);
+}
+
+#[tokio::test]
+async fn a_pinned_thread_that_panics_is_an_error() {
+    let err = runtime::spawn_pinned::<(), _, _>("doomed", || async { panic!("bad book") }).unwrap().await.unwrap_err();
+    assert!(format!("{:#}", err).contains("the doomed thread panicked"), "{:#}", err);
+}
+
+#[tokio::test]
+async fn shutdown_hooks_hear_why_the_stage_ended() {
+    let heard = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let h = heard.clone();
+    runtime::on_shutdown(move |reason| async move { h.lock().unwrap().push(reason) });
+    let res = runtime::supervise(async { anyhow::bail!("broker gone") }).await;
+    assert!(res.is_err());
+    assert_eq!(*heard.lock().unwrap(), ["error: broker gone"]);
+}
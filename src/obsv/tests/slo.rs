@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use obsv::slo::{parse, Evaluator, Kind, Slo};
+
+#[test]
+fn specs_parse_both_kinds() {
+    let specs = parse("e2e_p99=e2e_latency_ms<250ms@99, loss=sink_dropped_rows_total/consumed_total@99.99%").unwrap();
+    assert_eq!(specs[0].kind, Kind::Latency { metric: "e2e_latency_ms".into(), threshold: 250.0 });
+    assert_eq!(specs[0].target, 0.99);
+    assert_eq!(specs[1].kind, Kind::Ratio { bad: "sink_dropped_rows_total".into(), total: "consumed_total".into() });
+    assert!(parse("x=e2e_latency_ms<250").is_err());
+    assert!(parse("x=e2e_latency_ms<250@100").is_err());
+    assert!(parse("x=e2e_latency_ms@99").is_err());
+}
+
+#[test]
+fn burn_rates_and_multiwindow_alerts() {
+    let slo = Slo::new(parse("e2e=e2e_latency_ms<250@99").unwrap().remove(0));
+    let hour = 3_600_000;
+    assert_eq!(slo.burn_rate(hour, hour), None);
+    // A clean hour, then 20% of samples bad over the last 5 minutes.
+    for s in 0..360 {
+        slo.record(s * 10_000, 0, 100);
+    }
+    for s in 330..360 {
+        slo.record(s * 10_000, 20, 0);
+    }
+    let now = 3_599_999;
+    assert!((slo.burn_rate(now, 300_000).unwrap() - 20.0).abs() < 1e-9);
+    assert!((slo.burn_rate(now, hour).unwrap() - 600.0 / 36_000.0 / 0.01).abs() < 1e-9);
+    // Fast window is hot but the hour isn't past 14.4 yet.
+    assert!(slo.alerts(now).is_empty());
+
+    for s in 360..420 {
+        slo.record(s * 10_000, 100, 100);
+    }
+    assert_eq!(slo.alerts(4_199_999), ["page", "ticket"]);
+    // Recovered: the 5m window clears the page though the hour is still hot; the 30m one keeps the ticket.
+    for s in 420..450 {
+        slo.record(s * 10_000, 0, 100);
+    }
+    assert!(slo.burn_rate(4_499_999, hour).unwrap() > 14.4);
+    assert_eq!(slo.alerts(4_499_999), ["ticket"]);
+}
+
+#[test]
+fn the_evaluator_reports_each_alert_once_as_it_fires_and_clears() {
+    let slo = Arc::new(Slo::new(parse("e2e=e2e_latency_ms<250@99").unwrap().remove(0)));
+    let mut evaluator = Evaluator::new(vec![slo.clone()]);
+    for s in 0..360 {
+        slo.record(s * 10_000, 100, 100);
+    }
+    let fired = evaluator.tick(3_599_999);
+    assert_eq!(fired.iter().map(|a| (a.severity, a.state)).collect::<Vec<_>>(), [("page", "firing"), ("ticket", "firing")]);
+    assert_eq!((fired[0].slo.as_str(), fired[0].threshold), ("e2e", 14.4));
+    assert!((fired[0].burn_rate.unwrap() - 100.0).abs() < 1e-9);
+    assert!(evaluator.tick(3_599_999).is_empty());
+
+    // The 5m window clears the page; the 30m one keeps the ticket.
+    for s in 360..390 {
+        slo.record(s * 10_000, 0, 100);
+    }
+    let cleared = evaluator.tick(3_899_999);
+    assert_eq!(cleared.iter().map(|a| (a.severity, a.state)).collect::<Vec<_>>(), [("page", "resolved")]);
+    assert_eq!(cleared[0].ts_ms, 3_899_999);
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use obsv::startup::Retry;
+
+fn retry(attempts: u32) -> Retry {
+    Retry { attempts, initial: Duration::from_millis(1), max: Duration::from_millis(4) }
+}
+
+#[test]
+fn backoff_doubles_up_to_the_cap() {
+    let r = Retry { attempts: 10, initial: Duration::from_millis(500), max: Duration::from_secs(10) };
+    let delays: Vec<u128> = (1..=7).map(|a| r.delay(a).as_millis()).collect();
+    assert_eq!(delays, [500, 1000, 2000, 4000, 8000, 10_000, 10_000]);
+    assert_eq!(r.delay(100), Duration::from_secs(10));
+}
+
+#[tokio::test]
+async fn a_check_that_recovers_is_retried() {
+    let mut calls = 0;
+    let v = retry(5)
+        .wait_for("kafka", || {
+            calls += 1;
+            let n = calls;
+            async move { if n < 3 { anyhow::bail!("not yet") } else { Ok(n) } }
+        })
+        .await
+        .unwrap();
+    assert_eq!(v, 3);
+}
+
+#[tokio::test]
+async fn a_check_that_never_passes_names_its_dependency() {
+    let mut calls = 0;
+    let e = retry(3)
+        .wait_for::<(), _, _>("questdb", || {
+            calls += 1;
+            async { anyhow::bail!("connection refused") }
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(calls, 3);
+    let msg = format!("{:#}", e);
+    assert!(msg.contains("questdb not ready after 3 attempts") && msg.contains("connection refused"), "{}", msg);
+}
@@ -0,0 +1,22 @@
+//! On its own, in its own process: `RETRY_BUDGET` is read once per process.
+
+use std::time::Duration;
+
+use obsv::startup::Retry;
+
+#[tokio::test]
+async fn startup_retries_dont_draw_on_the_retry_budget() {
+    // One retry an hour: were startup on it, the second failure would give up.
+    std::env::set_var("RETRY_BUDGET", "1/3600");
+    assert!(retry::Budget::global().unwrap().try_take());
+    let mut calls = 0;
+    let v = Retry { attempts: 5, initial: Duration::from_millis(1), max: Duration::from_millis(4) }
+        .wait_for("kafka", || {
+            calls += 1;
+            let n = calls;
+            async move { if n < 4 { anyhow::bail!("not yet") } else { Ok(n) } }
+        })
+        .await
+        .unwrap();
+    assert_eq!(v, 4);
+}
@@ -0,0 +1,46 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use obsv::version::{self, Info};
+
+#[test]
+fn features_that_are_off_are_left_out() {
+    let info = Info::new("consumer", "0.1.0", &["chaos", "", "duckdb"]);
+    assert_eq!(info.features, ["chaos", "duckdb"]);
+    assert_eq!(info.features_label(), "chaos,duckdb");
+    assert_eq!(Info::new("consumer", "0.1.0", &[]).features_label(), "none");
+    assert!(!info.git_sha.is_empty() && !info.rustc.is_empty());
+}
+
+#[test]
+fn the_macro_describes_the_calling_crate() {
+    let info = obsv::build_info!();
+    assert_eq!((info.service.as_str(), info.version.as_str()), ("obsv", env!("CARGO_PKG_VERSION")));
+    assert!(info.features.is_empty());
+}
+
+fn get(port: u16, path: &str) -> String {
+    let mut conn = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    write!(conn, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path).unwrap();
+    let mut out = String::new();
+    conn.read_to_string(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn the_metrics_port_serves_the_version_and_build_info() {
+    version::init(Info::new("producer", "1.2.3", &["chaos"]));
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    obsv::init_metrics(port).unwrap();
+
+    let body = get(port, "/version");
+    let json: serde_json::Value = serde_json::from_str(body.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+    assert_eq!(json["service"], "producer");
+    assert_eq!(json["version"], "1.2.3");
+    assert_eq!(json["features"], serde_json::json!(["chaos"]));
+
+    let metrics = get(port, "/metrics");
+    let line = metrics.lines().find(|l| l.starts_with("build_info{")).unwrap_or_else(|| panic!("{}", metrics));
+    assert!(line.contains(r#"service="producer""#) && line.contains(r#"version="1.2.3""#) && line.contains(r#"features="chaos""#), "{}", line);
+    assert!(line.ends_with(" 1"), "{}", line);
+}
@@ -0,0 +1,216 @@
+//! `export`: stored trades or bars for a time range, written from QuestDB to
+//! local Parquet or CSV files for people who'd rather not query the database.
+//!
+//! One file per symbol and UTC day, read in pages of `--page` rows. Each file
+//! is written as `.tmp` and renamed once complete. `manifest.json` is written
+//! last, listing every file with its row count, size and time span, so a
+//! directory with a manifest is a finished export.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use consumer::export::{csv_header, csv_line, Dataset, Pager, ParquetExport, Range};
+use consumer::schema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const DAY_MS: i64 = 86_400_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Parquet,
+    Csv,
+}
+
+impl Format {
+    fn ext(&self) -> &'static str {
+        match self {
+            Format::Parquet => "parquet",
+            Format::Csv => "csv",
+        }
+    }
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct ExportArgs {
+    /// Symbols to export; `BTC-USDT` and `btc/usdt` mean `BTCUSDT`.
+    #[arg(long, required = true, value_delimiter = ',')]
+    pub symbol: Vec<String>,
+    /// Start of the range: RFC 3339, or a date for its UTC midnight.
+    #[arg(long, value_parser = parse_time)]
+    pub from: DateTime<Utc>,
+    /// End of the range, exclusive; default now.
+    #[arg(long, value_parser = parse_time)]
+    pub to: Option<DateTime<Utc>>,
+    #[arg(long, value_enum, default_value = "parquet")]
+    pub format: Format,
+    /// `trades` or `bars`.
+    #[arg(long, default_value = "trades", value_parser = Dataset::parse)]
+    pub dataset: Dataset,
+    /// Table to read (default: `trades` for trades, `trades_1m` for bars).
+    #[arg(long)]
+    pub table: Option<String>,
+    #[arg(long, default_value = "export")]
+    pub out: PathBuf,
+    /// Rows per QuestDB query (and Parquet row group).
+    #[arg(long, default_value_t = 100_000)]
+    pub page: usize,
+    #[arg(long, env = "QDB_HTTP_URL", default_value = "http://localhost:9000")]
+    pub qdb_http: String,
+}
+
+/// `2024-06-01T12:00:00Z`, or `2024-06-01` for its UTC midnight.
+pub fn parse_time(s: &str) -> Result<DateTime<Utc>> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.with_timezone(&Utc));
+    }
+    let day = NaiveDate::parse_from_str(s, "%Y-%m-%d").with_context(|| format!("{:?} is neither RFC 3339 nor YYYY-MM-DD", s))?;
+    Ok(Utc.from_utc_datetime(&day.and_hms_opt(0, 0, 0).unwrap()))
+}
+
+/// `BTC-USDT`, `btc/usdt` and `btc_usdt` as the stored `BTCUSDT`.
+pub fn normalize_symbol(s: &str) -> String {
+    s.chars().filter(|c| !matches!(c, '-' | '/' | '_')).collect::<String>().to_ascii_uppercase()
+}
+
+/// `[from_ms, to_ms)` split at UTC midnights, as `(day, from, to)`.
+pub fn days(from_ms: i64, to_ms: i64) -> Vec<(NaiveDate, i64, i64)> {
+    let mut out = Vec::new();
+    let mut at = from_ms;
+    while at < to_ms {
+        let end = ((at.div_euclid(DAY_MS) + 1) * DAY_MS).min(to_ms);
+        if let Some(t) = Utc.timestamp_millis_opt(at).single() {
+            out.push((t.date_naive(), at, end));
+        }
+        at = end;
+    }
+    out
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedFile {
+    /// Relative to the manifest.
+    pub path: String,
+    pub symbol: String,
+    pub day: NaiveDate,
+    pub rows: usize,
+    pub bytes: u64,
+    /// Designated timestamps of the first and last rows.
+    pub first: String,
+    pub last: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub dataset: String,
+    pub table: String,
+    pub format: Format,
+    pub symbols: Vec<String>,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub columns: Vec<String>,
+    /// One per symbol and day with data; days without rows have no file.
+    pub files: Vec<ExportedFile>,
+}
+
+enum Out {
+    Parquet(ParquetExport<BufWriter<File>>),
+    Csv(BufWriter<File>),
+}
+
+impl Out {
+    fn create(path: &Path, format: Format, dataset: Dataset) -> Result<Self> {
+        let file = BufWriter::new(File::create(path).with_context(|| format!("creating {}", path.display()))?);
+        Ok(match format {
+            Format::Parquet => Out::Parquet(ParquetExport::new(file, dataset)?),
+            Format::Csv => {
+                let mut file = file;
+                file.write_all(csv_header(dataset).as_bytes())?;
+                Out::Csv(file)
+            }
+        })
+    }
+
+    fn write(&mut self, rows: &[Vec<Value>]) -> Result<()> {
+        match self {
+            Out::Parquet(p) => p.write(rows),
+            Out::Csv(f) => rows.iter().try_for_each(|r| f.write_all(csv_line(r).as_bytes()).map_err(Into::into)),
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        let mut file = match self {
+            Out::Parquet(p) => p.finish()?,
+            Out::Csv(f) => f,
+        };
+        file.flush()?;
+        Ok(())
+    }
+}
+
+/// One symbol-day into `args.out`; `None` if it had no rows.
+async fn export_day(client: &schema::Client, range: &Range, day: NaiveDate, args: &ExportArgs) -> Result<Option<ExportedFile>> {
+    let symbol = &range.symbols[0];
+    let name = format!("{}-{}-{}.{}", symbol, range.dataset.name(), day, args.format.ext());
+    let (path, tmp) = (args.out.join(&name), args.out.join(format!("{}.tmp", name)));
+    let mut out: Option<Out> = None;
+    let (mut pager, mut count, mut first, mut last) = (Pager::new(range.clone(), args.page), 0, String::new(), String::new());
+    while let Some(sql) = pager.sql() {
+        let rows = pager.take(client.query(&sql).await?)?;
+        if let (Some(a), Some(b)) = (rows.first(), rows.last()) {
+            if first.is_empty() {
+                first = a[0].as_str().unwrap_or_default().to_string();
+            }
+            last = b[0].as_str().unwrap_or_default().to_string();
+            let file = match &mut out {
+                Some(file) => file,
+                none => none.insert(Out::create(&tmp, args.format, range.dataset)?),
+            };
+            file.write(&rows)?;
+        }
+        count += rows.len();
+    }
+    let Some(out) = out else { return Ok(None) };
+    out.finish()?;
+    std::fs::rename(&tmp, &path)?;
+    let bytes = std::fs::metadata(&path)?.len();
+    tracing::info!(target: "pipeline", file = %path.display(), rows = count, bytes, "exported");
+    Ok(Some(ExportedFile { path: name, symbol: symbol.clone(), day, rows: count, bytes, first, last }))
+}
+
+pub async fn run(args: &ExportArgs) -> Result<Manifest> {
+    let to = args.to.unwrap_or_else(Utc::now);
+    anyhow::ensure!(args.from < to, "--from must be before --to");
+    anyhow::ensure!(args.page > 0, "--page must be positive");
+    std::fs::create_dir_all(&args.out).with_context(|| format!("creating {}", args.out.display()))?;
+    let table = args.table.as_deref().map_or_else(|| args.dataset.default_table(), obsv::namespace::table);
+    let symbols: Vec<String> = args.symbol.iter().map(|s| normalize_symbol(s)).collect();
+    let client = schema::Client::authed(&args.qdb_http).await?;
+
+    let mut files = Vec::new();
+    for symbol in &symbols {
+        for (day, from_ms, to_ms) in days(args.from.timestamp_millis(), to.timestamp_millis()) {
+            let range = Range { dataset: args.dataset, table: table.clone(), symbols: vec![symbol.clone()], from_ms, to_ms };
+            files.extend(export_day(&client, &range, day, args).await.with_context(|| format!("exporting {} on {}", symbol, day))?);
+        }
+    }
+    let manifest = Manifest {
+        dataset: args.dataset.name().to_string(),
+        table,
+        format: args.format,
+        symbols,
+        from: args.from,
+        to,
+        created_at: Utc::now(),
+        columns: args.dataset.column_names().into_iter().map(String::from).collect(),
+        files,
+    };
+    let path = args.out.join("manifest.json");
+    std::fs::write(&path, serde_json::to_vec_pretty(&manifest)?).with_context(|| format!("writing {}", path.display()))?;
+    Ok(manifest)
+}
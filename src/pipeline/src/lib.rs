@@ -0,0 +1,6 @@
+//! Operator tools for a running pipeline, one subcommand each.
+
+pub mod export;
+pub mod offsets;
+pub mod peek;
+pub mod smoke;
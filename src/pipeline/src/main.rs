@@ -0,0 +1,61 @@
+use anyhow::Result;
+use bus::BusConfig;
+use clap::{Parser, Subcommand};
+use obsv::init_tracing;
+use pipeline::{export, offsets, peek, smoke};
+
+#[derive(Parser)]
+#[command(about = "Inspect and check a running pipeline")]
+struct Cli {
+    #[command(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Subcommand)]
+enum Cmd {
+    /// Tail a topic, decoding payloads and showing headers.
+    Peek(peek::PeekArgs),
+    /// Send a synthetic trade through and time each stage; exits non-zero if it doesn't arrive.
+    Smoke(smoke::SmokeArgs),
+    /// Export, reset or import a consumer group's offsets.
+    #[command(subcommand)]
+    Offsets(offsets::OffsetsCmd),
+    /// Write stored trades or bars for a time range to local Parquet or CSV files.
+    Export(export::ExportArgs),
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!(), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    let cli = Cli::parse();
+    let bus = BusConfig::from_env()?;
+    // Export reads QuestDB only.
+    if !matches!(cli.cmd, Cmd::Export(_)) {
+        bus::audit::start(&bus).await;
+    }
+
+    match cli.cmd {
+        Cmd::Peek(args) => {
+            let n = peek::run(&bus, &args).await?;
+            tracing::debug!(target: "pipeline", messages = n, "peek done");
+        }
+        Cmd::Smoke(args) => {
+            let report = smoke::run(&bus, &args).await?;
+            match args.json {
+                true => println!("{}", serde_json::to_string(&report)?),
+                false => report.print(),
+            }
+            anyhow::ensure!(report.failure.is_none(), "smoke test failed");
+        }
+        Cmd::Offsets(cmd) => offsets::run(&bus, &cmd)?,
+        Cmd::Export(args) => {
+            let manifest = export::run(&args).await?;
+            let rows: usize = manifest.files.iter().map(|f| f.rows).sum();
+            println!("{} files, {} rows, manifest at {}", manifest.files.len(), rows, args.out.join("manifest.json").display());
+        }
+    }
+    Ok(())
+}
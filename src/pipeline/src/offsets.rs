@@ -0,0 +1,225 @@
+//! `offsets`: a consumer group's committed offsets, exported to a file,
+//! reset to a point in time or the ends of a topic, or imported back.
+//!
+//! Kafka only accepts commits for a group with no live members, so stop the
+//! stage first. Every change prints a before/after plan and needs `--apply`.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bus::{BusConfig, Transport};
+use chrono::{DateTime, Utc};
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer};
+use rdkafka::{Offset, TopicPartitionList};
+use serde::{Deserialize, Serialize};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, clap::Subcommand)]
+pub enum OffsetsCmd {
+    /// Write a group's committed offsets as JSON.
+    Export {
+        #[arg(long)]
+        group: String,
+        /// Topics to include (default: every topic the group has commits on).
+        #[arg(long, value_delimiter = ',')]
+        topics: Vec<String>,
+        /// Output file (`-` for stdout).
+        #[arg(long, default_value = "-")]
+        out: PathBuf,
+    },
+    /// Move a group to the earliest or latest offset, a time, an offset, or by a shift.
+    Reset {
+        #[arg(long)]
+        group: String,
+        #[arg(long)]
+        topic: String,
+        /// Only this partition (default: all).
+        #[arg(long)]
+        partition: Option<i32>,
+        #[command(flatten)]
+        to: ResetTo,
+        /// Commit the plan; without it, only print it.
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Commit offsets from an exported file.
+    Import {
+        #[arg(long)]
+        file: PathBuf,
+        /// Commit them for this group instead of the file's.
+        #[arg(long)]
+        group: Option<String>,
+        #[arg(long)]
+        apply: bool,
+    },
+}
+
+#[derive(Debug, Clone, clap::Args)]
+#[group(required = true, multiple = false)]
+pub struct ResetTo {
+    #[arg(long)]
+    pub to_earliest: bool,
+    #[arg(long)]
+    pub to_latest: bool,
+    /// The first message at or after this time (RFC 3339).
+    #[arg(long)]
+    pub to_time: Option<DateTime<Utc>>,
+    #[arg(long)]
+    pub to_offset: Option<i64>,
+    /// Move by this many messages; negative to reprocess.
+    #[arg(long, allow_hyphen_values = true)]
+    pub shift_by: Option<i64>,
+}
+
+/// The export file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exported {
+    pub group: String,
+    pub exported_at: DateTime<Utc>,
+    /// topic -> partition -> committed offset.
+    pub offsets: BTreeMap<String, BTreeMap<i32, i64>>,
+}
+
+/// Where one partition goes, from its committed offset (if any) and watermarks.
+/// `at_time` is the broker's answer for `--to-time`: `None` past the last message.
+pub fn target(to: &ResetTo, committed: Option<i64>, low: i64, high: i64, at_time: Option<i64>) -> i64 {
+    let raw = if to.to_earliest {
+        low
+    } else if to.to_latest {
+        high
+    } else if to.to_time.is_some() {
+        at_time.unwrap_or(high)
+    } else if let Some(o) = to.to_offset {
+        o
+    } else {
+        committed.unwrap_or(low) + to.shift_by.unwrap_or(0)
+    };
+    raw.clamp(low, high)
+}
+
+fn admin(bus: &BusConfig, group: &str) -> Result<BaseConsumer> {
+    anyhow::ensure!(bus.transport == Transport::Kafka, "offsets are Kafka consumer-group offsets; TRANSPORT must be kafka");
+    Ok(bus.kafka_config().set("group.id", group).set("enable.auto.commit", "false").create()?)
+}
+
+/// Commits from outside a live group are rejected or overwritten; say so up front.
+fn ensure_inactive(c: &BaseConsumer, group: &str) -> Result<()> {
+    let list = c.fetch_group_list(Some(group), TIMEOUT)?;
+    if let Some(g) = list.groups().iter().find(|g| g.name() == group) {
+        anyhow::ensure!(
+            g.members().is_empty(),
+            "group {} has {} live member(s) (state {}); stop them before changing its offsets",
+            group, g.members().len(), g.state()
+        );
+    }
+    Ok(())
+}
+
+fn partitions(c: &BaseConsumer, topics: &[String]) -> Result<TopicPartitionList> {
+    let md = c.fetch_metadata(None, TIMEOUT)?;
+    let mut tpl = TopicPartitionList::new();
+    for t in md.topics() {
+        let wanted = match topics.is_empty() {
+            true => !t.name().starts_with("__"),
+            false => topics.iter().any(|w| w == t.name()),
+        };
+        for p in t.partitions().iter().filter(|_| wanted) {
+            tpl.add_partition(t.name(), p.id());
+        }
+    }
+    Ok(tpl)
+}
+
+fn committed(c: &BaseConsumer, topics: &[String]) -> Result<BTreeMap<String, BTreeMap<i32, i64>>> {
+    let mut out: BTreeMap<String, BTreeMap<i32, i64>> = BTreeMap::new();
+    for e in c.committed_offsets(partitions(c, topics)?, TIMEOUT)?.elements() {
+        if let Offset::Offset(o) = e.offset() {
+            out.entry(e.topic().to_string()).or_default().insert(e.partition(), o);
+        }
+    }
+    Ok(out)
+}
+
+/// Print the plan and, with `apply`, commit it.
+fn commit(c: &BaseConsumer, group: &str, plan: &[(String, i32, Option<i64>, i64)], apply: bool) -> Result<()> {
+    println!("{:<24} {:>9} {:>14} {:>14}", "topic", "partition", "committed", "new");
+    let mut tpl = TopicPartitionList::new();
+    for (topic, p, before, after) in plan {
+        println!("{:<24} {:>9} {:>14} {:>14}", topic, p, before.map_or("-".into(), |o| o.to_string()), after);
+        tpl.add_partition_offset(topic, *p, Offset::Offset(*after))?;
+    }
+    if !apply {
+        println!("dry run: re-run with --apply to commit for group {}", group);
+        return Ok(());
+    }
+    ensure_inactive(c, group)?;
+    c.commit(&tpl, CommitMode::Sync)?;
+    println!("committed {} partition(s) for group {}", plan.len(), group);
+    Ok(())
+}
+
+pub fn run(bus: &BusConfig, cmd: &OffsetsCmd) -> Result<()> {
+    match cmd {
+        OffsetsCmd::Export { group, topics, out } => {
+            let (group, topics) = (&bus.group(group), topics.iter().map(|t| bus.topic(t)).collect::<Vec<_>>());
+            let c = admin(bus, group)?;
+            let offsets = committed(&c, &topics)?;
+            anyhow::ensure!(!offsets.is_empty(), "group {} has no committed offsets on those topics", group);
+            let body = serde_json::to_string_pretty(&Exported { group: group.clone(), exported_at: Utc::now(), offsets })?;
+            match out.as_os_str() == "-" {
+                true => println!("{}", body),
+                false => std::fs::write(out, body + "\n").with_context(|| format!("writing {}", out.display()))?,
+            }
+        }
+        OffsetsCmd::Reset { group, topic, partition, to, apply } => {
+            let (group, topic) = (&bus.group(group), &bus.topic(topic));
+            let c = admin(bus, group)?;
+            let current = committed(&c, std::slice::from_ref(topic))?.remove(topic).unwrap_or_default();
+            let all = partitions(&c, std::slice::from_ref(topic))?;
+            anyhow::ensure!(all.count() > 0, "no topic {}", topic);
+            let mut plan = Vec::new();
+            for e in all.elements().iter().filter(|e| partition.is_none_or(|p| p == e.partition())) {
+                let (low, high) = c.fetch_watermarks(topic, e.partition(), TIMEOUT)?;
+                let at_time = match to.to_time {
+                    Some(t) => {
+                        let mut q = TopicPartitionList::new();
+                        q.add_partition_offset(topic, e.partition(), Offset::Offset(t.timestamp_millis()))?;
+                        let r = c.offsets_for_times(q, TIMEOUT)?;
+                        r.find_partition(topic, e.partition()).and_then(|p| match p.offset() {
+                            Offset::Offset(o) => Some(o),
+                            _ => None,
+                        })
+                    }
+                    None => None,
+                };
+                let before = current.get(&e.partition()).copied();
+                plan.push((topic.clone(), e.partition(), before, target(to, before, low, high, at_time)));
+            }
+            commit(&c, group, &plan, *apply)?;
+        }
+        OffsetsCmd::Import { file, group, apply } => {
+            let body = std::fs::read_to_string(file).with_context(|| format!("reading {}", file.display()))?;
+            let exported: Exported = serde_json::from_str(&body).with_context(|| format!("parsing {}", file.display()))?;
+            // The file's names are as exported, namespace included.
+            let group = group.as_deref().map_or_else(|| exported.group.clone(), |g| bus.group(g));
+            let c = admin(bus, &group)?;
+            let topics: Vec<String> = exported.offsets.keys().cloned().collect();
+            let current = committed(&c, &topics)?;
+            let mut plan = Vec::new();
+            for (topic, parts) in &exported.offsets {
+                for (&p, &o) in parts {
+                    let (low, high) = c.fetch_watermarks(topic, p, TIMEOUT)?;
+                    if o < low || o > high {
+                        tracing::warn!(target: "pipeline", topic = %topic, partition = p, offset = o, low, high, "imported offset outside the retained range; clamped");
+                    }
+                    plan.push((topic.clone(), p, current.get(topic).and_then(|m| m.get(&p)).copied(), o.clamp(low, high)));
+                }
+            }
+            commit(&c, &group, &plan, *apply)?;
+        }
+    }
+    Ok(())
+}
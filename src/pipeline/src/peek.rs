@@ -0,0 +1,250 @@
+//! `peek`: what's on a topic, readable. Payloads are decoded with the
+//! pipeline's own codecs rather than shown as bytes, and the headers stages
+//! rely on (`msg_id`, `ts_produce_ns`, `traceparent`, the envelope) are
+//! printed by name.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use bus::envelope::SCHEMA_VERSION;
+use bus::{MSG_ID, TS_PRODUCE_NS};
+use chrono::{DateTime, Utc};
+use codec::Codec;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::message::Headers as _;
+use rdkafka::{Message, Offset, TopicPartitionList};
+use serde_json::{json, Value};
+
+const META_TIMEOUT: Duration = Duration::from_secs(10);
+/// Headers shown first, in this order, even when absent.
+const KNOWN: [&str; 4] = [MSG_ID, TS_PRODUCE_NS, "traceparent", SCHEMA_VERSION];
+/// Set by stages that publish a non-JSON codec.
+pub const CONTENT_TYPE: &str = "content-type";
+
+#[derive(Debug, clap::Args)]
+pub struct PeekArgs {
+    #[arg(long, env = "TOPIC_IN")]
+    pub topic: String,
+    /// Only this partition (default: all).
+    #[arg(long)]
+    pub partition: Option<i32>,
+    /// Start this many messages before the end of each partition.
+    #[arg(long, default_value_t = 10)]
+    pub last: i64,
+    /// Start at the oldest retained message; overrides --last.
+    #[arg(long)]
+    pub from_beginning: bool,
+    /// Keep tailing for new messages instead of stopping at the end.
+    #[arg(short, long)]
+    pub follow: bool,
+    /// Stop after this many messages.
+    #[arg(long)]
+    pub count: Option<u64>,
+    /// Without --follow, stop once nothing has arrived for this many seconds.
+    #[arg(long, default_value_t = 10)]
+    pub idle_secs: u64,
+    /// auto (content-type header, else JSON, MessagePack, Protobuf in turn), json, msgpack, proto or raw.
+    #[arg(long, default_value = "auto")]
+    pub codec: String,
+    /// One JSON object per message, for piping into jq.
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// A message in plain values, so rendering doesn't need a broker.
+#[derive(Debug, Clone, Default)]
+pub struct Peeked {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub ts_ms: Option<i64>,
+    pub key: Option<Vec<u8>>,
+    pub headers: Vec<(String, Option<Vec<u8>>)>,
+    pub payload: Option<Vec<u8>>,
+}
+
+impl Peeked {
+    fn header(&self, name: &str) -> Option<String> {
+        self.headers.iter().find(|h| h.0 == name).and_then(|h| h.1.as_deref()).map(|v| String::from_utf8_lossy(v).into_owned())
+    }
+}
+
+/// The payload as JSON plus the codec that read it; `raw` when nothing could.
+pub fn decode(payload: &[u8], codec: &str, content_type: Option<&str>) -> (Value, &'static str) {
+    let forced = match codec {
+        "auto" => content_type.and_then(|ct| Codec::ALL.into_iter().find(|c| c.content_type() == ct)),
+        "raw" => return raw(payload),
+        other => Codec::parse(other),
+    };
+    if let Some(c) = forced {
+        return match c {
+            // Any JSON, not just trades: ticks.raw carries the venue's own.
+            Codec::Json => serde_json::from_slice(payload).map_or_else(|_| raw(payload), |v| (v, "json")),
+            c => c.decode(payload).ok().and_then(|t| serde_json::to_value(t).ok()).map_or_else(|| raw(payload), |v| (v, c.name())),
+        };
+    }
+    if let Ok(v) = serde_json::from_slice::<Value>(payload) {
+        return (v, "json");
+    }
+    // Protobuf decodes almost anything, so it goes last and must look like a trade.
+    for c in [Codec::MsgPack, Codec::Proto] {
+        if let Ok(t) = c.decode(payload) {
+            if !t.symbol.is_empty() && t.ts_ms > 0 {
+                return (serde_json::to_value(t).unwrap_or_default(), c.name());
+            }
+        }
+    }
+    raw(payload)
+}
+
+fn raw(payload: &[u8]) -> (Value, &'static str) {
+    match std::str::from_utf8(payload) {
+        Ok(s) => (Value::String(s.to_string()), "text"),
+        Err(_) => (Value::String(payload.iter().map(|b| format!("{:02x}", b)).collect()), "hex"),
+    }
+}
+
+/// `1717000000123456789` -> `2024-05-29T16:26:40.123Z (3.2s ago)`.
+fn ns_time(v: &str, now: DateTime<Utc>) -> Option<String> {
+    let ns: i64 = v.parse().ok()?;
+    let t = DateTime::from_timestamp(ns.div_euclid(1_000_000_000), ns.rem_euclid(1_000_000_000) as u32)?;
+    let ago = (now - t).num_milliseconds() as f64 / 1000.0;
+    Some(format!("{} ({:.1}s ago)", t.format("%Y-%m-%dT%H:%M:%S%.3fZ"), ago))
+}
+
+/// A human-readable block for one message.
+pub fn render(m: &Peeked, codec: &str, now: DateTime<Utc>) -> String {
+    let key = m.key.as_deref().map_or("-".to_string(), |k| String::from_utf8_lossy(k).into_owned());
+    let ts = m.ts_ms.and_then(DateTime::from_timestamp_millis).map_or("-".to_string(), |t| t.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string());
+    let mut out = format!("{}[{}]@{}  key={}  broker_ts={}\n", m.topic, m.partition, m.offset, key, ts);
+    let mut names: Vec<&str> = KNOWN.to_vec();
+    names.extend(m.headers.iter().map(|h| h.0.as_str()).filter(|n| !KNOWN.contains(n)));
+    for name in names {
+        let value = m.header(name);
+        let shown = match (name, &value) {
+            (_, None) => "-".to_string(),
+            (TS_PRODUCE_NS, Some(v)) => ns_time(v, now).map_or(v.clone(), |t| format!("{}  {}", v, t)),
+            (_, Some(v)) => v.clone(),
+        };
+        out += &format!("  {:<16}{}\n", name, shown);
+    }
+    match &m.payload {
+        None => out += "  (no payload)\n",
+        Some(p) => {
+            let (v, how) = decode(p, codec, m.header(CONTENT_TYPE).as_deref());
+            let body = match &v {
+                Value::String(s) => s.clone(),
+                v => serde_json::to_string_pretty(v).unwrap_or_default(),
+            };
+            out += &format!("  payload ({}, {} bytes):\n", how, p.len());
+            for line in body.lines() {
+                out += &format!("    {}\n", line);
+            }
+        }
+    }
+    out
+}
+
+/// The same, as one JSON object.
+pub fn render_json(m: &Peeked, codec: &str) -> Value {
+    let (payload, how) = m.payload.as_deref().map_or((Value::Null, "none"), |p| decode(p, codec, m.header(CONTENT_TYPE).as_deref()));
+    let headers: serde_json::Map<String, Value> = m.headers.iter()
+        .map(|h| (h.0.clone(), h.1.as_deref().map_or(Value::Null, |v| Value::String(String::from_utf8_lossy(v).into_owned()))))
+        .collect();
+    json!({
+        "topic": m.topic,
+        "partition": m.partition,
+        "offset": m.offset,
+        "ts_ms": m.ts_ms,
+        "key": m.key.as_deref().map(|k| String::from_utf8_lossy(k).into_owned()),
+        "headers": headers,
+        "codec": how,
+        "payload": payload,
+    })
+}
+
+fn to_peeked<M: Message>(m: &M) -> Peeked {
+    Peeked {
+        topic: m.topic().to_string(),
+        partition: m.partition(),
+        offset: m.offset(),
+        ts_ms: m.timestamp().to_millis(),
+        key: m.key().map(<[u8]>::to_vec),
+        headers: m.headers()
+            .map(|h| h.iter().map(|h| (h.key.to_string(), h.value.map(<[u8]>::to_vec))).collect())
+            .unwrap_or_default(),
+        payload: m.payload().map(<[u8]>::to_vec),
+    }
+}
+
+/// Print messages until the end of each partition (or forever with
+/// `--follow`); returns how many were printed. Kafka only; nothing is committed.
+///
+/// The end is the high-water mark when peek started. The last offset below
+/// it needn't be a message (a transaction marker, or a record compacted
+/// away), so the broker's end-of-partition event ends a partition too, and
+/// `--idle-secs` with nothing at all ends the run.
+pub async fn run(bus: &bus::BusConfig, args: &PeekArgs) -> Result<u64> {
+    let topic = &bus.topic(&args.topic);
+    anyhow::ensure!(bus.transport == bus::Transport::Kafka, "peek reads Kafka topics; TRANSPORT must be kafka");
+    let consumer: StreamConsumer = bus.kafka_config()
+        .set("group.id", "pipeline-peek")
+        .set("enable.auto.commit", "false")
+        .set("enable.partition.eof", if args.follow { "false" } else { "true" })
+        .create()?;
+    let md = consumer.fetch_metadata(Some(topic), META_TIMEOUT)?;
+    let partitions: Vec<i32> = md.topics().iter()
+        .flat_map(|t| t.partitions().iter().map(|p| p.id()))
+        .filter(|p| args.partition.is_none_or(|want| want == *p))
+        .collect();
+    anyhow::ensure!(!partitions.is_empty(), "topic {} has no matching partitions", topic);
+
+    let mut tpl = TopicPartitionList::new();
+    let mut end_of: HashMap<i32, i64> = HashMap::new();
+    for p in partitions {
+        let (low, high) = consumer.fetch_watermarks(topic, p, META_TIMEOUT)?;
+        let start = if args.from_beginning { low } else { (high - args.last.max(0)).max(low) };
+        tpl.add_partition_offset(topic, p, Offset::Offset(start))?;
+        if start < high {
+            end_of.insert(p, high);
+        }
+    }
+    consumer.assign(&tpl)?;
+
+    let mut printed = 0u64;
+    let idle = Duration::from_secs(args.idle_secs.max(1));
+    while args.follow || !end_of.is_empty() {
+        if args.count.is_some_and(|c| printed >= c) {
+            break;
+        }
+        let next = match args.follow {
+            true => Some(consumer.recv().await),
+            false => tokio::time::timeout(idle, consumer.recv()).await.ok(),
+        };
+        let msg = match next {
+            None => {
+                let mut left: Vec<i32> = end_of.into_keys().collect();
+                left.sort();
+                tracing::warn!(target: "pipeline", topic = %topic, partitions = ?left, idle_secs = idle.as_secs(), "nothing more arrived; stopping short of the end");
+                break;
+            }
+            Some(Err(KafkaError::PartitionEOF(p))) => {
+                end_of.remove(&p);
+                continue;
+            }
+            Some(res) => res?,
+        };
+        let m = to_peeked(&msg);
+        match args.json {
+            true => println!("{}", render_json(&m, &args.codec)),
+            false => println!("{}", render(&m, &args.codec, Utc::now())),
+        }
+        printed += 1;
+        if end_of.get(&m.partition).is_some_and(|&end| m.offset + 1 >= end) {
+            end_of.remove(&m.partition);
+        }
+    }
+    Ok(printed)
+}
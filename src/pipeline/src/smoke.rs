@@ -0,0 +1,192 @@
+//! `smoke`: one synthetic trade through the whole pipeline, timed per stage.
+//!
+//! The trade goes into `ticks.raw` looking like a Binance trade event, with
+//! a `msg_id` of `smoke-<uuid>` and a symbol of its own (`SMOKE` by default)
+//! so it's easy to find, and to filter out of analysis. It is then watched
+//! for on `ticks.norm` (Kafka only) and polled for in QuestDB by `msg_id`.
+//! The trade is stored like any other; it isn't deleted afterwards.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use bus::envelope::{Envelope, RAW_SCHEMA};
+use bus::{BusConfig, Headers, Transport, MSG_ID, TS_PRODUCE_NS};
+use chrono::Utc;
+use consumer::schema;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::Headers as _;
+use rdkafka::{Message, Offset, TopicPartitionList};
+use serde::Serialize;
+
+const META_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, clap::Args)]
+pub struct SmokeArgs {
+    #[arg(long, default_value = "ticks.raw")]
+    pub raw_topic: String,
+    #[arg(long, default_value = "ticks.norm")]
+    pub norm_topic: String,
+    /// QuestDB table the consumer writes trades to.
+    #[arg(long, default_value = "trades")]
+    pub table: String,
+    #[arg(long, default_value = "SMOKE")]
+    pub symbol: String,
+    /// Give up on the trade after this long (seconds).
+    #[arg(long, default_value_t = 30)]
+    pub timeout: u64,
+    /// Print the report as JSON.
+    #[arg(long)]
+    pub json: bool,
+    #[arg(long, env = "QDB_HTTP_URL", default_value = "http://localhost:9000")]
+    pub qdb_http: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Broker acked the raw trade.
+    Raw,
+    /// The producer's normalized copy is on the norm topic.
+    Norm,
+    /// The consumer wrote it to QuestDB.
+    Questdb,
+}
+
+/// Milliseconds from injection to each stage; `None` where it never arrived
+/// (or, for `norm`, wasn't checked).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub msg_id: String,
+    pub raw_ms: Option<f64>,
+    pub norm_ms: Option<f64>,
+    pub questdb_ms: Option<f64>,
+    pub norm_checked: bool,
+    /// Set when the trade didn't make it, naming where it stopped.
+    pub failure: Option<String>,
+}
+
+impl Report {
+    /// Where the trade stopped and what to look at, or `None` if it made it through.
+    pub fn diagnose(&self, error: Option<&str>) -> Option<String> {
+        let why = |e: &str| error.map_or(String::new(), |x| format!(" ({}: {})", e, x));
+        if self.raw_ms.is_none() {
+            return Some(format!("publishing to ticks.raw failed{}: check the brokers are reachable", why("publish")));
+        }
+        if self.questdb_ms.is_some() {
+            return None;
+        }
+        if self.norm_checked && self.norm_ms.is_none() {
+            return Some("the trade never reached the norm topic: check the producer is running, its lag on ticks.raw, and dropped_total / schema_rejected_total".into());
+        }
+        Some(format!(
+            "the trade {}never reached QuestDB{}: check the consumer is running, its lag, and sink_failures_total / paused",
+            if self.norm_checked { "was normalized but " } else { "" },
+            why("query"),
+        ))
+    }
+
+    pub fn print(&self) {
+        let row = |stage: &str, ms: Option<f64>, prev: Option<f64>| {
+            let at = ms.map_or("-".to_string(), |v| format!("{:.1}", v));
+            let delta = match (ms, prev) {
+                (Some(v), Some(p)) => format!("{:+.1}", v - p),
+                _ => "-".into(),
+            };
+            println!("{:<10} {:>10} {:>10}", stage, at, delta);
+        };
+        println!("msg_id {}", self.msg_id);
+        println!("{:<10} {:>10} {:>10}", "stage", "at_ms", "delta_ms");
+        row("raw", self.raw_ms, Some(0.0));
+        if self.norm_checked {
+            row("norm", self.norm_ms, self.raw_ms);
+        }
+        row("questdb", self.questdb_ms, if self.norm_checked { self.norm_ms } else { self.raw_ms });
+        match &self.failure {
+            None => println!("ok"),
+            Some(f) => println!("FAILED: {}", f),
+        }
+    }
+}
+
+/// Wait for `msg_id` on any partition `c` is assigned.
+async fn watch_norm(c: &StreamConsumer, msg_id: &str) -> Result<()> {
+    loop {
+        let m = c.recv().await?;
+        let id = m.headers().and_then(|h| h.iter().find(|h| h.key == MSG_ID).and_then(|h| h.value.map(<[u8]>::to_vec)));
+        if id.as_deref() == Some(msg_id.as_bytes()) {
+            return Ok(());
+        }
+    }
+}
+
+/// A consumer on every partition of `topic`, starting at its current end.
+fn assign_at_end(bus: &BusConfig, topic: &str) -> Result<StreamConsumer> {
+    let c: StreamConsumer = bus.kafka_config()
+        .set("group.id", "pipeline-smoke")
+        .set("enable.auto.commit", "false")
+        .create()?;
+    let md = c.fetch_metadata(Some(topic), META_TIMEOUT)?;
+    let mut tpl = TopicPartitionList::new();
+    for p in md.topics().iter().flat_map(|t| t.partitions()) {
+        let (_, high) = c.fetch_watermarks(topic, p.id(), META_TIMEOUT)?;
+        tpl.add_partition_offset(topic, p.id(), Offset::Offset(high))?;
+    }
+    c.assign(&tpl)?;
+    Ok(c)
+}
+
+pub async fn run(bus: &BusConfig, args: &SmokeArgs) -> Result<Report> {
+    let msg_id = format!("smoke-{}", uuid::Uuid::new_v4());
+    let mut report = Report { msg_id: msg_id.clone(), norm_checked: bus.transport == Transport::Kafka, ..Default::default() };
+    let deadline = Duration::from_secs(args.timeout);
+    // Positioned before publishing, so the trade can't slip past.
+    let norm = match report.norm_checked {
+        true => Some(assign_at_end(bus, &bus.topic(&args.norm_topic))?),
+        false => None,
+    };
+
+    let now = Utc::now();
+    // Within i64 and well clear of real trade ids; fine for dedup on (timestamp, symbol, trade_id).
+    let trade_id = now.timestamp_nanos_opt().unwrap_or_default() / 1000;
+    let payload = serde_json::json!({
+        "e": "trade", "E": now.timestamp_millis(), "s": args.symbol, "t": trade_id,
+        "p": "1.0", "q": "1.0", "T": now.timestamp_millis(), "m": false, "M": true,
+    }).to_string();
+    let envelope = Envelope::new(RAW_SCHEMA, "pipeline-smoke", env!("CARGO_PKG_VERSION"), "smoke", "spot");
+    let headers = envelope.apply(Headers::new()
+        .with(MSG_ID, &msg_id)
+        .with(TS_PRODUCE_NS, &now.timestamp_nanos_opt().unwrap_or_default().to_string()));
+
+    let t0 = Instant::now();
+    let ms = |t: Instant| t.duration_since(t0).as_secs_f64() * 1000.0;
+    let publisher = bus.publisher().await?;
+    if let Err(e) = publisher.publish(&args.raw_topic, &args.symbol, payload.as_bytes(), &headers).await {
+        report.failure = report.diagnose(Some(&e.to_string()));
+        return Ok(report);
+    }
+    report.raw_ms = Some(ms(Instant::now()));
+
+    let qdb = schema::Client::authed(&args.qdb_http).await?;
+    let sql = format!("SELECT ts_ms FROM {} WHERE msg_id = '{}'", obsv::namespace::table(&args.table), msg_id);
+    let mut last_error = None;
+    let mut norm = norm.map(|c| Box::pin(async move { watch_norm(&c, &msg_id).await }));
+    let mut poll = tokio::time::interval(Duration::from_millis(100));
+    while t0.elapsed() < deadline && report.questdb_ms.is_none() {
+        tokio::select! {
+            seen = async { norm.as_mut().unwrap().await }, if norm.is_some() && report.norm_ms.is_none() => {
+                match seen {
+                    Ok(()) => report.norm_ms = Some(ms(Instant::now())),
+                    Err(e) => { last_error = Some(e.to_string()); norm = None; }
+                }
+            }
+            _ = poll.tick() => match qdb.query(&sql).await {
+                Ok(rows) if !rows.is_empty() => report.questdb_ms = Some(ms(Instant::now())),
+                Ok(_) => {}
+                Err(e) => last_error = Some(e.to_string()),
+            },
+            _ = tokio::time::sleep(deadline.saturating_sub(t0.elapsed())) => break,
+        }
+    }
+    report.failure = report.diagnose(last_error.as_deref());
+    Ok(report)
+}
@@ -0,0 +1,36 @@
+use chrono::NaiveDate;
+use pipeline::export::{days, normalize_symbol, parse_time, Format, Manifest};
+
+const DAY: i64 = 86_400_000;
+
+#[test]
+fn symbols_and_times_are_read_the_way_people_write_them() {
+    assert_eq!(normalize_symbol("BTC-USDT"), "BTCUSDT");
+    assert_eq!(normalize_symbol("eth/usdt"), "ETHUSDT");
+    assert_eq!(parse_time("2024-06-01").unwrap().timestamp_millis(), 1_717_200_000_000);
+    assert_eq!(parse_time("2024-06-01T02:00:00+02:00").unwrap().timestamp_millis(), 1_717_200_000_000);
+    assert!(parse_time("June 1st").is_err());
+}
+
+#[test]
+fn ranges_split_at_utc_midnight() {
+    let from = 1_717_200_000_000 + DAY / 2;
+    let d = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+    assert_eq!(
+        days(from, from + DAY + 1),
+        vec![(d("2024-06-01"), from, from + DAY / 2), (d("2024-06-02"), from + DAY / 2, from + DAY + 1)],
+    );
+    assert!(days(from, from).is_empty());
+}
+
+#[test]
+fn the_manifest_names_the_format_in_lowercase() {
+    let json = serde_json::json!({
+        "dataset": "trades", "table": "trades", "format": "csv", "symbols": ["BTCUSDT"],
+        "from": "2024-06-01T00:00:00Z", "to": "2024-06-02T00:00:00Z", "created_at": "2024-06-02T00:00:01Z",
+        "columns": ["timestamp"], "files": [],
+    });
+    let m: Manifest = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(m.format, Format::Csv);
+    assert_eq!(serde_json::to_value(&m).unwrap()["format"], "csv");
+}
@@ -0,0 +1,28 @@
+use chrono::Utc;
+use pipeline::offsets::{target, Exported, ResetTo};
+
+fn to() -> ResetTo {
+    ResetTo { to_earliest: false, to_latest: false, to_time: None, to_offset: None, shift_by: None }
+}
+
+#[test]
+fn reset_targets_stay_within_the_retained_range() {
+    assert_eq!(target(&ResetTo { to_earliest: true, ..to() }, Some(50), 10, 100, None), 10);
+    assert_eq!(target(&ResetTo { to_latest: true, ..to() }, Some(50), 10, 100, None), 100);
+    assert_eq!(target(&ResetTo { to_offset: Some(5), ..to() }, Some(50), 10, 100, None), 10);
+    assert_eq!(target(&ResetTo { shift_by: Some(-20), ..to() }, Some(50), 10, 100, None), 30);
+    assert_eq!(target(&ResetTo { shift_by: Some(500), ..to() }, Some(50), 10, 100, None), 100);
+    // A group with no commit shifts from the oldest retained message.
+    assert_eq!(target(&ResetTo { shift_by: Some(5), ..to() }, None, 10, 100, None), 15);
+    // A time past the last message lands at the end.
+    let at = ResetTo { to_time: Some(Utc::now()), ..to() };
+    assert_eq!((target(&at, Some(50), 10, 100, Some(42)), target(&at, Some(50), 10, 100, None)), (42, 100));
+}
+
+#[test]
+fn export_files_round_trip() {
+    let body = r#"{"group":"consumer-stage","exported_at":"2024-06-01T00:00:00Z","offsets":{"ticks.norm":{"0":120,"1":98}}}"#;
+    let e: Exported = serde_json::from_str(body).unwrap();
+    assert_eq!(e.offsets["ticks.norm"][&1], 98);
+    assert_eq!(serde_json::from_str::<Exported>(&serde_json::to_string(&e).unwrap()).unwrap(), e);
+}
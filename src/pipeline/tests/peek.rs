@@ -0,0 +1,52 @@
+use chrono::DateTime;
+use codec::{Codec, Trade};
+use pipeline::peek::{decode, render, render_json, Peeked};
+
+fn trade() -> Trade {
+    Trade { ts_ms: 1_717_000_000_123, symbol: "BTCUSDT".into(), price: 67000.5, qty: 0.01, trade_id: 42, is_bm: true, ts_ns: None, ingest_ts_ns: None }
+}
+
+#[test]
+fn every_codec_is_recognised_without_being_told() {
+    for c in Codec::ALL {
+        let (v, how) = decode(&c.encode(&trade()).unwrap(), "auto", None);
+        assert_eq!(how, c.name());
+        assert_eq!((v["symbol"].as_str(), v["trade_id"].as_i64()), (Some("BTCUSDT"), Some(42)));
+    }
+    assert_eq!(decode(&[0xff, 0x00, 0x13], "auto", None).1, "hex");
+    assert_eq!(decode(b"not json", "auto", None).1, "text");
+    // The content-type header wins over guessing; a forced codec that fails falls back to bytes.
+    let proto = Codec::Proto.encode(&trade()).unwrap();
+    assert_eq!(decode(&proto, "auto", Some("application/x-protobuf")).1, "proto");
+    assert_eq!(decode(br#"{"a":1}"#, "proto", None).1, "text");
+}
+
+#[test]
+fn known_headers_come_first_and_produce_time_is_readable() {
+    let m = Peeked {
+        topic: "ticks.norm".into(),
+        partition: 3,
+        offset: 12,
+        ts_ms: Some(1_717_000_000_500),
+        key: Some(b"BTCUSDT".to_vec()),
+        headers: vec![
+            ("producer".into(), Some(b"producer/0.1.0".to_vec())),
+            ("ts_produce_ns".into(), Some(b"1717000000000000000".to_vec())),
+            ("msg_id".into(), Some(b"abc".to_vec())),
+        ],
+        payload: Some(Codec::Json.encode(&trade()).unwrap()),
+    };
+    let now = DateTime::from_timestamp(1_717_000_002, 0).unwrap();
+    let out = render(&m, "auto", now);
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "ticks.norm[3]@12  key=BTCUSDT  broker_ts=2024-05-29T16:26:40.500Z");
+    assert!(lines[1].starts_with("  msg_id") && lines[1].ends_with("abc"));
+    assert!(lines[2].ends_with("1717000000000000000  2024-05-29T16:26:40.000Z (2.0s ago)"), "{}", lines[2]);
+    assert!(lines[3].starts_with("  traceparent") && lines[3].ends_with('-'));
+    assert!(lines[5].ends_with("producer/0.1.0"));
+    assert!(out.contains("payload (json, ") && out.contains("\"symbol\": \"BTCUSDT\""));
+
+    let j = render_json(&m, "auto");
+    assert_eq!((j["codec"].as_str(), j["payload"]["price"].as_f64()), (Some("json"), Some(67000.5)));
+    assert_eq!(j["headers"]["msg_id"], "abc");
+}
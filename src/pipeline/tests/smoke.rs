@@ -0,0 +1,18 @@
+use pipeline::smoke::Report;
+
+#[test]
+fn diagnosis_names_the_stage_where_the_trade_stopped() {
+    let mut r = Report { msg_id: "smoke-1".into(), norm_checked: true, ..Default::default() };
+    assert!(r.diagnose(Some("broker down")).unwrap().contains("publishing to ticks.raw failed (publish: broker down)"));
+    r.raw_ms = Some(3.0);
+    assert!(r.diagnose(None).unwrap().contains("producer"));
+    r.norm_ms = Some(12.0);
+    let d = r.diagnose(Some("connection refused")).unwrap();
+    assert!(d.contains("was normalized but never reached QuestDB (query: connection refused)") && d.contains("consumer"));
+    r.questdb_ms = Some(250.0);
+    assert_eq!(r.diagnose(None), None);
+
+    // Without Kafka, ticks.norm isn't watched and a miss is blamed on the whole downstream.
+    let r = Report { raw_ms: Some(3.0), ..Default::default() };
+    assert!(r.diagnose(None).unwrap().starts_with("the trade never reached QuestDB"));
+}
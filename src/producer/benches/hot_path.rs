@@ -0,0 +1,72 @@
+use std::hint::black_box;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bus::envelope::{Envelope, NORM_SCHEMA, RAW_SCHEMA};
+use bus::{Delivery, Headers, Publisher, MSG_ID, TS_PRODUCE_NS};
+use calendar::Calendar;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use producer::enrich::Enricher;
+use producer::normalize::normalize;
+use producer::sequence::Sequencer;
+use producer::stage::{self, Topics};
+
+const RAW: &str = r#"{"e":"trade","E":1739880000139,"s":"BTCUSDT","t":4567890123,"p":"96123.45000000","q":"0.00012000","T":1739880000138,"m":true,"M":true}"#;
+
+/// Accepts everything instantly, so the pump bench measures our code, not a broker.
+struct NullPublisher;
+
+#[async_trait]
+impl Publisher for NullPublisher {
+    async fn publish(&self, _topic: &str, _key: &str, payload: &[u8], headers: &Headers) -> Result<()> {
+        black_box((payload, headers));
+        Ok(())
+    }
+}
+
+fn raw_delivery() -> Delivery {
+    let headers = Envelope::new(RAW_SCHEMA, "fetcher", "bench", "binance", "spot").apply(Headers::new()
+        .with(MSG_ID, "359039b3-296e-4d48-ba18-9d316bdbdee0")
+        .with(TS_PRODUCE_NS, "1739880000148582245"));
+    Delivery::detached("ticks.raw", Some("btcusdt"), RAW.as_bytes().to_vec(), headers)
+}
+
+fn bench_normalize(c: &mut Criterion) {
+    let mut g = c.benchmark_group("producer");
+    g.throughput(Throughput::Elements(1));
+    g.bench_function("parse_normalize", |b| b.iter(|| normalize(black_box(RAW)).unwrap()));
+    g.bench_function("parse_normalize_serialize", |b| {
+        b.iter(|| serde_json::to_string(&normalize(black_box(RAW)).unwrap()).unwrap())
+    });
+    g.finish();
+}
+
+fn bench_headers(c: &mut Criterion) {
+    let msg = raw_delivery();
+    c.bench_function("producer/header_extract", |b| {
+        b.iter(|| {
+            let ts = black_box(&msg).header(TS_PRODUCE_NS).map(str::to_string);
+            let id = black_box(&msg).header(MSG_ID).map(str::to_string);
+            (ts, id)
+        })
+    });
+}
+
+fn bench_pump(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let msg = raw_delivery();
+    let publisher = NullPublisher;
+    let topics = Topics { out: "ticks.norm".into(), latest: Some("ticks.latest".into()), listings: None, ..Topics::default() };
+    let envelope = Envelope::new(NORM_SCHEMA, "producer", "bench", "binance", "spot");
+    let enricher = Enricher::new(Calendar::parse(calendar::DEFAULT_SESSIONS, "").unwrap(), None);
+    let sequencer = Sequencer::new();
+    let mut g = c.benchmark_group("producer");
+    g.throughput(Throughput::Elements(1));
+    g.bench_function("message_pump", |b| {
+        b.to_async(&rt).iter(|| stage::process(black_box(&msg), &publisher, &topics, &envelope, &enricher, &sequencer, None))
+    });
+    g.finish();
+}
+
+criterion_group!(benches, bench_normalize, bench_headers, bench_pump);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+//! Fields the producer adds to a normalized trade beyond what the exchange sent.
+
+use anyhow::Result;
+use calendar::Calendar;
+use metrics::{counter, histogram};
+
+use crate::normalize::NormTrade;
+use crate::rates::{split_symbol, Rates};
+
+pub struct Enricher {
+    calendar: Calendar,
+    /// `None` when `NOTIONAL_CURRENCY` is empty.
+    rates: Option<Rates>,
+    /// Move `ts_ms` onto our clock by the fetcher's offset (`CLOCK_CORRECT`).
+    correct_clock: bool,
+}
+
+impl Enricher {
+    pub fn new(calendar: Calendar, rates: Option<Rates>) -> Self {
+        Self { calendar, rates, correct_clock: false }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let correct = std::env::var("CLOCK_CORRECT").is_ok_and(|v| v == "true");
+        Ok(Self::new(Calendar::from_env()?, Rates::from_env()?).correcting_clock(correct))
+    }
+
+    /// Subtract each raw message's `clock_offset_ms` from its trade's `ts_ms`.
+    pub fn correcting_clock(mut self, on: bool) -> Self {
+        self.correct_clock = on;
+        self
+    }
+
+    pub fn calendar(&self) -> &Calendar {
+        &self.calendar
+    }
+
+    /// With [`correcting_clock`](Self::correcting_clock), put `ts_ms` on our
+    /// clock: the venue's time minus `offset_ms`, how far it's ahead of the
+    /// fetcher's. Before [`apply`](Self::apply), so the session is tagged by
+    /// the corrected time.
+    pub fn correct(&self, t: &mut NormTrade, offset_ms: Option<i64>) {
+        let Some(offset) = offset_ms.filter(|o| self.correct_clock && *o != 0) else { return };
+        t.ts_ms -= offset;
+        t.ts_ns = t.ts_ns.map(|ns| ns - offset * 1_000_000);
+        counter!("clock_corrected_total").increment(1);
+        histogram!("clock_correction_ms").record(offset as f64);
+    }
+
+    /// Tag the session and, when a fresh rate is known, the converted notional.
+    pub fn apply(&self, t: &mut NormTrade) {
+        t.session = self.calendar.session(t.ts_ms);
+        if let Some(rates) = &self.rates {
+            rates.observe(&t.symbol, t.price, t.ts_ms);
+            t.notional = match t.contract_size {
+                Some(size) => rates.contract_notional(&t.symbol, t.qty, size, t.ts_ms),
+                None => rates.notional(&t.symbol, t.price, t.qty, t.ts_ms),
+            };
+            if t.notional.is_none() {
+                let quote = split_symbol(&t.symbol).map_or("unknown", |(_, q)| q);
+                counter!("notional_unconverted_total", "quote" => quote.to_string()).increment(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! Why a raw message wasn't forwarded, or its forward failed.
+//!
+//! A bad message is a data error: dropped, and the stream goes on. A failed
+//! publish is whatever kind the bus says it is (see [`bus::error::kind`]).
+
+use obsv::errors::{Classify, ErrorKind};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("empty or non-UTF-8 payload")]
+    Payload,
+    #[error("payload of {0} bytes is over MAX_PAYLOAD_BYTES")]
+    Oversize(usize),
+    #[error("signature check failed")]
+    Signature,
+    #[error("unsupported raw schema_version {0:?}")]
+    Schema(String),
+    #[error("unparseable trade: {0}")]
+    Parse(String),
+    #[error("bad listing marker: {0}")]
+    Marker(String),
+    #[error("publish to {topic} failed: {reason}")]
+    Publish { topic: String, kind: ErrorKind, reason: String },
+}
+
+impl Error {
+    pub fn publish(topic: &str, e: &anyhow::Error) -> Self {
+        Error::Publish { topic: topic.to_string(), kind: bus::error::kind(e), reason: format!("{:#}", e) }
+    }
+}
+
+impl Classify for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Publish { kind, .. } => *kind,
+            _ => ErrorKind::Data,
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! Per-symbol rate limits and fair dequeuing.
+//!
+//! One flooding symbol (a new listing, say) sits ahead of everyone sharing its
+//! partition. With limits set, the producer keeps reading past it: each
+//! symbol gets a token bucket and its own queue, and queues are served round
+//! robin, so a quiet symbol waits for at most one message per busy symbol.
+//!
+//! Nothing is dropped. A symbol whose queue reaches `queue_max` is served
+//! past its limit until it's back under, which bounds memory at the cost of
+//! the limit. Commits only move past messages that have been processed, so
+//! a throttled backlog holds everyone's commits back (redelivered, not lost,
+//! after a crash).
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use metrics::{counter, gauge, histogram};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    /// Messages per second.
+    pub rate: f64,
+    /// Bucket size: how many can go at once after a quiet spell.
+    pub burst: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Limits {
+    /// Applies to symbols without their own entry (`*=`).
+    pub default: Option<Limit>,
+    pub symbols: HashMap<String, Limit>,
+}
+
+impl Limits {
+    /// `*=200,BTCUSDT=1000:2000`: messages per second per symbol, with an
+    /// optional burst after the colon (default: one second's worth).
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut out = Self::default();
+        for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (symbol, limit) = item.split_once('=').with_context(|| format!("{:?} is not symbol=rate", item))?;
+            let (rate, burst) = match limit.split_once(':') {
+                Some((r, b)) => (r, Some(b)),
+                None => (limit, None),
+            };
+            let rate: f64 = rate.trim().parse().with_context(|| format!("bad rate in {:?}", item))?;
+            let burst: f64 = match burst {
+                Some(b) => b.trim().parse().with_context(|| format!("bad burst in {:?}", item))?,
+                None => rate,
+            };
+            anyhow::ensure!(rate > 0.0 && rate.is_finite(), "rate must be positive in {:?}", item);
+            let limit = Limit { rate, burst: burst.max(1.0) };
+            match symbol.trim() {
+                "*" => out.default = Some(limit),
+                s => { out.symbols.insert(s.to_uppercase(), limit); }
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.symbols.is_empty()
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<Limit> {
+        self.symbols.get(symbol).copied().or(self.default)
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    limit: Limit,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let dt = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + dt * self.limit.rate).min(self.limit.burst);
+        self.last = now;
+    }
+
+    /// When the next token is due.
+    fn ready_at(&self) -> Instant {
+        match self.tokens >= 1.0 {
+            true => self.last,
+            false => self.last + Duration::from_secs_f64((1.0 - self.tokens) / self.limit.rate),
+        }
+    }
+}
+
+struct Queue<T> {
+    items: VecDeque<(u64, Instant, T)>,
+    bucket: Option<Bucket>,
+}
+
+/// Per-symbol queues drained round robin under each symbol's limit.
+pub struct Fair<T> {
+    limits: Limits,
+    queue_max: usize,
+    queues: HashMap<String, Queue<T>>,
+    /// Symbols with something queued, in serving order.
+    order: VecDeque<String>,
+    next_seq: u64,
+    /// Sequence numbers pushed but not finished yet.
+    open: BTreeSet<u64>,
+    /// Finished items waiting for everything older to finish.
+    done: BTreeMap<u64, T>,
+    queued: usize,
+}
+
+impl<T> Fair<T> {
+    pub fn new(limits: Limits, queue_max: usize) -> Self {
+        Self {
+            limits,
+            queue_max: queue_max.max(1),
+            queues: HashMap::new(),
+            order: VecDeque::new(),
+            next_seq: 0,
+            open: BTreeSet::new(),
+            done: BTreeMap::new(),
+            queued: 0,
+        }
+    }
+
+    /// Messages waiting for their symbol's turn.
+    pub fn queued(&self) -> usize {
+        self.queued
+    }
+
+    /// Finished messages held until everything older is finished too.
+    pub fn waiting(&self) -> usize {
+        self.done.len()
+    }
+
+    pub fn push(&mut self, symbol: &str, item: T, now: Instant) {
+        let symbol = symbol.to_uppercase();
+        let q = self.queues.entry(symbol.clone()).or_insert_with(|| Queue {
+            items: VecDeque::new(),
+            bucket: self.limits.get(&symbol).map(|limit| Bucket { limit, tokens: limit.burst, last: now }),
+        });
+        if let Some(b) = &mut q.bucket {
+            b.refill(now);
+            if b.tokens < (q.items.len() + 1) as f64 {
+                counter!("symbol_rate_limited_total", "symbol" => symbol.clone()).increment(1);
+            }
+        }
+        if q.items.is_empty() {
+            self.order.push_back(symbol);
+        }
+        q.items.push_back((self.next_seq, now, item));
+        self.open.insert(self.next_seq);
+        self.next_seq += 1;
+        self.queued += 1;
+    }
+
+    /// The next message due, taking symbols in turn. Returns its sequence
+    /// number for [`Fair::finish`].
+    pub fn pop(&mut self, now: Instant) -> Option<(u64, T)> {
+        for _ in 0..self.order.len() {
+            let symbol = self.order.pop_front()?;
+            let q = self.queues.get_mut(&symbol).expect("ordered symbol has a queue");
+            let over = q.items.len() >= self.queue_max;
+            let allowed = match &mut q.bucket {
+                None => true,
+                Some(b) => {
+                    b.refill(now);
+                    if b.tokens >= 1.0 {
+                        b.tokens -= 1.0;
+                        true
+                    } else if over {
+                        counter!("symbol_rate_overflow_total", "symbol" => symbol.clone()).increment(1);
+                        true
+                    } else {
+                        false
+                    }
+                }
+            };
+            if !allowed {
+                self.order.push_back(symbol);
+                continue;
+            }
+            let (seq, queued_at, item) = q.items.pop_front().expect("ordered symbol has items");
+            if !q.items.is_empty() {
+                self.order.push_back(symbol);
+            }
+            self.queued -= 1;
+            histogram!("symbol_queue_delay_ms").record(now.saturating_duration_since(queued_at).as_secs_f64() * 1e3);
+            gauge!("symbol_queue_depth").set(self.queued as f64);
+            return Some((seq, item));
+        }
+        None
+    }
+
+    /// Earliest time [`Fair::pop`] can return something; `None` if nothing is queued.
+    pub fn ready_at(&self) -> Option<Instant> {
+        self.order.iter()
+            .map(|s| match &self.queues[s].bucket {
+                Some(b) if self.queues[s].items.len() < self.queue_max => b.ready_at(),
+                _ => Instant::now(),
+            })
+            .min()
+    }
+
+    /// Mark a popped message processed. `item` is kept for [`Fair::committable`];
+    /// pass `None` for one that needs no commit of its own.
+    pub fn finish(&mut self, seq: u64, item: Option<T>) {
+        self.open.remove(&seq);
+        if let Some(item) = item {
+            self.done.insert(seq, item);
+        }
+    }
+
+    /// Finished items older than anything still queued or in flight: safe to
+    /// commit, since offsets are cumulative per partition.
+    pub fn committable(&mut self) -> Vec<T> {
+        let oldest_open = self.open.first().copied().unwrap_or(u64::MAX);
+        let newer = self.done.split_off(&oldest_open);
+        std::mem::replace(&mut self.done, newer).into_values().collect()
+    }
+}
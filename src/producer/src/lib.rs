@@ -0,0 +1,10 @@
+//! Stage 2: normalize raw exchange trades from `ticks.raw` into `ticks.norm`.
+
+pub mod enrich;
+pub mod error;
+pub mod fair;
+pub mod normalize;
+pub mod rates;
+pub mod registry;
+pub mod sequence;
+pub mod stage;
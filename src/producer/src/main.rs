@@ -1,134 +1,194 @@
-use std::time::Duration;
-
-use anyhow::Result;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use bus::envelope::{Envelope, NORM_SCHEMA};
+use bus::commit::{CommitStrategy, Committer};
+use bus::compress::Compression;
+use bus::oversize::{Action, Limit};
+use bus::route::Routes;
+use bus::sign::Keyring;
+use bus::BusConfig;
 use chrono::Utc;
-use futures_util::StreamExt;
-use metrics::{counter, histogram};
-use obsv::{init_metrics, init_tracing, measure_ms_async};
-use rdkafka::config::ClientConfig;
-use rdkafka::consumer::{Consumer, StreamConsumer};
-use rdkafka::message::{BorrowedMessage, Header, Headers, OwnedHeaders};
-use rdkafka::producer::{FutureProducer, FutureRecord};
-use rdkafka::Message;
-use serde::{Deserialize, Serialize};
-use uuid::Uuid;
+use metrics::gauge;
+use obsv::errors;
+use obsv::load::Load;
+use obsv::{init_metrics, init_tracing, startup};
+use producer::enrich::Enricher;
+use producer::fair::{Fair, Limits};
+use producer::registry::{norm_trade_schema, Registry};
+use producer::sequence::{Sequencer, Store};
+use producer::stage::{self, Outcome, Topics};
+use retry::{Backoff, Policy};
 
 fn env<T: AsRef<str>>(k: T, default: &str) -> String {
     std::env::var(k.as_ref()).unwrap_or_else(|_| default.to_string())
 }
 
-#[derive(Debug, Deserialize)]
-struct RawTrade {
-    #[serde(rename = "s")] symbol: String,
-    #[serde(rename = "t")] trade_id: i64,
-    #[serde(rename = "p")] price: String,
-    #[serde(rename = "q")] qty: String,
-    #[serde(rename = "T")] ts_trade: i64,  // ms
-    #[serde(rename = "m")] is_bm: bool,
-}
-
-#[derive(Debug, Serialize)]
-struct NormTrade {
-    ts_ms: i64,
-    symbol: String,
-    price: f64,
-    qty: f64,
-    trade_id: i64,
-    is_bm: bool,
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!("chaos", "nats", "pulsar"), run())
 }
 
-fn header_str<'a>(m: &'a BorrowedMessage<'a>, key: &str) -> Option<&'a str> {
-    m.headers()?.iter().find(|h| h.key == key)
-        .and_then(|h| std::str::from_utf8(h.value?).ok())
-}
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    init_metrics(9465);
+async fn run() -> Result<()> {
     init_tracing();
+    init_metrics(9465)?;
 
-    let brokers   = env("KAFKA_BROKERS", "localhost:29092");
     let topic_in  = env("TOPIC_IN", "ticks.raw");
     let topic_out = env("TOPIC_OUT", "ticks.norm");
+    // Empty disables the compacted latest-state topic.
+    let topic_latest = env("TOPIC_LATEST", "ticks.latest");
     let group_id  = env("GROUP_ID", "producer-stage");
 
-    let consumer: StreamConsumer = ClientConfig::new()
-        .set("bootstrap.servers", &brokers)
-        .set("group.id", &group_id)
-        .set("enable.partition.eof", "false")
-        .set("auto.offset.reset", "latest")
-        .set("socket.keepalive.enable", "true")
-        .set("request.timeout.ms", "20000")
-        .create()?;
-    consumer.subscribe(&[&topic_in])?;
-
-    let producer: FutureProducer = ClientConfig::new()
-        .set("bootstrap.servers", &brokers)
-        .set("socket.keepalive.enable", "true")
-        .set("request.timeout.ms", "20000")
-        .create()?;
-
-    let mut stream = consumer.stream();
-
-    while let Some(result) = stream.next().await {
-        let msg = match result {
-            Ok(m) => m,
-            Err(e) => { tracing::error!(target="producer", error=?e, "poll error"); continue; }
-        };
-
-        let payload = match msg.payload_view::<str>() {
-            Some(Ok(s)) => s,
-            _ => { tracing::warn!(target="producer", "empty/invalid payload"); continue; }
-        };
-
-        counter!("consumed_total").increment(1);
-
-        let raw: RawTrade = match serde_json::from_str(payload) {
-            Ok(v) => v,
-            Err(e) => { tracing::error!(target="producer", error=?e, "parse error"); counter!("dropped_total").increment(1); continue; }
-        };
-
-        let norm = NormTrade {
-            ts_ms: raw.ts_trade,
-            symbol: raw.symbol,
-            price: raw.price.parse().unwrap_or(0.0),
-            qty: raw.qty.parse().unwrap_or(0.0),
-            trade_id: raw.trade_id,
-            is_bm: raw.is_bm,
-        };
-        let out_json = serde_json::to_string(&norm)?;
-
-        let orig_ts_ns = header_str(&msg, "ts_produce_ns")
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| Utc::now().timestamp_nanos_opt().unwrap().to_string());
-        let msg_id = header_str(&msg, "msg_id")
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| Uuid::new_v4().to_string());
+    // Each message is its own flush here, so `flush` behaves like `message`.
+    let mut committer = Committer::new(CommitStrategy::from_env("message")?);
+    // Empty means no limits: messages go straight through in arrival order.
+    let limits = Limits::parse(&env("SYMBOL_RATE_LIMITS", "")).context("bad SYMBOL_RATE_LIMITS")?;
+    if !limits.is_empty() {
+        tracing::info!(target="producer", ?limits, "per-symbol rate limits");
+    }
+    let mut fair = Fair::new(limits, env("SYMBOL_QUEUE_MAX", "10000").parse().unwrap_or(10_000));
 
-        counter!("produced_total").increment(1);
+    // Before anything is produced: a schema the registry's readers can't take must not reach the topic.
+    if let Some(registry) = Registry::from_env(&topic_out).await? {
+        registry.check(&norm_trade_schema()).await?;
+    }
 
-        // Await the send and time it
-        let (delivery, send_ms) = measure_ms_async(
-            producer.send(
-                FutureRecord::to(&topic_out)
-                    .payload(&out_json)
-                    .key(&norm.symbol)
-                    .headers(
-                        OwnedHeaders::new()
-                            .insert(Header { key: "msg_id", value: Some(msg_id.as_bytes()) })
-                            .insert(Header { key: "ts_produce_ns", value: Some(orig_ts_ns.as_bytes()) })
-                    ),
-                Duration::from_secs(5),
-            )
-        ).await;
-        histogram!("produce_latency_ms").record(send_ms);
+    let bus = BusConfig::from_env()?.compress_by_default(Compression::ZSTD)?;
+    let routes = Routes::from_env()?;
+    if !routes.is_empty() {
+        tracing::info!(target="producer", ?routes, "routing symbols to their own topics");
+    }
+    let topics: Vec<&str> = [&topic_in, &topic_out, &topic_latest].into_iter().map(String::as_str)
+        .chain(routes.topics())
+        .filter(|t| !t.is_empty())
+        .collect();
+    startup::wait_for("kafka", || bus.check_topics(&topics)).await?;
+    bus::audit::start(&bus).await;
+    let mut subscriber = bus.subscriber(&topic_in, &group_id).await?;
+    let publisher = bus.publisher().await?;
+    if !topic_latest.is_empty() {
+        bus.ensure_compacted(&topic_latest).await?;
+    }
+    let topics = Topics {
+        out: topic_out,
+        latest: Some(topic_latest).filter(|t| !t.is_empty()),
+        listings: Some(env("LISTINGS_TOPIC", "listings")).filter(|t| !t.is_empty()),
+        // Empty stops the stage on an undelivered trade rather than dead-lettering it.
+        dlq: Some(env("DLQ_TOPIC", "")).filter(|t| !t.is_empty()),
+        retries: Policy::times(
+            env("PUBLISH_RETRIES", "3").parse().unwrap_or(3),
+            Backoff::from_env("PUBLISH", Duration::from_millis(100), Duration::from_secs(30)),
+        ),
+        routes,
+        limit: Limit::from_env()?,
+    };
+    if let Some(l) = topics.limit.filter(|l| l.action != Action::Drop) {
+        anyhow::ensure!(topics.dlq.is_some(), "OVERSIZE_ACTION={} needs DLQ_TOPIC", l.action.name());
+    }
+    // EXCHANGE/MARKET only fill in for raw messages that predate the envelope.
+    let envelope = Envelope::new(NORM_SCHEMA, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"),
+        &env("EXCHANGE", "binance"), &env("MARKET", "spot"));
+
+    let enricher = Enricher::from_env()?;
+    let keys = Keyring::from_env()?;
+    // Empty SEQ_STATE_PATH seeds every key from the clock after a restart.
+    let seq_store = Some(env("SEQ_STATE_PATH", "seq-state.json")).filter(|p| !p.is_empty()).map(|p| Store::new(p.as_ref()));
+    let sequencer = match &seq_store {
+        Some(store) => Sequencer::resumed(store.load()?),
+        None => Sequencer::new(),
+    };
+    // Before any number is handed out, so a crash in the first second can't reuse the old ceilings.
+    if let Some(store) = &seq_store {
+        store.save(&sequencer).await.context("saving sequence ceilings")?;
+    }
+    let (store, seqs) = (seq_store.as_ref(), &sequencer);
+    let save_seq = move || async move {
+        let Some(store) = store else { return };
+        if let Err(e) = store.save(seqs).await {
+            tracing::warn!(target="producer", error=?e, "saving sequence ceilings failed");
+        }
+    };
+    // Wall clock, not trade time: alerts keyed on this must stay quiet even when no trades arrive.
+    {
+        let calendar = enricher.calendar().clone();
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(std::time::Duration::from_secs(1));
+            loop {
+                tick.tick().await;
+                gauge!("maintenance").set(if calendar.in_maintenance(Utc::now().timestamp_millis()) { 1.0 } else { 0.0 });
+            }
+        });
+    }
 
-        if let Err((e, _)) = delivery {
-            tracing::error!(target="producer", error=?e, "kafka delivery failed");
+    let load = Load::new("producer")?;
+    let mut shown = Instant::now();
+    let mut saved = Instant::now();
+    let mut stop = std::pin::pin!(obsv::runtime::stopping());
+    let (mut open, mut failed) = (true, None);
+    while failed.is_none() && (open || fair.queued() > 0) {
+        let wake = fair.ready_at();
+        tokio::select! {
+            // Stop reading, but publish and commit what's queued.
+            _ = &mut stop, if open => open = false,
+            next = subscriber.next(), if open => match next {
+                None => open = false,
+                Some(Err(e)) => {
+                    tracing::error!(target="producer", error=?e, "poll error");
+                    if let Err(e) = errors::escalate("producer", bus::error::kind(&e), e) {
+                        failed = Some(e);
+                    }
+                }
+                Some(Ok(msg)) => {
+                    let symbol = msg.key.clone().unwrap_or_default();
+                    fair.push(&symbol, msg, Instant::now());
+                }
+            },
+            _ = tokio::time::sleep_until(wake.unwrap_or_else(Instant::now).into()), if wake.is_some() => {}
         }
 
-        let _ = consumer.commit_message(&msg, rdkafka::consumer::CommitMode::Async);
+        // Processing and commits; waiting on the subscriber or the fair queue is the rest.
+        let _busy = load.busy();
+        while let Some((seq, msg)) = fair.pop(Instant::now()) {
+            load.processed(1);
+            match stage::process(&msg, publisher.as_ref(), &topics, &envelope, &enricher, &sequencer, keys.as_ref()).await {
+                Ok(outcome) => fair.finish(seq, matches!(outcome, Outcome::Forwarded | Outcome::DeadLettered | Outcome::Listing).then_some(msg)),
+                // Left open, so commits stop short of it and a restart redelivers it.
+                Err(e) => {
+                    failed = Some(e);
+                    break;
+                }
+            }
+        }
+        for msg in fair.committable() {
+            let res = match committer.done(subscriber.as_ref(), msg).await {
+                Ok(false) => committer.flushed(subscriber.as_ref()).await,
+                other => other,
+            };
+            if let Err(e) = res {
+                tracing::warn!(target="producer", error=?e, "commit failed");
+                if let Err(e) = errors::escalate("producer", bus::error::kind(&e), e) {
+                    failed = Some(e);
+                }
+            }
+        }
+        // The admin API's view of what's held, once a second at most.
+        if shown.elapsed() >= Duration::from_secs(1) {
+            obsv::admin::set("in_flight", "producer", serde_json::json!({
+                "queued": fair.queued(),
+                "waiting": fair.waiting(),
+                "uncommitted": committer.pending(),
+            }));
+            shown = Instant::now();
+        }
+        // Well within RESERVE numbers of a key's last ceiling.
+        if saved.elapsed() >= Duration::from_secs(1) {
+            save_seq().await;
+            saved = Instant::now();
+        }
+    }
+    save_seq().await;
+    if let Err(e) = committer.commit(subscriber.as_ref()).await {
+        tracing::warn!(target="producer", error=?e, "final commit failed");
     }
 
-    Ok(())
+    failed.map_or(Ok(()), Err)
 }
@@ -0,0 +1,443 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+pub struct RawTrade {
+    #[serde(rename = "s")] pub symbol: String,
+    #[serde(rename = "t")] pub trade_id: i64,
+    #[serde(rename = "p")] pub price: String,
+    #[serde(rename = "q")] pub qty: String,
+    #[serde(rename = "T")] pub ts_trade: i64,  // ms
+    #[serde(rename = "m")] pub is_bm: bool,
+}
+
+/// Binance `aggTrade` event: trades at one price from one taker order, ids `f..=l`.
+#[derive(Debug, Deserialize)]
+pub struct RawAggTrade {
+    #[serde(rename = "s")] pub symbol: String,
+    #[serde(rename = "f")] pub first_trade_id: i64,
+    #[serde(rename = "l")] pub last_trade_id: i64,
+    #[serde(rename = "p")] pub price: String,
+    #[serde(rename = "q")] pub qty: String,
+    #[serde(rename = "T")] pub ts_trade: i64,  // ms
+    #[serde(rename = "m")] pub is_bm: bool,
+}
+
+/// Gate.io `spot.trades` update; `side` is the taker's.
+#[derive(Debug, Deserialize)]
+pub struct RawGateTrade {
+    pub id: i64,
+    pub create_time_ms: String,
+    pub side: String,
+    pub currency_pair: String,
+    pub amount: String,
+    pub price: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GateUpdate {
+    result: RawGateTrade,
+}
+
+/// KuCoin `/market/match` data; `side` is the taker's. `tradeId` is an opaque
+/// hex string, so the per-symbol `sequence` stands in as the trade id.
+#[derive(Debug, Deserialize)]
+pub struct RawKucoinTrade {
+    pub symbol: String,
+    pub sequence: String,
+    pub price: String,
+    pub size: String,
+    pub side: String,
+    /// Nanoseconds.
+    pub time: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct KucoinMessage {
+    data: RawKucoinTrade,
+}
+
+/// Bitstamp `live_trades` data; `type` is the taker's side, 0 buy and 1 sell.
+#[derive(Debug, Deserialize)]
+pub struct RawBitstampTrade {
+    pub id: i64,
+    /// Microseconds.
+    pub microtimestamp: String,
+    pub amount_str: String,
+    pub price_str: String,
+    #[serde(rename = "type")] pub side: u8,
+}
+
+/// The pair is only on the channel: `live_trades_btcusd`.
+#[derive(Debug, Deserialize)]
+struct BitstampEvent {
+    channel: String,
+    data: RawBitstampTrade,
+}
+
+/// Gemini market data v2 `trade`; `side` is the taker's.
+#[derive(Debug, Deserialize)]
+pub struct RawGeminiTrade {
+    pub symbol: String,
+    pub event_id: i64,
+    pub timestamp: i64,  // ms
+    pub price: String,
+    pub quantity: String,
+    pub side: String,
+}
+
+/// Upbit (and Bithumb) `trade`: `code` is quote first (`KRW-BTC`) and
+/// `ask_bid` the taker's side.
+#[derive(Debug, Deserialize)]
+pub struct RawUpbitTrade {
+    pub code: String,
+    pub sequential_id: i64,
+    pub trade_timestamp: i64,  // ms
+    pub trade_price: f64,
+    pub trade_volume: f64,
+    pub ask_bid: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NormTrade {
+    pub ts_ms: i64,
+    pub symbol: String,
+    pub price: f64,
+    pub qty: f64,
+    pub trade_id: i64,
+    pub is_bm: bool,
+    /// UTC sessions open at `ts_ms` (`europe+us`), from the calendar; absent outside all of them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    /// `price × qty` (`qty × contract_size` for COIN-M) in `NOTIONAL_CURRENCY` (USD by default); absent when no fresh rate is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notional: Option<f64>,
+    /// Aggregated trades only: the first exchange trade id covered; `trade_id` is the last.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_trade_id: Option<i64>,
+    /// COIN-M futures only: quote currency per contract, from the raw `contract_size`
+    /// header. `qty` then counts contracts, not coins.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contract_size: Option<f64>,
+    /// The exchange the trade came from, so `(exchange, symbol, seq)` identifies
+    /// a record without the envelope headers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exchange: Option<String>,
+    /// Per-(exchange, symbol) sequence number from [`crate::sequence::Sequencer`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    /// When the fetcher received the trade (the raw `ts_produce_ns`); `ts_ms` is
+    /// when the exchange says it happened.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ingest_ts_ns: Option<i64>,
+    /// `first` on the first trade after the symbol was listed; see [`crate::sequence`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listing: Option<String>,
+    /// `ts_ms` in nanoseconds, to the venue's own precision, for venues that
+    /// send finer than milliseconds (KuCoin, Bitstamp, Gate.io).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ts_ns: Option<i64>,
+}
+
+/// Decimal string to a finite f64. NaN/inf can't travel as JSON numbers and a
+/// zero price is worse than a dropped trade, so both are errors.
+fn decimal(field: &str, s: &str) -> Result<f64> {
+    match s.parse::<f64>() {
+        Ok(v) if v.is_finite() => nonzero(field, v),
+        _ => anyhow::bail!("{} is not a finite decimal: {:?}", field, s),
+    }
+}
+
+/// A number the venue sent as JSON, so already finite; zero is still an error.
+fn nonzero(field: &str, v: f64) -> Result<f64> {
+    match v == 0.0 {
+        true => anyhow::bail!("{} is zero", field),
+        false => Ok(v),
+    }
+}
+
+impl TryFrom<RawTrade> for NormTrade {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawTrade) -> Result<Self> {
+        Ok(NormTrade {
+            ts_ms: raw.ts_trade,
+            price: decimal("price", &raw.price)?,
+            qty: decimal("qty", &raw.qty)?,
+            symbol: raw.symbol,
+            trade_id: raw.trade_id,
+            is_bm: raw.is_bm,
+            session: None,
+            notional: None,
+            first_trade_id: None,
+            contract_size: None,
+            exchange: None,
+            seq: None,
+            ingest_ts_ns: None,
+            listing: None,
+            ts_ns: None,
+        })
+    }
+}
+
+impl TryFrom<RawAggTrade> for NormTrade {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawAggTrade) -> Result<Self> {
+        anyhow::ensure!(raw.first_trade_id <= raw.last_trade_id,
+            "first trade id {} is after last {}", raw.first_trade_id, raw.last_trade_id);
+        Ok(NormTrade {
+            ts_ms: raw.ts_trade,
+            price: decimal("price", &raw.price)?,
+            qty: decimal("qty", &raw.qty)?,
+            symbol: raw.symbol,
+            trade_id: raw.last_trade_id,
+            is_bm: raw.is_bm,
+            session: None,
+            notional: None,
+            first_trade_id: Some(raw.first_trade_id),
+            contract_size: None,
+            exchange: None,
+            seq: None,
+            ingest_ts_ns: None,
+            listing: None,
+            ts_ns: None,
+        })
+    }
+}
+
+/// `BTC_USDT`, `BTC-USDT` as `BTCUSDT`, the form every other venue's symbols take on `ticks.norm`.
+fn canonical(pair: &str) -> String {
+    pair.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_uppercase()
+}
+
+/// Decimal milliseconds (`"1606292218213.4578"`) as whole nanoseconds,
+/// read as digits so the fraction isn't lost to a float.
+fn fractional_ms(s: &str) -> Result<i64> {
+    let bad = || anyhow::anyhow!("create_time_ms is not a decimal: {:?}", s);
+    let (ms, frac) = s.split_once('.').unwrap_or((s, ""));
+    if frac.len() > 6 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(bad());
+    }
+    let ms: i64 = ms.parse().map_err(|_| bad())?;
+    let frac: i64 = format!("{:0<6}", frac).parse().map_err(|_| bad())?;
+    ms.checked_mul(1_000_000).and_then(|ns| ns.checked_add(if s.starts_with('-') { -frac } else { frac })).ok_or_else(bad)
+}
+
+/// The taker sold, so the buyer was the maker.
+fn taker_sold(side: &str) -> Result<bool> {
+    match side {
+        "sell" => Ok(true),
+        "buy" => Ok(false),
+        other => anyhow::bail!("unknown taker side {:?}", other),
+    }
+}
+
+impl TryFrom<RawGateTrade> for NormTrade {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawGateTrade) -> Result<Self> {
+        // Milliseconds with a fractional part: "1606292218213.4578".
+        let ts_ns = fractional_ms(&raw.create_time_ms)?;
+        Ok(NormTrade {
+            ts_ms: ts_ns.div_euclid(1_000_000),
+            price: decimal("price", &raw.price)?,
+            qty: decimal("qty", &raw.amount)?,
+            symbol: canonical(&raw.currency_pair),
+            trade_id: raw.id,
+            is_bm: taker_sold(&raw.side)?,
+            session: None,
+            notional: None,
+            first_trade_id: None,
+            contract_size: None,
+            exchange: None,
+            seq: None,
+            ingest_ts_ns: None,
+            listing: None,
+            ts_ns: Some(ts_ns),
+        })
+    }
+}
+
+impl TryFrom<RawKucoinTrade> for NormTrade {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawKucoinTrade) -> Result<Self> {
+        let trade_id = raw.sequence.parse().map_err(|_| anyhow::anyhow!("sequence is not an integer: {:?}", raw.sequence))?;
+        let ts_ns: i64 = raw.time.parse().map_err(|_| anyhow::anyhow!("time is not an integer: {:?}", raw.time))?;
+        Ok(NormTrade {
+            ts_ms: ts_ns / 1_000_000,
+            price: decimal("price", &raw.price)?,
+            qty: decimal("qty", &raw.size)?,
+            symbol: canonical(&raw.symbol),
+            trade_id,
+            is_bm: taker_sold(&raw.side)?,
+            session: None,
+            notional: None,
+            first_trade_id: None,
+            contract_size: None,
+            exchange: None,
+            seq: None,
+            ingest_ts_ns: None,
+            listing: None,
+            ts_ns: Some(ts_ns),
+        })
+    }
+}
+
+impl TryFrom<BitstampEvent> for NormTrade {
+    type Error = anyhow::Error;
+
+    fn try_from(ev: BitstampEvent) -> Result<Self> {
+        let raw = ev.data;
+        let pair = ev.channel.strip_prefix("live_trades_")
+            .ok_or_else(|| anyhow::anyhow!("not a live_trades channel: {:?}", ev.channel))?;
+        let ts_us: i64 = raw.microtimestamp.parse()
+            .map_err(|_| anyhow::anyhow!("microtimestamp is not an integer: {:?}", raw.microtimestamp))?;
+        let is_bm = match raw.side {
+            0 => false,
+            1 => true,
+            other => anyhow::bail!("unknown taker side {}", other),
+        };
+        Ok(NormTrade {
+            ts_ms: ts_us / 1_000,
+            price: decimal("price", &raw.price_str)?,
+            qty: decimal("qty", &raw.amount_str)?,
+            symbol: canonical(pair),
+            trade_id: raw.id,
+            is_bm,
+            session: None,
+            notional: None,
+            first_trade_id: None,
+            contract_size: None,
+            exchange: None,
+            seq: None,
+            ingest_ts_ns: None,
+            listing: None,
+            ts_ns: ts_us.checked_mul(1_000),
+        })
+    }
+}
+
+impl TryFrom<RawGeminiTrade> for NormTrade {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawGeminiTrade) -> Result<Self> {
+        Ok(NormTrade {
+            ts_ms: raw.timestamp,
+            price: decimal("price", &raw.price)?,
+            qty: decimal("qty", &raw.quantity)?,
+            symbol: canonical(&raw.symbol),
+            trade_id: raw.event_id,
+            is_bm: taker_sold(&raw.side)?,
+            session: None,
+            notional: None,
+            first_trade_id: None,
+            contract_size: None,
+            exchange: None,
+            seq: None,
+            ingest_ts_ns: None,
+            listing: None,
+            ts_ns: None,
+        })
+    }
+}
+
+impl TryFrom<RawUpbitTrade> for NormTrade {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawUpbitTrade) -> Result<Self> {
+        let (quote, base) = raw.code.split_once('-')
+            .ok_or_else(|| anyhow::anyhow!("market code is not QUOTE-BASE: {:?}", raw.code))?;
+        let is_bm = match raw.ask_bid.as_str() {
+            "ASK" => true,
+            "BID" => false,
+            other => anyhow::bail!("unknown taker side {:?}", other),
+        };
+        Ok(NormTrade {
+            ts_ms: raw.trade_timestamp,
+            price: nonzero("price", raw.trade_price)?,
+            qty: nonzero("qty", raw.trade_volume)?,
+            symbol: canonical(&format!("{}{}", base, quote)),
+            trade_id: raw.sequential_id,
+            is_bm,
+            session: None,
+            notional: None,
+            first_trade_id: None,
+            contract_size: None,
+            exchange: None,
+            seq: None,
+            ingest_ts_ns: None,
+            listing: None,
+            ts_ns: None,
+        })
+    }
+}
+
+/// The trade formats [`normalize_as`] reads, one per venue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Venue {
+    /// `trade` and `aggTrade`.
+    Binance,
+    Gate,
+    Kucoin,
+    Bitstamp,
+    /// Upbit's format, which Bithumb shares.
+    Upbit,
+    Gemini,
+}
+
+impl Venue {
+    /// The venue for an envelope's `exchange`; `None` for one that doesn't
+    /// say (`unknown`, from MQTT, ingest and FIX feeds) or isn't ours.
+    pub fn parse(exchange: &str) -> Option<Self> {
+        Some(match exchange {
+            "binance" => Self::Binance,
+            "gate" => Self::Gate,
+            "kucoin" => Self::Kucoin,
+            "bitstamp" => Self::Bitstamp,
+            "upbit" | "bithumb" => Self::Upbit,
+            "gemini" => Self::Gemini,
+            _ => return None,
+        })
+    }
+
+    /// The venue `payload` looks like, for messages with no usable `exchange`.
+    pub fn sniff(payload: &str) -> Self {
+        // Each venue's event name tells them apart; a substring check is cheaper than parsing twice.
+        if payload.contains("\"spot.trades\"") {
+            Self::Gate
+        } else if payload.contains("\"trade.l3match\"") {
+            Self::Kucoin
+        } else if payload.contains("\"live_trades_") {
+            Self::Bitstamp
+        } else if payload.contains("\"ask_bid\"") {
+            Self::Upbit
+        } else if payload.contains("\"type\":\"trade\"") {
+            Self::Gemini
+        } else {
+            Self::Binance
+        }
+    }
+}
+
+/// Parse a trade in `venue`'s format into the normalized schema.
+pub fn normalize_as(venue: Venue, payload: &str) -> Result<NormTrade> {
+    match venue {
+        Venue::Gate => serde_json::from_str::<GateUpdate>(payload)?.result.try_into(),
+        Venue::Kucoin => serde_json::from_str::<KucoinMessage>(payload)?.data.try_into(),
+        Venue::Bitstamp => serde_json::from_str::<BitstampEvent>(payload)?.try_into(),
+        Venue::Upbit => serde_json::from_str::<RawUpbitTrade>(payload)?.try_into(),
+        Venue::Gemini => serde_json::from_str::<RawGeminiTrade>(payload)?.try_into(),
+        // One stream or the other, by the event type.
+        Venue::Binance if payload.contains("\"aggTrade\"") => serde_json::from_str::<RawAggTrade>(payload)?.try_into(),
+        Venue::Binance => serde_json::from_str::<RawTrade>(payload)?.try_into(),
+    }
+}
+
+/// Parse a Binance `trade` or `aggTrade`, Gate.io `spot.trades`, KuCoin
+/// match, Bitstamp `live_trades`, Upbit/Bithumb or Gemini `trade` payload
+/// into the normalized schema, telling the venue by [`Venue::sniff`].
+pub fn normalize(payload: &str) -> Result<NormTrade> {
+    normalize_as(Venue::sniff(payload), payload)
+}
@@ -0,0 +1,112 @@
+//! Reference rates for converting a trade's notional into one currency.
+//!
+//! Rates come from the trades passing through: a trade on a pair quoted in
+//! the target currency or a pegged one (`BTCUSDT`) prices its base, and a
+//! pair whose base is pegged (`USDTTRY`) prices its quote. Conversion is one
+//! hop: `ETHBTC` converts through the last `BTCUSDT` trade, so the fetcher
+//! must be subscribed to the reference pairs for cross pairs to convert.
+//! KRW markets convert through `USDTKRW` (Upbit's `KRW-USDT`), which carries
+//! the local premium itself; peg `KRW` to an FX rate to measure it instead.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+use anyhow::{Context, Result};
+
+/// Quote assets recognised when splitting a Binance symbol, longest first.
+const QUOTE_ASSETS: &[&str] = &[
+    "FDUSD", "USDT", "USDC", "TUSD", "BUSD", "USDP", "DAI", "USD", "EUR", "GBP", "TRY", "BRL", "JPY", "KRW",
+    "BTC", "ETH", "BNB",
+];
+
+/// Split `ETHBTC` into `("ETH", "BTC")`; `None` if no known quote asset ends it.
+pub fn split_symbol(symbol: &str) -> Option<(&str, &str)> {
+    QUOTE_ASSETS.iter().find_map(|q| {
+        let base = symbol.strip_suffix(q)?;
+        (!base.is_empty()).then_some((base, *q))
+    })
+}
+
+pub struct Rates {
+    target: String,
+    /// Assets taken at a fixed rate to the target (`USDT=1`).
+    pegs: HashMap<String, f64>,
+    max_age_ms: i64,
+    /// Derived rate per asset, with the time of the trade it came from.
+    derived: Mutex<HashMap<String, (f64, i64)>>,
+}
+
+impl Rates {
+    pub fn new(target: &str, pegs: &[(String, f64)], max_age_ms: i64) -> Self {
+        let mut pegs: HashMap<String, f64> = pegs.iter().map(|(k, v)| (k.to_uppercase(), *v)).collect();
+        pegs.insert(target.to_uppercase(), 1.0);
+        Self { target: target.to_uppercase(), pegs, max_age_ms, derived: Mutex::new(HashMap::new()) }
+    }
+
+    /// `NOTIONAL_CURRENCY` (default `USD`; empty disables), `NOTIONAL_PEGS` and `NOTIONAL_MAX_AGE_MS`.
+    pub fn from_env() -> Result<Option<Self>> {
+        let target = std::env::var("NOTIONAL_CURRENCY").unwrap_or_else(|_| "USD".into());
+        if target.trim().is_empty() {
+            return Ok(None);
+        }
+        let pegs = std::env::var("NOTIONAL_PEGS").unwrap_or_else(|_| "USDT=1,USDC=1,FDUSD=1,TUSD=1,BUSD=1,USDP=1,DAI=1".into());
+        let pegs = parse_pegs(&pegs).context("bad NOTIONAL_PEGS")?;
+        let max_age_ms = std::env::var("NOTIONAL_MAX_AGE_MS").ok().and_then(|v| v.parse().ok()).unwrap_or(60_000);
+        Ok(Some(Self::new(target.trim(), &pegs, max_age_ms)))
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Learn from a trade; pairs that aren't anchored to the target teach nothing.
+    pub fn observe(&self, symbol: &str, price: f64, ts_ms: i64) {
+        let Some((base, quote)) = split_symbol(symbol) else { return };
+        if price <= 0.0 {
+            return;
+        }
+        let learned = match (self.pegs.get(base), self.pegs.get(quote)) {
+            (_, Some(q)) if !self.pegs.contains_key(base) => Some((base, price * q)),
+            (Some(b), None) => Some((quote, b / price)),
+            _ => None,
+        };
+        if let Some((asset, rate)) = learned {
+            self.derived.lock().unwrap_or_else(PoisonError::into_inner).insert(asset.to_string(), (rate, ts_ms));
+        }
+    }
+
+    /// Value of one unit of `asset` in the target, if known and no older than the max age at `ts_ms`.
+    /// A rate from a trade later than `ts_ms` (a venue's clock ahead of another's) is stale too.
+    pub fn rate(&self, asset: &str, ts_ms: i64) -> Option<f64> {
+        if let Some(r) = self.pegs.get(asset) {
+            return Some(*r);
+        }
+        let derived = self.derived.lock().unwrap_or_else(PoisonError::into_inner);
+        let (rate, at) = derived.get(asset)?;
+        (0..=self.max_age_ms).contains(&(ts_ms - at)).then_some(*rate)
+    }
+
+    /// `price × qty` of a trade on `symbol`, in the target currency.
+    pub fn notional(&self, symbol: &str, price: f64, qty: f64, ts_ms: i64) -> Option<f64> {
+        let (_, quote) = split_symbol(symbol)?;
+        Some(price * qty * self.rate(quote, ts_ms)?)
+    }
+
+    /// Notional of `contracts` inverse futures (`BTCUSD_PERP`, `BTCUSD_250627`)
+    /// worth `size` of the quote each, in the target currency. The price
+    /// doesn't enter into it: that's what makes them inverse.
+    pub fn contract_notional(&self, symbol: &str, contracts: f64, size: f64, ts_ms: i64) -> Option<f64> {
+        let pair = symbol.split_once('_').map_or(symbol, |(pair, _)| pair);
+        let (_, quote) = split_symbol(pair)?;
+        Some(contracts * size * self.rate(quote, ts_ms)?)
+    }
+}
+
+fn parse_pegs(s: &str) -> Result<Vec<(String, f64)>> {
+    s.split(',').map(str::trim).filter(|p| !p.is_empty())
+        .map(|p| {
+            let (k, v) = p.split_once('=').with_context(|| format!("{:?} isn't ASSET=RATE", p))?;
+            Ok((k.trim().to_string(), v.trim().parse()?))
+        })
+        .collect()
+}
@@ -0,0 +1,212 @@
+//! Startup check of `NormTrade`'s JSON Schema against a schema registry.
+//!
+//! The registry (Confluent's REST API) holds the schema `ticks.norm`'s readers
+//! were built for, under `SCHEMA_REGISTRY_SUBJECT` (default `<TOPIC_OUT>-value`).
+//! Before producing, the local schema is compared with the subject's latest
+//! version at `SCHEMA_COMPATIBILITY`, and the producer refuses to start if
+//! they're incompatible; a bad deploy then crash-loops instead of writing
+//! records its readers can't take. The comparison is done here rather than
+//! by the registry so the level is the producer's, not whatever the subject
+//! is configured with.
+//!
+//! The schemas are flat objects, so compatibility comes down to three rules,
+//! applied from the reader's side: every field the reader requires, the
+//! writer always sends; a field both know has a type the reader accepts
+//! (`number` takes `integer`); and a field only the writer knows is fine
+//! unless the reader forbids additional properties.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// The local schema can read what the registered one wrote.
+    Backward,
+    /// The registered schema can read what the local one writes.
+    Forward,
+    Full,
+    None,
+}
+
+impl Level {
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s.trim().to_uppercase().as_str() {
+            "BACKWARD" => Level::Backward,
+            "FORWARD" => Level::Forward,
+            "FULL" => Level::Full,
+            "NONE" => Level::None,
+            other => anyhow::bail!("unknown compatibility level {:?} (expected BACKWARD|FORWARD|FULL|NONE)", other),
+        })
+    }
+}
+
+/// `ticks.norm`'s payload, as written by [`crate::normalize::NormTrade`].
+pub fn norm_trade_schema() -> Value {
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "NormTrade",
+        "type": "object",
+        "properties": {
+            "ts_ms": { "type": "integer" },
+            "symbol": { "type": "string" },
+            "price": { "type": "number" },
+            "qty": { "type": "number" },
+            "trade_id": { "type": "integer" },
+            "is_bm": { "type": "boolean" },
+            "session": { "type": "string" },
+            "notional": { "type": "number" },
+            "first_trade_id": { "type": "integer" },
+            "contract_size": { "type": "number" },
+            "exchange": { "type": "string" },
+            "seq": { "type": "integer" },
+            "ingest_ts_ns": { "type": "integer" },
+            "listing": { "type": "string" },
+            "ts_ns": { "type": "integer" },
+        },
+        "required": ["ts_ms", "symbol", "price", "qty", "trade_id", "is_bm"],
+    })
+}
+
+fn types(prop: &Value) -> Option<Vec<&str>> {
+    match prop.get("type")? {
+        Value::String(t) => Some(vec![t.as_str()]),
+        Value::Array(ts) => Some(ts.iter().filter_map(Value::as_str).collect()),
+        _ => None,
+    }
+}
+
+fn required(schema: &Value) -> Vec<&str> {
+    schema.get("required").and_then(Value::as_array).map(|r| r.iter().filter_map(Value::as_str).collect()).unwrap_or_default()
+}
+
+/// Why `reader` can't read everything `writer` writes; empty if it can.
+fn read_problems(reader: &Value, writer: &Value) -> Vec<String> {
+    let none = serde_json::Map::new();
+    let rp = reader.get("properties").and_then(Value::as_object).unwrap_or(&none);
+    let wp = writer.get("properties").and_then(Value::as_object).unwrap_or(&none);
+    let writer_required = required(writer);
+    let mut problems = Vec::new();
+
+    for field in required(reader) {
+        if !writer_required.contains(&field) {
+            problems.push(format!("{} is required by the reader but not always sent", field));
+        }
+    }
+    for (field, w) in wp {
+        match rp.get(field) {
+            Some(r) => {
+                // No `type` means anything goes.
+                let (Some(rt), Some(wt)) = (types(r), types(w)) else { continue };
+                let accepts = |t: &str| rt.contains(&t) || (t == "integer" && rt.contains(&"number"));
+                if let Some(t) = wt.iter().find(|t| !accepts(t)) {
+                    problems.push(format!("{} is {} in the writer, which the reader's {:?} doesn't accept", field, t, rt));
+                }
+            }
+            None if reader.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                problems.push(format!("{} is sent but the reader forbids additional properties", field));
+            }
+            None => {}
+        }
+    }
+    problems
+}
+
+/// Why `local` isn't compatible with `registered` at `level`; empty if it is.
+pub fn problems(level: Level, local: &Value, registered: &Value) -> Vec<String> {
+    let backward = || read_problems(local, registered).into_iter().map(|p| format!("backward: {}", p));
+    let forward = || read_problems(registered, local).into_iter().map(|p| format!("forward: {}", p));
+    match level {
+        Level::Backward => backward().collect(),
+        Level::Forward => forward().collect(),
+        Level::Full => backward().chain(forward()).collect(),
+        Level::None => Vec::new(),
+    }
+}
+
+#[derive(Deserialize)]
+struct Latest {
+    version: u32,
+    schema: String,
+    #[serde(rename = "schemaType", default)]
+    schema_type: Option<String>,
+}
+
+pub struct Registry {
+    http: reqwest::Client,
+    url: String,
+    subject: String,
+    level: Level,
+    register: bool,
+    basic_auth: Option<(String, String)>,
+}
+
+impl Registry {
+    /// `SCHEMA_REGISTRY_URL` (empty disables the check), `SCHEMA_REGISTRY_SUBJECT`,
+    /// `SCHEMA_COMPATIBILITY` (default `BACKWARD`), `SCHEMA_REGISTRY_REGISTER`
+    /// and the `user:password` secret `SCHEMA_REGISTRY_BASIC_AUTH`.
+    pub async fn from_env(topic_out: &str) -> Result<Option<Self>> {
+        let var = |k: &str| std::env::var(k).ok().filter(|v| !v.trim().is_empty());
+        let Some(url) = var("SCHEMA_REGISTRY_URL") else { return Ok(None) };
+        let basic_auth = match secrets::Secrets::from_env()?.get("SCHEMA_REGISTRY_BASIC_AUTH").await? {
+            Some(s) => {
+                let (user, password) = s.expose().split_once(':').context("SCHEMA_REGISTRY_BASIC_AUTH isn't user:password")?;
+                Some((user.to_string(), password.to_string()))
+            }
+            None => None,
+        };
+        Ok(Some(Self {
+            http: reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?,
+            url: url.trim_end_matches('/').to_string(),
+            subject: var("SCHEMA_REGISTRY_SUBJECT").unwrap_or_else(|| format!("{}-value", topic_out)),
+            level: Level::parse(&var("SCHEMA_COMPATIBILITY").unwrap_or_else(|| "BACKWARD".into()))?,
+            register: var("SCHEMA_REGISTRY_REGISTER").is_some_and(|v| v == "true"),
+            basic_auth,
+        }))
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let req = self.http.request(method, format!("{}{}", self.url, path));
+        match &self.basic_auth {
+            Some((user, password)) => req.basic_auth(user, Some(password)),
+            None => req,
+        }
+    }
+
+    /// The subject's latest version and schema; `None` if it has none yet.
+    async fn latest(&self) -> Result<Option<(u32, Value)>> {
+        let resp = self.request(reqwest::Method::GET, &format!("/subjects/{}/versions/latest", self.subject)).send().await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let latest: Latest = resp.error_for_status()?.json().await?;
+        anyhow::ensure!(latest.schema_type.as_deref() == Some("JSON"),
+            "{} v{} is a {} schema, not JSON", self.subject, latest.version, latest.schema_type.as_deref().unwrap_or("AVRO"));
+        let schema = serde_json::from_str(&latest.schema).with_context(|| format!("{} v{} isn't valid JSON", self.subject, latest.version))?;
+        Ok(Some((latest.version, schema)))
+    }
+
+    /// Refuse `local` if it's incompatible with the subject's latest version,
+    /// then register it if asked to. A registry that can't be reached is an
+    /// error too: the check can't be skipped by accident.
+    pub async fn check(&self, local: &Value) -> Result<()> {
+        match self.latest().await.with_context(|| format!("schema registry {}", self.url))? {
+            Some((version, registered)) => {
+                let problems = problems(self.level, local, &registered);
+                anyhow::ensure!(problems.is_empty(), "local schema is not {:?}-compatible with {} v{}: {}",
+                    self.level, self.subject, version, problems.join("; "));
+                tracing::info!(target="producer", subject=%self.subject, version, level=?self.level, "schema compatible with the registry");
+            }
+            None => tracing::warn!(target="producer", subject=%self.subject, "subject has no registered schema; nothing to check against"),
+        }
+        if self.register {
+            let body = json!({ "schemaType": "JSON", "schema": local.to_string() });
+            let resp: Value = self.request(reqwest::Method::POST, &format!("/subjects/{}/versions", self.subject))
+                .json(&body).send().await?.error_for_status()?.json().await?;
+            tracing::info!(target="producer", subject=%self.subject, id=%resp["id"], "schema registered");
+        }
+        Ok(())
+    }
+}
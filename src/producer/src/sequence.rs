@@ -0,0 +1,160 @@
+//! Per-(exchange, symbol) sequence numbers for `ticks.norm`, so readers can
+//! spot reordering and duplicates from the record alone.
+//!
+//! A key's first trade gets the wall clock in microseconds, and each later one
+//! the previous number plus one. Within one producer's ownership of a key the
+//! numbers are contiguous. The partition moving to another instance starts
+//! over from the clock, which is ahead of the old numbers unless the key
+//! averaged more than a trade a microsecond; so numbers never go backwards.
+//! A number seen twice is the same record delivered twice.
+//!
+//! A restart doesn't lean on the clock alone, which may have stepped back.
+//! Each key's [`RESERVE`] numbers past its next one are saved as a
+//! ceiling, and a key is seeded from the later of its ceiling and the clock.
+//!
+//! The sequencer also follows listing windows from the fetcher's listing
+//! markers, which arrive in order with the key's trades. After a `listed`
+//! marker, the key's next trade is tagged `listing: first`. A `delisted`
+//! marker hands back the key's last trade, which has already gone out by
+//! then, and forgets the key.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::normalize::NormTrade;
+
+/// The last trade numbered for a key.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LastTrade {
+    pub trade_id: i64,
+    pub seq: u64,
+    pub ts_ms: i64,
+}
+
+/// How far past a key's next number its saved ceiling is: more than a key
+/// is numbered between two saves.
+pub const RESERVE: u64 = 1_000_000;
+
+#[derive(Default)]
+struct Key {
+    next: Option<u64>,
+    /// No number below this, from a ceiling saved before a restart.
+    floor: u64,
+    /// A listing window opened and its first trade hasn't come yet.
+    opening: bool,
+    last: Option<LastTrade>,
+}
+
+#[derive(Default)]
+pub struct Sequencer {
+    keys: Mutex<HashMap<(String, String), Key>>,
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seeded from the ceilings a [`Store`] saved, as `(exchange, symbol, ceiling)`.
+    pub fn resumed(ceilings: Vec<(String, String, u64)>) -> Self {
+        let keys = ceilings.into_iter()
+            .map(|(exchange, symbol, floor)| ((exchange, symbol), Key { floor, ..Key::default() }))
+            .collect();
+        Self { keys: Mutex::new(keys) }
+    }
+
+    /// A bound on every number each key hands out until it's numbered
+    /// [`RESERVE`] more times; sorted, for a stable file. A resumed key that
+    /// hasn't been numbered yet starts at its floor, so it's bounded from there.
+    pub fn ceilings(&self) -> Vec<(String, String, u64)> {
+        let keys = self.keys.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut out: Vec<_> = keys.iter()
+            .filter(|(_, k)| k.next.is_some() || k.floor > 0)
+            .map(|((exchange, symbol), k)| (exchange.clone(), symbol.clone(), k.next.unwrap_or(k.floor) + RESERVE))
+            .collect();
+        out.sort();
+        out
+    }
+
+    /// The next number for `(exchange, symbol)`.
+    pub fn next(&self, exchange: &str, symbol: &str) -> u64 {
+        self.next_at(exchange, symbol, Utc::now().timestamp_micros() as u64)
+    }
+
+    /// As [`next`](Self::next), with the clock at `now_us` for a key seen the first time.
+    pub fn next_at(&self, exchange: &str, symbol: &str, now_us: u64) -> u64 {
+        let mut keys = self.keys.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = keys.entry((exchange.to_string(), symbol.to_string())).or_default();
+        let seq = key.next.unwrap_or(now_us.max(key.floor));
+        key.next = Some(seq + 1);
+        seq
+    }
+
+    /// Number `t` and, if it opens a listing window, tag it.
+    pub fn number(&self, exchange: &str, t: &mut NormTrade) {
+        let seq = self.next(exchange, &t.symbol);
+        t.seq = Some(seq);
+        let mut keys = self.keys.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = keys.entry((exchange.to_string(), t.symbol.clone())).or_default();
+        if std::mem::take(&mut key.opening) {
+            t.listing = Some("first".into());
+        }
+        key.last = Some(LastTrade { trade_id: t.trade_id, seq, ts_ms: t.ts_ms });
+    }
+
+    /// The symbol was listed: its next trade opens the window.
+    pub fn listed(&self, exchange: &str, symbol: &str) {
+        let mut keys = self.keys.lock().unwrap_or_else(PoisonError::into_inner);
+        keys.entry((exchange.to_string(), symbol.to_string())).or_default().opening = true;
+    }
+
+    /// The symbol was delisted: its last trade, if one was numbered since startup.
+    pub fn delisted(&self, exchange: &str, symbol: &str) -> Option<LastTrade> {
+        self.keys.lock().unwrap_or_else(PoisonError::into_inner).remove(&(exchange.to_string(), symbol.to_string()))?.last
+    }
+}
+
+/// The sequencer's ceilings on disk: a JSON file replaced atomically (write,
+/// fsync, rename).
+pub struct Store {
+    path: PathBuf,
+}
+
+impl Store {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf() }
+    }
+
+    /// The saved ceilings, or none if nothing has been saved yet.
+    pub fn load(&self) -> Result<Vec<(String, String, u64)>> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes).with_context(|| format!("reading {}", self.path.display()))?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write the sequencer's ceilings as they are now. The file is written
+    /// and fsynced on the blocking pool, off the caller's IO worker.
+    pub async fn save(&self, sequencer: &Sequencer) -> Result<()> {
+        let (path, ceilings) = (self.path.clone(), sequencer.ceilings());
+        tokio::task::spawn_blocking(move || write(&path, &ceilings)).await.context("saving sequence ceilings panicked")?
+    }
+}
+
+fn write(path: &Path, ceilings: &[(String, String, u64)]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut w = BufWriter::new(File::create(&tmp)?);
+    serde_json::to_writer(&mut w, ceilings)?;
+    w.flush()?;
+    w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use anyhow::Result;
+use bus::envelope::{self, Compat, Envelope, RAW_SCHEMA};
+use bus::oversize::{Action, Limit, OVERSIZE_BYTES};
+use bus::route::Routes;
+use bus::sign::Keyring;
+use bus::{Delivery, Headers, Publisher, MSG_ID, TS_PRODUCE_NS};
+use chrono::Utc;
+use metrics::{counter, histogram};
+use obsv::errors::{self, Classify, ErrorKind};
+use obsv::{measure_ms_async, runtime};
+use retry::Policy;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::enrich::Enricher;
+use crate::error::Error;
+use crate::normalize::{normalize_as, Venue};
+use crate::sequence::{LastTrade, Sequencer};
+
+/// What happened to one input message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Empty/non-UTF-8 payload; nothing to do.
+    Empty,
+    /// Unparseable payload, unknown raw schema major, failed signature check,
+    /// oversize payload with no DLQ to take it, or one `topics.out` refused as
+    /// bad data with no DLQ; counted in `dropped_total`.
+    Dropped,
+    /// Normalized and delivered to `topics.out`.
+    Forwarded,
+    /// Normalized, but `topics.out` wouldn't take it after its retries; the
+    /// trade went to `topics.dlq` instead. Also an oversize payload sent there
+    /// whole or truncated, per `topics.limit`. Counted in `produce_dlq_total`.
+    DeadLettered,
+    /// A listing marker: the window recorded and published to `topics.listings`.
+    Listing,
+}
+
+/// Header on a dead-lettered trade: why `topics.out` refused it.
+pub const DLQ_ERROR: &str = "dlq_error";
+
+/// Where [`process`] publishes, and how hard it tries.
+#[derive(Debug, Clone, Default)]
+pub struct Topics {
+    /// The normalized stream (`ticks.norm`).
+    pub out: String,
+    /// Compacted latest-trade-per-symbol topic (`ticks.latest`); `None` to skip it.
+    pub latest: Option<String>,
+    /// Listing windows opening and closing (`listings`); `None` to skip it.
+    pub listings: Option<String>,
+    /// Where a trade goes when `out` won't take it; `None` to stop instead.
+    pub dlq: Option<String>,
+    /// How [`process`] retries a transient `topics.out` failure.
+    pub retries: Policy,
+    /// Symbols published to a topic of their own instead of `out`.
+    pub routes: Routes,
+    /// The largest raw payload taken; those over it are dropped, or go to
+    /// `dlq` as the limit's action says. `None` takes any size.
+    pub limit: Option<Limit>,
+}
+
+impl Topics {
+    /// Where `symbol`'s trades go: its route's topic, or `out`.
+    pub fn out_for(&self, symbol: &str) -> &str {
+        self.routes.topic(symbol).unwrap_or(&self.out)
+    }
+}
+
+/// The fetcher's listing marker, as it sends it.
+#[derive(Deserialize)]
+struct Marker {
+    symbol: String,
+    ts_ms: i64,
+}
+
+/// One `listings` record: the fetcher listed or delisted `symbol`.
+#[derive(Debug, Serialize)]
+pub struct ListingWindow {
+    pub exchange: String,
+    pub symbol: String,
+    /// `listed` or `delisted`.
+    pub event: String,
+    /// When the fetcher noticed, ms since the epoch.
+    pub ts_ms: i64,
+    /// `delisted` only: the window's last trade, when this producer saw one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last: Option<LastTrade>,
+}
+
+/// Log `e` and count the message in `dropped_total` and `errors_total`.
+fn dropped(e: Error) -> Outcome {
+    tracing::error!(target="producer", error = %e, kind = e.kind().label(), "dropping message");
+    counter!("dropped_total").increment(1);
+    errors::record("producer", e.kind());
+    Outcome::Dropped
+}
+
+/// Log a failed publish and count it in `errors_total`.
+fn publish_failed(e: Error) {
+    tracing::error!(target="producer", error = %e, kind = e.kind().label(), "publish failed");
+    errors::record("producer", e.kind());
+}
+
+/// Normalize one raw message and publish it to `topics.out` or its symbol's
+/// route (and `topics.latest`), keeping its headers' meaning. `envelope` is
+/// this stage's identity; the
+/// exchange/market come from the raw message when it has them. `enricher`
+/// adds the session tag and converted notional, the latter by contract size
+/// when the raw message carries one, and corrects `ts_ms` by its clock offset
+/// if told to. `sequencer` numbers forwarded trades
+/// per (exchange, symbol). With `keys`, the raw message's signature is
+/// checked and the output signed. Listing markers (the `listing` header)
+/// aren't trades: they open or close the symbol's listing window and go to
+/// `topics.listings`.
+///
+/// The message may only be committed once this returns: a transient
+/// `topics.out` failure is retried per `topics.retries`, then the trade is
+/// dead-lettered to `topics.dlq`. Without a DLQ, a data error drops it and
+/// anything else is an error, so the message stays uncommitted.
+pub async fn process(msg: &Delivery, publisher: &dyn Publisher, topics: &Topics, envelope: &Envelope, enricher: &Enricher,
+    sequencer: &Sequencer, keys: Option<&Keyring>) -> Result<Outcome> {
+    // Before anything reads it: a multi-MB frame costs nothing more than its count.
+    if let Some(limit) = &topics.limit {
+        if let Some(action) = limit.exceeded("producer", msg.payload.len()) {
+            return oversize(msg, publisher, topics, limit, action).await;
+        }
+    }
+    let payload = match msg.payload_str() {
+        Some(s) if !s.is_empty() => s,
+        _ => {
+            tracing::warn!(target="producer", "empty/invalid payload");
+            errors::record("producer", Error::Payload.kind());
+            return Ok(Outcome::Empty);
+        }
+    };
+
+    counter!("consumed_total").increment(1);
+
+    if keys.is_some_and(|k| !k.check("producer", &msg.payload, &msg.headers)) {
+        return Ok(dropped(Error::Signature));
+    }
+
+    match envelope::compat(&msg.headers, RAW_SCHEMA) {
+        Compat::Supported(_) => {}
+        Compat::Unversioned => counter!("schema_unversioned_total").increment(1),
+        Compat::Unsupported(v) => {
+            counter!("schema_rejected_total", "schema_version" => v.clone()).increment(1);
+            return Ok(dropped(Error::Schema(v)));
+        }
+    }
+
+    if msg.header(envelope::LISTING).is_some() {
+        return listing(msg, payload, publisher, topics, &envelope.inherit(&msg.headers), sequencer).await;
+    }
+
+    // Parsing and encoding are the CPU-heavy part. For a big enough payload
+    // the worker's other tasks move elsewhere meanwhile; a single trade isn't worth it.
+    let encoded = runtime::cpu_here_for(payload.len(), || {
+        // The fetcher says which venue it read; only feeds that can't are sniffed.
+        let venue = msg.header(envelope::EXCHANGE).and_then(Venue::parse).unwrap_or_else(|| Venue::sniff(payload));
+        let mut norm = match normalize_as(venue, payload) {
+            Ok(v) => v,
+            Err(e) => return Ok(Err(Error::Parse(format!("{:#}", e)))),
+        };
+        // Only COIN-M trades carry one; a size that isn't a positive number is as good as none.
+        norm.contract_size = msg.header(envelope::CONTRACT_SIZE).and_then(|s| s.parse().ok()).filter(|s: &f64| s.is_finite() && *s > 0.0);
+        enricher.correct(&mut norm, msg.header(envelope::CLOCK_OFFSET_MS).and_then(|s| s.parse().ok()));
+        enricher.apply(&mut norm);
+
+        let orig_ts_ns = msg.header(TS_PRODUCE_NS)
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| Utc::now().timestamp_nanos_opt().unwrap().to_string());
+        let envelope = envelope.inherit(&msg.headers);
+        sequencer.number(&envelope.exchange, &mut norm);
+        norm.ingest_ts_ns = orig_ts_ns.parse().ok();
+        norm.exchange = Some(envelope.exchange.clone());
+        let out_json = serde_json::to_string(&norm)?;
+        anyhow::Ok(Ok((norm, orig_ts_ns, envelope, out_json)))
+    })?;
+    let (norm, orig_ts_ns, envelope, out_json) = match encoded {
+        Ok(v) => v,
+        Err(e) => return Ok(dropped(e)),
+    };
+    let msg_id = msg.header(MSG_ID)
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    counter!("produced_total").increment(1);
+
+    // Await the send and time it
+    let headers = envelope.apply(Headers::new()
+        .with(MSG_ID, &msg_id)
+        .with(TS_PRODUCE_NS, &orig_ts_ns));
+    let headers = match keys {
+        Some(k) => k.signed(out_json.as_bytes(), headers),
+        None => headers,
+    };
+    let send = measure_ms_async(deliver(publisher, topics, &norm.symbol, out_json.as_bytes(), &headers));
+    // Same body, keyed by symbol, so compaction leaves one current trade per symbol.
+    let latest = async {
+        match &topics.latest {
+            Some(topic) => publisher.publish(topic, &norm.symbol, out_json.as_bytes(), &headers).await,
+            None => Ok(()),
+        }
+    };
+    let ((delivery, send_ms), latest) = tokio::join!(send, latest);
+    histogram!("produce_latency_ms").record(send_ms);
+
+    if let (Err(e), Some(topic)) = (latest, &topics.latest) {
+        counter!("latest_publish_failed_total").increment(1);
+        publish_failed(Error::publish(topic, &e));
+    }
+    let Err(e) = delivery else { return Ok(Outcome::Forwarded) };
+    let failed = Error::publish(topics.out_for(&norm.symbol), &e);
+    let (kind, reason) = (failed.kind(), failed.to_string());
+    publish_failed(failed);
+    if let Some(dlq) = &topics.dlq {
+        match publisher.publish(dlq, &norm.symbol, out_json.as_bytes(), &headers.clone().with(DLQ_ERROR, &reason)).await {
+            Ok(()) => {
+                counter!("produce_dlq_total").increment(1);
+                return Ok(Outcome::DeadLettered);
+            }
+            Err(e) => publish_failed(Error::publish(dlq, &e)),
+        }
+    }
+    match kind {
+        // Redelivering it would fail the same way; don't hold the partition on it.
+        ErrorKind::Data => {
+            counter!("dropped_total").increment(1);
+            Ok(Outcome::Dropped)
+        }
+        _ => anyhow::bail!("not committing past an undelivered trade: {}", reason),
+    }
+}
+
+/// Drop an oversize raw message, or dead-letter it (or its head) to `topics.dlq`
+/// with its headers, [`DLQ_ERROR`] and its size in [`OVERSIZE_BYTES`].
+async fn oversize(msg: &Delivery, publisher: &dyn Publisher, topics: &Topics, limit: &Limit, action: Action) -> Result<Outcome> {
+    let e = Error::Oversize(msg.payload.len());
+    let Some(dlq) = topics.dlq.as_ref().filter(|_| action != Action::Drop) else { return Ok(dropped(e)) };
+    let headers = msg.headers.clone()
+        .with(DLQ_ERROR, &e.to_string())
+        .with(OVERSIZE_BYTES, &msg.payload.len().to_string());
+    match publisher.publish(dlq, msg.key.as_deref().unwrap_or_default(), limit.dead_letter(&msg.payload), &headers).await {
+        Ok(()) => {
+            tracing::warn!(target="producer", error = %e, action = action.name(), "dead-lettering message");
+            counter!("produce_dlq_total").increment(1);
+            errors::record("producer", e.kind());
+            Ok(Outcome::DeadLettered)
+        }
+        Err(err) => {
+            publish_failed(Error::publish(dlq, &err));
+            Ok(dropped(e))
+        }
+    }
+}
+
+/// Publish to `topics.out` (or the symbol's route), retrying transient failures.
+async fn deliver(publisher: &dyn Publisher, topics: &Topics, key: &str, payload: &[u8], headers: &Headers) -> Result<()> {
+    let topic = topics.out_for(key);
+    let mut retry = topics.retries.start("kafka_publish");
+    loop {
+        chaos::produce_delay().await;
+        let res = match chaos::fault("producer") {
+            Ok(()) => publisher.publish(topic, key, payload, headers).await,
+            Err(e) => Err(e.into()),
+        };
+        let e = match res {
+            Ok(()) => return Ok(()),
+            Err(e) if bus::error::kind(&e) != ErrorKind::Transient => return Err(e),
+            Err(e) => e,
+        };
+        tracing::warn!(target="producer", topic, attempt=retry.attempt() + 1, error=%format!("{:#}", e), "publish failed");
+        if !retry.failed(&e).await {
+            return Err(e);
+        }
+        counter!("produce_retries_total").increment(1);
+    }
+}
+
+/// Open or close the marker's listing window and publish it, keyed by symbol.
+async fn listing(msg: &Delivery, payload: &str, publisher: &dyn Publisher, topics: &Topics, envelope: &Envelope,
+    sequencer: &Sequencer) -> Result<Outcome> {
+    let marker: Marker = match serde_json::from_str(payload) {
+        Ok(m) => m,
+        Err(e) => return Ok(dropped(Error::Marker(e.to_string()))),
+    };
+    let symbol = marker.symbol.to_uppercase();
+    let event = msg.header(envelope::LISTING).unwrap_or_default();
+    let last = match event {
+        "listed" => { sequencer.listed(&envelope.exchange, &symbol); None }
+        "delisted" => sequencer.delisted(&envelope.exchange, &symbol),
+        other => return Ok(dropped(Error::Marker(format!("unknown listing event {:?}", other)))),
+    };
+    counter!("listing_windows_total", "event" => event.to_string()).increment(1);
+    tracing::info!(target="producer", exchange=%envelope.exchange, symbol=%symbol, event, ?last, "listing window");
+    if let Some(topic) = &topics.listings {
+        let window = ListingWindow { exchange: envelope.exchange.clone(), symbol, event: event.into(), ts_ms: marker.ts_ms, last };
+        let body = serde_json::to_vec(&window)?;
+        if let Err(e) = publisher.publish(topic, &window.symbol, &body, &Headers::new()).await {
+            publish_failed(Error::publish(topic, &e));
+        }
+    }
+    Ok(Outcome::Listing)
+}
@@ -0,0 +1,126 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bus::envelope::{Envelope, NORM_SCHEMA, RAW_SCHEMA};
+use bus::oversize::{Action, Limit, OVERSIZE_BYTES};
+use bus::route::Routes;
+use bus::{Delivery, Headers, Publisher, MSG_ID, TS_PRODUCE_NS};
+use calendar::Calendar;
+use producer::enrich::Enricher;
+use producer::sequence::Sequencer;
+use producer::stage::{self, Outcome, Topics, DLQ_ERROR};
+use retry::{Backoff, Policy};
+
+/// Fails the first `failures` publishes to `ticks.norm`, then takes them.
+#[derive(Default)]
+struct Flaky {
+    failures: Mutex<u32>,
+    taken: Mutex<Vec<(String, Headers)>>,
+}
+
+impl Flaky {
+    fn failing(failures: u32) -> Self {
+        Self { failures: Mutex::new(failures), ..Self::default() }
+    }
+
+    fn topics(&self) -> Vec<String> {
+        self.taken.lock().unwrap().iter().map(|(t, _)| t.clone()).collect()
+    }
+}
+
+#[async_trait]
+impl Publisher for Flaky {
+    async fn publish(&self, topic: &str, _key: &str, _payload: &[u8], headers: &Headers) -> Result<()> {
+        let mut failures = self.failures.lock().unwrap();
+        if topic == "ticks.norm" && *failures > 0 {
+            *failures -= 1;
+            anyhow::bail!("broker went away");
+        }
+        self.taken.lock().unwrap().push((topic.to_string(), headers.clone()));
+        Ok(())
+    }
+}
+
+fn raw() -> Delivery {
+    let payload = r#"{"e":"trade","s":"BTCUSDT","t":1,"p":"96123.45","q":"0.5","T":1739880000138,"m":true}"#;
+    let headers = Envelope::new(RAW_SCHEMA, "fetcher", "test", "binance", "spot").apply(Headers::new()
+        .with(MSG_ID, "m1")
+        .with(TS_PRODUCE_NS, "1739880000148582245"));
+    Delivery::detached("ticks.raw", Some("btcusdt"), payload.as_bytes().to_vec(), headers)
+}
+
+async fn run(publisher: &Flaky, dlq: Option<&str>) -> Result<Outcome> {
+    let topics = Topics {
+        out: "ticks.norm".into(),
+        dlq: dlq.map(String::from),
+        retries: Policy::times(2, Backoff::new(Duration::from_millis(1), Duration::from_millis(1))),
+        ..Topics::default()
+    };
+    process(publisher, &topics).await
+}
+
+async fn process(publisher: &Flaky, topics: &Topics) -> Result<Outcome> {
+    let envelope = Envelope::new(NORM_SCHEMA, "producer", "test", "binance", "spot");
+    let enricher = Enricher::new(Calendar::parse(calendar::DEFAULT_SESSIONS, "").unwrap(), None);
+    stage::process(&raw(), publisher, topics, &envelope, &enricher, &Sequencer::new(), None).await
+}
+
+#[tokio::test]
+async fn transient_failures_are_retried_until_delivered() {
+    let publisher = Flaky::failing(2);
+    assert_eq!(run(&publisher, None).await.unwrap(), Outcome::Forwarded);
+    assert_eq!(publisher.topics(), ["ticks.norm"]);
+}
+
+#[tokio::test]
+async fn an_undelivered_trade_is_an_error_so_it_is_not_committed() {
+    let publisher = Flaky::failing(3);
+    let err = run(&publisher, None).await.unwrap_err();
+    assert!(format!("{:#}", err).contains("broker went away"), "{:#}", err);
+    assert!(publisher.topics().is_empty());
+}
+
+#[tokio::test]
+async fn with_a_dlq_an_undelivered_trade_is_dead_lettered() {
+    let publisher = Flaky::failing(3);
+    assert_eq!(run(&publisher, Some("ticks.norm.dlq")).await.unwrap(), Outcome::DeadLettered);
+    let taken = publisher.taken.lock().unwrap();
+    assert_eq!(taken.len(), 1);
+    assert_eq!(taken[0].0, "ticks.norm.dlq");
+    assert!(taken[0].1.get(DLQ_ERROR).is_some_and(|e| e.contains("broker went away")));
+    assert_eq!(taken[0].1.get(MSG_ID), Some("m1"), "the original headers come along");
+}
+
+#[tokio::test]
+async fn a_routed_symbol_goes_to_its_own_topic() {
+    let publisher = Flaky::failing(1);
+    let topics = Topics {
+        out: "ticks.norm".into(),
+        routes: Routes::parse("BTCUSDT topic=ticks.norm.btc").unwrap(),
+        ..Topics::default()
+    };
+    assert_eq!(process(&publisher, &topics).await.unwrap(), Outcome::Forwarded);
+    assert_eq!(publisher.topics(), ["ticks.norm.btc"], "ticks.norm, which would have failed, isn't tried");
+}
+
+#[tokio::test]
+async fn oversize_payloads_never_reach_the_normalized_topic() {
+    let len = raw().payload.len();
+    let limited = |action| Topics { out: "ticks.norm".into(), dlq: Some("ticks.norm.dlq".into()), limit: Some(Limit::new(16, action)), ..Topics::default() };
+
+    let publisher = Flaky::default();
+    assert_eq!(process(&publisher, &limited(Action::Drop)).await.unwrap(), Outcome::Dropped);
+    assert!(publisher.topics().is_empty(), "a drop doesn't dead-letter");
+
+    let publisher = Flaky::default();
+    assert_eq!(process(&publisher, &limited(Action::Truncate)).await.unwrap(), Outcome::DeadLettered);
+    let (topic, headers) = publisher.taken.lock().unwrap()[0].clone();
+    assert_eq!(topic, "ticks.norm.dlq");
+    assert_eq!(headers.get(OVERSIZE_BYTES), Some(len.to_string().as_str()));
+    assert_eq!(headers.get(MSG_ID), Some("m1"));
+
+    let roomy = Topics { limit: Some(Limit::new(len, Action::Drop)), ..limited(Action::Drop) };
+    assert_eq!(process(&Flaky::default(), &roomy).await.unwrap(), Outcome::Forwarded, "the limit itself fits");
+}
@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+
+use producer::fair::{Fair, Limit, Limits};
+
+fn drain(f: &mut Fair<&'static str>, now: Instant) -> Vec<&'static str> {
+    let mut out = Vec::new();
+    while let Some((seq, item)) = f.pop(now) {
+        f.finish(seq, Some(item));
+        out.push(item);
+    }
+    out
+}
+
+#[test]
+fn limits_parse_with_default_and_burst() {
+    let l = Limits::parse("*=200, btcusdt=1000:2000").unwrap();
+    assert_eq!(l.get("BTCUSDT"), Some(Limit { rate: 1000.0, burst: 2000.0 }));
+    assert_eq!(l.get("ETHUSDT"), Some(Limit { rate: 200.0, burst: 200.0 }));
+    assert!(Limits::parse("").unwrap().is_empty());
+    assert!(Limits::parse("BTCUSDT=0").is_err() && Limits::parse("BTCUSDT").is_err());
+}
+
+#[test]
+fn a_flooding_symbol_is_throttled_without_starving_the_rest() {
+    let t0 = Instant::now();
+    let mut f = Fair::new(Limits::parse("NEWUSDT=10:2").unwrap(), 100);
+    for _ in 0..5 {
+        f.push("newusdt", "new", t0);
+    }
+    f.push("btcusdt", "btc", t0);
+    f.push("ethusdt", "eth", t0);
+
+    // Two from the burst, taken in turn with the others; the rest wait for tokens.
+    assert_eq!(drain(&mut f, t0), ["new", "btc", "eth", "new"]);
+    assert_eq!(f.queued(), 3);
+    let wake = f.ready_at().unwrap();
+    assert!(wake > t0 && wake <= t0 + Duration::from_millis(100));
+    // The throttled backlog holds commits that come after it.
+    assert_eq!(f.committable(), ["new", "new"]);
+
+    assert_eq!(drain(&mut f, t0 + Duration::from_millis(100)), ["new"]);
+    assert_eq!(drain(&mut f, t0 + Duration::from_secs(1)), ["new", "new"]);
+    assert_eq!(f.committable(), ["new", "new", "new", "btc", "eth"]);
+    assert!(f.ready_at().is_none());
+}
+
+#[test]
+fn a_full_queue_is_served_past_its_limit() {
+    let t0 = Instant::now();
+    let mut f = Fair::new(Limits::parse("*=1:1").unwrap(), 3);
+    for _ in 0..4 {
+        f.push("BTCUSDT", "btc", t0);
+    }
+    // One token, then overflow until the queue is back under its cap.
+    assert_eq!(drain(&mut f, t0).len(), 2);
+    assert_eq!(f.queued(), 2);
+}
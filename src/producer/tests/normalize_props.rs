@@ -0,0 +1,163 @@
+use producer::normalize::{normalize, normalize_as, Venue};
+use proptest::prelude::*;
+use serde_json::{json, Value};
+
+/// Binance-style decimal strings: up to 8 integer digits, always 8 fractional digits; never zero.
+fn decimal_str() -> impl Strategy<Value = String> {
+    (0u64..100_000_000, 0u64..100_000_000)
+        .prop_filter("zero is rejected", |(i, f)| (*i, *f) != (0, 0))
+        .prop_map(|(i, f)| format!("{}.{:08}", i, f))
+}
+
+fn any_json() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::from),
+        any::<i64>().prop_map(Value::from),
+        any::<f64>().prop_map(Value::from),
+        ".*".prop_map(Value::from),
+    ];
+    leaf.prop_recursive(3, 32, 6, |inner| prop_oneof![
+        prop::collection::vec(inner.clone(), 0..6).prop_map(Value::from),
+        prop::collection::hash_map("[sptqTmxE]|.*", inner, 0..8)
+            .prop_map(|m| Value::Object(m.into_iter().collect())),
+    ])
+}
+
+fn raw(symbol: &str, trade_id: i64, price: &str, qty: &str, ts: i64, m: bool) -> String {
+    json!({ "e": "trade", "s": symbol, "t": trade_id, "p": price, "q": qty, "T": ts, "m": m }).to_string()
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_text_never_panics(s in ".*") {
+        let _ = normalize(&s);
+    }
+
+    #[test]
+    fn arbitrary_json_never_panics(v in any_json()) {
+        let _ = normalize(&v.to_string());
+    }
+
+    /// Right keys, wrong-or-random values: must be an error or a finite trade, never a panic or NaN.
+    #[test]
+    fn mistyped_fields_are_rejected_or_finite(
+        s in any_json(), t in any_json(), p in any_json(), q in any_json(), ts in any_json(), m in any_json(),
+    ) {
+        let payload = json!({ "s": s, "t": t, "p": p, "q": q, "T": ts, "m": m }).to_string();
+        if let Ok(n) = normalize(&payload) {
+            prop_assert!(n.price.is_finite() && n.qty.is_finite());
+            prop_assert!(n.price != 0.0 && n.qty != 0.0);
+        }
+    }
+
+    #[test]
+    fn valid_trades_keep_every_field(
+        symbol in ".*", trade_id in any::<i64>(), price in decimal_str(), qty in decimal_str(),
+        ts in any::<i64>(), m in any::<bool>(),
+    ) {
+        let n = normalize(&raw(&symbol, trade_id, &price, &qty, ts, m)).unwrap();
+        prop_assert_eq!(&n.symbol, &symbol);
+        prop_assert_eq!(n.trade_id, trade_id);
+        prop_assert_eq!(n.ts_ms, ts);
+        prop_assert_eq!(n.is_bm, m);
+        prop_assert_eq!(n.price.to_bits(), price.parse::<f64>().unwrap().to_bits());
+        prop_assert_eq!(n.qty.to_bits(), qty.parse::<f64>().unwrap().to_bits());
+    }
+
+    /// What goes onto `ticks.norm` must parse back to the exact same f64s.
+    #[test]
+    fn normalized_json_round_trips_numbers(price in decimal_str(), qty in decimal_str(), x in any::<f64>()) {
+        let n = normalize(&raw("BTCUSDT", 1, &price, &qty, 0, false)).unwrap();
+        let back: Value = serde_json::from_str(&serde_json::to_string(&n).unwrap()).unwrap();
+        prop_assert_eq!(back["price"].as_f64().unwrap().to_bits(), n.price.to_bits());
+        prop_assert_eq!(back["qty"].as_f64().unwrap().to_bits(), n.qty.to_bits());
+
+        // Any finite f64 written by Display must also survive as a decimal string input.
+        prop_assume!(x.is_finite() && x != 0.0);
+        let n = normalize(&raw("BTCUSDT", 1, &x.to_string(), "1", 0, false)).unwrap();
+        prop_assert_eq!(n.price.to_bits(), x.to_bits());
+    }
+}
+
+#[test]
+fn zero_non_finite_and_garbage_numbers_are_errors() {
+    for bad in ["0", "0.00000000", "-0", "NaN", "nan", "inf", "-inf", "infinity", "1e999", "", "1,5", "0x10", "12.3.4"] {
+        assert!(normalize(&raw("BTCUSDT", 1, bad, "1", 0, false)).is_err(), "price {:?} accepted", bad);
+        assert!(normalize(&raw("BTCUSDT", 1, "1", bad, 0, false)).is_err(), "qty {:?} accepted", bad);
+    }
+}
+
+#[test]
+fn agg_trades_keep_their_trade_id_range() {
+    let agg = |f: i64, l: i64| json!({ "e": "aggTrade", "s": "BTCUSDT", "a": 7, "p": "100.5", "q": "0.3",
+        "f": f, "l": l, "T": 1_700_000_000_000i64, "m": true }).to_string();
+    let n = normalize(&agg(40, 43)).unwrap();
+    assert_eq!((n.first_trade_id, n.trade_id, n.qty, n.is_bm), (Some(40), 43, 0.3, true));
+    assert_eq!(serde_json::to_value(&n).unwrap()["first_trade_id"], 40);
+    assert!(normalize(&agg(43, 40)).is_err());
+
+    let n = normalize(&raw("BTCUSDT", 41, "1", "1", 0, false)).unwrap();
+    assert!(n.first_trade_id.is_none() && !serde_json::to_string(&n).unwrap().contains("first_trade_id"));
+}
+
+#[test]
+fn gate_and_kucoin_trades_normalize_to_canonical_symbols() {
+    let gate = r#"{"time":1606292218,"time_ms":1606292218231,"channel":"spot.trades","event":"update","result":{"id":309143071,
+        "create_time":1606292218,"create_time_ms":"1606292218213.4578","side":"sell","currency_pair":"GT_USDT","amount":"16.47","price":"0.4705"}}"#;
+    let n = normalize(gate).unwrap();
+    assert_eq!((n.symbol.as_str(), n.trade_id, n.ts_ms, n.price, n.qty, n.is_bm), ("GTUSDT", 309143071, 1606292218213, 0.4705, 16.47, true));
+    assert_eq!(n.ts_ns, Some(1606292218213457800), "the fraction is kept, not rounded through a float");
+
+    let kucoin = r#"{"type":"message","topic":"/market/match:BTC-USDT","subject":"trade.l3match","data":{"makerOrderId":"5c20c5d03aa6",
+        "price":"3585.5","sequence":"1545896669145","side":"buy","size":"0.01022222","symbol":"BTC-USDT","takerOrderId":"5c24c5d903aa",
+        "time":"1545913818099033203","tradeId":"5c24c5da03aa673885cd67aa","type":"match"}}"#;
+    let n = normalize(kucoin).unwrap();
+    assert_eq!((n.symbol.as_str(), n.trade_id, n.ts_ms, n.price, n.qty, n.is_bm), ("BTCUSDT", 1545896669145, 1545913818099, 3585.5, 0.01022222, false));
+    assert_eq!(n.ts_ns, Some(1545913818099033203));
+
+    // Acks and pongs never reach the producer, but shouldn't pass as trades if they did.
+    assert!(normalize(r#"{"time":1,"channel":"spot.trades","event":"subscribe","result":{"status":"success"}}"#).is_err());
+    assert!(normalize(&kucoin.replace(r#""side":"buy""#, r#""side":"both""#)).is_err());
+}
+
+#[test]
+fn bitstamp_and_gemini_usd_trades_normalize() {
+    let bitstamp = r#"{"data":{"id":348563578,"timestamp":"1724851508","amount":0.0097,"amount_str":"0.00970000","price":59352,
+        "price_str":"59352","type":1,"microtimestamp":"1724851508685124","buy_order_id":1780425633,"sell_order_id":1780425642},
+        "channel":"live_trades_btcusd","event":"trade"}"#;
+    let n = normalize(bitstamp).unwrap();
+    assert_eq!((n.symbol.as_str(), n.trade_id, n.ts_ms, n.price, n.qty, n.is_bm), ("BTCUSD", 348563578, 1724851508685, 59352.0, 0.0097, true));
+    assert_eq!(n.ts_ns, Some(1724851508685124000));
+
+    let gemini = r#"{"type":"trade","symbol":"ETHUSD","event_id":3575573053,"timestamp":1724851508123,"price":"2530.21","quantity":"0.0911","side":"buy"}"#;
+    let n = normalize(gemini).unwrap();
+    assert_eq!((n.symbol.as_str(), n.trade_id, n.ts_ms, n.price, n.qty, n.is_bm), ("ETHUSD", 3575573053, 1724851508123, 2530.21, 0.0911, false));
+    assert!(n.ts_ns.is_none() && !serde_json::to_string(&n).unwrap().contains("ts_ns"), "milliseconds only");
+
+    assert!(normalize(&bitstamp.replace(r#""type":1"#, r#""type":2"#)).is_err());
+}
+
+#[test]
+fn upbit_markets_are_quote_first() {
+    let upbit = r#"{"type":"trade","code":"KRW-BTC","timestamp":1724851508700,"trade_date":"2024-08-28","trade_time":"13:25:08",
+        "trade_timestamp":1724851508685,"trade_price":85100000.0,"trade_volume":0.0035,"ask_bid":"ASK","prev_closing_price":84900000.0,
+        "change":"RISE","change_price":200000.0,"sequential_id":17248515086850000,"stream_type":"REALTIME"}"#;
+    let n = normalize(upbit).unwrap();
+    assert_eq!((n.symbol.as_str(), n.trade_id, n.ts_ms, n.price, n.qty, n.is_bm), ("BTCKRW", 17248515086850000, 1724851508685, 85_100_000.0, 0.0035, true));
+    assert!(normalize(&upbit.replace("KRW-BTC", "KRWBTC")).is_err());
+    assert!(normalize(&upbit.replace(r#""trade_volume":0.0035"#, r#""trade_volume":0"#)).is_err());
+}
+
+#[test]
+fn the_envelopes_exchange_picks_the_format() {
+    assert_eq!(Venue::parse("bithumb"), Some(Venue::Upbit));
+    assert_eq!(Venue::parse("unknown"), None);
+    let upbit = r#"{"type":"trade","code":"KRW-BTC","trade_timestamp":1724851508685,"trade_price":85100000.0,"trade_volume":0.0035,
+        "ask_bid":"ASK","sequential_id":17248515086850000}"#;
+    assert_eq!(normalize_as(Venue::Upbit, upbit).unwrap().symbol, "BTCKRW");
+    // A payload in another venue's format is refused, not read as this one's.
+    assert!(normalize_as(Venue::Gemini, upbit).is_err());
+    assert!(normalize_as(Venue::Gate, &raw("BTCUSDT", 1, "1", "1", 0, false)).is_err());
+    assert_eq!(normalize_as(Venue::Binance, &raw("BTCUSDT", 1, "1", "1", 0, false)).unwrap().trade_id, 1);
+}
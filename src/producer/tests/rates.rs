@@ -0,0 +1,54 @@
+use producer::rates::{split_symbol, Rates};
+
+fn usd() -> Rates {
+    Rates::new("USD", &[("USDT".into(), 1.0), ("USDC".into(), 1.0)], 60_000)
+}
+
+#[test]
+fn symbols_split_on_the_longest_known_quote() {
+    assert_eq!(split_symbol("BTCFDUSD"), Some(("BTC", "FDUSD")));
+    assert_eq!(split_symbol("ETHBTC"), Some(("ETH", "BTC")));
+    assert_eq!(split_symbol("USDT"), None);
+    assert_eq!(split_symbol("XYZABC"), None);
+}
+
+#[test]
+fn cross_pairs_convert_through_the_last_reference_trade() {
+    let r = usd();
+    assert_eq!(r.notional("BTCUSDT", 60_000.0, 0.5, 0), Some(30_000.0));
+    assert_eq!(r.notional("ETHBTC", 0.05, 2.0, 1_000), None, "no BTC rate yet");
+
+    r.observe("BTCUSDT", 60_000.0, 1_000);
+    assert_eq!(r.notional("ETHBTC", 0.05, 2.0, 2_000), Some(6_000.0));
+    assert_eq!(r.notional("ETHBTC", 0.05, 2.0, 61_001), None, "the BTC rate went stale");
+    assert_eq!(r.notional("ETHBTC", 0.05, 2.0, 999), None, "the BTC rate is from after the trade");
+
+    // A pegged base prices the quote: 1 USDT = 40 TRY.
+    r.observe("USDTTRY", 40.0, 0);
+    assert_eq!(r.notional("BTCTRY", 2_400_000.0, 1.0, 0), Some(60_000.0));
+}
+
+#[test]
+fn krw_markets_convert_through_usdt_krw() {
+    let r = usd();
+    assert_eq!(split_symbol("BTCKRW"), Some(("BTC", "KRW")));
+    assert_eq!(r.notional("BTCKRW", 90_000_000.0, 0.1, 0), None);
+
+    r.observe("USDTKRW", 1_500.0, 0);
+    assert_eq!(r.notional("BTCKRW", 90_000_000.0, 0.1, 0), Some(6_000.0));
+    // Neither side is anchored, so the Korean BTC price doesn't replace the reference one.
+    r.observe("BTCUSDT", 58_000.0, 0);
+    r.observe("BTCKRW", 90_000_000.0, 0);
+    assert_eq!(r.rate("BTC", 0), Some(58_000.0));
+}
+
+#[test]
+fn coinm_contracts_are_worth_their_size_whatever_the_price() {
+    let r = usd();
+    assert_eq!(r.contract_notional("BTCUSD_PERP", 3.0, 100.0, 0), Some(300.0));
+    assert_eq!(r.contract_notional("ETHUSD_250627", 5.0, 10.0, 0), Some(50.0));
+    // The futures symbol isn't a pair, so it neither splits nor teaches a rate.
+    assert_eq!(r.notional("BTCUSD_PERP", 60_000.0, 3.0, 0), None);
+    r.observe("BTCUSD_PERP", 60_000.0, 0);
+    assert_eq!(r.rate("BTC", 0), None);
+}
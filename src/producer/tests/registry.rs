@@ -0,0 +1,68 @@
+use producer::normalize::normalize;
+use producer::registry::{norm_trade_schema, problems, Level};
+use serde_json::{json, Value};
+
+#[test]
+fn the_schema_describes_every_field_norm_trade_writes() {
+    let mut t = normalize(r#"{"e":"aggTrade","s":"BTCUSD_PERP","f":1,"l":3,"p":"60000","q":"2","T":1700000000000,"m":false}"#).unwrap();
+    t.session = Some("asia".into());
+    t.notional = Some(200.0);
+    t.contract_size = Some(100.0);
+    t.exchange = Some("binance".into());
+    t.seq = Some(1);
+    t.ingest_ts_ns = Some(1_700_000_000_001_000_000);
+    t.listing = Some("first".into());
+    t.ts_ns = Some(1_700_000_000_000_000_000);
+    let written = serde_json::to_value(&t).unwrap();
+    let schema = norm_trade_schema();
+    let mut fields: Vec<&String> = written.as_object().unwrap().keys().collect();
+    let mut described: Vec<&String> = schema["properties"].as_object().unwrap().keys().collect();
+    fields.sort();
+    described.sort();
+    assert_eq!(fields, described);
+    assert!(problems(Level::Full, &schema, &schema).is_empty());
+}
+
+fn with(f: impl FnOnce(&mut Value)) -> Value {
+    let mut s = norm_trade_schema();
+    f(&mut s);
+    s
+}
+
+#[test]
+fn adding_an_optional_field_is_backward_and_forward_compatible() {
+    let registered = with(|s| { s["properties"].as_object_mut().unwrap().remove("contract_size"); });
+    assert!(problems(Level::Full, &norm_trade_schema(), &registered).is_empty());
+
+    // A reader that forbids extras can't take the new field.
+    let strict = with(|s| {
+        s["properties"].as_object_mut().unwrap().remove("contract_size");
+        s["additionalProperties"] = json!(false);
+    });
+    assert!(problems(Level::Backward, &norm_trade_schema(), &strict).is_empty());
+    assert_eq!(problems(Level::Forward, &norm_trade_schema(), &strict),
+        ["forward: contract_size is sent but the reader forbids additional properties"]);
+}
+
+#[test]
+fn new_requirements_and_type_changes_break_compatibility() {
+    let local = with(|s| s["required"].as_array_mut().unwrap().push(json!("session")));
+    assert_eq!(problems(Level::Backward, &local, &norm_trade_schema()),
+        ["backward: session is required by the reader but not always sent"]);
+    assert!(problems(Level::Forward, &local, &norm_trade_schema()).is_empty());
+
+    let local = with(|s| s["properties"]["trade_id"] = json!({ "type": "string" }));
+    assert_eq!(problems(Level::Full, &local, &norm_trade_schema()).len(), 2);
+    assert!(problems(Level::None, &local, &norm_trade_schema()).is_empty());
+
+    // Widening an integer to a number only reads old data.
+    let local = with(|s| s["properties"]["ts_ms"] = json!({ "type": "number" }));
+    assert!(problems(Level::Backward, &local, &norm_trade_schema()).is_empty());
+    assert_eq!(problems(Level::Forward, &local, &norm_trade_schema()).len(), 1);
+}
+
+#[test]
+fn levels_parse_case_insensitively() {
+    assert_eq!(Level::parse("full").unwrap(), Level::Full);
+    assert!(Level::parse("BACKWARD_TRANSITIVE").is_err());
+}
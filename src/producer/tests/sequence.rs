@@ -0,0 +1,151 @@
+use std::sync::Mutex;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bus::envelope::{Envelope, CLOCK_OFFSET_MS, LISTING, NORM_SCHEMA, RAW_SCHEMA};
+use bus::{Delivery, Headers, Publisher, MSG_ID, TS_PRODUCE_NS};
+use calendar::Calendar;
+use producer::enrich::Enricher;
+use producer::sequence::{Sequencer, Store, RESERVE};
+use producer::stage::{self, Outcome, Topics};
+use serde_json::Value;
+
+#[test]
+fn keys_count_up_from_the_clock_at_first_sight() {
+    let s = Sequencer::new();
+    assert_eq!(s.next_at("binance", "BTCUSDT", 1_000), 1_000);
+    assert_eq!(s.next_at("binance", "BTCUSDT", 5_000), 1_001, "the clock only seeds a new key");
+    assert_eq!(s.next_at("binance", "ETHUSDT", 2_000), 2_000);
+    assert_eq!(s.next_at("kraken", "BTCUSDT", 3_000), 3_000, "keyed by exchange too");
+    assert_eq!(s.next_at("binance", "BTCUSDT", 0), 1_002);
+}
+
+#[tokio::test]
+async fn a_restart_seeds_from_the_saved_ceiling_or_the_clock_whichever_is_later() {
+    let path = std::env::temp_dir().join(format!("seq-{}.json", std::process::id()));
+    let store = Store::new(&path);
+    assert!(store.load().unwrap().is_empty());
+
+    let s = Sequencer::new();
+    assert_eq!(s.next_at("binance", "BTCUSDT", 5_000), 5_000);
+    assert_eq!(s.next_at("binance", "ETHUSDT", 7_000), 7_000);
+    store.save(&s).await.unwrap();
+
+    // The clock stepped back across the restart: the ceiling keeps the numbers ahead.
+    let s = Sequencer::resumed(store.load().unwrap());
+    let ceiling = 5_001 + RESERVE;
+    assert_eq!(s.next_at("binance", "BTCUSDT", 1_000), ceiling);
+    assert_eq!(s.next_at("binance", "BTCUSDT", 1_000), ceiling + 1);
+    // A clock already past the ceiling wins.
+    assert_eq!(s.next_at("binance", "ETHUSDT", 9 * RESERVE), 9 * RESERVE);
+    assert_eq!(s.next_at("kraken", "BTCUSDT", 3_000), 3_000);
+
+    // Saved straight after resuming, before any number: what a crash then reloads is past all of them.
+    let resumed = Sequencer::resumed(store.load().unwrap());
+    store.save(&resumed).await.unwrap();
+    let again = Sequencer::resumed(store.load().unwrap());
+    assert_eq!(again.next_at("binance", "BTCUSDT", 0), ceiling + RESERVE);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[derive(Default)]
+struct Recorder(Mutex<Vec<(String, Vec<u8>)>>);
+
+#[async_trait]
+impl Publisher for Recorder {
+    async fn publish(&self, topic: &str, _key: &str, payload: &[u8], _headers: &Headers) -> Result<()> {
+        self.0.lock().unwrap().push((topic.to_string(), payload.to_vec()));
+        Ok(())
+    }
+}
+
+fn raw(trade_id: i64, exchange: &str) -> Delivery {
+    let payload = format!(r#"{{"e":"trade","s":"BTCUSDT","t":{},"p":"96123.45","q":"0.5","T":1739880000138,"m":true}}"#, trade_id);
+    let headers = Envelope::new(RAW_SCHEMA, "fetcher", "test", exchange, "spot").apply(Headers::new()
+        .with(MSG_ID, &format!("m{}", trade_id))
+        .with(TS_PRODUCE_NS, "1739880000148582245"));
+    Delivery::detached("ticks.raw", Some("btcusdt"), payload.into_bytes(), headers)
+}
+
+#[tokio::test]
+async fn forwarded_trades_carry_exchange_sequence_and_ingest_time() {
+    let publisher = Recorder::default();
+    let topics = Topics { out: "ticks.norm".into(), latest: Some("ticks.latest".into()), listings: None, ..Topics::default() };
+    let envelope = Envelope::new(NORM_SCHEMA, "producer", "test", "binance", "spot");
+    let enricher = Enricher::new(Calendar::parse(calendar::DEFAULT_SESSIONS, "").unwrap(), None);
+    let sequencer = Sequencer::new();
+
+    for (id, exchange) in [(1, "binance"), (2, "binance"), (3, "bybit")] {
+        let outcome = stage::process(&raw(id, exchange), &publisher, &topics, &envelope, &enricher, &sequencer, None).await.unwrap();
+        assert_eq!(outcome, Outcome::Forwarded);
+    }
+
+    let out: Vec<Value> = publisher.0.lock().unwrap().iter()
+        .filter(|(topic, _)| topic == "ticks.norm")
+        .map(|(_, p)| serde_json::from_slice(p).unwrap())
+        .collect();
+    assert_eq!(out.len(), 3);
+    assert_eq!((out[0]["exchange"].as_str(), out[2]["exchange"].as_str()), (Some("binance"), Some("bybit")));
+    assert_eq!(out[0]["ingest_ts_ns"].as_i64(), Some(1739880000148582245));
+    assert_eq!(out[0]["ts_ms"].as_i64(), Some(1739880000138));
+    let seq = |i: usize| out[i]["seq"].as_u64().unwrap();
+    assert_eq!(seq(1), seq(0) + 1);
+    assert!(seq(2) >= seq(0), "another exchange starts its own count, from the clock");
+}
+
+fn marker(event: &str) -> Delivery {
+    let headers = Envelope::new(RAW_SCHEMA, "fetcher", "test", "binance", "spot").apply(Headers::new()
+        .with(MSG_ID, event)
+        .with(LISTING, event));
+    let payload = format!(r#"{{"symbol":"BTCUSDT","event":"{}","ts_ms":1739880000000}}"#, event);
+    Delivery::detached("ticks.raw", Some("btcusdt"), payload.into_bytes(), headers)
+}
+
+#[tokio::test]
+async fn listing_markers_tag_the_first_trade_and_name_the_last() {
+    let publisher = Recorder::default();
+    let topics = Topics { out: "ticks.norm".into(), latest: None, listings: Some("listings".into()), ..Topics::default() };
+    let envelope = Envelope::new(NORM_SCHEMA, "producer", "test", "binance", "spot");
+    let enricher = Enricher::new(Calendar::parse(calendar::DEFAULT_SESSIONS, "").unwrap(), None);
+    let sequencer = Sequencer::new();
+
+    let run = |msg: Delivery| {
+        let (publisher, topics, envelope, enricher, sequencer) = (&publisher, &topics, &envelope, &enricher, &sequencer);
+        async move { stage::process(&msg, publisher, topics, envelope, enricher, sequencer, None).await.unwrap() }
+    };
+    assert_eq!(run(marker("listed")).await, Outcome::Listing);
+    for id in [7, 8] {
+        assert_eq!(run(raw(id, "binance")).await, Outcome::Forwarded);
+    }
+    assert_eq!(run(marker("delisted")).await, Outcome::Listing);
+    assert_eq!(run(marker("paused")).await, Outcome::Dropped);
+
+    let out = publisher.0.lock().unwrap();
+    let on = |t: &str| out.iter().filter(|(topic, _)| topic == t).map(|(_, p)| serde_json::from_slice(p).unwrap()).collect::<Vec<Value>>();
+    let (trades, windows) = (on("ticks.norm"), on("listings"));
+    assert_eq!((trades[0]["listing"].as_str(), trades[1].get("listing")), (Some("first"), None));
+    assert_eq!(windows.len(), 2);
+    assert_eq!((windows[0]["event"].as_str(), windows[0].get("last")), (Some("listed"), None));
+    assert_eq!((windows[1]["exchange"].as_str(), windows[1]["symbol"].as_str()), (Some("binance"), Some("BTCUSDT")));
+    assert_eq!(windows[1]["last"]["trade_id"].as_i64(), Some(8));
+    assert_eq!(windows[1]["last"]["seq"], trades[1]["seq"]);
+}
+
+#[tokio::test]
+async fn clock_offsets_move_trade_times_only_when_correcting() {
+    let topics = Topics { out: "ticks.norm".into(), latest: None, listings: None, ..Topics::default() };
+    let envelope = Envelope::new(NORM_SCHEMA, "producer", "test", "binance", "spot");
+    let sequencer = Sequencer::new();
+    let mut msg = raw(1, "binance");
+    msg.headers = msg.headers.with(CLOCK_OFFSET_MS, "250");
+
+    let mut ts = Vec::new();
+    for correct in [false, true] {
+        let publisher = Recorder::default();
+        let enricher = Enricher::new(Calendar::parse(calendar::DEFAULT_SESSIONS, "").unwrap(), None).correcting_clock(correct);
+        stage::process(&msg, &publisher, &topics, &envelope, &enricher, &sequencer, None).await.unwrap();
+        let out: Value = serde_json::from_slice(&publisher.0.lock().unwrap()[0].1).unwrap();
+        ts.push(out["ts_ms"].as_i64().unwrap());
+    }
+    assert_eq!(ts, [1739880000138, 1739880000138 - 250], "the venue was 250ms ahead");
+}
@@ -0,0 +1,216 @@
+//! One request-weight budget per exchange for every REST call a process makes.
+//!
+//! Exchanges limit REST usage per IP, by weight: Binance allows 6000 a minute
+//! and bans IPs that carry on after a 429. Every caller (auditor paging,
+//! order placement, snapshots) takes its weight from the exchange's shared
+//! [`Limiter`] before sending, and waits its turn, in arrival order, when the
+//! budget is spent. Responses feed back: a `Retry-After` on a 429 or 418
+//! holds everyone until it has passed, and Binance's `X-MBX-USED-WEIGHT-1M`,
+//! which counts other processes on the same IP too, shrinks the budget to
+//! what is really left.
+//!
+//! `REST_LIMITS` sets budgets as `exchange=weight/seconds`, separated by
+//! commas. The default is `binance=4800/60`, a fifth under Binance's limit.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use metrics::{counter, gauge, histogram};
+use reqwest::StatusCode;
+use tokio::time::Instant;
+
+const DEFAULT_LIMITS: &str = "binance=4800/60";
+/// Sends of one request, counting retries after a 429.
+const MAX_ATTEMPTS: u32 = 3;
+/// Hold when a 429 or 418 comes without a usable `Retry-After`.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Budget {
+    pub weight: u32,
+    pub per: Duration,
+}
+
+/// `binance=4800/60,kraken=900/60`: weight per window of seconds.
+pub fn parse_limits(spec: &str) -> Result<HashMap<String, Budget>> {
+    let mut out = HashMap::new();
+    for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (exchange, budget) = item.split_once('=').with_context(|| format!("{:?} is not exchange=weight/seconds", item))?;
+        let (weight, secs) = budget.split_once('/').with_context(|| format!("{:?} is not exchange=weight/seconds", item))?;
+        let weight: u32 = weight.trim().parse().with_context(|| format!("bad weight in {:?}", item))?;
+        let secs: f64 = secs.trim().parse().with_context(|| format!("bad window in {:?}", item))?;
+        anyhow::ensure!(weight > 0 && secs > 0.0 && secs.is_finite(), "weight and window must be positive in {:?}", item);
+        out.insert(exchange.trim().to_lowercase(), Budget { weight, per: Duration::from_secs_f64(secs) });
+    }
+    Ok(out)
+}
+
+/// The header an exchange reports its own count of used weight in.
+fn used_weight_header(exchange: &str) -> Option<&'static str> {
+    match exchange {
+        "binance" => Some("x-mbx-used-weight-1m"),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    tokens: f64,
+    last: Instant,
+    blocked_until: Option<Instant>,
+}
+
+/// A token bucket holding one window's weight, refilled evenly across it.
+#[derive(Debug)]
+pub struct Limiter {
+    exchange: String,
+    budget: Budget,
+    state: Mutex<State>,
+    /// Held while waiting, so callers are served in arrival order.
+    turn: tokio::sync::Mutex<()>,
+}
+
+impl Limiter {
+    pub fn new(exchange: &str, budget: Budget) -> Self {
+        Self {
+            exchange: exchange.to_string(),
+            budget,
+            state: Mutex::new(State { tokens: budget.weight as f64, last: Instant::now(), blocked_until: None }),
+            turn: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    fn rate(&self) -> f64 {
+        self.budget.weight as f64 / self.budget.per.as_secs_f64()
+    }
+
+    fn refill(&self, s: &mut State, now: Instant) {
+        let dt = now.saturating_duration_since(s.last).as_secs_f64();
+        s.tokens = (s.tokens + dt * self.rate()).min(self.budget.weight as f64);
+        s.last = now;
+    }
+
+    /// Weight that could be spent right now.
+    pub fn available(&self) -> f64 {
+        let mut s = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        self.refill(&mut s, Instant::now());
+        match s.blocked_until {
+            Some(b) if b > s.last => 0.0,
+            _ => s.tokens,
+        }
+    }
+
+    /// Wait until `weight` can be spent, then spend it. More than a whole
+    /// window's weight is treated as exactly that.
+    pub async fn acquire(&self, weight: u32) {
+        let started = Instant::now();
+        let need = weight.min(self.budget.weight) as f64;
+        let labels = [("exchange", self.exchange.clone())];
+        gauge!("rest_queued", &labels).increment(1.0);
+        let turn = self.turn.lock().await;
+        loop {
+            let wait = {
+                let mut s = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+                let now = Instant::now();
+                self.refill(&mut s, now);
+                match s.blocked_until {
+                    Some(b) if b > now => b - now,
+                    _ if s.tokens >= need => {
+                        s.tokens -= need;
+                        break;
+                    }
+                    _ => Duration::from_secs_f64((need - s.tokens) / self.rate()),
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+        drop(turn);
+        gauge!("rest_queued", &labels).decrement(1.0);
+        counter!("rest_weight_spent_total", &labels).increment(weight as u64);
+        histogram!("rest_queue_wait_ms", &labels).record(started.elapsed().as_secs_f64() * 1e3);
+    }
+
+    /// Send nothing for `d`: the exchange asked us to back off.
+    pub fn hold(&self, d: Duration) {
+        let mut s = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let until = Instant::now() + d;
+        s.blocked_until = Some(s.blocked_until.map_or(until, |b| b.max(until)));
+        s.tokens = 0.0;
+    }
+
+    /// The exchange counts `used` of its window already spent (by anyone on
+    /// this IP); never plan on more than the rest.
+    pub fn used(&self, used: u32) {
+        let mut s = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        self.refill(&mut s, Instant::now());
+        s.tokens = s.tokens.min(self.budget.weight.saturating_sub(used) as f64);
+        gauge!("rest_weight_used", "exchange" => self.exchange.clone()).set(used as f64);
+    }
+
+    /// Learn from a response; `true` if it was a throttle (429 or 418).
+    pub fn observe(&self, resp: &reqwest::Response) -> bool {
+        let status = resp.status();
+        counter!("rest_requests_total", "exchange" => self.exchange.clone(), "status" => status.as_u16().to_string()).increment(1);
+        if let Some(used) = used_weight_header(&self.exchange)
+            .and_then(|h| resp.headers().get(h))
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+        {
+            self.used(used);
+        }
+        if status != StatusCode::TOO_MANY_REQUESTS && status.as_u16() != 418 {
+            return false;
+        }
+        let retry_after = resp.headers().get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map_or(DEFAULT_RETRY_AFTER, Duration::from_secs);
+        self.hold(retry_after);
+        counter!("rest_throttled_total", "exchange" => self.exchange.clone(), "status" => status.as_u16().to_string()).increment(1);
+        tracing::warn!(target: "ratelimit", exchange = %self.exchange, status = status.as_u16(), ?retry_after,
+            "exchange is throttling us; holding all REST calls");
+        true
+    }
+
+    /// Spend `weight`, send, and learn from the response. A 429 is retried
+    /// once the hold is over (it wasn't processed); a 418 ban is returned as is.
+    pub async fn send(&self, req: reqwest::RequestBuilder, weight: u32) -> Result<reqwest::Response> {
+        let mut req = req;
+        let mut attempt = 1;
+        loop {
+            let retry = req.try_clone();
+            self.acquire(weight).await;
+            let resp = req.send().await?;
+            let throttled = self.observe(&resp);
+            match retry {
+                Some(next) if throttled && resp.status() == StatusCode::TOO_MANY_REQUESTS && attempt < MAX_ATTEMPTS => {
+                    req = next;
+                    attempt += 1;
+                }
+                _ => return Ok(resp),
+            }
+        }
+    }
+}
+
+/// The process-wide limiter for `exchange`, budgeted from `REST_LIMITS`.
+/// Exchanges without a budget get one that never runs out but still
+/// honours `Retry-After`.
+pub fn shared(exchange: &str) -> Result<Arc<Limiter>> {
+    static LIMITERS: OnceLock<Mutex<HashMap<String, Arc<Limiter>>>> = OnceLock::new();
+    static BUDGETS: OnceLock<std::result::Result<HashMap<String, Budget>, String>> = OnceLock::new();
+    let budgets = BUDGETS.get_or_init(|| {
+        let spec = std::env::var("REST_LIMITS").unwrap_or_else(|_| DEFAULT_LIMITS.to_string());
+        parse_limits(&spec).map_err(|e| format!("REST_LIMITS: {:#}", e))
+    });
+    let budgets = budgets.as_ref().map_err(|e| anyhow::anyhow!("{}", e))?;
+    let exchange = exchange.to_lowercase();
+    let mut limiters = LIMITERS.get_or_init(Default::default).lock().unwrap_or_else(PoisonError::into_inner);
+    let limiter = limiters.entry(exchange.clone()).or_insert_with(|| {
+        let budget = budgets.get(&exchange).copied().unwrap_or(Budget { weight: u32::MAX, per: Duration::from_secs(1) });
+        Arc::new(Limiter::new(&exchange, budget))
+    });
+    Ok(limiter.clone())
+}
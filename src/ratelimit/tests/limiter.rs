@@ -0,0 +1,56 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use ratelimit::{parse_limits, Budget, Limiter};
+use tokio::time::Instant;
+
+#[test]
+fn limits_parse() {
+    let limits = parse_limits("binance=4800/60, Kraken=15/1.5").unwrap();
+    assert_eq!(limits["binance"], Budget { weight: 4800, per: Duration::from_secs(60) });
+    assert_eq!(limits["kraken"], Budget { weight: 15, per: Duration::from_millis(1500) });
+    assert!(parse_limits("binance=4800").is_err());
+    assert!(parse_limits("binance=0/60").is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn callers_wait_for_weight_in_arrival_order() {
+    // 10 weight a second: a full bucket, then one unit every 100ms.
+    let limiter = Arc::new(Limiter::new("test", Budget { weight: 10, per: Duration::from_secs(1) }));
+    let start = Instant::now();
+    limiter.acquire(10).await;
+    assert_eq!(start.elapsed(), Duration::ZERO);
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    for (i, weight) in [5u32, 1, 1].into_iter().enumerate() {
+        let (limiter, tx) = (limiter.clone(), tx.clone());
+        tokio::spawn(async move {
+            limiter.acquire(weight).await;
+            tx.send((i, start.elapsed())).unwrap();
+        });
+        tokio::task::yield_now().await;
+    }
+    drop(tx);
+    let mut served = Vec::new();
+    while let Some(s) = rx.recv().await {
+        served.push(s);
+    }
+    // The light callers don't jump the heavy one ahead of them.
+    let order: Vec<usize> = served.iter().map(|(i, _)| *i).collect();
+    assert_eq!(order, vec![0, 1, 2]);
+    let ms = |d: Duration| (d.as_secs_f64() * 1e3).round() as u64;
+    assert_eq!(served.iter().map(|(_, d)| ms(*d)).collect::<Vec<_>>(), vec![500, 600, 700]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn holds_and_reported_usage_shrink_the_budget() {
+    let limiter = Limiter::new("test", Budget { weight: 100, per: Duration::from_secs(10) });
+    limiter.used(90);
+    assert_eq!(limiter.available(), 10.0);
+
+    limiter.hold(Duration::from_secs(30));
+    assert_eq!(limiter.available(), 0.0);
+    let start = Instant::now();
+    limiter.acquire(1).await;
+    assert_eq!(start.elapsed(), Duration::from_secs(30));
+}
@@ -0,0 +1,324 @@
+//! Capture files as they end up archived: NDJSON as `dump` writes it, the
+//! same compressed with gzip, zstd or Snappy (framed, as `snzip` and most
+//! libraries write it), or Parquet with one row per record. [`open`] tells
+//! them apart by their first bytes, not their names.
+//!
+//! With a start time, reading begins at the first record at or after it.
+//! Plain NDJSON is bisected on `ts_ms`, so a large file isn't read from the
+//! top; since partitions interleave, the bisection lands [`SEEK_SLACK_MS`]
+//! early and reads forward from there. Parquet skips whole row groups by
+//! their `ts_ms` statistics. A compressed stream can only be read from the
+//! start, but records before the start time are passed over unparsed. One
+//! cut short fails with an `UnexpectedEof` error saying it's truncated,
+//! whatever the decoder's own words for it.
+
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use flate2::read::MultiGzDecoder;
+use parquet::basic::Compression;
+use parquet::data_type::{ByteArray, ByteArrayType, Int32Type, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::FileReader;
+use parquet::file::serialized_reader::{ReadOptionsBuilder, SerializedFileReader};
+use parquet::file::statistics::Statistics;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::record::{Field, Row};
+use parquet::schema::parser::parse_message_type;
+
+use crate::capture::{line_ts, read_records_from, Blob, Captured};
+
+/// How far before the start time a bisected NDJSON read begins.
+pub const SEEK_SLACK_MS: i64 = 60_000;
+
+/// Records per row group in a Parquet capture.
+pub const ROW_GROUP_ROWS: usize = 10_000;
+
+/// A capture's records, in file order.
+pub type Records = Box<dyn Iterator<Item = Result<Captured>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Ndjson,
+    Gzip,
+    Zstd,
+    Snappy,
+    Parquet,
+}
+
+impl Format {
+    /// The format a file starting with `head` is in; anything unrecognised is taken for NDJSON.
+    pub fn sniff(head: &[u8]) -> Self {
+        match head {
+            [0x1f, 0x8b, ..] => Format::Gzip,
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Format::Zstd,
+            [0xff, 0x06, 0x00, 0x00, b's', b'N', b'a', b'P', b'p', b'Y', ..] => Format::Snappy,
+            [b'P', b'A', b'R', b'1', ..] => Format::Parquet,
+            _ => Format::Ndjson,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Ndjson => "ndjson",
+            Format::Gzip => "gzip",
+            Format::Zstd => "zstd",
+            Format::Snappy => "snappy",
+            Format::Parquet => "parquet",
+        }
+    }
+}
+
+/// The records of the capture at `path`, from the first at or after `from_ts` (ms) when it's set.
+pub fn open(path: &Path, from_ts: Option<i64>) -> Result<Records> {
+    let mut file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut head = Vec::with_capacity(10);
+    file.by_ref().take(10).read_to_end(&mut head)?;
+    file.rewind()?;
+    let format = Format::sniff(&head);
+    tracing::info!(target: "replayer", file = %path.display(), format = format.name(), ?from_ts, "reading capture");
+    Ok(match format {
+        Format::Ndjson => {
+            let mut r = BufReader::new(file);
+            if let Some(ts) = from_ts {
+                let at = bisect(&mut r, ts.saturating_sub(SEEK_SLACK_MS))?;
+                r.seek(SeekFrom::Start(at))?;
+            }
+            Box::new(read_records_from(r, from_ts))
+        }
+        Format::Gzip => decoded(format, file, from_ts, |f| Ok(MultiGzDecoder::new(f)))?,
+        Format::Zstd => decoded(format, file, from_ts, zstd::stream::read::Decoder::new)?,
+        Format::Snappy => decoded(format, file, from_ts, |f| Ok(snap::read::FrameDecoder::new(f)))?,
+        Format::Parquet => parquet(file, from_ts).with_context(|| format!("reading {}", path.display()))?,
+    })
+}
+
+/// A compressed capture's records, read through `decoder`.
+fn decoded<D: Read + 'static>(
+    format: Format,
+    file: File,
+    from_ts: Option<i64>,
+    decoder: impl FnOnce(Tail) -> io::Result<D>,
+) -> Result<Records> {
+    let ended = Rc::new(Cell::new(false));
+    let inner = decoder(Tail { file, ended: ended.clone() })?;
+    Ok(Box::new(read_records_from(BufReader::new(Truncated { inner, format, ended }), from_ts)))
+}
+
+/// The file under a decoder, noting when it has all been read.
+struct Tail {
+    file: File,
+    ended: Rc<Cell<bool>>,
+}
+
+impl Read for Tail {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.file.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            self.ended.set(true);
+        }
+        Ok(n)
+    }
+}
+
+/// A decoder whose failures once the file has run out are reported as truncation:
+/// flate2 says "incomplete deflate stream", zstd "incomplete frame", and so on.
+struct Truncated<D> {
+    inner: D,
+    format: Format,
+    ended: Rc<Cell<bool>>,
+}
+
+impl<D: Read> Read for Truncated<D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf).map_err(|e| match e.kind() == io::ErrorKind::UnexpectedEof || self.ended.get() {
+            true => io::Error::new(io::ErrorKind::UnexpectedEof, format!("{} capture is truncated: {}", self.format.name(), e)),
+            false => e,
+        })
+    }
+}
+
+/// The first non-blank line starting at or after byte `pos`: where it starts, and its `ts_ms`.
+fn line_at<R: BufRead + Seek>(r: &mut R, pos: u64) -> Result<Option<(u64, Option<i64>)>> {
+    let mut start = pos;
+    let mut line = Vec::new();
+    if pos > 0 {
+        // Finish the line `pos` is in; one starting right at `pos` has a newline just before it.
+        r.seek(SeekFrom::Start(pos - 1))?;
+        start = pos - 1 + r.read_until(b'\n', &mut line)? as u64;
+    } else {
+        r.rewind()?;
+    }
+    loop {
+        line.clear();
+        let n = r.read_until(b'\n', &mut line)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        if !line.trim_ascii().is_empty() {
+            // Unreadable lines are left for the read to report.
+            return Ok(Some((start, line_ts(&line).ok().flatten())));
+        }
+        start += n as u64;
+    }
+}
+
+/// Where the first line with a `ts_ms` at or after `ts` starts, for records
+/// in time order; lines without one count as late, so it errs early.
+fn bisect<R: BufRead + Seek>(r: &mut R, ts: i64) -> Result<u64> {
+    let len = r.seek(SeekFrom::End(0))?;
+    let (mut lo, mut hi) = (0, len);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        match line_at(r, mid)? {
+            Some((_, Some(t))) if t < ts => lo = mid + 1,
+            _ => hi = mid,
+        }
+    }
+    Ok(line_at(r, lo)?.map_or(len, |(start, _)| start))
+}
+
+/// One row per record; `headers` is the JSON array an NDJSON capture has.
+const SCHEMA: &str = "
+message capture {
+    REQUIRED BINARY topic (STRING);
+    REQUIRED INT32 partition;
+    REQUIRED INT64 offset;
+    OPTIONAL INT64 ts_ms (TIMESTAMP(MILLIS, true));
+    OPTIONAL BINARY key;
+    OPTIONAL BINARY payload;
+    REQUIRED BINARY headers (STRING);
+}";
+
+const TS_COLUMN: usize = 3;
+
+fn parquet(file: File, from_ts: Option<i64>) -> Result<Records> {
+    let mut options = ReadOptionsBuilder::new();
+    if let Some(from) = from_ts {
+        // Groups with no statistics are read; the row filter below still applies.
+        options = options.with_predicate(Box::new(move |rg, _| match rg.column(TS_COLUMN).statistics() {
+            Some(Statistics::Int64(s)) => s.max_opt().is_none_or(|max| *max >= from),
+            _ => true,
+        }));
+    }
+    let reader = SerializedFileReader::new_with_options(file, options.build())?;
+    let columns = reader.metadata().file_metadata().schema_descr().num_columns();
+    anyhow::ensure!(columns == 7, "not a capture: {} columns", columns);
+    let mut waiting = from_ts;
+    Ok(Box::new(reader.into_iter().filter_map(move |row| {
+        let rec = match row.map_err(Into::into).and_then(|r| from_row(&r)) {
+            Ok(rec) => rec,
+            Err(e) => return Some(Err(e)),
+        };
+        if let Some(from) = waiting {
+            match rec.ts_ms {
+                Some(ts) if ts >= from => waiting = None,
+                _ => return None,
+            }
+        }
+        Some(Ok(rec))
+    })))
+}
+
+fn from_row(row: &Row) -> Result<Captured> {
+    let fields: Vec<&Field> = row.get_column_iter().map(|(_, f)| f).collect();
+    let bytes = |f: &Field| match f {
+        Field::Bytes(b) => Ok(Some(Blob(b.data().to_vec()))),
+        Field::Str(s) => Ok(Some(Blob(s.as_bytes().to_vec()))),
+        Field::Null => Ok(None),
+        other => Err(anyhow::anyhow!("expected bytes, got {}", other)),
+    };
+    let [Field::Str(topic), Field::Int(partition), Field::Long(offset), ts, key, payload, Field::Str(headers)] = fields.as_slice() else {
+        anyhow::bail!("not a capture row: {}", row);
+    };
+    let ts_ms = match ts {
+        Field::TimestampMillis(t) | Field::Long(t) => Some(*t),
+        _ => None,
+    };
+    Ok(Captured {
+        topic: topic.clone(),
+        partition: *partition,
+        offset: *offset,
+        ts_ms,
+        key: bytes(key)?,
+        payload: bytes(payload)?,
+        headers: serde_json::from_str(headers).context("bad headers column")?,
+    })
+}
+
+/// Writes a Parquet capture, a row group every [`ROW_GROUP_ROWS`] records.
+pub struct ParquetWriter<W: std::io::Write + Send> {
+    writer: SerializedFileWriter<W>,
+    buf: Vec<Captured>,
+}
+
+impl<W: std::io::Write + Send> ParquetWriter<W> {
+    pub fn new(w: W) -> Result<Self> {
+        let props = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+        let writer = SerializedFileWriter::new(w, Arc::new(parse_message_type(SCHEMA)?), Arc::new(props))?;
+        Ok(Self { writer, buf: Vec::new() })
+    }
+
+    pub fn write(&mut self, rec: &Captured) -> Result<()> {
+        self.buf.push(rec.clone());
+        if self.buf.len() >= ROW_GROUP_ROWS {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let recs = std::mem::take(&mut self.buf);
+        let text = |s: &str| ByteArray::from(s.as_bytes().to_vec());
+        // Optional columns: values for the present ones, and a definition level per row.
+        let optional = |get: &dyn Fn(&Captured) -> Option<&Blob>| {
+            let values: Vec<ByteArray> = recs.iter().filter_map(get).map(|b| ByteArray::from(b.0.clone())).collect();
+            let levels: Vec<i16> = recs.iter().map(|r| get(r).is_some() as i16).collect();
+            (values, levels)
+        };
+        let mut rg = self.writer.next_row_group()?;
+        let mut i = 0;
+        while let Some(mut col) = rg.next_column()? {
+            match i {
+                0 => col.typed::<ByteArrayType>().write_batch(&recs.iter().map(|r| text(&r.topic)).collect::<Vec<_>>(), None, None)?,
+                1 => col.typed::<Int32Type>().write_batch(&recs.iter().map(|r| r.partition).collect::<Vec<_>>(), None, None)?,
+                2 => col.typed::<Int64Type>().write_batch(&recs.iter().map(|r| r.offset).collect::<Vec<_>>(), None, None)?,
+                3 => {
+                    let values: Vec<i64> = recs.iter().filter_map(|r| r.ts_ms).collect();
+                    let levels: Vec<i16> = recs.iter().map(|r| r.ts_ms.is_some() as i16).collect();
+                    col.typed::<Int64Type>().write_batch(&values, Some(&levels), None)?
+                }
+                4 | 5 => {
+                    let (values, levels) = match i {
+                        4 => optional(&|r| r.key.as_ref()),
+                        _ => optional(&|r| r.payload.as_ref()),
+                    };
+                    col.typed::<ByteArrayType>().write_batch(&values, Some(&levels), None)?
+                }
+                _ => {
+                    let headers = recs.iter().map(|r| Ok(text(&serde_json::to_string(&r.headers)?))).collect::<Result<Vec<_>>>()?;
+                    col.typed::<ByteArrayType>().write_batch(&headers, None, None)?
+                }
+            };
+            col.close()?;
+            i += 1;
+        }
+        rg.close()?;
+        Ok(())
+    }
+
+    /// Write what's buffered and the footer; the file can't be read without it.
+    pub fn finish(mut self) -> Result<()> {
+        self.flush()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
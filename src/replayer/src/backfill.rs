@@ -0,0 +1,161 @@
+//! Rebuild a topic from QuestDB: read trades back out for a time range and
+//! republish them in the `ticks.norm` shape, with fresh `msg_id`s and a
+//! `replayed=true` header so consumers can tell them from live traffic.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use bus::envelope::{Envelope, NORM_SCHEMA};
+use bus::{BusConfig, Headers, Publisher, MSG_ID, REPLAYED, TS_PRODUCE_NS};
+use chrono::{DateTime, Utc};
+use consumer::schema::Client;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use producer::enrich::Enricher;
+use producer::normalize::NormTrade;
+use uuid::Uuid;
+
+#[derive(Debug, clap::Args)]
+pub struct BackfillArgs {
+    /// Window start (RFC 3339).
+    #[arg(long)]
+    pub from: DateTime<Utc>,
+    /// Window end, exclusive (RFC 3339).
+    #[arg(long)]
+    pub to: DateTime<Utc>,
+    /// Only these symbols (comma-separated; default: all).
+    #[arg(long, value_delimiter = ',')]
+    pub symbols: Vec<String>,
+    #[arg(long, env = "TOPIC_OUT", default_value = "ticks.norm")]
+    pub topic: String,
+    #[arg(long, env = "QDB_HTTP_URL", default_value = "http://localhost:9000")]
+    pub qdb_http: String,
+    #[arg(long, default_value = "trades")]
+    pub table: String,
+    /// Envelope exchange/market; QuestDB rows don't record them.
+    #[arg(long, env = "EXCHANGE", default_value = "binance")]
+    pub exchange: String,
+    #[arg(long, env = "MARKET", default_value = "spot")]
+    pub market: String,
+    /// Rows fetched from QuestDB per query.
+    #[arg(long, default_value_t = 10_000)]
+    pub page_size: u64,
+    /// Fixed rate in msgs/sec (default: as fast as the broker acks).
+    #[arg(long)]
+    pub rate: Option<f64>,
+    #[arg(long, default_value_t = 1000)]
+    pub max_in_flight: usize,
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// The page of trades starting `offset` rows in, in a stable order.
+pub fn query(args: &BackfillArgs, offset: u64) -> String {
+    let mut sql = format!(
+        "SELECT symbol, price, qty, trade_id, is_bm, ts_ms FROM {} \
+         WHERE timestamp >= cast({} AS timestamp) AND timestamp < cast({} AS timestamp)",
+        obsv::namespace::table(&args.table), args.from.timestamp_micros(), args.to.timestamp_micros(),
+    );
+    if !args.symbols.is_empty() {
+        let list: Vec<String> = args.symbols.iter().map(|s| quote(s.trim())).collect();
+        sql.push_str(&format!(" AND symbol IN ({})", list.join(", ")));
+    }
+    // Stable order so LIMIT paging neither skips nor repeats rows.
+    sql.push_str(&format!(" ORDER BY timestamp, symbol, trade_id LIMIT {}, {}", offset, offset + args.page_size));
+    sql
+}
+
+async fn page(client: &Client, args: &BackfillArgs, offset: u64) -> Result<Vec<NormTrade>> {
+    let resp = client.rows(&query(args, offset)).await?;
+    let col = |name: &str| resp.column(name);
+    let (sym, price, qty, id, bm, ts) = (col("symbol")?, col("price")?, col("qty")?, col("trade_id")?, col("is_bm")?, col("ts_ms")?);
+    resp.dataset.iter()
+        .map(|row| Ok(NormTrade {
+            symbol: row[sym].as_str().context("symbol")?.to_string(),
+            price: row[price].as_f64().context("price")?,
+            qty: row[qty].as_f64().context("qty")?,
+            trade_id: row[id].as_i64().context("trade_id")?,
+            is_bm: row[bm].as_bool().context("is_bm")?,
+            ts_ms: row[ts].as_i64().context("ts_ms")?,
+            session: None,
+            notional: None,
+            first_trade_id: None,
+            contract_size: None,
+            exchange: Some(args.exchange.clone()),
+            // Backfilled trades arrive out of order by design; only live ones are sequenced.
+            seq: None,
+            ingest_ts_ns: None,
+            listing: None,
+            ts_ns: None,
+        }))
+        .collect()
+}
+
+/// Returns (sent, failed).
+pub async fn run(bus: &BusConfig, args: &BackfillArgs) -> Result<(u64, u64)> {
+    anyhow::ensure!(args.from < args.to, "--from must be before --to");
+    let client = Client::authed(&args.qdb_http).await?.with_timeout(Duration::from_secs(60))?;
+    backfill(&client, bus.publisher().await?, args).await
+}
+
+/// [`run`] with the QuestDB client and publisher given.
+pub async fn backfill(client: &Client, publisher: Arc<dyn Publisher>, args: &BackfillArgs) -> Result<(u64, u64)> {
+    let envelope = Envelope::new(NORM_SCHEMA, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), &args.exchange, &args.market);
+    // Same session tags and notional the producer would have given them.
+    let enricher = Enricher::from_env()?;
+
+    let start = Instant::now();
+    let (mut sent, mut failed, mut offset) = (0u64, 0u64, 0u64);
+    let mut in_flight = FuturesUnordered::new();
+    loop {
+        let rows = page(client, args, offset).await?;
+        let done = (rows.len() as u64) < args.page_size;
+        offset += rows.len() as u64;
+
+        for mut t in rows {
+            enricher.apply(&mut t);
+            let i = sent + failed + in_flight.len() as u64;
+            if let Some(rate) = args.rate.filter(|r| *r > 0.0) {
+                // Keep acks flowing while pacing; a publish only progresses when polled.
+                let due = tokio::time::sleep_until((start + Duration::from_secs_f64(i as f64 / rate)).into());
+                tokio::pin!(due);
+                loop {
+                    tokio::select! {
+                        _ = &mut due => break,
+                        Some(res) = in_flight.next(), if !in_flight.is_empty() => tally(res, &mut sent, &mut failed),
+                    }
+                }
+            }
+
+            let headers = envelope.apply(Headers::new()
+                .with(MSG_ID, &Uuid::new_v4().to_string())
+                .with(TS_PRODUCE_NS, &Utc::now().timestamp_nanos_opt().unwrap().to_string())
+                .with(REPLAYED, "true"));
+            let body = serde_json::to_vec(&t)?;
+            let (publisher, topic) = (publisher.clone(), args.topic.as_str());
+            in_flight.push(async move { publisher.publish(topic, &t.symbol, &body, &headers).await });
+
+            while in_flight.len() >= args.max_in_flight.max(1) {
+                let Some(res) = in_flight.next().await else { break };
+                tally(res, &mut sent, &mut failed);
+            }
+        }
+        tracing::info!(target: "replayer", offset, sent, failed, "backfill progress");
+        if done {
+            break;
+        }
+    }
+    while let Some(res) = in_flight.next().await {
+        tally(res, &mut sent, &mut failed);
+    }
+    Ok((sent, failed))
+}
+
+fn tally(res: Result<()>, sent: &mut u64, failed: &mut u64) {
+    match res {
+        Ok(()) => *sent += 1,
+        Err(e) => { *failed += 1; tracing::error!(target: "replayer", error = ?e, "backfill publish failed"); }
+    }
+}
@@ -0,0 +1,107 @@
+//! Capture file format: NDJSON, one Kafka record per line.
+//!
+//! Keys, payloads and header values that are valid UTF-8 are stored as plain
+//! JSON strings (readable, still byte-identical on the way back); anything
+//! else is stored as `{"b64": "..."}`.
+
+use std::io::{BufRead, Write};
+
+use anyhow::Result;
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Raw bytes with the text-or-base64 encoding described above.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blob(pub Vec<u8>);
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum BlobRepr {
+    Text(String),
+    Binary { b64: String },
+}
+
+impl Serialize for Blob {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match std::str::from_utf8(&self.0) {
+            Ok(t) => BlobRepr::Text(t.to_string()),
+            Err(_) => BlobRepr::Binary { b64: B64.encode(&self.0) },
+        }
+        .serialize(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for Blob {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        match BlobRepr::deserialize(d)? {
+            BlobRepr::Text(t) => Ok(Blob(t.into_bytes())),
+            BlobRepr::Binary { b64 } => B64.decode(b64).map(Blob).map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Captured {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    /// Broker timestamp (ms); replay pacing is derived from the gaps between these.
+    pub ts_ms: Option<i64>,
+    pub key: Option<Blob>,
+    pub payload: Option<Blob>,
+    #[serde(default)]
+    pub headers: Vec<(String, Option<Blob>)>,
+}
+
+impl Captured {
+    pub fn header(&self, key: &str) -> Option<&[u8]> {
+        self.headers.iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, v)| v.as_ref())
+            .map(|b| b.0.as_slice())
+    }
+}
+
+pub fn write_record<W: Write>(w: &mut W, rec: &Captured) -> Result<()> {
+    serde_json::to_writer(&mut *w, rec)?;
+    w.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Lazily read records, skipping blank lines.
+pub fn read_records<R: BufRead>(r: R) -> impl Iterator<Item = Result<Captured>> {
+    read_records_from(r, None)
+}
+
+/// Just the timestamp, so records before a start time are passed over
+/// without decoding their payloads.
+#[derive(Deserialize)]
+struct Stamp {
+    ts_ms: Option<i64>,
+}
+
+/// The timestamp of one capture line, if it has one.
+pub fn line_ts(line: &[u8]) -> Result<Option<i64>> {
+    Ok(serde_json::from_slice::<Stamp>(line)?.ts_ms)
+}
+
+/// [`read_records`], starting at the first record at or after `from_ts` (ms).
+pub fn read_records_from<R: BufRead>(r: R, from_ts: Option<i64>) -> impl Iterator<Item = Result<Captured>> {
+    let mut waiting = from_ts;
+    r.lines().filter_map(move |line| {
+        let l = match line {
+            Ok(l) if l.trim().is_empty() => return None,
+            Ok(l) => l,
+            Err(e) => return Some(Err(e.into())),
+        };
+        if let Some(from) = waiting {
+            match line_ts(l.as_bytes()) {
+                Ok(Some(ts)) if ts >= from => waiting = None,
+                Ok(_) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        Some(serde_json::from_str(&l).map_err(Into::into))
+    })
+}
@@ -0,0 +1,115 @@
+//! Run captured traffic through every wire codec and compare size and cost.
+//!
+//! Per message: normalized trade → encode → decode → ILP line, i.e. everything
+//! the codec choice touches between the producer and QuestDB. Timings are
+//! single-threaded wall clock, so on an idle core they track CPU time.
+
+use std::path::PathBuf;
+use std::time::Instant;
+
+use anyhow::Result;
+use codec::{Codec, Trade};
+use consumer::ilp::{to_ilp_line, NormTrade};
+
+use crate::archive;
+
+#[derive(Debug, clap::Args)]
+pub struct CodecBenchArgs {
+    /// Capture of `ticks.raw` (normalized first) or `ticks.norm`, in any [`archive`] format.
+    #[arg(long)]
+    pub file: PathBuf,
+    /// Passes over the capture per codec; the first is discarded as warmup.
+    #[arg(long, default_value_t = 20)]
+    pub iterations: usize,
+    /// Codecs to compare (default: all).
+    #[arg(long, value_delimiter = ',')]
+    pub codecs: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct CodecReport {
+    pub codec: Codec,
+    pub messages: usize,
+    pub bytes_per_msg: f64,
+    pub encode_ns: f64,
+    pub decode_ns: f64,
+    pub e2e_p50_ns: u64,
+    pub e2e_p99_ns: u64,
+}
+
+/// Trades from a capture; records that are neither raw nor normalized trades are skipped.
+pub fn load_trades(args: &CodecBenchArgs) -> Result<Vec<Trade>> {
+    let mut trades = Vec::new();
+    for rec in archive::open(&args.file, None)? {
+        let Some(payload) = rec?.payload else { continue };
+        let Ok(text) = std::str::from_utf8(&payload.0) else { continue };
+        if let Ok(n) = producer::normalize::normalize(text) {
+            trades.push(Trade { ts_ms: n.ts_ms, symbol: n.symbol, price: n.price, qty: n.qty, trade_id: n.trade_id, is_bm: n.is_bm, ts_ns: n.ts_ns, ingest_ts_ns: n.ingest_ts_ns });
+        } else if let Ok(t) = serde_json::from_str::<Trade>(text) {
+            trades.push(t);
+        }
+    }
+    anyhow::ensure!(!trades.is_empty(), "no trades in {}", args.file.display());
+    Ok(trades)
+}
+
+fn ilp(t: Trade) -> String {
+    let n = NormTrade { ts_ms: t.ts_ms, symbol: t.symbol, price: t.price, qty: t.qty, trade_id: t.trade_id, is_bm: t.is_bm, session: None, notional: None, first_trade_id: None, contract_size: None, ts_ns: None, ingest_ts_ns: None };
+    to_ilp_line(&n, "00000000-0000-0000-0000-000000000000")
+}
+
+fn bench(c: Codec, trades: &[Trade], iterations: usize) -> Result<CodecReport> {
+    let (mut bytes, mut enc_ns, mut dec_ns) = (0u64, 0u128, 0u128);
+    let mut e2e = Vec::with_capacity(trades.len() * iterations);
+    for pass in 0..iterations.max(2) {
+        for t in trades {
+            let t0 = Instant::now();
+            let wire = c.encode(t)?;
+            let t1 = Instant::now();
+            let back = c.decode(&wire)?;
+            let t2 = Instant::now();
+            anyhow::ensure!(&back == t, "{} changed trade {} on round trip", c.name(), t.trade_id);
+            std::hint::black_box(ilp(back));
+            let t3 = Instant::now();
+            if pass > 0 {
+                bytes += wire.len() as u64;
+                enc_ns += (t1 - t0).as_nanos();
+                dec_ns += (t2 - t1).as_nanos();
+                e2e.push((t3 - t0).as_nanos() as u64);
+            }
+        }
+    }
+    e2e.sort_unstable();
+    let n = e2e.len();
+    Ok(CodecReport {
+        codec: c,
+        messages: trades.len(),
+        bytes_per_msg: bytes as f64 / n as f64,
+        encode_ns: enc_ns as f64 / n as f64,
+        decode_ns: dec_ns as f64 / n as f64,
+        e2e_p50_ns: e2e[n / 2],
+        e2e_p99_ns: e2e[(n * 99 / 100).min(n - 1)],
+    })
+}
+
+pub fn run(args: &CodecBenchArgs) -> Result<Vec<CodecReport>> {
+    let codecs = match args.codecs.is_empty() {
+        true => Codec::ALL.to_vec(),
+        false => args.codecs.iter()
+            .map(|s| Codec::parse(s).ok_or_else(|| anyhow::anyhow!("unknown codec {:?}", s)))
+            .collect::<Result<_>>()?,
+    };
+    let trades = load_trades(args)?;
+    codecs.into_iter().map(|c| bench(c, &trades, args.iterations)).collect()
+}
+
+pub fn print(reports: &[CodecReport]) {
+    let json = reports.iter().find(|r| r.codec == Codec::Json).map(|r| r.bytes_per_msg);
+    println!("{:<8} {:>8} {:>10} {:>8} {:>11} {:>11} {:>9} {:>9}",
+        "codec", "msgs", "bytes/msg", "vs json", "encode ns", "decode ns", "p50 ns", "p99 ns");
+    for r in reports {
+        let ratio = json.map(|j| format!("{:.2}x", r.bytes_per_msg / j)).unwrap_or_else(|| "-".into());
+        println!("{:<8} {:>8} {:>10.1} {:>8} {:>11.0} {:>11.0} {:>9} {:>9}",
+            r.codec.name(), r.messages, r.bytes_per_msg, ratio, r.encode_ns, r.decode_ns, r.e2e_p50_ns, r.e2e_p99_ns);
+    }
+}
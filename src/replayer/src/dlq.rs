@@ -0,0 +1,238 @@
+//! Put dead-lettered records back where they came from.
+//!
+//! A DLQ record is republished unchanged, or after a fix-up of its JSON
+//! payload. It keeps its key and `msg_id`. The DLQ headers are replaced by
+//! provenance: where it was dead-lettered from and when it was put back.
+//! It is never marked `replayed`: the consumer skips replayed messages, and
+//! these rows were never stored.
+//!
+//! Records the consumer dropped before any sink saw them (see its
+//! `DROPPED_DLQ`, which defaults to the first sink DLQ) carry a `dlq_reason`.
+//! They failed a signature check, had an unsupported schema or didn't decode,
+//! so republishing them unchanged would only have them dropped again. They're
+//! skipped unless `--dropped` asks for them, e.g. with a fix-up that mends them.
+//!
+//! Progress is committed under `--group`, so a second run starts after the
+//! first run's last republished record rather than republishing it again.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bus::{BusConfig, Transport};
+use chrono::Utc;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::message::{Header, Headers, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{Message, Offset, TopicPartitionList};
+use serde_json::Value;
+
+const META_TIMEOUT: Duration = Duration::from_secs(10);
+/// Header names the consumer's DLQs set (see `consumer::tee`).
+const DLQ_HEADERS: [&str; 3] = ["dlq_sink", "dlq_error", "dlq_reason"];
+pub const REPLAYED_FROM: &str = "dlq_replayed_from";
+pub const REPLAYED_AT_NS: &str = "dlq_replayed_at_ns";
+pub const ERROR_WAS: &str = "dlq_error_was";
+
+#[derive(Debug, clap::Args)]
+pub struct DlqArgs {
+    /// The dead-letter topic to drain, e.g. sink.dlq.
+    #[arg(long)]
+    pub topic: String,
+    /// Where records go back to.
+    #[arg(long, default_value = "ticks.norm")]
+    pub to: String,
+    /// Consumer group holding progress through the DLQ.
+    #[arg(long, default_value = "replayer-dlq")]
+    pub group: String,
+    /// Only records whose `dlq_error` contains this.
+    #[arg(long)]
+    pub error_contains: Option<String>,
+    /// Only records dead-lettered by this sink.
+    #[arg(long)]
+    pub sink: Option<String>,
+    /// Also republish records the consumer dropped unread (those with a `dlq_reason`).
+    #[arg(long)]
+    pub dropped: bool,
+    /// Fix-up: set a JSON field, `field=<json>` (values that aren't valid JSON are strings).
+    #[arg(long = "set", value_name = "FIELD=VALUE")]
+    pub set: Vec<String>,
+    /// Fix-up: rename a field, `old=new`.
+    #[arg(long = "rename", value_name = "OLD=NEW")]
+    pub rename: Vec<String>,
+    /// Fix-up: remove a field.
+    #[arg(long = "remove", value_name = "FIELD")]
+    pub remove: Vec<String>,
+    /// Show what would be republished; nothing is sent or committed.
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Give up if no record arrives for this long (seconds).
+    #[arg(long, default_value_t = 30)]
+    pub idle_timeout: u64,
+}
+
+/// Edits to a record's top-level JSON object, applied renames, then sets, then removes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fixup {
+    pub rename: Vec<(String, String)>,
+    pub set: Vec<(String, Value)>,
+    pub remove: Vec<String>,
+}
+
+fn pair(s: &str) -> Result<(String, String)> {
+    let (a, b) = s.split_once('=').with_context(|| format!("expected a=b, got {:?}", s))?;
+    Ok((a.to_string(), b.to_string()))
+}
+
+impl Fixup {
+    pub fn parse(set: &[String], rename: &[String], remove: &[String]) -> Result<Self> {
+        Ok(Self {
+            rename: rename.iter().map(|s| pair(s)).collect::<Result<_>>()?,
+            set: set.iter()
+                .map(|s| pair(s).map(|(k, v)| (k, serde_json::from_str(&v).unwrap_or(Value::String(v)))))
+                .collect::<Result<_>>()?,
+            remove: remove.to_vec(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rename.is_empty() && self.set.is_empty() && self.remove.is_empty()
+    }
+
+    /// The fixed payload; the payload itself when there's nothing to do.
+    pub fn apply(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        if self.is_empty() {
+            return Ok(payload.to_vec());
+        }
+        let mut v: Value = serde_json::from_slice(payload).context("fix-ups need a JSON payload")?;
+        let obj = v.as_object_mut().context("fix-ups need a JSON object")?;
+        for (old, new) in &self.rename {
+            if let Some(x) = obj.remove(old) {
+                obj.insert(new.clone(), x);
+            }
+        }
+        for (k, x) in &self.set {
+            obj.insert(k.clone(), x.clone());
+        }
+        for k in &self.remove {
+            obj.remove(k);
+        }
+        Ok(serde_json::to_vec(&v)?)
+    }
+}
+
+/// Whether a record with `headers` is picked out by `--error-contains`,
+/// `--sink` and `--dropped`.
+pub fn picked(headers: &[(String, Option<Vec<u8>>)], error_contains: Option<&str>, sink: Option<&str>, dropped: bool) -> bool {
+    let header = |name: &str| headers.iter().find(|h| h.0 == name).and_then(|h| h.1.as_deref()).map(String::from_utf8_lossy);
+    error_contains.is_none_or(|want| header("dlq_error").is_some_and(|e| e.contains(want)))
+        && sink.is_none_or(|want| header("dlq_sink").as_deref() == Some(want))
+        && (dropped || header("dlq_reason").is_none())
+}
+
+/// Headers for the republished record: the originals minus the DLQ's, plus provenance.
+pub fn provenance(headers: &[(String, Option<Vec<u8>>)], from: &str, at_ns: i64) -> Vec<(String, Option<Vec<u8>>)> {
+    let error = headers.iter().find(|h| h.0 == "dlq_error").and_then(|h| h.1.clone());
+    let mut out: Vec<_> = headers.iter()
+        .filter(|h| !DLQ_HEADERS.contains(&h.0.as_str()) && ![REPLAYED_FROM, REPLAYED_AT_NS, ERROR_WAS].contains(&h.0.as_str()))
+        .cloned()
+        .collect();
+    out.push((REPLAYED_FROM.into(), Some(from.as_bytes().to_vec())));
+    out.push((REPLAYED_AT_NS.into(), Some(at_ns.to_string().into_bytes())));
+    if let Some(e) = error {
+        out.push((ERROR_WAS.into(), Some(e)));
+    }
+    out
+}
+
+/// Returns (republished, skipped by the filters). Stops at each partition's
+/// high watermark as of the start, so records dead-lettered again mid-run
+/// wait for the next run.
+pub async fn run(bus: &BusConfig, args: &DlqArgs) -> Result<(u64, u64)> {
+    anyhow::ensure!(bus.transport == Transport::Kafka, "dlq replays Kafka topics; TRANSPORT must be kafka");
+    let topic = &bus.topic(&args.topic);
+    let fixup = Fixup::parse(&args.set, &args.rename, &args.remove)?;
+    let consumer: StreamConsumer = bus.kafka_config()
+        .set("group.id", bus.group(&args.group))
+        .set("enable.auto.commit", "false")
+        .set("enable.partition.eof", "false")
+        .create()?;
+    let producer: FutureProducer = bus.kafka_config().create()?;
+    let to = bus.topic(&args.to);
+
+    let md = consumer.fetch_metadata(Some(topic), META_TIMEOUT)?;
+    let mut wanted = TopicPartitionList::new();
+    for p in md.topics().iter().flat_map(|t| t.partitions()) {
+        wanted.add_partition(topic, p.id());
+    }
+    let committed = consumer.committed_offsets(wanted, META_TIMEOUT)?;
+    let mut tpl = TopicPartitionList::new();
+    let mut end_of: HashMap<i32, i64> = HashMap::new();
+    for e in committed.elements() {
+        let (low, high) = consumer.fetch_watermarks(topic, e.partition(), META_TIMEOUT)?;
+        let start = match e.offset() {
+            Offset::Offset(o) => o.max(low),
+            _ => low,
+        };
+        if start < high {
+            tpl.add_partition_offset(topic, e.partition(), Offset::Offset(start))?;
+            end_of.insert(e.partition(), high);
+            tracing::info!(target: "replayer", partition = e.partition(), start, end = high, "dlq range");
+        }
+    }
+    if end_of.is_empty() {
+        tracing::info!(target: "replayer", topic = %topic, "DLQ is drained");
+        return Ok((0, 0));
+    }
+    consumer.assign(&tpl)?;
+
+    let (mut sent, mut skipped) = (0u64, 0u64);
+    let mut done = TopicPartitionList::new();
+    while !end_of.is_empty() {
+        let msg = tokio::time::timeout(Duration::from_secs(args.idle_timeout), consumer.recv()).await
+            .map_err(|_| anyhow::anyhow!("no records for {}s with partitions {:?} unfinished", args.idle_timeout, end_of.keys()))??;
+        let Some(&end) = end_of.get(&msg.partition()) else { continue };
+        if msg.offset() >= end {
+            end_of.remove(&msg.partition());
+            continue;
+        }
+        let headers: Vec<(String, Option<Vec<u8>>)> = msg.headers()
+            .map(|h| h.iter().map(|h| (h.key.to_string(), h.value.map(<[u8]>::to_vec))).collect())
+            .unwrap_or_default();
+        let keep = picked(&headers, args.error_contains.as_deref(), args.sink.as_deref(), args.dropped);
+
+        if keep {
+            let from = format!("{}:{}:{}", msg.topic(), msg.partition(), msg.offset());
+            let payload = fixup.apply(msg.payload().unwrap_or_default()).with_context(|| format!("record {}", from))?;
+            let out = provenance(&headers, &from, Utc::now().timestamp_nanos_opt().unwrap_or_default());
+            if args.dry_run {
+                println!("{} -> {}: {}", from, to, String::from_utf8_lossy(&payload));
+            } else {
+                let mut owned = OwnedHeaders::new();
+                for (k, v) in &out {
+                    owned = owned.insert(Header { key: k, value: v.as_deref() });
+                }
+                let mut record = FutureRecord::to(&to).payload(&payload).headers(owned);
+                if let Some(k) = msg.key() {
+                    record = record.key(k);
+                }
+                producer.send(record, Duration::from_secs(30)).await
+                    .map_err(|(e, _)| anyhow::anyhow!("republishing {}: {}", from, e))?;
+            }
+            sent += 1;
+        } else {
+            skipped += 1;
+        }
+        done.set_partition_offset(msg.topic(), msg.partition(), Offset::Offset(msg.offset() + 1))?;
+        if !args.dry_run && (sent + skipped) % 1000 == 0 {
+            consumer.commit(&done, CommitMode::Sync)?;
+        }
+        if msg.offset() + 1 >= end {
+            end_of.remove(&msg.partition());
+        }
+    }
+    if !args.dry_run && done.count() > 0 {
+        consumer.commit(&done, CommitMode::Sync)?;
+    }
+    Ok((sent, skipped))
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use bus::BusConfig;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::Headers;
+use rdkafka::{Message, Offset, TopicPartitionList};
+
+use crate::archive::ParquetWriter;
+use crate::capture::{write_record, Blob, Captured};
+
+const META_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Dump a topic range, headers included, to a capture file.
+#[derive(Debug, clap::Args)]
+pub struct DumpArgs {
+    #[arg(long, env = "TOPIC_IN")]
+    pub topic: String,
+    /// Output file (`-` for stdout); one ending in `.parquet` is written as Parquet.
+    #[arg(long, default_value = "-")]
+    pub out: PathBuf,
+    /// Only this partition (default: all).
+    #[arg(long)]
+    pub partition: Option<i32>,
+    /// First offset to include (default: low watermark).
+    #[arg(long)]
+    pub from_offset: Option<i64>,
+    /// Stop before this offset (default: high watermark at start).
+    #[arg(long)]
+    pub to_offset: Option<i64>,
+    /// First broker timestamp (ms) to include; overrides --from-offset.
+    #[arg(long)]
+    pub from_ts: Option<i64>,
+    /// Stop at the first record at/after this timestamp (ms); overrides --to-offset.
+    #[arg(long)]
+    pub to_ts: Option<i64>,
+    /// Give up if no record arrives for this long (seconds).
+    #[arg(long, default_value_t = 30)]
+    pub idle_timeout: u64,
+}
+
+fn offset_for_time(c: &StreamConsumer, topic: &str, partition: i32, ts: i64) -> Result<Option<i64>> {
+    let mut q = TopicPartitionList::new();
+    q.add_partition_offset(topic, partition, Offset::Offset(ts))?;
+    let r = c.offsets_for_times(q, META_TIMEOUT)?;
+    Ok(r.find_partition(topic, partition).and_then(|p| match p.offset() {
+        Offset::Offset(o) => Some(o),
+        _ => None,
+    }))
+}
+
+fn to_captured<M: Message>(m: &M) -> Captured {
+    Captured {
+        topic: m.topic().to_string(),
+        partition: m.partition(),
+        offset: m.offset(),
+        ts_ms: m.timestamp().to_millis(),
+        key: m.key().map(|k| Blob(k.to_vec())),
+        payload: m.payload().map(|p| Blob(p.to_vec())),
+        headers: m.headers()
+            .map(|h| h.iter().map(|h| (h.key.to_string(), h.value.map(|v| Blob(v.to_vec())))).collect())
+            .unwrap_or_default(),
+    }
+}
+
+enum Out {
+    Ndjson(Box<dyn Write>),
+    Parquet(ParquetWriter<File>),
+}
+
+impl Out {
+    fn write(&mut self, rec: &Captured) -> Result<()> {
+        match self {
+            Out::Ndjson(w) => write_record(w, rec),
+            Out::Parquet(w) => w.write(rec),
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            Out::Ndjson(mut w) => Ok(w.flush()?),
+            Out::Parquet(w) => w.finish(),
+        }
+    }
+}
+
+pub async fn run(bus: &BusConfig, args: &DumpArgs) -> Result<u64> {
+    let topic = &bus.topic(&args.topic);
+    let consumer: StreamConsumer = bus.kafka_config()
+        .set("group.id", "replayer-dump")
+        .set("enable.auto.commit", "false")
+        .set("enable.partition.eof", "false")
+        .create()?;
+
+    let md = consumer.fetch_metadata(Some(topic), META_TIMEOUT)?;
+    let partitions: Vec<i32> = md.topics().iter()
+        .flat_map(|t| t.partitions().iter().map(|p| p.id()))
+        .filter(|p| args.partition.is_none_or(|want| want == *p))
+        .collect();
+    anyhow::ensure!(!partitions.is_empty(), "topic {} has no matching partitions", topic);
+
+    // Resolve [start, end) per partition up front so the dump has a fixed size.
+    let mut tpl = TopicPartitionList::new();
+    let mut end_of: HashMap<i32, i64> = HashMap::new();
+    for p in partitions {
+        let (low, high) = consumer.fetch_watermarks(topic, p, META_TIMEOUT)?;
+        let start = match args.from_ts {
+            Some(ts) => offset_for_time(&consumer, topic, p, ts)?.unwrap_or(high),
+            None => args.from_offset.unwrap_or(low).max(low),
+        };
+        let end = match args.to_ts {
+            Some(ts) => offset_for_time(&consumer, topic, p, ts)?.unwrap_or(high),
+            None => args.to_offset.unwrap_or(high).min(high),
+        };
+        if start < end {
+            tpl.add_partition_offset(topic, p, Offset::Offset(start))?;
+            end_of.insert(p, end);
+            tracing::info!(target: "replayer", partition = p, start, end, "dump range");
+        }
+    }
+    if end_of.is_empty() {
+        tracing::warn!(target: "replayer", "nothing to dump in the requested range");
+        return Ok(0);
+    }
+    consumer.assign(&tpl)?;
+
+    let mut out = match args.out.extension().is_some_and(|e| e == "parquet") {
+        true => Out::Parquet(ParquetWriter::new(File::create(&args.out)?)?),
+        false if args.out.as_os_str() == "-" => Out::Ndjson(Box::new(BufWriter::new(std::io::stdout().lock()))),
+        false => Out::Ndjson(Box::new(BufWriter::new(File::create(&args.out)?))),
+    };
+
+    let mut written = 0u64;
+    while !end_of.is_empty() {
+        let msg = tokio::time::timeout(Duration::from_secs(args.idle_timeout), consumer.recv()).await
+            .map_err(|_| anyhow::anyhow!("no records for {}s with partitions {:?} unfinished", args.idle_timeout, end_of.keys()))??;
+        let Some(&end) = end_of.get(&msg.partition()) else { continue };
+        if msg.offset() < end {
+            out.write(&to_captured(&msg))?;
+            written += 1;
+        }
+        if msg.offset() + 1 >= end {
+            end_of.remove(&msg.partition());
+        }
+    }
+    out.finish()?;
+    Ok(written)
+}
@@ -0,0 +1,15 @@
+//! Record/replay harness: dump a Kafka topic range (headers included) to a
+//! capture file and replay it byte-identically at a controlled speed, so
+//! stage changes can be regression-tested against real captured traffic.
+//! `backfill` rebuilds a topic from what QuestDB already stores, and
+//! `codec_bench` compares wire codecs on captured traffic, and `dlq` puts
+//! dead-lettered records back on their topic. Captures can be read back
+//! compressed or as Parquet (see [`archive`]).
+
+pub mod archive;
+pub mod backfill;
+pub mod capture;
+pub mod codec_bench;
+pub mod dlq;
+pub mod dump;
+pub mod replay;
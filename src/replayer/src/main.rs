@@ -0,0 +1,60 @@
+use anyhow::Result;
+use bus::BusConfig;
+use clap::{Parser, Subcommand};
+use obsv::init_tracing;
+use replayer::{backfill, codec_bench, dlq, dump, replay};
+
+#[derive(Parser)]
+#[command(about = "Dump Kafka topic ranges to capture files, replay them, or backfill topics from QuestDB")]
+struct Cli {
+    #[command(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Subcommand)]
+enum Cmd {
+    Dump(dump::DumpArgs),
+    Replay(replay::ReplayArgs),
+    Backfill(backfill::BackfillArgs),
+    /// Compare JSON, MessagePack and Protobuf on a capture file.
+    CodecBench(codec_bench::CodecBenchArgs),
+    /// Republish a dead-letter topic's records, optionally fixed up, to their original topic.
+    Dlq(dlq::DlqArgs),
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!(), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    let cli = Cli::parse();
+    let bus = BusConfig::from_env()?;
+    // The codec bench runs offline.
+    if !matches!(cli.cmd, Cmd::CodecBench(_)) {
+        bus::audit::start(&bus).await;
+    }
+
+    match cli.cmd {
+        Cmd::CodecBench(args) => codec_bench::print(&codec_bench::run(&args)?),
+        Cmd::Dump(args) => {
+            let n = dump::run(&bus, &args).await?;
+            tracing::info!(target: "replayer", records = n, out = %args.out.display(), "dump complete");
+        }
+        Cmd::Replay(args) => {
+            let (sent, failed) = replay::run(&bus, &args).await?;
+            tracing::info!(target: "replayer", sent, failed, "replay complete");
+            anyhow::ensure!(failed == 0, "{} records failed to replay", failed);
+        }
+        Cmd::Backfill(args) => {
+            let (sent, failed) = backfill::run(&bus, &args).await?;
+            tracing::info!(target: "replayer", sent, failed, topic = %args.topic, "backfill complete");
+            anyhow::ensure!(failed == 0, "{} trades failed to backfill", failed);
+        }
+        Cmd::Dlq(args) => {
+            let (sent, skipped) = dlq::run(&bus, &args).await?;
+            tracing::info!(target: "replayer", sent, skipped, from = %args.topic, to = %args.to, dry_run = args.dry_run, "dlq replay complete");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use bus::{BusConfig, TS_PRODUCE_NS};
+use chrono::Utc;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::future_producer::OwnedDeliveryResult;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+
+use crate::archive;
+use crate::capture::Captured;
+
+/// Replay a capture file byte-identically at a controlled speed.
+#[derive(Debug, clap::Args)]
+pub struct ReplayArgs {
+    /// NDJSON, gzip/zstd/Snappy-compressed NDJSON, or Parquet (see `archive`).
+    #[arg(long)]
+    pub file: PathBuf,
+    /// Start at the first record with a broker timestamp (ms) at or after this.
+    #[arg(long)]
+    pub from_ts: Option<i64>,
+    /// Target topic, in the namespace (default: each record's original topic, as captured).
+    #[arg(long, env = "TOPIC_OUT")]
+    pub topic: Option<String>,
+    /// Pace relative to the captured timestamps (2.0 = twice as fast, 0 = no pacing).
+    #[arg(long, default_value_t = 1.0)]
+    pub speed: f64,
+    /// Fixed rate in msgs/sec instead of captured pacing.
+    #[arg(long)]
+    pub rate: Option<f64>,
+    /// Produce to the captured partition instead of letting the key pick.
+    #[arg(long)]
+    pub keep_partition: bool,
+    /// Keep the captured broker timestamps instead of stamping replay time.
+    #[arg(long)]
+    pub keep_timestamps: bool,
+    /// Rewrite `ts_produce_ns` to replay time so downstream E2E latency stays meaningful.
+    /// Off by default: without it every byte, headers included, is replayed as captured.
+    #[arg(long)]
+    pub restamp: bool,
+    #[arg(long, default_value_t = 1000)]
+    pub max_in_flight: usize,
+}
+
+fn due_at(args: &ReplayArgs, start: Instant, ts0: Option<i64>, i: u64, rec: &Captured) -> Option<Instant> {
+    if let Some(rate) = args.rate.filter(|r| *r > 0.0) {
+        return Some(start + Duration::from_secs_f64(i as f64 / rate));
+    }
+    if args.speed <= 0.0 {
+        return None;
+    }
+    let gap_ms = (rec.ts_ms? - ts0?).max(0) as f64;
+    Some(start + Duration::from_secs_f64(gap_ms / 1000.0 / args.speed))
+}
+
+/// Returns (sent, failed).
+pub async fn run(bus: &BusConfig, args: &ReplayArgs) -> Result<(u64, u64)> {
+    let producer: FutureProducer = bus.kafka_config().create()?;
+    let target = args.topic.as_deref().map(|t| bus.topic(t));
+    let records = archive::open(&args.file, args.from_ts)?;
+
+    let start = Instant::now();
+    let mut ts0 = None;
+    let (mut sent, mut failed) = (0u64, 0u64);
+    let mut in_flight = FuturesUnordered::new();
+
+    for (i, rec) in records.enumerate() {
+        let rec = rec?;
+        ts0 = ts0.or(rec.ts_ms);
+        if let Some(due) = due_at(args, start, ts0, i as u64, &rec) {
+            tokio::time::sleep_until(due.into()).await;
+        }
+
+        let now_ns = Utc::now().timestamp_nanos_opt().unwrap().to_string();
+        let headers = rec.headers.iter().fold(OwnedHeaders::new(), |h, (k, v)| {
+            let value = if args.restamp && k == TS_PRODUCE_NS {
+                Some(now_ns.as_bytes())
+            } else {
+                v.as_ref().map(|b| b.0.as_slice())
+            };
+            h.insert(Header { key: k, value })
+        });
+        let topic = target.as_deref().unwrap_or(&rec.topic);
+        let mut record: FutureRecord<'_, [u8], [u8]> = FutureRecord::to(topic).headers(headers);
+        if let Some(k) = &rec.key { record = record.key(k.0.as_slice()); }
+        if let Some(p) = &rec.payload { record = record.payload(p.0.as_slice()); }
+        if args.keep_partition { record = record.partition(rec.partition); }
+        if args.keep_timestamps {
+            if let Some(ts) = rec.ts_ms { record = record.timestamp(ts); }
+        }
+
+        match producer.send_result(record) {
+            Ok(fut) => in_flight.push(fut),
+            Err((e, _)) => { failed += 1; tracing::error!(target: "replayer", error = ?e, "replay enqueue failed"); }
+        }
+        while in_flight.len() >= args.max_in_flight.max(1) {
+            let Some(res) = in_flight.next().await else { break };
+            tally(res, &mut sent, &mut failed);
+        }
+    }
+    while let Some(res) = in_flight.next().await {
+        tally(res, &mut sent, &mut failed);
+    }
+    Ok((sent, failed))
+}
+
+/// The outer error is the delivery future being canceled (producer dropped).
+fn tally<E>(res: Result<OwnedDeliveryResult, E>, sent: &mut u64, failed: &mut u64) {
+    match res {
+        Ok(Ok(_)) => *sent += 1,
+        Ok(Err((e, _))) => { *failed += 1; tracing::error!(target: "replayer", error = ?e, "replay delivery failed"); }
+        Err(_) => { *failed += 1; tracing::error!(target: "replayer", "replay delivery canceled"); }
+    }
+}
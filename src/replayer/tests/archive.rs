@@ -0,0 +1,123 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use replayer::archive::{self, Format, ParquetWriter, ROW_GROUP_ROWS, SEEK_SLACK_MS};
+use replayer::capture::{write_record, Blob, Captured};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("replayer-archive-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+fn load(path: &Path, from_ts: Option<i64>) -> anyhow::Result<Vec<Captured>> {
+    archive::open(path, from_ts)?.collect()
+}
+
+fn record(offset: i64, ts_ms: Option<i64>) -> Captured {
+    Captured {
+        topic: "ticks.raw".into(),
+        partition: (offset % 3) as i32,
+        offset,
+        ts_ms,
+        key: Some(Blob(b"btcusdt".to_vec())),
+        payload: match offset % 2 {
+            0 => Some(Blob(format!(r#"{{"t":{}}}"#, offset).into_bytes())),
+            _ => Some(Blob(vec![0xff, offset as u8])),
+        },
+        headers: vec![("msg_id".into(), Some(Blob(format!("m{}", offset).into_bytes()))), ("empty".into(), None)],
+    }
+}
+
+#[test]
+fn compressed_captures_read_as_the_plain_one() {
+    let plain = load(&fixture("ticks_raw_btcusdt.ndjson"), None).unwrap();
+    assert!(!plain.is_empty());
+    for name in ["ticks_raw_btcusdt.ndjson.gz", "ticks_raw_btcusdt.ndjson.zst"] {
+        assert_eq!(load(&fixture(name), None).unwrap(), plain, "{}", name);
+    }
+
+    let snappy = scratch("raw.ndjson.sz");
+    let mut w = snap::write::FrameEncoder::new(File::create(&snappy).unwrap());
+    w.write_all(&std::fs::read(fixture("ticks_raw_btcusdt.ndjson")).unwrap()).unwrap();
+    drop(w);
+    assert_eq!(load(&snappy, None).unwrap(), plain);
+
+    assert_eq!(Format::sniff(&std::fs::read(fixture("ticks_raw_btcusdt.ndjson.zst")).unwrap()), Format::Zstd);
+    assert_eq!(Format::sniff(b"{\"topic\""), Format::Ndjson);
+}
+
+#[test]
+fn concatenated_streams_are_read_through_and_cut_ones_fail() {
+    let gz = std::fs::read(fixture("ticks_raw_btcusdt.ndjson.gz")).unwrap();
+    let twice = scratch("twice.ndjson.gz");
+    std::fs::write(&twice, [gz.as_slice(), gz.as_slice()].concat()).unwrap();
+    let plain = load(&fixture("ticks_raw_btcusdt.ndjson"), None).unwrap();
+    assert_eq!(load(&twice, None).unwrap().len(), plain.len() * 2);
+
+    for name in ["ticks_raw_btcusdt.ndjson.gz", "ticks_raw_btcusdt.ndjson.zst"] {
+        let bytes = std::fs::read(fixture(name)).unwrap();
+        let cut = scratch(&format!("cut-{}", name));
+        std::fs::write(&cut, &bytes[..bytes.len() - 20]).unwrap();
+        let err = load(&cut, None).unwrap_err();
+        let io = err.downcast_ref::<std::io::Error>().unwrap_or_else(|| panic!("{}: {:#}", name, err));
+        assert_eq!(io.kind(), std::io::ErrorKind::UnexpectedEof, "{}: {:#}", name, err);
+        assert!(format!("{:#}", err).contains("truncated"), "{}: {:#}", name, err);
+    }
+}
+
+#[test]
+fn a_start_time_skips_what_came_before() {
+    let plain = load(&fixture("ticks_raw_btcusdt.ndjson"), None).unwrap();
+    let from = plain[plain.len() / 2].ts_ms.unwrap();
+    let expected: Vec<Captured> = plain.iter().skip_while(|r| r.ts_ms < Some(from)).cloned().collect();
+    for name in ["ticks_raw_btcusdt.ndjson", "ticks_raw_btcusdt.ndjson.gz", "ticks_raw_btcusdt.ndjson.zst"] {
+        assert_eq!(load(&fixture(name), Some(from)).unwrap(), expected, "{}", name);
+    }
+    assert!(load(&fixture("ticks_raw_btcusdt.ndjson"), Some(i64::MAX)).unwrap().is_empty());
+}
+
+#[test]
+fn large_plain_captures_are_bisected_to_the_start_time() {
+    // A record a second for a day, a few of them out of order by less than the slack.
+    let path = scratch("day.ndjson");
+    let mut w = BufWriter::new(File::create(&path).unwrap());
+    for i in 0..86_400i64 {
+        let jitter = if i % 97 == 0 { -(SEEK_SLACK_MS / 2) } else { 0 };
+        write_record(&mut w, &record(i, Some(i * 1000 + jitter))).unwrap();
+        if i % 1000 == 0 {
+            w.write_all(b"\n").unwrap();
+        }
+    }
+    drop(w);
+    let from = 50_000 * 1000;
+    let recs = load(&path, Some(from)).unwrap();
+    assert_eq!(recs[0].offset, 50_000);
+    assert_eq!(recs.len(), 36_400, "everything after the start is kept, early or not");
+    assert_eq!(load(&path, Some(i64::MIN)).unwrap().len(), 86_400);
+}
+
+#[test]
+fn parquet_captures_round_trip_and_skip_row_groups() {
+    let path = scratch("capture.parquet");
+    let recs: Vec<Captured> = (0..(ROW_GROUP_ROWS as i64 * 2 + 5))
+        .map(|i| record(i, (i % 7 != 3).then_some(1_000 + i)))
+        .collect();
+    let mut w = ParquetWriter::new(File::create(&path).unwrap()).unwrap();
+    for r in &recs {
+        w.write(r).unwrap();
+    }
+    w.finish().unwrap();
+
+    assert_eq!(Format::sniff(&std::fs::read(&path).unwrap()), Format::Parquet);
+    assert_eq!(load(&path, None).unwrap(), recs);
+    let from = 1_000 + ROW_GROUP_ROWS as i64 + 1;
+    let tail = load(&path, Some(from)).unwrap();
+    assert_eq!(tail[0].ts_ms, Some(from));
+    assert_eq!(tail.as_slice(), &recs[ROW_GROUP_ROWS + 1..]);
+}
@@ -0,0 +1,95 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bus::{Headers, Publisher, MSG_ID, REPLAYED};
+use chrono::{TimeZone, Utc};
+use consumer::schema::Client;
+use replayer::backfill::{backfill, query, BackfillArgs};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+fn args(symbols: &[&str], page_size: u64) -> BackfillArgs {
+    BackfillArgs {
+        from: Utc.timestamp_millis_opt(1_717_200_000_000).unwrap(),
+        to: Utc.timestamp_millis_opt(1_717_203_600_000).unwrap(),
+        symbols: symbols.iter().map(|s| s.to_string()).collect(),
+        topic: "ticks.norm".into(),
+        qdb_http: String::new(),
+        table: "trades".into(),
+        exchange: "binance".into(),
+        market: "spot".into(),
+        page_size,
+        rate: None,
+        max_in_flight: 10,
+    }
+}
+
+#[test]
+fn pages_are_read_in_a_stable_order_for_the_window_and_symbols() {
+    let sql = query(&args(&["BTCUSDT", " O'BRIEN"], 500), 1000);
+    assert_eq!(sql, "SELECT symbol, price, qty, trade_id, is_bm, ts_ms FROM trades \
+        WHERE timestamp >= cast(1717200000000000 AS timestamp) AND timestamp < cast(1717203600000000 AS timestamp) \
+        AND symbol IN ('BTCUSDT', 'O''BRIEN') ORDER BY timestamp, symbol, trade_id LIMIT 1000, 1500");
+    assert!(!query(&args(&[], 500), 0).contains("symbol IN"));
+}
+
+#[derive(Default)]
+struct Recorder(Mutex<Vec<(String, String, Value, Headers)>>);
+
+#[async_trait]
+impl Publisher for Recorder {
+    async fn publish(&self, topic: &str, key: &str, payload: &[u8], headers: &Headers) -> Result<()> {
+        self.0.lock().unwrap().push((topic.to_string(), key.to_string(), serde_json::from_slice(payload)?, headers.clone()));
+        Ok(())
+    }
+}
+
+/// Answers `/exec` with each page in turn, one connection per request.
+async fn questdb(pages: Vec<Value>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        for page in pages {
+            let (mut sock, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 8192];
+            let mut n = 0;
+            while !buf[..n].windows(4).any(|w| w == b"\r\n\r\n") {
+                n += sock.read(&mut buf[n..]).await.unwrap();
+            }
+            let body = page.to_string();
+            let resp = format!("HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}", body.len(), body);
+            sock.write_all(resp.as_bytes()).await.unwrap();
+        }
+    });
+    format!("http://{}", addr)
+}
+
+fn page(rows: Value) -> Value {
+    let columns: Vec<Value> = ["symbol", "price", "qty", "trade_id", "is_bm", "ts_ms"].iter().map(|c| json!({ "name": c })).collect();
+    json!({ "columns": columns, "dataset": rows })
+}
+
+#[tokio::test]
+async fn trades_are_republished_with_fresh_ids_and_the_replayed_header() {
+    let base = questdb(vec![
+        page(json!([["BTCUSDT", 65000.5, 0.1, 7, true, 1_717_200_000_000i64], ["ETHUSDT", 3500.0, 2.0, 8, false, 1_717_200_000_500i64]])),
+        page(json!([["BTCUSDT", 65001.0, 0.2, 9, false, 1_717_200_001_000i64]])),
+    ]).await;
+    let recorder = Arc::new(Recorder::default());
+    let (sent, failed) = backfill(&Client::new(&base), recorder.clone(), &args(&[], 2)).await.unwrap();
+    assert_eq!((sent, failed), (3, 0));
+
+    let mut published = recorder.0.lock().unwrap().clone();
+    published.sort_by_key(|(_, _, t, _)| t["trade_id"].as_i64());
+    let ids: Vec<(&str, &str, i64)> = published.iter().map(|(topic, key, t, _)| (topic.as_str(), key.as_str(), t["trade_id"].as_i64().unwrap())).collect();
+    assert_eq!(ids, [("ticks.norm", "BTCUSDT", 7), ("ticks.norm", "ETHUSDT", 8), ("ticks.norm", "BTCUSDT", 9)]);
+    let (_, _, first, headers) = &published[0];
+    assert_eq!((first["price"].as_f64(), first["is_bm"].as_bool(), first["exchange"].as_str()), (Some(65000.5), Some(true), Some("binance")));
+    assert!(first.get("seq").is_none(), "only live trades are sequenced");
+    assert!(published.iter().all(|(_, _, _, h)| h.get(REPLAYED) == Some("true")));
+    let msg_ids: std::collections::HashSet<_> = published.iter().filter_map(|(_, _, _, h)| h.get(MSG_ID)).collect();
+    assert_eq!(msg_ids.len(), 3);
+    assert!(headers.get(MSG_ID).is_some_and(|id| id.len() == 36));
+}
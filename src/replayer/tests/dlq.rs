@@ -0,0 +1,36 @@
+use replayer::dlq::{picked, provenance, Fixup, ERROR_WAS, REPLAYED_FROM};
+use serde_json::{json, Value};
+
+#[test]
+fn fixups_rename_then_set_then_remove() {
+    let f = Fixup::parse(&["qty=0.5".into(), "venue=binance".into()], &["quantity=qty".into()], &["bogus".into()]).unwrap();
+    let out = f.apply(br#"{"symbol":"BTCUSDT","quantity":"1","bogus":true}"#).unwrap();
+    assert_eq!(serde_json::from_slice::<Value>(&out).unwrap(), json!({"symbol": "BTCUSDT", "qty": 0.5, "venue": "binance"}));
+
+    assert_eq!(Fixup::default().apply(b"not json").unwrap(), b"not json");
+    assert!(f.apply(b"not json").is_err());
+    assert!(Fixup::parse(&["novalue".into()], &[], &[]).is_err());
+}
+
+#[test]
+fn provenance_replaces_the_dlq_headers() {
+    let h = |k: &str, v: &str| (k.to_string(), Some(v.as_bytes().to_vec()));
+    let out = provenance(&[h("msg_id", "m1"), h("dlq_sink", "archive"), h("dlq_error", "disk full"), h("schema_version", "1.2")], "sink.dlq:0:7", 5);
+    let names: Vec<&str> = out.iter().map(|h| h.0.as_str()).collect();
+    assert_eq!(names, ["msg_id", "schema_version", REPLAYED_FROM, "dlq_replayed_at_ns", ERROR_WAS]);
+    assert_eq!(out[2].1.as_deref(), Some(&b"sink.dlq:0:7"[..]));
+    assert_eq!(out[4].1.as_deref(), Some(&b"disk full"[..]));
+    assert!(!names.contains(&"replayed"), "the consumer would skip it");
+}
+
+#[test]
+fn records_the_consumer_dropped_are_only_taken_when_asked_for() {
+    let h = |k: &str, v: &str| (k.to_string(), Some(v.as_bytes().to_vec()));
+    let failed_write = [h("dlq_sink", "questdb"), h("dlq_error", "connection refused")];
+    let dropped = [h("dlq_reason", "signature"), h("dlq_error", "bad signature")];
+    assert!(picked(&failed_write, None, None, false));
+    assert!(!picked(&dropped, None, None, false), "it would only be dropped again");
+    assert!(picked(&dropped, Some("signature"), None, true));
+    assert!(!picked(&failed_write, None, Some("archive"), true));
+    assert!(!picked(&failed_write, Some("disk"), None, false));
+}
@@ -0,0 +1,385 @@
+//! One way to retry, for every call that can fail and be tried again:
+//! WebSocket and FIX reconnects, Kafka publishes, ILP writes, REST calls
+//! and sink flushes.
+//!
+//! A [`Policy`] is an exponential [`Backoff`] with jitter, so a fleet that
+//! lost the same dependency doesn't come back in step, plus how many
+//! retries to allow (or none: until it works). Two shared guards ride on
+//! top. A [`Budget`] caps retries per process, `RETRY_BUDGET` as
+//! `retries/seconds` (unset: no cap), so an outage doesn't multiply the
+//! load on whatever is struggling. A [`Breaker`] opens after a run of
+//! consecutive failures and fails callers fast until its cooldown has
+//! passed, when one try is let through to see if the dependency is back.
+//! Policies that retry forever wait out a spent budget or an open breaker
+//! instead of giving up.
+//!
+//! Every retry is counted by `op` (`questdb_write`, `ws_connect`): in
+//! `retries_total`, `retry_backoff_ms`, `retry_exhausted_total` when the
+//! attempts ran out and `retry_budget_denied_total` when the budget did.
+//! Breakers export `breaker_state{op}` (0 closed, 1 half-open, 2 open) and
+//! `breaker_opened_total{op}`.
+
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use metrics::{counter, gauge, histogram};
+use tokio::time::Instant;
+
+/// How far [`Backoff::delay`] may shorten a step, as a fraction of it.
+pub const DEFAULT_JITTER: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    /// The wait after the first failure.
+    pub initial: Duration,
+    pub max: Duration,
+    /// Growth per attempt; 2 doubles.
+    pub multiplier: f64,
+    /// 0 waits exactly [`step`](Backoff::step); 0.2 up to a fifth less.
+    pub jitter: f64,
+}
+
+impl Backoff {
+    /// Doubling from `initial` up to `max`, with [`DEFAULT_JITTER`].
+    pub const fn new(initial: Duration, max: Duration) -> Self {
+        Self { initial, max, multiplier: 2.0, jitter: DEFAULT_JITTER }
+    }
+
+    pub fn with_jitter(self, jitter: f64) -> Self {
+        Self { jitter: jitter.clamp(0.0, 1.0), ..self }
+    }
+
+    /// `{prefix}_BACKOFF_MS` and `{prefix}_BACKOFF_MAX_MS`, or the defaults.
+    pub fn from_env(prefix: &str, initial: Duration, max: Duration) -> Self {
+        let ms = |k: String, default: Duration| {
+            std::env::var(k).ok().and_then(|v| v.parse().ok()).map(Duration::from_millis).unwrap_or(default)
+        };
+        Self::new(ms(format!("{}_BACKOFF_MS", prefix), initial), ms(format!("{}_BACKOFF_MAX_MS", prefix), max))
+    }
+
+    /// The un-jittered wait after failed attempt `attempt` (1-based).
+    pub fn step(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.max(1.0).powi(attempt.saturating_sub(1).min(64) as i32);
+        Duration::try_from_secs_f64(self.initial.as_secs_f64() * factor).unwrap_or(self.max).min(self.max)
+    }
+
+    /// [`step`](Backoff::step), less a random share of up to `jitter` of it.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let step = self.step(attempt);
+        if self.jitter <= 0.0 {
+            return step;
+        }
+        step.mul_f64(1.0 - self.jitter.min(1.0) * rand::random::<f64>())
+    }
+}
+
+impl Default for Backoff {
+    /// 100ms doubling to 30s.
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100), Duration::from_secs(30))
+    }
+}
+
+/// A token bucket of retries, shared by everything that draws on it.
+#[derive(Debug)]
+pub struct Budget {
+    capacity: f64,
+    per_sec: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl Budget {
+    /// `retries` every `per`, up to `retries` at once.
+    pub fn new(retries: u32, per: Duration) -> Self {
+        let capacity = retries.max(1) as f64;
+        Self { capacity, per_sec: capacity / per.as_secs_f64().max(0.001), state: Mutex::new((capacity, Instant::now())) }
+    }
+
+    /// Parse `retries/seconds`, as in `RETRY_BUDGET`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (n, secs) = spec.split_once('/').context("expected retries/seconds")?;
+        let n: u32 = n.trim().parse().with_context(|| format!("retries {:?}", n))?;
+        let secs: u64 = secs.trim().parse().with_context(|| format!("seconds {:?}", secs))?;
+        anyhow::ensure!(n > 0 && secs > 0, "retries and seconds must be positive");
+        Ok(Self::new(n, Duration::from_secs(secs)))
+    }
+
+    /// The process's budget from `RETRY_BUDGET`, if one is set.
+    pub fn global() -> Option<Arc<Budget>> {
+        static GLOBAL: OnceLock<Option<Arc<Budget>>> = OnceLock::new();
+        GLOBAL.get_or_init(|| {
+            let spec = std::env::var("RETRY_BUDGET").ok().filter(|s| !s.trim().is_empty())?;
+            match Budget::parse(&spec) {
+                Ok(b) => Some(Arc::new(b)),
+                Err(e) => {
+                    tracing::error!(target: "retry", spec, error = %format!("{:#}", e), "ignoring malformed RETRY_BUDGET");
+                    None
+                }
+            }
+        }).clone()
+    }
+
+    /// Take a retry if one is left; otherwise how long until one is.
+    fn take(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let (tokens, at) = &mut *state;
+        let now = Instant::now();
+        *tokens = (*tokens + now.duration_since(*at).as_secs_f64() * self.per_sec).min(self.capacity);
+        *at = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - *tokens) / self.per_sec))
+        }
+    }
+
+    /// Take a retry if one is left.
+    pub fn try_take(&self) -> bool {
+        self.take().is_ok()
+    }
+
+    /// Take a retry, waiting for one if none is left.
+    pub async fn wait(&self) {
+        while let Err(wait) = self.take() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Closed,
+    /// The cooldown has passed: tries go through, and the next outcome decides.
+    HalfOpen,
+    Open,
+}
+
+/// A circuit breaker for one dependency.
+#[derive(Debug)]
+pub struct Breaker {
+    op: String,
+    failures: u32,
+    cooldown: Duration,
+    state: Mutex<(u32, Option<Instant>)>,
+}
+
+impl Breaker {
+    /// Open after `failures` consecutive failures, for `cooldown`.
+    pub fn new(op: &str, failures: u32, cooldown: Duration) -> Self {
+        gauge!("breaker_state", "op" => op.to_string()).set(0.0);
+        Self { op: op.to_string(), failures: failures.max(1), cooldown, state: Mutex::new((0, None)) }
+    }
+
+    pub fn state(&self) -> State {
+        match self.state.lock().unwrap_or_else(PoisonError::into_inner).1 {
+            None => State::Closed,
+            Some(until) if Instant::now() >= until => State::HalfOpen,
+            Some(_) => State::Open,
+        }
+    }
+
+    /// How long until tries go through again; zero unless open.
+    pub fn remaining(&self) -> Duration {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner).1.map_or(Duration::ZERO, |until| until.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn success(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.1.is_some() {
+            tracing::info!(target: "retry", op = %self.op, "breaker closed");
+        }
+        *state = (0, None);
+        gauge!("breaker_state", "op" => self.op.clone()).set(0.0);
+    }
+
+    pub fn failure(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.0 = state.0.saturating_add(1);
+        let half_open = state.1.is_some_and(|until| Instant::now() >= until);
+        if (state.1.is_none() && state.0 >= self.failures) || half_open {
+            state.1 = Some(Instant::now() + self.cooldown);
+            counter!("breaker_opened_total", "op" => self.op.clone()).increment(1);
+            gauge!("breaker_state", "op" => self.op.clone()).set(2.0);
+            tracing::warn!(target: "retry", op = %self.op, failures = state.0, cooldown_ms = self.cooldown.as_millis() as u64, "breaker open");
+        }
+    }
+
+    /// Whether a try may go through now, marking the breaker half-open
+    /// once its cooldown has passed.
+    pub fn allow(&self) -> bool {
+        let state = self.state();
+        if state == State::HalfOpen {
+            gauge!("breaker_state", "op" => self.op.clone()).set(1.0);
+        }
+        state != State::Open
+    }
+}
+
+/// The error a try gets from an open [`Breaker`].
+#[derive(Debug)]
+pub struct Open(pub String);
+
+impl std::fmt::Display for Open {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: circuit open", self.0)
+    }
+}
+
+impl std::error::Error for Open {}
+
+/// How to retry one kind of call.
+#[derive(Debug, Clone)]
+pub struct Policy {
+    /// Retries after the first try; `None` retries until it works.
+    pub attempts: Option<u32>,
+    pub backoff: Backoff,
+    budget: Option<Arc<Budget>>,
+    breaker: Option<Arc<Breaker>>,
+    retry_if: fn(&anyhow::Error) -> bool,
+}
+
+impl Policy {
+    /// Retry until it works, on the process's [`Budget::global`].
+    pub fn forever(backoff: Backoff) -> Self {
+        Self { attempts: None, backoff, budget: Budget::global(), breaker: None, retry_if: |_| true }
+    }
+
+    /// Retry up to `attempts` times, on the process's [`Budget::global`].
+    pub fn times(attempts: u32, backoff: Backoff) -> Self {
+        Self { attempts: Some(attempts), ..Self::forever(backoff) }
+    }
+
+    /// `{prefix}_RETRIES` retries (default `attempts`) on [`Backoff::from_env`].
+    pub fn from_env(prefix: &str, attempts: u32, initial: Duration, max: Duration) -> Self {
+        let attempts = std::env::var(format!("{}_RETRIES", prefix)).ok().and_then(|v| v.parse().ok()).unwrap_or(attempts);
+        Self::times(attempts, Backoff::from_env(prefix, initial, max))
+    }
+
+    pub fn budget(self, budget: Arc<Budget>) -> Self {
+        Self { budget: Some(budget), ..self }
+    }
+
+    pub fn breaker(self, breaker: Arc<Breaker>) -> Self {
+        Self { breaker: Some(breaker), ..self }
+    }
+
+    /// Only retry errors `retryable` accepts; others are returned at once.
+    pub fn only(self, retryable: fn(&anyhow::Error) -> bool) -> Self {
+        Self { retry_if: retryable, ..self }
+    }
+
+    /// Whether the breaker, if any, is refusing tries.
+    pub fn is_open(&self) -> bool {
+        self.breaker.as_ref().is_some_and(|b| !b.allow())
+    }
+
+    /// Start retrying one call, counted under `op`.
+    pub fn start<'a>(&'a self, op: &'a str) -> Retry<'a> {
+        Retry { policy: self, op, attempt: 0 }
+    }
+
+    /// Run `f` until it succeeds or the policy gives up, returning its last error.
+    pub async fn run<T, F, Fut>(&self, op: &str, mut f: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut retry = self.start(op);
+        loop {
+            if self.attempts.is_some() && self.is_open() {
+                return Err(Open(op.to_string()).into());
+            }
+            match f().await {
+                Ok(v) => {
+                    retry.succeeded();
+                    return Ok(v);
+                }
+                Err(e) if !retry.failed(&e).await => return Err(e),
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+impl Default for Policy {
+    /// Three retries on the default [`Backoff`].
+    fn default() -> Self {
+        Self::times(3, Backoff::default())
+    }
+}
+
+/// One call being retried, for loops that can't hand [`Policy::run`] a closure.
+pub struct Retry<'a> {
+    policy: &'a Policy,
+    op: &'a str,
+    attempt: u32,
+}
+
+impl Retry<'_> {
+    /// Retries so far.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// The call worked: close the breaker and start the backoff over.
+    pub fn succeeded(&mut self) {
+        if let Some(b) = &self.policy.breaker {
+            b.success();
+        }
+        self.attempt = 0;
+    }
+
+    /// The call failed with `e`. Waits out the backoff and returns `true` to
+    /// try again, or `false` at once when the policy gives up on it.
+    pub async fn failed(&mut self, e: &anyhow::Error) -> bool {
+        let policy = self.policy;
+        if let Some(b) = &policy.breaker {
+            b.failure();
+        }
+        if !(policy.retry_if)(e) {
+            return false;
+        }
+        if policy.attempts.is_some_and(|n| self.attempt >= n) {
+            counter!("retry_exhausted_total", "op" => self.op.to_string()).increment(1);
+            return false;
+        }
+        if policy.attempts.is_some() && policy.is_open() {
+            return false;
+        }
+        match (&policy.budget, policy.attempts) {
+            (Some(budget), Some(_)) if !budget.try_take() => {
+                counter!("retry_budget_denied_total", "op" => self.op.to_string()).increment(1);
+                return false;
+            }
+            (Some(budget), None) => budget.wait().await,
+            _ => {}
+        }
+        self.pause().await;
+        if policy.attempts.is_none() {
+            if let Some(b) = &policy.breaker {
+                tokio::time::sleep(b.remaining()).await;
+            }
+        }
+        true
+    }
+
+    /// Wait out the next backoff step with no error to judge, e.g. between
+    /// reconnects after a connection that ended cleanly.
+    pub async fn pause(&mut self) {
+        tokio::time::sleep(self.next_wait()).await;
+    }
+
+    /// Count a retry and draw its jittered wait, for a caller that sleeps on
+    /// its own. Neither the budget nor the breaker is consulted.
+    pub fn next_wait(&mut self) -> Duration {
+        self.attempt = self.attempt.saturating_add(1);
+        let wait = self.policy.backoff.delay(self.attempt);
+        counter!("retries_total", "op" => self.op.to_string()).increment(1);
+        histogram!("retry_backoff_ms", "op" => self.op.to_string()).record(wait.as_secs_f64() * 1000.0);
+        tracing::debug!(target: "retry", op = self.op, attempt = self.attempt, retry_in_ms = wait.as_millis() as u64, "retrying");
+        wait
+    }
+}
@@ -0,0 +1,143 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use retry::{Backoff, Breaker, Budget, Policy, State};
+
+fn ms(n: u64) -> Duration {
+    Duration::from_millis(n)
+}
+
+#[test]
+fn backoff_grows_to_the_cap_and_jitter_only_shortens_it() {
+    let b = Backoff::new(ms(100), ms(1000));
+    let steps: Vec<u128> = (1..=6).map(|a| b.step(a).as_millis()).collect();
+    assert_eq!(steps, [100, 200, 400, 800, 1000, 1000]);
+    assert_eq!(b.step(u32::MAX), ms(1000));
+    for a in 1..=6 {
+        let d = b.delay(a);
+        assert!(d <= b.step(a) && d >= b.step(a).mul_f64(0.8), "attempt {}: {:?}", a, d);
+    }
+    assert_eq!(b.with_jitter(0.0).delay(3), ms(400));
+}
+
+#[test]
+fn budgets_parse_as_retries_per_seconds() {
+    assert!(Budget::parse("600/60").is_ok());
+    for bad in ["600", "0/60", "600/0", "x/60", "600/y"] {
+        assert!(Budget::parse(bad).is_err(), "{:?}", bad);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_call_that_recovers_is_retried() {
+    let mut calls = 0;
+    let v = Policy::times(5, Backoff::new(ms(10), ms(100)))
+        .run("questdb_write", || {
+            calls += 1;
+            let n = calls;
+            async move { if n < 3 { anyhow::bail!("not yet") } else { Ok(n) } }
+        })
+        .await
+        .unwrap();
+    assert_eq!(v, 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_bounded_policy_returns_the_last_error() {
+    let mut calls = 0;
+    let e = Policy::times(2, Backoff::new(ms(10), ms(100)))
+        .run::<(), _, _>("sink_write", || {
+            calls += 1;
+            let n = calls;
+            async move { anyhow::bail!("refused {}", n) }
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(calls, 3);
+    assert_eq!(e.to_string(), "refused 3");
+}
+
+#[tokio::test(start_paused = true)]
+async fn errors_the_policy_rejects_are_not_retried() {
+    let mut calls = 0;
+    let res = Policy::times(5, Backoff::default())
+        .only(|e| !e.to_string().contains("malformed"))
+        .run::<(), _, _>("publish", || {
+            calls += 1;
+            async { anyhow::bail!("malformed record") }
+        })
+        .await;
+    assert!(res.is_err());
+    assert_eq!(calls, 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_spent_budget_stops_bounded_retries() {
+    let budget = Arc::new(Budget::new(2, Duration::from_secs(60)));
+    let policy = Policy::times(10, Backoff::new(ms(1), ms(1))).budget(budget.clone());
+    let mut calls = 0;
+    let res = policy
+        .run::<(), _, _>("publish", || {
+            calls += 1;
+            async { anyhow::bail!("down") }
+        })
+        .await;
+    assert!(res.is_err());
+    assert_eq!(calls, 3, "the first try and the budget's two retries");
+    assert!(!budget.try_take());
+    tokio::time::advance(Duration::from_secs(30)).await;
+    assert!(budget.try_take(), "half a window refills one retry");
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_forever_policy_waits_for_the_budget() {
+    let budget = Arc::new(Budget::new(1, Duration::from_secs(10)));
+    let policy = Policy::forever(Backoff::new(ms(1), ms(1)).with_jitter(0.0)).budget(budget);
+    let start = tokio::time::Instant::now();
+    let mut calls = 0;
+    policy
+        .run("ws_connect", || {
+            calls += 1;
+            let n = calls;
+            async move { if n < 3 { anyhow::bail!("down") } else { Ok(()) } }
+        })
+        .await
+        .unwrap();
+    assert!(start.elapsed() >= Duration::from_secs(10), "the second retry waits for a token: {:?}", start.elapsed());
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_breaker_opens_fails_fast_and_closes_after_a_good_try() {
+    let breaker = Arc::new(Breaker::new("questdb_write", 3, Duration::from_secs(5)));
+    let policy = Policy::times(10, Backoff::new(ms(1), ms(1))).breaker(breaker.clone());
+    let mut calls = 0;
+    let res = policy
+        .run::<(), _, _>("questdb_write", || {
+            calls += 1;
+            async { anyhow::bail!("down") }
+        })
+        .await;
+    assert!(res.is_err());
+    assert_eq!(calls, 3, "gives up once the breaker opens");
+    assert_eq!(breaker.state(), State::Open);
+
+    let e = policy.run::<(), _, _>("questdb_write", || async { Ok(()) }).await.unwrap_err();
+    assert!(e.downcast_ref::<retry::Open>().is_some(), "{:#}", e);
+
+    tokio::time::advance(Duration::from_secs(5)).await;
+    assert_eq!(breaker.state(), State::HalfOpen);
+    policy.run("questdb_write", || async { Ok(()) }).await.unwrap();
+    assert_eq!(breaker.state(), State::Closed);
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_failed_half_open_try_opens_the_breaker_again() {
+    let breaker = Breaker::new("sink_write", 2, Duration::from_secs(5));
+    breaker.failure();
+    breaker.failure();
+    tokio::time::advance(Duration::from_secs(5)).await;
+    assert!(breaker.allow());
+    breaker.failure();
+    assert_eq!(breaker.state(), State::Open);
+    assert_eq!(breaker.remaining(), Duration::from_secs(5));
+}
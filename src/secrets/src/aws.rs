@@ -0,0 +1,115 @@
+//! AWS Secrets Manager's `GetSecretValue`, signed with Signature Version 4.
+//!
+//! Credentials come from the standard `AWS_ACCESS_KEY_ID`,
+//! `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`, the region from
+//! `AWS_REGION` (or `AWS_DEFAULT_REGION`). `AWS_ENDPOINT_URL` points it
+//! somewhere else, such as LocalStack. Paths are secret names or ARNs.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use ring::{digest, hmac};
+use serde_json::{json, Value};
+
+use crate::Provider;
+
+const SERVICE: &str = "secretsmanager";
+const TARGET: &str = "secretsmanager.GetSecretValue";
+const CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn mac(key: &[u8], data: &str) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes()).as_ref().to_vec()
+}
+
+/// The SigV4 signing key for one day (`YYYYMMDD`), region and service.
+pub fn signing_key(secret_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let k = mac(format!("AWS4{}", secret_key).as_bytes(), date);
+    let k = mac(&k, region);
+    let k = mac(&k, service);
+    mac(&k, "aws4_request")
+}
+
+pub struct SecretsManager {
+    http: reqwest::Client,
+    region: String,
+    endpoint: reqwest::Url,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl SecretsManager {
+    /// `None` unless a region and an access key are both set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |k: &str| std::env::var(k).ok().filter(|v| !v.is_empty());
+        let (Some(region), Some(access_key)) = (var("AWS_REGION").or_else(|| var("AWS_DEFAULT_REGION")), var("AWS_ACCESS_KEY_ID")) else {
+            return Ok(None);
+        };
+        let secret_key = var("AWS_SECRET_ACCESS_KEY").context("AWS_ACCESS_KEY_ID is set but AWS_SECRET_ACCESS_KEY is not")?;
+        let endpoint = var("AWS_ENDPOINT_URL").unwrap_or_else(|| format!("https://{}.{}.amazonaws.com", SERVICE, region));
+        Ok(Some(Self {
+            http: reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?,
+            endpoint: endpoint.parse().with_context(|| format!("bad AWS_ENDPOINT_URL {:?}", endpoint))?,
+            region,
+            access_key,
+            secret_key,
+            session_token: var("AWS_SESSION_TOKEN"),
+        }))
+    }
+
+    /// `Authorization` for a `GetSecretValue` POST of `body` at `amz_date` (`YYYYMMDDTHHMMSSZ`).
+    fn authorization(&self, host: &str, amz_date: &str, body: &str) -> String {
+        let mut headers = vec![("content-type", CONTENT_TYPE), ("host", host), ("x-amz-date", amz_date)];
+        if let Some(t) = &self.session_token {
+            headers.push(("x-amz-security-token", t));
+        }
+        headers.push(("x-amz-target", TARGET));
+        let canonical_headers: String = headers.iter().map(|(k, v)| format!("{}:{}\n", k, v.trim())).collect();
+        let signed_headers = headers.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(";");
+        let body_hash = hex(digest::digest(&digest::SHA256, body.as_bytes()).as_ref());
+        let canonical = format!("POST\n/\n\n{}\n{}\n{}", canonical_headers, signed_headers, body_hash);
+
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, SERVICE);
+        let to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope,
+            hex(digest::digest(&digest::SHA256, canonical.as_bytes()).as_ref()));
+        let signature = hex(&mac(&signing_key(&self.secret_key, date, &self.region, SERVICE), &to_sign));
+        format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", self.access_key, scope, signed_headers, signature)
+    }
+}
+
+#[async_trait]
+impl Provider for SecretsManager {
+    async fn fetch(&self, path: &str) -> Result<String> {
+        let host = match self.endpoint.port() {
+            Some(p) => format!("{}:{}", self.endpoint.host_str().unwrap_or_default(), p),
+            None => self.endpoint.host_str().unwrap_or_default().to_string(),
+        };
+        let body = json!({ "SecretId": path }).to_string();
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let mut req = self.http.post(self.endpoint.clone())
+            .header("content-type", CONTENT_TYPE)
+            .header("x-amz-date", &amz_date)
+            .header("x-amz-target", TARGET)
+            .header("authorization", self.authorization(&host, &amz_date, &body));
+        if let Some(t) = &self.session_token {
+            req = req.header("x-amz-security-token", t);
+        }
+        let resp = req.body(body).send().await?;
+        let status = resp.status();
+        let v: Value = resp.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            let kind = v.get("__type").and_then(Value::as_str).unwrap_or("unknown");
+            let msg = v.get("message").or_else(|| v.get("Message")).and_then(Value::as_str).unwrap_or("no message");
+            anyhow::bail!("secrets manager {} for {}: {}: {}", status.as_u16(), path, kind, msg);
+        }
+        v.get("SecretString").and_then(Value::as_str).map(str::to_string)
+            .with_context(|| format!("{} has no SecretString (binary secrets aren't supported)", path))
+    }
+}
@@ -0,0 +1,303 @@
+//! API keys, tokens and passwords, from wherever they're kept.
+//!
+//! A secret setting `NAME` can be given three ways, tried in this order: the
+//! value itself in `NAME`, a file holding it in `NAME_FILE` (Docker and
+//! Kubernetes secrets), or a reference to a store in `NAME_REF`:
+//!
+//! - `env:OTHER_VAR`
+//! - `file:/run/secrets/binance`
+//! - `vault:secret/data/exec/binance#api_key` (HashiCorp Vault, see [`vault`])
+//! - `aws:prod/exec/binance#api_key` (AWS Secrets Manager, see [`aws`])
+//!
+//! `#field` picks one field of a secret stored as a JSON object. Other stores
+//! plug in as a [`Provider`]. Files and stores are read again every
+//! `SECRETS_REFRESH_SECS`, and a changed value reaches every [`Watched`]
+//! holder without a restart: that's the rotation hook.
+
+pub mod aws;
+pub mod vault;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use metrics::counter;
+use serde_json::Value;
+use tokio::sync::watch;
+
+/// A secret value. `Debug` never shows it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(Arc<str>);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into().into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+/// `scheme:path` with an optional `#field`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ref {
+    pub scheme: String,
+    pub path: String,
+    pub field: Option<String>,
+}
+
+impl Ref {
+    pub fn parse(s: &str) -> Result<Self> {
+        let (scheme, rest) = s.trim().split_once(':').with_context(|| format!("secret reference {:?} is not scheme:path", s))?;
+        anyhow::ensure!(!scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric()), "bad secret scheme {:?}", scheme);
+        let (path, field) = match rest.rsplit_once('#') {
+            Some((p, f)) if !f.is_empty() => (p, Some(f.to_string())),
+            _ => (rest, None),
+        };
+        anyhow::ensure!(!path.is_empty(), "secret reference {:?} has no path", s);
+        Ok(Self { scheme: scheme.to_string(), path: path.to_string(), field })
+    }
+}
+
+impl fmt::Display for Ref {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.scheme, self.path)?;
+        if let Some(field) = &self.field {
+            write!(f, "#{}", field)?;
+        }
+        Ok(())
+    }
+}
+
+/// Somewhere secrets are kept.
+#[async_trait]
+pub trait Provider: Send + Sync {
+    /// The text stored at `path`; JSON if the secret has fields.
+    async fn fetch(&self, path: &str) -> Result<String>;
+
+    /// Whether reading again can give a different answer.
+    fn rotates(&self) -> bool {
+        true
+    }
+}
+
+/// Another environment variable; fixed for the life of the process.
+struct EnvProvider;
+
+#[async_trait]
+impl Provider for EnvProvider {
+    async fn fetch(&self, path: &str) -> Result<String> {
+        std::env::var(path).with_context(|| format!("{} is not set", path))
+    }
+
+    fn rotates(&self) -> bool {
+        false
+    }
+}
+
+struct FileProvider;
+
+#[async_trait]
+impl Provider for FileProvider {
+    async fn fetch(&self, path: &str) -> Result<String> {
+        tokio::fs::read_to_string(path).await.with_context(|| format!("reading {}", path))
+    }
+}
+
+/// `field` of the JSON object in `text`, or all of `text` (trimmed) without one.
+pub fn field(text: &str, field: Option<&str>) -> Result<String> {
+    let Some(field) = field else { return Ok(text.trim().to_string()) };
+    let v: Value = serde_json::from_str(text).context("secret with a #field is not JSON")?;
+    match v.get(field).with_context(|| format!("secret has no field {:?}", field))? {
+        Value::String(s) => Ok(s.trim().to_string()),
+        other => Ok(other.to_string()),
+    }
+}
+
+async fn fetch(provider: &dyn Provider, r: &Ref) -> Result<Secret> {
+    let text = provider.fetch(&r.path).await.with_context(|| format!("fetching secret {}", r))?;
+    let value = field(&text, r.field.as_deref()).with_context(|| format!("secret {}", r))?;
+    anyhow::ensure!(!value.is_empty(), "secret {} is empty", r);
+    Ok(Secret::new(value))
+}
+
+/// Where a named secret comes from.
+enum Source {
+    Value(Secret),
+    Ref(Ref),
+}
+
+/// The registered providers and how often rotating secrets are re-read.
+pub struct Secrets {
+    providers: HashMap<String, Arc<dyn Provider>>,
+    refresh: Duration,
+}
+
+impl Secrets {
+    /// `env:` and `file:` only; a zero `refresh` never re-reads.
+    pub fn new(refresh: Duration) -> Self {
+        let mut providers: HashMap<String, Arc<dyn Provider>> = HashMap::new();
+        providers.insert("env".into(), Arc::new(EnvProvider));
+        providers.insert("file".into(), Arc::new(FileProvider));
+        Self { providers, refresh }
+    }
+
+    /// Register a provider for `scheme:` references.
+    pub fn with(mut self, scheme: &str, provider: impl Provider + 'static) -> Self {
+        self.providers.insert(scheme.to_string(), Arc::new(provider));
+        self
+    }
+
+    /// `SECRETS_REFRESH_SECS` (default 300), plus `vault:` when `VAULT_ADDR`
+    /// is set and `aws:` when AWS credentials and a region are.
+    pub fn from_env() -> Result<Self> {
+        let refresh = std::env::var("SECRETS_REFRESH_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(300);
+        let mut out = Self::new(Duration::from_secs(refresh));
+        if let Some(v) = vault::Vault::from_env()? {
+            out = out.with("vault", v);
+        }
+        if let Some(sm) = aws::SecretsManager::from_env()? {
+            out = out.with("aws", sm);
+        }
+        Ok(out)
+    }
+
+    fn provider(&self, scheme: &str) -> Result<Arc<dyn Provider>> {
+        self.providers.get(scheme).cloned().with_context(|| match scheme {
+            "vault" => "vault: secrets need VAULT_ADDR and VAULT_TOKEN".to_string(),
+            "aws" => "aws: secrets need AWS_REGION and AWS credentials".to_string(),
+            other => format!("no secret provider for {:?}", other),
+        })
+    }
+
+    /// Read a reference once.
+    pub async fn resolve(&self, r: &Ref) -> Result<Secret> {
+        fetch(self.provider(&r.scheme)?.as_ref(), r).await
+    }
+
+    fn source(name: &str) -> Result<Option<Source>> {
+        let var = |suffix: &str| std::env::var(format!("{}{}", name, suffix)).ok().filter(|v| !v.trim().is_empty());
+        if let Some(v) = var("") {
+            return Ok(Some(Source::Value(Secret::new(v))));
+        }
+        if let Some(path) = var("_FILE") {
+            return Ok(Some(Source::Ref(Ref { scheme: "file".into(), path, field: None })));
+        }
+        match var("_REF") {
+            Some(r) => Ref::parse(&r).with_context(|| format!("{}_REF", name)).map(|r| Some(Source::Ref(r))),
+            None => Ok(None),
+        }
+    }
+
+    /// `NAME`, `NAME_FILE` or `NAME_REF`; `None` if none is set.
+    pub async fn get(&self, name: &str) -> Result<Option<Secret>> {
+        match Self::source(name)? {
+            None => Ok(None),
+            Some(Source::Value(s)) => Ok(Some(s)),
+            Some(Source::Ref(r)) => self.resolve(&r).await.with_context(|| name.to_string()).map(Some),
+        }
+    }
+
+    pub async fn require(&self, name: &str) -> Result<Secret> {
+        self.get(name).await?.with_context(|| format!("{} (or {}_FILE, {}_REF) is required", name, name, name))
+    }
+
+    /// Like [`Secrets::get`], but kept current: rotating sources are re-read
+    /// every refresh interval for as long as a [`Watched`] copy is alive.
+    pub async fn watch(&self, name: &str) -> Result<Option<Watched>> {
+        match Self::source(name)? {
+            None => Ok(None),
+            Some(Source::Value(s)) => Ok(Some(Watched::fixed(s))),
+            Some(Source::Ref(r)) => self.watch_ref(name, r).await.with_context(|| name.to_string()).map(Some),
+        }
+    }
+
+    pub async fn require_watched(&self, name: &str) -> Result<Watched> {
+        self.watch(name).await?.with_context(|| format!("{} (or {}_FILE, {}_REF) is required", name, name, name))
+    }
+
+    /// Read `r` now and keep re-reading it, reporting changes as `name`.
+    pub async fn watch_ref(&self, name: &str, r: Ref) -> Result<Watched> {
+        let provider = self.provider(&r.scheme)?;
+        let first = fetch(provider.as_ref(), &r).await?;
+        if !provider.rotates() || self.refresh.is_zero() {
+            return Ok(Watched::fixed(first));
+        }
+        let (tx, rx) = watch::channel(first);
+        let (name, refresh) = (name.to_string(), self.refresh);
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(refresh);
+            tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            tick.tick().await;
+            loop {
+                tokio::select! {
+                    _ = tick.tick() => {}
+                    _ = tx.closed() => break,
+                }
+                match fetch(provider.as_ref(), &r).await {
+                    Ok(s) if s != *tx.borrow() => {
+                        tx.send_replace(s);
+                        counter!("secret_rotations_total", "name" => name.clone()).increment(1);
+                        tracing::info!(target: "secrets", name = %name, source = %r, "secret rotated");
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        // Keep serving the last good value.
+                        counter!("secret_refresh_failures_total", "name" => name.clone()).increment(1);
+                        tracing::warn!(target: "secrets", name = %name, error = ?e, "secret refresh failed");
+                    }
+                }
+            }
+        });
+        Ok(Watched { rx })
+    }
+}
+
+/// A secret that may be rotated under its holder. Clones share updates.
+#[derive(Debug, Clone)]
+pub struct Watched {
+    rx: watch::Receiver<Secret>,
+}
+
+impl Watched {
+    /// One that never changes.
+    pub fn fixed(value: impl Into<Secret>) -> Self {
+        let (_, rx) = watch::channel(value.into());
+        Self { rx }
+    }
+
+    pub fn current(&self) -> Secret {
+        self.rx.borrow().clone()
+    }
+
+    /// Wait for the next rotation and return the new value. Never returns
+    /// for a secret that can't rotate.
+    pub async fn rotated(&mut self) -> Secret {
+        if self.rx.changed().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+        self.rx.borrow_and_update().clone()
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(s: &str) -> Self {
+        Self::new(s)
+    }
+}
+
+impl From<String> for Secret {
+    fn from(s: String) -> Self {
+        Self::new(s)
+    }
+}
@@ -0,0 +1,89 @@
+//! HashiCorp Vault over its HTTP API, with token auth.
+//!
+//! Paths are API paths under `/v1/`: `secret/data/exec/binance` on a KV v2
+//! mount, `kv/exec/binance` on a KV v1 one. Either way the secret's fields
+//! come back as a JSON object for `#field` to pick from. A token in
+//! `VAULT_TOKEN_FILE` (where Vault Agent keeps it) is read on every fetch,
+//! so the agent can renew it underneath us.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::Provider;
+
+enum Token {
+    Value(String),
+    File(PathBuf),
+}
+
+pub struct Vault {
+    http: reqwest::Client,
+    addr: String,
+    token: Token,
+    namespace: Option<String>,
+}
+
+impl Vault {
+    pub fn new(addr: &str, token: &str, namespace: Option<&str>) -> Result<Self> {
+        Self::build(addr, Token::Value(token.to_string()), namespace)
+    }
+
+    fn build(addr: &str, token: Token, namespace: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            http: reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?,
+            addr: addr.trim_end_matches('/').to_string(),
+            token,
+            namespace: namespace.map(str::to_string),
+        })
+    }
+
+    /// `VAULT_ADDR`, `VAULT_TOKEN` or `VAULT_TOKEN_FILE`, and `VAULT_NAMESPACE`.
+    /// `None` without an address.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |k: &str| std::env::var(k).ok().filter(|v| !v.is_empty());
+        let Some(addr) = var("VAULT_ADDR") else { return Ok(None) };
+        let token = match (var("VAULT_TOKEN"), var("VAULT_TOKEN_FILE")) {
+            (Some(t), _) => Token::Value(t),
+            (None, Some(path)) => Token::File(path.into()),
+            (None, None) => anyhow::bail!("VAULT_ADDR is set but neither VAULT_TOKEN nor VAULT_TOKEN_FILE is"),
+        };
+        Self::build(&addr, token, var("VAULT_NAMESPACE").as_deref()).map(Some)
+    }
+
+    async fn token(&self) -> Result<String> {
+        match &self.token {
+            Token::Value(t) => Ok(t.clone()),
+            Token::File(path) => Ok(tokio::fs::read_to_string(path).await
+                .with_context(|| format!("reading {}", path.display()))?.trim().to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for Vault {
+    async fn fetch(&self, path: &str) -> Result<String> {
+        let mut req = self.http.get(format!("{}/v1/{}", self.addr, path.trim_start_matches('/')))
+            .header("X-Vault-Token", self.token().await?);
+        if let Some(ns) = &self.namespace {
+            req = req.header("X-Vault-Namespace", ns);
+        }
+        let resp = req.send().await?;
+        let status = resp.status();
+        let body: Value = resp.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            let errors = body.get("errors").map(Value::to_string).unwrap_or_default();
+            anyhow::bail!("vault {} for {}: {}", status.as_u16(), path, errors);
+        }
+        let data = body.get("data").context("vault response has no data")?;
+        // KV v2 nests the fields under data.data, next to their metadata.
+        let fields = match data.get("data") {
+            Some(inner) if data.get("metadata").is_some() => inner,
+            _ => data,
+        };
+        Ok(fields.to_string())
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use secrets::aws::signing_key;
+use secrets::{field, Ref, Secret, Secrets};
+
+#[test]
+fn references_split_into_scheme_path_and_field() {
+    let r = Ref::parse("vault:secret/data/exec/binance#api_key").unwrap();
+    assert_eq!((r.scheme.as_str(), r.path.as_str(), r.field.as_deref()), ("vault", "secret/data/exec/binance", Some("api_key")));
+    assert_eq!(r.to_string(), "vault:secret/data/exec/binance#api_key");
+    let r = Ref::parse("aws:arn:aws:secretsmanager:eu-west-1:123:secret:exec").unwrap();
+    assert_eq!((r.scheme.as_str(), r.path.as_str(), r.field), ("aws", "arn:aws:secretsmanager:eu-west-1:123:secret:exec", None));
+    assert!(Ref::parse("no-scheme").is_err());
+    assert!(Ref::parse("file:").is_err());
+}
+
+#[test]
+fn fields_are_picked_from_json_objects() {
+    assert_eq!(field(" token\n", None).unwrap(), "token");
+    assert_eq!(field(r#"{"api_key":"k","n":5}"#, Some("api_key")).unwrap(), "k");
+    assert_eq!(field(r#"{"api_key":"k","n":5}"#, Some("n")).unwrap(), "5");
+    assert!(field(r#"{"api_key":"k"}"#, Some("secret")).is_err());
+    assert!(field("not json", Some("api_key")).is_err());
+}
+
+#[test]
+fn debug_never_shows_the_value() {
+    assert_eq!(format!("{:?}", Secret::new("hunter2")), "Secret(..)");
+}
+
+#[test]
+fn signing_key_matches_the_aws_docs_example() {
+    let key = signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215", "us-east-1", "iam");
+    let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+    assert_eq!(hex, "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d");
+}
+
+#[tokio::test]
+async fn watched_files_pick_up_rotations() {
+    let path = std::env::temp_dir().join(format!("secrets-test-{}", std::process::id()));
+    std::fs::write(&path, "old\n").unwrap();
+    let secrets = Secrets::new(Duration::from_millis(20));
+    let r = Ref { scheme: "file".into(), path: path.display().to_string(), field: None };
+    let mut w = secrets.watch_ref("TEST_SECRET", r).await.unwrap();
+    assert_eq!(w.current().expose(), "old");
+
+    std::fs::write(&path, "new\n").unwrap();
+    let rotated = tokio::time::timeout(Duration::from_secs(5), w.rotated()).await.unwrap();
+    assert_eq!(rotated.expose(), "new");
+    assert_eq!(w.current().expose(), "new");
+
+    // A failed read keeps the last good value.
+    std::fs::remove_file(&path).unwrap();
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert_eq!(w.current().expose(), "new");
+}
+
+#[tokio::test]
+async fn unregistered_schemes_are_an_error() {
+    let secrets = Secrets::new(Duration::ZERO);
+    let err = secrets.resolve(&Ref::parse("vault:secret/data/x#k").unwrap()).await.unwrap_err();
+    assert!(err.to_string().contains("VAULT_ADDR"), "{}", err);
+}
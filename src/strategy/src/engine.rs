@@ -0,0 +1,70 @@
+//! Runs every configured strategy on every symbol and keeps their ledgers.
+
+use std::collections::HashMap;
+
+use consumer::ilp::NormTrade;
+use consumer::rollup::Bar;
+
+use crate::ledger::{Fill, Pnl, Position};
+use crate::signal::{Signal, Spec};
+
+struct Book {
+    signal: Box<dyn Signal>,
+    position: Position,
+}
+
+pub struct Engine {
+    specs: Vec<Spec>,
+    fee_bps: f64,
+    /// By (index into `specs`, symbol).
+    books: HashMap<(usize, String), Book>,
+}
+
+impl Engine {
+    pub fn new(specs: Vec<Spec>, fee_bps: f64) -> Self {
+        Self { specs, fee_bps, books: HashMap::new() }
+    }
+
+    /// Run the strategies on a closed bar. Returns the fills and every
+    /// strategy's position in the symbol as of the bar.
+    pub fn on_bar(&mut self, bar: &Bar) -> (Vec<Fill>, Vec<Pnl>) {
+        let fills = self.each(&bar.symbol, bar.ts_ms, bar.close, |s| s.on_bar(bar));
+        let pnl = (0..self.specs.len())
+            .filter_map(|i| {
+                let book = self.books.get(&(i, bar.symbol.clone()))?;
+                Some(Pnl { strategy: self.specs[i].name.clone(), symbol: bar.symbol.clone(), ts_ms: bar.ts_ms, position: book.position.clone() })
+            })
+            .collect();
+        (fills, pnl)
+    }
+
+    /// Run the strategies on a trade; also marks their positions to its price.
+    pub fn on_trade(&mut self, t: &NormTrade) -> Vec<Fill> {
+        self.each(&t.symbol, t.ts_ms, t.price, |s| s.on_trade(t))
+    }
+
+    fn each(&mut self, symbol: &str, ts_ms: i64, price: f64, mut f: impl FnMut(&mut dyn Signal) -> Option<f64>) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        for (i, spec) in self.specs.iter().enumerate() {
+            let book = self.books.entry((i, symbol.to_string()))
+                .or_insert_with(|| Book { signal: spec.make(), position: Position::default() });
+            book.position.mark = price;
+            let Some(target) = f(book.signal.as_mut()) else { continue };
+            let qty = target - book.position.qty;
+            if qty.abs() < 1e-12 {
+                continue;
+            }
+            let fee = book.position.fill(qty, price, self.fee_bps);
+            fills.push(Fill {
+                strategy: spec.name.clone(),
+                symbol: symbol.to_string(),
+                ts_ms,
+                qty,
+                price,
+                fee,
+                position: book.position.qty,
+            });
+        }
+        fills
+    }
+}
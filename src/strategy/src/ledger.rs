@@ -0,0 +1,103 @@
+//! A simulated position per strategy and symbol.
+//!
+//! Fills happen at the price that triggered them, less a fee of `fee_bps` of
+//! the traded notional. Realized PnL is booked against the average entry
+//! price as a position is reduced; the rest is marked to the last price.
+
+use consumer::ilp::escape_tag;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Position {
+    /// Signed, in base units.
+    pub qty: f64,
+    pub avg_price: f64,
+    /// Booked PnL net of fees, in quote units.
+    pub realized: f64,
+    pub mark: f64,
+}
+
+impl Position {
+    /// Trade `qty` (signed) at `price`; returns the fee charged.
+    pub fn fill(&mut self, qty: f64, price: f64, fee_bps: f64) -> f64 {
+        let fee = qty.abs() * price * fee_bps / 1e4;
+        self.realized -= fee;
+        if self.qty == 0.0 || self.qty.signum() == qty.signum() {
+            let total = self.qty.abs() + qty.abs();
+            self.avg_price = (self.avg_price * self.qty.abs() + price * qty.abs()) / total;
+        } else {
+            let closed = qty.abs().min(self.qty.abs());
+            self.realized += closed * (price - self.avg_price) * self.qty.signum();
+            if qty.abs() > self.qty.abs() {
+                // Flipped through flat: what's left opened at this price.
+                self.avg_price = price;
+            }
+        }
+        self.qty += qty;
+        if self.qty.abs() < 1e-12 {
+            (self.qty, self.avg_price) = (0.0, 0.0);
+        }
+        self.mark = price;
+        fee
+    }
+
+    pub fn unrealized(&self) -> f64 {
+        self.qty * (self.mark - self.avg_price)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    pub strategy: String,
+    pub symbol: String,
+    pub ts_ms: i64,
+    pub qty: f64,
+    pub price: f64,
+    pub fee: f64,
+    /// Position after the fill.
+    pub position: f64,
+}
+
+impl Fill {
+    pub fn to_ilp_line(&self, table: &str) -> String {
+        format!(
+            "{},strategy={},symbol={},side={} qty={},price={},fee={},position={} {}",
+            escape_tag(table),
+            escape_tag(&self.strategy),
+            escape_tag(&self.symbol),
+            if self.qty > 0.0 { "buy" } else { "sell" },
+            self.qty.abs(),
+            self.price,
+            self.fee,
+            self.position,
+            (self.ts_ms as i128) * 1_000_000i128
+        )
+    }
+}
+
+/// A position's state at a bar, for the PnL table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pnl {
+    pub strategy: String,
+    pub symbol: String,
+    pub ts_ms: i64,
+    pub position: Position,
+}
+
+impl Pnl {
+    pub fn to_ilp_line(&self, table: &str) -> String {
+        let p = &self.position;
+        format!(
+            "{},strategy={},symbol={} position={},avg_price={},mark={},realized={},unrealized={},pnl={} {}",
+            escape_tag(table),
+            escape_tag(&self.strategy),
+            escape_tag(&self.symbol),
+            p.qty,
+            p.avg_price,
+            p.mark,
+            p.realized,
+            p.unrealized(),
+            p.realized + p.unrealized(),
+            (self.ts_ms as i128) * 1_000_000i128
+        )
+    }
+}
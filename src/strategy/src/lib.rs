@@ -0,0 +1,6 @@
+//! Paper trading: pluggable signals run on live bars and trades, with a
+//! simulated position ledger. Nothing here routes orders.
+
+pub mod engine;
+pub mod ledger;
+pub mod signal;
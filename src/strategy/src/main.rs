@@ -0,0 +1,165 @@
+//! Strategy stage: `bars.1m` (and optionally `ticks.norm`) -> paper fills and PnL in QuestDB.
+//!
+//! Positions are only in memory: every strategy starts flat after a restart.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bus::commit::{CommitStrategy, Committer};
+use bus::envelope::{self, NORM_SCHEMA};
+use bus::{BusConfig, Subscriber};
+use consumer::decode::decode;
+use consumer::rollup::Bar;
+use consumer::sink::{parse_endpoints, IlpOptions, IlpPool, PoolMode};
+use metrics::counter;
+use obsv::load::Load;
+use obsv::{init_metrics, init_tracing, namespace, startup};
+use retry::Policy;
+use strategy::engine::Engine;
+use strategy::ledger::{Fill, Pnl};
+use strategy::signal;
+
+fn env<T: AsRef<str>>(k: T, default: &str) -> String {
+    std::env::var(k.as_ref()).unwrap_or_else(|_| default.to_string())
+}
+
+struct Out {
+    pool: IlpPool,
+    fills_table: String,
+    pnl_table: String,
+    policy: Policy,
+}
+
+impl Out {
+    /// Write fills and PnL rows on `policy`; once it gives up, the error ends
+    /// the stage rather than stall commits for the length of the outage.
+    async fn write(&mut self, fills: &[Fill], pnl: &[Pnl]) -> Result<()> {
+        if fills.is_empty() && pnl.is_empty() {
+            return Ok(());
+        }
+        for f in fills {
+            tracing::info!(target="strategy", strategy=%f.strategy, symbol=%f.symbol, qty=f.qty, price=f.price, position=f.position, "paper fill");
+            counter!("strategy_fills_total", "strategy" => f.strategy.clone()).increment(1);
+        }
+        let lines: String = fills.iter().map(|f| f.to_ilp_line(&self.fills_table) + "\n")
+            .chain(pnl.iter().map(|p| p.to_ilp_line(&self.pnl_table) + "\n"))
+            .collect();
+        let mut retry = self.policy.start("questdb_write");
+        while let Err(e) = self.pool.write_chunks(&[lines.as_bytes()]).await {
+            tracing::warn!(target="strategy", error=?e, "QuestDB write failed");
+            if !retry.failed(&e).await {
+                return Err(e).context("writing paper fills and PnL to QuestDB");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Waits forever when there's no trades subscription, so `select!` only polls bars.
+async fn next_trade(trades: &mut Option<Box<dyn Subscriber>>) -> Option<Result<bus::Delivery>> {
+    match trades {
+        Some(s) => s.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!("nats", "pulsar"), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    init_metrics(9473)?;
+
+    let bars_topic = env("BARS_TOPIC", &consumer::rollup::default_topic(consumer::rollup::window_from_env()));
+    // Empty: strategies see bars only.
+    let trades_topic = env("TRADES_TOPIC", "");
+    let group_id = env("GROUP_ID", "strategy");
+    let specs = signal::parse(&env("STRATEGIES", "sma_cross:10:30:1"))?;
+    anyhow::ensure!(!specs.is_empty(), "STRATEGIES is empty");
+    let fee_bps: f64 = env("STRATEGY_FEE_BPS", "10").parse().unwrap_or(10.0);
+    tracing::info!(target="strategy", strategies=?specs.iter().map(|s| &s.name).collect::<Vec<_>>(), fee_bps, "paper trading");
+    let mut engine = Engine::new(specs, fee_bps);
+
+    let ilp_port: u16 = env("QDB_ILP_PORT", "9009").parse().unwrap_or(9009);
+    let endpoints = parse_endpoints(&env("QDB_HOSTS", &format!("{}:{}", env("QDB_HOST", "localhost"), ilp_port)), ilp_port)?;
+    let mode = PoolMode::parse(&env("QDB_POOL_MODE", "failover"))?;
+    let pool = startup::wait_for("questdb", || IlpPool::connect(endpoints.clone(), mode, IlpOptions::default(), Duration::from_secs(5))).await?;
+    let mut out = Out {
+        pool,
+        fills_table: namespace::table(&env("STRATEGY_FILLS_TABLE", "paper_fills")),
+        pnl_table: namespace::table(&env("STRATEGY_PNL_TABLE", "paper_pnl")),
+        policy: Policy::from_env("QDB_WRITE", 5, Duration::from_millis(100), Duration::from_secs(5)),
+    };
+
+    let bus = BusConfig::from_env()?;
+    let topics: Vec<&str> = [&bars_topic, &trades_topic].into_iter().map(String::as_str).filter(|t| !t.is_empty()).collect();
+    startup::wait_for("kafka", || bus.check_topics(&topics)).await?;
+    bus::audit::start(&bus).await;
+    let mut bars = bus.subscriber(&bars_topic, &group_id).await?;
+    let mut trades = match trades_topic.is_empty() {
+        true => None,
+        false => Some(bus.subscriber(&trades_topic, &group_id).await?),
+    };
+    let interval = CommitStrategy::Interval(Duration::from_secs(1));
+    let (mut bar_commits, mut trade_commits) = (Committer::new(interval), Committer::new(interval));
+
+    let load = Load::new("strategy")?;
+    let mut stop = std::pin::pin!(obsv::runtime::stopping());
+    loop {
+        tokio::select! {
+            _ = &mut stop => break,
+            next = bars.next() => match next {
+                None => break,
+                Some(Err(e)) => tracing::error!(target="strategy", error=?e, "bar poll error"),
+                Some(Ok(d)) => {
+                    let _busy = load.busy();
+                    load.processed(1);
+                    match serde_json::from_slice::<Bar>(&d.payload) {
+                        Ok(bar) => {
+                            let (fills, pnl) = engine.on_bar(&bar);
+                            out.write(&fills, &pnl).await?;
+                        }
+                        Err(_) => counter!("strategy_dropped_total").increment(1),
+                    }
+                    if let Err(e) = bar_commits.done(bars.as_ref(), d).await {
+                        tracing::warn!(target="strategy", error=?e, "commit failed");
+                    }
+                }
+            },
+            next = next_trade(&mut trades) => match next {
+                None => break,
+                Some(Err(e)) => tracing::error!(target="strategy", error=?e, "trade poll error"),
+                Some(Ok(d)) => {
+                    let _busy = load.busy();
+                    load.processed(1);
+                    let compat = envelope::compat(&d.headers, NORM_SCHEMA);
+                    match d.payload_str().map(|p| decode(p, &compat)) {
+                        Some(Ok(t)) => {
+                            let fills = engine.on_trade(&t);
+                            out.write(&fills, &[]).await?;
+                        }
+                        _ => {
+                            counter!("strategy_dropped_total").increment(1);
+                            obsv::errors::record("strategy", obsv::errors::ErrorKind::Data);
+                        }
+                    }
+                    if let Some(sub) = &trades {
+                        if let Err(e) = trade_commits.done(sub.as_ref(), d).await {
+                            tracing::warn!(target="strategy", error=?e, "commit failed");
+                        }
+                    }
+                }
+            },
+        }
+    }
+    if let Err(e) = bar_commits.commit(bars.as_ref()).await {
+        tracing::warn!(target="strategy", error=?e, "final commit failed");
+    }
+    if let Some(sub) = &trades {
+        if let Err(e) = trade_commits.commit(sub.as_ref()).await {
+            tracing::warn!(target="strategy", error=?e, "final commit failed");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! The signal plug-in point.
+//!
+//! A [`Signal`] sees one symbol's bars and trades and answers with the
+//! position it wants (signed, in base units), or `None` to leave the
+//! position alone. The engine trades the difference. To add one, implement
+//! the trait and give it a name in [`parse`].
+
+use std::collections::VecDeque;
+
+use anyhow::{Context, Result};
+use consumer::ilp::NormTrade;
+use consumer::rollup::Bar;
+
+pub trait Signal: Send {
+    /// A closed bar for this signal's symbol.
+    fn on_bar(&mut self, _bar: &Bar) -> Option<f64> {
+        None
+    }
+
+    /// A trade for this signal's symbol; only seen when TRADES_TOPIC is set.
+    fn on_trade(&mut self, _trade: &NormTrade) -> Option<f64> {
+        None
+    }
+}
+
+/// A configured strategy: its name and how to make one signal per symbol.
+pub struct Spec {
+    pub name: String,
+    make: Box<dyn Fn() -> Box<dyn Signal> + Send + Sync>,
+}
+
+impl Spec {
+    pub fn new(name: &str, make: impl Fn() -> Box<dyn Signal> + Send + Sync + 'static) -> Self {
+        Self { name: name.to_string(), make: Box::new(make) }
+    }
+
+    pub fn make(&self) -> Box<dyn Signal> {
+        (self.make)()
+    }
+}
+
+/// `name:arg:arg,...`, e.g. `sma_cross:10:30:0.01,momentum:20:0.01`.
+pub fn parse(specs: &str) -> Result<Vec<Spec>> {
+    specs.split(',').map(str::trim).filter(|s| !s.is_empty())
+        .map(|s| {
+            let mut parts = s.split(':');
+            let kind = parts.next().unwrap_or_default();
+            let args: Vec<f64> = parts.map(|a| a.parse().with_context(|| format!("bad argument in {:?}", s))).collect::<Result<_>>()?;
+            let arg = |i: usize, default: f64| args.get(i).copied().unwrap_or(default);
+            Ok(match kind {
+                "sma_cross" => {
+                    let (fast, slow, size) = (arg(0, 10.0) as usize, arg(1, 30.0) as usize, arg(2, 1.0));
+                    anyhow::ensure!(0 < fast && fast < slow, "{:?}: fast must be below slow", s);
+                    Spec::new(s, move || Box::new(SmaCross::new(fast, slow, size)))
+                }
+                "momentum" => {
+                    let (lookback, size) = (arg(0, 20.0) as usize, arg(1, 1.0));
+                    anyhow::ensure!(lookback > 0, "{:?}: lookback must be positive", s);
+                    Spec::new(s, move || Box::new(Momentum::new(lookback, size)))
+                }
+                other => anyhow::bail!("unknown strategy {:?} (expected sma_cross|momentum)", other),
+            })
+        })
+        .collect()
+}
+
+/// Closes kept for a trailing window.
+struct Closes {
+    len: usize,
+    v: VecDeque<f64>,
+}
+
+impl Closes {
+    fn new(len: usize) -> Self {
+        Self { len, v: VecDeque::with_capacity(len + 1) }
+    }
+
+    fn push(&mut self, c: f64) {
+        self.v.push_back(c);
+        if self.v.len() > self.len {
+            self.v.pop_front();
+        }
+    }
+
+    fn full(&self) -> bool {
+        self.v.len() == self.len
+    }
+
+    fn mean_of_last(&self, n: usize) -> f64 {
+        self.v.iter().rev().take(n).sum::<f64>() / n as f64
+    }
+}
+
+/// Long `size` while the fast SMA of closes is above the slow one, short below.
+pub struct SmaCross {
+    fast: usize,
+    size: f64,
+    closes: Closes,
+}
+
+impl SmaCross {
+    pub fn new(fast: usize, slow: usize, size: f64) -> Self {
+        Self { fast, size, closes: Closes::new(slow) }
+    }
+}
+
+impl Signal for SmaCross {
+    fn on_bar(&mut self, bar: &Bar) -> Option<f64> {
+        self.closes.push(bar.close);
+        if !self.closes.full() {
+            return None;
+        }
+        let (fast, slow) = (self.closes.mean_of_last(self.fast), self.closes.mean_of_last(self.closes.len));
+        Some(if fast > slow { self.size } else if fast < slow { -self.size } else { 0.0 })
+    }
+}
+
+/// Long `size` after a rise over the last `lookback` bars, short after a fall.
+pub struct Momentum {
+    closes: Closes,
+    size: f64,
+}
+
+impl Momentum {
+    pub fn new(lookback: usize, size: f64) -> Self {
+        Self { closes: Closes::new(lookback + 1), size }
+    }
+}
+
+impl Signal for Momentum {
+    fn on_bar(&mut self, bar: &Bar) -> Option<f64> {
+        self.closes.push(bar.close);
+        if !self.closes.full() {
+            return None;
+        }
+        let first = self.closes.v.front().copied()?;
+        Some(if bar.close > first { self.size } else if bar.close < first { -self.size } else { 0.0 })
+    }
+}
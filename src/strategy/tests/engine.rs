@@ -0,0 +1,32 @@
+use consumer::rollup::Bar;
+use strategy::engine::Engine;
+use strategy::signal::parse;
+
+fn bar(ts_ms: i64, close: f64) -> Bar {
+    Bar { symbol: "BTC".into(), ts_ms, open: close, high: close, low: close, close, volume: 1.0, trades: 1 }
+}
+
+#[test]
+fn momentum_flips_with_the_trend_and_reports_pnl_per_bar() {
+    let mut e = Engine::new(parse("momentum:2:0.5").unwrap(), 0.0);
+    let (fills, pnl) = e.on_bar(&bar(0, 100.0));
+    assert!(fills.is_empty());
+    assert_eq!(pnl.len(), 1, "a position row even while flat");
+    e.on_bar(&bar(60_000, 101.0));
+
+    let (fills, _) = e.on_bar(&bar(120_000, 102.0));
+    assert_eq!((fills[0].qty, fills[0].price, fills[0].position), (0.5, 102.0, 0.5));
+    assert!(e.on_bar(&bar(180_000, 103.0)).0.is_empty(), "already at target");
+
+    let (fills, pnl) = e.on_bar(&bar(240_000, 90.0));
+    assert_eq!((fills[0].qty, fills[0].position), (-1.0, -0.5));
+    assert_eq!(pnl[0].position.realized, -6.0);
+    assert_eq!(pnl[0].strategy, "momentum:2:0.5");
+}
+
+#[test]
+fn specs_are_checked() {
+    assert!(parse("sma_cross:30:10").is_err());
+    assert!(parse("nope").is_err());
+    assert_eq!(parse("sma_cross, momentum:5").unwrap().len(), 2);
+}
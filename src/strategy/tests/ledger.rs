@@ -0,0 +1,27 @@
+use strategy::ledger::Position;
+
+#[test]
+fn realized_pnl_books_against_the_average_entry() {
+    let mut p = Position::default();
+    p.fill(1.0, 100.0, 0.0);
+    p.fill(1.0, 110.0, 0.0);
+    assert_eq!((p.qty, p.avg_price), (2.0, 105.0));
+
+    p.mark = 120.0;
+    assert_eq!(p.unrealized(), 30.0);
+
+    // Sell 3: close 2 at +15 each, then short 1 from 120.
+    p.fill(-3.0, 120.0, 0.0);
+    assert_eq!((p.qty, p.avg_price, p.realized), (-1.0, 120.0, 30.0));
+
+    p.fill(1.0, 100.0, 0.0);
+    assert_eq!((p.qty, p.avg_price, p.realized), (0.0, 0.0, 50.0));
+}
+
+#[test]
+fn fees_come_out_of_realized() {
+    let mut p = Position::default();
+    assert_eq!(p.fill(2.0, 100.0, 10.0), 0.2);
+    assert_eq!(p.fill(-2.0, 100.0, 10.0), 0.2);
+    assert!((p.realized + 0.4).abs() < 1e-12);
+}
@@ -0,0 +1,3 @@
+//! Log returns and realized volatility per symbol from closed bars.
+
+pub mod realized;
@@ -0,0 +1,131 @@
+//! Volatility stage: `bars.1m` -> `vol` table.
+//!
+//! Closes are kept in memory only. On startup the history for the longest
+//! horizon is read back from the bars table, so horizons are full straight
+//! away instead of after a day of bars.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bus::commit::{CommitStrategy, Committer};
+use bus::BusConfig;
+use chrono::Utc;
+use consumer::rollup::Bar;
+use consumer::schema;
+use consumer::sink::{parse_endpoints, IlpOptions, IlpPool, PoolMode};
+use metrics::counter;
+use obsv::load::Load;
+use obsv::{init_metrics, init_tracing, namespace, startup};
+use retry::Policy;
+use vol::realized::{Tracker, Vol};
+use vwap::calc::parse_window;
+
+fn env<T: AsRef<str>>(k: T, default: &str) -> String {
+    std::env::var(k.as_ref()).unwrap_or_else(|_| default.to_string())
+}
+
+/// Write rows on `policy`; once it gives up, the error ends the stage and
+/// the bar is read again after the restart.
+async fn write(pool: &mut IlpPool, policy: &Policy, table: &str, rows: &[Vol]) -> Result<()> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let lines: String = rows.iter().map(|v| v.to_ilp_line(table) + "\n").collect();
+    let mut retry = policy.start("questdb_write");
+    while let Err(e) = pool.write_chunks(&[lines.as_bytes()]).await {
+        tracing::warn!(target="vol", error=?e, "QuestDB write failed");
+        if !retry.failed(&e).await {
+            return Err(e).context("writing volatility to QuestDB");
+        }
+    }
+    counter!("vol_rows_total").increment(rows.len() as u64);
+    Ok(())
+}
+
+/// Feed the tracker the last `keep_ms` of closes from `table`, oldest first.
+async fn warm_start(client: &schema::Client, table: &str, keep_ms: i64, tracker: &mut Tracker) -> Result<usize> {
+    let since_us = (Utc::now().timestamp_millis() - keep_ms) * 1000;
+    let rows = client.query(&format!(
+        "SELECT symbol, close, timestamp FROM {} WHERE timestamp >= cast({} AS timestamp) ORDER BY timestamp",
+        table, since_us,
+    )).await?;
+    for row in &rows {
+        let (Some(symbol), Some(close), Some(ts)) = (row[0].as_str(), row[1].as_f64(), row[2].as_str().and_then(parse_ts)) else {
+            continue;
+        };
+        tracker.add(symbol, ts, close);
+    }
+    Ok(rows.len())
+}
+
+/// QuestDB's `/exec` timestamps, e.g. `2024-06-01T00:01:00.000000Z`.
+fn parse_ts(s: &str) -> Option<i64> {
+    s.parse::<chrono::DateTime<Utc>>().ok().map(|t| t.timestamp_millis())
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!("nats", "pulsar"), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    init_metrics(9472)?;
+
+    let topic_in = env("BARS_TOPIC", &consumer::rollup::default_topic(consumer::rollup::window_from_env()));
+    let group_id = env("GROUP_ID", "vol");
+    let table = namespace::table(&env("VOL_TABLE", "vol"));
+    let horizons = env("VOL_HORIZONS", "5m,1h,1d")
+        .split(',')
+        .map(str::trim)
+        .filter(|h| !h.is_empty())
+        .map(|h| Ok((h.to_string(), parse_window(h)?)))
+        .collect::<Result<Vec<_>>>()?;
+    anyhow::ensure!(!horizons.is_empty(), "VOL_HORIZONS is empty");
+    let keep_ms = horizons.iter().map(|(_, ms)| *ms).max().unwrap_or(0);
+    let mut tracker = Tracker::new(horizons);
+
+    // Empty VOL_WARM_START_TABLE starts from nothing.
+    let bars_table = namespace::table(&env("VOL_WARM_START_TABLE", "trades_1m"));
+    if !bars_table.is_empty() {
+        let client = schema::Client::authed(&env("QDB_HTTP_URL", "http://localhost:9000")).await?;
+        match warm_start(&client, &bars_table, keep_ms, &mut tracker).await {
+            Ok(n) => tracing::info!(target="vol", table=%bars_table, bars=n, "warm start"),
+            Err(e) => tracing::warn!(target="vol", error=?e, "warm start failed; horizons fill as bars arrive"),
+        }
+    }
+
+    let ilp_port: u16 = env("QDB_ILP_PORT", "9009").parse().unwrap_or(9009);
+    let endpoints = parse_endpoints(&env("QDB_HOSTS", &format!("{}:{}", env("QDB_HOST", "localhost"), ilp_port)), ilp_port)?;
+    let mode = PoolMode::parse(&env("QDB_POOL_MODE", "failover"))?;
+    let policy = Policy::from_env("QDB_WRITE", 5, Duration::from_millis(100), Duration::from_secs(5));
+    let mut pool = startup::wait_for("questdb", || IlpPool::connect(endpoints.clone(), mode, IlpOptions::default(), Duration::from_secs(5))).await?;
+
+    let bus = BusConfig::from_env()?;
+    let topics = [topic_in.as_str()];
+    startup::wait_for("kafka", || bus.check_topics(&topics)).await?;
+    bus::audit::start(&bus).await;
+    let mut subscriber = bus.subscriber(&topic_in, &group_id).await?;
+    let mut committer = Committer::new(CommitStrategy::Message);
+    tracing::info!(target="vol", input=%topic_in, table=%table, "computing volatility");
+
+    let load = Load::new("vol")?;
+    while let Some(next) = subscriber.next().await {
+        let d = match next {
+            Ok(d) => d,
+            Err(e) => { tracing::error!(target="vol", error=?e, "poll error"); continue; }
+        };
+        let _busy = load.busy();
+        load.processed(1);
+        match serde_json::from_slice::<Bar>(&d.payload) {
+            Ok(bar) => write(&mut pool, &policy, &table, &tracker.add(&bar.symbol, bar.ts_ms, bar.close)).await?,
+            Err(e) => {
+                tracing::warn!(target="vol", error=?e, "undecodable bar");
+                counter!("vol_dropped_total").increment(1);
+            }
+        }
+        if let Err(e) = committer.done(subscriber.as_ref(), d).await {
+            tracing::warn!(target="vol", error=?e, "commit failed");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Realized volatility over trailing horizons of bar closes.
+//!
+//! For a horizon `h` ending at the latest bar, the base is the last close at
+//! least `h` back, or the oldest one kept while the history is shorter. `ret` is the log return from
+//! the base to the latest close and `rv` the square root of the summed
+//! squared log returns between consecutive closes in that span. `rv_annual`
+//! scales `rv` to a 365-day year (crypto doesn't close). `bars` is how many
+//! returns went in, so a dashboard can ignore horizons not yet filled.
+
+use std::collections::{HashMap, VecDeque};
+
+use consumer::ilp::escape_tag;
+use serde::Serialize;
+
+const YEAR_MS: f64 = 365.0 * 86_400_000.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Vol {
+    pub symbol: String,
+    /// Horizon label as configured, e.g. `1h`.
+    pub horizon: String,
+    /// Start of the latest bar, ms since the epoch.
+    pub ts_ms: i64,
+    pub ret: f64,
+    pub rv: f64,
+    pub rv_annual: f64,
+    pub bars: u64,
+}
+
+impl Vol {
+    pub fn to_ilp_line(&self, table: &str) -> String {
+        format!(
+            "{},symbol={},horizon={} ret={},rv={},rv_annual={},bars={}i {}",
+            escape_tag(table),
+            escape_tag(&self.symbol),
+            escape_tag(&self.horizon),
+            self.ret,
+            self.rv,
+            self.rv_annual,
+            self.bars,
+            (self.ts_ms as i128) * 1_000_000i128
+        )
+    }
+}
+
+pub struct Tracker {
+    horizons: Vec<(String, i64)>,
+    keep_ms: i64,
+    /// `(bar start, close)` per symbol, oldest first.
+    closes: HashMap<String, VecDeque<(i64, f64)>>,
+}
+
+impl Tracker {
+    pub fn new(horizons: Vec<(String, i64)>) -> Self {
+        let keep_ms = horizons.iter().map(|(_, ms)| *ms).max().unwrap_or(0);
+        Self { horizons, keep_ms, closes: HashMap::new() }
+    }
+
+    /// Take a bar's close. A repeat of the latest bar replaces it and an older
+    /// one is ignored; returns the horizons recomputed, if any.
+    pub fn add(&mut self, symbol: &str, ts_ms: i64, close: f64) -> Vec<Vol> {
+        if !(close > 0.0 && close.is_finite()) {
+            return Vec::new();
+        }
+        let closes = self.closes.entry(symbol.to_string()).or_default();
+        match closes.back() {
+            Some(&(last, _)) if ts_ms < last => return Vec::new(),
+            Some(&(last, _)) if ts_ms == last => { closes.pop_back(); }
+            _ => {}
+        }
+        closes.push_back((ts_ms, close));
+        // Keep one close at or before the longest horizon, as its base.
+        while closes.len() > 1 && closes[1].0 <= ts_ms - self.keep_ms {
+            closes.pop_front();
+        }
+
+        self.horizons.iter()
+            .filter_map(|(label, h)| {
+                let from = closes.iter().rposition(|(t, _)| *t <= ts_ms - h).unwrap_or(0);
+                let cs: Vec<f64> = closes.range(from..).map(|(_, c)| *c).collect();
+                if cs.len() < 2 {
+                    return None;
+                }
+                let sq: f64 = cs.windows(2).map(|w| (w[1] / w[0]).ln().powi(2)).sum();
+                let rv = sq.sqrt();
+                Some(Vol {
+                    symbol: symbol.to_string(),
+                    horizon: label.clone(),
+                    ts_ms,
+                    ret: (cs[cs.len() - 1] / cs[0]).ln(),
+                    rv,
+                    rv_annual: rv * (YEAR_MS / *h as f64).sqrt(),
+                    bars: cs.len() as u64 - 1,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,34 @@
+use vol::realized::Tracker;
+
+const MIN: i64 = 60_000;
+
+#[test]
+fn returns_and_vol_over_trailing_horizons() {
+    let mut t = Tracker::new(vec![("2m".into(), 2 * MIN), ("1h".into(), 60 * MIN)]);
+    assert!(t.add("BTC", 0, 100.0).is_empty(), "one close has no return");
+
+    t.add("BTC", MIN, 110.0);
+    let v = t.add("BTC", 2 * MIN, 99.0);
+    assert_eq!(v.len(), 2);
+    let (r1, r2) = ((110.0f64 / 100.0).ln(), (99.0f64 / 110.0).ln());
+    assert!((v[0].rv - (r1 * r1 + r2 * r2).sqrt()).abs() < 1e-12);
+    assert!((v[0].ret - (99.0f64 / 100.0).ln()).abs() < 1e-12);
+    assert_eq!((v[0].bars, v[1].bars), (2, 2));
+
+    // Past two minutes the oldest close drops out of the 2m horizon but not the 1h one.
+    let v = t.add("BTC", 3 * MIN, 99.0);
+    assert_eq!((v[0].bars, v[1].bars), (2, 3));
+    assert!((v[0].ret - (99.0f64 / 110.0).ln()).abs() < 1e-12);
+    assert!((v[1].rv_annual / v[1].rv - (365.0f64 * 24.0).sqrt()).abs() < 1e-9);
+}
+
+#[test]
+fn a_rewritten_bar_replaces_the_last_close_and_old_ones_are_ignored() {
+    let mut t = Tracker::new(vec![("5m".into(), 5 * MIN)]);
+    t.add("ETH", 0, 10.0);
+    t.add("ETH", MIN, 20.0);
+    let v = t.add("ETH", MIN, 11.0);
+    assert_eq!(v[0].bars, 1);
+    assert!((v[0].ret - 1.1f64.ln()).abs() < 1e-12);
+    assert!(t.add("ETH", 0, 50.0).is_empty());
+}
@@ -0,0 +1,177 @@
+//! VWAP/TWAP accumulators.
+//!
+//! Windows are aligned to the epoch (a 5m window starts on a multiple of five
+//! minutes) and close the way rollup bars do: once a trade `grace` past the
+//! end has been seen for the symbol, or the wall clock is `idle` past it.
+//!
+//! TWAP weights each price by how long it stood. The price at a window's
+//! start is the last trade before it, carried over from the previous window,
+//! so a window opening on a quiet market still averages from its start; with
+//! nothing to carry (a symbol's first window) it averages from its first trade.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use consumer::ilp::{escape_tag, NormTrade};
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+
+/// `30s`, `1m`, `5m`, `1h`, `1d` to milliseconds.
+pub fn parse_window(s: &str) -> Result<i64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (n, unit) = s.split_at(split);
+    let n: i64 = n.parse().map_err(|_| anyhow::anyhow!("bad window {:?}", s))?;
+    let unit_ms = match unit {
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        _ => anyhow::bail!("bad window unit in {:?} (expected s|m|h|d)", s),
+    };
+    anyhow::ensure!(n > 0, "window {:?} must be positive", s);
+    Ok(n * unit_ms)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Avg {
+    pub symbol: String,
+    /// Window label as configured, e.g. `5m`.
+    pub window: String,
+    /// Window start, ms since the epoch.
+    pub ts_ms: i64,
+    pub vwap: f64,
+    pub twap: f64,
+    pub volume: f64,
+    pub trades: u64,
+}
+
+impl Avg {
+    pub fn to_ilp_line(&self, table: &str) -> String {
+        format!(
+            "{},symbol={},window={} vwap={},twap={},volume={},trades={}i {}",
+            escape_tag(table),
+            escape_tag(&self.symbol),
+            escape_tag(&self.window),
+            self.vwap,
+            self.twap,
+            self.volume,
+            self.trades,
+            (self.ts_ms as i128) * 1_000_000i128
+        )
+    }
+}
+
+/// One open window's running sums.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Acc {
+    pv: f64,
+    volume: f64,
+    trades: u64,
+    /// Σ price × ms it stood, up to `last_ts`.
+    twap_num: f64,
+    /// Where TWAP time starts: the window start, or the first trade if nothing carried in.
+    twap_from: i64,
+    last_price: Option<f64>,
+    last_ts: i64,
+}
+
+/// A symbol's state for one window length.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Series {
+    /// Open windows by start.
+    open: BTreeMap<i64, Acc>,
+    max_ts_ms: i64,
+    closed_before: i64,
+    /// Last price of the newest closed window, carried into the next one.
+    carry: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Calc {
+    windows: Vec<(String, i64)>,
+    grace_ms: i64,
+    idle_ms: i64,
+    /// symbol -> window label -> series.
+    series: BTreeMap<String, BTreeMap<String, Series>>,
+}
+
+impl Calc {
+    pub fn new(windows: Vec<(String, i64)>, grace_ms: i64, idle_ms: i64) -> Self {
+        Self { windows, grace_ms, idle_ms, series: BTreeMap::new() }
+    }
+
+    /// True if this state was built for the same windows, so it can be resumed.
+    pub fn same_windows(&self, other: &Calc) -> bool {
+        self.windows == other.windows
+    }
+
+    /// Resume under `other`'s grace and idle settings, which may have changed
+    /// since this state was saved.
+    pub fn settings_of(self, other: &Calc) -> Self {
+        Self { grace_ms: other.grace_ms, idle_ms: other.idle_ms, ..self }
+    }
+
+    /// Fold a trade into each window length. Trades should arrive in trade
+    /// order per symbol, which Kafka gives us.
+    pub fn add(&mut self, t: &NormTrade) {
+        for (label, len) in &self.windows {
+            let start = t.ts_ms.div_euclid(*len) * len;
+            let s = self.series.entry(t.symbol.clone()).or_default().entry(label.clone()).or_default();
+            if start < s.closed_before {
+                counter!("vwap_late_total", "symbol" => t.symbol.clone(), "window" => label.clone()).increment(1);
+                continue;
+            }
+            s.max_ts_ms = s.max_ts_ms.max(t.ts_ms);
+            let carry = s.carry;
+            let acc = s.open.entry(start).or_insert_with(|| Acc {
+                twap_from: if carry.is_some() { start } else { t.ts_ms },
+                last_price: carry,
+                last_ts: start,
+                ..Default::default()
+            });
+            if let Some(p) = acc.last_price {
+                acc.twap_num += p * (t.ts_ms.max(acc.last_ts) - acc.last_ts) as f64;
+            }
+            acc.last_ts = acc.last_ts.max(t.ts_ms);
+            acc.last_price = Some(t.price);
+            acc.pv += t.price * t.qty;
+            acc.volume += t.qty;
+            acc.trades += 1;
+        }
+    }
+
+    /// Remove and return every window that's done, oldest first per series.
+    pub fn close(&mut self, now_ms: i64) -> Vec<Avg> {
+        let mut done = Vec::new();
+        let all = self.series.iter_mut().flat_map(|(symbol, by_window)| by_window.iter_mut().map(move |(label, s)| (symbol, label, s)));
+        for (symbol, label, s) in all {
+            let len = self.windows.iter().find(|(l, _)| l == label).map_or(0, |(_, len)| *len);
+            while let Some(entry) = s.open.first_entry() {
+                let (start, end) = (*entry.key(), entry.key() + len);
+                if s.max_ts_ms < end + self.grace_ms && now_ms < end + self.idle_ms {
+                    break;
+                }
+                let acc = entry.remove();
+                s.closed_before = end;
+                s.carry = acc.last_price;
+                let mut twap_num = acc.twap_num;
+                if let Some(p) = acc.last_price {
+                    twap_num += p * (end - acc.last_ts) as f64;
+                }
+                let span = (end - acc.twap_from) as f64;
+                done.push(Avg {
+                    symbol: symbol.clone(),
+                    window: label.clone(),
+                    ts_ms: start,
+                    vwap: if acc.volume > 0.0 { acc.pv / acc.volume } else { 0.0 },
+                    twap: if span > 0.0 { twap_num / span } else { acc.last_price.unwrap_or(0.0) },
+                    volume: acc.volume,
+                    trades: acc.trades,
+                });
+            }
+        }
+        counter!("vwap_windows_total").increment(done.len() as u64);
+        done
+    }
+}
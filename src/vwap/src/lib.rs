@@ -0,0 +1,5 @@
+//! Per-symbol VWAP and TWAP over fixed event-time windows, with state that
+//! survives restarts.
+
+pub mod calc;
+pub mod state;
@@ -0,0 +1,180 @@
+//! VWAP/TWAP service: `ticks.norm` -> `vwap` topic and QuestDB table.
+//!
+//! Each window is published when it closes. Outputs are at-least-once: a
+//! crash after publishing but before the next snapshot publishes the same
+//! windows again, with the same values.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use bus::envelope::{self, NORM_SCHEMA};
+use bus::{BusConfig, Delivery, Headers, Publisher};
+use calendar::Calendar;
+use chrono::Utc;
+use consumer::decode::decode;
+use consumer::sink::{parse_endpoints, IlpOptions, IlpPool, PoolMode};
+use metrics::counter;
+use obsv::load::Load;
+use obsv::{init_metrics, init_tracing, namespace, startup};
+use retry::Policy;
+use vwap::calc::{parse_window, Avg, Calc};
+use vwap::state::{State, Store};
+
+fn env<T: AsRef<str>>(k: T, default: &str) -> String {
+    std::env::var(k.as_ref()).unwrap_or_else(|_| default.to_string())
+}
+
+struct Out {
+    publisher: Arc<dyn Publisher>,
+    topic: String,
+    pool: Option<IlpPool>,
+    table: String,
+    publishes: Policy,
+    writes: Policy,
+}
+
+impl Out {
+    /// Publish and write closed windows, each on its policy. An error once
+    /// one gives up ends the stage before the next snapshot, so the windows
+    /// are computed and emitted again after the restart.
+    async fn emit(&mut self, avgs: &[Avg]) -> Result<()> {
+        if avgs.is_empty() {
+            return Ok(());
+        }
+        let headers = Headers::new();
+        for a in avgs {
+            let body = serde_json::to_vec(a)?;
+            self.publishes.run("kafka_publish", || self.publisher.publish(&self.topic, &a.symbol, &body, &headers)).await
+                .with_context(|| format!("publishing the {} VWAP to {}", a.symbol, self.topic))?;
+        }
+        if let Some(pool) = &mut self.pool {
+            let lines: String = avgs.iter().map(|a| a.to_ilp_line(&self.table) + "\n").collect();
+            let mut retry = self.writes.start("questdb_write");
+            while let Err(e) = pool.write_chunks(&[lines.as_bytes()]).await {
+                tracing::warn!(target="vwap", error=?e, "QuestDB write failed");
+                if !retry.failed(&e).await {
+                    return Err(e).context("writing VWAPs to QuestDB");
+                }
+            }
+        }
+        counter!("vwap_published_total").increment(avgs.len() as u64);
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    obsv::runtime::run(obsv::build_info!("nats", "pulsar"), run())
+}
+
+async fn run() -> Result<()> {
+    init_tracing();
+    init_metrics(9470)?;
+
+    let topic_in = env("TOPIC_IN", "ticks.norm");
+    let group_id = env("GROUP_ID", "vwap");
+    let windows = env("VWAP_WINDOWS", "1m,5m,1h")
+        .split(',')
+        .map(str::trim)
+        .filter(|w| !w.is_empty())
+        .map(|w| Ok((w.to_string(), parse_window(w)?)))
+        .collect::<Result<Vec<_>>>()?;
+    anyhow::ensure!(!windows.is_empty(), "VWAP_WINDOWS is empty");
+    let grace_ms: i64 = env("VWAP_GRACE_MS", "2000").parse().unwrap_or(2000);
+    let idle_ms: i64 = env("VWAP_IDLE_MS", "60000").parse().unwrap_or(60_000);
+    let save_every = Duration::from_millis(env("VWAP_STATE_EVERY_MS", "1000").parse().unwrap_or(1000));
+    let calendar = Calendar::from_env()?;
+    let store = Store::new(env("VWAP_STATE_PATH", "vwap-state.json").as_ref());
+
+    let fresh = Calc::new(windows, grace_ms, idle_ms);
+    let mut state = match store.load()? {
+        Some(s) if s.calc.same_windows(&fresh) => {
+            tracing::info!(target="vwap", offsets=?s.offsets, "resuming from snapshot");
+            State { calc: s.calc.settings_of(&fresh), ..s }
+        }
+        Some(_) => {
+            tracing::warn!(target="vwap", "VWAP_WINDOWS changed since the snapshot; starting over");
+            State::new(fresh)
+        }
+        None => State::new(fresh),
+    };
+
+    let bus = BusConfig::from_env()?;
+    let topic_out = env("VWAP_TOPIC", "vwap");
+    let topics = [topic_in.as_str(), topic_out.as_str()];
+    startup::wait_for("kafka", || bus.check_topics(&topics)).await?;
+    bus::audit::start(&bus).await;
+    let mut subscriber = bus.subscriber(&topic_in, &group_id).await?;
+    // Empty VWAP_TABLE publishes to the topic only.
+    let table = namespace::table(&env("VWAP_TABLE", "vwap"));
+    let pool = match table.is_empty() {
+        true => None,
+        false => {
+            let port: u16 = env("QDB_ILP_PORT", "9009").parse().unwrap_or(9009);
+            let endpoints = parse_endpoints(&env("QDB_HOSTS", &format!("{}:{}", env("QDB_HOST", "localhost"), port)), port)?;
+            let mode = PoolMode::parse(&env("QDB_POOL_MODE", "failover"))?;
+            Some(startup::wait_for("questdb", || IlpPool::connect(endpoints.clone(), mode, IlpOptions::default(), Duration::from_secs(5))).await?)
+        }
+    };
+    let mut out = Out {
+        publisher: bus.publisher().await?,
+        topic: topic_out,
+        pool,
+        table,
+        publishes: Policy::from_env("PUBLISH", 5, Duration::from_millis(100), Duration::from_secs(5)),
+        writes: Policy::from_env("QDB_WRITE", 5, Duration::from_millis(100), Duration::from_secs(5)),
+    };
+
+    let mut pending: Vec<Delivery> = Vec::new();
+    let mut last_save = Instant::now();
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    let load = Load::new("vwap")?;
+    loop {
+        tokio::select! {
+            next = subscriber.next() => match next {
+                None => break,
+                Some(Err(e)) => tracing::error!(target="vwap", error=?e, "poll error"),
+                Some(Ok(d)) => {
+                    let _busy = load.busy();
+                    load.processed(1);
+                    if state.applied(&d) {
+                        counter!("vwap_redelivered_total").increment(1);
+                    } else {
+                        let compat = envelope::compat(&d.headers, NORM_SCHEMA);
+                        match d.payload_str().map(|p| decode(p, &compat)) {
+                            Some(Ok(t)) if calendar.in_maintenance(t.ts_ms) => {
+                                counter!("maintenance_skipped_total", "stage" => "vwap").increment(1);
+                            }
+                            Some(Ok(t)) => state.calc.add(&t),
+                            _ => {
+                                counter!("vwap_dropped_total").increment(1);
+                                obsv::errors::record("vwap", obsv::errors::ErrorKind::Data);
+                            }
+                        }
+                        state.mark(&d);
+                    }
+                    pending.push(d);
+                }
+            },
+            _ = tick.tick() => {}
+        }
+
+        // Closing, emitting and snapshotting; waiting on the subscriber is the rest.
+        let _busy = load.busy();
+        let closed = state.calc.close(Utc::now().timestamp_millis());
+        out.emit(&closed).await?;
+
+        // Snapshot, then commit what it covers.
+        if last_save.elapsed() >= save_every && !pending.is_empty() {
+            store.save(&state)?;
+            if let Err(e) = subscriber.commit_all(&pending).await {
+                tracing::warn!(target="vwap", error=?e, "commit failed");
+            }
+            pending.clear();
+            last_save = Instant::now();
+        }
+    }
+    store.save(&state)?;
+    subscriber.commit_all(&pending).await?;
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use consumer::ilp::NormTrade;
+use vwap::calc::{parse_window, Calc};
+use vwap::state::State;
+
+fn trade(ts_ms: i64, price: f64, qty: f64) -> NormTrade {
+    NormTrade { ts_ms, symbol: "BTCUSDT".into(), price, qty, trade_id: ts_ms, is_bm: false, session: None, notional: None, first_trade_id: None, contract_size: None, ts_ns: None, ingest_ts_ns: None }
+}
+
+#[test]
+fn vwap_weights_by_volume_and_twap_by_time() {
+    let mut c = Calc::new(vec![("1m".into(), parse_window("1m").unwrap())], 0, 3_600_000);
+    // First window: nothing carried in, so TWAP starts at the first trade.
+    c.add(&trade(30_000, 10.0, 1.0));
+    c.add(&trade(45_000, 20.0, 3.0));
+    c.add(&trade(60_000, 30.0, 1.0));
+    let w = c.close(0);
+    assert_eq!(w.len(), 1);
+    assert_eq!((w[0].ts_ms, w[0].trades, w[0].volume), (0, 2, 4.0));
+    assert_eq!(w[0].vwap, 17.5);
+    // 10 for 15s, 20 for 15s.
+    assert_eq!(w[0].twap, 15.0);
+
+    // The 30 at 60s opens the second window, so it stands for the whole minute.
+    c.add(&trade(120_000, 40.0, 1.0));
+    let w = c.close(0);
+    assert_eq!((w[0].ts_ms, w[0].vwap, w[0].twap), (60_000, 30.0, 30.0));
+    assert_eq!(
+        w[0].to_ilp_line("vwap"),
+        "vwap,symbol=BTCUSDT,window=1m vwap=30,twap=30,volume=1,trades=1i 60000000000"
+    );
+}
+
+#[test]
+fn state_round_trips_mid_window() {
+    let windows = vec![("1m".to_string(), 60_000), ("5m".to_string(), 300_000)];
+    let mut c = Calc::new(windows.clone(), 0, 3_600_000);
+    c.add(&trade(0, 10.0, 1.0));
+    c.add(&trade(30_000, 20.0, 1.0));
+
+    let saved = serde_json::to_string(&State::new(c.clone())).unwrap();
+    let mut resumed = serde_json::from_str::<State>(&saved).unwrap().calc;
+    assert!(resumed.same_windows(&Calc::new(windows, 0, 0)));
+
+    for calc in [&mut c, &mut resumed] {
+        calc.add(&trade(60_000, 30.0, 1.0));
+    }
+    assert_eq!(c.close(0), resumed.close(0));
+    assert!(parse_window("5x").is_err());
+    assert_eq!(parse_window("2h").unwrap(), 7_200_000);
+}
+
+#[test]
+fn a_resumed_state_takes_the_current_grace_and_idle() {
+    let windows = vec![("1m".to_string(), 60_000)];
+    let mut c = Calc::new(windows.clone(), 0, 3_600_000);
+    c.add(&trade(0, 10.0, 1.0));
+    c.add(&trade(60_000, 20.0, 1.0));
+
+    let saved = serde_json::to_string(&State::new(c)).unwrap();
+    let resumed = serde_json::from_str::<State>(&saved).unwrap().calc;
+    // Saved with no grace, resumed with ten seconds of it: the minute stays open.
+    let mut resumed = resumed.settings_of(&Calc::new(windows, 10_000, 3_600_000));
+    assert!(resumed.close(0).is_empty());
+    resumed.add(&trade(70_000, 30.0, 1.0));
+    assert_eq!(resumed.close(0).len(), 1);
+}